@@ -0,0 +1,52 @@
+//! Compares chunked literal search against materializing the text first.
+//!
+//! ```sh
+//! cargo run --release -p dip_core --example search_bench -- 1024
+//! ```
+//!
+//! The argument is the size of the searched text in megabytes, 1GB by default.
+
+use dip_core::search::find_literal;
+use std::{env, time::Instant};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+fn main() {
+    let megabytes: usize = env::args()
+        .nth(1)
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(1024);
+
+    let line = "2022-01-01T00:00:00Z INFO request handled in 12ms path=/api/v1/items\n";
+    let mut log = String::with_capacity(megabytes * 1024 * 1024);
+    let mut i = 0;
+    while log.len() < megabytes * 1024 * 1024 {
+        if i % 10_000 == 0 {
+            log.push_str("2022-01-01T00:00:00Z ERROR connection reset by peer\n");
+        } else {
+            log.push_str(line);
+        }
+        i += 1;
+    }
+
+    // Chunks stand in for the pieces of a loaded document.
+    let chunks: Vec<&str> = log
+        .as_bytes()
+        .chunks(CHUNK_SIZE)
+        .map(|c| std::str::from_utf8(c).unwrap())
+        .collect();
+
+    let start = Instant::now();
+    let chunked = find_literal(chunks.iter().copied(), "connection reset");
+    let chunked_time = start.elapsed();
+
+    let start = Instant::now();
+    let text = chunks.concat();
+    let naive: Vec<_> = text.match_indices("connection reset").collect();
+    let naive_time = start.elapsed();
+
+    assert_eq!(chunked.len(), naive.len());
+    println!("{megabytes} MB, {} matches", chunked.len());
+    println!("chunked: {chunked_time:?}");
+    println!("naive:   {naive_time:?} (materialize + str::match_indices)");
+}
@@ -0,0 +1,203 @@
+//! Types into a large Rust file at a steady rate through the editing systems, with syntax
+//! highlighting and a find running every frame, and reports frame and edit latencies and
+//! the lines laid out again per frame.
+//!
+//! ```sh
+//! cargo run --release -p dip_core --example typing_bench -- 100000 10
+//! ```
+//!
+//! The arguments are the lines of the file and the seconds of typing. Frames are simulated
+//! at 60 a second, typing 1000 characters a second between them, in a view of 50 lines
+//! scrolled along with the cursor.
+
+use bevy::{
+    app::App,
+    core::CorePlugin,
+    ecs::{
+        event::Events,
+        schedule::ExclusiveSystemDescriptorCoercion,
+        system::{IntoExclusiveSystem, Query, ResMut},
+    },
+};
+use dip_core::{
+    announce::AnnouncePlugin,
+    command::UICommand,
+    control::RevealPosition,
+    cursor::{Cursor, CursorPlugin, Selection, TypeText},
+    damage::{DamagePlugin, RedrawMetrics, VisibleLines},
+    document::{Document, DocumentPlugin},
+    format::FormatPlugin,
+    indent::IndentPlugin,
+    memory::EvictCache,
+    pipeline::{AppPipelineExt, EditorStage, PipelinePlugin},
+    search::{Find, SearchOptions, SearchPlugin},
+    syntax::{Highlights, SyntaxPlugin},
+    text_buffer::TextBuffer,
+};
+use std::{
+    env,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+const FRAMES_PER_SECOND: usize = 60;
+const CHARS_PER_SECOND: usize = 1000;
+const QUERY: &str = "fn ";
+const VIEW_LINES: usize = 50;
+
+const SOURCE: &str = "fn handle(request: &Request) -> Result<Response, Error> {
+    let items = request.items().iter().filter(|item| item.visible).count();
+    if items > 10 {
+        return Err(Error::TooMany(items));
+    }
+    Ok(Response::new(items))
+}
+";
+
+/// When the edits of the frame started, and how long those of each frame took.
+#[derive(Default)]
+struct Clock {
+    edits_started: Option<Instant>,
+    edits: Vec<Duration>,
+    matches: usize,
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let lines: usize = args
+        .next()
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(100_000);
+    let seconds: usize = args.next().and_then(|arg| arg.parse().ok()).unwrap_or(10);
+
+    let text = SOURCE.repeat(lines.div_ceil(SOURCE.lines().count()));
+    let middle = text[..text.len() / 2].rfind('\n').map_or(0, |i| i + 1);
+
+    let mut app = App::new();
+    app.add_plugin(CorePlugin)
+        .add_plugin(PipelinePlugin)
+        .add_plugin(DocumentPlugin)
+        .add_plugin(DamagePlugin)
+        .add_plugin(AnnouncePlugin)
+        .add_plugin(IndentPlugin)
+        .add_plugin(FormatPlugin)
+        .add_plugin(CursorPlugin)
+        .add_plugin(SyntaxPlugin)
+        .add_plugin(SearchPlugin)
+        // Sent by the plugins left out, a view and a memory budget, or read by the UI.
+        .add_event::<RevealPosition>()
+        .add_event::<EvictCache>()
+        .add_event::<UICommand>()
+        .init_resource::<Clock>()
+        .add_editor_system(
+            EditorStage::Edits,
+            start_edits.exclusive_system().at_start(),
+        )
+        .add_editor_system(EditorStage::Derive, find)
+        .add_editor_system(EditorStage::Derive, scroll)
+        .add_editor_system(EditorStage::Derive, end_edits.exclusive_system().at_end());
+
+    let entity = app
+        .world
+        .spawn()
+        .insert(Document::new(
+            Some(PathBuf::from("bench.rs")),
+            TextBuffer::from(text.as_str()),
+        ))
+        .insert(Cursor::at(middle))
+        .insert(Selection { anchor: middle })
+        .id();
+    // Parses the file and highlights it as a whole once, before typing starts.
+    let start = Instant::now();
+    app.update();
+    println!("{lines} lines, {} KB", text.len() / 1024);
+    println!("first frame: {:?}", start.elapsed());
+    assert!(app.world.get::<Highlights>(entity).is_some());
+    app.world.get_resource_mut::<Clock>().unwrap().edits.clear();
+    let line = text[..middle].lines().count();
+    app.world
+        .entity_mut(entity)
+        .insert(VisibleLines(line..line + VIEW_LINES));
+
+    let typed: Vec<char> = SOURCE.chars().collect();
+    let mut next = 0;
+    let mut frames = vec![];
+    for frame in 0..seconds * FRAMES_PER_SECOND {
+        // Spreads the characters evenly over the frames of a second.
+        let due = (frame + 1) * CHARS_PER_SECOND / FRAMES_PER_SECOND
+            - frame * CHARS_PER_SECOND / FRAMES_PER_SECOND;
+        let chunk: String = (0..due).map(|i| typed[(next + i) % typed.len()]).collect();
+        next += due;
+        app.world
+            .get_resource_mut::<Events<TypeText>>()
+            .unwrap()
+            .send(TypeText {
+                entity,
+                text: chunk,
+            });
+
+        let start = Instant::now();
+        app.update();
+        frames.push(start.elapsed());
+    }
+
+    let clock = app.world.get_resource::<Clock>().unwrap();
+    let len = app.world.get::<Document>(entity).unwrap().buffer().len();
+    println!(
+        "typed {next} characters in {} frames, {} KB now, {} matches of {QUERY:?}",
+        frames.len(),
+        len / 1024,
+        clock.matches
+    );
+    report("frame", frames);
+    report("edit", clock.edits.clone());
+    let redraws = app.world.get_resource::<RedrawMetrics>().unwrap();
+    println!(
+        "lines laid out: average {:.1}, peak {}, last {}",
+        redraws.average(),
+        redraws.peak,
+        redraws.last_frame
+    );
+}
+
+fn start_edits(mut clock: ResMut<Clock>) {
+    clock.edits_started = Some(Instant::now());
+}
+
+/// Find as you type, over the whole document.
+fn find(mut clock: ResMut<Clock>, documents: Query<&Document>) {
+    for document in documents.iter() {
+        clock.matches = document
+            .buffer()
+            .find(QUERY, SearchOptions::default())
+            .len();
+    }
+}
+
+/// Keeps the cursor in view, scrolling a whole view at a time like a page down would.
+fn scroll(mut views: Query<(&Document, &Cursor, &mut VisibleLines)>) {
+    for (document, cursor, mut visible) in views.iter_mut() {
+        let line = document.buffer().line_at(cursor.offset);
+        if !visible.0.contains(&line) {
+            visible.0 = line..line + VIEW_LINES;
+        }
+    }
+}
+
+/// Edits are done once the state derived from them, highlights included, caught up.
+fn end_edits(mut clock: ResMut<Clock>) {
+    if let Some(started) = clock.edits_started.take() {
+        clock.edits.push(started.elapsed());
+    }
+}
+
+fn report(name: &str, mut durations: Vec<Duration>) {
+    durations.sort();
+    let at = |p: usize| durations[(durations.len() * p / 100).min(durations.len() - 1)];
+    println!(
+        "{name}: p50 {:?}, p95 {:?}, max {:?}",
+        at(50),
+        at(95),
+        durations.last().unwrap()
+    );
+}
@@ -0,0 +1,53 @@
+//! Compares workspace search on one worker against the whole task pool.
+//!
+//! ```sh
+//! cargo run --release -p dip_core --example workspace_search_bench -- ~/src/linux fn
+//! ```
+
+use bevy::tasks::{TaskPool, TaskPoolBuilder};
+use dip_core::{
+    workspace::{Workspace, WorkspaceRoot},
+    workspace_search::{WorkspaceSearch, WorkspaceSearchSettings},
+};
+use std::{
+    env,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let root = args.next().map(PathBuf::from).unwrap_or_else(|| ".".into());
+    let query = args.next().unwrap_or_else(|| "fn".to_string());
+    let workspace = Workspace::folder(root);
+    let settings = WorkspaceSearchSettings::default();
+
+    let sequential = TaskPoolBuilder::new().num_threads(1).build();
+    let parallel = TaskPool::new();
+
+    // Warm the page cache so both runs measure the same thing.
+    run(&parallel, workspace.roots(), &query, &settings);
+
+    let (files, matches, one) = run(&sequential, workspace.roots(), &query, &settings);
+    let (_, _, all) = run(&parallel, workspace.roots(), &query, &settings);
+
+    println!("{files} files, {matches} matching");
+    println!("1 worker:   {one:?}");
+    println!(
+        "{} workers: {all:?} ({:.1}x)",
+        parallel.thread_num(),
+        one.as_secs_f64() / all.as_secs_f64()
+    );
+}
+
+fn run(
+    pool: &TaskPool,
+    roots: &[WorkspaceRoot],
+    query: &str,
+    settings: &WorkspaceSearchSettings,
+) -> (usize, usize, Duration) {
+    let start = Instant::now();
+    let search = WorkspaceSearch::start(pool, roots, query.to_string(), settings);
+    let matches = search.wait();
+    (search.files_searched(), matches.len(), start.elapsed())
+}
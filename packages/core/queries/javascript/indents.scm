@@ -0,0 +1,22 @@
+; Lines inside these nodes are indented one level deeper than the line they start on.
+[
+  (arguments)
+  (array)
+  (array_pattern)
+  (class_body)
+  (export_clause)
+  (formal_parameters)
+  (named_imports)
+  (object)
+  (object_pattern)
+  (parenthesized_expression)
+  (statement_block)
+  (switch_body)
+] @indent
+
+; Closing brackets stay at the level of the line opening the block.
+[
+  "}"
+  ")"
+  "]"
+] @outdent
@@ -0,0 +1,22 @@
+; Lines inside these nodes are indented one level deeper than the line they start on.
+[
+  (arguments)
+  (array_expression)
+  (block)
+  (declaration_list)
+  (enum_variant_list)
+  (field_declaration_list)
+  (field_initializer_list)
+  (match_block)
+  (parameters)
+  (token_tree)
+  (tuple_expression)
+  (use_list)
+] @indent
+
+; Closing brackets stay at the level of the line opening the block.
+[
+  "}"
+  ")"
+  "]"
+] @outdent
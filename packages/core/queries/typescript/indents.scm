@@ -0,0 +1,6 @@
+; Added to the JavaScript query for TypeScript and TSX.
+[
+  (enum_body)
+  (interface_body)
+  (object_type)
+] @indent
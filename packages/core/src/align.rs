@@ -0,0 +1,121 @@
+use crate::{
+    command::{RegisterCommand, RunCommand},
+    cursor::{self, Documents, Secondaries},
+    document::{DocumentChanged, DocumentEditSet},
+    pipeline::{AppPipelineExt, EditorStage},
+    workspace::Workspace,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::Res,
+    },
+    log::{debug, warn},
+};
+use std::ops::Range;
+
+pub use dip_editor::align::{align, align_ranges, line_edit, width, AlignError};
+
+/// An assignment operator as a whole, e.g. `+=` or `=>`, so its `=` lines up with plain ones.
+const EQUALS: &str = r"[-+*/%&|^<>!.:]*=+>?";
+
+pub struct AlignPlugin;
+
+impl Plugin for AlignPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<AlignSelection>()
+            .add_event::<AlignFailed>()
+            .register_command("align.equals", "Align", "Align by =")
+            .register_command("align.colon", "Align", "Align by :")
+            .register_command("align.comma", "Align", "Align Columns by ,")
+            .add_editor_system(EditorStage::Commands, run_align_commands)
+            .add_editor_system(EditorStage::Edits, align_selections.label(DocumentEditSet));
+    }
+}
+
+/// Pads the lines of each selection so `delimiter` lines up, or the lines of the paragraph
+/// around a cursor without one. Lines without it are left alone.
+#[derive(Clone, Debug)]
+pub struct AlignSelection {
+    pub entity: Entity,
+    pub delimiter: String,
+    /// Read `delimiter` as a regular expression. Otherwise a run of it, like `::` for `:`,
+    /// is not a delimiter.
+    pub regex: bool,
+    /// Line every occurrence up into columns, e.g. of a table, rather than only the first.
+    pub all: bool,
+}
+
+/// Nothing was changed.
+#[derive(Clone, Debug)]
+pub struct AlignFailed {
+    pub entity: Entity,
+    pub error: String,
+}
+
+fn run_align_commands(
+    mut events: EventReader<RunCommand>,
+    workspace: Res<Workspace>,
+    mut aligns: EventWriter<AlignSelection>,
+) {
+    for e in events.iter() {
+        let entity = match (e.id.starts_with("align."), workspace.active()) {
+            (true, Some(entity)) => entity,
+            _ => continue,
+        };
+        let (delimiter, regex, all) = match e.id.as_str() {
+            "align.equals" => (EQUALS, true, false),
+            "align.colon" => (":", false, false),
+            "align.comma" => (",", false, true),
+            _ => continue,
+        };
+        aligns.send(AlignSelection {
+            entity,
+            delimiter: delimiter.to_string(),
+            regex,
+            all,
+        });
+    }
+}
+
+fn align_selections(
+    mut events: EventReader<AlignSelection>,
+    mut documents: Documents<()>,
+    mut secondaries: Secondaries,
+    mut changed: EventWriter<DocumentChanged>,
+    mut failed: EventWriter<AlignFailed>,
+) {
+    for e in events.iter() {
+        let (mut document, cursor, selection, ()) = match documents.get_mut(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let ranges: Vec<Range<usize>> =
+            cursor::carets(e.entity, (&cursor, &selection), &mut secondaries)
+                .iter()
+                .map(|caret| caret.range())
+                .collect();
+        match align_ranges(&mut document, &ranges, &e.delimiter, e.regex, e.all) {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(error) => {
+                warn!("📐 {error}");
+                failed.send(AlignFailed {
+                    entity: e.entity,
+                    error: error.to_string(),
+                });
+                continue;
+            }
+        }
+        debug!("📐 Aligned by {}", e.delimiter);
+        changed.send(DocumentChanged {
+            entity: e.entity,
+            version: document.version(),
+            changes: document.take_changes(),
+            cursor: None,
+        });
+    }
+}
@@ -0,0 +1,81 @@
+use crate::{
+    command::UICommand,
+    pipeline::{AppPipelineExt, EditorStage},
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::event::{EventReader, EventWriter},
+};
+use unicode_segmentation::UnicodeSegmentation;
+
+pub struct AnnouncePlugin;
+
+impl Plugin for AnnouncePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Announcement>()
+            .add_editor_system(EditorStage::Present, send_announcements);
+    }
+}
+
+/// Text for screen readers describing what an action did, sent to the view as
+/// [`UICommand::Announce`] for its live region to read out.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Announcement {
+    pub text: String,
+    /// Interrupts what is being read instead of waiting for it to finish.
+    pub assertive: bool,
+}
+
+impl Announcement {
+    pub fn polite(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            assertive: false,
+        }
+    }
+
+    /// "Moved to line 42, column 7", from a zero based line and column.
+    pub fn moved_to(line: usize, column: usize) -> Self {
+        Self::polite(format!("Moved to line {}, column {}", line + 1, column + 1))
+    }
+
+    /// "Deleted 3 lines", or the characters deleted within a line, counted as they are
+    /// seen. Deleting a line break joins lines. Nothing is said for a single character,
+    /// screen readers read it out already.
+    pub fn deleted(text: &str) -> Option<Self> {
+        if text == "\n" || text == "\r\n" {
+            return Some(Self::polite("Joined lines"));
+        }
+        let lines = text.matches('\n').count();
+        let chars = text.graphemes(true).count();
+        let what = match (lines, chars) {
+            (0, 0 | 1) => return None,
+            (0, chars) => count(chars, "character"),
+            (lines, _) => count(lines, "line"),
+        };
+        Some(Self::polite(format!("Deleted {what}")))
+    }
+
+    /// "2 of 15 matches", with `index` zero based.
+    pub fn match_of(index: usize, total: usize) -> Self {
+        Self::polite(format!("{} of {}", index + 1, count(total, "match")))
+    }
+}
+
+fn send_announcements(
+    mut announcements: EventReader<Announcement>,
+    mut ui: EventWriter<UICommand>,
+) {
+    for announcement in announcements.iter() {
+        ui.send(UICommand::Announce(announcement.clone()));
+    }
+}
+
+/// "1 line", "3 lines".
+pub fn count(n: usize, noun: &str) -> String {
+    match n {
+        1 => format!("1 {noun}"),
+        n if noun.ends_with("ch") => format!("{n} {noun}es"),
+        n => format!("{n} {noun}s"),
+    }
+}
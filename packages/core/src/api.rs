@@ -0,0 +1,235 @@
+//! The events plugins outside this repository integrate through, versioned apart from the
+//! crate so the rest of `dip_core` can change freely.
+//!
+//! Within a major [`API_VERSION`], the events here keep their names and fields and keep
+//! being sent when they are today. Minor versions only add events, fields and variants,
+//! which is why every struct is `#[non_exhaustive]`: read fields by name and build
+//! [`ExecuteCommand`] with its constructor. Everything else in `dip_core` is internal and
+//! may change in any release.
+//!
+//! [`ApiPlugin`] translates the internal events into these and back, so this is the only
+//! layer that has to follow when internals change.
+
+use crate::{
+    command::RunCommand,
+    cursor::{Cursor, Selection},
+    document::{Document, DocumentChanged},
+    pipeline::{AppPipelineExt, EditorStage},
+    workspace,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        query::{Added, With},
+        system::{Local, Query},
+    },
+};
+use std::{collections::HashMap, fmt, ops::Range, path::PathBuf};
+
+pub const API_VERSION: ApiVersion = ApiVersion { major: 1, minor: 0 };
+
+pub struct ApiPlugin;
+
+impl Plugin for ApiPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(API_VERSION)
+            .add_event::<DocumentOpened>()
+            .add_event::<DocumentClosed>()
+            .add_event::<BufferChanged>()
+            .add_event::<CursorMoved>()
+            .add_event::<ExecuteCommand>()
+            .add_event::<CommandExecuted>()
+            .add_system(send_opened)
+            .add_system(send_closed)
+            .add_editor_system(EditorStage::Derive, send_changed)
+            .add_editor_system(EditorStage::Derive, send_cursor_moves)
+            .add_editor_system(EditorStage::Input, execute_commands)
+            .add_system(send_executed);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ApiVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl ApiVersion {
+    pub const fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+
+    /// Whether a plugin written against `required` works with this version.
+    pub fn supports(&self, required: ApiVersion) -> bool {
+        self.major == required.major && self.minor >= required.minor
+    }
+}
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+pub trait AppApiExt {
+    /// Checks the app speaks `required`, to call first in a plugin's `build`. Panics
+    /// otherwise, as the plugin would miss events or misread them.
+    fn require_api(&mut self, required: ApiVersion) -> &mut Self;
+}
+
+impl AppApiExt for App {
+    fn require_api(&mut self, required: ApiVersion) -> &mut Self {
+        let version = self
+            .world
+            .get_resource::<ApiVersion>()
+            .copied()
+            .expect("ApiPlugin must be added before plugins requiring the API");
+        assert!(
+            version.supports(required),
+            "plugin requires dip API {required}, this is {version}"
+        );
+        self
+    }
+}
+
+/// A document was spawned, opened from a file or otherwise, e.g. untitled or restored.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct DocumentOpened {
+    pub entity: Entity,
+    /// None for untitled documents.
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct DocumentClosed {
+    pub entity: Entity,
+    pub path: Option<PathBuf>,
+}
+
+/// The text of a document changed, by an edit, undo, redo or reload.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct BufferChanged {
+    pub entity: Entity,
+    /// Grows with every change, for telling stale results apart.
+    pub version: u64,
+    /// In the order they were made, each against the text the previous one left.
+    pub edits: Vec<Edit>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Edit {
+    /// Replaced byte range.
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+/// The primary cursor or the selection of a document moved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CursorMoved {
+    pub entity: Entity,
+    /// Byte offset of the caret.
+    pub offset: usize,
+    /// The other end of the selection, `offset` when nothing is selected.
+    pub anchor: usize,
+}
+
+/// Runs the command `id`, e.g. `edit.undo`, as the command palette does.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct ExecuteCommand {
+    pub id: String,
+}
+
+impl ExecuteCommand {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self { id: id.into() }
+    }
+}
+
+/// The command `id` was run, whoever asked for it.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct CommandExecuted {
+    pub id: String,
+}
+
+fn send_opened(
+    documents: Query<(Entity, &Document), Added<Document>>,
+    mut opened: EventWriter<DocumentOpened>,
+) {
+    for (entity, document) in documents.iter() {
+        opened.send(DocumentOpened {
+            entity,
+            path: document.path().map(PathBuf::from),
+        });
+    }
+}
+
+fn send_closed(
+    mut events: EventReader<workspace::DocumentClosed>,
+    mut closed: EventWriter<DocumentClosed>,
+) {
+    for e in events.iter() {
+        closed.send(DocumentClosed {
+            entity: e.entity,
+            path: e.path.clone(),
+        });
+    }
+}
+
+fn send_changed(mut events: EventReader<DocumentChanged>, mut changed: EventWriter<BufferChanged>) {
+    for e in events.iter().filter(|e| !e.changes.is_empty()) {
+        changed.send(BufferChanged {
+            entity: e.entity,
+            version: e.version,
+            edits: e
+                .changes
+                .iter()
+                .map(|change| Edit {
+                    range: change.range.clone(),
+                    text: change.text.clone(),
+                })
+                .collect(),
+        });
+    }
+}
+
+/// Compares with what was sent last, as systems may touch a cursor without moving it.
+fn send_cursor_moves(
+    documents: Query<(Entity, &Cursor, &Selection), With<Document>>,
+    mut last: Local<HashMap<Entity, (usize, usize)>>,
+    mut moved: EventWriter<CursorMoved>,
+) {
+    let mut seen = HashMap::with_capacity(last.len());
+    for (entity, cursor, selection) in documents.iter() {
+        let now = (cursor.offset, selection.anchor);
+        if last.get(&entity) != Some(&now) {
+            moved.send(CursorMoved {
+                entity,
+                offset: now.0,
+                anchor: now.1,
+            });
+        }
+        seen.insert(entity, now);
+    }
+    *last = seen;
+}
+
+fn execute_commands(mut events: EventReader<ExecuteCommand>, mut runs: EventWriter<RunCommand>) {
+    for e in events.iter() {
+        runs.send(RunCommand { id: e.id.clone() });
+    }
+}
+
+fn send_executed(mut events: EventReader<RunCommand>, mut executed: EventWriter<CommandExecuted>) {
+    for e in events.iter() {
+        executed.send(CommandExecuted { id: e.id.clone() });
+    }
+}
@@ -0,0 +1,257 @@
+use crate::{
+    document::{
+        Document, DocumentSaveFailed, DocumentSaved, SaveConflict, SaveDocument, SaveDocuments,
+        READ_CHUNK_SIZE,
+    },
+    elevate::SaveElevated,
+    journal::state_dir,
+    memory::MemoryUsage,
+    text_buffer::TextBuffer,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Commands, Query, Res, ResMut},
+    },
+    log::{debug, warn},
+};
+use dip_editor::date::civil_date;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub struct AuditPlugin;
+
+impl Plugin for AuditPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AuditLog>()
+            .add_event::<ShowAuditLog>()
+            .add_event::<AuditLogShown>()
+            .add_system(digest_before_save.before(SaveDocuments))
+            .add_system(record_saves.after(SaveDocuments))
+            .add_system(show_audit_log);
+    }
+}
+
+/// Size and SHA-256 of a file's contents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FileDigest {
+    pub len: u64,
+    pub sha256: [u8; 32],
+}
+
+impl FileDigest {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let mut sha = Sha256::new();
+        let mut len = 0;
+        let mut chunk = vec![0; READ_CHUNK_SIZE];
+        loop {
+            let n = file.read(&mut chunk)?;
+            if n == 0 {
+                break;
+            }
+            sha.update(&chunk[..n]);
+            len += n as u64;
+        }
+        Ok(Self {
+            len,
+            sha256: sha.finalize().into(),
+        })
+    }
+}
+
+impl fmt::Display for FileDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.sha256.iter().try_for_each(|b| write!(f, "{b:02x}"))
+    }
+}
+
+/// A save, with the file as it was on disk right before and after.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub path: PathBuf,
+    pub time: SystemTime,
+    /// None if the file did not exist yet.
+    pub before: Option<FileDigest>,
+    pub after: FileDigest,
+}
+
+impl AuditEntry {
+    /// Bytes the file grew by, negative when it shrank.
+    pub fn delta(&self) -> i64 {
+        self.after.len as i64 - self.before.map_or(0, |b| b.len as i64)
+    }
+}
+
+impl fmt::Display for AuditEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self
+            .time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let (year, month, day) = civil_date(secs / 86_400);
+        let (h, m, s) = (secs / 3600 % 24, secs / 60 % 60, secs % 60);
+        write!(
+            f,
+            "{year:04}-{month:02}-{day:02}T{h:02}:{m:02}:{s:02}Z  {}  {:+} bytes  ",
+            self.path.display(),
+            self.delta()
+        )?;
+        match self.before {
+            Some(before) => write!(f, "{before} -> {}", self.after),
+            None => write!(f, "new -> {}", self.after),
+        }
+    }
+}
+
+/// Every save of this session, oldest first, also appended to [`AuditLog::file`].
+#[derive(Debug)]
+pub struct AuditLog {
+    entries: Vec<AuditEntry>,
+    /// Files as they were before saves still in flight, by document and destination. A
+    /// save requested again, or retried, keeps the first digest.
+    pending: HashMap<(Entity, PathBuf), Option<FileDigest>>,
+    /// Where each entry is appended as a line when recorded, across sessions. Entries are
+    /// kept in memory only without one.
+    pub file: Option<PathBuf>,
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        Self {
+            entries: vec![],
+            pending: HashMap::new(),
+            file: state_dir().map(|dir| dir.join("audit.log")),
+        }
+    }
+}
+
+impl AuditLog {
+    pub fn entries(&self) -> &[AuditEntry] {
+        &self.entries
+    }
+
+    fn record(&mut self, entry: AuditEntry) {
+        if let Some(file) = &self.file {
+            if let Err(error) = append_line(file, &entry.to_string()) {
+                warn!("🔏 Failed to write {}: {error}", file.display());
+            }
+        }
+        self.entries.push(entry);
+    }
+}
+
+fn append_line(file: &Path, line: &str) -> io::Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file)?
+        .write_all(format!("{line}\n").as_bytes())
+}
+
+impl fmt::Display for AuditLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.entries
+            .iter()
+            .try_for_each(|entry| writeln!(f, "{entry}"))
+    }
+}
+
+/// Opens what the audit log holds so far as an untitled document.
+#[derive(Clone, Copy, Debug)]
+pub struct ShowAuditLog;
+
+#[derive(Clone, Copy, Debug)]
+pub struct AuditLogShown {
+    pub entity: Entity,
+}
+
+/// Digests the files saves are about to write, elevated ones included.
+fn digest_before_save(
+    mut saves: EventReader<SaveDocument>,
+    mut elevated: EventReader<SaveElevated>,
+    documents: Query<&Document>,
+    mut log: ResMut<AuditLog>,
+) {
+    let saves = saves.iter().map(|e| (e.entity, e.path.clone()));
+    let elevated = elevated.iter().map(|e| (e.entity, Some(e.path.clone())));
+    for (entity, path) in saves.chain(elevated) {
+        let path = match (path, documents.get(entity).map(|d| d.path())) {
+            (Some(path), _) => path,
+            (None, Ok(Some(path))) => path.to_path_buf(),
+            _ => continue,
+        };
+        log.pending
+            .entry((entity, path))
+            .or_insert_with_key(|(_, path)| FileDigest::read(path).ok());
+    }
+}
+
+fn record_saves(
+    mut saved: EventReader<DocumentSaved>,
+    mut failed: EventReader<DocumentSaveFailed>,
+    mut conflicts: EventReader<SaveConflict>,
+    mut log: ResMut<AuditLog>,
+) {
+    for e in failed.iter() {
+        if let Some(path) = &e.path {
+            log.pending.remove(&(e.entity, path.clone()));
+        }
+    }
+    for e in conflicts.iter() {
+        log.pending.remove(&(e.entity, e.path.clone()));
+    }
+    // A file saved twice in one frame was digested once, before the first save.
+    let mut saved_now = HashMap::new();
+    for e in saved.iter() {
+        let pending = log.pending.remove(&(e.entity, e.path.clone())).flatten();
+        let before = saved_now.get(&e.path).copied().or(pending);
+        let after = match FileDigest::read(&e.path) {
+            Ok(after) => after,
+            Err(error) => {
+                warn!("🔏 {}: {error}", e.path.display());
+                continue;
+            }
+        };
+        let entry = AuditEntry {
+            path: e.path.clone(),
+            time: SystemTime::now(),
+            before,
+            after,
+        };
+        debug!("🔏 {entry}");
+        saved_now.insert(entry.path.clone(), entry.after);
+        log.record(entry);
+    }
+}
+
+fn show_audit_log(
+    mut commands: Commands,
+    mut events: EventReader<ShowAuditLog>,
+    log: Res<AuditLog>,
+    mut shown: EventWriter<AuditLogShown>,
+) {
+    if events.iter().count() == 0 {
+        return;
+    }
+    let text = log.to_string();
+    let entity = commands
+        .spawn()
+        .insert(Document::new(None, TextBuffer::from(text.as_str())))
+        .insert(MemoryUsage::default())
+        .id();
+    shown.send(AuditLogShown { entity });
+}
@@ -0,0 +1,228 @@
+use crate::{
+    announce::count,
+    command::{RegisterCommand, RunCommand},
+    cursor::{self, Documents, Placed, Secondaries},
+    document::{DocumentChanged, DocumentEditSet},
+    pipeline::{AppPipelineExt, EditorStage},
+    workspace::Workspace,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Commands, Res, ResMut},
+    },
+    log::{debug, warn},
+};
+use std::ops::Range;
+
+pub use dip_editor::calc::{evaluate, evaluation, insert_sum, CalcError, Sum, MAX_DEPTH};
+
+pub struct CalcPlugin;
+
+impl Plugin for CalcPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<EvaluateSelection>()
+            .add_event::<SumNumbers>()
+            .add_event::<NumbersSummed>()
+            .add_event::<CalcFailed>()
+            .register_command("calc.evaluate", "Calculate", "Append Result")
+            .register_command("calc.replace", "Calculate", "Replace with Result")
+            .register_command("calc.sum", "Calculate", "Sum Numbers")
+            .add_editor_system(EditorStage::Commands, run_calc_commands)
+            .add_editor_system(
+                EditorStage::Edits,
+                evaluate_selections.label(DocumentEditSet),
+            )
+            .add_editor_system(EditorStage::Edits, sum_numbers.label(DocumentEditSet));
+    }
+}
+
+/// Evaluates the selection of each cursor with [`evaluate`], or the line it is on if
+/// nothing is selected. The result replaces the expression, or is appended after ` = `
+/// without `replace`. Nothing changes if any of them fails.
+#[derive(Clone, Debug)]
+pub struct EvaluateSelection {
+    pub entity: Entity,
+    pub replace: bool,
+}
+
+/// Adds up the numbers at the cursors, or selected, e.g. a column of them with a cursor
+/// on each line, and writes the total on a line below the last one.
+#[derive(Clone, Debug)]
+pub struct SumNumbers {
+    pub entity: Entity,
+}
+
+#[derive(Clone, Debug)]
+pub struct NumbersSummed {
+    pub entity: Entity,
+    pub count: usize,
+    pub total: String,
+}
+
+/// Nothing was changed.
+#[derive(Clone, Debug)]
+pub struct CalcFailed {
+    pub entity: Entity,
+    pub error: String,
+}
+
+fn run_calc_commands(
+    mut events: EventReader<RunCommand>,
+    workspace: Res<Workspace>,
+    mut evaluates: EventWriter<EvaluateSelection>,
+    mut sums: EventWriter<SumNumbers>,
+) {
+    for e in events.iter() {
+        let entity = match (e.id.starts_with("calc."), workspace.active()) {
+            (true, Some(entity)) => entity,
+            _ => continue,
+        };
+        match e.id.as_str() {
+            "calc.evaluate" | "calc.replace" => evaluates.send(EvaluateSelection {
+                entity,
+                replace: e.id == "calc.replace",
+            }),
+            "calc.sum" => sums.send(SumNumbers { entity }),
+            _ => {}
+        }
+    }
+}
+
+fn evaluate_selections(
+    mut commands: Commands,
+    mut events: EventReader<EvaluateSelection>,
+    mut documents: Documents<()>,
+    mut secondaries: Secondaries,
+    mut placed: ResMut<Placed>,
+    mut changed: EventWriter<DocumentChanged>,
+    mut failed: EventWriter<CalcFailed>,
+) {
+    for e in events.iter() {
+        let (mut document, mut cursor, mut selection, ()) = match documents.get_mut(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let mut fail = |error: String| {
+            warn!("🔢 {error}");
+            failed.send(CalcFailed {
+                entity: e.entity,
+                error,
+            });
+        };
+        if document.is_read_only() {
+            fail("The document is read only".to_string());
+            continue;
+        }
+        let mut carets = cursor::carets(e.entity, (&cursor, &selection), &mut secondaries);
+
+        // The edit of each caret, by its range, with the text written.
+        let buffer = document.buffer();
+        let mut edits: Vec<(Range<usize>, Range<usize>, String)> = vec![];
+        let mut error = None;
+        for caret in &carets {
+            match evaluation(buffer, caret.range(), e.replace) {
+                Ok(Some((range, _))) if edits.iter().any(|(_, r, _)| r.start == range.start) => {}
+                Ok(Some((range, text))) => edits.push((caret.range(), range, text)),
+                Ok(None) => {}
+                Err(failure) => {
+                    error = Some(failure);
+                    break;
+                }
+            }
+        }
+        if let Some(error) = error {
+            fail(error);
+            continue;
+        }
+        if edits.is_empty() {
+            fail("Nothing to evaluate".to_string());
+            continue;
+        }
+
+        // A replaced expression ends up selected, the cursor goes after an appended result.
+        document.history_mut().begin();
+        cursor::edit_carets(&mut document, &mut carets, |document, caret| {
+            match edits.iter().find(|(r, ..)| *r == caret.range()) {
+                Some((_, range, text)) => {
+                    document.delete(range.clone());
+                    document.insert(range.start, text);
+                    let end = range.start + text.len();
+                    match e.replace {
+                        true => range.start..end,
+                        false => end..end,
+                    }
+                }
+                None => caret.range(),
+            }
+        });
+        cursor::store(
+            carets,
+            (&mut cursor, &mut selection),
+            &mut secondaries,
+            &mut commands,
+        );
+        placed.0.insert((e.entity, document.version()));
+        debug!("🔢 Evaluated {}", count(edits.len(), "expression"));
+        changed.send(DocumentChanged {
+            entity: e.entity,
+            version: document.version(),
+            changes: document.take_changes(),
+            cursor: None,
+        });
+    }
+}
+
+fn sum_numbers(
+    mut events: EventReader<SumNumbers>,
+    mut documents: Documents<()>,
+    mut secondaries: Secondaries,
+    mut changed: EventWriter<DocumentChanged>,
+    mut summed: EventWriter<NumbersSummed>,
+    mut failed: EventWriter<CalcFailed>,
+) {
+    for e in events.iter() {
+        let (mut document, cursor, selection, ()) = match documents.get_mut(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let mut fail = |error: String| {
+            warn!("🔢 {error}");
+            failed.send(CalcFailed {
+                entity: e.entity,
+                error,
+            });
+        };
+        if document.is_read_only() {
+            fail("The document is read only".to_string());
+            continue;
+        }
+        let ranges: Vec<Range<usize>> =
+            cursor::carets(e.entity, (&cursor, &selection), &mut secondaries)
+                .iter()
+                .map(|caret| caret.range())
+                .collect();
+        let sum = match insert_sum(&mut document, &ranges) {
+            Some(sum) => sum,
+            None => {
+                fail("No numbers at the cursors".to_string());
+                continue;
+            }
+        };
+        debug!("🔢 Summed {} to {}", count(sum.count, "number"), sum.total);
+        changed.send(DocumentChanged {
+            entity: e.entity,
+            version: document.version(),
+            changes: document.take_changes(),
+            cursor: Some(sum.cursor),
+        });
+        summed.send(NumbersSummed {
+            entity: e.entity,
+            count: sum.count,
+            total: sum.total,
+        });
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{
+    diff::{CompareFiles, MergeFiles},
+    document::OpenDocument,
+    stdin::ReadStdin,
+    workspace::{OpenWorkspace, WORKSPACE_EXTENSION},
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{event::EventWriter, system::Res},
+};
+use std::{fmt, path::PathBuf};
+
+pub const USAGE: &str = "\
+Usage:
+    dip [PATH]...       (- reads standard input)
+    dip --diff LEFT RIGHT
+    dip --merge BASE OURS THEIRS OUTPUT";
+
+/// Dispatches the command line the app was started with. Insert an [`Invocation`] before
+/// adding it, nothing is opened otherwise.
+pub struct CliPlugin;
+
+impl Plugin for CliPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Invocation>()
+            .add_startup_system(dispatch_invocation);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Invocation {
+    /// Files are opened as documents, a folder or workspace file as the workspace and `-`
+    /// standard input.
+    Open(Vec<PathBuf>),
+    Diff {
+        left: PathBuf,
+        right: PathBuf,
+    },
+    Merge {
+        base: PathBuf,
+        ours: PathBuf,
+        theirs: PathBuf,
+        output: PathBuf,
+    },
+}
+
+impl Default for Invocation {
+    fn default() -> Self {
+        Invocation::Open(vec![])
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CliError {
+    UnknownFlag(String),
+    /// The flag and the number of paths it takes.
+    WrongArity(&'static str, usize),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::UnknownFlag(flag) => write!(f, "unknown flag {flag}"),
+            CliError::WrongArity(flag, n) => write!(f, "{flag} takes {n} paths"),
+        }
+    }
+}
+
+impl Invocation {
+    /// Parses arguments without the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, CliError> {
+        let mut args: Vec<String> = args.into_iter().collect();
+        let flag = match args.first() {
+            Some(first) if first.starts_with("--") => args.remove(0),
+            _ => {
+                if let Some(flag) = args.iter().find(|a| a.starts_with("--")) {
+                    return Err(CliError::UnknownFlag(flag.clone()));
+                }
+                return Ok(Invocation::Open(
+                    args.into_iter().map(PathBuf::from).collect(),
+                ));
+            }
+        };
+
+        let mut paths = args.into_iter().map(PathBuf::from);
+        let mut exactly = |flag, n| {
+            let taken: Vec<PathBuf> = paths.by_ref().take(n + 1).collect();
+            if taken.len() == n {
+                Ok(taken)
+            } else {
+                Err(CliError::WrongArity(flag, n))
+            }
+        };
+        match flag.as_str() {
+            "--diff" => {
+                let [left, right]: [PathBuf; 2] = exactly("--diff", 2)?.try_into().unwrap();
+                Ok(Invocation::Diff { left, right })
+            }
+            "--merge" => {
+                let [base, ours, theirs, output]: [PathBuf; 4] =
+                    exactly("--merge", 4)?.try_into().unwrap();
+                Ok(Invocation::Merge {
+                    base,
+                    ours,
+                    theirs,
+                    output,
+                })
+            }
+            _ => Err(CliError::UnknownFlag(flag)),
+        }
+    }
+}
+
+fn dispatch_invocation(
+    invocation: Res<Invocation>,
+    mut workspaces: EventWriter<OpenWorkspace>,
+    mut documents: EventWriter<OpenDocument>,
+    mut stdin: EventWriter<ReadStdin>,
+    mut comparisons: EventWriter<CompareFiles>,
+    mut merges: EventWriter<MergeFiles>,
+) {
+    match invocation.clone() {
+        Invocation::Open(paths) => {
+            for path in paths {
+                let is_workspace = path.extension().is_some_and(|e| e == WORKSPACE_EXTENSION);
+                if path.as_os_str() == "-" {
+                    stdin.send(ReadStdin);
+                } else if path.is_dir() || is_workspace {
+                    workspaces.send(OpenWorkspace { path });
+                } else {
+                    documents.send(OpenDocument { path });
+                }
+            }
+        }
+        Invocation::Diff { left, right } => comparisons.send(CompareFiles { left, right }),
+        Invocation::Merge {
+            base,
+            ours,
+            theirs,
+            output,
+        } => merges.send(MergeFiles {
+            base,
+            ours,
+            theirs,
+            output,
+        }),
+    }
+}
@@ -0,0 +1,303 @@
+use crate::{
+    announce::count,
+    cursor::{self, Caret, Cursor, Documents, Placed, Secondaries, Selection},
+    document::{Document, DocumentChanged, DocumentEditSet},
+    pipeline::{AppPipelineExt, EditorStage},
+    text_buffer::TextBuffer,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Commands, NonSendMut, Query, ResMut},
+    },
+    log::{debug, warn},
+};
+use std::ops::Range;
+
+pub struct ClipboardPlugin;
+
+impl Plugin for ClipboardPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Clipboard>()
+            .init_non_send_resource::<SystemClipboard>()
+            .add_event::<CopyText>()
+            .add_event::<CutText>()
+            .add_event::<PasteText>()
+            .add_system(copy_text)
+            .add_editor_system(EditorStage::Edits, cut_text.label(DocumentEditSet))
+            .add_editor_system(EditorStage::Edits, paste_text.label(DocumentEditSet));
+    }
+}
+
+/// Copies the selections of a document, or the lines of its cursors if nothing is
+/// selected.
+#[derive(Clone, Copy, Debug)]
+pub struct CopyText {
+    pub entity: Entity,
+}
+
+/// Copies like [`CopyText`] and deletes what was copied.
+#[derive(Clone, Copy, Debug)]
+pub struct CutText {
+    pub entity: Entity,
+}
+
+/// Replaces the selections with the clipboard. Lines copied without a selection go above
+/// the cursor's line instead, and text copied from as many cursors is split among them.
+#[derive(Clone, Copy, Debug)]
+pub struct PasteText {
+    pub entity: Entity,
+}
+
+/// What was copied last. Kept besides the system clipboard, which holds a single string
+/// and may not be there at all, e.g. without a display.
+#[derive(Debug, Default)]
+pub struct Clipboard {
+    /// One text per cursor, in document order.
+    pieces: Vec<String>,
+    /// Whole lines, copied without a selection.
+    lines: bool,
+}
+
+impl Clipboard {
+    /// The pieces as put on the system clipboard, one per line.
+    pub fn text(&self) -> String {
+        if self.lines {
+            self.pieces.concat()
+        } else {
+            self.pieces.join("\n")
+        }
+    }
+
+    fn copy(&mut self, buffer: &TextBuffer, ranges: &[Range<usize>], lines: bool) {
+        self.pieces = ranges
+            .iter()
+            .map(|range| {
+                let mut text = buffer.text_in(range.clone());
+                if lines && !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                text
+            })
+            .collect();
+        self.lines = lines;
+    }
+}
+
+/// The OS clipboard, connected to on first use. Not `Send` everywhere, so the systems
+/// using it run on the main thread.
+#[derive(Default)]
+struct SystemClipboard(Option<Option<arboard::Clipboard>>);
+
+impl SystemClipboard {
+    fn get(&mut self) -> Option<&mut arboard::Clipboard> {
+        self.0
+            .get_or_insert_with(|| match arboard::Clipboard::new() {
+                Ok(clipboard) => Some(clipboard),
+                Err(error) => {
+                    warn!("📋 No system clipboard, copying within dip only: {error}");
+                    None
+                }
+            })
+            .as_mut()
+    }
+
+    fn set_text(&mut self, text: String) {
+        if let Some(clipboard) = self.get() {
+            if let Err(error) = clipboard.set_text(text) {
+                warn!("📋 Failed to copy to the system clipboard: {error}");
+            }
+        }
+    }
+
+    fn text(&mut self) -> Option<String> {
+        self.get()?.get_text().ok()
+    }
+}
+
+/// What copying takes from `carets`, in document order: the selections, or the whole line
+/// of each cursor if nothing is selected. True for whole lines.
+fn copied(buffer: &TextBuffer, carets: &[Caret]) -> (Vec<Range<usize>>, bool) {
+    let mut ranges: Vec<Range<usize>> = carets
+        .iter()
+        .map(Caret::range)
+        .filter(|range| !range.is_empty())
+        .collect();
+    let lines = ranges.is_empty();
+    if lines {
+        ranges = carets
+            .iter()
+            .map(|caret| line_with_feed(buffer, caret.range().start))
+            .collect();
+    }
+    ranges.sort_by_key(|range| range.start);
+    ranges.dedup();
+    (ranges, lines)
+}
+
+/// The line of `offset`, with its line feed.
+fn line_with_feed(buffer: &TextBuffer, offset: usize) -> Range<usize> {
+    let line = buffer.line_at(offset);
+    let end = if line + 1 < buffer.line_count() {
+        buffer.line_start(line + 1)
+    } else {
+        buffer.len()
+    };
+    buffer.line_start(line)..end
+}
+
+fn copy_text(
+    mut events: EventReader<CopyText>,
+    documents: Query<(&Document, &Cursor, &Selection)>,
+    mut secondaries: Secondaries,
+    (mut clipboard, mut system): (ResMut<Clipboard>, NonSendMut<SystemClipboard>),
+) {
+    for e in events.iter() {
+        let (document, cursor, selection) = match documents.get(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let carets = cursor::carets(e.entity, (cursor, selection), &mut secondaries);
+        let (ranges, lines) = copied(document.buffer(), &carets);
+        clipboard.copy(document.buffer(), &ranges, lines);
+        system.set_text(clipboard.text());
+        let what = if lines { "line" } else { "selection" };
+        debug!("📋 Copied {}", count(ranges.len(), what));
+    }
+}
+
+fn cut_text(
+    mut commands: Commands,
+    mut events: EventReader<CutText>,
+    mut documents: Documents<()>,
+    mut secondaries: Secondaries,
+    mut placed: ResMut<Placed>,
+    (mut clipboard, mut system): (ResMut<Clipboard>, NonSendMut<SystemClipboard>),
+    mut changed: EventWriter<DocumentChanged>,
+) {
+    for e in events.iter() {
+        let (mut document, mut cursor, mut selection, ()) = match documents.get_mut(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let mut carets = cursor::carets(e.entity, (&cursor, &selection), &mut secondaries);
+        let (ranges, lines) = copied(document.buffer(), &carets);
+        clipboard.copy(document.buffer(), &ranges, lines);
+        system.set_text(clipboard.text());
+        if document.is_read_only() {
+            continue;
+        }
+
+        if lines {
+            for caret in &mut carets {
+                caret.select(line_with_feed(document.buffer(), caret.range().start));
+            }
+        }
+        // Cursors on the same line are merged first, so it is deleted once.
+        let mut carets = cursor::merge(carets, &mut commands);
+        if carets.iter().all(|caret| caret.range().is_empty()) {
+            continue;
+        }
+        document.history_mut().begin();
+        cursor::edit_carets(&mut document, &mut carets, |document, caret| {
+            let range = caret.range();
+            if !range.is_empty() {
+                document.delete(range.clone());
+            }
+            range.start..range.start
+        });
+        let what = if lines { "line" } else { "selection" };
+        debug!("📋 Cut {}", count(ranges.len(), what));
+
+        let primary = cursor::primary_offset(&carets);
+        cursor::store(
+            carets,
+            (&mut cursor, &mut selection),
+            &mut secondaries,
+            &mut commands,
+        );
+        placed.0.insert((e.entity, document.version()));
+        changed.send(DocumentChanged {
+            entity: e.entity,
+            version: document.version(),
+            changes: document.take_changes(),
+            cursor: Some(primary),
+        });
+    }
+}
+
+fn paste_text(
+    mut commands: Commands,
+    mut events: EventReader<PasteText>,
+    mut documents: Documents<()>,
+    mut secondaries: Secondaries,
+    mut placed: ResMut<Placed>,
+    (clipboard, mut system): (ResMut<Clipboard>, NonSendMut<SystemClipboard>),
+    mut changed: EventWriter<DocumentChanged>,
+) {
+    for e in events.iter() {
+        let (mut document, mut cursor, mut selection, ()) = match documents.get_mut(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let ours = clipboard.text();
+        let text = system.text().unwrap_or_else(|| ours.clone());
+        if text.is_empty() || document.is_read_only() {
+            continue;
+        }
+
+        let mut carets = cursor::carets(e.entity, (&cursor, &selection), &mut secondaries);
+        carets.sort_by_key(|caret| caret.range().start);
+        // Anything copied elsewhere since is pasted as a whole at every cursor.
+        let split = text == ours && carets.len() > 1 && clipboard.pieces.len() == carets.len();
+        let lines = text == ours && clipboard.lines;
+        let pieces: Vec<(usize, &str)> = carets
+            .iter()
+            .enumerate()
+            .map(|(i, caret)| {
+                let piece = if split { &clipboard.pieces[i] } else { &text };
+                (caret.range().start, piece.as_str())
+            })
+            .collect();
+
+        document.history_mut().begin();
+        cursor::edit_carets(&mut document, &mut carets, |document, caret| {
+            let range = caret.range();
+            let (_, text) = pieces
+                .iter()
+                .find(|(start, _)| *start == range.start)
+                .expect("every caret has a piece");
+            if lines && range.is_empty() {
+                let buffer = document.buffer();
+                let start = buffer.line_start(buffer.line_at(range.start));
+                document.insert(start, text);
+                let offset = range.start + text.len();
+                offset..offset
+            } else {
+                document.delete(range.clone());
+                document.insert(range.start, text);
+                let end = range.start + text.len();
+                end..end
+            }
+        });
+        debug!("📋 Pasted at {}", count(carets.len(), "cursor"));
+
+        let primary = cursor::primary_offset(&carets);
+        cursor::store(
+            carets,
+            (&mut cursor, &mut selection),
+            &mut secondaries,
+            &mut commands,
+        );
+        placed.0.insert((e.entity, document.version()));
+        changed.send(DocumentChanged {
+            entity: e.entity,
+            version: document.version(),
+            changes: document.take_changes(),
+            cursor: Some(primary),
+        });
+    }
+}
@@ -0,0 +1,291 @@
+use crate::{
+    announce::count,
+    command::{RegisterCommand, RunCommand},
+    cursor::{self, Cursor, Documents, Placed, Secondaries, Selection},
+    document::{Document, DocumentChanged, DocumentEditSet},
+    pipeline::{AppPipelineExt, EditorStage},
+    text_buffer::TextBuffer,
+    workspace::Workspace,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Commands, Query, Res, ResMut},
+    },
+    log::{debug, warn},
+};
+use std::ops::Range;
+
+pub use dip_editor::codec::{base64_decode, base64_encode, detect, Codec, CodecError};
+
+/// Characters of decoded text shown in a suggestion.
+const PREVIEW_LEN: usize = 80;
+
+pub struct CodecPlugin;
+
+impl Plugin for CodecPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<TransformSelection>()
+            .add_event::<SuggestDecodings>()
+            .add_event::<DecodingsSuggested>()
+            .add_event::<TransformFailed>()
+            .register_command("codec.base64Encode", "Transform", "Base64 Encode")
+            .register_command("codec.base64Decode", "Transform", "Base64 Decode")
+            .register_command("codec.urlEncode", "Transform", "URL Encode")
+            .register_command("codec.urlDecode", "Transform", "URL Decode")
+            .register_command("codec.htmlEncode", "Transform", "Encode HTML Entities")
+            .register_command("codec.htmlDecode", "Transform", "Decode HTML Entities")
+            .register_command("codec.jsonEscape", "Transform", "Escape as JSON String")
+            .register_command("codec.jsonUnescape", "Transform", "Unescape JSON String")
+            .register_command("codec.hexDump", "Transform", "Hex Dump")
+            .register_command("codec.hexDecode", "Transform", "Decode Hex")
+            .register_command(
+                "codec.decodeDetected",
+                "Transform",
+                "Decode (Detect Format)",
+            )
+            .register_command("codec.suggest", "Transform", "Suggest Decodings")
+            .add_editor_system(EditorStage::Commands, run_codec_commands)
+            .add_editor_system(
+                EditorStage::Edits,
+                transform_selections.label(DocumentEditSet),
+            )
+            .add_system(suggest_decodings);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transform {
+    Encode(Codec),
+    Decode(Codec),
+    /// Decodes with the first codec [`detect`] finds for each selection.
+    DecodeDetected,
+}
+
+/// Replaces the selection of each cursor, or the run of non-blank characters around it,
+/// which ends up selected. Nothing changes if any of them fails to decode.
+#[derive(Clone, Copy, Debug)]
+pub struct TransformSelection {
+    pub entity: Entity,
+    pub transform: Transform,
+}
+
+/// Detects what the primary selection, or the run of non-blank characters around the
+/// cursor, is encoded with.
+#[derive(Clone, Copy, Debug)]
+pub struct SuggestDecodings {
+    pub entity: Entity,
+}
+
+#[derive(Clone, Debug)]
+pub struct DecodingsSuggested {
+    pub entity: Entity,
+    pub suggestions: Vec<DecodeSuggestion>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodeSuggestion {
+    pub codec: Codec,
+    /// The start of the decoded text.
+    pub preview: String,
+}
+
+/// Nothing was changed.
+#[derive(Clone, Debug)]
+pub struct TransformFailed {
+    pub entity: Entity,
+    pub error: String,
+}
+
+/// The selection, or the non-blank characters around an empty one.
+fn target(buffer: &TextBuffer, selection: Range<usize>) -> Range<usize> {
+    if !selection.is_empty() {
+        return selection;
+    }
+    let offset = selection.start.min(buffer.len());
+    let line = buffer.line_at(offset);
+    let start = buffer.line_start(line);
+    let content = buffer.get_line_content(line);
+    let column = (offset - start).min(content.len());
+    let from = content[..column].rfind(char::is_whitespace).map_or(0, |i| {
+        i + content[i..].chars().next().map_or(1, char::len_utf8)
+    });
+    let to = content[column..]
+        .find(char::is_whitespace)
+        .map_or(content.len(), |i| column + i);
+    start + from..start + to
+}
+
+fn run_codec_commands(
+    mut events: EventReader<RunCommand>,
+    workspace: Res<Workspace>,
+    mut transforms: EventWriter<TransformSelection>,
+    mut suggests: EventWriter<SuggestDecodings>,
+) {
+    for e in events.iter() {
+        let entity = match (e.id.starts_with("codec."), workspace.active()) {
+            (true, Some(entity)) => entity,
+            _ => continue,
+        };
+        let transform = match e.id.as_str() {
+            "codec.base64Encode" => Transform::Encode(Codec::Base64),
+            "codec.base64Decode" => Transform::Decode(Codec::Base64),
+            "codec.urlEncode" => Transform::Encode(Codec::Url),
+            "codec.urlDecode" => Transform::Decode(Codec::Url),
+            "codec.htmlEncode" => Transform::Encode(Codec::Html),
+            "codec.htmlDecode" => Transform::Decode(Codec::Html),
+            "codec.jsonEscape" => Transform::Encode(Codec::Json),
+            "codec.jsonUnescape" => Transform::Decode(Codec::Json),
+            "codec.hexDump" => Transform::Encode(Codec::Hex),
+            "codec.hexDecode" => Transform::Decode(Codec::Hex),
+            "codec.decodeDetected" => Transform::DecodeDetected,
+            "codec.suggest" => {
+                suggests.send(SuggestDecodings { entity });
+                continue;
+            }
+            _ => continue,
+        };
+        transforms.send(TransformSelection { entity, transform });
+    }
+}
+
+fn transform_selections(
+    mut commands: Commands,
+    mut events: EventReader<TransformSelection>,
+    mut documents: Documents<()>,
+    mut secondaries: Secondaries,
+    mut placed: ResMut<Placed>,
+    mut changed: EventWriter<DocumentChanged>,
+    mut failed: EventWriter<TransformFailed>,
+) {
+    for e in events.iter() {
+        let (mut document, mut cursor, mut selection, ()) = match documents.get_mut(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let mut fail = |error: String| {
+            warn!("🔣 {error}");
+            failed.send(TransformFailed {
+                entity: e.entity,
+                error,
+            });
+        };
+        if document.is_read_only() {
+            fail("The document is read only".to_string());
+            continue;
+        }
+        let mut carets = cursor::carets(e.entity, (&cursor, &selection), &mut secondaries);
+
+        // The text replacing each caret's target, by the caret's range.
+        let buffer = document.buffer();
+        let mut edits: Vec<(Range<usize>, Range<usize>, String)> = vec![];
+        let mut error = None;
+        for caret in &carets {
+            let range = target(buffer, caret.range());
+            let text = buffer.text_in(range.clone());
+            if text.is_empty() || edits.iter().any(|(_, r, _)| r.start == range.start) {
+                continue;
+            }
+            let result = match e.transform {
+                Transform::Encode(codec) => Ok(codec.encode(&text)),
+                Transform::Decode(codec) => codec
+                    .decode(&text)
+                    .map_err(|error| format!("Failed to decode {}: {error}", codec.name())),
+                Transform::DecodeDetected => match detect(&text).first() {
+                    Some(codec) => codec
+                        .decode(&text)
+                        .map_err(|error| format!("Failed to decode {}: {error}", codec.name())),
+                    None => Err(format!("No encoding detected in {}", preview(&text))),
+                },
+            };
+            match result {
+                Ok(result) => edits.push((caret.range(), range, result)),
+                Err(e) => {
+                    error = Some(e);
+                    break;
+                }
+            }
+        }
+        if let Some(error) = error {
+            fail(error);
+            continue;
+        }
+        if edits.is_empty() {
+            fail("Nothing to transform".to_string());
+            continue;
+        }
+
+        document.history_mut().begin();
+        cursor::edit_carets(&mut document, &mut carets, |document, caret| {
+            match edits.iter().find(|(r, ..)| *r == caret.range()) {
+                Some((_, range, text)) => {
+                    document.delete(range.clone());
+                    document.insert(range.start, text);
+                    range.start..range.start + text.len()
+                }
+                None => caret.range(),
+            }
+        });
+        cursor::store(
+            carets,
+            (&mut cursor, &mut selection),
+            &mut secondaries,
+            &mut commands,
+        );
+        placed.0.insert((e.entity, document.version()));
+        debug!(
+            "🔣 {:?} of {}",
+            e.transform,
+            count(edits.len(), "selection")
+        );
+        changed.send(DocumentChanged {
+            entity: e.entity,
+            version: document.version(),
+            changes: document.take_changes(),
+            cursor: None,
+        });
+    }
+}
+
+fn preview(text: &str) -> String {
+    match text.char_indices().nth(PREVIEW_LEN) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+fn suggest_decodings(
+    mut events: EventReader<SuggestDecodings>,
+    documents: Query<(&Document, &Cursor, &Selection)>,
+    mut suggested: EventWriter<DecodingsSuggested>,
+) {
+    for e in events.iter() {
+        let (document, cursor, selection) = match documents.get(e.entity) {
+            Ok(found) => found,
+            Err(_) => continue,
+        };
+        let buffer = document.buffer();
+        let text = buffer.text_in(target(buffer, selection.range(cursor)));
+        let suggestions: Vec<DecodeSuggestion> = detect(&text)
+            .into_iter()
+            .filter_map(|codec| {
+                Some(DecodeSuggestion {
+                    codec,
+                    preview: preview(&codec.decode(&text).ok()?),
+                })
+            })
+            .collect();
+        debug!(
+            "🔣 {} for {}",
+            count(suggestions.len(), "decoding"),
+            preview(&text)
+        );
+        suggested.send(DecodingsSuggested {
+            entity: e.entity,
+            suggestions,
+        });
+    }
+}
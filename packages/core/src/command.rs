@@ -1,12 +1,426 @@
-use crate::Mode;
+use crate::{
+    announce::Announcement,
+    clipboard::{CopyText, CutText, PasteText},
+    cursor::{
+        AddCursor, AddCursorAtNextOccurrence, ClearSecondaryCursors, DeleteText, MoveCursor,
+        Movement, TypeText,
+    },
+    document::{RedoDocument, SaveDocument, UndoDocument},
+    fuzzy::fuzzy_match,
+    gutter::{Gutter, GutterClick},
+    keymap::Keymap,
+    minimap::Minimap,
+    pipeline::{AppPipelineExt, EditorStage},
+    playback::{PlaybackControl, PlaybackState},
+    presence::PresenceTags,
+    render::Frame,
+    scroll::WheelUnit,
+    status_bar::StatusSegment,
+    tab::{CloseAllTabs, ReopenClosedTab, TabAction, TabStrip},
+    theme::DesignTokens,
+    workspace::{CloseDocument, Workspace},
+    zoom::{ChangeZoom, ZoomChange},
+    Mode, ModeType,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        event::{EventReader, EventWriter},
+        schedule::{ParallelSystemDescriptorCoercion, SystemLabel},
+        system::{Res, SystemParam},
+    },
+    log::{debug, warn},
+};
+use std::path::PathBuf;
 
 #[derive(Debug, Clone)]
 pub enum CoreCommand {
     Click,
     Exit,
+    /// Runs a registered command, e.g. picked from the command palette.
+    Run(String),
+    /// The editor area was resized to this many physical pixels.
+    Resize {
+        width: f32,
+        height: f32,
+    },
+    /// The document on screen was scrolled to `top` and `left` physical pixels.
+    Scroll {
+        top: f32,
+        left: f32,
+    },
+    /// The mouse wheel or trackpad scrolled the document on screen by `x` and `y`.
+    Wheel {
+        x: f32,
+        y: f32,
+        unit: WheelUnit,
+    },
+    GutterClick(GutterClick),
+    /// The mark of the part in view was dragged to `y` physical pixels from the top of the
+    /// minimap.
+    MinimapDrag {
+        y: f32,
+    },
+    /// From the scrubber of the replay on screen.
+    Playback(PlaybackControl),
+    /// Picked from the list of [`UICommand::RecentlyClosed`].
+    ReopenClosedTab(usize),
+    Tab(TabAction),
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum UICommand {
     ModeChange(Mode),
+    /// The view restyles itself with these, sent once on startup too.
+    ThemeChange(DesignTokens),
+    /// The visible lines of the document on screen, after any of them were drawn again.
+    Render(Frame),
+    Gutter(Gutter),
+    /// None hides the minimap.
+    Minimap(Option<Minimap>),
+    /// The carets of the other participants of a shared session on screen.
+    Presence(PresenceTags),
+    /// The segments of the status bar, sent when any changed.
+    StatusBar(Vec<StatusSegment>),
+    /// The replay on screen, None once none is.
+    Playback(Option<PlaybackState>),
+    /// The paths of recently closed tabs, most recent first, sent when they changed.
+    RecentlyClosed(Vec<PathBuf>),
+    /// The tabs of the editor group, sent when they changed.
+    Tabs(TabStrip),
+    /// Read out by screen readers, the same text again included.
+    Announce(Announcement),
+    /// Raises the window, e.g. for a file opened from a terminal.
+    FocusWindow,
+}
+
+pub struct CommandPlugin;
+
+/// The system turning built-in commands into the events of their plugins.
+#[derive(SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RunCommands;
+
+impl Plugin for CommandPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CommandRegistry>()
+            .add_event::<RunCommand>()
+            .add_editor_system(EditorStage::Commands, run_core_commands)
+            .add_editor_system(EditorStage::Commands, warn_unknown_commands)
+            .add_editor_system(
+                EditorStage::Commands,
+                run_builtin_commands.label(RunCommands),
+            );
+    }
+}
+
+/// Movements of the built-in `cursor.`, `select.` and `delete.` commands, with their
+/// titles. Only the ones within a line delete.
+const MOVEMENTS: &[(&str, Movement, &str, bool)] = &[
+    ("left", Movement::Left, "Left", true),
+    ("right", Movement::Right, "Right", true),
+    ("wordLeft", Movement::WordLeft, "Word Left", true),
+    ("wordRight", Movement::WordRight, "Word Right", true),
+    ("up", Movement::Up, "Up", false),
+    ("down", Movement::Down, "Down", false),
+    ("pageUp", Movement::PageUp, "Page Up", false),
+    ("pageDown", Movement::PageDown, "Page Down", false),
+    ("lineStart", Movement::LineStart, "Line Start", true),
+    ("lineEnd", Movement::LineEnd, "Line End", true),
+    (
+        "documentStart",
+        Movement::DocumentStart,
+        "Document Start",
+        false,
+    ),
+    ("documentEnd", Movement::DocumentEnd, "Document End", false),
+];
+
+/// Built-in commands that only type text, e.g. for macros to record the text instead.
+pub const TYPING_COMMANDS: &[(&str, &str)] = &[("edit.newline", "\n"), ("edit.tab", "\t")];
+
+const BUILTINS: &[(&str, &str, &str)] = &[
+    ("edit.newline", "Edit", "Insert Line Break"),
+    ("edit.tab", "Edit", "Insert Tab"),
+    ("edit.undo", "Edit", "Undo"),
+    ("edit.redo", "Edit", "Redo"),
+    ("edit.copy", "Edit", "Copy"),
+    ("edit.cut", "Edit", "Cut"),
+    ("edit.paste", "Edit", "Paste"),
+    ("cursor.addAbove", "Cursor", "Add Cursor Above"),
+    ("cursor.addBelow", "Cursor", "Add Cursor Below"),
+    (
+        "cursor.addNextOccurrence",
+        "Cursor",
+        "Add Cursor at Next Occurrence",
+    ),
+    (
+        "cursor.clearSecondary",
+        "Cursor",
+        "Remove Secondary Cursors",
+    ),
+    ("file.save", "File", "Save"),
+    ("file.close", "File", "Close"),
+    ("tab.closeAll", "Tab", "Close All"),
+    ("tab.reopenClosed", "Tab", "Reopen Closed Tab"),
+    ("view.zoomIn", "View", "Zoom In"),
+    ("view.zoomOut", "View", "Zoom Out"),
+    ("view.zoomReset", "View", "Reset Zoom"),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandInfo {
+    /// What keymaps and [`RunCommand`] name it by, e.g. `edit.undo`.
+    pub id: String,
+    pub category: String,
+    pub title: String,
+}
+
+impl CommandInfo {
+    pub fn new(
+        id: impl Into<String>,
+        category: impl Into<String>,
+        title: impl Into<String>,
+    ) -> Self {
+        Self {
+            id: id.into(),
+            category: category.into(),
+            title: title.into(),
+        }
+    }
+
+    /// How the command palette shows it, e.g. `Edit: Undo`.
+    pub fn label(&self) -> String {
+        format!("{}: {}", self.category, self.title)
+    }
+}
+
+/// Every command that can be run, for the command palette to list.
+#[derive(Debug)]
+pub struct CommandRegistry {
+    commands: Vec<CommandInfo>,
+}
+
+impl Default for CommandRegistry {
+    fn default() -> Self {
+        let mut registry = Self { commands: vec![] };
+        for (name, _, title, deletes) in MOVEMENTS {
+            registry.register(CommandInfo::new(
+                format!("cursor.{name}"),
+                "Cursor",
+                format!("Move {title}"),
+            ));
+            registry.register(CommandInfo::new(
+                format!("select.{name}"),
+                "Selection",
+                format!("Select {title}"),
+            ));
+            if *deletes {
+                registry.register(CommandInfo::new(
+                    format!("delete.{name}"),
+                    "Edit",
+                    format!("Delete {title}"),
+                ));
+            }
+        }
+        for (id, category, title) in BUILTINS {
+            registry.register(CommandInfo::new(*id, *category, *title));
+        }
+        registry
+    }
+}
+
+/// A command found by [`CommandRegistry::search`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandMatch {
+    pub id: String,
+    pub label: String,
+    /// The keys running it in the current keymap, e.g. `ctrl+z`.
+    pub keybinding: Option<String>,
+    pub score: i32,
+    /// Char indices of `label` matching the query, to highlight.
+    pub positions: Vec<usize>,
+}
+
+impl CommandRegistry {
+    /// Adds a command, or replaces the one with the same id.
+    pub fn register(&mut self, info: CommandInfo) {
+        match self.commands.iter_mut().find(|c| c.id == info.id) {
+            Some(existing) => *existing = info,
+            None => self.commands.push(info),
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&CommandInfo> {
+        self.commands.iter().find(|c| c.id == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &CommandInfo> {
+        self.commands.iter()
+    }
+
+    /// Commands whose label fuzzy matches `query`, best first. All of them by label
+    /// for an empty query.
+    pub fn search(
+        &self,
+        query: &str,
+        keymap: &Keymap,
+        mode: Option<ModeType>,
+    ) -> Vec<CommandMatch> {
+        let mut matches: Vec<CommandMatch> = self
+            .commands
+            .iter()
+            .filter_map(|command| {
+                let label = command.label();
+                let (score, positions) = fuzzy_match(query, &label)?;
+                Some(CommandMatch {
+                    id: command.id.clone(),
+                    keybinding: keymap.keybinding(&command.id, mode),
+                    label,
+                    score,
+                    positions,
+                })
+            })
+            .collect();
+        matches.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.label.cmp(&b.label)));
+        matches
+    }
+}
+
+/// Lets plugins add their commands while the app is built.
+pub trait RegisterCommand {
+    fn register_command(&mut self, id: &str, category: &str, title: &str) -> &mut Self;
+}
+
+impl RegisterCommand for App {
+    fn register_command(&mut self, id: &str, category: &str, title: &str) -> &mut Self {
+        self.init_resource::<CommandRegistry>();
+        self.world
+            .get_resource_mut::<CommandRegistry>()
+            .unwrap()
+            .register(CommandInfo::new(id, category, title));
+        self
+    }
+}
+
+/// Runs the command `id`. Each plugin runs its own; the built-in ones act on the active
+/// document.
+#[derive(Clone, Debug)]
+pub struct RunCommand {
+    pub id: String,
+}
+
+fn run_core_commands(mut events: EventReader<CoreCommand>, mut runs: EventWriter<RunCommand>) {
+    for e in events.iter() {
+        if let CoreCommand::Run(id) = e {
+            runs.send(RunCommand { id: id.clone() });
+        }
+    }
+}
+
+fn warn_unknown_commands(mut events: EventReader<RunCommand>, registry: Res<CommandRegistry>) {
+    for e in events.iter() {
+        match registry.get(&e.id) {
+            Some(command) => debug!("🧠 Running {}", command.label()),
+            None => warn!("🧠 Unknown command {}", e.id),
+        }
+    }
+}
+
+/// Where the built-in commands go.
+#[derive(SystemParam)]
+struct Targets<'w, 's> {
+    moves: EventWriter<'w, 's, MoveCursor>,
+    types: EventWriter<'w, 's, TypeText>,
+    deletes: EventWriter<'w, 's, DeleteText>,
+    add_cursors: EventWriter<'w, 's, AddCursor>,
+    next_occurrences: EventWriter<'w, 's, AddCursorAtNextOccurrence>,
+    clear_cursors: EventWriter<'w, 's, ClearSecondaryCursors>,
+    undos: EventWriter<'w, 's, UndoDocument>,
+    redos: EventWriter<'w, 's, RedoDocument>,
+    copies: EventWriter<'w, 's, CopyText>,
+    cuts: EventWriter<'w, 's, CutText>,
+    pastes: EventWriter<'w, 's, PasteText>,
+    saves: EventWriter<'w, 's, SaveDocument>,
+    closes: EventWriter<'w, 's, CloseDocument>,
+    zooms: EventWriter<'w, 's, ChangeZoom>,
+    reopens: EventWriter<'w, 's, ReopenClosedTab>,
+    close_all: EventWriter<'w, 's, CloseAllTabs>,
+}
+
+fn run_builtin_commands(
+    mut events: EventReader<RunCommand>,
+    workspace: Res<Workspace>,
+    mut targets: Targets,
+) {
+    for e in events.iter() {
+        // Not about the active document.
+        match e.id.as_str() {
+            "tab.reopenClosed" => {
+                targets.reopens.send(ReopenClosedTab::Last);
+                continue;
+            }
+            "tab.closeAll" => {
+                targets.close_all.send(CloseAllTabs);
+                continue;
+            }
+            _ => {}
+        }
+        let entity = match workspace.active() {
+            Some(entity) => entity,
+            None => continue,
+        };
+        let (group, name) = e.id.split_once('.').unwrap_or(("", &e.id));
+        let movement = MOVEMENTS.iter().find(|(n, ..)| *n == name);
+        if let Some((_, movement, ..)) = movement {
+            match group {
+                "cursor" | "select" => targets.moves.send(MoveCursor {
+                    entity,
+                    movement: *movement,
+                    select: group == "select",
+                }),
+                "delete" => targets.deletes.send(DeleteText {
+                    entity,
+                    movement: *movement,
+                }),
+                _ => {}
+            }
+            continue;
+        }
+        if let Some((_, text)) = TYPING_COMMANDS.iter().find(|(id, _)| *id == e.id) {
+            targets.types.send(TypeText {
+                entity,
+                text: text.to_string(),
+            });
+            continue;
+        }
+        match e.id.as_str() {
+            "edit.undo" => targets.undos.send(UndoDocument { entity }),
+            "edit.redo" => targets.redos.send(RedoDocument { entity }),
+            "edit.copy" => targets.copies.send(CopyText { entity }),
+            "edit.cut" => targets.cuts.send(CutText { entity }),
+            "edit.paste" => targets.pastes.send(PasteText { entity }),
+            "cursor.addAbove" | "cursor.addBelow" => targets.add_cursors.send(AddCursor {
+                entity,
+                above: e.id == "cursor.addAbove",
+            }),
+            "cursor.addNextOccurrence" => targets
+                .next_occurrences
+                .send(AddCursorAtNextOccurrence { entity }),
+            "cursor.clearSecondary" => targets.clear_cursors.send(ClearSecondaryCursors { entity }),
+            "file.save" => targets.saves.send(SaveDocument { entity, path: None }),
+            "file.close" => targets.closes.send(CloseDocument {
+                entity,
+                discard: false,
+            }),
+            "view.zoomIn" | "view.zoomOut" | "view.zoomReset" => {
+                let change = match name {
+                    "zoomIn" => ZoomChange::In,
+                    "zoomOut" => ZoomChange::Out,
+                    _ => ZoomChange::Reset,
+                };
+                targets.zooms.send(ChangeZoom { entity, change });
+            }
+            _ => {}
+        }
+    }
 }
@@ -0,0 +1,619 @@
+use crate::{
+    command::{RegisterCommand, RunCommand},
+    cursor::Cursor,
+    document::{Change, Document, DocumentChanged, DocumentEditSet},
+    fuzzy::fuzzy_match,
+    keymap::{parse_keys, Binding, Keymap, Layer},
+    lsp::{CompletionsReady, RequestCompletion},
+    pipeline::{AppPipelineExt, EditorStage},
+    text_buffer::TextBuffer,
+    workspace::Workspace,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        query::Changed,
+        schedule::{ParallelSystemDescriptorCoercion, SystemLabel},
+        system::{Commands, Local, Query, Res, ResMut},
+    },
+    log::debug,
+};
+use lsp_types::CompletionItemKind;
+use std::{collections::HashSet, ops::Range};
+
+/// More than anyone scrolls through, and ranking all of a large buffer's words is slow.
+const MAX_SUGGESTIONS: usize = 100;
+/// Lines above and below the cursor the words of the buffer are taken from.
+const WORD_LINES: usize = 5000;
+const LAYER: &str = "completion";
+/// What server suggestions are offered as.
+const SERVER_PROVIDER: &str = "language server";
+
+pub struct CompletionPlugin;
+
+impl Plugin for CompletionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CompletionProviders>()
+            .add_event::<TriggerCompletion>()
+            .add_event::<SelectCompletion>()
+            .add_event::<AcceptCompletion>()
+            .add_event::<DismissCompletion>()
+            .add_event::<CompletionsChanged>()
+            .register_completion_provider(Words)
+            .register_command("completion.trigger", "Completion", "Trigger Suggest")
+            .register_command("completion.next", "Completion", "Select Next Suggestion")
+            .register_command(
+                "completion.previous",
+                "Completion",
+                "Select Previous Suggestion",
+            )
+            .register_command("completion.accept", "Completion", "Accept Suggestion")
+            .register_command("completion.dismiss", "Completion", "Hide Suggestions")
+            .add_editor_system(EditorStage::Commands, run_completion_commands)
+            .add_system(trigger_completions)
+            .add_editor_system(
+                EditorStage::Edits,
+                follow_completions.label(FollowCompletions),
+            )
+            .add_editor_system(
+                EditorStage::Edits,
+                accept_completions
+                    .label(DocumentEditSet)
+                    .after(FollowCompletions),
+            )
+            .add_system(bind_completion_keys);
+    }
+}
+
+#[derive(SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct FollowCompletions;
+
+/// Suggests completions of the word before the primary cursor. They are filtered as
+/// typing goes on, until one is accepted, the cursor leaves the word or they are dismissed.
+#[derive(Clone, Copy, Debug)]
+pub struct TriggerCompletion {
+    pub entity: Entity,
+}
+
+/// Moves the selected suggestion by `delta`, wrapping around.
+#[derive(Clone, Copy, Debug)]
+pub struct SelectCompletion {
+    pub entity: Entity,
+    pub delta: isize,
+}
+
+/// Replaces the word with a suggestion, the selected one without an index.
+#[derive(Clone, Copy, Debug)]
+pub struct AcceptCompletion {
+    pub entity: Entity,
+    pub index: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DismissCompletion {
+    pub entity: Entity,
+}
+
+/// The [`Completion`] of a document was opened, filtered, selected in or closed.
+#[derive(Clone, Copy, Debug)]
+pub struct CompletionsChanged {
+    pub entity: Entity,
+}
+
+/// Something a provider offers to complete a word with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Candidate {
+    /// What is shown and matched against the word.
+    pub label: String,
+    /// Inserted in place of the word.
+    pub text: String,
+    pub detail: Option<String>,
+    pub kind: Option<CompletionItemKind>,
+    /// Replaced instead of the word before the cursor, as the text stood when offered.
+    pub range: Option<Range<usize>>,
+    /// The name of the provider that offered it.
+    pub provider: String,
+}
+
+impl Candidate {
+    pub fn new(label: impl Into<String>) -> Self {
+        let label = label.into();
+        Self {
+            text: label.clone(),
+            label,
+            detail: None,
+            kind: None,
+            range: None,
+            provider: String::new(),
+        }
+    }
+}
+
+/// A candidate that matched the word, with how well.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Suggestion {
+    pub candidate: Candidate,
+    pub score: i32,
+    /// The char indices of the label the word matched, to highlight them.
+    pub positions: Vec<usize>,
+}
+
+/// Offers candidates for the word being typed.
+pub trait CompletionProvider: Send + Sync + 'static {
+    fn name(&self) -> &str;
+
+    /// Candidates for `prefix`, the word ending at `offset`. They are filtered and ranked
+    /// afterwards, so more than match may be returned.
+    fn complete(&self, document: &Document, offset: usize, prefix: &str) -> Vec<Candidate>;
+}
+
+/// What plugins registered with [`RegisterCompletionProvider`].
+#[derive(Default)]
+pub struct CompletionProviders {
+    providers: Vec<Box<dyn CompletionProvider>>,
+}
+
+impl CompletionProviders {
+    /// The candidates of every provider, in the order they were registered.
+    pub fn complete(&self, document: &Document, offset: usize, prefix: &str) -> Vec<Candidate> {
+        self.providers
+            .iter()
+            .flat_map(|provider| {
+                provider
+                    .complete(document, offset, prefix)
+                    .into_iter()
+                    .map(|candidate| Candidate {
+                        provider: provider.name().to_string(),
+                        ..candidate
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Lets plugins offer completions while the app is built.
+pub trait RegisterCompletionProvider {
+    fn register_completion_provider(&mut self, provider: impl CompletionProvider) -> &mut Self;
+}
+
+impl RegisterCompletionProvider for App {
+    fn register_completion_provider(&mut self, provider: impl CompletionProvider) -> &mut Self {
+        self.init_resource::<CompletionProviders>();
+        self.world
+            .get_resource_mut::<CompletionProviders>()
+            .unwrap()
+            .providers
+            .push(Box::new(provider));
+        self
+    }
+}
+
+/// The words of the document around the cursor, besides the one being typed.
+pub struct Words;
+
+impl CompletionProvider for Words {
+    fn name(&self) -> &str {
+        "words"
+    }
+
+    fn complete(&self, document: &Document, offset: usize, _prefix: &str) -> Vec<Candidate> {
+        let buffer = document.buffer();
+        let line = buffer.line_at(offset);
+        let lines = line.saturating_sub(WORD_LINES)..(line + WORD_LINES).min(buffer.line_count());
+        let mut seen = HashSet::new();
+        let mut candidates = vec![];
+        for line in lines {
+            let start = buffer.line_start(line);
+            let content = buffer.get_line_content(line);
+            for (range, word) in words(&content) {
+                let touches = start + range.start <= offset && offset <= start + range.end;
+                if touches || word.chars().count() < 2 || !seen.insert(word.to_string()) {
+                    continue;
+                }
+                candidates.push(Candidate::new(word));
+            }
+        }
+        candidates
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The byte ranges of the words in `text`, with them.
+fn words(text: &str) -> impl Iterator<Item = (Range<usize>, &str)> {
+    let mut start = None;
+    text.char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+        .filter_map(move |(i, c)| match (is_word(c), start) {
+            (true, None) => {
+                start = Some(i);
+                None
+            }
+            (false, Some(from)) => {
+                start = None;
+                Some((from..i, &text[from..i]))
+            }
+            _ => None,
+        })
+}
+
+fn word_start(buffer: &TextBuffer, offset: usize) -> usize {
+    let start = buffer.line_start(buffer.line_at(offset));
+    let line = buffer.text_in(start..offset);
+    let word = line
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_word(*c))
+        .last();
+    start + word.map_or(line.len(), |(i, _)| i)
+}
+
+/// The candidates matching `prefix`, best first: those starting with it as typed, then
+/// ignoring case, then the rest by how well they fuzzy match. Only the first of candidates
+/// with the same label is kept.
+pub fn rank(prefix: &str, candidates: Vec<Candidate>) -> Vec<Suggestion> {
+    let lower = prefix.to_lowercase();
+    let mut labels = HashSet::new();
+    let mut suggestions: Vec<(u8, Suggestion)> = candidates
+        .into_iter()
+        .filter(|candidate| labels.insert(candidate.label.clone()))
+        .filter_map(|candidate| {
+            let (score, positions) = fuzzy_match(prefix, &candidate.label)?;
+            let class = if candidate.label.starts_with(prefix) {
+                0
+            } else if candidate.label.to_lowercase().starts_with(&lower) {
+                1
+            } else {
+                2
+            };
+            let suggestion = Suggestion {
+                candidate,
+                score,
+                positions,
+            };
+            Some((class, suggestion))
+        })
+        .collect();
+    suggestions.sort_by(|(a_class, a), (b_class, b)| {
+        a_class
+            .cmp(b_class)
+            .then(b.score.cmp(&a.score))
+            .then(a.candidate.label.len().cmp(&b.candidate.label.len()))
+            .then_with(|| a.candidate.label.cmp(&b.candidate.label))
+    });
+    suggestions.truncate(MAX_SUGGESTIONS);
+    suggestions.into_iter().map(|(_, s)| s).collect()
+}
+
+/// Suggestions open for a document.
+#[derive(Component, Clone, Debug)]
+pub struct Completion {
+    /// Where the word being completed starts.
+    pub start: usize,
+    /// The cursor, the end of the word.
+    pub offset: usize,
+    pub suggestions: Vec<Suggestion>,
+    pub selected: usize,
+    candidates: Vec<Candidate>,
+    version: u64,
+    /// Where the language server was last asked, to tell its answer from stale ones.
+    requested: usize,
+    /// The server wants to be asked again as typing goes on.
+    incomplete: bool,
+}
+
+impl Completion {
+    pub fn selected(&self) -> Option<&Suggestion> {
+        self.suggestions.get(self.selected)
+    }
+
+    fn prefix(&self, buffer: &TextBuffer) -> String {
+        buffer.text_in(self.start..self.offset).to_string()
+    }
+
+    fn rank(&mut self, buffer: &TextBuffer) {
+        let selected = self.selected().map(|s| s.candidate.label.clone());
+        self.suggestions = rank(&self.prefix(buffer), self.candidates.clone());
+        self.selected = selected
+            .and_then(|label| {
+                self.suggestions
+                    .iter()
+                    .position(|s| s.candidate.label == label)
+            })
+            .unwrap_or(0);
+    }
+
+    /// Follows `change`, false once it left the word.
+    fn follow(&mut self, change: &Change) -> bool {
+        let range = &change.range;
+        let shift = |offset: usize| offset - range.len() + change.text.len();
+        if range.start > self.offset {
+            return true;
+        }
+        if range.end <= self.start && range.start < self.start {
+            self.start = shift(self.start);
+            self.offset = shift(self.offset);
+            self.requested = shift(self.requested);
+        } else if range.start >= self.start && range.end <= self.offset {
+            self.offset = shift(self.offset);
+        } else {
+            return false;
+        }
+        let map = |offset: usize, inside: usize| {
+            if offset <= range.start {
+                offset
+            } else if offset >= range.end {
+                shift(offset)
+            } else {
+                inside
+            }
+        };
+        for candidate in &mut self.candidates {
+            if let Some(r) = &mut candidate.range {
+                let start = map(r.start, range.start);
+                *r = start..map(r.end, range.start + change.text.len()).max(start);
+            }
+        }
+        true
+    }
+}
+
+fn run_completion_commands(
+    mut events: EventReader<RunCommand>,
+    workspace: Res<Workspace>,
+    mut triggers: EventWriter<TriggerCompletion>,
+    mut selects: EventWriter<SelectCompletion>,
+    mut accepts: EventWriter<AcceptCompletion>,
+    mut dismisses: EventWriter<DismissCompletion>,
+) {
+    for e in events.iter() {
+        let entity = match (e.id.starts_with("completion."), workspace.active()) {
+            (true, Some(entity)) => entity,
+            _ => continue,
+        };
+        match e.id.as_str() {
+            "completion.trigger" => triggers.send(TriggerCompletion { entity }),
+            "completion.next" => selects.send(SelectCompletion { entity, delta: 1 }),
+            "completion.previous" => selects.send(SelectCompletion { entity, delta: -1 }),
+            "completion.accept" => accepts.send(AcceptCompletion {
+                entity,
+                index: None,
+            }),
+            "completion.dismiss" => dismisses.send(DismissCompletion { entity }),
+            _ => {}
+        }
+    }
+}
+
+fn trigger_completions(
+    mut commands: Commands,
+    mut events: EventReader<TriggerCompletion>,
+    documents: Query<(&Document, &Cursor)>,
+    providers: Res<CompletionProviders>,
+    mut requests: EventWriter<RequestCompletion>,
+    mut changed: EventWriter<CompletionsChanged>,
+) {
+    for e in events.iter() {
+        let (document, cursor) = match documents.get(e.entity) {
+            Ok(found) => found,
+            Err(_) => continue,
+        };
+        let buffer = document.buffer();
+        // The cursor catches up with other edits of this frame later.
+        let offset = cursor.offset.min(buffer.len());
+        let start = word_start(buffer, offset);
+        let prefix = buffer.text_in(start..offset).to_string();
+        let mut completion = Completion {
+            start,
+            offset,
+            suggestions: vec![],
+            selected: 0,
+            candidates: providers.complete(document, offset, &prefix),
+            version: document.version(),
+            requested: offset,
+            incomplete: false,
+        };
+        completion.rank(buffer);
+        debug!(
+            "💡 {} suggestions for {prefix:?}",
+            completion.suggestions.len()
+        );
+        commands.entity(e.entity).insert(completion);
+        requests.send(RequestCompletion {
+            entity: e.entity,
+            offset,
+        });
+        changed.send(CompletionsChanged { entity: e.entity });
+    }
+}
+
+/// Keeps suggestions in step with edits, the cursor and the language server.
+fn follow_completions(
+    mut commands: Commands,
+    (mut edits, mut ready): (EventReader<DocumentChanged>, EventReader<CompletionsReady>),
+    (mut selects, mut dismisses): (
+        EventReader<SelectCompletion>,
+        EventReader<DismissCompletion>,
+    ),
+    mut completions: Query<(&Document, &mut Completion)>,
+    moved: Query<(Entity, &Cursor), Changed<Cursor>>,
+    mut requests: EventWriter<RequestCompletion>,
+    mut changed: EventWriter<CompletionsChanged>,
+) {
+    let mut closed = HashSet::new();
+    for e in edits.iter() {
+        let (document, mut completion) = match completions.get_mut(e.entity) {
+            Ok(found) => found,
+            Err(_) => continue,
+        };
+        if e.version <= completion.version || closed.contains(&e.entity) {
+            continue;
+        }
+        completion.version = e.version;
+        let buffer = document.buffer();
+        let followed = e.changes.iter().all(|change| completion.follow(change));
+        if !followed
+            || completion.offset > buffer.len()
+            || !completion.prefix(buffer).chars().all(is_word)
+        {
+            closed.insert(e.entity);
+            continue;
+        }
+        completion.rank(buffer);
+        if completion.incomplete {
+            completion.requested = completion.offset;
+            requests.send(RequestCompletion {
+                entity: e.entity,
+                offset: completion.offset,
+            });
+        }
+        changed.send(CompletionsChanged { entity: e.entity });
+    }
+
+    for (entity, cursor) in moved.iter() {
+        if let Ok((_, completion)) = completions.get(entity) {
+            if cursor.offset != completion.offset {
+                closed.insert(entity);
+            }
+        }
+    }
+    for e in dismisses.iter() {
+        if completions.get(e.entity).is_ok() {
+            closed.insert(e.entity);
+        }
+    }
+
+    for e in ready.iter() {
+        let (document, mut completion) = match completions.get_mut(e.entity) {
+            Ok(found) => found,
+            Err(_) => continue,
+        };
+        if e.offset != completion.requested {
+            continue;
+        }
+        // Server suggestions go first, so they win over words with the same label.
+        let mut candidates: Vec<Candidate> = e
+            .items
+            .iter()
+            .map(|item| Candidate {
+                label: item.label.clone(),
+                text: item.text.clone(),
+                detail: item.detail.clone(),
+                kind: item.kind,
+                range: Some(item.range.clone()),
+                provider: SERVER_PROVIDER.to_string(),
+            })
+            .collect();
+        candidates.extend(
+            completion
+                .candidates
+                .drain(..)
+                .filter(|c| c.provider != SERVER_PROVIDER),
+        );
+        completion.candidates = candidates;
+        completion.incomplete = e.incomplete;
+        completion.rank(document.buffer());
+        changed.send(CompletionsChanged { entity: e.entity });
+    }
+
+    for e in selects.iter() {
+        let mut completion = match completions.get_mut(e.entity) {
+            Ok((_, completion)) => completion,
+            Err(_) => continue,
+        };
+        let len = completion.suggestions.len() as isize;
+        if len == 0 {
+            continue;
+        }
+        completion.selected = (completion.selected as isize + e.delta).rem_euclid(len) as usize;
+        changed.send(CompletionsChanged { entity: e.entity });
+    }
+
+    for entity in closed {
+        commands.entity(entity).remove::<Completion>();
+        changed.send(CompletionsChanged { entity });
+    }
+}
+
+fn accept_completions(
+    mut commands: Commands,
+    mut events: EventReader<AcceptCompletion>,
+    mut documents: Query<(&mut Document, &Completion)>,
+    mut edited: EventWriter<DocumentChanged>,
+    mut changed: EventWriter<CompletionsChanged>,
+) {
+    for e in events.iter() {
+        let (mut document, completion) = match documents.get_mut(e.entity) {
+            Ok(found) => found,
+            Err(_) => continue,
+        };
+        let suggestion = match completion
+            .suggestions
+            .get(e.index.unwrap_or(completion.selected))
+        {
+            Some(suggestion) => suggestion,
+            None => continue,
+        };
+        if document.is_read_only() {
+            continue;
+        }
+        let candidate = &suggestion.candidate;
+        let range = match &candidate.range {
+            Some(range) => range.start.min(completion.start)..range.end.max(completion.offset),
+            None => completion.start..completion.offset,
+        };
+        debug!("💡 Accepted {}", candidate.label);
+        document.history_mut().begin();
+        document.apply_edits(vec![(range, candidate.text.clone())], 0);
+        document.history_mut().commit();
+        edited.send(DocumentChanged {
+            entity: e.entity,
+            version: document.version(),
+            changes: document.take_changes(),
+            cursor: None,
+        });
+        commands.entity(e.entity).remove::<Completion>();
+        changed.send(CompletionsChanged { entity: e.entity });
+    }
+}
+
+/// Takes the keys for picking a suggestion while the active document shows any.
+fn bind_completion_keys(
+    workspace: Res<Workspace>,
+    completions: Query<&Completion>,
+    mut keymap: ResMut<Keymap>,
+    mut bound: Local<bool>,
+) {
+    let showing = workspace
+        .active()
+        .and_then(|entity| completions.get(entity).ok())
+        .is_some_and(|completion| !completion.suggestions.is_empty());
+    if showing == *bound {
+        return;
+    }
+    *bound = showing;
+    if !showing {
+        keymap.remove_layer(LAYER);
+        return;
+    }
+    let mut layer = Layer::new(LAYER);
+    for (keys, command) in [
+        ("down", "completion.next"),
+        ("up", "completion.previous"),
+        ("enter", "completion.accept"),
+        ("tab", "completion.accept"),
+        ("escape", "completion.dismiss"),
+    ] {
+        layer.bindings.push(Binding {
+            keys: parse_keys(keys).expect("built-in keys parse"),
+            command: Some(command.to_string()),
+            mode: None,
+        });
+    }
+    keymap.set_layer(layer);
+}
@@ -0,0 +1,306 @@
+use crate::{
+    document::{DiskStamp, Document, LineEnding},
+    indent::IndentUnit,
+    keymap::Preset,
+    macros::config_dir,
+    theme::Palette,
+};
+use bevy::{
+    app::{App, Plugin},
+    core::Time,
+    ecs::{
+        event::{EventReader, EventWriter},
+        query::Added,
+        system::{Local, Query, Res, ResMut},
+    },
+    log::{debug, warn},
+};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// How often the settings file is checked for changes, in seconds.
+const POLL_INTERVAL: f64 = 1.0;
+
+pub struct ConfigPlugin;
+
+impl Plugin for ConfigPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ConfigFile>()
+            .init_resource::<Settings>()
+            .add_event::<SetSetting>()
+            .add_event::<SettingChanged>()
+            .add_startup_system(load_settings)
+            .add_system(reload_settings)
+            .add_system(set_settings)
+            .add_system(apply_default_eol);
+    }
+}
+
+/// The user settings file, below the settings of workspaces. `settings.toml` in the config
+/// directory if there is one, `settings.json` otherwise.
+#[derive(Clone, Debug)]
+pub struct ConfigFile {
+    pub path: Option<PathBuf>,
+}
+
+impl Default for ConfigFile {
+    fn default() -> Self {
+        let path = config_dir().map(|dir| {
+            let toml = dir.join("settings.toml");
+            match toml.exists() {
+                true => toml,
+                false => dir.join("settings.json"),
+            }
+        });
+        Self { path }
+    }
+}
+
+/// Settings by their dotted key, e.g. `editor.tabSize`: the ones of the settings file with
+/// the ones set at runtime on top. Plugins read them typed with [`Settings::get`] and
+/// notice changes through [`SettingChanged`] or the resource's change detection.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Settings {
+    file: Map<String, Value>,
+    overrides: Map<String, Value>,
+}
+
+impl Settings {
+    pub fn value(&self, key: &str) -> Option<&Value> {
+        self.overrides.get(key).or_else(|| self.file.get(key))
+    }
+
+    /// The setting as `T`, or None if unset or of another type.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let value = self.value(key)?;
+        match serde_json::from_value(value.clone()) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                warn!("⚙️ Invalid {key}: {e}");
+                None
+            }
+        }
+    }
+
+    /// Every setting, runtime ones winning.
+    pub fn values(&self) -> Map<String, Value> {
+        let mut values = self.file.clone();
+        values.extend(self.overrides.clone());
+        values
+    }
+
+    /// Columns of a tab and of an indentation level, 4 by default.
+    pub fn tab_size(&self) -> usize {
+        self.get("editor.tabSize").filter(|&n| n > 0).unwrap_or(4)
+    }
+
+    /// Indentation for documents that don't have any to detect it from.
+    pub fn indent_unit(&self) -> IndentUnit {
+        match self.get("editor.insertSpaces").unwrap_or(true) {
+            true => IndentUnit::Spaces(self.tab_size()),
+            false => IndentUnit::Tab,
+        }
+    }
+
+    /// Line ending of new documents, `"\n"` or `"\r\n"` in `files.eol`.
+    pub fn eol(&self) -> LineEnding {
+        match self.get::<String>("files.eol").as_deref() {
+            Some("\r\n") => LineEnding::CrLf,
+            _ => LineEnding::Lf,
+        }
+    }
+
+    pub fn palette(&self) -> Option<Palette> {
+        self.get("workbench.colorPalette")
+    }
+
+    /// `vim` or `emacs` in `keymap.preset`, bound on top of the default keys.
+    pub fn keymap_preset(&self) -> Option<Preset> {
+        self.get("keymap.preset")
+    }
+
+    /// Keys whose value differs between `self` and `other`.
+    fn changed_keys(&self, other: &Settings) -> BTreeSet<String> {
+        let (before, after) = (self.values(), other.values());
+        before
+            .keys()
+            .chain(after.keys())
+            .filter(|key| before.get(*key) != after.get(*key))
+            .cloned()
+            .collect()
+    }
+}
+
+/// A typed part of the settings, e.g. [`crate::theme::ThemeSettings`], read from layers
+/// of settings where the later ones win: user, workspace and the settings file of a root.
+pub trait SettingsSection: DeserializeOwned + Default {
+    /// Settings of `other` win over the ones in `self`.
+    fn merge(&mut self, other: Self);
+
+    /// Merges the keys of `values`, the settings of `source`, unless they are invalid.
+    fn merge_values(&mut self, values: Map<String, Value>, source: &str) {
+        match serde_json::from_value(values.into()) {
+            Ok(other) => self.merge(other),
+            Err(e) => warn!("Invalid {source}: {e}"),
+        }
+    }
+
+    /// Merges the keys of a JSON settings file, unless it is missing or invalid.
+    fn merge_file(&mut self, path: &Path) {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(_) => return,
+        };
+        match serde_json::from_str(&source) {
+            Ok(other) => self.merge(other),
+            Err(e) => warn!("{}: {e}", path.display()),
+        }
+    }
+}
+
+/// Parses a settings file, JSON or, by its extension, TOML. Tables in TOML name the
+/// section of their keys, so `[editor]` with `tabSize = 2` is `editor.tabSize`.
+pub fn parse_settings(path: &Path, source: &str) -> io::Result<Map<String, Value>> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+    if path.extension().is_some_and(|e| e == "toml") {
+        let table: toml::Table = toml::from_str(source).map_err(|e| invalid(e.to_string()))?;
+        let mut values = Map::new();
+        for (key, value) in table {
+            let value = serde_json::to_value(value).map_err(|e| invalid(e.to_string()))?;
+            match value {
+                Value::Object(section) => {
+                    for (name, value) in section {
+                        values.insert(format!("{key}.{name}"), value);
+                    }
+                }
+                value => {
+                    values.insert(key, value);
+                }
+            }
+        }
+        Ok(values)
+    } else {
+        serde_json::from_str(source).map_err(|e| invalid(e.to_string()))
+    }
+}
+
+/// Sets `key` for this run, over the settings file. None goes back to the file's value.
+#[derive(Clone, Debug)]
+pub struct SetSetting {
+    pub key: String,
+    pub value: Option<Value>,
+}
+
+/// The value of `key` changed, by the settings file or [`SetSetting`]. None when it was
+/// unset.
+#[derive(Clone, Debug)]
+pub struct SettingChanged {
+    pub key: String,
+    pub value: Option<Value>,
+}
+
+/// Swaps in `next`, telling what changed. The resource is only marked changed when it
+/// did.
+fn update(
+    settings: &mut ResMut<Settings>,
+    next: Settings,
+    changed: &mut EventWriter<SettingChanged>,
+) {
+    for key in settings.changed_keys(&next) {
+        debug!("⚙️ {key} changed");
+        changed.send(SettingChanged {
+            value: next.value(&key).cloned(),
+            key,
+        });
+    }
+    if **settings != next {
+        **settings = next;
+    }
+}
+
+fn read_file(path: &Path) -> Map<String, Value> {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(_) => return Map::new(),
+    };
+    parse_settings(path, &source).unwrap_or_else(|e| {
+        warn!("⚙️ Failed to read {}: {e}", path.display());
+        Map::new()
+    })
+}
+
+fn load_settings(
+    file: Res<ConfigFile>,
+    mut settings: ResMut<Settings>,
+    mut changed: EventWriter<SettingChanged>,
+) {
+    if let Some(path) = &file.path {
+        let next = Settings {
+            file: read_file(path),
+            ..settings.clone()
+        };
+        update(&mut settings, next, &mut changed);
+    }
+}
+
+/// Polls the file rather than watching it, it is small and usually in a directory full of
+/// unrelated files.
+fn reload_settings(
+    time: Res<Time>,
+    file: Res<ConfigFile>,
+    mut last: Local<(f64, Option<Option<DiskStamp>>)>,
+    mut settings: ResMut<Settings>,
+    mut changed: EventWriter<SettingChanged>,
+) {
+    let path = match &file.path {
+        Some(path) => path,
+        None => return,
+    };
+    let now = time.seconds_since_startup();
+    if now - last.0 < POLL_INTERVAL {
+        return;
+    }
+    last.0 = now;
+    let stamp = DiskStamp::read(path);
+    // The first look only remembers the stamp, the file was read on startup.
+    if last.1.replace(stamp).is_none_or(|before| before == stamp) {
+        return;
+    }
+    let next = Settings {
+        file: read_file(path),
+        ..settings.clone()
+    };
+    update(&mut settings, next, &mut changed);
+}
+
+fn set_settings(
+    mut events: EventReader<SetSetting>,
+    mut settings: ResMut<Settings>,
+    mut changed: EventWriter<SettingChanged>,
+) {
+    for e in events.iter() {
+        let mut next = settings.clone();
+        match &e.value {
+            Some(value) => next.overrides.insert(e.key.clone(), value.clone()),
+            None => next.overrides.remove(&e.key),
+        };
+        update(&mut settings, next, &mut changed);
+    }
+}
+
+/// Documents without a line break to detect their line ending from use the default one.
+fn apply_default_eol(
+    settings: Res<Settings>,
+    mut documents: Query<&mut Document, Added<Document>>,
+) {
+    for mut document in documents.iter_mut() {
+        if document.buffer().line_count() < 2 {
+            document.set_line_ending(settings.eol());
+        }
+    }
+}
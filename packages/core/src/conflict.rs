@@ -0,0 +1,178 @@
+use crate::{
+    diff::{diff, Hunk},
+    document::{Document, DocumentChanged, DocumentEditSet, SaveDocument},
+    pipeline::{AppPipelineExt, EditorStage},
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        query::With,
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Commands, Query, Res},
+    },
+    log::{debug, warn},
+    tasks::AsyncComputeTaskPool,
+};
+use std::{
+    path::PathBuf,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+};
+
+pub struct ConflictPlugin;
+
+impl Plugin for ConflictPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PendingComparisons>()
+            .add_event::<ResolveConflict>()
+            .add_editor_system(EditorStage::Edits, resolve_conflicts.label(DocumentEditSet))
+            .add_system(finish_comparisons);
+    }
+}
+
+/// How to go on after a [`crate::document::SaveConflict`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    /// Saves the document over what is on disk.
+    KeepMine,
+    /// Replaces the document with what is on disk. Can be undone.
+    TakeTheirs,
+    /// Puts a [`Conflict`] on the document to decide from, and keeps both as they are.
+    Compare,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ResolveConflict {
+    pub entity: Entity,
+    pub resolution: Resolution,
+}
+
+/// What changed on disk, as hunks from the document's lines to the file's.
+#[derive(Component, Clone, Debug)]
+pub struct Conflict {
+    pub path: PathBuf,
+    pub theirs: String,
+    pub hunks: Vec<Hunk>,
+}
+
+/// Put on a document while it is diffed against the disk, and taken off when the conflict
+/// is resolved another way first, so the late diff is dropped.
+#[derive(Component)]
+struct Comparing;
+
+/// Documents are diffed against the disk on the async compute pool and report back here.
+struct PendingComparisons {
+    sender: Mutex<Sender<(Entity, Conflict)>>,
+    receiver: Mutex<Receiver<(Entity, Conflict)>>,
+}
+
+impl Default for PendingComparisons {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender: Mutex::new(sender),
+            receiver: Mutex::new(receiver),
+        }
+    }
+}
+
+fn resolve_conflicts(
+    mut commands: Commands,
+    mut events: EventReader<ResolveConflict>,
+    (pool, pending): (Res<AsyncComputeTaskPool>, Res<PendingComparisons>),
+    mut documents: Query<&mut Document>,
+    mut saves: EventWriter<SaveDocument>,
+    mut changed: EventWriter<DocumentChanged>,
+) {
+    for e in events.iter() {
+        let mut document = match documents.get_mut(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let path = match document.path() {
+            Some(path) => path.to_path_buf(),
+            None => continue,
+        };
+        match e.resolution {
+            Resolution::KeepMine => {
+                debug!("📄 Keeping mine over {}", path.display());
+                document.accept_disk();
+                commands
+                    .entity(e.entity)
+                    .remove::<Conflict>()
+                    .remove::<Comparing>();
+                saves.send(SaveDocument {
+                    entity: e.entity,
+                    path: None,
+                });
+            }
+            Resolution::TakeTheirs => match document.read_from_disk() {
+                Ok(theirs) => {
+                    debug!("📄 Taking {} from disk", path.display());
+                    document.take_disk(theirs);
+                    commands
+                        .entity(e.entity)
+                        .remove::<Conflict>()
+                        .remove::<Comparing>();
+                    let changes = document.take_changes();
+                    if !changes.is_empty() {
+                        changed.send(DocumentChanged {
+                            entity: e.entity,
+                            version: document.version(),
+                            changes,
+                            cursor: None,
+                        });
+                    }
+                }
+                Err(error) => warn!("📄 Failed to read {}: {error}", path.display()),
+            },
+            Resolution::Compare => match document.read_from_disk() {
+                Ok(theirs) => {
+                    let mine = document.buffer().to_string();
+                    let theirs = theirs.buffer().to_string();
+                    let (entity, sender) = (e.entity, pending.sender.lock().unwrap().clone());
+                    commands.entity(entity).insert(Comparing);
+                    pool.spawn(async move {
+                        let a: Vec<&str> = mine.lines().collect();
+                        let b: Vec<&str> = theirs.lines().collect();
+                        let hunks = diff(&a, &b);
+                        debug!(
+                            "📄 {} differs from disk in {} hunks",
+                            path.display(),
+                            hunks.len()
+                        );
+                        let conflict = Conflict {
+                            path,
+                            theirs,
+                            hunks,
+                        };
+                        let _ = sender.send((entity, conflict));
+                    })
+                    .detach();
+                }
+                Err(error) => warn!("📄 Failed to read {}: {error}", path.display()),
+            },
+        }
+    }
+}
+
+fn finish_comparisons(
+    mut commands: Commands,
+    pending: Res<PendingComparisons>,
+    comparing: Query<(), With<Comparing>>,
+) {
+    let receiver = pending.receiver.lock().unwrap();
+    while let Ok((entity, conflict)) = receiver.try_recv() {
+        if comparing.get(entity).is_ok() {
+            commands
+                .entity(entity)
+                .remove::<Comparing>()
+                .insert(conflict);
+        }
+    }
+}
@@ -0,0 +1,313 @@
+use crate::{
+    command::UICommand,
+    document::{same_file, Document, DocumentLoadFailed, DocumentOpened, OpenDocument},
+    text_buffer::Position,
+    workspace::{FocusDocument, Workspace},
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        system::{Commands, Query, Res, ResMut},
+    },
+    log::{debug, warn},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::{mpsc::Sender, OnceLock},
+};
+
+/// Set for processes started by the editor, so scripts in its terminal find the socket.
+pub const SOCKET_ENV: &str = "DIP_SOCKET";
+
+/// Set once the socket listens. Passed to child processes through
+/// [`crate::toolchain::Toolchains::env`] instead of the editor's own environment, which
+/// other threads may be reading.
+static SOCKET_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Where the control socket of this process listens, if it does.
+pub fn socket_path() -> Option<&'static Path> {
+    SOCKET_PATH.get().map(PathBuf::as_path)
+}
+
+/// Listens on a local socket for [`ControlRequest`]s, one JSON object per line, and
+/// answers each with a [`ControlResponse`] line:
+///
+/// ```text
+/// {"command":"goto","path":"/src/main.rs","line":12,"column":5}
+/// "ok"
+/// ```
+pub struct ControlPlugin;
+
+impl Plugin for ControlPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<FocusWindow>()
+            .add_event::<RevealPosition>()
+            .add_startup_system(start_control_socket)
+            .add_system(handle_control_requests)
+            .add_system(reveal_opened_documents)
+            .add_system(send_focus_window);
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "command", rename_all = "camelCase")]
+pub enum ControlRequest {
+    /// Raises the window, after opening `path` or switching to the document `id` if given.
+    /// `path` has to be absolute, like the one of [`ControlRequest::Goto`].
+    Focus {
+        #[serde(default)]
+        path: Option<PathBuf>,
+        #[serde(default)]
+        id: Option<u64>,
+    },
+    List,
+    /// Opens `path` and moves the cursor there. Lines and columns start at 1, as printed
+    /// by compilers and test runners.
+    Goto {
+        path: PathBuf,
+        line: usize,
+        #[serde(default = "first")]
+        column: usize,
+    },
+}
+
+fn first() -> usize {
+    1
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ControlResponse {
+    Ok,
+    Documents(Vec<DocumentInfo>),
+    Error(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DocumentInfo {
+    /// Stable for as long as the document is open.
+    pub id: u64,
+    /// None for an untitled document.
+    pub path: Option<PathBuf>,
+    pub active: bool,
+    pub dirty: bool,
+}
+
+/// The window should be raised, sent to the view as [`UICommand::FocusWindow`].
+#[derive(Clone, Copy, Debug)]
+pub struct FocusWindow;
+
+/// The cursor of `entity` should move to `position` and scroll it into view.
+#[derive(Clone, Copy, Debug)]
+pub struct RevealPosition {
+    pub entity: Entity,
+    pub position: Position,
+}
+
+type Pending = (ControlRequest, Sender<ControlResponse>);
+
+#[cfg_attr(not(unix), allow(dead_code))]
+struct ControlSocket {
+    #[cfg(unix)]
+    path: PathBuf,
+    receiver: std::sync::Mutex<std::sync::mpsc::Receiver<Pending>>,
+    /// Goto requests waiting for their document to open.
+    reveals: Vec<(PathBuf, Position)>,
+}
+
+#[cfg(unix)]
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(unix)]
+fn start_control_socket(mut commands: Commands) {
+    use std::{env, os::unix::net::UnixListener, process, sync::mpsc, thread};
+
+    let path = env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir)
+        .join(format!("dip-{}.sock", process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("🔧 Failed to listen on {}: {e}", path.display());
+            return;
+        }
+    };
+    debug!("🔧 Listening on {}", path.display());
+    let _ = SOCKET_PATH.set(path.clone());
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let sender = sender.clone();
+                    thread::spawn(move || serve(stream, sender));
+                }
+                Err(e) => warn!("🔧 Control connection failed: {e}"),
+            }
+        }
+    });
+
+    commands.insert_resource(ControlSocket {
+        path,
+        receiver: std::sync::Mutex::new(receiver),
+        reveals: vec![],
+    });
+}
+
+#[cfg(not(unix))]
+fn start_control_socket() {
+    warn!("🔧 The control socket is only available on Unix");
+}
+
+/// Answers the requests of one connection until it is closed.
+#[cfg(unix)]
+fn serve(stream: std::os::unix::net::UnixStream, sender: Sender<Pending>) {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        sync::mpsc,
+        time::Duration,
+    };
+
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) if line.trim().is_empty() => continue,
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        let response = match serde_json::from_str::<ControlRequest>(&line) {
+            Ok(request) => {
+                let (reply, response) = mpsc::channel();
+                if sender.send((request, reply)).is_err() {
+                    return;
+                }
+                response
+                    .recv_timeout(Duration::from_secs(5))
+                    .unwrap_or_else(|_| ControlResponse::Error("timed out".to_string()))
+            }
+            Err(e) => ControlResponse::Error(e.to_string()),
+        };
+        let mut json = serde_json::to_string(&response).unwrap();
+        json.push('\n');
+        if writer.write_all(json.as_bytes()).is_err() {
+            return;
+        }
+    }
+}
+
+fn handle_control_requests(
+    socket: Option<ResMut<ControlSocket>>,
+    workspace: Res<Workspace>,
+    documents: Query<&Document>,
+    mut open: EventWriter<OpenDocument>,
+    mut focus_document: EventWriter<FocusDocument>,
+    mut focus: EventWriter<FocusWindow>,
+) {
+    let mut socket = match socket {
+        Some(socket) => socket,
+        None => return,
+    };
+    let pending: Vec<Pending> = socket.receiver.lock().unwrap().try_iter().collect();
+
+    for (request, reply) in pending {
+        debug!("🔧 {request:?}");
+        let response = match request {
+            ControlRequest::List => ControlResponse::Documents(
+                workspace
+                    .documents()
+                    .iter()
+                    .filter_map(|&entity| {
+                        let document = documents.get(entity).ok()?;
+                        Some(DocumentInfo {
+                            id: entity.to_bits(),
+                            path: document.path().map(PathBuf::from),
+                            active: workspace.active() == Some(entity),
+                            dirty: document.is_dirty(),
+                        })
+                    })
+                    .collect(),
+            ),
+            ControlRequest::Focus { id: Some(id), .. }
+                if !workspace.is_open(Entity::from_bits(id)) =>
+            {
+                ControlResponse::Error(format!("no document {id}"))
+            }
+            // The client's working directory is unknown here.
+            ControlRequest::Focus {
+                path: Some(path), ..
+            } if path.is_relative() => {
+                ControlResponse::Error(format!("{} is not absolute", path.display()))
+            }
+            ControlRequest::Focus { path, id } => {
+                if let Some(path) = path {
+                    open.send(OpenDocument { path });
+                }
+                if let Some(id) = id {
+                    focus_document.send(FocusDocument {
+                        entity: Entity::from_bits(id),
+                    });
+                }
+                focus.send(FocusWindow);
+                ControlResponse::Ok
+            }
+            ControlRequest::Goto { path, .. } if path.is_relative() => {
+                ControlResponse::Error(format!("{} is not absolute", path.display()))
+            }
+            ControlRequest::Goto { path, line, column } => {
+                let position = Position::new(line.saturating_sub(1), column.saturating_sub(1));
+                socket.reveals.push((path.clone(), position));
+                open.send(OpenDocument { path });
+                focus.send(FocusWindow);
+                ControlResponse::Ok
+            }
+        };
+        let _ = reply.send(response);
+    }
+}
+
+fn reveal_opened_documents(
+    socket: Option<ResMut<ControlSocket>>,
+    mut opened: EventReader<DocumentOpened>,
+    mut failed: EventReader<DocumentLoadFailed>,
+    mut reveal: EventWriter<RevealPosition>,
+) {
+    let mut socket = match socket {
+        Some(socket) => socket,
+        None => return,
+    };
+    for e in opened.iter() {
+        socket.reveals.retain(|(path, position)| {
+            if !same_file(path, &e.path) {
+                return true;
+            }
+            reveal.send(RevealPosition {
+                entity: e.entity,
+                position: *position,
+            });
+            false
+        });
+    }
+    for e in failed.iter() {
+        socket.reveals.retain(|(path, _)| !same_file(path, &e.path));
+    }
+}
+
+fn send_focus_window(mut focus: EventReader<FocusWindow>, mut ui: EventWriter<UICommand>) {
+    // Raised once however many requests asked for it.
+    if focus.iter().count() > 0 {
+        ui.send(UICommand::FocusWindow);
+    }
+}
@@ -0,0 +1,1053 @@
+use crate::{
+    announce::Announcement,
+    config::Settings,
+    control::RevealPosition,
+    damage::VisibleLines,
+    document::{Change, Document, DocumentChanged, DocumentEditSet},
+    fold::Folds,
+    format::{self, FormatOnTypeRequested, OnTypeTriggers},
+    indent::{self, IndentRules, IndentUnit},
+    layout::Layout,
+    pairs::{AutoClosePair, AutoClosePairs},
+    pipeline::{AppPipelineExt, EditorStage},
+    search::find_literal,
+    syntax::Syntax,
+    text_buffer::{Position, TextBuffer},
+    workspace::Workspace,
+};
+use bevy::{
+    app::{App, Plugin},
+    core::FloatOrd,
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        query::{Added, Without},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Commands, Local, Query, Res, ResMut},
+    },
+};
+use std::{collections::HashSet, mem, ops::Range};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Lines moved by page up and down when no view reported its [`VisibleLines`].
+const DEFAULT_PAGE_LINES: usize = 20;
+/// Frames a reveal waits for its document to be spawned with a cursor.
+const REVEAL_FRAMES: usize = 10;
+
+pub struct CursorPlugin;
+
+impl Plugin for CursorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AutoClosePairs>()
+            .init_resource::<Placed>()
+            .add_event::<MoveCursor>()
+            .add_event::<TypeText>()
+            .add_event::<DeleteText>()
+            .add_event::<AddCursor>()
+            .add_event::<AddCursorAtNextOccurrence>()
+            .add_event::<ClearSecondaryCursors>()
+            .add_system(attach_cursors)
+            .add_system(move_cursors)
+            .add_editor_system(EditorStage::Edits, type_text.label(DocumentEditSet))
+            .add_editor_system(EditorStage::Edits, delete_text.label(DocumentEditSet))
+            .add_system(add_cursors)
+            .add_system(add_cursors_at_next_occurrence)
+            .add_system(clear_secondary_cursors)
+            .add_editor_system(EditorStage::Derive, remap_cursors)
+            .add_system(reveal_positions)
+            .add_system(despawn_orphaned_cursors);
+    }
+}
+
+/// Byte offset of the caret in its document, always on a grapheme boundary. The document
+/// entity holds the primary cursor, others are [`SecondaryCursor`] entities.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Cursor {
+    pub offset: usize,
+    /// Column kept while moving up and down through shorter lines.
+    goal_column: Option<usize>,
+    /// The same for moving through visual lines of a [`Layout`], where it is drawn.
+    goal_x: Option<FloatOrd>,
+}
+
+/// The end of the selection that stays put while the [`Cursor`] moves. Empty when both
+/// are at the same offset.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Selection {
+    pub anchor: usize,
+}
+
+impl Selection {
+    pub fn range(&self, cursor: &Cursor) -> Range<usize> {
+        self.anchor.min(cursor.offset)..self.anchor.max(cursor.offset)
+    }
+
+    pub fn is_empty(&self, cursor: &Cursor) -> bool {
+        self.anchor == cursor.offset
+    }
+}
+
+/// An additional cursor of `document`, spawned with its own [`Cursor`] and [`Selection`].
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SecondaryCursor {
+    pub document: Entity,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Movement {
+    Left,
+    Right,
+    WordLeft,
+    WordRight,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    /// First non-blank character of the line, or its start if already there.
+    LineStart,
+    LineEnd,
+    DocumentStart,
+    DocumentEnd,
+}
+
+/// Moves the cursor of a document, extending the selection if `select` is set.
+#[derive(Clone, Copy, Debug)]
+pub struct MoveCursor {
+    pub entity: Entity,
+    pub movement: Movement,
+    pub select: bool,
+}
+
+/// Types `text` at every cursor of a document, replacing selections. A line break is
+/// indented to the surrounding block and a closing bracket on a blank line is outdented to
+/// its opening one, like a line typed to match the decrease pattern of [`IndentRules`].
+#[derive(Clone, Debug)]
+pub struct TypeText {
+    pub entity: Entity,
+    pub text: String,
+}
+
+/// Deletes the selections, or from each empty one to where `movement` would take its
+/// cursor, e.g. [`Movement::Left`] for backspace.
+#[derive(Clone, Copy, Debug)]
+pub struct DeleteText {
+    pub entity: Entity,
+    pub movement: Movement,
+}
+
+/// Adds a cursor on the line below the last cursor, or above the first one.
+#[derive(Clone, Copy, Debug)]
+pub struct AddCursor {
+    pub entity: Entity,
+    pub above: bool,
+}
+
+/// Selects the word at the primary cursor, or when something is selected, adds a cursor
+/// selecting the next occurrence of it after the last cursor.
+#[derive(Clone, Copy, Debug)]
+pub struct AddCursorAtNextOccurrence {
+    pub entity: Entity,
+}
+
+/// Goes back to the primary cursor only.
+#[derive(Clone, Copy, Debug)]
+pub struct ClearSecondaryCursors {
+    pub entity: Entity,
+}
+
+impl Cursor {
+    pub fn at(offset: usize) -> Self {
+        Self {
+            offset,
+            goal_column: None,
+            goal_x: None,
+        }
+    }
+
+    pub fn position(&self, buffer: &TextBuffer) -> Position {
+        buffer.position_at(self.offset)
+    }
+
+    /// Where `movement` takes the cursor, moving `page` lines for page up and down.
+    pub fn moved(&self, buffer: &TextBuffer, movement: Movement, page: usize) -> Self {
+        self.moved_over(buffer, movement, page, &Folds::default())
+    }
+
+    /// Like [`Cursor::moved`], stepping over the lines `folds` hides as if they were not
+    /// there and counting pages in lines on screen.
+    pub fn moved_over(
+        &self,
+        buffer: &TextBuffer,
+        movement: Movement,
+        page: usize,
+        folds: &Folds,
+    ) -> Self {
+        let offset = self.offset;
+        let line = buffer.line_at(offset);
+        let start = buffer.line_start(line);
+        let content = buffer.get_line_content(line);
+        let column = offset - start;
+        let last_line = buffer.line_count() - 1;
+
+        let vertical = |target: Option<usize>| {
+            let goal = self
+                .goal_column
+                .unwrap_or_else(|| buffer.position_at(offset).column);
+            let offset = match target {
+                Some(line) => buffer.offset_at(line, goal),
+                None if line == 0 => 0,
+                None => buffer.len(),
+            };
+            Self {
+                offset,
+                goal_column: Some(goal),
+                goal_x: None,
+            }
+        };
+
+        let offset = match movement {
+            Movement::Left if column == 0 => match folds.line_above(line) {
+                Some(above) => line_end(buffer, above),
+                None => 0,
+            },
+            Movement::Left => {
+                let previous = content[..column].grapheme_indices(true).next_back();
+                start + previous.map_or(0, |(i, _)| i)
+            }
+            Movement::Right if column >= content.len() => {
+                match folds.line_below(line, buffer.line_count()) {
+                    Some(below) => buffer.line_start(below),
+                    None => offset,
+                }
+            }
+            Movement::Right => {
+                let next = content[column..].graphemes(true).next();
+                offset + next.map_or(0, str::len)
+            }
+            Movement::WordLeft => {
+                let to = buffer.prev_word_boundary(offset);
+                match folds.is_hidden(buffer.line_at(to)) {
+                    true => line_end(buffer, folds.shown_as(buffer.line_at(to))),
+                    false => to,
+                }
+            }
+            Movement::WordRight => {
+                let to = buffer.next_word_boundary(offset);
+                match folds.is_hidden(buffer.line_at(to)) {
+                    true => match folds.line_below(buffer.line_at(to), buffer.line_count()) {
+                        Some(below) => buffer.line_start(below),
+                        None => line_end(buffer, line),
+                    },
+                    false => to,
+                }
+            }
+            Movement::Up => return vertical(folds.line_above(line)),
+            Movement::Down => return vertical(folds.line_below(line, buffer.line_count())),
+            Movement::PageUp => {
+                let visible = folds.visible_line(line).saturating_sub(page);
+                return vertical(Some(folds.document_line(visible)));
+            }
+            Movement::PageDown => {
+                let last = folds.visible_line(last_line);
+                let visible = (folds.visible_line(line) + page).min(last);
+                return vertical(Some(folds.document_line(visible)));
+            }
+            Movement::LineStart => {
+                let indent = content.len() - content.trim_start().len();
+                if column == indent {
+                    start
+                } else {
+                    start + indent
+                }
+            }
+            Movement::LineEnd => start + content.len(),
+            Movement::DocumentStart => 0,
+            Movement::DocumentEnd => buffer.len(),
+        };
+        Self::at(offset)
+    }
+
+    /// Moves `rows` visual lines of `layout` down, or up when negative, keeping to where it
+    /// was drawn.
+    pub fn moved_visually(&self, buffer: &TextBuffer, layout: &Layout, rows: isize) -> Self {
+        let position = buffer.position_at(self.offset.min(buffer.len()));
+        let (position, goal) = layout.moved(buffer, position, self.goal_x.map(|x| x.0), rows);
+        Self {
+            offset: buffer.offset_at(position.line, position.column),
+            goal_column: None,
+            goal_x: Some(FloatOrd(goal)),
+        }
+    }
+
+    /// Follows `change` made to the document, ending up after text inserted at the cursor.
+    pub fn remap(&mut self, change: &Change) {
+        self.offset = remap(self.offset, change);
+        self.goal_column = None;
+        self.goal_x = None;
+    }
+}
+
+/// Lines on screen, which page up and down move by.
+fn page_lines(visible: Option<&VisibleLines>, folds: &Folds) -> usize {
+    let lines = match visible {
+        Some(visible) => folds.visible_ranges(visible.0.clone()),
+        None => return DEFAULT_PAGE_LINES,
+    };
+    lines.iter().map(Range::len).sum::<usize>().max(1)
+}
+
+/// Offset after the last character of `line`, before its line ending.
+fn line_end(buffer: &TextBuffer, line: usize) -> usize {
+    buffer.line_start(line) + buffer.get_line_length(line)
+}
+
+fn remap(offset: usize, change: &Change) -> usize {
+    let range = &change.range;
+    if offset < range.start {
+        offset
+    } else if offset >= range.end {
+        offset - range.len() + change.text.len()
+    } else {
+        range.start + change.text.len()
+    }
+}
+
+/// Snaps `offset` into the buffer and onto the start of the grapheme containing it.
+pub(crate) fn clamp(buffer: &TextBuffer, offset: usize) -> usize {
+    let position = buffer.position_at(offset.min(buffer.len()));
+    buffer.offset_at(position.line, position.column)
+}
+
+/// A cursor with its selection, `entity` being None for the primary one.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Caret {
+    entity: Option<Entity>,
+    cursor: Cursor,
+    selection: Selection,
+}
+
+impl Caret {
+    fn at(offset: usize) -> Self {
+        Self {
+            entity: None,
+            cursor: Cursor::at(offset),
+            selection: Selection { anchor: offset },
+        }
+    }
+
+    pub(crate) fn range(&self) -> Range<usize> {
+        self.selection.range(&self.cursor)
+    }
+
+    /// Selects `range` with the cursor at its end.
+    pub(crate) fn select(&mut self, range: Range<usize>) {
+        self.selection.anchor = range.start;
+        self.cursor = Cursor::at(range.end);
+    }
+}
+
+/// Changes whose cursors were placed by the system making them, so they are not remapped a
+/// second time.
+#[derive(Default)]
+pub(crate) struct Placed(pub(crate) HashSet<(Entity, u64)>);
+
+pub(crate) type Documents<'w, 's, T> = Query<
+    'w,
+    's,
+    (
+        &'static mut Document,
+        &'static mut Cursor,
+        &'static mut Selection,
+        T,
+    ),
+>;
+
+pub(crate) type Secondaries<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static SecondaryCursor,
+        &'static mut Cursor,
+        &'static mut Selection,
+    ),
+    Without<Document>,
+>;
+
+/// Every cursor of `document`, the primary one first.
+pub(crate) fn carets(
+    document: Entity,
+    primary: (&Cursor, &Selection),
+    secondaries: &mut Secondaries,
+) -> Vec<Caret> {
+    let mut carets = vec![Caret {
+        entity: None,
+        cursor: *primary.0,
+        selection: *primary.1,
+    }];
+    for (entity, secondary, cursor, selection) in secondaries.iter_mut() {
+        if secondary.document == document {
+            carets.push(Caret {
+                entity: Some(entity),
+                cursor: *cursor,
+                selection: *selection,
+            });
+        }
+    }
+    carets
+}
+
+/// Merges overlapping carets, despawning the secondary cursors merged away. The primary
+/// cursor survives a merge.
+pub(crate) fn merge(mut carets: Vec<Caret>, commands: &mut Commands) -> Vec<Caret> {
+    carets.sort_by_key(|caret| (caret.range().start, caret.range().end));
+    let mut kept: Vec<Caret> = Vec::with_capacity(carets.len());
+    for caret in carets {
+        let last = match kept.last_mut() {
+            Some(last) if caret.range().start < last.range().end => last,
+            Some(last) if caret.range().start == last.range().start => last,
+            _ => {
+                kept.push(caret);
+                continue;
+            }
+        };
+        let (survivor, merged) = match last.entity {
+            Some(_) if caret.entity.is_none() => (caret, *last),
+            _ => (*last, caret),
+        };
+        let range = survivor.range().start..survivor.range().end.max(merged.range().end);
+        let forward = survivor.selection.anchor <= survivor.cursor.offset;
+        let (anchor, offset) = if forward {
+            (range.start, range.end)
+        } else {
+            (range.end, range.start)
+        };
+        if let Some(entity) = merged.entity {
+            commands.entity(entity).despawn();
+        }
+        *last = Caret {
+            entity: survivor.entity,
+            cursor: Cursor {
+                offset,
+                ..survivor.cursor
+            },
+            selection: Selection { anchor },
+        };
+    }
+    kept
+}
+
+/// Writes `carets` back after merging them.
+pub(crate) fn store(
+    carets: Vec<Caret>,
+    primary: (&mut Cursor, &mut Selection),
+    secondaries: &mut Secondaries,
+    commands: &mut Commands,
+) {
+    for caret in merge(carets, commands) {
+        match caret.entity {
+            None => {
+                *primary.0 = caret.cursor;
+                *primary.1 = caret.selection;
+            }
+            Some(entity) => {
+                if let Ok((_, _, mut cursor, mut selection)) = secondaries.get_mut(entity) {
+                    *cursor = caret.cursor;
+                    *selection = caret.selection;
+                }
+            }
+        }
+    }
+}
+
+fn despawn_secondaries(document: Entity, secondaries: &mut Secondaries, commands: &mut Commands) {
+    for (entity, secondary, _, _) in secondaries.iter_mut() {
+        if secondary.document == document {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn attach_cursors(
+    mut commands: Commands,
+    documents: Query<Entity, (Added<Document>, Without<Cursor>)>,
+) {
+    for entity in documents.iter() {
+        commands
+            .entity(entity)
+            .insert(Cursor::default())
+            .insert(Selection::default());
+    }
+}
+
+fn move_cursors(
+    mut commands: Commands,
+    mut events: EventReader<MoveCursor>,
+    mut documents: Documents<(Option<&VisibleLines>, Option<&Folds>, Option<&Layout>)>,
+    mut secondaries: Secondaries,
+) {
+    let unfolded = Folds::default();
+    for e in events.iter() {
+        let (mut document, mut cursor, mut selection, (visible, folds, layout)) =
+            match documents.get_mut(e.entity) {
+                Ok(document) => document,
+                Err(_) => continue,
+            };
+        // Typing elsewhere afterwards is undone separately.
+        document.history_mut().break_group();
+        let folds = folds.unwrap_or(&unfolded);
+        // Up and down go by visual lines once a view laid them out.
+        let buffer = document.buffer();
+        let layout = layout.filter(|layout| layout.is_current(buffer.line_count()));
+        let page = match (layout, visible) {
+            (Some(layout), Some(visible)) => layout.visual_lines(visible.0.clone()).len().max(1),
+            _ => page_lines(visible, folds),
+        };
+
+        let mut carets = carets(e.entity, (&cursor, &selection), &mut secondaries);
+        for caret in &mut carets {
+            // Left and right collapse a selection to the side they point to.
+            let range = caret.range();
+            caret.cursor = match e.movement {
+                Movement::Left if !e.select && !range.is_empty() => Cursor::at(range.start),
+                Movement::Right if !e.select && !range.is_empty() => Cursor::at(range.end),
+                movement => match (layout, movement) {
+                    (Some(layout), Movement::Up) => caret.cursor.moved_visually(buffer, layout, -1),
+                    (Some(layout), Movement::Down) => {
+                        caret.cursor.moved_visually(buffer, layout, 1)
+                    }
+                    (Some(layout), Movement::PageUp) => {
+                        caret
+                            .cursor
+                            .moved_visually(buffer, layout, -(page as isize))
+                    }
+                    (Some(layout), Movement::PageDown) => {
+                        caret.cursor.moved_visually(buffer, layout, page as isize)
+                    }
+                    _ => caret.cursor.moved_over(buffer, movement, page, folds),
+                },
+            };
+            if !e.select {
+                caret.selection.anchor = caret.cursor.offset;
+            }
+        }
+        store(
+            carets,
+            (&mut cursor, &mut selection),
+            &mut secondaries,
+            &mut commands,
+        );
+    }
+}
+
+/// How one document reacts to typing.
+struct Typing<'a> {
+    rules: &'a IndentRules,
+    pairs: Vec<&'a AutoClosePair>,
+    /// Without a language server formatting on type, the built-in formatting is applied
+    /// in the same undo step, in languages whose rules allow it.
+    builtin_format: bool,
+    /// Indentation of new lines in documents without any to detect.
+    unit: IndentUnit,
+    syntax: Option<&'a Syntax>,
+}
+
+impl Typing<'_> {
+    fn pair(&self, open: char) -> Option<&AutoClosePair> {
+        self.pairs.iter().copied().find(|pair| pair.open == open)
+    }
+
+    fn closes(&self, c: char) -> bool {
+        self.pairs.iter().any(|pair| pair.close == c)
+    }
+
+    /// Whether typing the opening character of `pair` at `offset` inserts the closing one
+    /// too. Only done before whitespace or closing characters, so typing in front of a
+    /// word doesn't leave a stray one behind.
+    fn auto_closes(&self, buffer: &TextBuffer, offset: usize, pair: &AutoClosePair) -> bool {
+        let (before, after) = around(buffer, offset);
+        if pair.not_in.contains(&self.rules.context(&before)) {
+            return false;
+        }
+        let fits = after
+            .chars()
+            .next()
+            .is_none_or(|c| c.is_whitespace() || self.closes(c) || ";:.,=>".contains(c));
+        // A quote right after a word is an apostrophe or closes a string.
+        let previous = before.chars().last();
+        let attached = pair.is_symmetric()
+            && previous.is_some_and(|c| c.is_alphanumeric() || c == '_' || c == pair.open);
+        fits && !attached
+    }
+}
+
+/// The line of `offset` split at it.
+fn around(buffer: &TextBuffer, offset: usize) -> (String, String) {
+    let line = buffer.line_at(offset);
+    let content = buffer.get_line_content(line);
+    let (before, after) = content.split_at((offset - buffer.line_start(line)).min(content.len()));
+    (before.to_string(), after.to_string())
+}
+
+/// Types `text` over `range` and returns the selection afterwards, with the cursor at its
+/// end.
+fn type_at(
+    document: &mut Document,
+    range: Range<usize>,
+    text: &str,
+    typing: &Typing,
+) -> Range<usize> {
+    let mut chars = text.chars();
+    let typed = match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    };
+    let rules = typing.rules;
+    // The tree only matches the text until the first edit.
+    let scope = typing
+        .syntax
+        .filter(|syntax| range.is_empty() && syntax.is_current(document))
+        .and_then(|syntax| syntax.indent_scope(document.buffer(), range.start));
+
+    if let Some(pair) = typed.and_then(|c| typing.pair(c)) {
+        if !range.is_empty() {
+            document.history_mut().begin();
+            document.insert(range.end, &pair.close.to_string());
+            document.insert(range.start, text);
+            return range.start + text.len()..range.end + text.len();
+        }
+    }
+    if let Some(c) = typed.filter(|&c| typing.closes(c)) {
+        let buffer = document.buffer();
+        let next = buffer
+            .chunks_in(range.start..buffer.len())
+            .flat_map(str::chars)
+            .next();
+        if range.is_empty() && next == Some(c) {
+            let offset = range.start + c.len_utf8();
+            return offset..offset;
+        }
+    }
+
+    // Replacing a selection or reindenting is undone together with the typed text.
+    if !range.is_empty() {
+        document.history_mut().begin();
+        document.delete(range.clone());
+    }
+    let mut offset = range.start;
+
+    let cursor = match typed {
+        Some('\n') => {
+            let unit = IndentUnit::detect_or(document.buffer(), typing.unit);
+            let (text, cursor) = match &scope {
+                Some(scope) => indent::newline_in(document.buffer(), offset, scope, unit),
+                None => indent::newline(document.buffer(), offset, rules, unit),
+            };
+            document.insert(offset, &text);
+            offset + cursor
+        }
+        Some(c) if typing.pair(c).is_some() => {
+            let pair = typing.pair(c).unwrap();
+            let mut text = text.to_string();
+            if typing.auto_closes(document.buffer(), offset, pair) {
+                text.push(pair.close);
+            }
+            document.insert(offset, &text);
+            offset + c.len_utf8()
+        }
+        Some(c) if rules.is_closing(c) || rules.decrease.is_some() => {
+            let buffer = document.buffer();
+            let scope = scope.filter(|scope| scope.is_closed_by(buffer, c, rules));
+            let reindent = match (rules.is_closing(c), scope) {
+                (true, Some(scope)) => indent::outdent_in(buffer, offset, &scope),
+                (true, None) => indent::outdent(buffer, offset, c, rules),
+                (false, _) => indent::dedent(buffer, offset, c, rules),
+            };
+            if let Some((indent, with)) = reindent {
+                document.history_mut().begin();
+                document.delete(indent.clone());
+                document.insert(indent.start, &with);
+                offset = offset - indent.len() + with.len();
+            }
+            document.insert(offset, text);
+            offset + text.len()
+        }
+        _ => {
+            document.insert(offset, text);
+            offset + text.len()
+        }
+    };
+
+    let edits = match typed {
+        Some(c) if typing.builtin_format => format::format_on_type(document.buffer(), cursor, c),
+        _ => vec![],
+    };
+    if edits.is_empty() {
+        return cursor..cursor;
+    }
+    document.history_mut().amend();
+    let cursor = document.apply_edits(edits, cursor);
+    cursor..cursor
+}
+
+/// Applies `edit` at every caret from the last to the first, so the offsets of the ones
+/// not edited yet stay valid, then shifts each caret by the edits made before it. `edit`
+/// returns the selection of the caret afterwards, with the cursor at its end.
+pub(crate) fn edit_carets(
+    document: &mut Document,
+    carets: &mut [Caret],
+    mut edit: impl FnMut(&mut Document, &Caret) -> Range<usize>,
+) {
+    carets.sort_by_key(|caret| caret.range().start);
+    if carets.len() > 1 {
+        document.history_mut().begin();
+    }
+    let mut placed = vec![(0..0, 0isize); carets.len()];
+    for (i, caret) in carets.iter().enumerate().rev() {
+        let before = document.buffer().len() as isize;
+        let selection = edit(document, caret);
+        placed[i] = (selection, document.buffer().len() as isize - before);
+    }
+    document.history_mut().commit();
+
+    let mut shift = 0;
+    for (caret, (selection, grown)) in carets.iter_mut().zip(placed) {
+        let shifted = |offset: usize| (offset as isize + shift) as usize;
+        caret.cursor = Cursor::at(shifted(selection.end));
+        caret.selection.anchor = shifted(selection.start);
+        shift += grown;
+    }
+}
+
+pub(crate) fn primary_offset(carets: &[Caret]) -> usize {
+    carets
+        .iter()
+        .find(|caret| caret.entity.is_none())
+        .map_or(0, |caret| caret.cursor.offset)
+}
+
+#[allow(clippy::type_complexity)]
+fn type_text(
+    mut commands: Commands,
+    mut events: EventReader<TypeText>,
+    (default_rules, default_pairs, workspace): (
+        Res<IndentRules>,
+        Res<AutoClosePairs>,
+        Option<Res<Workspace>>,
+    ),
+    mut documents: Documents<(
+        Option<&IndentRules>,
+        Option<&AutoClosePairs>,
+        Option<&OnTypeTriggers>,
+        Option<&Syntax>,
+    )>,
+    mut secondaries: Secondaries,
+    (mut placed, user): (ResMut<Placed>, Option<Res<Settings>>),
+    (mut changed, mut format): (
+        EventWriter<DocumentChanged>,
+        EventWriter<FormatOnTypeRequested>,
+    ),
+) {
+    for e in events.iter() {
+        let (mut document, mut cursor, mut selection, (rules, pairs, triggers, syntax)) =
+            match documents.get_mut(e.entity) {
+                Ok(document) => document,
+                Err(_) => continue,
+            };
+        let settings = workspace
+            .as_ref()
+            .zip(document.path())
+            .and_then(|(workspace, path)| workspace.root_for(path))
+            .map(|root| root.pairs.clone())
+            .unwrap_or_default();
+        let rules = rules.unwrap_or(&default_rules);
+        let typing = Typing {
+            rules,
+            pairs: pairs.unwrap_or(&default_pairs).enabled(&settings).collect(),
+            builtin_format: triggers.is_none() && rules.format_on_type,
+            unit: user
+                .as_ref()
+                .map_or_else(IndentUnit::default, |user| user.indent_unit()),
+            syntax,
+        };
+
+        let mut carets = carets(e.entity, (&cursor, &selection), &mut secondaries);
+        edit_carets(&mut document, &mut carets, |document, caret| {
+            type_at(document, caret.range(), &e.text, &typing)
+        });
+        let primary = primary_offset(&carets);
+        // Servers format around one position, the primary cursor's.
+        let trigger = e
+            .text
+            .chars()
+            .last()
+            .filter(|c| triggers.is_some_and(|t| t.0.contains(c)));
+        if let Some(trigger) = trigger {
+            format.send(FormatOnTypeRequested {
+                entity: e.entity,
+                version: document.version(),
+                position: document.utf16_position(primary),
+                trigger,
+            });
+        }
+        store(
+            carets,
+            (&mut cursor, &mut selection),
+            &mut secondaries,
+            &mut commands,
+        );
+
+        placed.0.insert((e.entity, document.version()));
+        changed.send(DocumentChanged {
+            entity: e.entity,
+            version: document.version(),
+            changes: document.take_changes(),
+            cursor: Some(primary),
+        });
+    }
+}
+
+fn delete_text(
+    mut commands: Commands,
+    mut events: EventReader<DeleteText>,
+    mut documents: Documents<(Option<&VisibleLines>, Option<&Folds>)>,
+    mut secondaries: Secondaries,
+    mut placed: ResMut<Placed>,
+    mut changed: EventWriter<DocumentChanged>,
+    mut announce: EventWriter<Announcement>,
+) {
+    let unfolded = Folds::default();
+    for e in events.iter() {
+        let (mut document, mut cursor, mut selection, (visible, folds)) =
+            match documents.get_mut(e.entity) {
+                Ok(document) => document,
+                Err(_) => continue,
+            };
+        let folds = folds.unwrap_or(&unfolded);
+        let page = page_lines(visible, folds);
+
+        // Carets deleting overlapping ranges are merged first, so text is deleted once.
+        let mut carets = carets(e.entity, (&cursor, &selection), &mut secondaries);
+        for caret in &mut carets {
+            if caret.range().is_empty() {
+                let moved = caret
+                    .cursor
+                    .moved_over(document.buffer(), e.movement, page, folds);
+                caret.selection.anchor = moved.offset;
+            }
+        }
+        let mut carets = merge(carets, &mut commands);
+        if carets.iter().all(|caret| caret.range().is_empty()) {
+            continue;
+        }
+
+        let mut deleted = String::new();
+        edit_carets(&mut document, &mut carets, |document, caret| {
+            let range = caret.range();
+            if !range.is_empty() {
+                deleted.push_str(&document.buffer().text_in(range.clone()));
+                document.delete(range.clone());
+            }
+            range.start..range.start
+        });
+        if let Some(announcement) = Announcement::deleted(&deleted) {
+            announce.send(announcement);
+        }
+        let primary = primary_offset(&carets);
+        store(
+            carets,
+            (&mut cursor, &mut selection),
+            &mut secondaries,
+            &mut commands,
+        );
+
+        placed.0.insert((e.entity, document.version()));
+        changed.send(DocumentChanged {
+            entity: e.entity,
+            version: document.version(),
+            changes: document.take_changes(),
+            cursor: Some(primary),
+        });
+    }
+}
+
+fn add_cursors(
+    mut commands: Commands,
+    mut events: EventReader<AddCursor>,
+    documents: Query<(&Document, &Cursor, &Selection, Option<&Folds>)>,
+    mut secondaries: Secondaries,
+) {
+    let unfolded = Folds::default();
+    for e in events.iter() {
+        let (document, cursor, selection, folds) = match documents.get(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let carets = carets(e.entity, (cursor, selection), &mut secondaries);
+        let edge = if e.above {
+            carets.iter().min_by_key(|caret| caret.cursor.offset)
+        } else {
+            carets.iter().max_by_key(|caret| caret.cursor.offset)
+        };
+        let edge = edge.unwrap().cursor;
+        let buffer = document.buffer();
+        let movement = if e.above {
+            Movement::Up
+        } else {
+            Movement::Down
+        };
+        let added = edge.moved_over(buffer, movement, 1, folds.unwrap_or(&unfolded));
+        if buffer.line_at(added.offset) == buffer.line_at(edge.offset) {
+            continue;
+        }
+        commands
+            .spawn()
+            .insert(SecondaryCursor { document: e.entity })
+            .insert(added)
+            .insert(Selection {
+                anchor: added.offset,
+            });
+    }
+}
+
+fn add_cursors_at_next_occurrence(
+    mut commands: Commands,
+    mut events: EventReader<AddCursorAtNextOccurrence>,
+    mut documents: Query<(&Document, &mut Cursor, &mut Selection)>,
+    mut secondaries: Secondaries,
+    mut announce: EventWriter<Announcement>,
+) {
+    for e in events.iter() {
+        let (document, mut cursor, mut selection) = match documents.get_mut(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let buffer = document.buffer();
+        let range = selection.range(&cursor);
+        if range.is_empty() {
+            if let Some(word) = buffer.word_at(cursor.offset) {
+                selection.anchor = word.start;
+                *cursor = Cursor::at(word.end);
+            }
+            continue;
+        }
+
+        let needle = buffer.text_in(range);
+        let carets = carets(e.entity, (&cursor, &selection), &mut secondaries);
+        let last = carets.iter().map(|caret| caret.range().end).max().unwrap();
+        let matches = find_literal(buffer.chunks(), &needle);
+        // Wraps around to the start of the document.
+        let next = matches
+            .iter()
+            .position(|m| m.start >= last)
+            .or_else(|| (!matches.is_empty()).then_some(0))
+            .filter(|&i| carets.iter().all(|caret| caret.range() != matches[i]));
+        if let Some(i) = next {
+            announce.send(Announcement::match_of(i, matches.len()));
+            let next = &matches[i];
+            commands
+                .spawn()
+                .insert(SecondaryCursor { document: e.entity })
+                .insert(Cursor::at(next.end))
+                .insert(Selection { anchor: next.start });
+        }
+    }
+}
+
+fn clear_secondary_cursors(
+    mut commands: Commands,
+    mut events: EventReader<ClearSecondaryCursors>,
+    mut secondaries: Secondaries,
+) {
+    for e in events.iter() {
+        despawn_secondaries(e.entity, &mut secondaries, &mut commands);
+    }
+}
+
+/// Keeps cursors in place relative to the text around them when the document changes.
+/// After an undo or redo only the primary cursor is left, where the change happened.
+fn remap_cursors(
+    mut commands: Commands,
+    mut events: EventReader<DocumentChanged>,
+    mut documents: Query<(&Document, &mut Cursor, &mut Selection)>,
+    mut secondaries: Secondaries,
+    mut placed: ResMut<Placed>,
+) {
+    for e in events.iter() {
+        if placed.0.remove(&(e.entity, e.version)) {
+            continue;
+        }
+        let (document, mut cursor, mut selection) = match documents.get_mut(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let buffer = document.buffer();
+
+        let carets = match e.cursor {
+            Some(offset) => {
+                despawn_secondaries(e.entity, &mut secondaries, &mut commands);
+                vec![Caret::at(offset)]
+            }
+            None => {
+                let mut carets = carets(e.entity, (&cursor, &selection), &mut secondaries);
+                for caret in &mut carets {
+                    for change in &e.changes {
+                        caret.cursor.remap(change);
+                        caret.selection.anchor = remap(caret.selection.anchor, change);
+                    }
+                    caret.cursor.offset = clamp(buffer, caret.cursor.offset);
+                    caret.selection.anchor = clamp(buffer, caret.selection.anchor);
+                }
+                carets
+            }
+        };
+        store(
+            carets,
+            (&mut cursor, &mut selection),
+            &mut secondaries,
+            &mut commands,
+        );
+    }
+}
+
+/// A document revealed right after it was opened may not be spawned or have its cursor
+/// yet, those are retried for up to [`REVEAL_FRAMES`] frames.
+fn reveal_positions(
+    mut commands: Commands,
+    mut events: EventReader<RevealPosition>,
+    mut waiting: Local<Vec<(RevealPosition, usize)>>,
+    mut documents: Query<(&Document, Option<&mut Cursor>, Option<&mut Selection>)>,
+    mut secondaries: Secondaries,
+    mut announce: EventWriter<Announcement>,
+) {
+    let mut reveals = mem::take(&mut *waiting);
+    reveals.extend(events.iter().map(|e| (*e, 0)));
+    for (e, tries) in reveals {
+        match documents.get_mut(e.entity) {
+            Ok((document, Some(mut cursor), Some(mut selection))) => {
+                let buffer = document.buffer();
+                let line = e.position.line.min(buffer.line_count() - 1);
+                *cursor = Cursor::at(buffer.offset_at(line, e.position.column));
+                selection.anchor = cursor.offset;
+                despawn_secondaries(e.entity, &mut secondaries, &mut commands);
+                let position = buffer.position_at(cursor.offset);
+                announce.send(Announcement::moved_to(position.line, position.column));
+            }
+            _ if tries < REVEAL_FRAMES => waiting.push((e, tries + 1)),
+            _ => {}
+        }
+    }
+}
+
+fn despawn_orphaned_cursors(
+    mut commands: Commands,
+    secondaries: Query<(Entity, &SecondaryCursor)>,
+    documents: Query<&Document>,
+) {
+    for (entity, secondary) in secondaries.iter() {
+        if documents.get(secondary.document).is_err() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use crate::{
+    document::{Document, DocumentChanged, LineChange},
+    fold::Folds,
+    pipeline::{AppPipelineExt, EditorStage},
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        query::{Added, Changed},
+        schedule::{ParallelSystemDescriptorCoercion, SystemLabel},
+        system::{Commands, Query, ResMut},
+    },
+};
+use std::ops::Range;
+
+pub struct DamagePlugin;
+
+/// The system sending [`Redraw`], for the ones drawing in [`EditorStage::Present`] to run
+/// after.
+#[derive(SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CollectDamage;
+
+impl Plugin for DamagePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RedrawMetrics>()
+            .add_event::<DecorationsChanged>()
+            .add_event::<Redraw>()
+            .add_editor_system(EditorStage::Layout, track_damage)
+            .add_editor_system(EditorStage::Present, collect_damage.label(CollectDamage));
+    }
+}
+
+/// Lines of a document whose layout is stale.
+///
+/// Lines below an edit only move when lines are inserted or removed above them; they are
+/// shifted along, not damaged.
+#[derive(Component, Debug, Default)]
+pub struct Damage {
+    /// Sorted and disjoint.
+    lines: Vec<Range<usize>>,
+    all: bool,
+}
+
+impl Damage {
+    pub fn record(&mut self, change: &LineChange) {
+        let old_end = change.start + change.removed + 1;
+        let new_end = change.start + change.inserted + 1;
+        let mut damaged = change.start..new_end;
+
+        let mut lines = Vec::with_capacity(self.lines.len() + 1);
+        for range in self.lines.drain(..) {
+            if range.end <= change.start {
+                lines.push(range);
+            } else if range.start >= old_end {
+                lines.push(range.start - old_end + new_end..range.end - old_end + new_end);
+            } else {
+                damaged.start = damaged.start.min(range.start);
+                if range.end > old_end {
+                    damaged.end = damaged.end.max(range.end - old_end + new_end);
+                }
+            }
+        }
+        lines.push(damaged);
+        self.lines = lines;
+        self.normalize();
+    }
+
+    /// Damages `lines` without moving anything, e.g. when their decorations change.
+    pub fn mark(&mut self, lines: Range<usize>) {
+        if !lines.is_empty() {
+            self.lines.push(lines);
+            self.normalize();
+        }
+    }
+
+    pub fn mark_all(&mut self) {
+        self.all = true;
+        self.lines.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.all && self.lines.is_empty()
+    }
+
+    /// Damaged lines within `visible`, clearing all damage. Lines outside it are laid out
+    /// anyway once they scroll into view.
+    pub fn take(&mut self, visible: Range<usize>) -> Vec<Range<usize>> {
+        let lines = std::mem::take(&mut self.lines);
+        if std::mem::take(&mut self.all) {
+            return vec![visible];
+        }
+        lines
+            .into_iter()
+            .map(|range| range.start.max(visible.start)..range.end.min(visible.end))
+            .filter(|range| !range.is_empty())
+            .collect()
+    }
+
+    fn normalize(&mut self) {
+        self.lines.sort_by_key(|range| range.start);
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(self.lines.len());
+        for range in self.lines.drain(..) {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        self.lines = merged;
+    }
+}
+
+/// Lines of a document currently on screen, kept up to date by the view showing it.
+#[derive(Component, Clone, Debug, Default)]
+pub struct VisibleLines(pub Range<usize>);
+
+#[derive(Clone, Debug)]
+pub struct DecorationsChanged {
+    pub entity: Entity,
+    pub lines: Range<usize>,
+}
+
+/// Visible lines to lay out again this frame, leaving out folded ones.
+#[derive(Clone, Debug)]
+pub struct Redraw {
+    pub entity: Entity,
+    pub lines: Vec<Range<usize>>,
+}
+
+/// Lines laid out again per frame, reported by the `typing_bench` example.
+#[derive(Debug, Default)]
+pub struct RedrawMetrics {
+    /// Lines laid out in the last frame that redrew anything.
+    pub last_frame: usize,
+    pub peak: usize,
+    frames: u64,
+    lines: u64,
+}
+
+impl RedrawMetrics {
+    /// Average lines laid out per frame that redrew anything.
+    pub fn average(&self) -> f64 {
+        if self.frames == 0 {
+            0.
+        } else {
+            self.lines as f64 / self.frames as f64
+        }
+    }
+
+    fn record(&mut self, lines: usize) {
+        self.last_frame = lines;
+        self.peak = self.peak.max(lines);
+        self.frames += 1;
+        self.lines += lines as u64;
+    }
+}
+
+fn track_damage(
+    mut commands: Commands,
+    mut changes: EventReader<DocumentChanged>,
+    mut decorations: EventReader<DecorationsChanged>,
+    added: Query<Entity, Added<Document>>,
+    shown: Query<Entity, Changed<VisibleLines>>,
+    mut damage: Query<&mut Damage>,
+) {
+    for entity in added.iter() {
+        commands.entity(entity).insert(Damage::default());
+    }
+
+    for e in changes.iter() {
+        if let Ok(mut damage) = damage.get_mut(e.entity) {
+            for change in &e.changes {
+                damage.record(&change.lines);
+            }
+        }
+    }
+
+    for e in decorations.iter() {
+        if let Ok(mut damage) = damage.get_mut(e.entity) {
+            damage.mark(e.lines.clone());
+        }
+    }
+
+    // Scrolled or resized, the view lays out whatever it shows.
+    for entity in shown.iter() {
+        if let Ok(mut damage) = damage.get_mut(entity) {
+            damage.mark_all();
+        }
+    }
+}
+
+fn collect_damage(
+    mut documents: Query<(Entity, &mut Damage, Option<&VisibleLines>, Option<&Folds>)>,
+    mut redraw: EventWriter<Redraw>,
+    mut metrics: ResMut<RedrawMetrics>,
+) {
+    let mut total = 0;
+    for (entity, mut damage, visible, folds) in documents.iter_mut() {
+        if damage.is_empty() {
+            continue;
+        }
+        let visible = match visible {
+            Some(visible) => visible.0.clone(),
+            None => 0..0,
+        };
+        let mut lines = damage.take(visible);
+        // Folded lines are not laid out at all.
+        if let Some(folds) = folds.filter(|folds| !folds.is_empty()) {
+            lines = lines
+                .into_iter()
+                .flat_map(|lines| folds.visible_ranges(lines))
+                .collect();
+        }
+        if !lines.is_empty() {
+            total += lines.iter().map(Range::len).sum::<usize>();
+            redraw.send(Redraw { entity, lines });
+        }
+    }
+
+    if total > 0 {
+        metrics.record(total);
+    }
+}
@@ -0,0 +1,248 @@
+use crate::{
+    damage::DecorationsChanged,
+    document::{Change, Document, DocumentChanged},
+    pipeline::{AppPipelineExt, EditorStage},
+    text_buffer::TextBuffer,
+    theme::Severity,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        system::{Commands, Query},
+    },
+    log::debug,
+};
+use std::{collections::HashMap, ops::Range};
+
+pub struct DiagnosticsPlugin;
+
+impl Plugin for DiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PublishDiagnostics>()
+            .add_event::<ClearDiagnostics>()
+            .add_event::<DiagnosticsChanged>()
+            .add_system(publish_diagnostics)
+            .add_editor_system(EditorStage::Derive, shift_diagnostics);
+    }
+}
+
+/// Replaces what `provider` reported for a document, e.g. a linter, a build or a language
+/// server. What other providers reported stays.
+#[derive(Clone, Debug)]
+pub struct PublishDiagnostics {
+    pub entity: Entity,
+    pub provider: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Drops what `provider` reported for a document, or everything without one.
+#[derive(Clone, Debug)]
+pub struct ClearDiagnostics {
+    pub entity: Entity,
+    pub provider: Option<String>,
+}
+
+/// The [`Diagnostics`] of a document were published or cleared.
+#[derive(Clone, Copy, Debug)]
+pub struct DiagnosticsChanged {
+    pub entity: Entity,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub range: Range<usize>,
+    pub severity: Severity,
+    pub message: String,
+    /// The tool that reported it, e.g. `rustc` or `clippy`.
+    pub source: Option<String>,
+    pub code: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+struct Published {
+    provider: String,
+    diagnostic: Diagnostic,
+}
+
+/// What providers last published for a document, sorted by start. Ranges move along with
+/// edits until a provider publishes again.
+#[derive(Component, Clone, Debug, Default)]
+pub struct Diagnostics {
+    published: Vec<Published>,
+}
+
+impl Diagnostics {
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.published.iter().map(|p| &p.diagnostic)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.published.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.published.len()
+    }
+
+    /// What `provider` published.
+    pub fn from_provider<'a>(&'a self, provider: &'a str) -> impl Iterator<Item = &'a Diagnostic> {
+        self.published
+            .iter()
+            .filter(move |p| p.provider == provider)
+            .map(|p| &p.diagnostic)
+    }
+
+    /// The diagnostics overlapping `range`, e.g. the visible lines to underline. Empty ones
+    /// at its edges count.
+    pub fn in_range(&self, range: Range<usize>) -> impl Iterator<Item = &Diagnostic> {
+        self.iter()
+            .take_while(move |d| d.range.start <= range.end)
+            .filter(move |d| d.range.end >= range.start)
+    }
+
+    pub fn count(&self, severity: Severity) -> usize {
+        self.iter().filter(|d| d.severity == severity).count()
+    }
+
+    /// The most severe diagnostic starting on each of `lines` that has any, for gutter
+    /// markers.
+    pub fn gutter(&self, buffer: &TextBuffer, lines: Range<usize>) -> Vec<(usize, Severity)> {
+        let end = match lines.end < buffer.line_count() {
+            true => buffer.line_start(lines.end),
+            false => buffer.len() + 1,
+        };
+        let start = buffer.line_start(lines.start);
+        let mut markers: Vec<(usize, Severity)> = vec![];
+        for d in self
+            .iter()
+            .skip_while(|d| d.range.start < start)
+            .take_while(|d| d.range.start < end)
+        {
+            let line = buffer.line_at(d.range.start);
+            match markers.last_mut() {
+                Some((last, severity)) if *last == line => *severity = (*severity).min(d.severity),
+                _ => markers.push((line, d.severity)),
+            }
+        }
+        markers
+    }
+
+    fn publish(&mut self, provider: &str, diagnostics: Vec<Diagnostic>) {
+        self.clear(Some(provider));
+        self.published
+            .extend(diagnostics.into_iter().map(|diagnostic| Published {
+                provider: provider.to_string(),
+                diagnostic,
+            }));
+        self.published
+            .sort_by_key(|p| (p.diagnostic.range.start, p.diagnostic.range.end));
+    }
+
+    fn clear(&mut self, provider: Option<&str>) {
+        match provider {
+            Some(provider) => self.published.retain(|p| p.provider != provider),
+            None => self.published.clear(),
+        }
+    }
+
+    fn shift(&mut self, change: &Change) {
+        let inserted = change.text.len();
+        let map = |offset: usize, inside: usize| {
+            if offset <= change.range.start {
+                offset
+            } else if offset >= change.range.end {
+                offset - change.range.len() + inserted
+            } else {
+                inside
+            }
+        };
+        for p in &mut self.published {
+            let d = &mut p.diagnostic;
+            let start = map(d.range.start, change.range.start);
+            let end = map(d.range.end, change.range.start + inserted);
+            d.range = start..end.max(start);
+        }
+    }
+}
+
+fn publish_diagnostics(
+    mut commands: Commands,
+    (mut published, mut cleared): (
+        EventReader<PublishDiagnostics>,
+        EventReader<ClearDiagnostics>,
+    ),
+    mut documents: Query<(&Document, Option<&mut Diagnostics>)>,
+    mut changed: EventWriter<DiagnosticsChanged>,
+    mut decorations: EventWriter<DecorationsChanged>,
+) {
+    let mut notify = |entity: Entity, document: &Document| {
+        changed.send(DiagnosticsChanged { entity });
+        decorations.send(DecorationsChanged {
+            entity,
+            lines: 0..document.buffer().line_count(),
+        });
+    };
+
+    for e in cleared.iter() {
+        if let Ok((document, Some(mut diagnostics))) = documents.get_mut(e.entity) {
+            if diagnostics.is_empty() {
+                continue;
+            }
+            diagnostics.clear(e.provider.as_deref());
+            notify(e.entity, document);
+        }
+    }
+
+    // Several providers may publish for a document before its component is inserted.
+    let mut inserted: HashMap<Entity, Diagnostics> = HashMap::new();
+    for e in published.iter() {
+        let (document, current) = match documents.get_mut(e.entity) {
+            Ok(found) => found,
+            Err(_) => continue,
+        };
+        // Providers may lag behind edits, so nothing is allowed past the end.
+        let len = document.buffer().len();
+        let diagnostics = e
+            .diagnostics
+            .iter()
+            .cloned()
+            .map(|mut d| {
+                let start = d.range.start.min(len);
+                d.range = start..d.range.end.clamp(start, len);
+                d
+            })
+            .collect();
+        debug!(
+            "🩺 {} published {} diagnostics",
+            e.provider,
+            e.diagnostics.len()
+        );
+        match current {
+            Some(mut current) => current.publish(&e.provider, diagnostics),
+            None => inserted
+                .entry(e.entity)
+                .or_default()
+                .publish(&e.provider, diagnostics),
+        }
+        notify(e.entity, document);
+    }
+    for (entity, diagnostics) in inserted {
+        commands.entity(entity).insert(diagnostics);
+    }
+}
+
+fn shift_diagnostics(
+    mut changes: EventReader<DocumentChanged>,
+    mut diagnostics: Query<&mut Diagnostics>,
+) {
+    for e in changes.iter() {
+        if let Ok(mut diagnostics) = diagnostics.get_mut(e.entity) {
+            for change in &e.changes {
+                diagnostics.shift(change);
+            }
+        }
+    }
+}
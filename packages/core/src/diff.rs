@@ -0,0 +1,193 @@
+use crate::{
+    document::{Document, DocumentError},
+    memory::MemoryUsage,
+    text_buffer::TextBuffer,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        component::Component,
+        event::EventReader,
+        system::{Commands, Res},
+    },
+    log::{debug, warn},
+    tasks::AsyncComputeTaskPool,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+};
+
+pub use dip_document::diff::{diff, merge3, Hunk, Merged};
+
+pub struct DiffPlugin;
+
+impl Plugin for DiffPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PendingDiffs>()
+            .add_event::<CompareFiles>()
+            .add_event::<MergeFiles>()
+            .add_system(compare_files)
+            .add_system(merge_files)
+            .add_system(finish_diffs);
+    }
+}
+
+/// Compares two files line by line, e.g. for `dip --diff a b` used as a git difftool.
+#[derive(Clone, Debug)]
+pub struct CompareFiles {
+    pub left: PathBuf,
+    pub right: PathBuf,
+}
+
+/// Merges `ours` and `theirs` into a document saved to `output`, for git mergetool.
+#[derive(Clone, Debug)]
+pub struct MergeFiles {
+    pub base: PathBuf,
+    pub ours: PathBuf,
+    pub theirs: PathBuf,
+    pub output: PathBuf,
+}
+
+#[derive(Component, Clone, Debug)]
+pub struct Comparison {
+    pub left: PathBuf,
+    pub right: PathBuf,
+    pub hunks: Vec<Hunk>,
+}
+
+/// Put on the output document of a merge.
+#[derive(Component, Clone, Debug)]
+pub struct Merge {
+    pub base: PathBuf,
+    pub ours: PathBuf,
+    pub theirs: PathBuf,
+    /// Conflicts left when the merge was opened.
+    pub conflicts: usize,
+}
+
+enum Outcome {
+    Compared(Comparison),
+    Merged {
+        output: PathBuf,
+        text: String,
+        merge: Merge,
+    },
+}
+
+/// Files are read and diffed on the async compute pool, as large ones take a while, and
+/// report back here.
+struct PendingDiffs {
+    sender: Mutex<Sender<Outcome>>,
+    receiver: Mutex<Receiver<Outcome>>,
+}
+
+impl Default for PendingDiffs {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender: Mutex::new(sender),
+            receiver: Mutex::new(receiver),
+        }
+    }
+}
+
+fn read(path: &Path) -> Result<String, DocumentError> {
+    Ok(fs::read_to_string(path)?)
+}
+
+fn compare(e: &CompareFiles) -> Result<Comparison, DocumentError> {
+    let (left, right) = (read(&e.left)?, read(&e.right)?);
+    let a: Vec<&str> = left.lines().collect();
+    let b: Vec<&str> = right.lines().collect();
+    let hunks = diff(&a, &b);
+    debug!("🔀 {} hunks", hunks.len());
+    Ok(Comparison {
+        left: e.left.clone(),
+        right: e.right.clone(),
+        hunks,
+    })
+}
+
+fn merge(e: &MergeFiles) -> Result<Outcome, DocumentError> {
+    let (base, ours, theirs) = (read(&e.base)?, read(&e.ours)?, read(&e.theirs)?);
+    let merged = merge3(&base, &ours, &theirs);
+    debug!("🔀 Merged with {} conflicts", merged.conflicts);
+    Ok(Outcome::Merged {
+        output: e.output.clone(),
+        text: merged.text,
+        merge: Merge {
+            base: e.base.clone(),
+            ours: e.ours.clone(),
+            theirs: e.theirs.clone(),
+            conflicts: merged.conflicts,
+        },
+    })
+}
+
+fn compare_files(
+    mut events: EventReader<CompareFiles>,
+    pool: Res<AsyncComputeTaskPool>,
+    pending: Res<PendingDiffs>,
+) {
+    for e in events.iter() {
+        let e = e.clone();
+        let sender = pending.sender.lock().unwrap().clone();
+        pool.spawn(async move {
+            match compare(&e) {
+                Ok(comparison) => {
+                    let _ = sender.send(Outcome::Compared(comparison));
+                }
+                Err(err) => warn!("🔀 Failed to compare: {err}"),
+            }
+        })
+        .detach();
+    }
+}
+
+fn merge_files(
+    mut events: EventReader<MergeFiles>,
+    pool: Res<AsyncComputeTaskPool>,
+    pending: Res<PendingDiffs>,
+) {
+    for e in events.iter() {
+        let e = e.clone();
+        let sender = pending.sender.lock().unwrap().clone();
+        pool.spawn(async move {
+            match merge(&e) {
+                Ok(merged) => {
+                    let _ = sender.send(merged);
+                }
+                Err(err) => warn!("🔀 Failed to merge: {err}"),
+            }
+        })
+        .detach();
+    }
+}
+
+fn finish_diffs(mut commands: Commands, pending: Res<PendingDiffs>) {
+    let receiver = pending.receiver.lock().unwrap();
+    while let Ok(outcome) = receiver.try_recv() {
+        match outcome {
+            Outcome::Compared(comparison) => {
+                commands.spawn().insert(comparison);
+            }
+            Outcome::Merged {
+                output,
+                text,
+                merge,
+            } => {
+                let buffer = TextBuffer::from(text.as_str());
+                commands
+                    .spawn()
+                    .insert(Document::new(Some(output), buffer))
+                    .insert(MemoryUsage::default())
+                    .insert(merge);
+            }
+        }
+    }
+}
@@ -0,0 +1,586 @@
+use crate::{
+    memory::{Cache, EvictCache, MemoryUsage},
+    pipeline::{AppPipelineExt, EditorStage},
+    vault::Vaults,
+};
+use bevy::{
+    app::{App, Plugin},
+    core::Time,
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        query::{Changed, Without},
+        schedule::{ParallelSystemDescriptorCoercion, SystemLabel},
+        system::{Commands, Local, Query, RemovedComponents, Res, ResMut, SystemParam},
+    },
+    log::{debug, warn},
+    tasks::AsyncComputeTaskPool,
+};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+};
+
+pub use dip_document::document::{
+    difference, Change, DiskStamp, Document, DocumentError, Edit, LineChange, LineEnding,
+    Utf16Position, Utf8Decoder, MAX_DOCUMENT_SIZE, READ_CHUNK_SIZE,
+};
+
+pub struct LargeFileSettings {
+    /// Files at least this large open memory-mapped and read-only instead of being read
+    /// into memory, e.g. to browse logs. None always reads them.
+    pub map_threshold: Option<u64>,
+}
+
+impl Default for LargeFileSettings {
+    fn default() -> Self {
+        Self {
+            map_threshold: Some(256 * 1024 * 1024),
+        }
+    }
+}
+
+/// Saves failing in a way that may pass, e.g. a network file system timing out, are tried
+/// again before [`DocumentSaveFailed`] is sent.
+pub struct SaveRetrySettings {
+    pub attempts: u32,
+    /// Seconds before the first retry, doubled for each one after it.
+    pub backoff: f64,
+}
+
+impl Default for SaveRetrySettings {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: 0.5,
+        }
+    }
+}
+
+/// Saves that failed in a way that may pass, to be sent again.
+#[derive(Default)]
+pub struct SaveRetries {
+    attempts: Vec<(Entity, u32)>,
+    /// When to send each save again.
+    due: Vec<(f64, SaveDocument)>,
+}
+
+impl SaveRetries {
+    /// Sends `save` again after a backoff, unless it was tried often enough already.
+    pub fn schedule(
+        &mut self,
+        save: &SaveDocument,
+        settings: &SaveRetrySettings,
+        now: f64,
+    ) -> bool {
+        let attempt = self.attempts(save.entity);
+        if attempt >= settings.attempts {
+            return false;
+        }
+        let delay = settings.backoff * 2f64.powi(attempt as i32);
+        self.forget(save.entity);
+        self.attempts.push((save.entity, attempt + 1));
+        self.due.push((now + delay, save.clone()));
+        true
+    }
+
+    /// Times the save of `entity` was tried again so far.
+    pub fn attempts(&self, entity: Entity) -> u32 {
+        self.attempts
+            .iter()
+            .find(|(e, _)| *e == entity)
+            .map_or(0, |(_, attempt)| *attempt)
+    }
+
+    fn forget(&mut self, entity: Entity) {
+        self.attempts.retain(|(e, _)| *e != entity);
+    }
+}
+
+pub struct DocumentPlugin;
+
+/// The system writing documents to disk, for systems that look at files around a save.
+#[derive(SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SaveDocuments;
+
+/// Systems editing the text of documents, all in [`EditorStage::Edits`]. Systems in the
+/// stage looking at documents or their [`DocumentChanged`] run `.after(DocumentEditSet)`,
+/// the others are in a later stage.
+#[derive(SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DocumentEditSet;
+
+/// A document standing in for other files, e.g. search results. Saving it is left to
+/// the plugin that made it, so [`SaveDocument`] does not write it anywhere.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct VirtualDocument;
+
+impl Plugin for DocumentPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LargeFileSettings>()
+            .init_resource::<SaveRetrySettings>()
+            .init_resource::<SaveRetries>()
+            .init_resource::<Vaults>()
+            .init_resource::<PendingMaps>()
+            .add_event::<OpenDocument>()
+            .add_event::<DocumentOpened>()
+            .add_event::<DocumentLoadFailed>()
+            .add_event::<EditDocument>()
+            .add_event::<UndoDocument>()
+            .add_event::<RedoDocument>()
+            .add_event::<DocumentChanged>()
+            .add_event::<SaveDocument>()
+            .add_event::<DocumentSaved>()
+            .add_event::<DocumentSaveFailed>()
+            .add_event::<SaveConflict>()
+            .add_system(open_documents)
+            .add_system(finish_maps)
+            .add_editor_system(EditorStage::Edits, edit_documents.label(DocumentEditSet))
+            .add_editor_system(EditorStage::Edits, undo_documents.label(DocumentEditSet))
+            .add_editor_system(EditorStage::Edits, redo_documents.label(DocumentEditSet))
+            .add_system(save_documents.label(SaveDocuments))
+            .add_system(retry_saves.before(SaveDocuments))
+            .add_system(evict_undo_history)
+            .add_editor_system(EditorStage::Derive, update_memory_usage)
+            .add_system(mark_documents_used);
+    }
+}
+
+/// A path that is already open is not read again, [`DocumentOpened`] is sent for its
+/// document instead.
+#[derive(Clone, Debug)]
+pub struct OpenDocument {
+    pub path: PathBuf,
+}
+
+#[derive(Clone, Debug)]
+pub struct DocumentOpened {
+    pub entity: Entity,
+    pub path: PathBuf,
+}
+
+/// Saves to `path`, or to the document's own path if None.
+#[derive(Clone, Debug)]
+pub struct SaveDocument {
+    pub entity: Entity,
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug)]
+pub struct DocumentSaved {
+    pub entity: Entity,
+    pub path: PathBuf,
+}
+
+#[derive(Debug)]
+pub struct DocumentSaveFailed {
+    pub entity: Entity,
+    /// Where the document was to be saved, None if it has no path.
+    pub path: Option<PathBuf>,
+    pub error: io::Error,
+}
+
+/// The file was written by someone else since the document read it, so the save was not
+/// done. Resolved with [`crate::conflict::ResolveConflict`].
+#[derive(Clone, Debug)]
+pub struct SaveConflict {
+    pub entity: Entity,
+    pub path: PathBuf,
+}
+
+#[derive(Debug)]
+pub struct DocumentLoadFailed {
+    pub path: PathBuf,
+    pub error: DocumentError,
+}
+
+#[derive(Clone, Debug)]
+pub struct EditDocument {
+    pub entity: Entity,
+    pub edit: Edit,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct UndoDocument {
+    pub entity: Entity,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RedoDocument {
+    pub entity: Entity,
+}
+
+/// Sent after every edit, undo and redo. Each [`Change`] carries the replaced range and
+/// the new text, so listeners can update incrementally.
+#[derive(Clone, Debug)]
+pub struct DocumentChanged {
+    pub entity: Entity,
+    /// [`Document::version`] after the changes.
+    pub version: u64,
+    pub changes: Vec<Change>,
+    /// Where the cursor ended up after undo or redo.
+    pub cursor: Option<usize>,
+}
+
+/// Documents whose text changed since the system last ran, including ones just spawned.
+/// Narrower than `Changed<Document>`, which saving or evicting undo history set too.
+#[derive(SystemParam)]
+pub struct EditedDocuments<'w, 's> {
+    documents: Query<'w, 's, (Entity, &'static Document), Changed<Document>>,
+    removed: RemovedComponents<'w, Document>,
+    versions: Local<'s, HashMap<Entity, u64>>,
+}
+
+impl EditedDocuments<'_, '_> {
+    pub fn iter(&mut self) -> Vec<(Entity, &Document)> {
+        for entity in self.removed.iter() {
+            self.versions.remove(&entity);
+        }
+        let versions = &mut *self.versions;
+        self.documents
+            .iter()
+            .filter(|(entity, document)| {
+                versions.insert(*entity, document.version()) != Some(document.version())
+            })
+            .collect()
+    }
+}
+
+/// Files being mapped on the async compute pool, as indexing the lines of a large one takes
+/// a while, and reporting back here.
+struct PendingMaps {
+    sender: Mutex<Sender<(PathBuf, Result<Document, DocumentError>)>>,
+    receiver: Mutex<Receiver<(PathBuf, Result<Document, DocumentError>)>>,
+    /// Opening one of these again waits for the mapping under way.
+    paths: Vec<PathBuf>,
+}
+
+impl Default for PendingMaps {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender: Mutex::new(sender),
+            receiver: Mutex::new(receiver),
+            paths: vec![],
+        }
+    }
+}
+
+fn open_documents(
+    mut commands: Commands,
+    mut events: EventReader<OpenDocument>,
+    (settings, vaults, pool): (
+        Res<LargeFileSettings>,
+        Res<Vaults>,
+        Res<AsyncComputeTaskPool>,
+    ),
+    mut pending: ResMut<PendingMaps>,
+    documents: Query<(Entity, &Document)>,
+    mut opened: EventWriter<DocumentOpened>,
+    mut failed: EventWriter<DocumentLoadFailed>,
+) {
+    for e in events.iter() {
+        let existing = documents
+            .iter()
+            .find(|(_, document)| document.path().is_some_and(|path| same_file(path, &e.path)));
+        if let Some((entity, _)) = existing {
+            opened.send(DocumentOpened {
+                entity,
+                path: e.path.clone(),
+            });
+            continue;
+        }
+        if pending.paths.iter().any(|path| same_file(path, &e.path)) {
+            continue;
+        }
+
+        let size = fs::metadata(&e.path).map_or(0, |m| m.len());
+        let map = settings.map_threshold.is_some_and(|t| size >= t);
+        let result = match vaults.key_for(&e.path) {
+            Some(key) => Document::from_encrypted(&e.path, key),
+            None if map => {
+                let path = e.path.clone();
+                let sender = pending.sender.lock().unwrap().clone();
+                pool.spawn(async move {
+                    let result = Document::map_path(&path);
+                    let _ = sender.send((path, result));
+                })
+                .detach();
+                pending.paths.push(e.path.clone());
+                continue;
+            }
+            None => Document::from_path(&e.path),
+        };
+        spawn_opened(&mut commands, &e.path, result, &mut opened, &mut failed);
+    }
+}
+
+fn finish_maps(
+    mut commands: Commands,
+    mut pending: ResMut<PendingMaps>,
+    mut opened: EventWriter<DocumentOpened>,
+    mut failed: EventWriter<DocumentLoadFailed>,
+) {
+    let pending = &mut *pending;
+    let receiver = pending.receiver.lock().unwrap();
+    while let Ok((path, result)) = receiver.try_recv() {
+        pending.paths.retain(|p| *p != path);
+        spawn_opened(&mut commands, &path, result, &mut opened, &mut failed);
+    }
+}
+
+fn spawn_opened(
+    commands: &mut Commands,
+    path: &Path,
+    result: Result<Document, DocumentError>,
+    opened: &mut EventWriter<DocumentOpened>,
+    failed: &mut EventWriter<DocumentLoadFailed>,
+) {
+    match result {
+        Ok(document) => {
+            let how = if document.is_read_only() {
+                ", read-only"
+            } else {
+                ""
+            };
+            debug!("📄 Opened {}{how}", path.display());
+            let entity = commands
+                .spawn()
+                .insert(document)
+                .insert(MemoryUsage::default())
+                .id();
+            opened.send(DocumentOpened {
+                entity,
+                path: path.to_path_buf(),
+            });
+        }
+        Err(error) => {
+            warn!("📄 Failed to open {}: {error}", path.display());
+            failed.send(DocumentLoadFailed {
+                path: path.to_path_buf(),
+                error,
+            });
+        }
+    }
+}
+
+pub(crate) fn same_file(a: &Path, b: &Path) -> bool {
+    a == b
+        || match (fs::canonicalize(a), fs::canonicalize(b)) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        }
+}
+
+fn edit_documents(
+    mut events: EventReader<EditDocument>,
+    mut documents: Query<&mut Document>,
+    mut changed: EventWriter<DocumentChanged>,
+) {
+    for e in events.iter() {
+        if let Ok(mut document) = documents.get_mut(e.entity) {
+            match &e.edit {
+                Edit::Insert { offset, text } => document.insert(*offset, text),
+                Edit::Delete(range) => document.delete(range.clone()),
+            }
+            changed.send(DocumentChanged {
+                entity: e.entity,
+                version: document.version(),
+                changes: document.take_changes(),
+                cursor: None,
+            });
+        }
+    }
+}
+
+fn undo_documents(
+    mut events: EventReader<UndoDocument>,
+    mut documents: Query<&mut Document>,
+    mut changed: EventWriter<DocumentChanged>,
+) {
+    for e in events.iter() {
+        let mut document = match documents.get_mut(e.entity) {
+            // Only borrowed mutably with something to undo, so it isn't marked changed.
+            Ok(document) if document.history().can_undo() => document,
+            _ => continue,
+        };
+        if let Some(cursor) = document.undo() {
+            changed.send(DocumentChanged {
+                entity: e.entity,
+                version: document.version(),
+                changes: document.take_changes(),
+                cursor: Some(cursor),
+            });
+        }
+    }
+}
+
+fn redo_documents(
+    mut events: EventReader<RedoDocument>,
+    mut documents: Query<&mut Document>,
+    mut changed: EventWriter<DocumentChanged>,
+) {
+    for e in events.iter() {
+        let mut document = match documents.get_mut(e.entity) {
+            Ok(document) if document.history().can_redo() => document,
+            _ => continue,
+        };
+        if let Some(cursor) = document.redo() {
+            changed.send(DocumentChanged {
+                entity: e.entity,
+                version: document.version(),
+                changes: document.take_changes(),
+                cursor: Some(cursor),
+            });
+        }
+    }
+}
+
+#[derive(SystemParam)]
+struct SaveOutcomes<'w, 's> {
+    saved: EventWriter<'w, 's, DocumentSaved>,
+    failed: EventWriter<'w, 's, DocumentSaveFailed>,
+    conflicts: EventWriter<'w, 's, SaveConflict>,
+}
+
+fn save_documents(
+    mut events: EventReader<SaveDocument>,
+    vaults: Res<Vaults>,
+    time: Res<Time>,
+    settings: Res<SaveRetrySettings>,
+    mut retries: ResMut<SaveRetries>,
+    mut documents: Query<&mut Document, Without<VirtualDocument>>,
+    mut outcomes: SaveOutcomes,
+) {
+    for e in events.iter() {
+        let mut document = match documents.get_mut(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let destination = e
+            .path
+            .clone()
+            .or_else(|| document.path().map(Path::to_path_buf));
+        if let Some(path) = &destination {
+            if let Some(key) = vaults.key_for(path) {
+                document.encrypt_with(key);
+            } else if !document.is_encrypted() && vaults.is_locked(path) {
+                let error = io::Error::other("vault is locked");
+                warn!("📄 Failed to save: {error}");
+                outcomes.failed.send(DocumentSaveFailed {
+                    entity: e.entity,
+                    path: destination.clone(),
+                    error,
+                });
+                continue;
+            }
+            let own_path = document.path().is_some_and(|own| own == path);
+            if own_path && document.changed_on_disk() {
+                warn!("📄 Not saving {}, it changed on disk", path.display());
+                retries.forget(e.entity);
+                outcomes.conflicts.send(SaveConflict {
+                    entity: e.entity,
+                    path: path.clone(),
+                });
+                continue;
+            }
+        }
+        let result = match &e.path {
+            Some(path) => document.save_as(path.clone()),
+            None => document.save(),
+        };
+        match result {
+            Ok(()) => {
+                retries.forget(e.entity);
+                let path = document.path().unwrap().to_path_buf();
+                debug!("📄 Saved {}", path.display());
+                outcomes.saved.send(DocumentSaved {
+                    entity: e.entity,
+                    path,
+                });
+            }
+            Err(error) => {
+                let now = time.seconds_since_startup();
+                if is_transient(&error) && retries.schedule(e, &settings, now) {
+                    debug!("📄 Failed to save: {error}, trying again");
+                    continue;
+                }
+                retries.forget(e.entity);
+                warn!("📄 Failed to save: {error}");
+                outcomes.failed.send(DocumentSaveFailed {
+                    entity: e.entity,
+                    path: destination,
+                    error,
+                });
+            }
+        }
+    }
+}
+
+/// Errors of connections and network file systems, which may pass on their own.
+fn is_transient(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::Interrupted
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::NetworkDown
+            | io::ErrorKind::NetworkUnreachable
+            | io::ErrorKind::HostUnreachable
+            | io::ErrorKind::StaleNetworkFileHandle
+            | io::ErrorKind::ResourceBusy
+    )
+}
+
+fn retry_saves(
+    time: Res<Time>,
+    mut retries: ResMut<SaveRetries>,
+    mut saves: EventWriter<SaveDocument>,
+) {
+    let now = time.seconds_since_startup();
+    retries.due.retain(|(due, save)| {
+        if *due > now {
+            return true;
+        }
+        saves.send(save.clone());
+        false
+    });
+}
+
+fn evict_undo_history(mut events: EventReader<EvictCache>, mut documents: Query<&mut Document>) {
+    for e in events.iter().filter(|e| e.cache == Cache::Undo) {
+        if let Ok(mut document) = documents.get_mut(e.entity) {
+            document.history_mut().compress();
+        }
+    }
+}
+
+fn update_memory_usage(mut documents: Query<(&Document, &mut MemoryUsage), Changed<Document>>) {
+    for (document, mut usage) in documents.iter_mut() {
+        usage.buffer = document.buffer().heap_size();
+        usage.undo = document.history().heap_size();
+    }
+}
+
+/// Edits count as use, going by the events as evicting a cache changes the document too.
+/// Being shown counts as well, see [`crate::memory::Visible`].
+fn mark_documents_used(
+    time: Res<Time>,
+    mut events: EventReader<DocumentChanged>,
+    mut usages: Query<&mut MemoryUsage>,
+) {
+    for e in events.iter() {
+        if let Ok(mut usage) = usages.get_mut(e.entity) {
+            usage.last_used = time.seconds_since_startup();
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use crate::document::{Document, DocumentSaveFailed, DocumentSaved, SaveDocuments};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Query, Res},
+    },
+    log::{debug, warn},
+    tasks::IoTaskPool,
+};
+use std::{
+    ffi::OsString,
+    fs::{self, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+};
+
+pub struct ElevatePlugin;
+
+impl Plugin for ElevatePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ElevateSettings>()
+            .init_resource::<PendingSaves>()
+            .add_event::<ElevatedSaveAvailable>()
+            .add_event::<SaveElevated>()
+            .add_system(offer_elevated_save)
+            .add_system(start_elevated_saves.label(SaveDocuments))
+            .add_system(poll_elevated_saves.label(SaveDocuments));
+    }
+}
+
+pub struct ElevateSettings {
+    /// Runs a command as administrator, asking for the password itself, e.g. `pkexec`.
+    /// None turns elevated saving off.
+    pub helper: Option<Vec<OsString>>,
+}
+
+impl Default for ElevateSettings {
+    fn default() -> Self {
+        let helper: Option<&[&str]> = if cfg!(target_os = "linux") {
+            Some(&["pkexec"])
+        } else if cfg!(unix) {
+            // Asks through the program in SUDO_ASKPASS, there is no terminal to ask on.
+            Some(&["sudo", "-A"])
+        } else {
+            None
+        };
+        Self {
+            helper: helper.map(|args| args.iter().map(OsString::from).collect()),
+        }
+    }
+}
+
+/// A save failed for lack of permission and could be retried as administrator, e.g. for a
+/// system config. The prompt asking whether to is up to the UI, answered with
+/// [`SaveElevated`].
+#[derive(Clone, Debug)]
+pub struct ElevatedSaveAvailable {
+    pub entity: Entity,
+    pub path: PathBuf,
+}
+
+/// Saves to `path` through the helper. The file is written in place, so it keeps its owner
+/// and permissions, but unlike a normal save it is not atomic. Ends in
+/// [`DocumentSaved`] or [`DocumentSaveFailed`] like any save.
+#[derive(Clone, Debug)]
+pub struct SaveElevated {
+    pub entity: Entity,
+    pub path: PathBuf,
+}
+
+type Outcome = (Entity, PathBuf, u64, io::Result<()>);
+
+/// Saves wait on the helper, and the password prompt, on the IO pool and report back here.
+struct PendingSaves {
+    sender: Mutex<Sender<Outcome>>,
+    receiver: Mutex<Receiver<Outcome>>,
+}
+
+impl Default for PendingSaves {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender: Mutex::new(sender),
+            receiver: Mutex::new(receiver),
+        }
+    }
+}
+
+fn offer_elevated_save(
+    mut failed: EventReader<DocumentSaveFailed>,
+    settings: Res<ElevateSettings>,
+    mut available: EventWriter<ElevatedSaveAvailable>,
+) {
+    if settings.helper.is_none() {
+        return;
+    }
+    for e in failed.iter() {
+        if let (io::ErrorKind::PermissionDenied, Some(path)) = (e.error.kind(), &e.path) {
+            available.send(ElevatedSaveAvailable {
+                entity: e.entity,
+                path: path.clone(),
+            });
+        }
+    }
+}
+
+fn start_elevated_saves(
+    mut events: EventReader<SaveElevated>,
+    settings: Res<ElevateSettings>,
+    documents: Query<&Document>,
+    pool: Res<IoTaskPool>,
+    pending: Res<PendingSaves>,
+) {
+    let helper = match &settings.helper {
+        Some(helper) if !helper.is_empty() => helper,
+        _ => return,
+    };
+    for e in events.iter() {
+        let document = match documents.get(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let sender = pending.sender.lock().unwrap().clone();
+        let version = document.version();
+        // Staged on this thread, the pool can't read the document.
+        let staged = Staged::new(document, &e.path);
+        let (entity, path, helper) = (e.entity, e.path.clone(), helper.clone());
+        debug!("📄 Saving {} as administrator", path.display());
+        pool.spawn(async move {
+            let result = staged.and_then(|staged| {
+                staged.check()?;
+                copy_as_admin(&helper, &staged.file, &path)
+            });
+            let _ = sender.send((entity, path, version, result));
+        })
+        .detach();
+    }
+}
+
+/// A copy of the document for the helper to install, in a directory of its own that only
+/// the user can enter. Removed with the directory when dropped.
+struct Staged {
+    dir: PathBuf,
+    file: PathBuf,
+}
+
+impl Staged {
+    /// Writes the document under a random name nobody else can have created or replaced,
+    /// since the helper copies it as administrator.
+    fn new(document: &Document, path: &Path) -> io::Result<Self> {
+        let mut random = [0; 16];
+        getrandom::getrandom(&mut random).map_err(io::Error::other)?;
+        let name: String = random.iter().map(|b| format!("{b:02x}")).collect();
+        let dir = std::env::temp_dir().join(format!("dip-elevate-{name}"));
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(&dir)?;
+        let staged = Self {
+            file: dir.join(path.file_name().unwrap_or_default()),
+            dir,
+        };
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut out = BufWriter::new(options.open(&staged.file)?);
+        document.encode(&mut out)?;
+        out.flush()?;
+        Ok(staged)
+    }
+
+    /// Fails unless the directory and the file are still the user's own and private, right
+    /// before the helper reads them.
+    fn check(&self) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            let uid = unsafe { libc::geteuid() };
+            let private = |m: &fs::Metadata| m.uid() == uid && m.mode() & 0o077 == 0;
+            let dir = fs::symlink_metadata(&self.dir)?;
+            let file = fs::symlink_metadata(&self.file)?;
+            if !(dir.is_dir() && file.is_file() && private(&dir) && private(&file)) {
+                let message = format!("{} was tampered with", self.file.display());
+                return Err(io::Error::other(message));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Staged {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// `cp` into an existing file overwrites its contents and leaves owner and mode alone.
+fn copy_as_admin(helper: &[OsString], staged: &Path, path: &Path) -> io::Result<()> {
+    let output = Command::new(&helper[0])
+        .args(&helper[1..])
+        .arg("cp")
+        .arg("--")
+        .arg(staged)
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = match stderr.trim() {
+            "" => format!(
+                "{} exited with {}",
+                helper[0].to_string_lossy(),
+                output.status
+            ),
+            stderr => stderr.to_string(),
+        };
+        Err(io::Error::other(message))
+    }
+}
+
+fn poll_elevated_saves(
+    pending: Res<PendingSaves>,
+    mut documents: Query<&mut Document>,
+    mut saved: EventWriter<DocumentSaved>,
+    mut failed: EventWriter<DocumentSaveFailed>,
+) {
+    let receiver = pending.receiver.lock().unwrap();
+    while let Ok((entity, path, version, result)) = receiver.try_recv() {
+        match result {
+            Ok(()) => {
+                debug!("📄 Saved {} as administrator", path.display());
+                if let Ok(mut document) = documents.get_mut(entity) {
+                    document.saved_elsewhere(path.clone(), version);
+                }
+                saved.send(DocumentSaved { entity, path });
+            }
+            Err(error) => {
+                warn!("📄 Failed to save as administrator: {error}");
+                // Not denied permission in a way elevating again would help with.
+                let error = io::Error::other(error.to_string());
+                failed.send(DocumentSaveFailed {
+                    entity,
+                    path: Some(path),
+                    error,
+                });
+            }
+        }
+    }
+}
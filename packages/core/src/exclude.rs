@@ -0,0 +1,138 @@
+use crate::config::SettingsSection;
+use bevy::log::warn;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+use std::{collections::BTreeMap, path::Path};
+
+pub const SETTINGS_FILE: &str = ".dip/settings.json";
+
+/// Globs matched against paths relative to the workspace root. A matching directory
+/// excludes everything below it.
+#[derive(Clone, Debug)]
+pub struct Exclude {
+    patterns: Vec<String>,
+    set: GlobSet,
+}
+
+impl Default for Exclude {
+    fn default() -> Self {
+        Self {
+            patterns: vec![],
+            set: GlobSet::empty(),
+        }
+    }
+}
+
+impl Exclude {
+    pub fn new<S: AsRef<str>>(
+        patterns: impl IntoIterator<Item = S>,
+    ) -> Result<Self, globset::Error> {
+        let mut builder = GlobSetBuilder::new();
+        let mut kept = vec![];
+        for pattern in patterns {
+            let pattern = pattern.as_ref();
+            builder.add(Glob::new(pattern)?);
+            kept.push(pattern.to_string());
+        }
+        Ok(Self {
+            patterns: kept,
+            set: builder.build()?,
+        })
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// Whether `relative` or one of its parents matches. Use [`Exclude::matches`] instead
+    /// when walking down a tree and parents were checked already.
+    pub fn is_excluded(&self, relative: &Path) -> bool {
+        relative
+            .ancestors()
+            .filter(|p| !p.as_os_str().is_empty())
+            .any(|p| self.set.is_match(p))
+    }
+
+    pub fn matches(&self, relative: &Path) -> bool {
+        self.set.is_match(relative)
+    }
+}
+
+/// Paths left out of watching, indexing and search, and never auto-saved. Each set already
+/// includes `files.exclude`.
+#[derive(Clone, Debug)]
+pub struct Excludes {
+    pub watcher: Exclude,
+    pub search: Exclude,
+    pub autosave: Exclude,
+}
+
+impl Default for Excludes {
+    fn default() -> Self {
+        Self::from_settings(&ExcludeSettings::default())
+    }
+}
+
+impl Excludes {
+    /// Defaults overridden by the settings file of `root`.
+    pub fn load(root: &Path) -> Self {
+        let mut settings = ExcludeSettings::default();
+        settings.merge_file(&root.join(SETTINGS_FILE));
+        Self::from_settings(&settings)
+    }
+
+    pub fn from_settings(settings: &ExcludeSettings) -> Self {
+        let build = |specific: &BTreeMap<String, bool>| {
+            let patterns = enabled(&settings.files).chain(enabled(specific));
+            Exclude::new(patterns).unwrap_or_else(|e| {
+                warn!("Invalid exclude glob: {e}");
+                Exclude::default()
+            })
+        };
+        Self {
+            watcher: build(&settings.watcher),
+            search: build(&settings.search),
+            autosave: build(&settings.autosave),
+        }
+    }
+}
+
+/// The exclude part of the workspace settings file. Like VS Code, each map turns globs
+/// on or off, so a workspace can disable a default with `false`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ExcludeSettings {
+    #[serde(rename = "files.exclude", default)]
+    pub files: BTreeMap<String, bool>,
+    #[serde(rename = "files.watcherExclude", default)]
+    pub watcher: BTreeMap<String, bool>,
+    #[serde(rename = "search.exclude", default)]
+    pub search: BTreeMap<String, bool>,
+    #[serde(rename = "files.autoSaveExclude", default)]
+    pub autosave: BTreeMap<String, bool>,
+}
+
+impl Default for ExcludeSettings {
+    fn default() -> Self {
+        let globs = |globs: &[&str]| globs.iter().map(|g| (g.to_string(), true)).collect();
+        Self {
+            files: globs(&["**/.git"]),
+            watcher: globs(&["**/node_modules", "**/target"]),
+            search: globs(&["**/node_modules", "**/target"]),
+            autosave: BTreeMap::new(),
+        }
+    }
+}
+
+impl SettingsSection for ExcludeSettings {
+    /// Globs of `other` win over the ones in `self`.
+    fn merge(&mut self, other: Self) {
+        self.files.extend(other.files);
+        self.watcher.extend(other.watcher);
+        self.search.extend(other.search);
+        self.autosave.extend(other.autosave);
+    }
+}
+
+fn enabled(globs: &BTreeMap<String, bool>) -> impl Iterator<Item = &String> {
+    globs.iter().filter(|(_, on)| **on).map(|(glob, _)| glob)
+}
@@ -0,0 +1,382 @@
+//! Turns the [`Timeline`] of a document into something to show others how it was edited,
+//! e.g. in a tutorial or a bug report: an [asciinema] cast playing the edits back in a
+//! terminal, with what each one typed highlighted, or a Markdown list of the diff each
+//! one made.
+//!
+//! [asciinema]: https://docs.asciinema.org/manual/asciicast/v2/
+
+use crate::{
+    command::{RegisterCommand, RunCommand},
+    config::Settings,
+    diff::diff,
+    document::Document,
+    memory::MemoryUsage,
+    pipeline::{AppPipelineExt, EditorStage},
+    playback::{Playback, PlaybackSettings, Timeline},
+    text_buffer::TextBuffer,
+    vault::Vaults,
+    workspace::Workspace,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        system::{Commands, Query, Res},
+    },
+    log::{debug, warn},
+};
+use serde_json::json;
+use std::{fmt::Write, fs, ops::Range, path::PathBuf};
+
+/// Unchanged lines shown around each change of a diff.
+const CONTEXT: usize = 2;
+
+const HIGHLIGHT: &str = "\x1b[30;42m";
+const REVERSE: &str = "\x1b[7m";
+const RESET: &str = "\x1b[0m";
+
+pub struct ExportPlugin;
+
+impl Plugin for ExportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ExportSession>()
+            .add_event::<SessionExported>()
+            .add_event::<SessionExportFailed>()
+            .register_command("history.exportCast", "History", "Export Edits as Cast")
+            .register_command("history.exportDiffs", "History", "Export Edits as Diffs")
+            .add_editor_system(EditorStage::Commands, run_export_commands)
+            .add_system(export_sessions);
+    }
+}
+
+/// `export.cast.columns` and `export.cast.rows`, the size of the terminal a cast plays in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExportSettings {
+    pub columns: usize,
+    pub rows: usize,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self {
+            columns: 80,
+            rows: 24,
+        }
+    }
+}
+
+impl ExportSettings {
+    pub fn new(settings: Option<&Settings>) -> Self {
+        let default = Self::default();
+        let settings = match settings {
+            Some(settings) => settings,
+            None => return default,
+        };
+        Self {
+            columns: settings
+                .get("export.cast.columns")
+                .unwrap_or(default.columns),
+            rows: settings.get("export.cast.rows").unwrap_or(default.rows),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionFormat {
+    /// An asciicast v2 file, a frame of the terminal per edit.
+    Cast,
+    /// Markdown with a `diff` block per edit.
+    Diffs,
+}
+
+/// Exports what was done to `entity` since it was opened, or to the source of the replay
+/// it shows. Written to `path`, or opened as an untitled document to look over and save
+/// if None. Edits of an encrypted document are only written into an unlocked vault, and
+/// sealed with its key.
+#[derive(Clone, Debug)]
+pub struct ExportSession {
+    pub entity: Entity,
+    pub format: SessionFormat,
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SessionExported {
+    pub entity: Entity,
+    pub format: SessionFormat,
+    pub path: Option<PathBuf>,
+    /// The untitled document it was opened as.
+    pub document: Option<Entity>,
+    pub steps: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct SessionExportFailed {
+    pub entity: Entity,
+    pub path: Option<PathBuf>,
+    pub error: String,
+}
+
+/// The lines of `buffer` in `lines`, without their line breaks.
+fn lines_of(buffer: &TextBuffer, lines: Range<usize>) -> Vec<String> {
+    lines
+        .map(|line| buffer.get_line_content(line).into_owned())
+        .collect()
+}
+
+/// One terminal screen: the lines around `line`, with `highlights` set off, and a status
+/// line at the bottom.
+fn screen(
+    buffer: &TextBuffer,
+    line: usize,
+    highlights: &[Range<usize>],
+    status: &str,
+    settings: ExportSettings,
+) -> String {
+    let rows = settings.rows.saturating_sub(1).max(1);
+    let top = line
+        .saturating_sub(rows / 2)
+        .min(buffer.line_count().saturating_sub(rows));
+    let mut out = String::from("\x1b[H\x1b[2J");
+    for line in top..(top + rows).min(buffer.line_count()) {
+        let start = buffer.line_start(line);
+        let content = buffer.get_line_content(line);
+        let end = content
+            .char_indices()
+            .nth(settings.columns)
+            .map_or(content.len(), |(i, _)| i);
+        let mut shown = 0;
+        for range in highlights {
+            let from = range.start.saturating_sub(start).clamp(shown, end);
+            let to = range.end.saturating_sub(start).clamp(from, end);
+            if from == to {
+                continue;
+            }
+            let _ = write!(
+                out,
+                "{}{HIGHLIGHT}{}{RESET}",
+                &content[shown..from],
+                &content[from..to]
+            );
+            shown = to;
+        }
+        out.push_str(&content[shown..end]);
+        out.push_str("\r\n");
+    }
+    let _ = write!(out, "\x1b[{};1H{REVERSE} {status} {RESET}", settings.rows);
+    out
+}
+
+/// The edits of `timeline` as an asciicast v2, a screen per edit showing what it typed.
+/// Pauses longer than `idle` seconds are cut short when played.
+pub fn cast(timeline: &Timeline, title: &str, idle: f64, settings: ExportSettings) -> String {
+    let header = json!({
+        "version": 2,
+        "width": settings.columns,
+        "height": settings.rows,
+        "idle_time_limit": idle,
+        "title": title,
+    });
+    let mut out = format!("{header}\n");
+    let steps = timeline.steps();
+    let start = timeline.at(0);
+    let mut buffer = timeline.text_at(0);
+    let status = |position: usize| format!("{title} · {position}/{}", steps.len());
+    let first = screen(&buffer, 0, &[], &status(0), settings);
+    let _ = writeln!(out, "{}", json!([0., "o", first]));
+
+    for (i, step) in steps.iter().enumerate() {
+        // Where the text of each change is now, moved along by the ones after it.
+        let mut highlights: Vec<Range<usize>> = vec![];
+        for (range, text) in &step.changes {
+            buffer.delete(range.start, range.len());
+            buffer.insert(range.start, text);
+            let shift = text.len() as isize - range.len() as isize;
+            highlights.retain(|h| h.end <= range.start || h.start >= range.end);
+            for h in highlights.iter_mut().filter(|h| h.start >= range.end) {
+                *h = (h.start as isize + shift) as usize..(h.end as isize + shift) as usize;
+            }
+            highlights.push(range.start..range.start + text.len());
+        }
+        highlights.sort_by_key(|h| h.start);
+        let line = step
+            .changes
+            .last()
+            .map_or(0, |(range, text)| buffer.line_at(range.start + text.len()));
+        let frame = screen(&buffer, line, &highlights, &status(i + 1), settings);
+        let _ = writeln!(out, "{}", json!([step.at - start, "o", frame]));
+    }
+    out
+}
+
+fn diff_lines(out: &mut String, prefix: char, lines: &[String]) {
+    for line in lines {
+        let _ = writeln!(out, "{prefix}{line}");
+    }
+}
+
+/// The edits of `timeline` as Markdown, a `diff` block per edit with the lines it changed.
+pub fn diffs(timeline: &Timeline, title: &str) -> String {
+    let mut out = format!("# {title}\n");
+    let start = timeline.at(0);
+    let mut buffer = timeline.text_at(0);
+    for (i, step) in timeline.steps().iter().enumerate() {
+        let _ = write!(
+            out,
+            "\n## Edit {} at {:.1}s\n\n```diff\n",
+            i + 1,
+            step.at - start
+        );
+        for (range, text) in &step.changes {
+            let first = buffer.line_at(range.start);
+            let before = lines_of(&buffer, first..buffer.line_at(range.end) + 1);
+            let above = lines_of(&buffer, first.saturating_sub(CONTEXT)..first);
+            let end = first + before.len();
+            let below = lines_of(&buffer, end..(end + CONTEXT).min(buffer.line_count()));
+            buffer.delete(range.start, range.len());
+            buffer.insert(range.start, text);
+            let after = lines_of(&buffer, first..buffer.line_at(range.start + text.len()) + 1);
+
+            let context = above.len() + below.len();
+            let _ = writeln!(
+                out,
+                "@@ -{},{} +{},{} @@",
+                first - above.len() + 1,
+                before.len() + context,
+                first - above.len() + 1,
+                after.len() + context
+            );
+            diff_lines(&mut out, ' ', &above);
+            let mut copied = 0;
+            for hunk in diff(&before, &after) {
+                diff_lines(&mut out, ' ', &before[copied..hunk.old.start]);
+                diff_lines(&mut out, '-', &before[hunk.old.clone()]);
+                diff_lines(&mut out, '+', &after[hunk.new.clone()]);
+                copied = hunk.old.end;
+            }
+            diff_lines(&mut out, ' ', &before[copied..]);
+            diff_lines(&mut out, ' ', &below);
+        }
+        out.push_str("```\n");
+    }
+    out
+}
+
+fn run_export_commands(
+    mut events: EventReader<RunCommand>,
+    workspace: Res<Workspace>,
+    mut exports: EventWriter<ExportSession>,
+) {
+    for e in events.iter() {
+        let format = match e.id.as_str() {
+            "history.exportCast" => SessionFormat::Cast,
+            "history.exportDiffs" => SessionFormat::Diffs,
+            _ => continue,
+        };
+        if let Some(entity) = workspace.active() {
+            exports.send(ExportSession {
+                entity,
+                format,
+                path: None,
+            });
+        }
+    }
+}
+
+fn export_sessions(
+    mut commands: Commands,
+    mut events: EventReader<ExportSession>,
+    (user, vaults): (Option<Res<Settings>>, Res<Vaults>),
+    playbacks: Query<&Playback>,
+    timelines: Query<(&Document, &Timeline)>,
+    mut exported: EventWriter<SessionExported>,
+    mut failed: EventWriter<SessionExportFailed>,
+) {
+    for e in events.iter() {
+        let source = playbacks.get(e.entity).map_or(e.entity, |p| p.source);
+        let (document, timeline) = match timelines.get(source) {
+            Ok(found) => found,
+            Err(_) => {
+                failed.send(SessionExportFailed {
+                    entity: e.entity,
+                    path: e.path.clone(),
+                    error: "nothing was recorded for it".to_string(),
+                });
+                continue;
+            }
+        };
+        let key = match &e.path {
+            Some(path) => vaults.key_for(path),
+            None => document.path().and_then(|path| vaults.key_for(path)),
+        };
+        let locked = e.path.as_deref().is_some_and(|path| vaults.is_locked(path));
+        if key.is_none() && (document.is_encrypted() || locked) {
+            let error = if locked {
+                "vault is locked"
+            } else {
+                "it is encrypted, export it into its vault"
+            };
+            warn!("🎬 Not exporting: {error}");
+            failed.send(SessionExportFailed {
+                entity: e.entity,
+                path: e.path.clone(),
+                error: error.to_string(),
+            });
+            continue;
+        }
+        let title = document
+            .path()
+            .and_then(|path| path.file_name())
+            .map_or("Untitled".to_string(), |name| {
+                name.to_string_lossy().into_owned()
+            });
+        let text = match e.format {
+            SessionFormat::Cast => {
+                let idle = PlaybackSettings::new(user.as_deref()).max_pause;
+                cast(timeline, &title, idle, ExportSettings::new(user.as_deref()))
+            }
+            SessionFormat::Diffs => diffs(timeline, &title),
+        };
+        let steps = timeline.steps().len();
+        debug!("🎬 Exported {steps} edits of {title} as {:?}", e.format);
+
+        let document = match &e.path {
+            Some(path) => {
+                let bytes = match &key {
+                    Some(key) => key.seal(text.as_bytes()),
+                    None => text.into_bytes(),
+                };
+                if let Err(error) = fs::write(path, bytes) {
+                    warn!("🎬 Failed to write {}: {error}", path.display());
+                    failed.send(SessionExportFailed {
+                        entity: e.entity,
+                        path: Some(path.clone()),
+                        error: error.to_string(),
+                    });
+                    continue;
+                }
+                None
+            }
+            None => {
+                let mut untitled = Document::new(None, TextBuffer::from(text.as_str()));
+                if let Some(key) = key {
+                    untitled.encrypt_with(key);
+                }
+                let entity = commands
+                    .spawn()
+                    .insert(untitled)
+                    .insert(MemoryUsage::default())
+                    .id();
+                Some(entity)
+            }
+        };
+        exported.send(SessionExported {
+            entity: e.entity,
+            format: e.format,
+            path: e.path.clone(),
+            document,
+            steps,
+        });
+    }
+}
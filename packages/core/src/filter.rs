@@ -0,0 +1,367 @@
+use crate::{
+    announce::count,
+    cursor::{self, Documents, Placed, Secondaries},
+    diff::{diff, Hunk},
+    document::{DocumentChanged, DocumentEditSet},
+    pipeline::{AppPipelineExt, EditorStage},
+    toolchain::Toolchains,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Commands, Res, ResMut},
+    },
+    log::{debug, warn},
+    tasks::IoTaskPool,
+};
+use std::{
+    io::{self, Write},
+    ops::Range,
+    process::{Command, Stdio},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+    thread,
+};
+
+pub struct FilterPlugin;
+
+impl Plugin for FilterPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PendingFilters>()
+            .add_event::<FilterText>()
+            .add_event::<FilterPreview>()
+            .add_event::<TextFiltered>()
+            .add_event::<FilterFailed>()
+            .add_system(start_filters)
+            .add_editor_system(EditorStage::Edits, finish_filters.label(DocumentEditSet));
+    }
+}
+
+/// Pipes each selection of a document through a shell command, e.g. `sort` or `jq .`, and
+/// replaces it with the output, all in one undo step. The whole document goes through it
+/// if nothing is selected. With `preview`, nothing changes and the result comes back as
+/// a [`FilterPreview`].
+#[derive(Clone, Debug)]
+pub struct FilterText {
+    pub entity: Entity,
+    pub command: String,
+    pub preview: bool,
+}
+
+/// What a filter would replace, in document order.
+#[derive(Clone, Debug)]
+pub struct FilterPreview {
+    pub entity: Entity,
+    pub command: String,
+    pub edits: Vec<FilterEdit>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FilterEdit {
+    pub range: Range<usize>,
+    pub text: String,
+    /// Lines changed within `range`, old lines against the lines of `text`.
+    pub hunks: Vec<Hunk>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TextFiltered {
+    pub entity: Entity,
+    pub command: String,
+}
+
+/// The command failed to run or exited with an error, or the document changed while it
+/// ran. Nothing was changed.
+#[derive(Clone, Debug)]
+pub struct FilterFailed {
+    pub entity: Entity,
+    pub command: String,
+    pub error: String,
+}
+
+struct Outcome {
+    entity: Entity,
+    version: u64,
+    request: FilterText,
+    /// The selections filtered, empty for the whole document.
+    selections: Vec<Range<usize>>,
+    result: io::Result<Vec<String>>,
+}
+
+/// Commands run on the IO pool and report back here.
+struct PendingFilters {
+    sender: Mutex<Sender<Outcome>>,
+    receiver: Mutex<Receiver<Outcome>>,
+}
+
+impl Default for PendingFilters {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender: Mutex::new(sender),
+            receiver: Mutex::new(receiver),
+        }
+    }
+}
+
+fn start_filters(
+    mut events: EventReader<FilterText>,
+    mut documents: Documents<()>,
+    mut secondaries: Secondaries,
+    (toolchains, pool, pending): (Res<Toolchains>, Res<IoTaskPool>, Res<PendingFilters>),
+    mut failed: EventWriter<FilterFailed>,
+) {
+    for e in events.iter() {
+        let (document, cursor, selection, ()) = match documents.get_mut(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        if document.is_read_only() && !e.preview {
+            failed.send(FilterFailed {
+                entity: e.entity,
+                command: e.command.clone(),
+                error: "The document is read only".to_string(),
+            });
+            continue;
+        }
+        let selections = selections(e.entity, (&cursor, &selection), &mut secondaries);
+        let buffer = document.buffer();
+        let inputs: Vec<String> = if selections.is_empty() {
+            vec![buffer.to_string()]
+        } else {
+            selections
+                .iter()
+                .map(|r| buffer.text_in(r.clone()))
+                .collect()
+        };
+
+        let toolchains = toolchains.clone();
+        let sender = pending.sender.lock().unwrap().clone();
+        let (entity, version, request) = (e.entity, document.version(), e.clone());
+        let what = match selections.len() {
+            0 => "the document".to_string(),
+            n => count(n, "selection"),
+        };
+        debug!("🚰 Filtering {what} through {}", e.command);
+        pool.spawn(async move {
+            let result = inputs
+                .iter()
+                .map(|input| run(shell(&toolchains, &request.command), input))
+                .collect();
+            let _ = sender.send(Outcome {
+                entity,
+                version,
+                request,
+                selections,
+                result,
+            });
+        })
+        .detach();
+    }
+}
+
+/// The non-empty selections of a document in order, merged where they overlap.
+fn selections(
+    entity: Entity,
+    primary: (&cursor::Cursor, &cursor::Selection),
+    secondaries: &mut Secondaries,
+) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = cursor::carets(entity, primary, secondaries)
+        .iter()
+        .map(cursor::Caret::range)
+        .filter(|range| !range.is_empty())
+        .collect();
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// `command` run by the shell of the platform, in the workspace root.
+fn shell(toolchains: &Toolchains, command: &str) -> Command {
+    let mut shell = if cfg!(windows) {
+        let mut shell = toolchains.command("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = toolchains.command("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command);
+    shell
+}
+
+fn run(mut command: Command, input: &str) -> io::Result<String> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Written from another thread, a command filling its stdout before reading all of
+    // stdin would block both ends otherwise.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = input.to_string();
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output()?;
+    match writer.join() {
+        // Commands such as `head` may stop reading early.
+        Ok(Err(e)) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e),
+        _ => {}
+    }
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = match stderr.trim() {
+            "" => format!("Exited with {}", output.status),
+            stderr => stderr.to_string(),
+        };
+        return Err(io::Error::other(message));
+    }
+    String::from_utf8(output.stdout)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "The output is not UTF-8"))
+}
+
+fn finish_filters(
+    mut commands: Commands,
+    pending: Res<PendingFilters>,
+    mut documents: Documents<()>,
+    mut secondaries: Secondaries,
+    mut placed: ResMut<Placed>,
+    (mut previews, mut filtered, mut failed): (
+        EventWriter<FilterPreview>,
+        EventWriter<TextFiltered>,
+        EventWriter<FilterFailed>,
+    ),
+    mut changed: EventWriter<DocumentChanged>,
+) {
+    let receiver = pending.receiver.lock().unwrap();
+    while let Ok(outcome) = receiver.try_recv() {
+        let Outcome {
+            entity,
+            version,
+            request,
+            selections,
+            result,
+        } = outcome;
+        let fail = |failed: &mut EventWriter<FilterFailed>, error: String| {
+            warn!("🚰 {} failed: {error}", request.command);
+            failed.send(FilterFailed {
+                entity,
+                command: request.command.clone(),
+                error,
+            });
+        };
+        let outputs = match result {
+            Ok(outputs) => outputs,
+            Err(error) => {
+                fail(&mut failed, error.to_string());
+                continue;
+            }
+        };
+        let (mut document, mut cursor, mut selection, ()) = match documents.get_mut(entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let moved = self::selections(entity, (&cursor, &selection), &mut secondaries) != selections;
+        if document.version() != version || (!request.preview && moved) {
+            fail(
+                &mut failed,
+                "The document changed while filtering".to_string(),
+            );
+            continue;
+        }
+
+        let mut ranges = selections;
+        if ranges.is_empty() {
+            ranges.push(0..document.buffer().len());
+        }
+        let edits: Vec<(Range<usize>, String)> = ranges.into_iter().zip(outputs).collect();
+        if request.preview {
+            let edits = edits
+                .into_iter()
+                .map(|(range, text)| FilterEdit {
+                    hunks: line_hunks(&document.buffer().text_in(range.clone()), &text),
+                    range,
+                    text,
+                })
+                .collect();
+            previews.send(FilterPreview {
+                entity,
+                command: request.command,
+                edits,
+            });
+            continue;
+        }
+
+        if edits
+            .iter()
+            .all(|(range, text)| document.buffer().text_in(range.clone()) == *text)
+        {
+            filtered.send(TextFiltered {
+                entity,
+                command: request.command,
+            });
+            continue;
+        }
+        let whole = edits.len() == 1 && edits[0].0 == (0..document.buffer().len());
+        let position = cursor.position(document.buffer());
+        document.history_mut().begin();
+        if whole {
+            document.apply_edits(edits, 0);
+            document.history_mut().commit();
+        } else {
+            // The output of each selection ends up selected.
+            let mut carets = cursor::carets(entity, (&cursor, &selection), &mut secondaries);
+            cursor::edit_carets(&mut document, &mut carets, |document, caret| {
+                let range = caret.range();
+                match edits.iter().find(|(r, _)| r.start == range.start) {
+                    Some((r, text)) => {
+                        document.delete(r.clone());
+                        document.insert(r.start, text);
+                        r.start..r.start + text.len()
+                    }
+                    None => range,
+                }
+            });
+            cursor::store(
+                carets,
+                (&mut cursor, &mut selection),
+                &mut secondaries,
+                &mut commands,
+            );
+            placed.0.insert((entity, document.version()));
+        }
+        debug!("🚰 Filtered through {}", request.command);
+
+        // Replaced as a whole, the document keeps the line and column of the cursor.
+        let buffer = document.buffer();
+        let line = position.line.min(buffer.line_count().saturating_sub(1));
+        let offset = whole.then(|| buffer.offset_at(line, position.column));
+        changed.send(DocumentChanged {
+            entity,
+            version: document.version(),
+            changes: document.take_changes(),
+            cursor: offset,
+        });
+        filtered.send(TextFiltered {
+            entity,
+            command: request.command,
+        });
+    }
+}
+
+fn line_hunks(old: &str, new: &str) -> Vec<Hunk> {
+    let old: Vec<&str> = old.split_inclusive('\n').collect();
+    let new: Vec<&str> = new.split_inclusive('\n').collect();
+    diff(&old, &new)
+}
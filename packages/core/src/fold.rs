@@ -0,0 +1,267 @@
+use crate::{
+    command::{RegisterCommand, RunCommand},
+    cursor::{Cursor, Secondaries, SecondaryCursor, Selection},
+    damage::DecorationsChanged,
+    document::{Document, DocumentChanged},
+    pipeline::{AppPipelineExt, EditorStage},
+    text_buffer::TextBuffer,
+    workspace::Workspace,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        query::{Added, Changed, Without},
+        system::{Commands, Query, Res},
+        world::Mut,
+    },
+    log::debug,
+};
+use std::{collections::HashMap, ops::Range};
+
+pub use dip_editor::fold::{indentation_regions, Folds};
+
+pub struct FoldPlugin;
+
+impl Plugin for FoldPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ChangeFolds>()
+            .add_event::<SetFoldingRanges>()
+            .add_event::<FoldsChanged>()
+            .register_command("fold.fold", "Fold", "Fold")
+            .register_command("fold.unfold", "Fold", "Unfold")
+            .register_command("fold.toggle", "Fold", "Toggle Fold")
+            .register_command("fold.foldAll", "Fold", "Fold All")
+            .register_command("fold.unfoldAll", "Fold", "Unfold All")
+            .add_system(attach_folds)
+            .add_editor_system(EditorStage::Commands, run_fold_commands)
+            .add_system(change_folds)
+            .add_system(set_folding_ranges)
+            .add_editor_system(EditorStage::Derive, shift_folds)
+            .add_system(reveal_cursors);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FoldChange {
+    /// Folds `lines`, or the innermost region around the primary cursor that is still open.
+    Fold(Option<Range<usize>>),
+    /// Unfolds what overlaps `lines`, or the folds starting on the primary cursor's line.
+    Unfold(Option<Range<usize>>),
+    Toggle,
+    FoldAll,
+    UnfoldAll,
+}
+
+#[derive(Clone, Debug)]
+pub struct ChangeFolds {
+    pub entity: Entity,
+    pub change: FoldChange,
+}
+
+/// Regions a provider like a language server found foldable, by line, replacing the ones
+/// found by indentation. None goes back to indentation.
+#[derive(Clone, Debug)]
+pub struct SetFoldingRanges {
+    pub entity: Entity,
+    pub ranges: Option<Vec<Range<usize>>>,
+}
+
+/// Lines of a document were folded or unfolded.
+#[derive(Clone, Copy, Debug)]
+pub struct FoldsChanged {
+    pub entity: Entity,
+}
+
+fn attach_folds(
+    mut commands: Commands,
+    documents: Query<Entity, (Added<Document>, Without<Folds>)>,
+) {
+    for entity in documents.iter() {
+        commands.entity(entity).insert(Folds::default());
+    }
+}
+
+fn run_fold_commands(
+    mut events: EventReader<RunCommand>,
+    workspace: Res<Workspace>,
+    mut changes: EventWriter<ChangeFolds>,
+) {
+    for e in events.iter() {
+        let entity = match (e.id.starts_with("fold."), workspace.active()) {
+            (true, Some(entity)) => entity,
+            _ => continue,
+        };
+        let change = match e.id.as_str() {
+            "fold.fold" => FoldChange::Fold(None),
+            "fold.unfold" => FoldChange::Unfold(None),
+            "fold.toggle" => FoldChange::Toggle,
+            "fold.foldAll" => FoldChange::FoldAll,
+            "fold.unfoldAll" => FoldChange::UnfoldAll,
+            _ => continue,
+        };
+        changes.send(ChangeFolds { entity, change });
+    }
+}
+
+/// Moves a cursor `folds` hides to the end of the header folding it.
+fn move_out(
+    buffer: &TextBuffer,
+    folds: &Folds,
+    cursor: &mut Mut<Cursor>,
+    selection: &mut Mut<Selection>,
+) {
+    let line = buffer.line_at(cursor.offset.min(buffer.len()));
+    if folds.is_hidden(line) {
+        let header = folds.shown_as(line);
+        let end = buffer.line_start(header) + buffer.get_line_length(header);
+        **cursor = Cursor::at(end);
+        selection.anchor = end;
+    }
+}
+
+fn change_folds(
+    mut events: EventReader<ChangeFolds>,
+    mut documents: Query<(&Document, &mut Cursor, &mut Selection, &mut Folds)>,
+    mut secondaries: Secondaries,
+    mut folded: EventWriter<FoldsChanged>,
+    mut decorations: EventWriter<DecorationsChanged>,
+) {
+    for e in events.iter() {
+        let (document, mut cursor, mut selection, mut folds) = match documents.get_mut(e.entity) {
+            Ok(found) => found,
+            Err(_) => continue,
+        };
+        let buffer = document.buffer();
+        let line = buffer.line_at(cursor.offset.min(buffer.len()));
+        let on_line = |folds: &Folds| folds.folded().iter().any(|r| r.start == line);
+        let change = match &e.change {
+            FoldChange::Toggle if on_line(&folds) => FoldChange::Unfold(None),
+            FoldChange::Toggle => FoldChange::Fold(None),
+            change => change.clone(),
+        };
+        // Lines below the first one changed move on screen.
+        let first = match &change {
+            FoldChange::Fold(Some(lines)) | FoldChange::Unfold(Some(lines)) => lines.start,
+            FoldChange::FoldAll | FoldChange::UnfoldAll => 0,
+            _ => line,
+        };
+        let changed = match change {
+            FoldChange::Fold(Some(lines)) => {
+                folds.fold(lines.start..lines.end.min(buffer.line_count()))
+            }
+            FoldChange::Fold(None) => match folds.region_at(buffer, line) {
+                Some(region) => folds.fold(region),
+                None => false,
+            },
+            FoldChange::Unfold(Some(lines)) => folds.unfold(lines),
+            FoldChange::Unfold(None) => folds.unfold_header(line),
+            FoldChange::FoldAll => folds.fold_all(buffer),
+            FoldChange::UnfoldAll => folds.unfold_all(),
+            FoldChange::Toggle => unreachable!("resolved above"),
+        };
+        if !changed {
+            continue;
+        }
+        // Otherwise they would unfold it again right away.
+        move_out(buffer, &folds, &mut cursor, &mut selection);
+        for (_, secondary, mut cursor, mut selection) in secondaries.iter_mut() {
+            if secondary.document == e.entity {
+                move_out(buffer, &folds, &mut cursor, &mut selection);
+            }
+        }
+        debug!(
+            "📑 {} folded, {} of {} lines visible",
+            folds.folded().len(),
+            folds.visible_line_count(buffer.line_count()),
+            buffer.line_count()
+        );
+        folded.send(FoldsChanged { entity: e.entity });
+        decorations.send(DecorationsChanged {
+            entity: e.entity,
+            lines: first..buffer.line_count(),
+        });
+    }
+}
+
+fn set_folding_ranges(
+    mut events: EventReader<SetFoldingRanges>,
+    mut documents: Query<(&Document, &mut Folds)>,
+) {
+    for e in events.iter() {
+        if let Ok((document, mut folds)) = documents.get_mut(e.entity) {
+            let line_count = document.buffer().line_count();
+            folds.provide(e.ranges.as_deref(), line_count);
+        }
+    }
+}
+
+fn shift_folds(
+    mut changes: EventReader<DocumentChanged>,
+    mut documents: Query<(&Document, &mut Folds)>,
+    mut folded: EventWriter<FoldsChanged>,
+) {
+    for e in changes.iter() {
+        let (document, mut folds) = match documents.get_mut(e.entity) {
+            Ok(found) => found,
+            Err(_) => continue,
+        };
+        let line_count = document.buffer().line_count();
+        let mut unfolded = false;
+        for change in &e.changes {
+            unfolded |= folds.edited(&change.lines, line_count);
+        }
+        if unfolded {
+            folded.send(FoldsChanged { entity: e.entity });
+        }
+    }
+}
+
+/// Unfolds what hides a cursor that ended up in it, e.g. after going to a search result.
+/// Moving the cursor over folds never does.
+fn reveal_cursors(
+    primaries: Query<(Entity, &Cursor), Changed<Cursor>>,
+    secondaries: Query<(&SecondaryCursor, &Cursor), Changed<Cursor>>,
+    mut documents: Query<(&Document, &mut Folds)>,
+    mut folded: EventWriter<FoldsChanged>,
+    mut decorations: EventWriter<DecorationsChanged>,
+) {
+    let mut offsets: HashMap<Entity, Vec<usize>> = HashMap::new();
+    for (entity, cursor) in primaries.iter() {
+        offsets.entry(entity).or_default().push(cursor.offset);
+    }
+    for (secondary, cursor) in secondaries.iter() {
+        offsets
+            .entry(secondary.document)
+            .or_default()
+            .push(cursor.offset);
+    }
+    for (entity, offsets) in offsets {
+        let (document, mut folds) = match documents.get_mut(entity) {
+            Ok(found) => found,
+            Err(_) => continue,
+        };
+        if folds.is_empty() {
+            continue;
+        }
+        let buffer = document.buffer();
+        let lines: Vec<usize> = offsets
+            .into_iter()
+            .map(|offset| buffer.line_at(offset.min(buffer.len())))
+            .filter(|line| folds.is_hidden(*line))
+            .collect();
+        let first = match lines.iter().min() {
+            Some(first) => *first,
+            None => continue,
+        };
+        for line in lines {
+            folds.unfold(line..line + 1);
+        }
+        folded.send(FoldsChanged { entity });
+        decorations.send(DecorationsChanged {
+            entity,
+            lines: first.saturating_sub(1)..buffer.line_count(),
+        });
+    }
+}
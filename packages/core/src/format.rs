@@ -0,0 +1,135 @@
+use crate::{
+    document::{Document, DocumentChanged, DocumentEditSet, Utf16Position},
+    pipeline::{AppPipelineExt, EditorStage},
+    text_buffer::TextBuffer,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::Query,
+    },
+    log::debug,
+};
+use lsp_types::TextEdit;
+use std::ops::Range;
+
+pub struct FormatPlugin;
+
+impl Plugin for FormatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<FormatOnTypeRequested>()
+            .add_event::<FormatOnTypeEdits>()
+            .add_editor_system(
+                EditorStage::Edits,
+                apply_on_type_edits.label(DocumentEditSet),
+            );
+    }
+}
+
+/// Characters a language server formats after, from its `documentOnTypeFormattingProvider`.
+/// Documents without it are formatted by [`format_on_type`] if their
+/// [`IndentRules::format_on_type`](crate::indent::IndentRules::format_on_type) is set.
+#[derive(Component, Clone, Debug, PartialEq, Eq)]
+pub struct OnTypeTriggers(pub Vec<char>);
+
+/// `trigger` was typed in a document with [`OnTypeTriggers`], the cursor is at `position`.
+/// The language client answers with [`FormatOnTypeEdits`].
+#[derive(Clone, Copy, Debug)]
+pub struct FormatOnTypeRequested {
+    pub entity: Entity,
+    pub version: u64,
+    pub position: Utf16Position,
+    pub trigger: char,
+}
+
+/// Edits a server returned for [`FormatOnTypeRequested`]. Dropped if the document changed
+/// since, otherwise undone together with the keystroke.
+#[derive(Clone, Debug)]
+pub struct FormatOnTypeEdits {
+    pub entity: Entity,
+    pub version: u64,
+    pub edits: Vec<TextEdit>,
+}
+
+/// Built-in formatting after `trigger` was typed before `offset`: a line break trims the
+/// line it ended and a semicolon drops the spaces before it. Closing brackets are already
+/// aligned by [`crate::indent::outdent`].
+pub fn format_on_type(
+    buffer: &TextBuffer,
+    offset: usize,
+    trigger: char,
+) -> Vec<(Range<usize>, String)> {
+    let line = buffer.line_at(offset);
+    match trigger {
+        '\n' if line > 0 => {
+            let start = buffer.line_start(line - 1);
+            let content = buffer.get_line_content(line - 1);
+            let trailing = start + content.trim_end().len()..start + content.len();
+            if trailing.is_empty() {
+                return vec![];
+            }
+            vec![(trailing, String::new())]
+        }
+        ';' => {
+            let start = buffer.line_start(line);
+            let content = buffer.get_line_content(line);
+            let before = match content.get(..offset - start - 1) {
+                Some(before) => before,
+                None => return vec![],
+            };
+            let code = before.trim_end();
+            if code.trim_start().is_empty() || code.len() == before.len() {
+                return vec![];
+            }
+            vec![(start + code.len()..start + before.len(), String::new())]
+        }
+        _ => vec![],
+    }
+}
+
+fn apply_on_type_edits(
+    mut events: EventReader<FormatOnTypeEdits>,
+    mut documents: Query<&mut Document>,
+    mut changed: EventWriter<DocumentChanged>,
+) {
+    for e in events.iter() {
+        let mut document = match documents.get_mut(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        if document.version() != e.version || e.edits.is_empty() {
+            debug!("🧮 Dropped on type formatting for version {}", e.version);
+            continue;
+        }
+        let offset = |p: lsp_types::Position| {
+            document.offset_at_utf16(Utf16Position {
+                line: p.line as usize,
+                column: p.character as usize,
+            })
+        };
+        let edits = e
+            .edits
+            .iter()
+            .map(|edit| {
+                (
+                    offset(edit.range.start)..offset(edit.range.end),
+                    edit.new_text.clone(),
+                )
+            })
+            .collect();
+
+        document.history_mut().amend();
+        document.apply_edits(edits, 0);
+        document.history_mut().commit();
+        changed.send(DocumentChanged {
+            entity: e.entity,
+            version: document.version(),
+            changes: document.take_changes(),
+            cursor: None,
+        });
+    }
+}
@@ -0,0 +1,448 @@
+use crate::{
+    announce::count,
+    command::{RegisterCommand, RunCommand},
+    cursor::{self, Documents, Placed, Secondaries},
+    document::{DocumentChanged, DocumentEditSet},
+    pipeline::{AppPipelineExt, EditorStage},
+    workspace::Workspace,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Commands, Res, ResMut},
+    },
+    log::{debug, warn},
+};
+use dip_editor::date::civil_date;
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const ALPHANUMERIC: &[u8; 62] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+const LOREM: [&str; 3] = [
+    "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor \
+     incididunt ut labore et dolore magna aliqua. Ut enim ad minim veniam, quis nostrud \
+     exercitation ullamco laboris nisi ut aliquip ex ea commodo consequat.",
+    "Duis aute irure dolor in reprehenderit in voluptate velit esse cillum dolore eu fugiat \
+     nulla pariatur. Excepteur sint occaecat cupidatat non proident, sunt in culpa qui \
+     officia deserunt mollit anim id est laborum.",
+    "Sed ut perspiciatis unde omnis iste natus error sit voluptatem accusantium doloremque \
+     laudantium, totam rem aperiam, eaque ipsa quae ab illo inventore veritatis et quasi \
+     architecto beatae vitae dicta sunt explicabo.",
+];
+
+pub struct GeneratePlugin;
+
+impl Plugin for GeneratePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GenerateSettings>()
+            .init_resource::<Generators>()
+            .add_event::<InsertGenerated>()
+            .add_event::<GenerateFailed>()
+            .register_generator(UuidV4)
+            .register_generator(UuidV7)
+            .register_generator(Timestamp)
+            .register_generator(RandomString)
+            .register_generator(Lorem)
+            .add_editor_system(EditorStage::Commands, run_generate_commands)
+            .add_editor_system(EditorStage::Edits, insert_generated.label(DocumentEditSet));
+    }
+}
+
+pub struct GenerateSettings {
+    /// Where [`Timestamp`] tells the time.
+    pub timezone: Timezone,
+    /// Characters of a [`RandomString`].
+    pub random_length: usize,
+    /// Paragraphs of [`Lorem`] ipsum.
+    pub lorem_paragraphs: usize,
+}
+
+impl Default for GenerateSettings {
+    fn default() -> Self {
+        Self {
+            timezone: Timezone::Local,
+            random_length: 16,
+            lorem_paragraphs: 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timezone {
+    Utc,
+    /// The system's, or UTC where it is unknown.
+    Local,
+    /// Minutes east of UTC.
+    Offset(i32),
+}
+
+impl Timezone {
+    /// Minutes east of UTC at `unix_secs`.
+    pub fn offset_at(self, unix_secs: i64) -> i32 {
+        match self {
+            Timezone::Utc => 0,
+            Timezone::Local => local_offset(unix_secs),
+            Timezone::Offset(minutes) => minutes,
+        }
+    }
+}
+
+/// Replaces every selection with a fresh value of the generator `name`, e.g. `uuid`, each
+/// caret getting its own.
+#[derive(Clone, Debug)]
+pub struct InsertGenerated {
+    pub entity: Entity,
+    pub generator: String,
+}
+
+/// Nothing was inserted.
+#[derive(Clone, Debug)]
+pub struct GenerateFailed {
+    pub entity: Entity,
+    pub error: String,
+}
+
+/// Makes up text to insert. Each one registered with [`RegisterGenerator`] gets a
+/// `generate.<name>` command and is the `${name}` variable of templates and snippets.
+pub trait Generator: Send + Sync + 'static {
+    fn name(&self) -> &str;
+    /// The title of its command.
+    fn title(&self) -> &str;
+    fn generate(&self, settings: &GenerateSettings) -> Result<String, String>;
+}
+
+/// What plugins registered with [`RegisterGenerator`].
+#[derive(Default)]
+pub struct Generators {
+    generators: Vec<Box<dyn Generator>>,
+}
+
+impl Generators {
+    pub fn get(&self, name: &str) -> Option<&dyn Generator> {
+        self.generators
+            .iter()
+            .find(|generator| generator.name() == name)
+            .map(Box::as_ref)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Generator> {
+        self.generators.iter().map(Box::as_ref)
+    }
+
+    /// A fresh value of every generator by name, to fill in `${name}` variables. Ones
+    /// failing are left out.
+    pub fn variables(&self, settings: &GenerateSettings) -> HashMap<String, String> {
+        self.iter()
+            .filter_map(|generator| {
+                let value = generator.generate(settings).ok()?;
+                Some((generator.name().to_string(), value))
+            })
+            .collect()
+    }
+}
+
+/// Lets plugins add generators while the app is built.
+pub trait RegisterGenerator {
+    fn register_generator(&mut self, generator: impl Generator) -> &mut Self;
+}
+
+impl RegisterGenerator for App {
+    fn register_generator(&mut self, generator: impl Generator) -> &mut Self {
+        let id = format!("generate.{}", generator.name());
+        let title = generator.title().to_string();
+        self.register_command(&id, "Insert", &title);
+        self.init_resource::<Generators>();
+        self.world
+            .get_resource_mut::<Generators>()
+            .unwrap()
+            .generators
+            .push(Box::new(generator));
+        self
+    }
+}
+
+/// A random UUID, version 4.
+pub struct UuidV4;
+
+impl Generator for UuidV4 {
+    fn name(&self) -> &str {
+        "uuid"
+    }
+
+    fn title(&self) -> &str {
+        "UUID"
+    }
+
+    fn generate(&self, _: &GenerateSettings) -> Result<String, String> {
+        Ok(uuid_v4(random_bytes()?))
+    }
+}
+
+/// A UUID ordered by the time it was made, version 7.
+pub struct UuidV7;
+
+impl Generator for UuidV7 {
+    fn name(&self) -> &str {
+        "uuidV7"
+    }
+
+    fn title(&self) -> &str {
+        "UUID (Time Ordered)"
+    }
+
+    fn generate(&self, _: &GenerateSettings) -> Result<String, String> {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        Ok(uuid_v7(millis, random_bytes()?))
+    }
+}
+
+/// The current time as ISO 8601, e.g. `2024-01-31T09:30:00+09:00`.
+pub struct Timestamp;
+
+impl Generator for Timestamp {
+    fn name(&self) -> &str {
+        "timestamp"
+    }
+
+    fn title(&self) -> &str {
+        "Timestamp"
+    }
+
+    fn generate(&self, settings: &GenerateSettings) -> Result<String, String> {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        Ok(timestamp(secs, settings.timezone))
+    }
+}
+
+/// Letters and digits picked at random.
+pub struct RandomString;
+
+impl Generator for RandomString {
+    fn name(&self) -> &str {
+        "randomString"
+    }
+
+    fn title(&self) -> &str {
+        "Random String"
+    }
+
+    fn generate(&self, settings: &GenerateSettings) -> Result<String, String> {
+        let mut out = String::with_capacity(settings.random_length);
+        while out.len() < settings.random_length {
+            let bytes: [u8; 32] = random_bytes()?;
+            // Up to 247 so each character is as likely as the others.
+            out.extend(
+                bytes
+                    .iter()
+                    .filter(|b| usize::from(**b) < ALPHANUMERIC.len() * 4)
+                    .map(|b| ALPHANUMERIC[usize::from(*b) % ALPHANUMERIC.len()] as char)
+                    .take(settings.random_length - out.len()),
+            );
+        }
+        Ok(out)
+    }
+}
+
+/// Placeholder paragraphs, separated by blank lines.
+pub struct Lorem;
+
+impl Generator for Lorem {
+    fn name(&self) -> &str {
+        "lorem"
+    }
+
+    fn title(&self) -> &str {
+        "Lorem Ipsum"
+    }
+
+    fn generate(&self, settings: &GenerateSettings) -> Result<String, String> {
+        Ok(lorem(settings.lorem_paragraphs))
+    }
+}
+
+/// `paragraphs` of lorem ipsum, always starting with the usual one.
+pub fn lorem(paragraphs: usize) -> String {
+    LOREM
+        .iter()
+        .cycle()
+        .take(paragraphs)
+        .copied()
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+pub fn uuid_v4(mut bytes: [u8; 16]) -> String {
+    bytes[6] = bytes[6] & 0x0f | 0x40;
+    bytes[8] = bytes[8] & 0x3f | 0x80;
+    format_uuid(bytes)
+}
+
+/// The first 48 bits are `unix_millis`, the rest of `random` is kept.
+pub fn uuid_v7(unix_millis: u64, mut random: [u8; 16]) -> String {
+    random[..6].copy_from_slice(&unix_millis.to_be_bytes()[2..]);
+    random[6] = random[6] & 0x0f | 0x70;
+    random[8] = random[8] & 0x3f | 0x80;
+    format_uuid(random)
+}
+
+fn format_uuid(bytes: [u8; 16]) -> String {
+    let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// `unix_secs` as ISO 8601 in `timezone`, ending in `Z` for UTC.
+pub fn timestamp(unix_secs: i64, timezone: Timezone) -> String {
+    let offset = timezone.offset_at(unix_secs);
+    let local = (unix_secs + i64::from(offset) * 60).max(0) as u64;
+    let (year, month, day) = civil_date(local / 86_400);
+    let secs = local % 86_400;
+    let zone = match offset {
+        0 => "Z".to_string(),
+        _ => {
+            let sign = if offset < 0 { '-' } else { '+' };
+            let minutes = offset.unsigned_abs();
+            format!("{sign}{:02}:{:02}", minutes / 60, minutes % 60)
+        }
+    };
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}{zone}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+pub(crate) fn random_bytes<const N: usize>() -> Result<[u8; N], String> {
+    let mut bytes = [0; N];
+    getrandom::getrandom(&mut bytes).map_err(|e| format!("No randomness available: {e}"))?;
+    Ok(bytes)
+}
+
+#[cfg(unix)]
+fn local_offset(unix_secs: i64) -> i32 {
+    let time = unix_secs as libc::time_t;
+    // SAFETY: `tm` is plain data filled in by `localtime_r`, which keeps no reference.
+    unsafe {
+        let mut tm = std::mem::zeroed::<libc::tm>();
+        match libc::localtime_r(&time, &mut tm).is_null() {
+            true => 0,
+            false => (tm.tm_gmtoff / 60) as i32,
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn local_offset(_: i64) -> i32 {
+    0
+}
+
+fn run_generate_commands(
+    mut events: EventReader<RunCommand>,
+    workspace: Res<Workspace>,
+    mut inserts: EventWriter<InsertGenerated>,
+) {
+    for e in events.iter() {
+        let (generator, entity) = match (e.id.strip_prefix("generate."), workspace.active()) {
+            (Some(generator), Some(entity)) => (generator, entity),
+            _ => continue,
+        };
+        inserts.send(InsertGenerated {
+            entity,
+            generator: generator.to_string(),
+        });
+    }
+}
+
+fn insert_generated(
+    mut commands: Commands,
+    mut events: EventReader<InsertGenerated>,
+    mut documents: Documents<()>,
+    mut secondaries: Secondaries,
+    (generators, settings, mut placed): (Res<Generators>, Res<GenerateSettings>, ResMut<Placed>),
+    mut changed: EventWriter<DocumentChanged>,
+    mut failed: EventWriter<GenerateFailed>,
+) {
+    for e in events.iter() {
+        let (mut document, mut cursor, mut selection, ()) = match documents.get_mut(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let mut fail = |error: String| {
+            warn!("🎲 {error}");
+            failed.send(GenerateFailed {
+                entity: e.entity,
+                error,
+            });
+        };
+        let generator = match generators.get(&e.generator) {
+            Some(generator) => generator,
+            None => {
+                fail(format!("No generator named {}", e.generator));
+                continue;
+            }
+        };
+        if document.is_read_only() {
+            fail("The document is read only".to_string());
+            continue;
+        }
+        let mut carets = cursor::carets(e.entity, (&cursor, &selection), &mut secondaries);
+
+        let values: Result<Vec<String>, String> = carets
+            .iter()
+            .map(|_| generator.generate(&settings))
+            .collect();
+        let values = match values {
+            Ok(values) => values,
+            Err(error) => {
+                fail(error);
+                continue;
+            }
+        };
+
+        document.history_mut().begin();
+        let inserted = values.len();
+        let mut values = values.into_iter().rev();
+        cursor::edit_carets(&mut document, &mut carets, |document, caret| {
+            let range = caret.range();
+            let text = values.next().unwrap_or_default();
+            document.delete(range.clone());
+            document.insert(range.start, &text);
+            let end = range.start + text.len();
+            end..end
+        });
+        cursor::store(
+            carets,
+            (&mut cursor, &mut selection),
+            &mut secondaries,
+            &mut commands,
+        );
+        placed.0.insert((e.entity, document.version()));
+        debug!(
+            "🎲 Inserted {} at {}",
+            generator.title(),
+            count(inserted, "caret")
+        );
+        changed.send(DocumentChanged {
+            entity: e.entity,
+            version: document.version(),
+            changes: document.take_changes(),
+            cursor: None,
+        });
+    }
+}
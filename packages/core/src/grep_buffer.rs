@@ -0,0 +1,382 @@
+use crate::{
+    announce::count,
+    document::{
+        same_file, Document, DocumentChanged, DocumentEditSet, DocumentSaveFailed, DocumentSaved,
+        SaveConflict, SaveDocument, SaveDocuments, VirtualDocument,
+    },
+    memory::MemoryUsage,
+    pipeline::{AppPipelineExt, EditorStage},
+    text_buffer::TextBuffer,
+    workspace::Workspace,
+    workspace_search::{FileMatches, SearchWorkspace, WorkspaceSearchFinished},
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        query::{With, Without},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Commands, Query, Res, ResMut},
+    },
+    log::{debug, warn},
+};
+use std::{collections::HashMap, fs, path::PathBuf};
+
+pub struct GrepBufferPlugin;
+
+impl Plugin for GrepBufferPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PendingGrep>()
+            .init_resource::<GrepSaves>()
+            .add_event::<OpenGrepBuffer>()
+            .add_event::<GrepBufferOpened>()
+            .add_event::<GrepEditsApplied>()
+            .add_system(open_grep_buffers)
+            .add_system(collect_grep_results)
+            .add_editor_system(EditorStage::Edits, apply_grep_edits.label(DocumentEditSet))
+            .add_editor_system(EditorStage::Edits, save_grep_edits.after(DocumentEditSet))
+            .add_system(close_written_back.after(SaveDocuments));
+    }
+}
+
+/// Searches the workspace and opens the results as a document of `path:line:text`
+/// lines. Editing the text of a line and saving the document writes it to the file.
+#[derive(Clone, Debug)]
+pub struct OpenGrepBuffer {
+    pub query: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct GrepBufferOpened {
+    pub entity: Entity,
+    pub query: String,
+    pub lines: usize,
+}
+
+/// Sent after a grep buffer was saved. Lines that no longer read as they did in the
+/// search are skipped, so edits made to the files since are not overwritten.
+#[derive(Clone, Copy, Debug)]
+pub struct GrepEditsApplied {
+    pub entity: Entity,
+    pub files: usize,
+    pub lines: usize,
+    pub skipped: usize,
+}
+
+/// The search results a grep buffer was rendered from, keyed by the prefix of each line.
+#[derive(Component, Debug)]
+pub struct GrepBuffer {
+    pub query: String,
+    lines: HashMap<String, GrepLine>,
+}
+
+#[derive(Clone, Debug)]
+struct GrepLine {
+    path: PathBuf,
+    line: usize,
+    /// The text of the line when it was found, or last written back.
+    text: String,
+}
+
+impl GrepBuffer {
+    /// The result `line` of the buffer belongs to, and its text without the prefix. The
+    /// path may have colons of its own, so every `:<digits>:` is tried from the left.
+    fn parse<'a>(&self, line: &'a str) -> Option<(&str, &'a str)> {
+        line.match_indices(':').find_map(|(i, _)| {
+            let rest = &line[i + 1..];
+            let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+            if digits == 0 || rest.as_bytes().get(digits) != Some(&b':') {
+                return None;
+            }
+            let end = i + digits + 2;
+            let (prefix, _) = self.lines.get_key_value(&line[..end])?;
+            Some((prefix.as_str(), &line[end..]))
+        })
+    }
+}
+
+/// A file a grep buffer wrote to that was not open, opened for the save and closed after.
+#[derive(Component)]
+struct WrittenBack;
+
+/// Documents a grep buffer edited, saved once the frame's edits are done. Saves go through
+/// [`SaveDocument`] like any other, so they are checked and audited the same way.
+#[derive(Default)]
+struct GrepSaves(Vec<Entity>);
+
+/// The search a grep buffer is waiting on, and the matches found so far.
+#[derive(Default)]
+struct PendingGrep(Option<(String, Vec<FileMatches>)>);
+
+fn open_grep_buffers(
+    mut events: EventReader<OpenGrepBuffer>,
+    mut pending: ResMut<PendingGrep>,
+    mut searches: EventWriter<SearchWorkspace>,
+) {
+    for e in events.iter() {
+        debug!("🔎 Grepping the workspace for {:?}", e.query);
+        pending.0 = Some((e.query.clone(), vec![]));
+        searches.send(SearchWorkspace {
+            query: e.query.clone(),
+        });
+    }
+}
+
+fn collect_grep_results(
+    mut commands: Commands,
+    mut pending: ResMut<PendingGrep>,
+    workspace: Res<Workspace>,
+    (mut searches, mut matches, mut finished): (
+        EventReader<SearchWorkspace>,
+        EventReader<FileMatches>,
+        EventReader<WorkspaceSearchFinished>,
+    ),
+    mut opened: EventWriter<GrepBufferOpened>,
+) {
+    // Another search replaces the one the buffer was waiting on.
+    let superseded = searches.iter().any(|e| {
+        pending
+            .0
+            .as_ref()
+            .is_some_and(|(query, _)| *query != e.query)
+    });
+    if superseded {
+        pending.0 = None;
+    }
+    let (query, found) = match &mut pending.0 {
+        Some(pending) => pending,
+        None => {
+            matches.iter().for_each(drop);
+            finished.iter().for_each(drop);
+            return;
+        }
+    };
+    found.extend(matches.iter().cloned());
+    if !finished.iter().any(|e| e.query == *query) {
+        return;
+    }
+
+    let (query, mut found) = pending.0.take().unwrap();
+    found.sort_by(|a, b| a.path.cmp(&b.path));
+    let (text, lines) = render(&workspace, &found);
+    let results = lines.len();
+    debug!("🔎 Opened {} for {query:?}", count(results, "result"));
+    let entity = commands
+        .spawn()
+        .insert(Document::new(None, TextBuffer::from(text.as_str())))
+        .insert(VirtualDocument)
+        .insert(GrepBuffer {
+            query: query.clone(),
+            lines,
+        })
+        .insert(MemoryUsage::default())
+        .id();
+    opened.send(GrepBufferOpened {
+        entity,
+        query,
+        lines: results,
+    });
+}
+
+/// One line per matching line of each file, prefixed with where it is.
+fn render(workspace: &Workspace, found: &[FileMatches]) -> (String, HashMap<String, GrepLine>) {
+    let mut text = String::new();
+    let mut lines = HashMap::new();
+    for file in found {
+        let contents = match fs::read_to_string(&file.path) {
+            Ok(contents) => contents,
+            Err(error) => {
+                warn!("🔎 Failed to read {}: {error}", file.path.display());
+                continue;
+            }
+        };
+        let shown = match workspace.qualify(&file.path) {
+            Some(path) => path.to_string(),
+            None => file.path.display().to_string(),
+        };
+        // Ranges come in order, so lines are counted from the previous one.
+        let (mut counted, mut line, mut last) = (0, 0, None);
+        for range in &file.ranges {
+            let start = contents[..range.start].rfind('\n').map_or(0, |i| i + 1);
+            line += contents[counted..start].matches('\n').count();
+            counted = start;
+            if last == Some(line) {
+                continue;
+            }
+            last = Some(line);
+            let end = contents[start..]
+                .find('\n')
+                .map_or(contents.len(), |i| start + i);
+            let mut content = contents[start..end]
+                .strip_suffix('\r')
+                .unwrap_or(&contents[start..end]);
+            if line == 0 {
+                content = content.strip_prefix('\u{feff}').unwrap_or(content);
+            }
+
+            let prefix = format!("{shown}:{}:", line + 1);
+            text.push_str(&prefix);
+            text.push_str(content);
+            text.push('\n');
+            lines.insert(
+                prefix,
+                GrepLine {
+                    path: file.path.clone(),
+                    line,
+                    text: content.to_string(),
+                },
+            );
+        }
+    }
+    (text, lines)
+}
+
+fn apply_grep_edits(
+    mut events: EventReader<SaveDocument>,
+    mut greps: Query<(&mut Document, &mut GrepBuffer)>,
+    mut documents: Query<(Entity, &mut Document), Without<GrepBuffer>>,
+    (mut commands, mut saves): (Commands, ResMut<GrepSaves>),
+    mut changed: EventWriter<DocumentChanged>,
+    mut applied: EventWriter<GrepEditsApplied>,
+) {
+    for e in events.iter() {
+        let (mut grep_document, mut grep) = match greps.get_mut(e.entity) {
+            Ok(grep) => grep,
+            Err(_) => continue,
+        };
+
+        // Edits per file, in the order of the buffer. Lines whose prefix was removed are
+        // left alone.
+        let mut edits: Vec<(PathBuf, Vec<(String, String)>)> = vec![];
+        let buffer = grep_document.buffer();
+        for i in 0..buffer.line_count() {
+            let content = buffer.get_line_content(i);
+            let (prefix, text) = match grep.parse(&content) {
+                Some(parsed) => parsed,
+                None => continue,
+            };
+            let result = &grep.lines[prefix];
+            if result.text == text {
+                continue;
+            }
+            let edit = (prefix.to_string(), text.to_string());
+            match edits.iter_mut().find(|(path, _)| *path == result.path) {
+                Some((_, file)) => file.push(edit),
+                None => edits.push((result.path.clone(), vec![edit])),
+            }
+        }
+
+        let (mut lines, mut skipped) = (0, 0);
+        for (path, file) in &edits {
+            let replaced = |document: &mut Document| {
+                let mut done = vec![];
+                document.history_mut().begin();
+                for (prefix, text) in file {
+                    let result = &grep.lines[prefix];
+                    let buffer = document.buffer();
+                    if result.line >= buffer.line_count()
+                        || buffer.get_line_content(result.line) != result.text
+                    {
+                        continue;
+                    }
+                    let start = buffer.line_start(result.line);
+                    let end = start + buffer.get_line_length(result.line);
+                    document.delete(start..end);
+                    document.insert(start, text);
+                    done.push(prefix.clone());
+                }
+                document.history_mut().commit();
+                done
+            };
+
+            let open = documents
+                .iter_mut()
+                .find(|(_, document)| document.path().is_some_and(|own| same_file(own, path)));
+            let done = match open {
+                // Open files are edited like any other change, and saved unless they had
+                // changes of their own.
+                Some((entity, mut document)) => {
+                    let clean = !document.is_dirty();
+                    let done = replaced(&mut document);
+                    if !done.is_empty() {
+                        changed.send(DocumentChanged {
+                            entity,
+                            version: document.version(),
+                            changes: document.take_changes(),
+                            cursor: None,
+                        });
+                        if clean {
+                            saves.0.push(entity);
+                        }
+                    }
+                    done
+                }
+                None => match Document::from_path(path) {
+                    Ok(mut document) => {
+                        let done = replaced(&mut document);
+                        if !done.is_empty() {
+                            let entity = commands.spawn().insert(document).insert(WrittenBack).id();
+                            saves.0.push(entity);
+                        }
+                        done
+                    }
+                    Err(error) => {
+                        warn!("🔎 Failed to open {}: {error}", path.display());
+                        skipped += file.len();
+                        continue;
+                    }
+                },
+            };
+
+            skipped += file.len() - done.len();
+            lines += done.len();
+            for (prefix, text) in file {
+                if done.contains(prefix) {
+                    grep.lines.get_mut(prefix).unwrap().text = text.clone();
+                }
+            }
+        }
+
+        if skipped == 0 {
+            grep_document.mark_clean();
+        }
+        debug!(
+            "🔎 Wrote {} to {}, skipped {skipped}",
+            count(lines, "line"),
+            count(edits.len(), "file")
+        );
+        applied.send(GrepEditsApplied {
+            entity: e.entity,
+            files: edits.len(),
+            lines,
+            skipped,
+        });
+    }
+}
+
+fn save_grep_edits(mut saves: ResMut<GrepSaves>, mut events: EventWriter<SaveDocument>) {
+    for entity in saves.0.drain(..) {
+        events.send(SaveDocument { entity, path: None });
+    }
+}
+
+fn close_written_back(
+    mut commands: Commands,
+    (mut saved, mut failed, mut conflicts): (
+        EventReader<DocumentSaved>,
+        EventReader<DocumentSaveFailed>,
+        EventReader<SaveConflict>,
+    ),
+    written: Query<(), With<WrittenBack>>,
+) {
+    let saved = saved.iter().map(|e| e.entity);
+    let failed = failed.iter().map(|e| e.entity);
+    let conflicts = conflicts.iter().map(|e| e.entity);
+    for entity in saved.chain(failed).chain(conflicts) {
+        if written.get(entity).is_ok() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,469 @@
+//! The column left of the text: line numbers, fold arrows and markers for breakpoints and
+//! diagnostics, for the visible lines of the document on screen. The view sends clicks on
+//! it as [`CoreCommand::GutterClick`], which are mapped back to the line clicked.
+
+use crate::{
+    command::{CoreCommand, RegisterCommand, RunCommand, UICommand},
+    config::Settings,
+    cursor::Cursor,
+    damage::VisibleLines,
+    diagnostics::Diagnostics,
+    document::{Document, DocumentChanged, LineChange},
+    fold::{ChangeFolds, FoldChange, Folds},
+    layout::Layout,
+    pipeline::{AppPipelineExt, EditorStage},
+    render::Viewport,
+    theme::{Marker, Theme},
+    workspace::Workspace,
+    zoom::LayoutMetrics,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        query::{Added, Changed, Or, Without},
+        system::{Commands, Local, Query, Res},
+    },
+    log::debug,
+};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeSet, HashMap},
+    ops::Range,
+};
+
+pub struct GutterPlugin;
+
+impl Plugin for GutterPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<GutterClicked>()
+            .add_event::<ToggleBreakpoint>()
+            .register_command("debug.toggleBreakpoint", "Debug", "Toggle Breakpoint")
+            .add_system(attach_breakpoints)
+            .add_editor_system(EditorStage::Input, map_gutter_clicks)
+            .add_editor_system(EditorStage::Commands, run_gutter_commands)
+            .add_editor_system(EditorStage::Commands, handle_gutter_clicks)
+            .add_system(toggle_breakpoints)
+            .add_editor_system(EditorStage::Derive, shift_breakpoints)
+            .add_editor_system(EditorStage::Present, render_gutter);
+    }
+}
+
+/// `editor.lineNumbers` in the settings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineNumbers {
+    #[default]
+    On,
+    /// Counted from the cursor's line on screen, which shows its own number.
+    Relative,
+    Off,
+}
+
+/// What the gutter shows, from `editor.lineNumbers`, `editor.folding` and
+/// `editor.glyphMargin`, the column of breakpoint and diagnostic markers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GutterSettings {
+    pub line_numbers: LineNumbers,
+    pub folding: bool,
+    pub markers: bool,
+}
+
+impl Default for GutterSettings {
+    fn default() -> Self {
+        Self {
+            line_numbers: LineNumbers::On,
+            folding: true,
+            markers: true,
+        }
+    }
+}
+
+impl GutterSettings {
+    pub fn new(settings: Option<&Settings>) -> Self {
+        let default = Self::default();
+        let settings = match settings {
+            Some(settings) => settings,
+            None => return default,
+        };
+        Self {
+            line_numbers: settings
+                .get("editor.lineNumbers")
+                .unwrap_or(default.line_numbers),
+            folding: settings.get("editor.folding").unwrap_or(default.folding),
+            markers: settings
+                .get("editor.glyphMargin")
+                .unwrap_or(default.markers),
+        }
+    }
+}
+
+/// Lines of a document a debugger stops on, moving along with edits.
+#[derive(Component, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Breakpoints(BTreeSet<usize>);
+
+impl Breakpoints {
+    pub fn contains(&self, line: usize) -> bool {
+        self.0.contains(&line)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().copied()
+    }
+
+    /// Sets or clears the breakpoint on `line`, returning whether it is set now.
+    pub fn toggle(&mut self, line: usize) -> bool {
+        if self.0.remove(&line) {
+            return false;
+        }
+        self.0.insert(line);
+        true
+    }
+
+    /// Moves the breakpoints below `change` along. The ones on lines it removed go to the
+    /// line the change starts on.
+    fn edited(&mut self, change: &LineChange) {
+        let old_end = change.start + change.removed;
+        let new_end = change.start + change.inserted;
+        let moved = self.0.split_off(&(change.start + 1));
+        for line in moved {
+            let line = match line > old_end {
+                true => line - old_end + new_end,
+                false => change.start,
+            };
+            self.0.insert(line);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GutterArea {
+    LineNumber,
+    Fold,
+    Marker,
+}
+
+/// A click on the gutter of the document on screen, `y` physical pixels from the top of
+/// the document.
+#[derive(Clone, Copy, Debug)]
+pub struct GutterClick {
+    pub y: f32,
+    pub area: GutterArea,
+}
+
+/// A click on the gutter mapped back to the line it was on. Clicks on fold arrows fold or
+/// unfold and ones on markers toggle breakpoints, the others are left to listeners.
+#[derive(Clone, Copy, Debug)]
+pub struct GutterClicked {
+    pub entity: Entity,
+    pub line: usize,
+    pub area: GutterArea,
+}
+
+/// Sets or clears a breakpoint, on the primary cursor's line if `line` is None.
+#[derive(Clone, Copy, Debug)]
+pub struct ToggleBreakpoint {
+    pub entity: Entity,
+    pub line: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FoldArrow {
+    Open,
+    Folded,
+}
+
+/// The gutter of one visible line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GutterLine {
+    pub line: usize,
+    /// From the top of the document, as [`crate::render::RenderedLine::top`].
+    pub top: f32,
+    pub number: Option<String>,
+    /// The line of the primary cursor.
+    pub current: bool,
+    pub fold: Option<FoldArrow>,
+    pub breakpoint: bool,
+    /// For the most severe diagnostic starting on the line.
+    pub diagnostic: Option<Marker>,
+}
+
+/// The gutter of the document on screen, sent when any of it changed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gutter {
+    pub entity: Entity,
+    /// Wide enough for the number of the last line.
+    pub width: f32,
+    pub lines: Vec<GutterLine>,
+}
+
+/// The number shown for `line`, counting lines on screen from `current` when relative.
+pub fn line_number(
+    settings: LineNumbers,
+    folds: &Folds,
+    line: usize,
+    current: usize,
+) -> Option<String> {
+    match settings {
+        LineNumbers::Off => None,
+        LineNumbers::On => Some((line + 1).to_string()),
+        LineNumbers::Relative if line == current => Some((line + 1).to_string()),
+        LineNumbers::Relative => {
+            let (line, current) = (folds.visible_line(line), folds.visible_line(current));
+            Some(line.abs_diff(current).to_string())
+        }
+    }
+}
+
+fn attach_breakpoints(
+    mut commands: Commands,
+    documents: Query<Entity, (Added<Document>, Without<Breakpoints>)>,
+) {
+    for entity in documents.iter() {
+        commands.entity(entity).insert(Breakpoints::default());
+    }
+}
+
+fn map_gutter_clicks(
+    mut events: EventReader<CoreCommand>,
+    (viewport, workspace): (Res<Viewport>, Option<Res<Workspace>>),
+    documents: Query<(&Layout, &LayoutMetrics)>,
+    mut clicked: EventWriter<GutterClicked>,
+) {
+    for e in events.iter() {
+        let click = match e {
+            CoreCommand::GutterClick(click) => *click,
+            _ => continue,
+        };
+        let entity = match viewport.shown(workspace.as_deref()) {
+            Some(entity) => entity,
+            None => continue,
+        };
+        let (layout, metrics) = match documents.get(entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        if metrics.line_height <= 0. || layout.visual_line_count() == 0 {
+            continue;
+        }
+        let visual = (click.y.max(0.) / metrics.line_height) as usize;
+        clicked.send(GutterClicked {
+            entity,
+            line: layout.line_at(visual),
+            area: click.area,
+        });
+    }
+}
+
+fn run_gutter_commands(
+    mut events: EventReader<RunCommand>,
+    workspace: Res<Workspace>,
+    mut toggles: EventWriter<ToggleBreakpoint>,
+) {
+    for e in events.iter() {
+        if let ("debug.toggleBreakpoint", Some(entity)) = (e.id.as_str(), workspace.active()) {
+            toggles.send(ToggleBreakpoint { entity, line: None });
+        }
+    }
+}
+
+fn handle_gutter_clicks(
+    mut events: EventReader<GutterClicked>,
+    documents: Query<(&Document, &Folds)>,
+    mut folds: EventWriter<ChangeFolds>,
+    mut toggles: EventWriter<ToggleBreakpoint>,
+) {
+    for e in events.iter() {
+        match e.area {
+            GutterArea::Fold => {
+                let (document, current) = match documents.get(e.entity) {
+                    Ok(document) => document,
+                    Err(_) => continue,
+                };
+                let change = match current.folded().iter().any(|r| r.start == e.line) {
+                    true => FoldChange::Unfold(Some(e.line..e.line + 1)),
+                    false => match outermost_region(current, document, e.line) {
+                        Some(region) => FoldChange::Fold(Some(region)),
+                        None => continue,
+                    },
+                };
+                folds.send(ChangeFolds {
+                    entity: e.entity,
+                    change,
+                });
+            }
+            GutterArea::Marker => toggles.send(ToggleBreakpoint {
+                entity: e.entity,
+                line: Some(e.line),
+            }),
+            GutterArea::LineNumber => {}
+        }
+    }
+}
+
+/// The largest region starting on `line`, which the fold arrow folds.
+fn outermost_region(folds: &Folds, document: &Document, line: usize) -> Option<Range<usize>> {
+    folds
+        .regions(document.buffer())
+        .into_iter()
+        .find(|region| region.start == line)
+}
+
+fn toggle_breakpoints(
+    mut events: EventReader<ToggleBreakpoint>,
+    mut documents: Query<(&Document, &Cursor, &mut Breakpoints)>,
+) {
+    for e in events.iter() {
+        let (document, cursor, mut breakpoints) = match documents.get_mut(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let buffer = document.buffer();
+        let line = e
+            .line
+            .unwrap_or_else(|| buffer.line_at(cursor.offset.min(buffer.len())));
+        if line >= buffer.line_count() {
+            continue;
+        }
+        match breakpoints.toggle(line) {
+            true => debug!("🔴 Set a breakpoint on line {}", line + 1),
+            false => debug!("🔴 Cleared the breakpoint on line {}", line + 1),
+        }
+    }
+}
+
+fn shift_breakpoints(
+    mut changes: EventReader<DocumentChanged>,
+    mut documents: Query<&mut Breakpoints>,
+) {
+    for e in changes.iter() {
+        let mut breakpoints = match documents.get_mut(e.entity) {
+            Ok(breakpoints) => breakpoints,
+            Err(_) => continue,
+        };
+        // Left untouched without any, so the gutter isn't sent again for every edit.
+        if breakpoints.0.is_empty() {
+            continue;
+        }
+        for change in &e.changes {
+            breakpoints.edited(&change.lines);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn render_gutter(
+    (viewport, workspace, user, theme): (
+        Res<Viewport>,
+        Option<Res<Workspace>>,
+        Option<Res<Settings>>,
+        Res<Theme>,
+    ),
+    documents: Query<(
+        &Document,
+        &Layout,
+        &LayoutMetrics,
+        &VisibleLines,
+        &Cursor,
+        Option<&Folds>,
+        Option<&Breakpoints>,
+        Option<&Diagnostics>,
+    )>,
+    (changed, folds_changed): (
+        Query<
+            (),
+            Or<(
+                Changed<Layout>,
+                Changed<VisibleLines>,
+                Changed<Cursor>,
+                Changed<Folds>,
+                Changed<Breakpoints>,
+                Changed<Diagnostics>,
+            )>,
+        >,
+        Query<(), Changed<Folds>>,
+    ),
+    // Where the regions of the document on screen start, as of the version they were
+    // found in.
+    mut starts: Local<Option<(Entity, u64, BTreeSet<usize>)>>,
+    mut ui: EventWriter<UICommand>,
+) {
+    let entity = match viewport.shown(workspace.as_deref()) {
+        Some(entity) => entity,
+        None => return,
+    };
+    let settings_changed = user.as_ref().is_some_and(|user| user.is_changed());
+    if changed.get(entity).is_err() && !settings_changed && !theme.is_changed() {
+        return;
+    }
+    let (document, layout, metrics, visible, cursor, folds, breakpoints, diagnostics) =
+        match documents.get(entity) {
+            Ok(document) => document,
+            Err(_) => return,
+        };
+    let settings = GutterSettings::new(user.as_deref());
+    let buffer = document.buffer();
+    let unfolded = Folds::default();
+    let folds = folds.unwrap_or(&unfolded);
+    let current = buffer.line_at(cursor.offset.min(buffer.len()));
+
+    let found = (entity, document.version());
+    let stale = match &*starts {
+        Some((entity, version, _)) => (*entity, *version) != found,
+        None => true,
+    };
+    if settings.folding && (stale || folds_changed.get(entity).is_ok()) {
+        let regions = folds.regions(buffer);
+        let lines = regions.iter().map(|region| region.start).collect();
+        *starts = Some((found.0, found.1, lines));
+    }
+    let no_regions = BTreeSet::new();
+    let starts = match (&*starts, settings.folding) {
+        (Some((.., lines)), true) => lines,
+        _ => &no_regions,
+    };
+    let severities: HashMap<usize, Marker> = match (settings.markers, diagnostics) {
+        (true, Some(diagnostics)) => diagnostics
+            .gutter(buffer, visible.0.clone())
+            .into_iter()
+            .map(|(line, severity)| (line, theme.severity(severity)))
+            .collect(),
+        _ => HashMap::new(),
+    };
+
+    let lines = visible
+        .0
+        .clone()
+        .filter_map(|line| {
+            let visual = layout.visual_lines(line..line + 1);
+            if visual.is_empty() {
+                return None;
+            }
+            let fold = match folds.folded().iter().any(|r| r.start == line) {
+                true => Some(FoldArrow::Folded),
+                false if starts.contains(&line) => Some(FoldArrow::Open),
+                false => None,
+            };
+            Some(GutterLine {
+                line,
+                top: visual.start as f32 * metrics.line_height,
+                number: line_number(settings.line_numbers, folds, line, current),
+                current: line == current,
+                fold,
+                breakpoint: settings.markers && breakpoints.is_some_and(|b| b.contains(line)),
+                diagnostic: severities.get(&line).copied(),
+            })
+        })
+        .collect();
+    let digits = match settings.line_numbers {
+        LineNumbers::Off => 0,
+        _ => buffer.line_count().to_string().len().max(2),
+    };
+    ui.send(UICommand::Gutter(Gutter {
+        entity,
+        width: digits as f32 * metrics.char_width,
+        lines,
+    }));
+}
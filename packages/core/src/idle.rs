@@ -0,0 +1,157 @@
+use crate::command::CoreCommand;
+use bevy::{
+    app::{App, CoreStage, Plugin},
+    core::Time,
+    ecs::{
+        event::{EventReader, EventWriter},
+        schedule::ShouldRun,
+        system::{Local, Res, ResMut, SystemParam},
+    },
+    input::{
+        keyboard::KeyboardInput,
+        mouse::{MouseButtonInput, MouseWheel},
+    },
+    log::debug,
+};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+pub struct IdlePlugin;
+
+impl Plugin for IdlePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Idle>()
+            .init_resource::<IdleSettings>()
+            .add_event::<IdleChanged>()
+            .add_system_to_stage(CoreStage::PreUpdate, detect_idle)
+            .add_system_to_stage(CoreStage::Last, pace_idle_frames);
+    }
+}
+
+pub struct IdleSettings {
+    /// No input for this long makes the user idle.
+    pub timeout: Duration,
+    /// Frame interval while idle. Frames are paced by sleeping at their end, so the first
+    /// input after idling waits for at most this long.
+    pub idle_frame_interval: Duration,
+}
+
+impl Default for IdleSettings {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            idle_frame_interval: Duration::from_millis(250),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Idle {
+    last_input: f64,
+    idle: bool,
+}
+
+impl Idle {
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    /// Seconds since startup of the last user input.
+    pub fn last_input(&self) -> f64 {
+        self.last_input
+    }
+
+    /// Frame interval to run at, `None` meaning as fast as the runner likes.
+    pub fn frame_interval(&self, settings: &IdleSettings) -> Option<Duration> {
+        self.idle.then_some(settings.idle_frame_interval)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IdleChanged {
+    Idle,
+    Active,
+}
+
+/// Run criteria running a system every `active` interval, or every `idle` interval while the
+/// user is idle. Background work like polling and indexing should be throttled with it.
+///
+/// ```ignore
+/// app.add_system(poll.with_run_criteria(throttled(Duration::from_secs(1), Duration::from_secs(10))));
+/// ```
+pub fn throttled(
+    active: Duration,
+    idle: Duration,
+) -> impl FnMut(Res<Idle>, Res<Time>, Local<Option<f64>>) -> ShouldRun {
+    move |state: Res<Idle>, time: Res<Time>, mut last_run: Local<Option<f64>>| {
+        let now = time.seconds_since_startup();
+        let interval = if state.is_idle() { idle } else { active };
+
+        // Input arriving after the last run means the user is back: catch up right away.
+        let resumed = last_run.is_some_and(|last| state.last_input() > last);
+        let due = last_run.is_none_or(|last| now - last >= interval.as_secs_f64());
+
+        if due || resumed {
+            *last_run = Some(now);
+            ShouldRun::Yes
+        } else {
+            ShouldRun::No
+        }
+    }
+}
+
+#[derive(SystemParam)]
+struct UserInput<'w, 's> {
+    keyboard: EventReader<'w, 's, KeyboardInput>,
+    mouse: EventReader<'w, 's, MouseButtonInput>,
+    wheel: EventReader<'w, 's, MouseWheel>,
+    commands: EventReader<'w, 's, CoreCommand>,
+}
+
+impl UserInput<'_, '_> {
+    fn any(&mut self) -> bool {
+        self.keyboard.iter().count()
+            + self.mouse.iter().count()
+            + self.wheel.iter().count()
+            + self.commands.iter().count()
+            > 0
+    }
+}
+
+/// Sleeps out the rest of [`Idle::frame_interval`] while idle, whatever runner drives the app.
+fn pace_idle_frames(
+    idle: Res<Idle>,
+    settings: Res<IdleSettings>,
+    mut last_frame: Local<Option<Instant>>,
+) {
+    if let (Some(interval), Some(last)) = (idle.frame_interval(&settings), *last_frame) {
+        if let Some(rest) = interval.checked_sub(last.elapsed()) {
+            thread::sleep(rest);
+        }
+    }
+    *last_frame = Some(Instant::now());
+}
+
+fn detect_idle(
+    mut input: UserInput,
+    time: Res<Time>,
+    settings: Res<IdleSettings>,
+    mut idle: ResMut<Idle>,
+    mut changed: EventWriter<IdleChanged>,
+) {
+    let now = time.seconds_since_startup();
+    if input.any() {
+        idle.last_input = now;
+        if idle.idle {
+            debug!("⏰ Active");
+            idle.idle = false;
+            changed.send(IdleChanged::Active);
+        }
+    } else if !idle.idle && now - idle.last_input >= settings.timeout.as_secs_f64() {
+        debug!("💤 Idle");
+        idle.idle = true;
+        changed.send(IdleChanged::Idle);
+    }
+}
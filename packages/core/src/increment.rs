@@ -0,0 +1,423 @@
+use crate::{
+    announce::count,
+    command::{RegisterCommand, RunCommand},
+    cursor::{self, Documents, Placed, Secondaries},
+    document::{DocumentChanged, DocumentEditSet},
+    pipeline::{AppPipelineExt, EditorStage},
+    text_buffer::TextBuffer,
+    workspace::Workspace,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Commands, Res, ResMut},
+    },
+    log::{debug, warn},
+};
+use std::{cmp::Reverse, ops::Range};
+
+pub struct IncrementPlugin;
+
+impl Plugin for IncrementPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Increments>()
+            .add_event::<IncrementNumbers>()
+            .add_event::<IncrementFailed>()
+            .register_increment(Dates)
+            .register_command("number.increment", "Number", "Increment")
+            .register_command("number.decrement", "Number", "Decrement")
+            .register_command(
+                "number.incrementSequence",
+                "Number",
+                "Increment as Sequence",
+            )
+            .register_command(
+                "number.decrementSequence",
+                "Number",
+                "Decrement as Sequence",
+            )
+            .add_editor_system(EditorStage::Commands, run_increment_commands)
+            .add_editor_system(EditorStage::Edits, increment_numbers.label(DocumentEditSet));
+    }
+}
+
+/// Steps the number under or after each cursor by `delta`, or the first one on each line
+/// of a selection. Decimal, hex (`0x`) and binary (`0b`) integers keep their width and
+/// case; anything a plugin registered with [`RegisterIncrement`] is stepped too, e.g.
+/// dates.
+#[derive(Clone, Debug)]
+pub struct IncrementNumbers {
+    pub entity: Entity,
+    pub delta: i64,
+    /// Step the n-th number in document order by `n * delta`, counting from one, e.g. to
+    /// number a column of cursors all on `0`.
+    pub sequence: bool,
+}
+
+/// Nothing was changed.
+#[derive(Clone, Debug)]
+pub struct IncrementFailed {
+    pub entity: Entity,
+    pub error: String,
+}
+
+/// Something besides integers that [`IncrementNumbers`] steps.
+pub trait Increment: Send + Sync + 'static {
+    /// The range of `line` to replace, and what with, for the match under the byte offset
+    /// `column` or the first one after it.
+    fn increment(&self, line: &str, column: usize, delta: i64) -> Option<(Range<usize>, String)>;
+}
+
+/// What plugins registered with [`RegisterIncrement`].
+#[derive(Default)]
+pub struct Increments {
+    increments: Vec<Box<dyn Increment>>,
+}
+
+impl Increments {
+    /// The match of any of them or an integer under `column`, the longest one if they
+    /// overlap, or else the first one after it.
+    pub fn increment(
+        &self,
+        line: &str,
+        column: usize,
+        delta: i64,
+    ) -> Option<(Range<usize>, String)> {
+        self.increments
+            .iter()
+            .filter_map(|increment| increment.increment(line, column, delta))
+            .chain(increment_integer(line, column, delta))
+            .min_by_key(|(range, _)| {
+                let under = range.start <= column;
+                let start = if under { 0 } else { range.start };
+                (!under, start, Reverse(range.len()))
+            })
+    }
+}
+
+/// Lets plugins teach [`IncrementNumbers`] other things to step while the app is built.
+pub trait RegisterIncrement {
+    fn register_increment(&mut self, increment: impl Increment) -> &mut Self;
+}
+
+impl RegisterIncrement for App {
+    fn register_increment(&mut self, increment: impl Increment) -> &mut Self {
+        self.init_resource::<Increments>();
+        self.world
+            .get_resource_mut::<Increments>()
+            .unwrap()
+            .increments
+            .push(Box::new(increment));
+        self
+    }
+}
+
+/// The integer under `column` or the first one after it. A `-` right before it is its
+/// sign unless it follows a word, as in `item-2`.
+fn increment_integer(line: &str, column: usize, delta: i64) -> Option<(Range<usize>, String)> {
+    let bytes = line.as_bytes();
+    let digits = |from: usize, radix: u32| {
+        bytes[from..]
+            .iter()
+            .position(|b| !(*b as char).is_digit(radix))
+            .map_or(bytes.len(), |n| from + n)
+    };
+    let mut i = 0;
+    while i < bytes.len() {
+        if !bytes[i].is_ascii_digit() {
+            i += 1;
+            continue;
+        }
+        let prefixed = |prefix: u8, radix: u32| {
+            bytes[i] == b'0'
+                && bytes.get(i + 1).map(u8::to_ascii_lowercase) == Some(prefix)
+                && bytes
+                    .get(i + 2)
+                    .is_some_and(|b| (*b as char).is_digit(radix))
+        };
+        let (digits_start, radix) = if prefixed(b'x', 16) {
+            (i + 2, 16)
+        } else if prefixed(b'b', 2) {
+            (i + 2, 2)
+        } else {
+            (i, 10)
+        };
+        let end = digits(digits_start, radix);
+        let word = |b: &u8| b.is_ascii_alphanumeric() || *b == b'_';
+        let negative =
+            radix == 10 && i > 0 && bytes[i - 1] == b'-' && !(i > 1 && word(&bytes[i - 2]));
+        let start = i - negative as usize;
+        if end <= column {
+            i = end;
+            continue;
+        }
+
+        let text = &line[digits_start..end];
+        let stepped = if radix == 10 {
+            let value: i128 = line[start..end].parse().ok()?;
+            let value = value.checked_add(delta as i128)?;
+            // Zero padding keeps the number as wide, e.g. `007`.
+            let width = if text.len() > 1 && text.starts_with('0') {
+                text.len()
+            } else {
+                0
+            };
+            let sign = if value < 0 { "-" } else { "" };
+            format!("{sign}{:0width$}", value.unsigned_abs())
+        } else {
+            let value = u128::from_str_radix(text, radix).ok()?;
+            let value = value.checked_add_signed(delta as i128)?;
+            let width = text.len();
+            let digits = match radix {
+                16 if text.bytes().any(|b| b.is_ascii_uppercase()) => format!("{value:0width$X}"),
+                16 => format!("{value:0width$x}"),
+                _ => format!("{value:0width$b}"),
+            };
+            format!("{}{digits}", &line[i..digits_start])
+        };
+        return Some((start..end, stepped));
+    }
+    None
+}
+
+/// Steps ISO dates such as `2024-01-31` by the part under the cursor, the day unless it is
+/// on the year or month. Days past the end of a month clamp to its last day.
+pub struct Dates;
+
+impl Increment for Dates {
+    fn increment(&self, line: &str, column: usize, delta: i64) -> Option<(Range<usize>, String)> {
+        let bytes = line.as_bytes();
+        let digit = |i: usize| bytes.get(i).is_some_and(u8::is_ascii_digit);
+        let number = |range: Range<usize>| line[range].parse::<i64>().ok();
+        let mut start = 0;
+        while start + 10 <= bytes.len() {
+            let end = start + 10;
+            let is_date = (start..end).all(|i| match i - start {
+                4 | 7 => bytes[i] == b'-',
+                _ => bytes[i].is_ascii_digit(),
+            }) && !(start > 0 && digit(start - 1))
+                && !digit(end);
+            if !is_date || end <= column {
+                start += 1;
+                continue;
+            }
+            let (year, month, day) = (
+                number(start..start + 4)?,
+                number(start + 5..start + 7)?,
+                number(start + 8..end)?,
+            );
+            if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+                start += 1;
+                continue;
+            }
+
+            let part = column.saturating_sub(start);
+            let (year, month, day) = if column >= start && part < 4 {
+                let year = year + delta;
+                (year, month, day.min(days_in_month(year, month)))
+            } else if column >= start && part < 7 {
+                let months = year * 12 + month - 1 + delta;
+                let (year, month) = (months.div_euclid(12), months.rem_euclid(12) + 1);
+                (year, month, day.min(days_in_month(year, month)))
+            } else {
+                civil_from_days(days_from_civil(year, month, day) + delta)
+            };
+            if !(0..=9999).contains(&year) {
+                return None;
+            }
+            return Some((start..end, format!("{year:04}-{month:02}-{day:02}")));
+        }
+        None
+    }
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+fn run_increment_commands(
+    mut events: EventReader<RunCommand>,
+    workspace: Res<Workspace>,
+    mut increments: EventWriter<IncrementNumbers>,
+) {
+    for e in events.iter() {
+        let entity = match (e.id.starts_with("number."), workspace.active()) {
+            (true, Some(entity)) => entity,
+            _ => continue,
+        };
+        let (delta, sequence) = match e.id.as_str() {
+            "number.increment" => (1, false),
+            "number.decrement" => (-1, false),
+            "number.incrementSequence" => (1, true),
+            "number.decrementSequence" => (-1, true),
+            _ => continue,
+        };
+        increments.send(IncrementNumbers {
+            entity,
+            delta,
+            sequence,
+        });
+    }
+}
+
+/// Where to look for a number: a cursor, or one line of a selection.
+fn targets(buffer: &TextBuffer, range: Range<usize>) -> Vec<(usize, usize, usize)> {
+    let first = buffer.line_at(range.start);
+    let last = if range.is_empty() {
+        first
+    } else {
+        buffer.line_at(range.end)
+    };
+    (first..=last)
+        .map(|line| {
+            let start = buffer.line_start(line);
+            let end = start + buffer.get_line_length(line);
+            let column = range.start.max(start) - start;
+            let limit = if range.is_empty() {
+                end
+            } else {
+                range.end.min(end)
+            };
+            (line, column, limit)
+        })
+        .collect()
+}
+
+fn increment_numbers(
+    mut commands: Commands,
+    mut events: EventReader<IncrementNumbers>,
+    mut documents: Documents<()>,
+    mut secondaries: Secondaries,
+    (increments, mut placed): (Res<Increments>, ResMut<Placed>),
+    mut changed: EventWriter<DocumentChanged>,
+    mut failed: EventWriter<IncrementFailed>,
+) {
+    for e in events.iter() {
+        let (mut document, mut cursor, mut selection, ()) = match documents.get_mut(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let mut fail = |error: String| {
+            warn!("➕ {error}");
+            failed.send(IncrementFailed {
+                entity: e.entity,
+                error,
+            });
+        };
+        if document.is_read_only() {
+            fail("The document is read only".to_string());
+            continue;
+        }
+        let mut carets = cursor::carets(e.entity, (&cursor, &selection), &mut secondaries);
+        carets.sort_by_key(|caret| caret.range().start);
+
+        // The edits of each caret in document order. Two cursors on one number step it once.
+        let buffer = document.buffer();
+        let mut edits: Vec<Vec<(Range<usize>, String)>> = vec![vec![]; carets.len()];
+        let mut taken: Vec<Range<usize>> = vec![];
+        for (caret, edits) in carets.iter().zip(&mut edits) {
+            for (line, column, limit) in targets(buffer, caret.range()) {
+                let start = buffer.line_start(line);
+                let content = buffer.get_line_content(line);
+                let delta = match e.sequence {
+                    true => e.delta.saturating_mul(taken.len() as i64 + 1),
+                    false => e.delta,
+                };
+                let (range, text) = match increments.increment(&content, column, delta) {
+                    Some((range, text)) if start + range.start <= limit => {
+                        (start + range.start..start + range.end, text)
+                    }
+                    _ => continue,
+                };
+                if taken
+                    .iter()
+                    .any(|r| r.start < range.end && range.start < r.end)
+                {
+                    continue;
+                }
+                taken.push(range.clone());
+                edits.push((range, text));
+            }
+        }
+        if taken.is_empty() {
+            fail("No number at the cursor".to_string());
+            continue;
+        }
+
+        // A cursor ends up on the last character of its number, a selection still covers
+        // the lines it did.
+        document.history_mut().begin();
+        let mut i = carets.len();
+        cursor::edit_carets(&mut document, &mut carets, |document, caret| {
+            i -= 1;
+            let range = caret.range();
+            let mut grown = 0isize;
+            let mut stepped = range.clone();
+            for (r, text) in edits[i].iter().rev() {
+                document.delete(r.clone());
+                document.insert(r.start, text);
+                grown += text.len() as isize - r.len() as isize;
+                stepped = r.start..r.start + text.len();
+            }
+            match (range.is_empty(), edits[i].is_empty()) {
+                (_, true) => range,
+                (true, false) => {
+                    let text = document.buffer().text_in(stepped.clone());
+                    let back = text.chars().next_back().map_or(0, char::len_utf8);
+                    stepped.end - back..stepped.end - back
+                }
+                (false, false) => range.start..(range.end as isize + grown) as usize,
+            }
+        });
+        cursor::store(
+            carets,
+            (&mut cursor, &mut selection),
+            &mut secondaries,
+            &mut commands,
+        );
+        placed.0.insert((e.entity, document.version()));
+        debug!("➕ Stepped {} by {}", count(taken.len(), "number"), e.delta);
+        changed.send(DocumentChanged {
+            entity: e.entity,
+            version: document.version(),
+            changes: document.take_changes(),
+            cursor: None,
+        });
+    }
+}
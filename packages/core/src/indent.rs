@@ -0,0 +1,616 @@
+use crate::{document::Document, text_buffer::TextBuffer};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        query::Added,
+        system::{Commands, Query, Res},
+    },
+};
+use regex::Regex;
+use std::{fmt, ops::Range, path::Path};
+
+/// Lines looked at when searching for the bracket a typed one closes.
+const MAX_SCAN_LINES: usize = 1000;
+
+pub struct IndentPlugin;
+
+impl Plugin for IndentPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<IndentRules>()
+            .init_resource::<IndentLanguages>()
+            .add_system(attach_indent_rules);
+    }
+}
+
+/// What the text at a position belongs to, as far as its line tells.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyntaxContext {
+    Code,
+    String,
+    Comment,
+}
+
+/// How a language opens and closes indented blocks. Used as a resource for documents
+/// without their own rules. Brackets in strings and after a line comment are ignored.
+#[derive(Component, Clone, Debug, PartialEq, Eq)]
+pub struct IndentRules {
+    pub brackets: Vec<(char, char)>,
+    pub line_comment: Option<String>,
+    pub quotes: Vec<char>,
+    /// Lines ending in what it matches indent the next one, e.g. `:` in Python.
+    pub increase: Option<IndentPattern>,
+    /// Lines it matches are outdented from the block they are typed in, e.g. `end`.
+    pub decrease: Option<IndentPattern>,
+    /// Whether the built-in on type formatting applies, see [`crate::format::format_on_type`].
+    /// Off by default, trailing spaces are line breaks in Markdown and text in prose.
+    pub format_on_type: bool,
+}
+
+impl Default for IndentRules {
+    fn default() -> Self {
+        Self {
+            brackets: vec![('{', '}'), ('(', ')'), ('[', ']')],
+            line_comment: Some("//".to_string()),
+            quotes: vec!['"', '\'', '`'],
+            increase: None,
+            decrease: None,
+            format_on_type: false,
+        }
+    }
+}
+
+impl IndentRules {
+    fn increases(&self, line: &str) -> bool {
+        self.increase.as_ref().is_some_and(|p| p.is_match(line))
+    }
+
+    fn decreases(&self, line: &str) -> bool {
+        self.decrease.as_ref().is_some_and(|p| p.is_match(line))
+    }
+
+    /// Whether the line before a line break indents the next one.
+    fn opens(&self, line: &str) -> bool {
+        self.unclosed(line) > 0 || self.increases(line)
+    }
+
+    pub fn is_closing(&self, c: char) -> bool {
+        self.brackets.iter().any(|&(_, close)| close == c)
+    }
+
+    /// Splits `line` into strings and a trailing line comment. A `'` within a word is an
+    /// apostrophe. With `apostrophes`, one left open at the end of the line is taken for an
+    /// apostrophe or a lifetime too, and the line lexed again after it.
+    fn lex(&self, line: &str, apostrophes: bool) -> Lexed {
+        let mut lexed = Lexed {
+            strings: vec![],
+            unclosed: false,
+            comment: None,
+        };
+        let mut open = None;
+        let mut escaped = false;
+        let mut previous = None;
+        let mut i = 0;
+        loop {
+            let c = match line[i..].chars().next() {
+                Some(c) => c,
+                None => match open {
+                    Some((start, '\'')) if apostrophes => {
+                        open = None;
+                        previous = Some('\'');
+                        i = start + 1;
+                        continue;
+                    }
+                    _ => break,
+                },
+            };
+            if let Some((start, q)) = open {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == q {
+                    lexed.strings.push(start..i + c.len_utf8());
+                    open = None;
+                }
+            } else if self.quotes.contains(&c)
+                && !is_apostrophe(c, previous, &line[i + c.len_utf8()..])
+            {
+                open = Some((i, c));
+            } else if let Some(comment) = &self.line_comment {
+                if line[i..].starts_with(comment.as_str()) {
+                    lexed.comment = Some(i);
+                    break;
+                }
+            }
+            previous = Some(c);
+            i += c.len_utf8();
+        }
+        if let Some((start, _)) = open {
+            lexed.strings.push(start..line.len());
+            lexed.unclosed = true;
+        }
+        lexed
+    }
+
+    /// Brackets of `line` outside strings and comments.
+    fn brackets<'a>(&'a self, line: &'a str) -> impl Iterator<Item = char> + 'a {
+        let lexed = self.lex(line, true);
+        let code = &line[..lexed.comment.unwrap_or(line.len())];
+        let mut strings = lexed.strings.into_iter().peekable();
+        code.char_indices().filter_map(move |(i, c)| {
+            while strings.next_if(|s| s.end <= i).is_some() {}
+            let quoted = strings.peek().is_some_and(|s| s.contains(&i));
+            let bracket = self.brackets.iter().any(|&(o, cl)| c == o || c == cl);
+            (bracket && !quoted).then_some(c)
+        })
+    }
+
+    /// Brackets opened and not closed in `line`.
+    fn unclosed(&self, line: &str) -> usize {
+        let mut open = 0usize;
+        for c in self.brackets(line) {
+            if self.is_closing(c) {
+                open = open.saturating_sub(1);
+            } else {
+                open += 1;
+            }
+        }
+        open
+    }
+
+    /// Context at the end of `before`, the start of a line up to a position.
+    pub fn context(&self, before: &str) -> SyntaxContext {
+        let lexed = self.lex(before, false);
+        if lexed.comment.is_some() {
+            SyntaxContext::Comment
+        } else if lexed.unclosed {
+            SyntaxContext::String
+        } else {
+            SyntaxContext::Code
+        }
+    }
+
+    pub fn context_at(&self, buffer: &TextBuffer, offset: usize) -> SyntaxContext {
+        let start = buffer.line_start(buffer.line_at(offset));
+        self.context(&buffer.text_in(start..offset))
+    }
+
+    /// The closed string of `line` around `column`, quotes included. A column right before
+    /// or after the quotes counts too.
+    pub fn string_at(&self, line: &str, column: usize) -> Option<Range<usize>> {
+        let lexed = self.lex(line, true);
+        let closed = lexed.strings.len() - usize::from(lexed.unclosed);
+        lexed
+            .strings
+            .into_iter()
+            .take(closed)
+            .find(|s| s.start <= column && column <= s.end)
+    }
+}
+
+/// Whether `c` between `previous` and `after` is an apostrophe, as in "don't".
+fn is_apostrophe(c: char, previous: Option<char>, after: &str) -> bool {
+    c == '\''
+        && previous.is_some_and(char::is_alphanumeric)
+        && after.chars().next().is_some_and(char::is_alphanumeric)
+}
+
+/// A regular expression matched against the text of a line, compared by its source.
+#[derive(Clone)]
+pub struct IndentPattern(Regex);
+
+impl IndentPattern {
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Regex::new(pattern).map(Self)
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        self.0.is_match(line)
+    }
+}
+
+impl PartialEq for IndentPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Eq for IndentPattern {}
+
+impl fmt::Debug for IndentPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("IndentPattern")
+            .field(&self.0.as_str())
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndentLanguage {
+    pub name: String,
+    /// File extensions it is used for, without the dot.
+    pub extensions: Vec<String>,
+    pub rules: IndentRules,
+}
+
+impl IndentLanguage {
+    pub fn new(name: &str, extensions: &[&str], rules: IndentRules) -> Self {
+        Self {
+            name: name.to_string(),
+            extensions: extensions.iter().map(|e| e.to_string()).collect(),
+            rules,
+        }
+    }
+}
+
+/// The rules documents get by file extension when opened, unless they have their own.
+/// Others use the [`IndentRules`] resource. JavaScript, TypeScript, Python, Ruby, Rust and
+/// the C family come built in.
+#[derive(Clone, Debug)]
+pub struct IndentLanguages {
+    languages: Vec<IndentLanguage>,
+}
+
+impl Default for IndentLanguages {
+    fn default() -> Self {
+        let pattern = |p: &str| Some(IndentPattern::new(p).expect("built-in patterns are valid"));
+        let code = IndentRules {
+            format_on_type: true,
+            ..IndentRules::default()
+        };
+        let python = IndentRules {
+            line_comment: Some("#".to_string()),
+            quotes: vec!['"', '\''],
+            increase: pattern(r":\s*(#.*)?$"),
+            decrease: pattern(r"^\s*(elif|else|except|finally)\b.*:\s*$"),
+            ..code.clone()
+        };
+        let ruby = IndentRules {
+            line_comment: Some("#".to_string()),
+            quotes: vec!['"', '\''],
+            increase: pattern(
+                r"^\s*(class|module|def|if|unless|case|while|until|for|begin)\b|\bdo(\s*\|[^|]*\|)?\s*$",
+            ),
+            decrease: pattern(r"^\s*(end|else|ensure)\s*$|^\s*(elsif|when|rescue)\b"),
+            ..code.clone()
+        };
+        Self {
+            languages: vec![
+                IndentLanguage::new(
+                    "javascript",
+                    &["js", "mjs", "cjs", "jsx", "ts", "mts", "cts", "tsx"],
+                    code.clone(),
+                ),
+                IndentLanguage::new("python", &["py", "pyi"], python),
+                IndentLanguage::new("ruby", &["rb"], ruby),
+                IndentLanguage::new(
+                    "c",
+                    &[
+                        "c", "h", "cc", "cpp", "hpp", "cs", "go", "java", "kt", "swift",
+                    ],
+                    code.clone(),
+                ),
+                IndentLanguage::new("rust", &["rs"], code),
+            ],
+        }
+    }
+}
+
+impl IndentLanguages {
+    /// Adds a language, or replaces the one with the same name. Documents opened from now
+    /// on use it.
+    pub fn add(&mut self, language: IndentLanguage) {
+        match self.languages.iter_mut().find(|l| l.name == language.name) {
+            Some(existing) => *existing = language,
+            None => self.languages.push(language),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&IndentLanguage> {
+        self.languages.iter().find(|l| l.name == name)
+    }
+
+    pub fn for_path(&self, path: &Path) -> Option<&IndentLanguage> {
+        let extension = path.extension()?.to_str()?;
+        self.languages.iter().rev().find(|l| {
+            l.extensions
+                .iter()
+                .any(|e| e.eq_ignore_ascii_case(extension))
+        })
+    }
+}
+
+fn attach_indent_rules(
+    mut commands: Commands,
+    languages: Res<IndentLanguages>,
+    documents: Query<(Entity, &Document, Option<&IndentRules>), Added<Document>>,
+) {
+    for (entity, document, own) in documents.iter() {
+        if own.is_some() {
+            continue;
+        }
+        if let Some(language) = document.path().and_then(|path| languages.for_path(path)) {
+            commands.entity(entity).insert(language.rules.clone());
+        }
+    }
+}
+
+struct Lexed {
+    strings: Vec<Range<usize>>,
+    /// The last string runs to the end of the line.
+    unclosed: bool,
+    comment: Option<usize>,
+}
+
+/// One level of indentation, detected from the lines of a document.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndentUnit {
+    Tab,
+    Spaces(usize),
+}
+
+impl Default for IndentUnit {
+    fn default() -> Self {
+        IndentUnit::Spaces(4)
+    }
+}
+
+impl IndentUnit {
+    /// Uses the indentation most lines start with, looking at the first few hundred.
+    pub fn detect(buffer: &TextBuffer) -> Self {
+        Self::detect_or(buffer, Self::default())
+    }
+
+    /// Like [`IndentUnit::detect`], with `fallback` when no line is indented.
+    pub fn detect_or(buffer: &TextBuffer, fallback: Self) -> Self {
+        let (mut tabs, mut spaces) = (0, [0usize; 9]);
+        let mut previous = 0;
+        for line in 0..buffer.line_count().min(500) {
+            let content = buffer.get_line_content(line);
+            if content.trim().is_empty() {
+                continue;
+            }
+            if content.starts_with('\t') {
+                tabs += 1;
+                continue;
+            }
+            let width = content.len() - content.trim_start_matches(' ').len();
+            // Steps between lines reveal the unit better than absolute widths.
+            let step = width.abs_diff(previous);
+            if (2..=8).contains(&step) {
+                spaces[step] += 1;
+            }
+            previous = width;
+        }
+        let (step, count) = spaces
+            .iter()
+            .enumerate()
+            .max_by_key(|&(step, count)| (*count, step))
+            .unwrap();
+        match (tabs, count) {
+            (0, 0) => fallback,
+            (tabs, count) if tabs > *count => IndentUnit::Tab,
+            _ => IndentUnit::Spaces(step),
+        }
+    }
+
+    pub fn text(&self) -> String {
+        match self {
+            IndentUnit::Tab => "\t".to_string(),
+            IndentUnit::Spaces(n) => " ".repeat(*n),
+        }
+    }
+}
+
+fn leading_whitespace(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+/// `indent` one level shallower.
+fn outdented(indent: &str, unit: IndentUnit) -> &str {
+    let spaces = indent.len() - indent.trim_end_matches(' ').len();
+    let width = match unit {
+        _ if spaces == 0 => usize::from(indent.ends_with('\t')),
+        IndentUnit::Tab => spaces,
+        IndentUnit::Spaces(n) => spaces.min(n),
+    };
+    &indent[..indent.len() - width]
+}
+
+/// Text to insert for a line break typed at `offset` and where the cursor goes in it. The
+/// new line keeps the indentation of the current one, one level deeper after an unclosed
+/// bracket or a line matching the increase pattern, one shallower when the text moved to
+/// it matches the decrease pattern. Between a pair of brackets the closing one moves to a
+/// line of its own.
+pub fn newline(
+    buffer: &TextBuffer,
+    offset: usize,
+    rules: &IndentRules,
+    unit: IndentUnit,
+) -> (String, usize) {
+    let line = buffer.line_at(offset);
+    let content = buffer.get_line_content(line);
+    let column = (offset - buffer.line_start(line)).min(content.len());
+    let (before, after) = content.split_at(column);
+    let base = leading_whitespace(&content);
+
+    let mut text = format!("\n{base}");
+    match (rules.opens(before), rules.decreases(after)) {
+        (false, false) => return (text.clone(), text.len()),
+        (false, true) => {
+            let text = format!("\n{}", outdented(base, unit));
+            return (text.clone(), text.len());
+        }
+        (true, true) if !after.trim_start().starts_with(|c| rules.is_closing(c)) => {
+            return (text.clone(), text.len());
+        }
+        (true, _) => {}
+    }
+    text.push_str(&unit.text());
+    let cursor = text.len();
+    let closes = after
+        .trim_start()
+        .chars()
+        .next()
+        .is_some_and(|c| rules.is_closing(c));
+    if closes {
+        text.push('\n');
+        text.push_str(base);
+    }
+    (text, cursor)
+}
+
+/// A node of the syntax tree whose lines are indented one level deeper than the line it
+/// starts on, as told by the indents query of its grammar.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndentScope {
+    pub range: Range<usize>,
+    /// Where the node closing it starts, e.g. its `}`.
+    pub close: Option<usize>,
+}
+
+impl IndentScope {
+    /// Whether `close` is the bracket closing what the scope opens with.
+    pub fn is_closed_by(&self, buffer: &TextBuffer, close: char, rules: &IndentRules) -> bool {
+        let line = buffer.line_at(self.range.start);
+        let content = buffer.get_line_content(line);
+        let opening = &content[self.range.start - buffer.line_start(line)..];
+        rules
+            .brackets
+            .iter()
+            .any(|&(o, c)| c == close && opening.starts_with(o))
+    }
+
+    /// Indentation of the line the scope starts on.
+    fn base(&self, buffer: &TextBuffer) -> String {
+        let opening = buffer.get_line_content(buffer.line_at(self.range.start));
+        leading_whitespace(&opening).to_string()
+    }
+}
+
+/// Like [`newline`], with the block the line break is typed in told by the syntax tree
+/// instead of brackets: the new line is indented one level deeper than the line `scope`
+/// starts on. Right before the node closing the scope, that node moves to a line of its own
+/// at the level of the line the scope starts on.
+pub fn newline_in(
+    buffer: &TextBuffer,
+    offset: usize,
+    scope: &IndentScope,
+    unit: IndentUnit,
+) -> (String, usize) {
+    let base = scope.base(buffer);
+    let line = buffer.line_at(offset);
+    let closes = scope.close.is_some_and(|close| {
+        close >= offset
+            && buffer.line_at(close) == line
+            && buffer.text_in(offset..close).trim().is_empty()
+    });
+    let mut text = format!("\n{base}");
+    // The line break moves the closing node down, the line the cursor is on stays.
+    if closes && buffer.line_at(scope.range.start) != line {
+        return (text.clone(), text.len());
+    }
+    text.push_str(&unit.text());
+    let cursor = text.len();
+    if closes {
+        text.push('\n');
+        text.push_str(&base);
+    }
+    (text, cursor)
+}
+
+/// Like [`outdent`], aligning the bracket typed on an otherwise blank line with the line
+/// `scope` starts on.
+pub fn outdent_in(
+    buffer: &TextBuffer,
+    offset: usize,
+    scope: &IndentScope,
+) -> Option<(Range<usize>, String)> {
+    let line = buffer.line_at(offset);
+    let start = buffer.line_start(line);
+    let content = buffer.get_line_content(line);
+    let column = offset - start;
+    if !content[..column.min(content.len())].trim().is_empty() {
+        return None;
+    }
+    let indent = scope.base(buffer);
+    let current = start..start + leading_whitespace(&content).len().min(column);
+    (buffer.text_in(current.clone()) != indent).then_some((current, indent))
+}
+
+/// Typing the closing bracket `close` at `offset` on an otherwise blank line aligns the
+/// line with the one holding the matching opening bracket. Returns the indentation to
+/// replace and its replacement.
+pub fn outdent(
+    buffer: &TextBuffer,
+    offset: usize,
+    close: char,
+    rules: &IndentRules,
+) -> Option<(Range<usize>, String)> {
+    let open = rules
+        .brackets
+        .iter()
+        .find(|&&(_, c)| c == close)
+        .map(|&(o, _)| o)?;
+    let line = buffer.line_at(offset);
+    let start = buffer.line_start(line);
+    let content = buffer.get_line_content(line);
+    let column = offset - start;
+    if !content[..column.min(content.len())].trim().is_empty() {
+        return None;
+    }
+
+    let mut depth = 0usize;
+    for previous in (line.saturating_sub(MAX_SCAN_LINES)..line).rev() {
+        let text = buffer.get_line_content(previous);
+        let brackets: Vec<char> = rules.brackets(&text).collect();
+        for c in brackets.into_iter().rev() {
+            if c == close {
+                depth += 1;
+            } else if c == open && depth > 0 {
+                depth -= 1;
+            } else if c == open {
+                let indent = leading_whitespace(&text).to_string();
+                let current = start..start + leading_whitespace(&content).len().min(column);
+                return (buffer.text_in(current.clone()) != indent).then_some((current, indent));
+            }
+        }
+    }
+    None
+}
+
+/// Typing `typed` at `offset` so that its line starts matching the decrease pattern, e.g.
+/// the last letter of `else:`, outdents the line from the block it is typed in. Returns
+/// the indentation to replace and its replacement.
+pub fn dedent(
+    buffer: &TextBuffer,
+    offset: usize,
+    typed: char,
+    rules: &IndentRules,
+) -> Option<(Range<usize>, String)> {
+    let decrease = rules.decrease.as_ref()?;
+    let line = buffer.line_at(offset);
+    let start = buffer.line_start(line);
+    let content = buffer.get_line_content(line);
+    let column = (offset - start).min(content.len());
+    let (before, after) = content.split_at(column);
+    if decrease.is_match(&content) || !decrease.is_match(&format!("{before}{typed}{after}")) {
+        return None;
+    }
+
+    let previous = (line.saturating_sub(MAX_SCAN_LINES)..line)
+        .rev()
+        .map(|previous| buffer.get_line_content(previous))
+        .find(|text| !text.trim().is_empty())?;
+    let base = leading_whitespace(&previous);
+    // Right after the line opening the block there is nothing to outdent from.
+    let target = match rules.opens(&previous) {
+        true => base,
+        false => outdented(base, IndentUnit::detect(buffer)),
+    };
+    let current = leading_whitespace(&content);
+    (current.len() > target.len() && column >= current.len())
+        .then(|| (start..start + current.len(), target.to_string()))
+}
@@ -0,0 +1,448 @@
+use crate::{
+    document::{DiskStamp, Document, DocumentChanged, DocumentSaved},
+    memory::MemoryUsage,
+    pipeline::{AppPipelineExt, EditorStage},
+    playback::Playback,
+    shutdown::{AppShutdownExt, Shutdown, ShutdownStage, ShutdownStageStarted},
+    text_buffer::TextBuffer,
+};
+use bevy::{
+    app::{App, Plugin},
+    core::Time,
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        query::Without,
+        system::{Commands, Query, Res, ResMut},
+    },
+    log::{debug, warn},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    env,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, UNIX_EPOCH},
+};
+
+/// Bumped when the format changes. Journals of other versions are not recovered.
+const JOURNAL_VERSION: u32 = 1;
+
+const PARTICIPANT: &str = "journal";
+
+pub struct JournalPlugin;
+
+impl Plugin for JournalPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<JournalSettings>()
+            .init_resource::<Journals>()
+            .add_event::<DocumentRecovered>()
+            .add_event::<JournalRecoveryFailed>()
+            .add_shutdown_participant(ShutdownStage::Flush, PARTICIPANT)
+            .add_startup_system(recover_journals)
+            .add_editor_system(EditorStage::Derive, record_changes)
+            .add_system(flush_journals)
+            .add_system(discard_saved)
+            .add_system(discard_on_shutdown);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct JournalSettings {
+    /// Where journals are kept, one file per document with unsaved changes. None turns
+    /// journaling off.
+    pub dir: Option<PathBuf>,
+    /// How often unsaved edits are written out, at most this much typing is lost in a
+    /// crash.
+    pub interval: Duration,
+}
+
+impl Default for JournalSettings {
+    fn default() -> Self {
+        Self {
+            dir: state_dir().map(|dir| dir.join("journal")),
+            interval: Duration::from_secs(2),
+        }
+    }
+}
+
+/// `$XDG_STATE_HOME/dip`, `~/.local/state/dip`, or `%LOCALAPPDATA%\dip` on Windows.
+pub(crate) fn state_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state"))
+            })
+    };
+    base.map(|base| base.join("dip"))
+}
+
+/// A document was restored from the journal a crashed run left behind. Its unsaved edits
+/// are one undo step.
+#[derive(Clone, Debug)]
+pub struct DocumentRecovered {
+    pub entity: Entity,
+    pub path: Option<PathBuf>,
+}
+
+/// A journal could not be applied, e.g. as its file changed since. It is kept with the
+/// `.failed` extension instead of being tried again.
+#[derive(Clone, Debug)]
+pub struct JournalRecoveryFailed {
+    pub journal: PathBuf,
+    pub path: Option<PathBuf>,
+    pub reason: String,
+}
+
+/// The first line of a journal. Every other line is a [`Record`].
+#[derive(Debug, Deserialize, Serialize)]
+struct Header {
+    version: u32,
+    /// The process writing it, so journals of a running instance aren't taken for ones
+    /// left by a crash.
+    pid: u32,
+    /// None for untitled documents, whose edits apply to an empty buffer.
+    path: Option<PathBuf>,
+    /// The file the edits apply to, as seconds and nanoseconds since the epoch.
+    modified: Option<(u64, u32)>,
+    len: u64,
+}
+
+impl Header {
+    fn new(path: Option<&Path>, stamp: Option<DiskStamp>) -> Self {
+        let modified = stamp
+            .and_then(|stamp| stamp.modified)
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|since| (since.as_secs(), since.subsec_nanos()));
+        Self {
+            version: JOURNAL_VERSION,
+            pid: process::id(),
+            path: path.map(Path::to_path_buf),
+            modified,
+            len: stamp.map_or(0, |stamp| stamp.len),
+        }
+    }
+
+    fn stamp(&self) -> DiskStamp {
+        DiskStamp {
+            modified: self
+                .modified
+                .map(|(secs, nanos)| UNIX_EPOCH + Duration::new(secs, nanos)),
+            len: self.len,
+        }
+    }
+}
+
+/// `range` of the text before it replaced with `text`.
+#[derive(Debug, Deserialize, Serialize)]
+struct Record {
+    start: usize,
+    end: usize,
+    text: String,
+}
+
+#[derive(Debug)]
+struct Journal {
+    file: PathBuf,
+    header: Option<Header>,
+    pending: Vec<Record>,
+}
+
+impl Journal {
+    fn flush(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        if let Some(dir) = self.file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut lines = String::new();
+        if let Some(header) = self.header.take() {
+            lines += &serde_json::to_string(&header)?;
+            lines.push('\n');
+        }
+        for record in self.pending.drain(..) {
+            lines += &serde_json::to_string(&record)?;
+            lines.push('\n');
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.file)?
+            .write_all(lines.as_bytes())
+    }
+
+    fn discard(self) {
+        if let Err(error) = fs::remove_file(&self.file) {
+            if error.kind() != io::ErrorKind::NotFound {
+                warn!("📓 Failed to remove {}: {error}", self.file.display());
+            }
+        }
+    }
+}
+
+/// The journals of documents edited since they were opened or last saved.
+#[derive(Debug, Default)]
+pub struct Journals {
+    open: HashMap<Entity, Journal>,
+    last_flush: f64,
+}
+
+impl Journals {
+    /// Whether `entity` has edits that are journaled but not saved.
+    pub fn is_journaled(&self, entity: Entity) -> bool {
+        self.open.contains_key(&entity)
+    }
+}
+
+/// One file per document path, or per untitled document of this process.
+fn journal_file(dir: &Path, entity: Entity, path: Option<&Path>) -> PathBuf {
+    let name = match path {
+        Some(path) => {
+            let digest = Sha256::digest(path.to_string_lossy().as_bytes());
+            digest[..8].iter().map(|b| format!("{b:02x}")).collect()
+        }
+        None => format!("untitled-{}-{}", process::id(), entity.id()),
+    };
+    dir.join(name).with_extension("journal")
+}
+
+fn record_changes(
+    mut events: EventReader<DocumentChanged>,
+    settings: Res<JournalSettings>,
+    mut journals: ResMut<Journals>,
+    // Replays only show edits made before, kept where they were made.
+    documents: Query<&Document, Without<Playback>>,
+) {
+    let dir = match &settings.dir {
+        Some(dir) => dir,
+        None => return,
+    };
+    for e in events.iter().filter(|e| !e.changes.is_empty()) {
+        let document = match documents.get(e.entity) {
+            // Encrypted documents never reach the disk as plain text.
+            Ok(document) if !document.is_encrypted() => document,
+            _ => continue,
+        };
+        let journal = journals.open.entry(e.entity).or_insert_with(|| {
+            let path = document.path();
+            Journal {
+                file: journal_file(dir, e.entity, path),
+                header: Some(Header::new(path, document.disk_stamp())),
+                pending: vec![],
+            }
+        });
+        journal
+            .pending
+            .extend(e.changes.iter().map(|change| Record {
+                start: change.range.start,
+                end: change.range.end,
+                text: change.text.clone(),
+            }));
+    }
+}
+
+fn flush_journals(
+    settings: Res<JournalSettings>,
+    time: Res<Time>,
+    mut journals: ResMut<Journals>,
+    documents: Query<&Document>,
+) {
+    let now = time.seconds_since_startup();
+    if now - journals.last_flush < settings.interval.as_secs_f64() {
+        return;
+    }
+    journals.last_flush = now;
+
+    // Closing a document without saving discards its edits.
+    let closed: Vec<Entity> = journals
+        .open
+        .keys()
+        .copied()
+        .filter(|&entity| documents.get(entity).is_err())
+        .collect();
+    for entity in closed {
+        journals.open.remove(&entity).unwrap().discard();
+    }
+    for journal in journals.open.values_mut() {
+        if let Err(error) = journal.flush() {
+            warn!("📓 Failed to write {}: {error}", journal.file.display());
+        }
+    }
+}
+
+fn discard_saved(mut saved: EventReader<DocumentSaved>, mut journals: ResMut<Journals>) {
+    for e in saved.iter() {
+        if let Some(journal) = journals.open.remove(&e.entity) {
+            debug!("📓 Saved {}, discarding its journal", e.path.display());
+            journal.discard();
+        }
+    }
+}
+
+/// By the time journals are flushed on the way out, unsaved changes were saved or
+/// deliberately thrown away, so only a crash leaves journals behind.
+fn discard_on_shutdown(
+    mut started: EventReader<ShutdownStageStarted>,
+    mut journals: ResMut<Journals>,
+    mut shutdown: ResMut<Shutdown>,
+) {
+    if started.iter().any(|e| e.0 == ShutdownStage::Flush) {
+        for (_, journal) in journals.open.drain() {
+            journal.discard();
+        }
+        shutdown.complete(PARTICIPANT);
+    }
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    // Signal 0 only checks the process exists.
+    unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_running(pid: u32) -> bool {
+    pid == process::id()
+}
+
+/// The header and records of `file`. A crash may have cut off the last line, which is
+/// left out.
+fn read_journal(file: &Path) -> Result<(Header, Vec<Record>), String> {
+    let text = fs::read_to_string(file).map_err(|e| e.to_string())?;
+    let mut lines = text.lines();
+    let header: Header = lines
+        .next()
+        .and_then(|line| serde_json::from_str(line).ok())
+        .ok_or("no header")?;
+    let records = lines
+        .map_while(|line| serde_json::from_str(line).ok())
+        .collect();
+    Ok((header, records))
+}
+
+/// The document `header` was written for, with `records` applied as one undo step.
+fn replay(header: &Header, records: &[Record]) -> Result<Document, String> {
+    if header.version != JOURNAL_VERSION {
+        return Err(format!("unknown journal version {}", header.version));
+    }
+    let mut document = match &header.path {
+        Some(path) => {
+            if DiskStamp::read(path) != Some(header.stamp()) {
+                return Err(format!("{} changed since", path.display()));
+            }
+            Document::from_path(path).map_err(|e| e.to_string())?
+        }
+        None => Document::new(None, TextBuffer::default()),
+    };
+    document.history_mut().begin();
+    for record in records {
+        let range: Range<usize> = record.start..record.end;
+        let text = document.buffer();
+        let fits = range.start <= range.end
+            && range.end <= text.len()
+            && [range.start, range.end]
+                .iter()
+                .all(|&offset| is_char_boundary(text, offset));
+        if !fits {
+            return Err(format!("edit {range:?} is out of bounds"));
+        }
+        document.delete(range.clone());
+        document.insert(range.start, &record.text);
+    }
+    document.history_mut().commit();
+    Ok(document)
+}
+
+fn is_char_boundary(buffer: &TextBuffer, offset: usize) -> bool {
+    // A continuation byte never starts a char.
+    buffer
+        .bytes_at(offset)
+        .first()
+        .is_none_or(|&byte| (byte as i8) >= -0x40)
+}
+
+fn recover_journals(
+    mut commands: Commands,
+    settings: Res<JournalSettings>,
+    mut journals: ResMut<Journals>,
+    mut recovered: EventWriter<DocumentRecovered>,
+    mut failed: EventWriter<JournalRecoveryFailed>,
+) {
+    let dir = match &settings.dir {
+        Some(dir) => dir,
+        None => return,
+    };
+    let files = match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|entry| Some(entry.ok()?.path())),
+        Err(_) => return,
+    };
+    for file in files.filter(|file| file.extension().is_some_and(|e| e == "journal")) {
+        let (header, records) = match read_journal(&file) {
+            Ok(journal) => journal,
+            Err(reason) => {
+                set_aside(&file, None, reason, &mut failed);
+                continue;
+            }
+        };
+        if header.pid != process::id() && is_running(header.pid) {
+            continue;
+        }
+        let mut document = match replay(&header, &records) {
+            Ok(document) => document,
+            Err(reason) => {
+                set_aside(&file, header.path, reason, &mut failed);
+                continue;
+            }
+        };
+        debug!("📓 Recovered {}", file.display());
+        document.take_changes();
+        let path = header.path.clone();
+        let entity = commands
+            .spawn()
+            .insert(document)
+            .insert(MemoryUsage::default())
+            .id();
+        // Rewritten under this process, so other instances leave it be, and journaled on
+        // from there.
+        let mut journal = Journal {
+            file,
+            header: Some(Header {
+                pid: process::id(),
+                ..header
+            }),
+            pending: records,
+        };
+        let _ = fs::remove_file(&journal.file);
+        if let Err(error) = journal.flush() {
+            warn!("📓 Failed to write {}: {error}", journal.file.display());
+        }
+        journals.open.insert(entity, journal);
+        recovered.send(DocumentRecovered { entity, path });
+    }
+}
+
+/// Keeps a journal that can't be recovered as `.failed`, so it isn't tried again.
+fn set_aside(
+    file: &Path,
+    path: Option<PathBuf>,
+    reason: String,
+    failed: &mut EventWriter<JournalRecoveryFailed>,
+) {
+    warn!("📓 Failed to recover {}: {reason}", file.display());
+    let kept = file.with_extension("failed");
+    let _ = fs::rename(file, &kept);
+    failed.send(JournalRecoveryFailed {
+        journal: kept,
+        path,
+        reason,
+    });
+}
@@ -0,0 +1,772 @@
+use crate::{
+    command::RunCommand,
+    config::Settings,
+    cursor::TypeText,
+    pipeline::{AppPipelineExt, EditorStage},
+    vim::Vim,
+    workspace::Workspace,
+    Mode, ModeType,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        event::{EventReader, EventWriter},
+        schedule::{ParallelSystemDescriptorCoercion, SystemLabel},
+        system::{Local, Query, Res, ResMut},
+    },
+    input::{
+        keyboard::{KeyCode, KeyboardInput},
+        ElementState, Input,
+    },
+    log::debug,
+    window::ReceivedCharacter,
+};
+use serde::Deserialize;
+use std::{fmt, str::FromStr};
+
+pub struct KeymapPlugin;
+
+/// The system running the commands keys are bound to and typing the characters of the
+/// others.
+#[derive(SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MatchKeys;
+
+impl Plugin for KeymapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Keymap>()
+            .init_resource::<PendingKeys>()
+            .init_resource::<DeferredKeys>()
+            .add_editor_system(EditorStage::Input, match_keys.label(MatchKeys))
+            .add_system(apply_preset);
+    }
+}
+
+/// A key pressed together with modifiers, written like `ctrl+shift+k`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Chord {
+    pub key: KeyCode,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    /// The command or windows key.
+    pub logo: bool,
+}
+
+impl Chord {
+    pub fn new(key: KeyCode) -> Self {
+        Self {
+            key,
+            ctrl: false,
+            shift: false,
+            alt: false,
+            logo: false,
+        }
+    }
+
+    /// Shortcuts, whose characters are not text. Ctrl with alt is AltGr on Windows, which
+    /// types characters like `@` on many layouts.
+    fn is_shortcut(&self) -> bool {
+        self.logo || self.ctrl && !self.alt
+    }
+
+    /// Whether `c`, the next character received, was typed by this chord. Characters come
+    /// in after their keys, but not every key types one, and which one is up to the layout.
+    fn typed(&self, c: char) -> bool {
+        if c.is_control() {
+            return self.ctrl
+                || matches!(
+                    self.key,
+                    KeyCode::Return
+                        | KeyCode::NumpadEnter
+                        | KeyCode::Tab
+                        | KeyCode::Back
+                        | KeyCode::Escape
+                        | KeyCode::Delete
+                );
+        }
+        if !types_text(self.key) {
+            return false;
+        }
+        // Some platforms send the character of a shortcut and some don't, so it's only
+        // taken when it is the key's own.
+        !self.is_shortcut()
+            || key_name(self.key).is_some_and(|name| name == c.to_lowercase().to_string())
+    }
+}
+
+impl FromStr for Chord {
+    type Err = KeymapError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        // `ctrl++` binds the plus key itself.
+        let (modifiers, key) = match s.strip_suffix("++") {
+            _ if s == "+" => ("", "+"),
+            Some(modifiers) => (modifiers, "+"),
+            None => s.rsplit_once('+').unwrap_or(("", &s)),
+        };
+        let key = key_code(key).ok_or_else(|| KeymapError::UnknownKey(key.to_string()))?;
+        let mut chord = Chord::new(key);
+        for modifier in modifiers.split('+').filter(|m| !m.is_empty()) {
+            match modifier {
+                "ctrl" | "control" => chord.ctrl = true,
+                "shift" => chord.shift = true,
+                "alt" | "option" | "meta" => chord.alt = true,
+                "cmd" | "super" | "win" => chord.logo = true,
+                _ => return Err(KeymapError::UnknownModifier(modifier.to_string())),
+            }
+        }
+        Ok(chord)
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (set, name) in [
+            (self.ctrl, "ctrl+"),
+            (self.shift, "shift+"),
+            (self.alt, "alt+"),
+            (self.logo, "cmd+"),
+        ] {
+            if set {
+                write!(f, "{name}")?;
+            }
+        }
+        match key_name(self.key) {
+            Some(name) => write!(f, "{name}"),
+            None => write!(f, "{:?}", self.key),
+        }
+    }
+}
+
+/// Chords pressed one after the other, written like `ctrl+k ctrl+c`.
+pub fn parse_keys(s: &str) -> Result<Vec<Chord>, KeymapError> {
+    let keys = s
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+    if keys.is_empty() {
+        return Err(KeymapError::NoKeys);
+    }
+    Ok(keys)
+}
+
+fn show_keys(keys: &[Chord]) -> String {
+    let keys: Vec<String> = keys.iter().map(Chord::to_string).collect();
+    keys.join(" ")
+}
+
+const LETTERS: [KeyCode; 26] = [
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+];
+
+const DIGITS: [KeyCode; 10] = [
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+const FUNCTION_KEYS: [KeyCode; 12] = [
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+    KeyCode::F9,
+    KeyCode::F10,
+    KeyCode::F11,
+    KeyCode::F12,
+];
+
+/// Keys besides letters, digits and function keys. The first name of a key is shown.
+const NAMED_KEYS: &[(&str, KeyCode)] = &[
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+    ("insert", KeyCode::Insert),
+    ("delete", KeyCode::Delete),
+    ("backspace", KeyCode::Back),
+    ("enter", KeyCode::Return),
+    ("return", KeyCode::Return),
+    ("escape", KeyCode::Escape),
+    ("esc", KeyCode::Escape),
+    ("tab", KeyCode::Tab),
+    ("space", KeyCode::Space),
+    ("-", KeyCode::Minus),
+    ("=", KeyCode::Equals),
+    ("+", KeyCode::Plus),
+    (",", KeyCode::Comma),
+    (".", KeyCode::Period),
+    ("/", KeyCode::Slash),
+    ("\\", KeyCode::Backslash),
+    (";", KeyCode::Semicolon),
+    ("'", KeyCode::Apostrophe),
+    ("[", KeyCode::LBracket),
+    ("]", KeyCode::RBracket),
+    ("`", KeyCode::Grave),
+];
+
+fn key_code(name: &str) -> Option<KeyCode> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.clone().next()) {
+        match c {
+            'a'..='z' => return Some(LETTERS[c as usize - 'a' as usize]),
+            '0'..='9' => return Some(DIGITS[c as usize - '0' as usize]),
+            _ => {}
+        }
+    }
+    if let Some(n) = name.strip_prefix('f').and_then(|n| n.parse::<usize>().ok()) {
+        return FUNCTION_KEYS.get(n.checked_sub(1)?).copied();
+    }
+    NAMED_KEYS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, key)| *key)
+}
+
+pub(crate) fn key_name(key: KeyCode) -> Option<String> {
+    let index = |keys: &[KeyCode]| keys.iter().position(|k| *k == key);
+    if let Some(i) = index(&LETTERS) {
+        return Some(((b'a' + i as u8) as char).to_string());
+    }
+    if let Some(i) = index(&DIGITS) {
+        return Some(i.to_string());
+    }
+    if let Some(i) = index(&FUNCTION_KEYS) {
+        return Some(format!("f{}", i + 1));
+    }
+    NAMED_KEYS
+        .iter()
+        .find(|(_, k)| *k == key)
+        .map(|(name, _)| name.to_string())
+}
+
+/// Keys that move, delete or switch something rather than type a character.
+fn types_text(key: KeyCode) -> bool {
+    let other = matches!(
+        key,
+        KeyCode::Left
+            | KeyCode::Right
+            | KeyCode::Up
+            | KeyCode::Down
+            | KeyCode::Home
+            | KeyCode::End
+            | KeyCode::PageUp
+            | KeyCode::PageDown
+            | KeyCode::Insert
+            | KeyCode::Delete
+            | KeyCode::Back
+            | KeyCode::Return
+            | KeyCode::NumpadEnter
+            | KeyCode::Escape
+            | KeyCode::Tab
+            | KeyCode::Capital
+            | KeyCode::Numlock
+            | KeyCode::Scroll
+            | KeyCode::Snapshot
+            | KeyCode::Pause
+            | KeyCode::Apps
+    );
+    !other && !is_modifier(key) && !FUNCTION_KEYS.contains(&key)
+}
+
+fn is_modifier(key: KeyCode) -> bool {
+    matches!(
+        key,
+        KeyCode::LControl
+            | KeyCode::RControl
+            | KeyCode::LShift
+            | KeyCode::RShift
+            | KeyCode::LAlt
+            | KeyCode::RAlt
+            | KeyCode::LWin
+            | KeyCode::RWin
+    )
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum KeymapError {
+    NoKeys,
+    UnknownKey(String),
+    UnknownModifier(String),
+    UnknownMode(String),
+    /// The layer is not valid JSON.
+    Json(String),
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeymapError::NoKeys => write!(f, "no keys"),
+            KeymapError::UnknownKey(key) => write!(f, "unknown key `{key}`"),
+            KeymapError::UnknownModifier(m) => write!(f, "unknown modifier `{m}`"),
+            KeymapError::UnknownMode(mode) => write!(f, "unknown mode `{mode}`"),
+            KeymapError::Json(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for KeymapError {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Binding {
+    pub keys: Vec<Chord>,
+    /// None takes the keys away from the layers below.
+    pub command: Option<String>,
+    /// Only bound in this mode, e.g. the normal mode of vim.
+    pub mode: Option<ModeType>,
+}
+
+/// Bindings that override the ones of the layers below them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+    pub name: String,
+    pub bindings: Vec<Binding>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    Vim,
+    Emacs,
+}
+
+/// A binding as written in a keymap file, e.g.
+/// `{ "key": "ctrl+k ctrl+c", "command": "edit.copy" }`.
+#[derive(Deserialize)]
+struct RawBinding {
+    key: String,
+    command: Option<String>,
+    mode: Option<String>,
+}
+
+impl Layer {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            bindings: vec![],
+        }
+    }
+
+    /// Parses a JSON array of bindings. A null command unbinds its keys.
+    pub fn parse(name: impl Into<String>, source: &str) -> Result<Self, KeymapError> {
+        let raw: Vec<RawBinding> =
+            serde_json::from_str(source).map_err(|e| KeymapError::Json(e.to_string()))?;
+        let mut layer = Layer::new(name);
+        for binding in raw {
+            let mode = match binding.mode.as_deref() {
+                None => None,
+                Some("normal") => Some(ModeType::Normal),
+                Some("insert") => Some(ModeType::Insert),
+                Some("command") => Some(ModeType::Command),
+                Some("visual") => Some(ModeType::Visual),
+                Some(mode) => return Err(KeymapError::UnknownMode(mode.to_string())),
+            };
+            layer.bindings.push(Binding {
+                keys: parse_keys(&binding.key)?,
+                command: binding.command,
+                mode,
+            });
+        }
+        Ok(layer)
+    }
+
+    pub fn preset(preset: Preset) -> Self {
+        match preset {
+            Preset::Vim => vim(),
+            Preset::Emacs => emacs(),
+        }
+    }
+
+    /// Adds a built-in binding, whose keys are known to parse.
+    fn bind(mut self, keys: &str, command: &str) -> Self {
+        self.bindings.push(Binding {
+            keys: parse_keys(keys).expect("built-in keys parse"),
+            command: Some(command.to_string()),
+            mode: None,
+        });
+        self
+    }
+
+    fn bind_in(mut self, mode: ModeType, keys: &str, command: &str) -> Self {
+        self = self.bind(keys, command);
+        self.bindings.last_mut().unwrap().mode = Some(mode);
+        self
+    }
+}
+
+/// Bindings found in most editors.
+fn default_layer() -> Layer {
+    let mut layer = Layer::new("default");
+    for (key, movement) in [
+        ("left", "left"),
+        ("right", "right"),
+        ("up", "up"),
+        ("down", "down"),
+        ("ctrl+left", "wordLeft"),
+        ("ctrl+right", "wordRight"),
+        ("pageup", "pageUp"),
+        ("pagedown", "pageDown"),
+        ("home", "lineStart"),
+        ("end", "lineEnd"),
+        ("ctrl+home", "documentStart"),
+        ("ctrl+end", "documentEnd"),
+    ] {
+        layer = layer
+            .bind(key, &format!("cursor.{movement}"))
+            .bind(&format!("shift+{key}"), &format!("select.{movement}"));
+    }
+    layer
+        .bind("backspace", "delete.left")
+        .bind("delete", "delete.right")
+        .bind("ctrl+backspace", "delete.wordLeft")
+        .bind("ctrl+delete", "delete.wordRight")
+        .bind("enter", "edit.newline")
+        .bind("tab", "edit.tab")
+        .bind("ctrl+z", "edit.undo")
+        .bind("ctrl+shift+z", "edit.redo")
+        .bind("ctrl+y", "edit.redo")
+        .bind("ctrl+c", "edit.copy")
+        .bind("ctrl+x", "edit.cut")
+        .bind("ctrl+v", "edit.paste")
+        .bind("ctrl+d", "cursor.addNextOccurrence")
+        .bind("ctrl+alt+up", "cursor.addAbove")
+        .bind("ctrl+alt+down", "cursor.addBelow")
+        .bind("escape", "cursor.clearSecondary")
+        .bind("ctrl+space", "completion.trigger")
+        .bind("ctrl+shift+[", "fold.fold")
+        .bind("ctrl+shift+]", "fold.unfold")
+        .bind("ctrl+s", "file.save")
+        .bind("ctrl+w", "file.close")
+        .bind("ctrl+shift+t", "tab.reopenClosed")
+        .bind("f4", "location.next")
+        .bind("shift+f4", "location.previous")
+        .bind("ctrl+=", "view.zoomIn")
+        .bind("ctrl+-", "view.zoomOut")
+        .bind("ctrl+0", "view.zoomReset")
+}
+
+/// Motions and edits of the normal mode.
+fn vim() -> Layer {
+    let normal = ModeType::Normal;
+    Layer::new("vim")
+        .bind_in(normal, "h", "cursor.left")
+        .bind_in(normal, "j", "cursor.down")
+        .bind_in(normal, "k", "cursor.up")
+        .bind_in(normal, "l", "cursor.right")
+        .bind_in(normal, "w", "cursor.wordRight")
+        .bind_in(normal, "b", "cursor.wordLeft")
+        .bind_in(normal, "0", "cursor.lineStart")
+        .bind_in(normal, "shift+4", "cursor.lineEnd")
+        .bind_in(normal, "g g", "cursor.documentStart")
+        .bind_in(normal, "shift+g", "cursor.documentEnd")
+        .bind_in(normal, "ctrl+f", "cursor.pageDown")
+        .bind_in(normal, "ctrl+b", "cursor.pageUp")
+        .bind_in(normal, "x", "delete.right")
+        .bind_in(normal, "u", "edit.undo")
+        .bind_in(normal, "ctrl+r", "edit.redo")
+        .bind_in(normal, "ctrl+a", "number.increment")
+        .bind_in(normal, "ctrl+x", "number.decrement")
+        .bind_in(normal, "y y", "edit.copy")
+        .bind_in(normal, "d d", "edit.cut")
+        .bind_in(normal, "p", "edit.paste")
+        .bind_in(normal, "z a", "fold.toggle")
+        .bind_in(normal, "z c", "fold.fold")
+        .bind_in(normal, "z o", "fold.unfold")
+        .bind_in(normal, "z shift+m", "fold.foldAll")
+        .bind_in(normal, "z shift+r", "fold.unfoldAll")
+        .bind_in(ModeType::Insert, "ctrl+n", "completion.trigger")
+}
+
+fn emacs() -> Layer {
+    Layer::new("emacs")
+        .bind("ctrl+f", "cursor.right")
+        .bind("ctrl+b", "cursor.left")
+        .bind("ctrl+n", "cursor.down")
+        .bind("ctrl+p", "cursor.up")
+        .bind("alt+f", "cursor.wordRight")
+        .bind("alt+b", "cursor.wordLeft")
+        .bind("ctrl+a", "cursor.lineStart")
+        .bind("ctrl+e", "cursor.lineEnd")
+        .bind("alt+shift+,", "cursor.documentStart")
+        .bind("alt+shift+.", "cursor.documentEnd")
+        .bind("ctrl+v", "cursor.pageDown")
+        .bind("alt+v", "cursor.pageUp")
+        .bind("ctrl+d", "delete.right")
+        .bind("alt+backspace", "delete.wordLeft")
+        .bind("alt+d", "delete.wordRight")
+        .bind("ctrl+/", "edit.undo")
+        .bind("ctrl+shift+-", "edit.undo")
+        .bind("ctrl+w", "edit.cut")
+        .bind("alt+w", "edit.copy")
+        .bind("ctrl+y", "edit.paste")
+        .bind("ctrl+g", "cursor.clearSecondary")
+        .bind("alt+/", "completion.trigger")
+        .bind("ctrl+x ctrl+s", "file.save")
+        .bind("ctrl+x k", "file.close")
+}
+
+/// Layers from the bottom up, starting with the default bindings. Presets go right above
+/// them, user overrides and the layers of plugins on top.
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    layers: Vec<Layer>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            layers: vec![default_layer()],
+        }
+    }
+}
+
+/// What a sequence of keys is bound to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lookup<'a> {
+    Command(&'a str),
+    /// The start of longer sequences, so the next key decides.
+    Prefix,
+    Unbound,
+}
+
+impl Keymap {
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    /// Puts `layer` on top, or in place of the layer with the same name.
+    pub fn set_layer(&mut self, layer: Layer) {
+        match self.layers.iter_mut().find(|l| l.name == layer.name) {
+            Some(existing) => *existing = layer,
+            None => self.layers.push(layer),
+        }
+    }
+
+    /// Puts `layer` right above the default bindings, below the layers put on top, or in
+    /// place of the layer with the same name. For presets, which the bindings a user or a
+    /// plugin adds override.
+    pub fn set_base_layer(&mut self, layer: Layer) {
+        match self.layers.iter_mut().find(|l| l.name == layer.name) {
+            Some(existing) => *existing = layer,
+            None => self.layers.insert(1.min(self.layers.len()), layer),
+        }
+    }
+
+    pub fn remove_layer(&mut self, name: &str) {
+        self.layers.retain(|layer| layer.name != name);
+    }
+
+    /// What the topmost layer binding `keys`, or longer sequences starting with them,
+    /// binds them to. Later bindings of a layer win over earlier ones.
+    pub fn lookup(&self, keys: &[Chord], mode: Option<ModeType>) -> Lookup<'_> {
+        for layer in self.layers.iter().rev() {
+            let mut bindings = layer
+                .bindings
+                .iter()
+                .filter(|binding| binding.mode.is_none() || binding.mode == mode);
+            if let Some(binding) = bindings.clone().rev().find(|b| b.keys == keys) {
+                return match &binding.command {
+                    Some(command) => Lookup::Command(command),
+                    None => Lookup::Unbound,
+                };
+            }
+            let prefix = bindings.any(|binding| {
+                binding.command.is_some()
+                    && binding.keys.len() > keys.len()
+                    && binding.keys.starts_with(keys)
+            });
+            if prefix {
+                return Lookup::Prefix;
+            }
+        }
+        Lookup::Unbound
+    }
+
+    /// The keys running `command`, as shown next to it in menus.
+    pub fn keybinding(&self, command: &str, mode: Option<ModeType>) -> Option<String> {
+        self.layers
+            .iter()
+            .rev()
+            .flat_map(|layer| layer.bindings.iter().rev())
+            .filter(|binding| binding.command.as_deref() == Some(command))
+            .find(|binding| self.lookup(&binding.keys, mode) == Lookup::Command(command))
+            .map(|binding| show_keys(&binding.keys))
+    }
+}
+
+/// The chords of a sequence pressed so far.
+#[derive(Default)]
+struct PendingKeys(Vec<Chord>);
+
+/// A key pressed or a character typed, in the order they came in.
+#[derive(Clone, Copy, Debug)]
+enum KeyInput {
+    /// A key, with the character it typed if it typed one.
+    Press(Chord, Option<char>),
+    /// A character no key accounts for, e.g. one an input method composed.
+    Text(char),
+}
+
+/// Pairs the keys pressed in a frame with the characters received in it.
+fn key_inputs(chords: Vec<Chord>, characters: Vec<char>) -> Vec<KeyInput> {
+    let mut characters = characters.into_iter().peekable();
+    let mut inputs: Vec<KeyInput> = chords
+        .into_iter()
+        .map(|chord| KeyInput::Press(chord, characters.next_if(|c| chord.typed(*c))))
+        .collect();
+    inputs.extend(characters.map(KeyInput::Text));
+    inputs
+}
+
+/// Input after a command, read in the next frame. The command has taken effect by then,
+/// so text typed after it lands after it, in the mode it switched to.
+#[derive(Default)]
+struct DeferredKeys(Vec<KeyInput>);
+
+/// Runs the commands of bound keys and types the characters of the others into the focused
+/// document, in insert mode, in the order they came in. A command coming after typed text
+/// waits for the next frame, so the text is in before it runs. Control characters, e.g. of
+/// enter or backspace, are left to their bindings.
+fn match_keys(
+    (mut keys, mut characters): (EventReader<KeyboardInput>, EventReader<ReceivedCharacter>),
+    pressed: Res<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    modes: Query<&Mode>,
+    workspace: Res<Workspace>,
+    (mut pending, mut deferred): (ResMut<PendingKeys>, ResMut<DeferredKeys>),
+    (mut commands, mut types): (EventWriter<RunCommand>, EventWriter<TypeText>),
+) {
+    let held = |a, b| pressed.pressed(a) || pressed.pressed(b);
+    let chords = keys
+        .iter()
+        .filter_map(|e| match e.key_code {
+            Some(key) if e.state == ElementState::Pressed && !is_modifier(key) => Some(key),
+            _ => None,
+        })
+        .map(|key| Chord {
+            key,
+            ctrl: held(KeyCode::LControl, KeyCode::RControl),
+            shift: held(KeyCode::LShift, KeyCode::RShift),
+            alt: held(KeyCode::LAlt, KeyCode::RAlt),
+            logo: held(KeyCode::LWin, KeyCode::RWin),
+        })
+        .collect();
+    let characters = characters.iter().map(|e| e.char).collect();
+    let mut inputs = std::mem::take(&mut deferred.0);
+    inputs.extend(key_inputs(chords, characters));
+
+    let mode = modes.get_single().ok().map(|mode| mode.0);
+    let target = workspace
+        .active()
+        .filter(|_| mode == Some(ModeType::Insert));
+    let mut text = String::new();
+    for (i, input) in inputs.iter().enumerate() {
+        let (chord, typed) = match *input {
+            KeyInput::Press(chord, typed) => (chord, typed),
+            KeyInput::Text(c) => {
+                text.extend(Some(c).filter(|c| !c.is_control()));
+                continue;
+            }
+        };
+        pending.0.push(chord);
+        match keymap.lookup(&pending.0, mode) {
+            Lookup::Command(_) if target.is_some() && !text.is_empty() => {
+                pending.0.pop();
+                deferred.0 = inputs[i..].to_vec();
+                break;
+            }
+            Lookup::Command(command) => {
+                debug!("⌨️ {} runs {command}", show_keys(&pending.0));
+                commands.send(RunCommand {
+                    id: command.to_string(),
+                });
+                pending.0.clear();
+                deferred.0 = inputs[i + 1..].to_vec();
+                break;
+            }
+            Lookup::Prefix => debug!("⌨️ {} pressed, waiting", show_keys(&pending.0)),
+            Lookup::Unbound => {
+                if pending.0.len() > 1 {
+                    debug!("⌨️ {} is not bound", show_keys(&pending.0));
+                }
+                pending.0.clear();
+                if !chord.is_shortcut() {
+                    text.extend(typed.filter(|c| !c.is_control()));
+                }
+            }
+        }
+    }
+    if let (Some(entity), false) = (target, text.is_empty()) {
+        types.send(TypeText { entity, text });
+    }
+}
+
+/// Puts the preset of the `keymap.preset` setting on top of the default bindings, in place
+/// of the previous one, below the layers of users and plugins. The vim preset is left out
+/// with [`crate::vim::VimPlugin`] added, which reads vim keys itself.
+fn apply_preset(
+    settings: Option<Res<Settings>>,
+    vim: Option<Res<Vim>>,
+    mut keymap: ResMut<Keymap>,
+    mut applied: Local<Option<Preset>>,
+) {
+    let settings = match settings {
+        Some(settings) if settings.is_changed() => settings,
+        _ => return,
+    };
+    let preset = settings
+        .keymap_preset()
+        .filter(|preset| *preset != Preset::Vim || vim.is_none());
+    if preset == *applied {
+        return;
+    }
+    if let Some(previous) = applied.take() {
+        keymap.remove_layer(&Layer::preset(previous).name);
+    }
+    if let Some(preset) = preset {
+        debug!("⌨️ Using the {preset:?} preset");
+        keymap.set_base_layer(Layer::preset(preset));
+    }
+    *applied = preset;
+}
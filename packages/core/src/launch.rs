@@ -0,0 +1,535 @@
+use crate::{
+    diagnostics::{Diagnostic, PublishDiagnostics},
+    document::{same_file, Document, DocumentOpened, DocumentSaved},
+    process::{ProcessKind, ProcessSpec, SpawnProcess},
+    text_buffer::TextBuffer,
+    theme::Severity,
+    workspace::{Workspace, WorkspaceChanged},
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        schedule::{ParallelSystemDescriptorCoercion, SystemLabel},
+        system::{Local, Query, Res, ResMut},
+    },
+    log::{debug, warn},
+};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    env, fmt, fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+pub const LAUNCH_FILE: &str = ".dip/launch.json";
+/// The provider the problems of the launch file are published as.
+const DIAGNOSTICS_PROVIDER: &str = "launch";
+
+pub struct LaunchPlugin;
+
+impl Plugin for LaunchPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LaunchConfigurations>()
+            .add_event::<ReloadLaunchConfigurations>()
+            .add_event::<StartDebugging>()
+            .add_event::<DebugSessionRequested>()
+            .add_system(reload_launch_configurations.label(LoadLaunchFile))
+            .add_system(publish_launch_diagnostics.after(LoadLaunchFile))
+            .add_system(start_debugging)
+            .add_system(run_debug_sessions);
+    }
+}
+
+/// The system reading the launch file, for its problems to be published the same frame.
+#[derive(SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct LoadLaunchFile;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LaunchFile {
+    #[serde(default)]
+    pub configurations: Vec<LaunchConfiguration>,
+    #[serde(default)]
+    pub compounds: Vec<Compound>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LaunchConfiguration {
+    pub name: String,
+    /// Debug adapter type, e.g. `lldb` or `node`.
+    #[serde(rename = "type")]
+    pub adapter: String,
+    pub request: Request,
+    pub program: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub cwd: Option<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Adapter specific fields, passed through untouched.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Request {
+    Launch,
+    Attach,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Compound {
+    pub name: String,
+    pub configurations: Vec<String>,
+}
+
+/// Where a problem was found in the launch file, 0-based.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LaunchDiagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl LaunchDiagnostic {
+    /// From where it points in `buffer`, the launch file open, to the end of that line.
+    fn range(&self, buffer: &TextBuffer) -> Range<usize> {
+        if self.line >= buffer.line_count() {
+            return buffer.len()..buffer.len();
+        }
+        let line = buffer.line_range(self.line);
+        (line.start + self.column).min(line.end)..line.end
+    }
+}
+
+impl fmt::Display for LaunchDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line + 1, self.column + 1, self.message)
+    }
+}
+
+impl LaunchFile {
+    /// Parses and validates a launch file. Every problem is reported with its location in
+    /// `source` so it can be shown inline in the config file.
+    pub fn parse(source: &str) -> Result<Self, Vec<LaunchDiagnostic>> {
+        let file: LaunchFile = serde_json::from_str(source).map_err(|e| {
+            vec![LaunchDiagnostic {
+                line: e.line().saturating_sub(1),
+                column: e.column().saturating_sub(1),
+                message: e.to_string(),
+            }]
+        })?;
+
+        let diagnostics = file.validate(source);
+        if diagnostics.is_empty() {
+            Ok(file)
+        } else {
+            Err(diagnostics)
+        }
+    }
+
+    fn validate(&self, source: &str) -> Vec<LaunchDiagnostic> {
+        let mut diagnostics = vec![];
+        let mut seen = Vec::<&str>::new();
+
+        for config in &self.configurations {
+            // The configurations named the same before this one, to point at this one.
+            let nth = seen.iter().filter(|name| **name == config.name).count();
+            if nth > 0 {
+                diagnostics.push(diagnostic_at(
+                    source,
+                    Occurrence::Name(&config.name),
+                    nth,
+                    format!("duplicate configuration name `{}`", config.name),
+                ));
+            }
+            seen.push(&config.name);
+
+            if config.request == Request::Launch && config.program.is_none() {
+                diagnostics.push(diagnostic_at(
+                    source,
+                    Occurrence::Name(&config.name),
+                    nth,
+                    format!("`{}` is a launch request without a `program`", config.name),
+                ));
+            }
+        }
+
+        let mut referenced = Vec::<&str>::new();
+        for compound in &self.compounds {
+            for name in &compound.configurations {
+                let nth = referenced.iter().filter(|n| **n == name).count();
+                referenced.push(name);
+                if !self.configurations.iter().any(|c| &c.name == name) {
+                    diagnostics.push(diagnostic_at(
+                        source,
+                        Occurrence::Reference(name),
+                        nth,
+                        format!(
+                            "compound `{}` refers to unknown configuration `{name}`",
+                            compound.name
+                        ),
+                    ));
+                }
+            }
+        }
+
+        diagnostics
+    }
+
+    /// Resolves a configuration or compound by name into the configurations to start, in
+    /// the order the compound lists them.
+    pub fn resolve(
+        &self,
+        name: &str,
+        vars: &Variables,
+    ) -> Result<Vec<LaunchConfiguration>, InterpolationError> {
+        let names: Vec<&str> = match self.compounds.iter().find(|c| c.name == name) {
+            Some(compound) => compound.configurations.iter().map(String::as_str).collect(),
+            None => vec![name],
+        };
+
+        names
+            .iter()
+            .filter_map(|name| self.configurations.iter().find(|c| c.name == *name))
+            .map(|c| c.interpolate(vars))
+            .collect()
+    }
+}
+
+/// A string in the launch file to point at.
+#[derive(Clone, Copy)]
+enum Occurrence<'a> {
+    /// The value of a `name`.
+    Name(&'a str),
+    /// An entry of the `configurations` of a compound.
+    Reference(&'a str),
+}
+
+impl Occurrence<'_> {
+    /// Whether the string at `offset` of `source` is this one, given it has the same text.
+    fn is_at(self, source: &str, offset: usize) -> bool {
+        let before = source[..offset].trim_end();
+        match self {
+            Occurrence::Name(_) => before
+                .strip_suffix(':')
+                .is_some_and(|before| before.trim_end().ends_with("\"name\"")),
+            Occurrence::Reference(_) => {
+                source[..offset].contains("\"compounds\"") && before.ends_with(['[', ','])
+            }
+        }
+    }
+}
+
+/// Points at the `nth` occurrence of `value` in `source`, 0-based.
+fn diagnostic_at(source: &str, value: Occurrence, nth: usize, message: String) -> LaunchDiagnostic {
+    let (Occurrence::Name(text) | Occurrence::Reference(text)) = value;
+    let (line, column) = source
+        .match_indices(&format!("\"{text}\""))
+        .filter(|(offset, _)| value.is_at(source, *offset))
+        .nth(nth)
+        .map(|(offset, _)| {
+            let before = &source[..offset];
+            let line = before.matches('\n').count();
+            let column = offset - before.rfind('\n').map_or(0, |i| i + 1);
+            (line, column)
+        })
+        .unwrap_or_default();
+
+    LaunchDiagnostic {
+        line,
+        column,
+        message,
+    }
+}
+
+/// Values available to `${...}` variables.
+#[derive(Clone, Debug, Default)]
+pub struct Variables {
+    pub workspace_folder: PathBuf,
+    pub file: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum InterpolationError {
+    Unknown(String),
+    Unterminated,
+    NoActiveFile(String),
+}
+
+impl fmt::Display for InterpolationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterpolationError::Unknown(name) => write!(f, "unknown variable ${{{name}}}"),
+            InterpolationError::Unterminated => write!(f, "unterminated ${{"),
+            InterpolationError::NoActiveFile(name) => {
+                write!(f, "${{{name}}} needs an active file")
+            }
+        }
+    }
+}
+
+impl Variables {
+    /// Expands `${workspaceFolder}`, `${workspaceFolderBasename}`, `${file}`, `${fileBasename}`,
+    /// `${fileDirname}` and `${env:NAME}` in `value`.
+    pub fn interpolate(&self, value: &str) -> Result<String, InterpolationError> {
+        let mut out = String::with_capacity(value.len());
+        let mut rest = value;
+
+        while let Some(start) = rest.find("${") {
+            out.push_str(&rest[..start]);
+            let end = rest[start..]
+                .find('}')
+                .ok_or(InterpolationError::Unterminated)?;
+            out.push_str(&self.lookup(&rest[start + 2..start + end])?);
+            rest = &rest[start + end + 1..];
+        }
+        out.push_str(rest);
+
+        Ok(out)
+    }
+
+    fn lookup(&self, name: &str) -> Result<String, InterpolationError> {
+        if let Some(var) = name.strip_prefix("env:") {
+            return Ok(env::var(var).unwrap_or_default());
+        }
+
+        let file = || {
+            self.file
+                .as_deref()
+                .ok_or_else(|| InterpolationError::NoActiveFile(name.to_string()))
+        };
+        let display = |path: Option<&Path>| {
+            path.map(|p| p.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+
+        match name {
+            "workspaceFolder" => Ok(display(Some(&self.workspace_folder))),
+            "workspaceFolderBasename" => {
+                Ok(display(self.workspace_folder.file_name().map(Path::new)))
+            }
+            "file" => Ok(display(Some(file()?))),
+            "fileBasename" => Ok(display(file()?.file_name().map(Path::new))),
+            "fileDirname" => Ok(display(file()?.parent())),
+            _ => Err(InterpolationError::Unknown(name.to_string())),
+        }
+    }
+}
+
+impl LaunchConfiguration {
+    fn interpolate(&self, vars: &Variables) -> Result<Self, InterpolationError> {
+        let mut config = self.clone();
+        config.program = self
+            .program
+            .as_deref()
+            .map(|p| vars.interpolate(p))
+            .transpose()?;
+        config.cwd = self
+            .cwd
+            .as_deref()
+            .map(|p| vars.interpolate(p))
+            .transpose()?;
+        config.args = self
+            .args
+            .iter()
+            .map(|a| vars.interpolate(a))
+            .collect::<Result<_, _>>()?;
+        for value in config.env.values_mut() {
+            *value = vars.interpolate(value)?;
+        }
+
+        Ok(config)
+    }
+
+    /// The process running `program` with its arguments, working directory and
+    /// environment.
+    fn process(&self, program: &str) -> ProcessSpec {
+        ProcessSpec {
+            kind: ProcessKind::Debuggee,
+            name: self.name.clone(),
+            program: program.into(),
+            args: self.args.iter().map(Into::into).collect(),
+            piped: false,
+            cwd: self.cwd.as_ref().map(PathBuf::from),
+            env: self
+                .env
+                .iter()
+                .map(|(name, value)| (name.into(), value.into()))
+                .collect(),
+        }
+    }
+}
+
+/// The launch file of the first workspace root, which `${workspaceFolder}` stands for.
+#[derive(Debug, Default)]
+pub struct LaunchConfigurations {
+    /// None without a workspace root.
+    pub path: Option<PathBuf>,
+    pub file: LaunchFile,
+    pub diagnostics: Vec<LaunchDiagnostic>,
+    pub variables: Variables,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ReloadLaunchConfigurations;
+
+/// Starts the configuration or compound with the given name.
+#[derive(Clone, Debug)]
+pub struct StartDebugging(pub String);
+
+/// One resolved configuration for the debug adapter plugin to launch.
+#[derive(Clone, Debug)]
+pub struct DebugSessionRequested(pub LaunchConfiguration);
+
+fn load(launch: &mut LaunchConfigurations, workspace: &Workspace) {
+    let root = match workspace.roots().first() {
+        Some(root) => root.path.clone(),
+        None => {
+            *launch = LaunchConfigurations::default();
+            return;
+        }
+    };
+    let path = root.join(LAUNCH_FILE);
+    launch.variables.workspace_folder = root;
+    launch.path = Some(path.clone());
+
+    let source = match fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(_) => {
+            launch.file = LaunchFile::default();
+            launch.diagnostics.clear();
+            return;
+        }
+    };
+
+    match LaunchFile::parse(&source) {
+        Ok(file) => {
+            launch.file = file;
+            launch.diagnostics.clear();
+        }
+        Err(diagnostics) => {
+            for d in &diagnostics {
+                warn!("{}:{}", path.display(), d);
+            }
+            launch.diagnostics = diagnostics;
+        }
+    }
+}
+
+/// Loads the launch file on the first frame, once the workspace is open, and again when
+/// its roots change, it is saved or [`ReloadLaunchConfigurations`] asks.
+fn reload_launch_configurations(
+    mut loaded: Local<bool>,
+    (mut reloads, mut changed, mut saved): (
+        EventReader<ReloadLaunchConfigurations>,
+        EventReader<WorkspaceChanged>,
+        EventReader<DocumentSaved>,
+    ),
+    workspace: Res<Workspace>,
+    mut launch: ResMut<LaunchConfigurations>,
+) {
+    let path = launch.path.as_deref();
+    let saved = saved
+        .iter()
+        .filter(|e| path.is_some_and(|path| same_file(path, &e.path)))
+        .count();
+    if reloads.iter().count() + changed.iter().count() + saved > 0 || !*loaded {
+        *loaded = true;
+        load(&mut launch, &workspace);
+    }
+}
+
+/// Shows the problems of the launch file in it while it is open.
+fn publish_launch_diagnostics(
+    launch: Res<LaunchConfigurations>,
+    mut opened: EventReader<DocumentOpened>,
+    documents: Query<(Entity, &Document)>,
+    mut publish: EventWriter<PublishDiagnostics>,
+) {
+    let path = match &launch.path {
+        Some(path) => path,
+        None => return,
+    };
+    let reopened = opened.iter().filter(|e| same_file(&e.path, path)).count() > 0;
+    if !launch.is_changed() && !reopened {
+        return;
+    }
+
+    for (entity, document) in documents.iter() {
+        if !document.path().is_some_and(|p| same_file(p, path)) {
+            continue;
+        }
+        let diagnostics = launch
+            .diagnostics
+            .iter()
+            .map(|d| Diagnostic {
+                range: d.range(document.buffer()),
+                severity: Severity::Error,
+                message: d.message.clone(),
+                source: Some(DIAGNOSTICS_PROVIDER.to_string()),
+                code: None,
+            })
+            .collect();
+        publish.send(PublishDiagnostics {
+            entity,
+            provider: DIAGNOSTICS_PROVIDER.to_string(),
+            diagnostics,
+        });
+    }
+}
+
+fn start_debugging(
+    mut events: EventReader<StartDebugging>,
+    launch: Res<LaunchConfigurations>,
+    workspace: Res<Workspace>,
+    documents: Query<&Document>,
+    mut sessions: EventWriter<DebugSessionRequested>,
+) {
+    for StartDebugging(name) in events.iter() {
+        // `${file}` is the document in front.
+        let mut variables = launch.variables.clone();
+        variables.file = workspace
+            .active()
+            .and_then(|entity| documents.get(entity).ok())
+            .and_then(Document::path)
+            .map(PathBuf::from);
+
+        match launch.file.resolve(name, &variables) {
+            Ok(configs) if configs.is_empty() => warn!("No launch configuration named `{name}`"),
+            Ok(configs) => {
+                for config in configs {
+                    sessions.send(DebugSessionRequested(config));
+                }
+            }
+            Err(e) => warn!("Failed to resolve launch configuration `{name}`: {e}"),
+        }
+    }
+}
+
+/// No debug adapter is wired up yet, so a launch request runs its program without one, like
+/// running without debugging does, and an attach request is only reported.
+fn run_debug_sessions(
+    mut sessions: EventReader<DebugSessionRequested>,
+    mut spawn: EventWriter<SpawnProcess>,
+) {
+    for DebugSessionRequested(config) in sessions.iter() {
+        match (config.request, &config.program) {
+            (Request::Launch, Some(program)) => {
+                debug!("🐞 Running `{}` without a debugger", config.name);
+                spawn.send(SpawnProcess(config.process(program)));
+            }
+            // Turned down when the launch file was read.
+            (Request::Launch, None) => {}
+            (Request::Attach, _) => warn!(
+                "🐞 `{}` attaches through `{}`, which has no debug adapter yet",
+                config.name, config.adapter
+            ),
+        }
+    }
+}
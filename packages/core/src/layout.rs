@@ -0,0 +1,440 @@
+use crate::{
+    document::{Document, DocumentChanged, LineChange},
+    fold::Folds,
+    pipeline::{AppPipelineExt, EditorStage},
+    text_buffer::{Position, TextBuffer},
+    wrap::WrapCache,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        component::Component,
+        event::EventReader,
+        query::Changed,
+        schedule::{ParallelSystemDescriptorCoercion, SystemLabel},
+        system::Query,
+    },
+    tasks::TaskPool,
+};
+use std::{borrow::Cow, collections::BTreeMap, fmt, ops::Range, sync::Arc};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Tab stops of [`monospace`], in characters.
+const TAB_WIDTH: f32 = 4.;
+
+/// Lines laid out above and below the visible ones, so scrolling a little needs no layout.
+const MARGIN: usize = 50;
+
+pub struct LayoutPlugin;
+
+/// Systems bringing [`Layout`]s up to date with edits and folds, for the ones in
+/// [`EditorStage::Layout`] reading them to run after.
+#[derive(SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LayoutSet;
+
+impl Plugin for LayoutPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_editor_system(EditorStage::Layout, follow_edits.label(LayoutSet))
+            .add_editor_system(EditorStage::Layout, follow_folds.label(LayoutSet));
+    }
+}
+
+/// Width of a grapheme cluster as the view draws it, e.g. in pixels.
+pub type Measure = Arc<dyn Fn(&str) -> f32 + Send + Sync>;
+
+/// Every grapheme one `char_width` wide, a tab four.
+pub fn monospace(char_width: f32) -> Measure {
+    Arc::new(move |grapheme| match grapheme {
+        "\t" => char_width * TAB_WIDTH,
+        _ => char_width,
+    })
+}
+
+/// The rows one buffer line is wrapped into.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LineLayout {
+    /// Where each grapheme starts, by column, then where the line ends.
+    stops: Vec<f32>,
+    /// Column each row starts at, the first one at 0.
+    rows: Vec<usize>,
+}
+
+impl LineLayout {
+    /// Wraps `line` into rows no wider than `width`, after whitespace where possible.
+    /// Whitespace at the end of a row may hang past it. Without a width nothing wraps.
+    pub fn new(line: &str, width: Option<f32>, measure: &Measure) -> Self {
+        let mut stops = vec![];
+        let mut rows = vec![0];
+        let mut x = 0.;
+        let mut row_x = 0.;
+        // Column just after the last whitespace in the current row.
+        let mut candidate: Option<usize> = None;
+        for (column, grapheme) in line.graphemes(true).enumerate() {
+            let advance = measure(grapheme);
+            let blank = grapheme.chars().all(char::is_whitespace);
+            let row_start = *rows.last().expect("rows start with one");
+            stops.push(x);
+            if width.is_some_and(|width| x + advance - row_x > width)
+                && column > row_start
+                && !blank
+            {
+                let start = match candidate.take() {
+                    Some(candidate) if candidate > row_start => candidate,
+                    _ => column,
+                };
+                rows.push(start);
+                row_x = stops[start];
+            }
+            x += advance;
+            if blank {
+                candidate = Some(column + 1);
+            }
+        }
+        stops.push(x);
+        Self { stops, rows }
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub(crate) fn row_starts(&self) -> &[usize] {
+        &self.rows
+    }
+
+    /// Of the whole line, as if it did not wrap.
+    pub fn line_width(&self) -> f32 {
+        self.stops[self.stops.len() - 1]
+    }
+
+    fn column_count(&self) -> usize {
+        self.stops.len() - 1
+    }
+
+    /// Columns of `row`. A column where a row wraps belongs to the next one.
+    pub fn row_columns(&self, row: usize) -> Range<usize> {
+        let end = self
+            .rows
+            .get(row + 1)
+            .copied()
+            .unwrap_or(self.stops.len() - 1);
+        self.rows[row]..end
+    }
+
+    pub fn row_width(&self, row: usize) -> f32 {
+        let columns = self.row_columns(row);
+        self.stops[columns.end] - self.stops[columns.start]
+    }
+
+    /// The row showing `column`, which is past the end of the line for the last one.
+    pub fn row_of(&self, column: usize) -> usize {
+        self.rows.partition_point(|start| *start <= column) - 1
+    }
+
+    /// Where `column` is drawn within its row.
+    pub fn x_of(&self, column: usize) -> f32 {
+        let column = column.min(self.stops.len() - 1);
+        self.stops[column] - self.stops[self.rows[self.row_of(column)]]
+    }
+
+    /// The column of `row` nearest to `x`. Only the last row reaches the end of the line,
+    /// the others stop before the column they wrap at.
+    pub fn column_at(&self, row: usize, x: f32) -> usize {
+        let columns = self.row_columns(row.min(self.rows.len() - 1));
+        let last = match columns.end == self.stops.len() - 1 {
+            true => columns.end,
+            false => columns.end.saturating_sub(1).max(columns.start),
+        };
+        let start = self.stops[columns.start];
+        (columns.start..=last)
+            .min_by(|a, b| {
+                let a = (self.stops[*a] - start - x).abs();
+                let b = (self.stops[*b] - start - x).abs();
+                a.total_cmp(&b)
+            })
+            .unwrap_or(columns.start)
+    }
+}
+
+/// Lines of a document laid out into visual lines, kept up to date by the view showing it
+/// and following edits and folds on its own.
+///
+/// Only the lines around the visible ones are laid out. The others only count as the rows
+/// a [`WrapCache`] knows they wrap into, or as one row until it does. Laying out any of
+/// them later never moves the ones above.
+///
+/// Folded lines take no visual lines. A hidden line maps to the last one of the header
+/// folding it.
+#[derive(Component)]
+pub struct Layout {
+    width: Option<f32>,
+    measure: Measure,
+    wraps: WrapCache,
+    /// The lines around the visible ones, to draw and to move through.
+    lines: BTreeMap<usize, LineLayout>,
+    /// Visual lines before each line, then the total.
+    before: Vec<usize>,
+}
+
+impl fmt::Debug for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Layout")
+            .field("width", &self.width)
+            .field("lines", &self.wraps.len())
+            .field("laid_out", &self.lines.len())
+            .field("visual_lines", &self.visual_line_count())
+            .finish()
+    }
+}
+
+impl Layout {
+    /// Lays out nothing yet, every line counting as one row until [`Layout::lay_out`] or
+    /// [`Layout::rebuild`] gets to it.
+    pub fn new(buffer: &TextBuffer, width: Option<f32>, measure: Measure, folds: &Folds) -> Self {
+        let mut layout = Self {
+            width,
+            wraps: WrapCache::new(buffer.line_count(), width, measure.clone()),
+            measure,
+            lines: BTreeMap::new(),
+            before: vec![],
+        };
+        layout.count(folds);
+        layout
+    }
+
+    pub fn width(&self) -> Option<f32> {
+        self.width
+    }
+
+    /// Wraps lines at `width` from now on, e.g. after the view was resized. Lines narrower
+    /// than both widths keep their rows.
+    pub fn set_width(&mut self, width: Option<f32>, folds: &Folds) {
+        if self.width != width {
+            self.width = width;
+            self.wraps.set_width(width);
+            self.lines.clear();
+            self.count(folds);
+        }
+    }
+
+    /// Forgets every line, e.g. after the font changed `measure`.
+    pub fn set_measure(&mut self, measure: Measure, folds: &Folds) {
+        self.wraps = WrapCache::new(self.wraps.len(), self.width, measure.clone());
+        self.measure = measure;
+        self.lines.clear();
+        self.count(folds);
+    }
+
+    /// Whether it was laid out for a buffer of `line_count` lines, or is behind an edit.
+    pub fn is_current(&self, line_count: usize) -> bool {
+        self.wraps.len() == line_count
+    }
+
+    /// The layout of `line`, if it is around the visible ones.
+    pub fn line(&self, line: usize) -> Option<&LineLayout> {
+        self.lines.get(&line)
+    }
+
+    /// Whether the lines around `visible` are laid out.
+    pub fn is_laid_out(&self, visible: Range<usize>) -> bool {
+        around(visible, self.wraps.len()).all(|line| self.lines.contains_key(&line))
+    }
+
+    /// Lays out the lines around `visible` that aren't, and forgets the others.
+    pub fn lay_out(&mut self, buffer: &TextBuffer, visible: Range<usize>, folds: &Folds) {
+        let lines = around(visible, self.wraps.len().min(buffer.line_count()));
+        self.lines.retain(|line, _| lines.contains(line));
+        let mut moved = false;
+        for line in lines {
+            if self.lines.contains_key(&line) {
+                continue;
+            }
+            let text = buffer.get_line_content(line);
+            let layout = LineLayout::new(&text, self.width, &self.measure);
+            moved |= layout.row_count() != self.wraps.row_count(line);
+            self.wraps.record(line, &text, &layout);
+            self.lines.insert(line, layout);
+        }
+        if moved {
+            self.count(folds);
+        }
+    }
+
+    /// Whether lines off screen still count as one row for lack of their wraps.
+    pub fn needs_rebuild(&self) -> bool {
+        !self.wraps.is_complete() && !self.wraps.is_rebuilding()
+    }
+
+    /// Wraps the lines that are stale on `pool`, for [`Layout::receive`] to count.
+    pub fn rebuild(&mut self, pool: &TaskPool, buffer: &TextBuffer) {
+        self.wraps.rebuild_in_background(pool, buffer);
+    }
+
+    pub fn is_rebuilding(&self) -> bool {
+        self.wraps.is_rebuilding()
+    }
+
+    /// Counts the lines wrapped on the pool so far. Returns whether any were.
+    pub fn receive(&mut self, folds: &Folds) -> bool {
+        let received = self.wraps.receive();
+        if received {
+            self.count(folds);
+        }
+        received
+    }
+
+    /// The widest line known, as if none wrapped.
+    pub fn max_line_width(&self) -> f32 {
+        self.wraps.max_line_width()
+    }
+
+    pub fn visual_line_count(&self) -> usize {
+        self.before.last().copied().unwrap_or(0)
+    }
+
+    /// The visual lines `lines` take on screen, e.g. to find what to draw.
+    pub fn visual_lines(&self, lines: Range<usize>) -> Range<usize> {
+        let end = lines.end.min(self.wraps.len());
+        self.before[lines.start.min(end)]..self.before[end]
+    }
+
+    /// The line `visual` is a row of, clamped to the last one.
+    pub fn line_at(&self, visual: usize) -> usize {
+        let visual = visual.min(self.visual_line_count().saturating_sub(1));
+        (self.before.partition_point(|before| *before <= visual) - 1)
+            .min(self.wraps.len().saturating_sub(1))
+    }
+
+    /// The visual line showing `position` and where on it.
+    pub fn visual_position(&self, buffer: &TextBuffer, position: Position) -> (usize, f32) {
+        let line = position.line.min(self.wraps.len() - 1);
+        if self.before[line] == self.before[line + 1] {
+            return (self.before[line].saturating_sub(1), 0.);
+        }
+        let layout = self.line_layout(buffer, line);
+        let column = position.column.min(layout.column_count());
+        (
+            self.before[line] + layout.row_of(column),
+            layout.x_of(column),
+        )
+    }
+
+    /// The position shown nearest to `x` on `visual`, clamped to the last visual line.
+    pub fn position_at(&self, buffer: &TextBuffer, visual: usize, x: f32) -> Position {
+        let line = self.line_at(visual);
+        let row = visual.saturating_sub(self.before[line]);
+        Position::new(line, self.line_layout(buffer, line).column_at(row, x))
+    }
+
+    /// Where moving `rows` visual lines down from `position`, or up when negative, lands,
+    /// keeping to `goal` or else the current x. Returns the x to keep for the next move.
+    /// Moving past the first or last visual line goes to the start or end of the document.
+    pub fn moved(
+        &self,
+        buffer: &TextBuffer,
+        position: Position,
+        goal: Option<f32>,
+        rows: isize,
+    ) -> (Position, f32) {
+        let (visual, x) = self.visual_position(buffer, position);
+        let goal = goal.unwrap_or(x);
+        let target = visual as isize + rows;
+        if target < 0 {
+            return (Position::new(0, 0), goal);
+        }
+        if target as usize >= self.visual_line_count() {
+            let line = self.wraps.len() - 1;
+            let end = self.line_layout(buffer, line).column_count();
+            return (Position::new(line, end), goal);
+        }
+        (self.position_at(buffer, target as usize, goal), goal)
+    }
+
+    /// Follows the changes of one edit, made one after the other. The lines they touched
+    /// are laid out again once they are in view.
+    pub fn edited(&mut self, changes: impl IntoIterator<Item = LineChange>, folds: &Folds) {
+        for change in changes {
+            let end = (change.start + change.removed + 1).min(self.wraps.len());
+            let start = change.start.min(end);
+            let new_end = start + change.inserted + 1;
+            self.wraps.edited(start..end, change.inserted + 1);
+            let below = self.lines.split_off(&end);
+            self.lines.retain(|line, _| *line < start);
+            self.lines.extend(
+                below
+                    .into_iter()
+                    .map(|(line, layout)| (line - end + new_end, layout)),
+            );
+        }
+        self.count(folds);
+    }
+
+    /// Forgets every line, for a buffer of `line_count` lines.
+    fn reset(&mut self, line_count: usize, folds: &Folds) {
+        self.wraps = WrapCache::new(line_count, self.width, self.measure.clone());
+        self.lines.clear();
+        self.count(folds);
+    }
+
+    /// Counts the visual lines again after lines were folded or unfolded.
+    pub fn set_folds(&mut self, folds: &Folds) {
+        self.count(folds);
+    }
+
+    /// The layout of `line`, laid out now if it is off screen. Its rows are the ones it is
+    /// counted as, so it lands where the visual lines around it say.
+    fn line_layout(&self, buffer: &TextBuffer, line: usize) -> Cow<'_, LineLayout> {
+        if let Some(layout) = self.lines.get(&line) {
+            return Cow::Borrowed(layout);
+        }
+        let width = match self.wraps.rows(line) {
+            Some(_) => self.width,
+            None => None,
+        };
+        let text = buffer.get_line_content(line);
+        Cow::Owned(LineLayout::new(&text, width, &self.measure))
+    }
+
+    fn count(&mut self, folds: &Folds) {
+        let mut before = 0;
+        self.before = Vec::with_capacity(self.wraps.len() + 1);
+        for line in 0..self.wraps.len() {
+            self.before.push(before);
+            if !folds.is_hidden(line) {
+                before += self.wraps.row_count(line);
+            }
+        }
+        self.before.push(before);
+    }
+}
+
+/// `visible` and the margin around it, within `line_count` lines.
+fn around(visible: Range<usize>, line_count: usize) -> Range<usize> {
+    let end = visible.end.saturating_add(MARGIN).min(line_count);
+    visible.start.saturating_sub(MARGIN).min(end)..end
+}
+
+fn follow_edits(
+    mut changes: EventReader<DocumentChanged>,
+    mut documents: Query<(&Document, &mut Layout, Option<&Folds>)>,
+) {
+    let unfolded = Folds::default();
+    for e in changes.iter() {
+        if let Ok((document, mut layout, folds)) = documents.get_mut(e.entity) {
+            let folds = folds.unwrap_or(&unfolded);
+            let changes = e.changes.iter().map(|change| change.lines);
+            layout.edited(changes, folds);
+            // Edits arriving out of step with the buffer are caught up on at once.
+            let line_count = document.buffer().line_count();
+            if !layout.is_current(line_count) {
+                layout.reset(line_count, folds);
+            }
+        }
+    }
+}
+
+fn follow_folds(mut documents: Query<(&mut Layout, &Folds), Changed<Folds>>) {
+    for (mut layout, folds) in documents.iter_mut() {
+        layout.set_folds(folds);
+    }
+}
@@ -1,20 +1,162 @@
+pub mod align;
+pub mod announce;
+pub mod api;
+pub mod audit;
+pub mod calc;
+pub mod cli;
+pub mod clipboard;
+pub mod codec;
 pub mod command;
+pub mod completion;
+pub mod config;
+pub mod conflict;
+pub mod control;
+pub mod cursor;
+pub mod damage;
+pub mod diagnostics;
+pub mod diff;
+pub mod document;
+pub mod elevate;
+pub mod exclude;
+pub mod export;
+pub mod filter;
+pub mod fold;
+pub mod format;
+pub mod generate;
+pub mod grep_buffer;
+pub mod gutter;
+pub mod idle;
+pub mod increment;
+pub mod indent;
+pub mod journal;
+pub mod keymap;
+pub mod launch;
+pub mod layout;
+pub mod limbo;
+pub mod location_list;
+pub mod lsp;
+pub mod macros;
+pub mod markers;
+pub mod memory;
+pub mod minimap;
+pub mod pairs;
+pub mod permissions;
+pub mod pipeline;
+pub mod playback;
+pub mod presence;
+pub mod process;
+pub mod quotes;
+pub mod render;
+pub mod scaffold;
+pub mod scroll;
+pub mod search;
+pub mod session;
+pub mod shutdown;
+pub mod snippet;
+pub mod status_bar;
+pub mod stdin;
+pub mod symbol_index;
+pub mod syntax;
+pub mod tab;
+pub mod table;
+pub mod theme;
+pub mod toolchain;
+pub mod vault;
+pub mod vim;
+pub mod watcher;
+pub mod workspace;
+pub mod workspace_search;
+pub mod wrap;
+pub mod zoom;
 
+/// The text engine, a crate of its own so other projects can embed it without Bevy.
+pub use dip_text as text_buffer;
+
+/// Undo history of documents, kept with them in `dip_document`.
+pub use dip_document::{history, payload};
+/// Fuzzy matching for pickers, an editing behavior of `dip_editor` that needs no document.
+pub use dip_editor::fuzzy;
+
+use align::AlignPlugin;
+use announce::AnnouncePlugin;
+use api::ApiPlugin;
+use audit::AuditPlugin;
 use bevy::{
-    app::{App, AppExit, CoreStage, Plugin},
+    app::{App, Plugin},
     core::CorePlugin,
     ecs::{
         component::Component,
         event::{EventReader, EventWriter},
         query::{Changed, With},
-        system::{Commands, Query},
+        system::{Commands, Query, Res},
     },
     input::keyboard::{KeyCode, KeyboardInput},
     log::{debug, LogPlugin},
 };
-use command::{CoreCommand, UICommand};
+use calc::CalcPlugin;
+use cli::CliPlugin;
+use clipboard::ClipboardPlugin;
+use codec::CodecPlugin;
+use command::{CommandPlugin, CoreCommand, UICommand};
+use completion::CompletionPlugin;
+use config::ConfigPlugin;
+use conflict::ConflictPlugin;
+use control::ControlPlugin;
+use cursor::CursorPlugin;
+use damage::DamagePlugin;
+use diagnostics::DiagnosticsPlugin;
+use diff::DiffPlugin;
+use document::DocumentPlugin;
+use elevate::ElevatePlugin;
+use export::ExportPlugin;
+use filter::FilterPlugin;
+use fold::FoldPlugin;
+use format::FormatPlugin;
+use generate::GeneratePlugin;
+use grep_buffer::GrepBufferPlugin;
+use gutter::GutterPlugin;
+use idle::IdlePlugin;
+use increment::IncrementPlugin;
+use indent::IndentPlugin;
+use journal::JournalPlugin;
+use keymap::KeymapPlugin;
+use launch::LaunchPlugin;
+use layout::LayoutPlugin;
 use leafwing_input_manager::prelude::*;
+use limbo::LimboPlugin;
+use location_list::LocationListPlugin;
+use lsp::LspPlugin;
+use macros::MacroPlugin;
+use markers::MarkersPlugin;
+use memory::MemoryPlugin;
+use minimap::MinimapPlugin;
+use permissions::PermissionsPlugin;
+use pipeline::{AppPipelineExt, EditorStage, PipelinePlugin};
+use playback::PlaybackPlugin;
+use presence::PresencePlugin;
+use process::ProcessPlugin;
+use quotes::QuotesPlugin;
+use render::RenderPlugin;
+use scaffold::ScaffoldPlugin;
+use scroll::ScrollPlugin;
+use search::SearchPlugin;
+use session::SessionPlugin;
+use shutdown::ShutdownPlugin;
+use snippet::SnippetPlugin;
+use status_bar::StatusBarPlugin;
 use std::fs;
+use stdin::StdinPlugin;
+use symbol_index::SymbolIndexPlugin;
+use syntax::SyntaxPlugin;
+use tab::TabPlugin;
+use table::TablePlugin;
+use theme::{DesignTokens, ThemePlugin};
+use toolchain::ToolchainPlugin;
+use vault::VaultPlugin;
+use watcher::FileWatcherPlugin;
+use workspace::WorkspacePlugin;
+use workspace_search::WorkspaceSearchPlugin;
+use zoom::ZoomPlugin;
 
 pub struct DipCorePlugin;
 
@@ -23,6 +165,7 @@ pub enum ModeType {
     Normal,
     Insert,
     Command,
+    Visual,
 }
 
 #[derive(Component, Clone, Copy, Debug, PartialEq)]
@@ -42,16 +185,82 @@ enum Action {
 
 impl Plugin for DipCorePlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugin(LogPlugin::default())
+        app.add_plugin(LogPlugin)
             .add_plugin(InputManagerPlugin::<Action>::default())
-            .add_plugin(CorePlugin::default())
+            .add_plugin(CorePlugin)
+            .add_plugin(PipelinePlugin)
+            .add_plugin(LaunchPlugin)
+            .add_plugin(ToolchainPlugin)
+            .add_plugin(TabPlugin)
+            .add_plugin(ShutdownPlugin)
+            .add_plugin(ConfigPlugin)
+            .add_plugin(ProcessPlugin)
+            .add_plugin(MemoryPlugin)
+            .add_plugin(IdlePlugin)
+            .add_plugin(DocumentPlugin)
+            .add_plugin(WorkspaceSearchPlugin)
+            .add_plugin(SymbolIndexPlugin)
+            .add_plugin(WorkspacePlugin)
+            .add_plugin(LimboPlugin)
+            .add_plugin(DamagePlugin)
+            .add_plugin(DiffPlugin)
+            .add_plugin(ScaffoldPlugin)
+            .add_plugin(StdinPlugin)
+            .add_plugin(CliPlugin)
+            .add_plugin(IndentPlugin)
+            .add_plugin(CursorPlugin)
+            .add_plugin(FoldPlugin)
+            .add_plugin(LayoutPlugin)
+            .add_plugin(RenderPlugin)
+            .add_plugin(ScrollPlugin)
+            .add_plugin(GutterPlugin)
+            .add_plugin(MinimapPlugin)
+            .add_plugin(ControlPlugin)
+            .add_plugin(SearchPlugin)
+            .add_plugin(FormatPlugin)
+            .add_plugin(ZoomPlugin)
+            .add_plugin(QuotesPlugin)
+            .add_plugin(ThemePlugin)
+            .add_plugin(AnnouncePlugin)
+            .add_plugin(VaultPlugin)
+            .add_plugin(AuditPlugin)
+            .add_plugin(ConflictPlugin)
+            .add_plugin(FileWatcherPlugin)
+            .add_plugin(JournalPlugin)
+            .add_plugin(PlaybackPlugin)
+            .add_plugin(ExportPlugin)
+            .add_plugin(SessionPlugin)
+            .add_plugin(ElevatePlugin)
+            .add_plugin(GrepBufferPlugin)
+            .add_plugin(ClipboardPlugin)
+            .add_plugin(LocationListPlugin)
+            .add_plugin(KeymapPlugin)
+            .add_plugin(MacroPlugin)
+            .add_plugin(CommandPlugin)
+            .add_plugin(CalcPlugin)
+            .add_plugin(FilterPlugin)
+            .add_plugin(SyntaxPlugin)
+            .add_plugin(DiagnosticsPlugin)
+            .add_plugin(StatusBarPlugin)
+            .add_plugin(MarkersPlugin)
+            .add_plugin(PermissionsPlugin)
+            .add_plugin(PresencePlugin)
+            .add_plugin(LspPlugin)
+            .add_plugin(CompletionPlugin)
+            .add_plugin(AlignPlugin)
+            .add_plugin(TablePlugin)
+            .add_plugin(CodecPlugin)
+            .add_plugin(IncrementPlugin)
+            .add_plugin(GeneratePlugin)
+            .add_plugin(SnippetPlugin)
+            .add_plugin(ApiPlugin)
             .add_startup_system(spawn_user)
-            .add_system(handle_app_exit)
             .add_system(change_mode)
             .add_system(log_core_command)
             .add_system(log_keyboard_event_system)
             .add_startup_system(load_file)
-            .add_system_to_stage(CoreStage::PostUpdate, send_mode_change);
+            .add_editor_system(EditorStage::Present, send_mode_change)
+            .add_editor_system(EditorStage::Present, send_theme_change);
     }
 }
 
@@ -72,15 +281,6 @@ fn spawn_user(mut commands: Commands) {
         .insert(Mode::default());
 }
 
-fn handle_app_exit(mut events: EventReader<CoreCommand>, mut exit: EventWriter<AppExit>) {
-    for cmd in events.iter() {
-        match cmd {
-            CoreCommand::Exit => exit.send(AppExit),
-            _ => {}
-        }
-    }
-}
-
 fn load_file() {
     let data = fs::read_to_string("./README.md").expect("Failed to read file");
     println!("############################################");
@@ -95,18 +295,21 @@ fn log_keyboard_event_system(mut events: EventReader<KeyboardInput>) {
     }
 }
 
-fn log_core_command(mut events: EventReader<CoreCommand>, mut event: EventWriter<AppExit>) {
+fn log_core_command(mut events: EventReader<CoreCommand>) {
     for cmd in events.iter() {
         debug!("🧠 {:?}", cmd);
-
-        match cmd {
-            CoreCommand::Exit => event.send(AppExit),
-            _ => {}
-        }
     }
 }
 
-fn change_mode(mut query: Query<(&ActionState<Action>, &mut Mode), With<User>>) {
+/// Switches between insert and normal mode on `i` and escape, unless [`vim::VimPlugin`]
+/// was added, which reads these keys as part of its commands, e.g. `"iyy`.
+fn change_mode(
+    mut query: Query<(&ActionState<Action>, &mut Mode), With<User>>,
+    vim: Option<Res<vim::Vim>>,
+) {
+    if vim.is_some() {
+        return;
+    }
     let (action_state, mut mode) = query.single_mut();
     match mode.0 {
         ModeType::Normal => {
@@ -119,7 +322,13 @@ fn change_mode(mut query: Query<(&ActionState<Action>, &mut Mode), With<User>>)
                 mode.0 = ModeType::Normal;
             }
         }
-        ModeType::Command => {}
+        ModeType::Command | ModeType::Visual => {}
+    }
+}
+
+fn send_theme_change(mut ui: EventWriter<UICommand>, tokens: Res<DesignTokens>) {
+    if tokens.is_changed() {
+        ui.send(UICommand::ThemeChange(tokens.clone()));
     }
 }
 
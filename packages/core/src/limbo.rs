@@ -0,0 +1,90 @@
+//! Closed documents stay around for a while before they are despawned, so reopening one,
+//! e.g. with [`crate::tab::ReopenClosedTab`], gets its buffer and undo history back
+//! without reading the file, and language servers aren't told it closed when it comes
+//! right back. Documents with unsaved changes
+//! are never despawned here.
+
+use crate::{
+    document::{Document, DocumentOpened},
+    memory::MemoryPressure,
+};
+use bevy::{
+    app::{App, Plugin},
+    core::Time,
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::EventReader,
+        system::{Commands, Query, Res},
+    },
+    log::debug,
+};
+use std::time::Duration;
+
+pub struct LimboPlugin;
+
+impl Plugin for LimboPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LimboSettings>()
+            .add_system(revive_documents)
+            .add_system(free_closed_documents);
+    }
+}
+
+pub struct LimboSettings {
+    /// How long a closed document is kept. Memory pressure frees them all at once.
+    pub timeout: Duration,
+}
+
+impl Default for LimboSettings {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// A document that was closed, at `at` seconds since startup, and is no longer in the
+/// [`crate::workspace::Workspace`]. Only documents with a path go here, the others are
+/// despawned right away as nothing could open them again. So are documents closed with
+/// their changes thrown away.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Closed {
+    pub at: f64,
+}
+
+/// Opening a closed document's path finds the document, which sends [`DocumentOpened`].
+fn revive_documents(
+    mut commands: Commands,
+    mut opened: EventReader<DocumentOpened>,
+    closed: Query<&Closed>,
+) {
+    for e in opened.iter() {
+        if closed.get(e.entity).is_ok() {
+            debug!("🪦 Reopened {} from the closed documents", e.path.display());
+            commands.entity(e.entity).remove::<Closed>();
+        }
+    }
+}
+
+fn free_closed_documents(
+    mut commands: Commands,
+    (mut pressure, mut opened): (EventReader<MemoryPressure>, EventReader<DocumentOpened>),
+    settings: Res<LimboSettings>,
+    time: Res<Time>,
+    closed: Query<(Entity, &Closed, &Document)>,
+) {
+    let now = time.seconds_since_startup();
+    let pressed = pressure.iter().count() > 0;
+    // Still closed until the revival is applied.
+    let reopened: Vec<Entity> = opened.iter().map(|e| e.entity).collect();
+    for (entity, closed, document) in closed.iter() {
+        if reopened.contains(&entity) || document.is_dirty() {
+            continue;
+        }
+        if pressed || now - closed.at >= settings.timeout.as_secs_f64() {
+            debug!("🪦 Freed closed document {entity:?}");
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,317 @@
+use crate::{
+    announce::count,
+    command::{RegisterCommand, RunCommand},
+    control::RevealPosition,
+    document::{same_file, DocumentLoadFailed, DocumentOpened},
+    pipeline::{AppPipelineExt, EditorStage},
+    tab::PreviewDocument,
+    text_buffer::{Position, TextBuffer},
+    workspace_search::{FileMatches, SearchWorkspace, WorkspaceSearchFinished},
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        event::{EventReader, EventWriter},
+        system::{Local, ResMut},
+    },
+    log::{debug, warn},
+};
+use std::{fs, ops::Range, path::PathBuf};
+
+/// Lists kept to go back to, like the quickfix history of vim.
+const MAX_LISTS: usize = 10;
+
+const BOM: char = '\u{feff}';
+
+pub struct LocationListPlugin;
+
+impl Plugin for LocationListPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LocationLists>()
+            .add_event::<SetLocationList>()
+            .add_event::<GoToLocation>()
+            .add_event::<SwitchLocationList>()
+            .add_event::<LocationListChanged>()
+            .register_command("location.next", "Location", "Go to Next Location")
+            .register_command("location.previous", "Location", "Go to Previous Location")
+            .register_command("location.older", "Location", "Show Older List")
+            .register_command("location.newer", "Location", "Show Newer List")
+            .add_editor_system(EditorStage::Commands, run_location_commands)
+            .add_system(set_location_lists)
+            .add_system(collect_search_locations)
+            .add_system(go_to_locations)
+            .add_system(switch_location_lists)
+            .add_system(reveal_opened_locations);
+    }
+}
+
+/// A place in a file with something to say about it, e.g. a search match, a diagnostic,
+/// a problem found by a task or a reference.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub path: PathBuf,
+    pub range: Range<Position>,
+    pub message: String,
+    /// What found it, e.g. `search` or the name of a language server.
+    pub source: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct LocationList {
+    pub title: String,
+    pub locations: Vec<Location>,
+    /// The location gone to last.
+    current: Option<usize>,
+}
+
+impl LocationList {
+    pub fn new(title: impl Into<String>, locations: Vec<Location>) -> Self {
+        Self {
+            title: title.into(),
+            locations,
+            current: None,
+        }
+    }
+
+    pub fn current(&self) -> Option<&Location> {
+        self.locations.get(self.current?)
+    }
+
+    pub fn current_index(&self) -> Option<usize> {
+        self.current
+    }
+
+    /// Moves to the location `to` and returns it. Next and previous wrap around.
+    fn go(&mut self, to: GoToLocation) -> Option<&Location> {
+        let len = self.locations.len();
+        if len == 0 {
+            return None;
+        }
+        let index = match (to, self.current) {
+            (GoToLocation::Index(i), _) if i < len => i,
+            (GoToLocation::Index(_), _) => return None,
+            (GoToLocation::Next, Some(i)) => (i + 1) % len,
+            (GoToLocation::Next, None) => 0,
+            (GoToLocation::Previous, Some(i)) => (i + len - 1) % len,
+            (GoToLocation::Previous, None) => len - 1,
+        };
+        self.current = Some(index);
+        self.locations.get(index)
+    }
+}
+
+/// The lists made so far, oldest first, and the one navigation goes through.
+#[derive(Debug, Default)]
+pub struct LocationLists {
+    lists: Vec<LocationList>,
+    active: Option<usize>,
+    /// Locations waiting for their document to open.
+    reveals: Vec<(PathBuf, Position)>,
+}
+
+impl LocationLists {
+    pub fn lists(&self) -> &[LocationList] {
+        &self.lists
+    }
+
+    pub fn active(&self) -> Option<&LocationList> {
+        self.lists.get(self.active?)
+    }
+
+    /// Makes `list` the newest. Lists newer than the active one are dropped first, the
+    /// way a new page drops the forward history of a browser.
+    fn push(&mut self, list: LocationList) {
+        if let Some(active) = self.active {
+            self.lists.truncate(active + 1);
+        }
+        self.lists.push(list);
+        if self.lists.len() > MAX_LISTS {
+            self.lists.remove(0);
+        }
+        self.active = Some(self.lists.len() - 1);
+    }
+}
+
+/// Replaces the active list with a new one, keeping the old one in the history.
+#[derive(Clone, Debug)]
+pub struct SetLocationList(pub LocationList);
+
+/// Opens a location of the active list and moves the cursor there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GoToLocation {
+    Next,
+    Previous,
+    Index(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwitchLocationList {
+    Older,
+    Newer,
+}
+
+/// Sent when a list is added, switched to, or moved through.
+#[derive(Clone, Copy, Debug)]
+pub struct LocationListChanged;
+
+fn run_location_commands(
+    mut events: EventReader<RunCommand>,
+    mut goes: EventWriter<GoToLocation>,
+    mut switches: EventWriter<SwitchLocationList>,
+) {
+    for e in events.iter() {
+        match e.id.as_str() {
+            "location.next" => goes.send(GoToLocation::Next),
+            "location.previous" => goes.send(GoToLocation::Previous),
+            "location.older" => switches.send(SwitchLocationList::Older),
+            "location.newer" => switches.send(SwitchLocationList::Newer),
+            _ => {}
+        }
+    }
+}
+
+fn set_location_lists(
+    mut events: EventReader<SetLocationList>,
+    mut lists: ResMut<LocationLists>,
+    mut changed: EventWriter<LocationListChanged>,
+) {
+    for e in events.iter() {
+        debug!(
+            "📍 {}: {}",
+            e.0.title,
+            count(e.0.locations.len(), "location")
+        );
+        lists.push(e.0.clone());
+        changed.send(LocationListChanged);
+    }
+}
+
+/// Turns each workspace search into a list once it finishes.
+fn collect_search_locations(
+    mut searches: EventReader<SearchWorkspace>,
+    mut matches: EventReader<FileMatches>,
+    mut finished: EventReader<WorkspaceSearchFinished>,
+    mut found: Local<Vec<Location>>,
+    mut lists: ResMut<LocationLists>,
+    mut changed: EventWriter<LocationListChanged>,
+) {
+    if searches.iter().count() > 0 {
+        found.clear();
+    }
+    for file in matches.iter() {
+        found.extend(search_locations(file));
+    }
+    for e in finished.iter() {
+        let mut locations = std::mem::take(&mut *found);
+        locations.sort_by(|a, b| (&a.path, a.range.start).cmp(&(&b.path, b.range.start)));
+        let title = format!("Search: {}", e.query);
+        debug!("📍 {title}: {}", count(locations.len(), "location"));
+        lists.push(LocationList::new(title, locations));
+        changed.send(LocationListChanged);
+    }
+}
+
+fn search_locations(file: &FileMatches) -> Vec<Location> {
+    let text = match fs::read_to_string(&file.path) {
+        Ok(text) => text,
+        Err(error) => {
+            warn!("📍 Failed to read {}: {error}", file.path.display());
+            return vec![];
+        }
+    };
+    // Documents keep the byte order mark out of their buffer, so positions do as well.
+    let (text, skipped) = match text.strip_prefix(BOM) {
+        Some(text) => (text, BOM.len_utf8()),
+        None => (text.as_str(), 0),
+    };
+    let buffer = TextBuffer::from(text);
+    file.ranges
+        .iter()
+        .map(|range| {
+            let start = buffer.position_at(range.start.saturating_sub(skipped));
+            let end = buffer.position_at(range.end.saturating_sub(skipped));
+            Location {
+                path: file.path.clone(),
+                range: start..end,
+                message: buffer.get_line_content(start.line).trim().to_string(),
+                source: "search".to_string(),
+            }
+        })
+        .collect()
+}
+
+fn go_to_locations(
+    mut events: EventReader<GoToLocation>,
+    mut lists: ResMut<LocationLists>,
+    mut previews: EventWriter<PreviewDocument>,
+    mut changed: EventWriter<LocationListChanged>,
+) {
+    for e in events.iter() {
+        let lists = &mut *lists;
+        let location = match lists.active.and_then(|i| lists.lists.get_mut(i)) {
+            Some(list) => match list.go(*e) {
+                Some(location) => location.clone(),
+                None => continue,
+            },
+            None => continue,
+        };
+        debug!(
+            "📍 Going to {}:{}",
+            location.path.display(),
+            location.range.start.line + 1
+        );
+        lists
+            .reveals
+            .push((location.path.clone(), location.range.start));
+        // Stepping through the results reuses one tab.
+        previews.send(PreviewDocument {
+            path: location.path,
+        });
+        changed.send(LocationListChanged);
+    }
+}
+
+fn switch_location_lists(
+    mut events: EventReader<SwitchLocationList>,
+    mut lists: ResMut<LocationLists>,
+    mut changed: EventWriter<LocationListChanged>,
+) {
+    for e in events.iter() {
+        let active = match lists.active {
+            Some(active) => active,
+            None => continue,
+        };
+        let next = match e {
+            SwitchLocationList::Older => active.checked_sub(1),
+            SwitchLocationList::Newer => Some(active + 1).filter(|i| *i < lists.lists.len()),
+        };
+        if let Some(next) = next {
+            lists.active = Some(next);
+            debug!("📍 Back to {}", lists.lists[next].title);
+            changed.send(LocationListChanged);
+        }
+    }
+}
+
+fn reveal_opened_locations(
+    mut opened: EventReader<DocumentOpened>,
+    mut failed: EventReader<DocumentLoadFailed>,
+    mut lists: ResMut<LocationLists>,
+    mut reveal: EventWriter<RevealPosition>,
+) {
+    for e in opened.iter() {
+        lists.reveals.retain(|(path, position)| {
+            if !same_file(path, &e.path) {
+                return true;
+            }
+            reveal.send(RevealPosition {
+                entity: e.entity,
+                position: *position,
+            });
+            false
+        });
+    }
+    for e in failed.iter() {
+        lists.reveals.retain(|(path, _)| *path != e.path);
+    }
+}
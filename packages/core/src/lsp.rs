@@ -0,0 +1,946 @@
+mod rpc;
+mod sync;
+
+pub use sync::ChangeBatcher;
+
+use crate::{
+    diagnostics::{ClearDiagnostics, Diagnostic, PublishDiagnostics},
+    document::{Document, DocumentChanged, DocumentSaved, Utf16Position},
+    idle::throttled,
+    process::{ChildProcesses, ProcessId, ProcessKind, ProcessSpec},
+    shutdown::{AppShutdownExt, Shutdown, ShutdownStage},
+    theme::Severity,
+    toolchain::Toolchains,
+    workspace::Workspace,
+};
+use bevy::{
+    app::{App, Plugin},
+    core::Time,
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        query::Added,
+        schedule::{ParallelSystemDescriptorCoercion, SystemLabel},
+        system::{Local, Query, RemovedComponents, Res, ResMut},
+    },
+    log::{debug, warn},
+};
+use lsp_types::{
+    notification::{self, Notification},
+    request::{self, Request},
+    ClientCapabilities, ClientInfo, CompletionClientCapabilities, CompletionContext,
+    CompletionItemKind, CompletionParams, CompletionResponse, CompletionTextEdit,
+    CompletionTriggerKind, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, DidSaveTextDocumentParams, HoverClientCapabilities, HoverContents,
+    HoverParams, HoverProviderCapability, InitializeParams, InitializeResult, InitializedParams,
+    MarkedString, MarkupKind, PublishDiagnosticsClientCapabilities, PublishDiagnosticsParams,
+    ServerCapabilities, TextDocumentClientCapabilities, TextDocumentIdentifier, TextDocumentItem,
+    TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind,
+    TextDocumentSyncSaveOptions, Url, WorkspaceClientCapabilities, WorkspaceFolder,
+};
+use rpc::{Message, Transport};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::mpsc::TryRecvError,
+    time::Duration,
+};
+
+/// How long servers get to answer `shutdown` and exit before they are killed.
+const SHUTDOWN_GRACE_SECS: f64 = 2.0;
+const SHUTDOWN_PARTICIPANT: &str = "language servers";
+/// How often server messages are read while the user is idle, every frame otherwise.
+const IDLE_RECEIVE_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct LspPlugin;
+
+impl Plugin for LspPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LspSettings>()
+            .init_resource::<LanguageServers>()
+            .add_event::<ServerStarted>()
+            .add_event::<ServerFailed>()
+            .add_event::<RequestHover>()
+            .add_event::<HoverReady>()
+            .add_event::<RequestCompletion>()
+            .add_event::<CompletionsReady>()
+            .add_system(start_servers)
+            .add_system(
+                receive_messages
+                    .with_run_criteria(throttled(Duration::ZERO, IDLE_RECEIVE_INTERVAL)),
+            )
+            .add_system(sync_documents.label(SyncDocuments))
+            .add_system(send_requests.after(SyncDocuments))
+            .add_system(stop_servers_on_shutdown)
+            .add_shutdown_participant(ShutdownStage::StopServers, SHUTDOWN_PARTICIPANT);
+    }
+}
+
+#[derive(SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct SyncDocuments;
+
+/// Language servers to start for the documents they handle. The first server matching a
+/// document's extension gets it, one server process per workspace root.
+#[derive(Clone, Debug)]
+pub struct LspSettings {
+    pub servers: Vec<ServerConfig>,
+}
+
+impl Default for LspSettings {
+    fn default() -> Self {
+        Self {
+            servers: vec![
+                ServerConfig::new("rust-analyzer", "rust-analyzer", &[], &[("rs", "rust")]),
+                ServerConfig::new(
+                    "typescript-language-server",
+                    "typescript-language-server",
+                    &["--stdio"],
+                    &[
+                        ("ts", "typescript"),
+                        ("tsx", "typescriptreact"),
+                        ("js", "javascript"),
+                        ("jsx", "javascriptreact"),
+                        ("mjs", "javascript"),
+                        ("cjs", "javascript"),
+                    ],
+                ),
+            ],
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerConfig {
+    pub name: String,
+    pub command: String,
+    pub args: Vec<String>,
+    /// File extensions the server handles, with the language id each is opened as.
+    pub languages: Vec<(String, String)>,
+}
+
+impl ServerConfig {
+    pub fn new(name: &str, command: &str, args: &[&str], languages: &[(&str, &str)]) -> Self {
+        Self {
+            name: name.to_string(),
+            command: command.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            languages: languages
+                .iter()
+                .map(|(extension, id)| (extension.to_string(), id.to_string()))
+                .collect(),
+        }
+    }
+
+    pub fn language_id(&self, path: &Path) -> Option<&str> {
+        let extension = path.extension()?.to_str()?;
+        self.languages
+            .iter()
+            .find(|(e, _)| e.eq_ignore_ascii_case(extension))
+            .map(|(_, id)| id.as_str())
+    }
+}
+
+/// A server answered `initialize` and has the documents for its root open.
+#[derive(Clone, Debug)]
+pub struct ServerStarted {
+    pub name: String,
+    pub root: PathBuf,
+}
+
+/// A server failed to start or exited on its own. It is not started again for its root
+/// until the editor restarts, so a broken install does not respawn on every file opened.
+#[derive(Clone, Debug)]
+pub struct ServerFailed {
+    pub name: String,
+    pub root: PathBuf,
+    pub error: String,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RequestHover {
+    pub entity: Entity,
+    pub offset: usize,
+}
+
+/// Nothing is sent when the server has nothing to show.
+#[derive(Clone, Debug)]
+pub struct HoverReady {
+    pub entity: Entity,
+    pub offset: usize,
+    /// Markdown, with code blocks tagged by language.
+    pub contents: String,
+    /// The text the hover is about, if the server said.
+    pub range: Option<Range<usize>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RequestCompletion {
+    pub entity: Entity,
+    pub offset: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct CompletionsReady {
+    pub entity: Entity,
+    pub offset: usize,
+    /// In the order the server wants them shown.
+    pub items: Vec<CompletionItem>,
+    /// Typing further should ask again rather than filter these.
+    pub incomplete: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompletionItem {
+    pub label: String,
+    pub kind: Option<CompletionItemKind>,
+    pub detail: Option<String>,
+    /// Replaces `range` when accepted.
+    pub text: String,
+    pub range: Range<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ServerState {
+    Initializing,
+    Running,
+    ShuttingDown,
+}
+
+enum Pending {
+    Initialize,
+    Hover { entity: Entity, offset: usize },
+    Completion { entity: Entity, offset: usize },
+    Shutdown,
+}
+
+struct OpenDocument {
+    uri: Url,
+    /// The version sent with didOpen. Changes up to it are already in the server's copy.
+    opened: u64,
+    changes: ChangeBatcher,
+}
+
+struct Server {
+    name: String,
+    root: PathBuf,
+    process: ProcessId,
+    transport: Transport,
+    state: ServerState,
+    capabilities: ServerCapabilities,
+    next_id: i64,
+    pending: HashMap<i64, Pending>,
+    documents: HashMap<Entity, OpenDocument>,
+    /// Documents waiting for the server to initialize.
+    queued: Vec<Entity>,
+}
+
+impl Server {
+    fn spawn(
+        config: &ServerConfig,
+        root: &Path,
+        toolchains: &Toolchains,
+        processes: &mut ChildProcesses,
+    ) -> io::Result<Self> {
+        let spec = ProcessSpec {
+            kind: ProcessKind::LanguageServer,
+            name: config.name.clone(),
+            program: config.command.clone().into(),
+            args: config.args.iter().map(Into::into).collect(),
+            piped: true,
+            cwd: None,
+            env: vec![],
+        };
+        let process = processes.spawn(spec, toolchains)?;
+        let child = processes
+            .child_mut(process)
+            .expect("the process was just spawned");
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        let mut server = Self {
+            name: config.name.clone(),
+            root: root.to_path_buf(),
+            process,
+            transport: Transport::new(stdin, stdout),
+            state: ServerState::Initializing,
+            capabilities: ServerCapabilities::default(),
+            next_id: 0,
+            pending: HashMap::new(),
+            documents: HashMap::new(),
+            queued: vec![],
+        };
+        let uri = Url::from_directory_path(root).ok();
+        let folder = uri.clone().map(|uri| WorkspaceFolder {
+            uri,
+            name: root.file_name().map_or_else(
+                || root.display().to_string(),
+                |n| n.to_string_lossy().into(),
+            ),
+        });
+        server.request::<request::Initialize>(
+            InitializeParams {
+                process_id: Some(std::process::id()),
+                root_uri: uri,
+                capabilities: client_capabilities(),
+                workspace_folders: folder.map(|folder| vec![folder]),
+                client_info: Some(ClientInfo {
+                    name: "dip".to_string(),
+                    version: Some(env!("CARGO_PKG_VERSION").to_string()),
+                }),
+                ..Default::default()
+            },
+            Pending::Initialize,
+        );
+        Ok(server)
+    }
+
+    fn request<R: Request>(&mut self, params: R::Params, pending: Pending) {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.insert(id, pending);
+        let params = serde_json::to_value(params).expect("LSP params serialize");
+        self.transport.request(id, R::METHOD, params);
+    }
+
+    fn notify<N: Notification>(&self, params: N::Params) {
+        let params = serde_json::to_value(params).expect("LSP params serialize");
+        self.transport.notify(N::METHOD, params);
+    }
+
+    fn open(&mut self, entity: Entity, document: &Document, language_id: &str) {
+        let uri = match document.path().map(Url::from_file_path) {
+            Some(Ok(uri)) => uri,
+            _ => return,
+        };
+        debug!("🛰 {} opened {uri}", self.name);
+        self.notify::<notification::DidOpenTextDocument>(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem {
+                uri: uri.clone(),
+                language_id: language_id.to_string(),
+                version: document.version() as i32,
+                text: document.buffer().to_string(),
+            },
+        });
+        let changes = ChangeBatcher::new(uri.clone(), self.sync_kind(), document.version());
+        self.documents.insert(
+            entity,
+            OpenDocument {
+                uri,
+                opened: document.version(),
+                changes,
+            },
+        );
+    }
+
+    fn sync_kind(&self) -> TextDocumentSyncKind {
+        match &self.capabilities.text_document_sync {
+            Some(TextDocumentSyncCapability::Kind(kind)) => *kind,
+            Some(TextDocumentSyncCapability::Options(options)) => {
+                options.change.unwrap_or(TextDocumentSyncKind::NONE)
+            }
+            None => TextDocumentSyncKind::NONE,
+        }
+    }
+
+    fn wants_save(&self) -> bool {
+        match &self.capabilities.text_document_sync {
+            Some(TextDocumentSyncCapability::Options(options)) => matches!(
+                options.save,
+                Some(TextDocumentSyncSaveOptions::Supported(true))
+                    | Some(TextDocumentSyncSaveOptions::SaveOptions(_))
+            ),
+            _ => false,
+        }
+    }
+
+    fn flush_now(&mut self, entity: Entity, document: &Document) {
+        if let Some(params) = self
+            .documents
+            .get_mut(&entity)
+            .and_then(|open| open.changes.flush_now(document))
+        {
+            self.notify::<notification::DidChangeTextDocument>(params);
+        }
+    }
+
+    fn entity_for(&self, uri: &Url) -> Option<Entity> {
+        self.documents
+            .iter()
+            .find(|(_, open)| open.uri == *uri)
+            .map(|(entity, _)| *entity)
+    }
+
+    fn position(
+        &self,
+        entity: Entity,
+        document: &Document,
+        offset: usize,
+    ) -> Option<TextDocumentPositionParams> {
+        let open = self.documents.get(&entity)?;
+        let position = document.utf16_position(offset.min(document.buffer().len()));
+        Some(TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier {
+                uri: open.uri.clone(),
+            },
+            position: lsp_types::Position::new(position.line as u32, position.column as u32),
+        })
+    }
+}
+
+fn client_capabilities() -> ClientCapabilities {
+    ClientCapabilities {
+        text_document: Some(TextDocumentClientCapabilities {
+            hover: Some(HoverClientCapabilities {
+                content_format: Some(vec![MarkupKind::Markdown, MarkupKind::PlainText]),
+                ..Default::default()
+            }),
+            completion: Some(CompletionClientCapabilities::default()),
+            publish_diagnostics: Some(PublishDiagnosticsClientCapabilities {
+                version_support: Some(true),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        workspace: Some(WorkspaceClientCapabilities {
+            workspace_folders: Some(true),
+            configuration: Some(true),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Every running language server, and the ones that could not be run.
+#[derive(Default)]
+struct LanguageServers {
+    servers: Vec<Server>,
+    failed: HashSet<(String, PathBuf)>,
+}
+
+impl LanguageServers {
+    fn fail(&mut self, i: usize, error: String, failed: &mut EventWriter<ServerFailed>) -> Server {
+        let server = self.servers.remove(i);
+        warn!("🛰 {} failed: {error}", server.name);
+        self.failed
+            .insert((server.name.clone(), server.root.clone()));
+        failed.send(ServerFailed {
+            name: server.name.clone(),
+            root: server.root.clone(),
+            error,
+        });
+        server
+    }
+}
+
+fn start_servers(
+    documents: Query<(Entity, &Document), Added<Document>>,
+    (settings, workspace, toolchains): (Res<LspSettings>, Res<Workspace>, Res<Toolchains>),
+    mut processes: ResMut<ChildProcesses>,
+    mut servers: ResMut<LanguageServers>,
+    mut failed: EventWriter<ServerFailed>,
+) {
+    for (entity, document) in documents.iter() {
+        let path = match document.path() {
+            Some(path) => path,
+            None => continue,
+        };
+        let (config, language_id) = match settings
+            .servers
+            .iter()
+            .find_map(|config| Some((config, config.language_id(path)?)))
+        {
+            Some(found) => found,
+            None => continue,
+        };
+        let (root, toolchains) = match workspace.root_for(path) {
+            Some(root) => (root.path.clone(), root.toolchains.clone()),
+            None => {
+                let root = path.parent().unwrap_or(path).to_path_buf();
+                let toolchains = Toolchains {
+                    root: root.clone(),
+                    ..toolchains.clone()
+                };
+                (root, toolchains)
+            }
+        };
+        if servers
+            .failed
+            .contains(&(config.name.clone(), root.clone()))
+        {
+            continue;
+        }
+
+        let i = match servers
+            .servers
+            .iter()
+            .position(|s| s.name == config.name && s.root == root)
+        {
+            Some(i) => i,
+            None => match Server::spawn(config, &root, &toolchains, &mut processes) {
+                Ok(server) => {
+                    debug!("🛰 Starting {} in {}", config.name, root.display());
+                    servers.servers.push(server);
+                    servers.servers.len() - 1
+                }
+                Err(e) => {
+                    warn!("🛰 Failed to start {}: {e}", config.name);
+                    servers.failed.insert((config.name.clone(), root.clone()));
+                    failed.send(ServerFailed {
+                        name: config.name.clone(),
+                        root,
+                        error: e.to_string(),
+                    });
+                    continue;
+                }
+            },
+        };
+        let server = &mut servers.servers[i];
+        match server.state {
+            ServerState::Running => server.open(entity, document, language_id),
+            ServerState::Initializing => server.queued.push(entity),
+            ServerState::ShuttingDown => {}
+        }
+    }
+}
+
+fn receive_messages(
+    mut servers: ResMut<LanguageServers>,
+    documents: Query<&Document>,
+    (settings, mut processes): (Res<LspSettings>, ResMut<ChildProcesses>),
+    (mut started, mut failed): (EventWriter<ServerStarted>, EventWriter<ServerFailed>),
+    (mut hovers, mut completions): (EventWriter<HoverReady>, EventWriter<CompletionsReady>),
+    (mut published, mut cleared): (
+        EventWriter<PublishDiagnostics>,
+        EventWriter<ClearDiagnostics>,
+    ),
+) {
+    let mut i = 0;
+    while i < servers.servers.len() {
+        let server = &mut servers.servers[i];
+        let message = match server.transport.try_recv() {
+            Ok(message) => message,
+            Err(TryRecvError::Empty) => {
+                i += 1;
+                continue;
+            }
+            Err(TryRecvError::Disconnected) => {
+                if server.state == ServerState::ShuttingDown {
+                    debug!("🛰 {} exited", server.name);
+                    servers.servers.remove(i);
+                } else {
+                    // What it reported can no longer be kept up to date.
+                    let server = servers.fail(i, "The server exited".to_string(), &mut failed);
+                    for entity in server.documents.keys() {
+                        cleared.send(ClearDiagnostics {
+                            entity: *entity,
+                            provider: Some(server.name.clone()),
+                        });
+                    }
+                }
+                continue;
+            }
+        };
+
+        match message {
+            Message::Request { id, method, params } => {
+                let result = match method.as_str() {
+                    // No settings of our own, the server falls back to its defaults.
+                    request::WorkspaceConfiguration::METHOD => {
+                        let items = params["items"].as_array().map_or(0, Vec::len);
+                        Value::Array(vec![Value::Null; items])
+                    }
+                    request::ApplyWorkspaceEdit::METHOD => json!({ "applied": false }),
+                    _ => Value::Null,
+                };
+                server.transport.respond(id, result);
+            }
+            Message::Notification { method, params } => match method.as_str() {
+                notification::PublishDiagnostics::METHOD => {
+                    let params: PublishDiagnosticsParams = match serde_json::from_value(params) {
+                        Ok(params) => params,
+                        Err(e) => {
+                            warn!("🛰 {} sent invalid diagnostics: {e}", server.name);
+                            continue;
+                        }
+                    };
+                    let entity = match server.entity_for(&params.uri) {
+                        Some(entity) => entity,
+                        None => continue,
+                    };
+                    let document = match documents.get(entity) {
+                        Ok(document) => document,
+                        Err(_) => continue,
+                    };
+                    published.send(PublishDiagnostics {
+                        entity,
+                        provider: server.name.clone(),
+                        diagnostics: diagnostics(document, params.diagnostics),
+                    });
+                }
+                notification::LogMessage::METHOD | notification::ShowMessage::METHOD => {
+                    if let Some(message) = params["message"].as_str() {
+                        debug!("🛰 {}: {message}", server.name);
+                    }
+                }
+                _ => {}
+            },
+            Message::Response { id, result } => {
+                let pending = match server.pending.remove(&id) {
+                    Some(pending) => pending,
+                    None => continue,
+                };
+                let result = match result {
+                    Ok(result) => result,
+                    Err(error) if matches!(pending, Pending::Initialize) => {
+                        let server = servers.fail(i, error, &mut failed);
+                        let _ = processes.kill(server.process);
+                        continue;
+                    }
+                    Err(error) => {
+                        debug!("🛰 {} returned an error: {error}", server.name);
+                        continue;
+                    }
+                };
+                match pending {
+                    Pending::Initialize => {
+                        let capabilities = serde_json::from_value::<InitializeResult>(result)
+                            .map(|result| result.capabilities)
+                            .unwrap_or_default();
+                        server.capabilities = capabilities;
+                        server.state = ServerState::Running;
+                        server.notify::<notification::Initialized>(InitializedParams {});
+                        debug!("🛰 {} initialized", server.name);
+                        for entity in std::mem::take(&mut server.queued) {
+                            let document = match documents.get(entity) {
+                                Ok(document) => document,
+                                Err(_) => continue,
+                            };
+                            let language_id = document.path().and_then(|path| {
+                                settings
+                                    .servers
+                                    .iter()
+                                    .find(|config| config.name == server.name)?
+                                    .language_id(path)
+                            });
+                            if let Some(language_id) = language_id {
+                                server.open(entity, document, language_id);
+                            }
+                        }
+                        started.send(ServerStarted {
+                            name: server.name.clone(),
+                            root: server.root.clone(),
+                        });
+                    }
+                    Pending::Hover { entity, offset } => {
+                        let hover = match serde_json::from_value::<Option<lsp_types::Hover>>(result)
+                        {
+                            Ok(Some(hover)) => hover,
+                            _ => continue,
+                        };
+                        let document = match documents.get(entity) {
+                            Ok(document) => document,
+                            Err(_) => continue,
+                        };
+                        let contents = hover_contents(hover.contents);
+                        if contents.trim().is_empty() {
+                            continue;
+                        }
+                        hovers.send(HoverReady {
+                            entity,
+                            offset,
+                            contents,
+                            range: hover.range.map(|range| byte_range(document, range)),
+                        });
+                    }
+                    Pending::Completion { entity, offset } => {
+                        let response =
+                            match serde_json::from_value::<Option<CompletionResponse>>(result) {
+                                Ok(Some(response)) => response,
+                                _ => continue,
+                            };
+                        let document = match documents.get(entity) {
+                            Ok(document) => document,
+                            Err(_) => continue,
+                        };
+                        let (items, incomplete) = match response {
+                            CompletionResponse::Array(items) => (items, false),
+                            CompletionResponse::List(list) => (list.items, list.is_incomplete),
+                        };
+                        completions.send(CompletionsReady {
+                            entity,
+                            offset,
+                            items: completion_items(document, offset, items),
+                            incomplete,
+                        });
+                    }
+                    Pending::Shutdown => server.notify::<notification::Exit>(()),
+                }
+            }
+        }
+    }
+}
+
+fn diagnostics(document: &Document, diagnostics: Vec<lsp_types::Diagnostic>) -> Vec<Diagnostic> {
+    diagnostics
+        .into_iter()
+        .map(|d| Diagnostic {
+            range: byte_range(document, d.range),
+            // Servers leaving it out mean errors in practice.
+            severity: d.severity.map_or(Severity::Error, Severity::from),
+            message: d.message,
+            source: d.source,
+            code: d.code.map(|code| match code {
+                lsp_types::NumberOrString::Number(n) => n.to_string(),
+                lsp_types::NumberOrString::String(s) => s,
+            }),
+        })
+        .collect()
+}
+
+fn hover_contents(contents: HoverContents) -> String {
+    let marked = |marked: MarkedString| match marked {
+        MarkedString::String(text) => text,
+        MarkedString::LanguageString(code) => format!("```{}\n{}\n```", code.language, code.value),
+    };
+    match contents {
+        HoverContents::Scalar(contents) => marked(contents),
+        HoverContents::Array(contents) => contents
+            .into_iter()
+            .map(marked)
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        HoverContents::Markup(contents) => contents.value,
+    }
+}
+
+fn completion_items(
+    document: &Document,
+    offset: usize,
+    mut items: Vec<lsp_types::CompletionItem>,
+) -> Vec<CompletionItem> {
+    items.sort_by(|a, b| {
+        let key = |item: &lsp_types::CompletionItem| {
+            item.sort_text.clone().unwrap_or_else(|| item.label.clone())
+        };
+        key(a).cmp(&key(b))
+    });
+    // Without an edit of its own an item replaces the word typed so far.
+    let word = word_start(document, offset)..offset;
+    items
+        .into_iter()
+        .map(|item| {
+            let (text, range) = match item.text_edit {
+                Some(CompletionTextEdit::Edit(edit)) => {
+                    (edit.new_text, byte_range(document, edit.range))
+                }
+                Some(CompletionTextEdit::InsertAndReplace(edit)) => {
+                    (edit.new_text, byte_range(document, edit.replace))
+                }
+                None => (
+                    item.insert_text.unwrap_or_else(|| item.label.clone()),
+                    word.clone(),
+                ),
+            };
+            CompletionItem {
+                label: item.label,
+                kind: item.kind,
+                detail: item.detail,
+                text,
+                range,
+            }
+        })
+        .collect()
+}
+
+fn word_start(document: &Document, offset: usize) -> usize {
+    let buffer = document.buffer();
+    let start = buffer.line_start(buffer.line_at(offset));
+    let line = buffer.text_in(start..offset);
+    let word = line
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_alphanumeric() || *c == '_')
+        .last();
+    start + word.map_or(line.len(), |(i, _)| i)
+}
+
+fn byte_range(document: &Document, range: lsp_types::Range) -> Range<usize> {
+    let offset = |position: lsp_types::Position| {
+        document.offset_at_utf16(Utf16Position {
+            line: position.line as usize,
+            column: position.character as usize,
+        })
+    };
+    let start = offset(range.start);
+    start..offset(range.end).max(start)
+}
+
+fn sync_documents(
+    time: Res<Time>,
+    (mut changes, mut saved): (EventReader<DocumentChanged>, EventReader<DocumentSaved>),
+    removed: RemovedComponents<Document>,
+    documents: Query<&Document>,
+    mut servers: ResMut<LanguageServers>,
+) {
+    let now = time.seconds_since_startup();
+    for e in changes.iter() {
+        for server in &mut servers.servers {
+            if let Some(open) = server.documents.get_mut(&e.entity) {
+                if e.version > open.opened {
+                    open.changes.push(&e.changes, e.version, now);
+                }
+            }
+        }
+    }
+
+    for server in &mut servers.servers {
+        let mut notifications: Vec<DidChangeTextDocumentParams> = vec![];
+        for (entity, open) in &mut server.documents {
+            if let Some(params) = documents
+                .get(*entity)
+                .ok()
+                .and_then(|document| open.changes.flush(document, now))
+            {
+                notifications.push(params);
+            }
+        }
+        for params in notifications {
+            server.notify::<notification::DidChangeTextDocument>(params);
+        }
+    }
+
+    for e in saved.iter() {
+        for server in &mut servers.servers {
+            if !server.wants_save() {
+                continue;
+            }
+            if let (Some(open), Ok(document)) =
+                (server.documents.get(&e.entity), documents.get(e.entity))
+            {
+                let uri = open.uri.clone();
+                server.flush_now(e.entity, document);
+                server.notify::<notification::DidSaveTextDocument>(DidSaveTextDocumentParams {
+                    text_document: TextDocumentIdentifier { uri },
+                    text: None,
+                });
+            }
+        }
+    }
+
+    for entity in removed.iter() {
+        for server in &mut servers.servers {
+            server.queued.retain(|queued| *queued != entity);
+            if let Some(open) = server.documents.remove(&entity) {
+                debug!("🛰 {} closed {}", server.name, open.uri);
+                server.notify::<notification::DidCloseTextDocument>(DidCloseTextDocumentParams {
+                    text_document: TextDocumentIdentifier { uri: open.uri },
+                });
+            }
+        }
+    }
+}
+
+fn send_requests(
+    (mut hovers, mut completions): (EventReader<RequestHover>, EventReader<RequestCompletion>),
+    documents: Query<&Document>,
+    mut servers: ResMut<LanguageServers>,
+) {
+    let requests = hovers
+        .iter()
+        .map(|e| (e.entity, e.offset, true))
+        .chain(completions.iter().map(|e| (e.entity, e.offset, false)));
+    for (entity, offset, hover) in requests {
+        let document = match documents.get(entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let server = match servers
+            .servers
+            .iter_mut()
+            .find(|s| s.state == ServerState::Running && s.documents.contains_key(&entity))
+        {
+            Some(server) => server,
+            None => continue,
+        };
+        let supported = if hover {
+            !matches!(
+                server.capabilities.hover_provider,
+                None | Some(HoverProviderCapability::Simple(false))
+            )
+        } else {
+            server.capabilities.completion_provider.is_some()
+        };
+        if !supported {
+            continue;
+        }
+
+        // Positions refer to the document as it is now.
+        server.flush_now(entity, document);
+        let position = match server.position(entity, document, offset) {
+            Some(position) => position,
+            None => continue,
+        };
+        if hover {
+            server.request::<request::HoverRequest>(
+                HoverParams {
+                    text_document_position_params: position,
+                    work_done_progress_params: Default::default(),
+                },
+                Pending::Hover { entity, offset },
+            );
+        } else {
+            server.request::<request::Completion>(
+                CompletionParams {
+                    text_document_position: position,
+                    work_done_progress_params: Default::default(),
+                    partial_result_params: Default::default(),
+                    context: Some(CompletionContext {
+                        trigger_kind: CompletionTriggerKind::INVOKED,
+                        trigger_character: None,
+                    }),
+                },
+                Pending::Completion { entity, offset },
+            );
+        }
+    }
+}
+
+fn stop_servers_on_shutdown(
+    time: Res<Time>,
+    mut asked_at: Local<Option<f64>>,
+    mut shutdown: ResMut<Shutdown>,
+    mut servers: ResMut<LanguageServers>,
+    mut processes: ResMut<ChildProcesses>,
+) {
+    if !shutdown.is_pending(SHUTDOWN_PARTICIPANT) {
+        return;
+    }
+
+    let now = time.seconds_since_startup();
+    let asked = *asked_at.get_or_insert_with(|| {
+        // A server still initializing cannot be asked to shut down yet.
+        servers.servers.retain(|server| {
+            if server.state == ServerState::Initializing {
+                let _ = processes.kill(server.process);
+            }
+            server.state != ServerState::Initializing
+        });
+        for server in &mut servers.servers {
+            debug!("🛰 Shutting down {}", server.name);
+            server.request::<request::Shutdown>((), Pending::Shutdown);
+            server.state = ServerState::ShuttingDown;
+        }
+        now
+    });
+
+    // Servers leave the list as they exit.
+    if servers.servers.is_empty() || now - asked >= SHUTDOWN_GRACE_SECS {
+        for server in servers.servers.drain(..) {
+            let _ = processes.kill(server.process);
+        }
+        shutdown.complete(SHUTDOWN_PARTICIPANT);
+    }
+}
@@ -0,0 +1,157 @@
+use serde_json::{json, Value};
+use std::{
+    io::{self, BufRead, Write},
+    process::{ChildStdin, ChildStdout},
+    sync::{
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Mutex,
+    },
+    thread,
+};
+
+/// A message from a server, split by the JSON-RPC fields it carries.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Message {
+    Request {
+        id: Value,
+        method: String,
+        params: Value,
+    },
+    Response {
+        id: i64,
+        result: Result<Value, String>,
+    },
+    Notification {
+        method: String,
+        params: Value,
+    },
+}
+
+impl Message {
+    fn parse(mut value: Value) -> Option<Self> {
+        let method = value
+            .get("method")
+            .and_then(Value::as_str)
+            .map(String::from);
+        let params = value
+            .get_mut("params")
+            .map(Value::take)
+            .unwrap_or(Value::Null);
+        match (method, value.get_mut("id").map(Value::take)) {
+            (Some(method), Some(id)) => Some(Message::Request { id, method, params }),
+            (Some(method), None) => Some(Message::Notification { method, params }),
+            (None, Some(id)) => {
+                let result = match value.get("error") {
+                    Some(error) => Err(error
+                        .get("message")
+                        .and_then(Value::as_str)
+                        .unwrap_or("Unknown error")
+                        .to_string()),
+                    None => Ok(value
+                        .get_mut("result")
+                        .map(Value::take)
+                        .unwrap_or(Value::Null)),
+                };
+                Some(Message::Response {
+                    id: id.as_i64()?,
+                    result,
+                })
+            }
+            (None, None) => None,
+        }
+    }
+}
+
+/// Reads `Content-Length` framed messages from a server, `None` at the end of the stream.
+pub(crate) fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length"))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub(crate) fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    writer.flush()
+}
+
+/// Both ends of a server's stdio. Reading and writing happen on their own threads, so a
+/// slow server never blocks a frame.
+pub(crate) struct Transport {
+    outgoing: Mutex<Sender<Value>>,
+    incoming: Mutex<Receiver<Message>>,
+}
+
+impl Transport {
+    pub(crate) fn new(stdin: ChildStdin, stdout: ChildStdout) -> Self {
+        let (outgoing, to_server) = mpsc::channel::<Value>();
+        let (from_server, incoming) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut stdin = stdin;
+            for message in to_server {
+                if write_message(&mut stdin, &message).is_err() {
+                    break;
+                }
+            }
+        });
+        thread::spawn(move || {
+            let mut stdout = io::BufReader::new(stdout);
+            while let Ok(Some(value)) = read_message(&mut stdout) {
+                if let Some(message) = Message::parse(value) {
+                    if from_server.send(message).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Self {
+            outgoing: Mutex::new(outgoing),
+            incoming: Mutex::new(incoming),
+        }
+    }
+
+    pub(crate) fn request(&self, id: i64, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+    }
+
+    pub(crate) fn notify(&self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    pub(crate) fn respond(&self, id: Value, result: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "result": result }));
+    }
+
+    /// The next message received, `Err(Disconnected)` once the server closed its stdout.
+    pub(crate) fn try_recv(&self) -> Result<Message, TryRecvError> {
+        self.incoming.lock().unwrap().try_recv()
+    }
+
+    fn send(&self, message: Value) {
+        // A server that went away is noticed on the receiving end.
+        let _ = self.outgoing.lock().unwrap().send(message);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::document::{Change, Document, Utf16Position};
+use lsp_types::{
+    DidChangeTextDocumentParams, Position, Range, TextDocumentContentChangeEvent,
+    TextDocumentSyncKind, Url, VersionedTextDocumentIdentifier,
+};
+use std::time::Duration;
+
+/// Past this many pending changes a full sync is cheaper for both sides.
+const MAX_INCREMENTAL_CHANGES: usize = 256;
+
+/// Coalesces a document's changes into `textDocument/didChange` notifications for one
+/// server, in the sync kind the server asked for.
+///
+/// Changes are held until edits pause for `debounce`, or at most `max_delay` after the
+/// first pending one. The version sent is the document's own version counter, and a
+/// batch falls back to a full sync if it missed any change in between.
+#[derive(Debug)]
+pub struct ChangeBatcher {
+    uri: Url,
+    kind: TextDocumentSyncKind,
+    debounce: Duration,
+    max_delay: Duration,
+    pending: Vec<TextDocumentContentChangeEvent>,
+    /// Document version the pending changes lead to.
+    pending_version: Option<u64>,
+    full: bool,
+    /// Seconds since startup of the first and last pending change.
+    first_change: f64,
+    last_change: f64,
+    last_sent: u64,
+}
+
+impl ChangeBatcher {
+    /// `version` is the document version the server last saw, e.g. sent with didOpen.
+    pub fn new(uri: Url, kind: TextDocumentSyncKind, version: u64) -> Self {
+        Self {
+            uri,
+            kind,
+            debounce: Duration::from_millis(50),
+            max_delay: Duration::from_millis(500),
+            pending: vec![],
+            pending_version: None,
+            full: false,
+            first_change: 0.,
+            last_change: 0.,
+            last_sent: version,
+        }
+    }
+
+    pub fn with_debounce(mut self, debounce: Duration, max_delay: Duration) -> Self {
+        self.debounce = debounce;
+        self.max_delay = max_delay;
+        self
+    }
+
+    pub fn has_pending(&self) -> bool {
+        self.pending_version.is_some()
+    }
+
+    /// Queues `changes`, which brought the document to `version`.
+    pub fn push(&mut self, changes: &[Change], version: u64, now: f64) {
+        if self.kind == TextDocumentSyncKind::NONE || changes.is_empty() {
+            return;
+        }
+
+        let expected = self.pending_version.unwrap_or(self.last_sent) + changes.len() as u64;
+        if self.kind == TextDocumentSyncKind::FULL || expected != version {
+            self.full = true;
+        }
+        if !self.full {
+            for change in changes {
+                self.push_incremental(change);
+            }
+            if self.pending.len() > MAX_INCREMENTAL_CHANGES {
+                self.full = true;
+            }
+        }
+        if self.full {
+            self.pending.clear();
+        }
+
+        if self.pending_version.is_none() {
+            self.first_change = now;
+        }
+        self.last_change = now;
+        self.pending_version = Some(version);
+    }
+
+    /// The notification to send now, if the batch is due.
+    pub fn flush(&mut self, document: &Document, now: f64) -> Option<DidChangeTextDocumentParams> {
+        self.pending_version?;
+        let quiet = now - self.last_change >= self.debounce.as_secs_f64();
+        let overdue = now - self.first_change >= self.max_delay.as_secs_f64();
+        if !quiet && !overdue {
+            return None;
+        }
+        self.flush_now(document)
+    }
+
+    /// The notification for everything pending, regardless of debouncing. Needed before
+    /// any request whose positions refer to the current document, e.g. completion.
+    pub fn flush_now(&mut self, document: &Document) -> Option<DidChangeTextDocumentParams> {
+        let version = self.pending_version.take()?;
+
+        let content_changes = if self.full || version != document.version() {
+            vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: document.buffer().to_string(),
+            }]
+        } else {
+            std::mem::take(&mut self.pending)
+        };
+        self.pending.clear();
+        self.full = false;
+        self.last_sent = document.version();
+
+        Some(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier {
+                uri: self.uri.clone(),
+                version: self.last_sent as i32,
+            },
+            content_changes,
+        })
+    }
+
+    fn push_incremental(&mut self, change: &Change) {
+        let start = position(change.start);
+        let end = position(change.end);
+
+        if let Some(last) = self.pending.last_mut() {
+            let last_range = last.range.expect("incremental changes have a range");
+            // Typing: this insert starts where the last one ended.
+            if change.range.is_empty()
+                && last_range.start == last_range.end
+                && advance(last_range.start, &last.text) == start
+            {
+                last.text.push_str(&change.text);
+                return;
+            }
+            // Backspacing: this delete ends where the last one started.
+            if change.text.is_empty() && last.text.is_empty() && end == last_range.start {
+                last.range = Some(Range::new(start, last_range.end));
+                return;
+            }
+        }
+
+        self.pending.push(TextDocumentContentChangeEvent {
+            range: Some(Range::new(start, end)),
+            range_length: None,
+            text: change.text.clone(),
+        });
+    }
+}
+
+fn position(position: Utf16Position) -> Position {
+    Position::new(position.line as u32, position.column as u32)
+}
+
+/// Where the cursor ends up after typing `text` at `position`.
+fn advance(mut position: Position, text: &str) -> Position {
+    match text.rfind('\n') {
+        Some(i) => {
+            position.line += text.matches('\n').count() as u32;
+            position.character = text[i + 1..].encode_utf16().count() as u32;
+        }
+        None => position.character += text.encode_utf16().count() as u32,
+    }
+    position
+}
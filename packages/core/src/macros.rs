@@ -0,0 +1,346 @@
+use crate::{
+    announce::count,
+    command::{
+        CommandInfo, CommandRegistry, RegisterCommand, RunCommand, RunCommands, TYPING_COMMANDS,
+    },
+    cursor::TypeText,
+    keymap::{parse_keys, Binding, Keymap, Layer},
+    pipeline::{AppPipelineExt, EditorStage},
+    workspace::Workspace,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        event::{EventReader, EventWriter},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Res, ResMut},
+    },
+    log::{debug, warn},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, env, fs, io, path::PathBuf};
+
+/// Saved macros run as `macro.<name>`.
+const PREFIX: &str = "macro.";
+
+pub struct MacroPlugin;
+
+impl Plugin for MacroPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MacroSettings>()
+            .init_resource::<Macros>()
+            .add_event::<SaveMacro>()
+            .add_event::<MacroSaved>()
+            .register_command("macro.record", "Macro", "Start or Stop Recording")
+            .register_command("macro.play", "Macro", "Play Last Recording")
+            .add_startup_system(load_macros)
+            .add_system(record_macros)
+            .add_editor_system(EditorStage::Commands, run_macro_commands)
+            .add_editor_system(EditorStage::Commands, play_macros.before(RunCommands))
+            .add_system(save_macros);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MacroSettings {
+    /// Where saved macros are kept, one JSON file each, so they can be shared by
+    /// copying the files.
+    pub dir: Option<PathBuf>,
+}
+
+impl Default for MacroSettings {
+    fn default() -> Self {
+        Self {
+            dir: config_dir().map(|dir| dir.join("macros")),
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME/dip`, `~/.config/dip`, or `%APPDATA%\dip` on Windows.
+pub(crate) fn config_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.map(|base| base.join("dip"))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MacroStep {
+    /// A command by id, run like a key bound to it.
+    Command(String),
+    /// Text typed into the active document.
+    Type(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Macro {
+    pub name: String,
+    pub title: String,
+    /// Keys running it, e.g. `ctrl+alt+m`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub steps: Vec<MacroStep>,
+}
+
+impl Macro {
+    pub fn command(&self) -> String {
+        format!("{PREFIX}{}", self.name)
+    }
+
+    /// A step running a macro, which only a file edited by hand has, as they are never
+    /// recorded. Playing it could run macros without end.
+    fn macro_step(&self) -> Option<&str> {
+        self.steps.iter().find_map(|step| match step {
+            MacroStep::Command(id) if id.starts_with(PREFIX) => Some(id.as_str()),
+            _ => None,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Macros {
+    /// Steps so far, while recording.
+    recording: Option<Vec<MacroStep>>,
+    last: Vec<MacroStep>,
+    saved: Vec<Macro>,
+    /// Steps left to play.
+    playing: VecDeque<MacroStep>,
+    /// Steps played but not seen by the recorder yet, so they are not recorded again.
+    echoes: VecDeque<MacroStep>,
+    /// Playing waits a frame after each step, see `play_macros`.
+    waiting: bool,
+}
+
+impl Macros {
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub fn last(&self) -> &[MacroStep] {
+        &self.last
+    }
+
+    pub fn saved(&self) -> &[Macro] {
+        &self.saved
+    }
+
+    /// Keeps `step` if it is not one being played.
+    fn record(&mut self, step: MacroStep) {
+        if self.echoes.front() == Some(&step) {
+            self.echoes.pop_front();
+        } else if let Some(recording) = &mut self.recording {
+            recording.push(step);
+        }
+    }
+}
+
+/// Saves the last recording as the command `macro.<name>`. Saving under a name in use
+/// replaces that macro.
+#[derive(Clone, Debug)]
+pub struct SaveMacro {
+    pub name: String,
+    pub title: Option<String>,
+    pub key: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct MacroSaved {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+fn load_macros(
+    settings: Res<MacroSettings>,
+    mut macros: ResMut<Macros>,
+    mut registry: ResMut<CommandRegistry>,
+    mut keymap: ResMut<Keymap>,
+) {
+    let dir = match &settings.dir {
+        Some(dir) => dir,
+        None => return,
+    };
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+        Err(e) => {
+            warn!("⏺ Failed to read {}: {e}", dir.display());
+            return;
+        }
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+        let parsed = fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|json| serde_json::from_str::<Macro>(&json).map_err(|e| e.to_string()));
+        match parsed {
+            Ok(saved) => match saved.macro_step() {
+                Some(id) => warn!("⏺ Not loading {}, it runs {id}", path.display()),
+                None => add_macro(&mut macros, &mut registry, saved),
+            },
+            Err(error) => warn!("⏺ Failed to load {}: {error}", path.display()),
+        }
+    }
+    debug!("⏺ Loaded {}", count(macros.saved.len(), "macro"));
+    keymap.set_layer(macro_layer(&macros.saved));
+}
+
+fn add_macro(macros: &mut Macros, registry: &mut CommandRegistry, saved: Macro) {
+    registry.register(CommandInfo::new(saved.command(), "Macro", &saved.title));
+    macros.saved.retain(|m| m.name != saved.name);
+    macros.saved.push(saved);
+}
+
+/// The keys of the saved macros, above the layers there were when they loaded.
+fn macro_layer(saved: &[Macro]) -> Layer {
+    let mut layer = Layer::new("macros");
+    for saved in saved {
+        let key = match &saved.key {
+            Some(key) => key,
+            None => continue,
+        };
+        match parse_keys(key) {
+            Ok(keys) => layer.bindings.push(Binding {
+                keys,
+                command: Some(saved.command()),
+                mode: None,
+            }),
+            Err(error) => warn!("⏺ Not binding {} to {key}: {error}", saved.name),
+        }
+    }
+    layer
+}
+
+fn record_macros(
+    mut commands: EventReader<RunCommand>,
+    mut typed: EventReader<TypeText>,
+    mut macros: ResMut<Macros>,
+) {
+    for e in commands.iter() {
+        // Macros are left out, so one cannot end up playing itself.
+        let typing = TYPING_COMMANDS.iter().any(|(id, _)| *id == e.id);
+        if !typing && !e.id.starts_with(PREFIX) {
+            macros.record(MacroStep::Command(e.id.clone()));
+        }
+    }
+    for e in typed.iter() {
+        macros.record(MacroStep::Type(e.text.clone()));
+    }
+}
+
+fn run_macro_commands(mut events: EventReader<RunCommand>, mut macros: ResMut<Macros>) {
+    for e in events.iter() {
+        let name = match e.id.strip_prefix(PREFIX) {
+            Some(name) => name,
+            None => continue,
+        };
+        match name {
+            "record" => match macros.recording.take() {
+                Some(steps) => {
+                    debug!("⏺ Recorded {}", count(steps.len(), "step"));
+                    if !steps.is_empty() {
+                        macros.last = steps;
+                    }
+                }
+                None => {
+                    debug!("⏺ Recording");
+                    macros.recording = Some(vec![]);
+                }
+            },
+            "play" => {
+                let steps = macros.last.clone();
+                macros.playing.extend(steps);
+            }
+            _ => match macros.saved.iter().find(|m| m.name == name) {
+                Some(saved) => {
+                    let steps = saved.steps.clone();
+                    macros.playing.extend(steps);
+                }
+                None => warn!("⏺ No macro named {name}"),
+            },
+        }
+    }
+}
+
+/// Plays a step every other frame. Commands reach the systems doing them in the frame
+/// after at the latest, so a step is done before the next one starts, even when the two
+/// are done by different systems.
+fn play_macros(
+    mut macros: ResMut<Macros>,
+    workspace: Res<Workspace>,
+    mut commands: EventWriter<RunCommand>,
+    mut types: EventWriter<TypeText>,
+) {
+    if macros.waiting {
+        macros.waiting = false;
+        return;
+    }
+    let step = match macros.playing.pop_front() {
+        Some(step) => step,
+        None => return,
+    };
+    match &step {
+        MacroStep::Command(id) => commands.send(RunCommand { id: id.clone() }),
+        MacroStep::Type(text) => match workspace.active() {
+            Some(entity) => types.send(TypeText {
+                entity,
+                text: text.clone(),
+            }),
+            None => return,
+        },
+    }
+    macros.echoes.push_back(step);
+    macros.waiting = true;
+}
+
+fn save_macros(
+    mut events: EventReader<SaveMacro>,
+    settings: Res<MacroSettings>,
+    mut macros: ResMut<Macros>,
+    (mut registry, mut keymap): (ResMut<CommandRegistry>, ResMut<Keymap>),
+    mut saved: EventWriter<MacroSaved>,
+) {
+    for e in events.iter() {
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        if e.name.is_empty() || !e.name.chars().all(valid) {
+            warn!("⏺ Not saving a macro named {:?}", e.name);
+            continue;
+        }
+        if macros.last.is_empty() {
+            warn!("⏺ Nothing recorded to save as {}", e.name);
+            continue;
+        }
+        let dir = match &settings.dir {
+            Some(dir) => dir,
+            None => {
+                warn!("⏺ No config directory to save {} in", e.name);
+                continue;
+            }
+        };
+        let new = Macro {
+            name: e.name.clone(),
+            title: e.title.clone().unwrap_or_else(|| e.name.clone()),
+            key: e.key.clone(),
+            steps: macros.last.clone(),
+        };
+        let path = dir.join(format!("{}.json", e.name));
+        let json = serde_json::to_string_pretty(&new).expect("macros serialize");
+        if let Err(error) = fs::create_dir_all(dir).and_then(|()| fs::write(&path, json)) {
+            warn!("⏺ Failed to save {}: {error}", path.display());
+            continue;
+        }
+        debug!("⏺ Saved {}", path.display());
+        add_macro(&mut macros, &mut registry, new);
+        keymap.set_layer(macro_layer(&macros.saved));
+        saved.send(MacroSaved {
+            name: e.name.clone(),
+            path,
+        });
+    }
+}
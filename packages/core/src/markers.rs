@@ -0,0 +1,236 @@
+use crate::{
+    damage::DecorationsChanged,
+    document::{Change, Document, DocumentChanged},
+    pipeline::{AppPipelineExt, EditorStage},
+    text_buffer::TextBuffer,
+    theme::{Rgb, Underline},
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        system::{Commands, Query},
+    },
+    log::debug,
+};
+use std::{collections::HashMap, ops::Range};
+
+pub use dip_document::markers::{shift_range, Stickiness};
+
+pub struct MarkersPlugin;
+
+impl Plugin for MarkersPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SetMarkers>()
+            .add_event::<ClearMarkers>()
+            .add_event::<MarkersChanged>()
+            .add_system(set_markers)
+            .add_editor_system(EditorStage::Derive, shift_markers);
+    }
+}
+
+/// Replaces the markers `owner` attached to a document, e.g. search matches or a plugin's
+/// inline hints. What other owners attached stays.
+#[derive(Clone, Debug)]
+pub struct SetMarkers {
+    pub entity: Entity,
+    pub owner: String,
+    pub markers: Vec<TextMarker>,
+}
+
+/// Drops the markers of `owner`, or all of them without one.
+#[derive(Clone, Debug)]
+pub struct ClearMarkers {
+    pub entity: Entity,
+    pub owner: Option<String>,
+}
+
+/// The [`Markers`] of a document were set or cleared.
+#[derive(Clone, Copy, Debug)]
+pub struct MarkersChanged {
+    pub entity: Entity,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextMarker {
+    pub range: Range<usize>,
+    pub style: MarkerStyle,
+    pub stickiness: Stickiness,
+}
+
+impl TextMarker {
+    pub fn new(range: Range<usize>, style: MarkerStyle) -> Self {
+        Self {
+            range,
+            style,
+            stickiness: Stickiness::default(),
+        }
+    }
+
+    pub fn with_stickiness(mut self, stickiness: Stickiness) -> Self {
+        self.stickiness = stickiness;
+        self
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MarkerStyle {
+    Background(Rgb),
+    Underline(Rgb, Underline),
+    /// Where the view draws the widget its owner knows by this key, e.g. an inlay hint.
+    /// Usually empty.
+    Widget(String),
+}
+
+#[derive(Clone, Debug)]
+struct Attached {
+    owner: String,
+    marker: TextMarker,
+}
+
+/// What owners attached to a document, sorted by start. Ranges move along with edits
+/// until their owner sets them again.
+#[derive(Component, Clone, Debug, Default)]
+pub struct Markers {
+    attached: Vec<Attached>,
+}
+
+impl Markers {
+    pub fn iter(&self) -> impl Iterator<Item = &TextMarker> {
+        self.attached.iter().map(|a| &a.marker)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.attached.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.attached.len()
+    }
+
+    /// What `owner` attached.
+    pub fn from_owner<'a>(&'a self, owner: &'a str) -> impl Iterator<Item = &'a TextMarker> {
+        self.attached
+            .iter()
+            .filter(move |a| a.owner == owner)
+            .map(|a| &a.marker)
+    }
+
+    /// The markers overlapping `range`, e.g. the visible lines to draw. Empty ones at its
+    /// edges count.
+    pub fn in_range(&self, range: Range<usize>) -> impl Iterator<Item = &TextMarker> {
+        self.iter()
+            .take_while(move |m| m.range.start <= range.end)
+            .filter(move |m| m.range.end >= range.start)
+    }
+
+    /// The markers overlapping `lines`.
+    pub fn on_lines(
+        &self,
+        buffer: &TextBuffer,
+        lines: Range<usize>,
+    ) -> impl Iterator<Item = &TextMarker> {
+        let offset = |line: usize| match line < buffer.line_count() {
+            true => buffer.line_start(line),
+            false => buffer.len(),
+        };
+        let (start, end) = (offset(lines.start), offset(lines.end));
+        self.in_range(start..end)
+    }
+
+    fn set(&mut self, owner: &str, markers: Vec<TextMarker>) {
+        self.clear(Some(owner));
+        self.attached
+            .extend(markers.into_iter().map(|marker| Attached {
+                owner: owner.to_string(),
+                marker,
+            }));
+        self.sort();
+    }
+
+    fn clear(&mut self, owner: Option<&str>) {
+        match owner {
+            Some(owner) => self.attached.retain(|a| a.owner != owner),
+            None => self.attached.clear(),
+        }
+    }
+
+    fn shift(&mut self, change: &Change) {
+        for a in &mut self.attached {
+            a.marker.range = shift_range(&a.marker.range, change, a.marker.stickiness);
+        }
+        self.sort();
+    }
+
+    fn sort(&mut self) {
+        self.attached
+            .sort_by_key(|a| (a.marker.range.start, a.marker.range.end));
+    }
+}
+
+fn set_markers(
+    mut commands: Commands,
+    (mut set, mut cleared): (EventReader<SetMarkers>, EventReader<ClearMarkers>),
+    mut documents: Query<(&Document, Option<&mut Markers>)>,
+    mut changed: EventWriter<MarkersChanged>,
+    mut decorations: EventWriter<DecorationsChanged>,
+) {
+    let mut notify = |entity: Entity, document: &Document| {
+        changed.send(MarkersChanged { entity });
+        decorations.send(DecorationsChanged {
+            entity,
+            lines: 0..document.buffer().line_count(),
+        });
+    };
+
+    for e in cleared.iter() {
+        if let Ok((document, Some(mut markers))) = documents.get_mut(e.entity) {
+            if markers.is_empty() {
+                continue;
+            }
+            markers.clear(e.owner.as_deref());
+            notify(e.entity, document);
+        }
+    }
+
+    // Several owners may attach markers before the component is inserted.
+    let mut inserted: HashMap<Entity, Markers> = HashMap::new();
+    for e in set.iter() {
+        let (document, current) = match documents.get_mut(e.entity) {
+            Ok(found) => found,
+            Err(_) => continue,
+        };
+        let len = document.buffer().len();
+        let markers = e
+            .markers
+            .iter()
+            .cloned()
+            .map(|mut m| {
+                let start = m.range.start.min(len);
+                m.range = start..m.range.end.clamp(start, len);
+                m
+            })
+            .collect();
+        debug!("🖍 {} set {} markers", e.owner, e.markers.len());
+        match current {
+            Some(mut current) => current.set(&e.owner, markers),
+            None => inserted.entry(e.entity).or_default().set(&e.owner, markers),
+        }
+        notify(e.entity, document);
+    }
+    for (entity, markers) in inserted {
+        commands.entity(entity).insert(markers);
+    }
+}
+
+fn shift_markers(mut changes: EventReader<DocumentChanged>, mut markers: Query<&mut Markers>) {
+    for e in changes.iter() {
+        if let Ok(mut markers) = markers.get_mut(e.entity) {
+            for change in &e.changes {
+                markers.shift(change);
+            }
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use crate::idle::throttled;
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::EventWriter,
+        query::Without,
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Query, Res},
+    },
+    log::debug,
+};
+use std::time::Duration;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+pub struct MemoryPlugin;
+
+impl Plugin for MemoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MemoryBudget>()
+            .add_event::<EvictCache>()
+            .add_event::<MemoryPressure>()
+            .add_system(
+                enforce_memory_budget
+                    .with_run_criteria(throttled(CHECK_INTERVAL, IDLE_CHECK_INTERVAL)),
+            );
+    }
+}
+
+pub struct MemoryBudget {
+    pub limit: usize,
+}
+
+impl Default for MemoryBudget {
+    fn default() -> Self {
+        Self {
+            limit: 512 * 1024 * 1024,
+        }
+    }
+}
+
+/// Bytes held by a document, kept up to date by whichever plugin owns each part.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct MemoryUsage {
+    pub buffer: usize,
+    pub undo: usize,
+    pub syntax: usize,
+    /// Seconds since startup the document was last shown or edited.
+    pub last_used: f64,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.buffer + self.undo + self.syntax
+    }
+
+    /// Rough number of bytes evicting `cache` gives back.
+    pub fn reclaimable(&self, cache: Cache) -> usize {
+        match cache {
+            Cache::SyntaxTree => self.syntax,
+            Cache::Undo => self.undo / 2,
+        }
+    }
+}
+
+/// Marks documents currently on screen, the active one of the workspace. Their caches are
+/// never evicted.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct Visible;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cache {
+    /// Drop the syntax tree, to be re-parsed when the document is shown again.
+    SyntaxTree,
+    /// Compress undo history.
+    Undo,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct EvictCache {
+    pub entity: Entity,
+    pub cache: Cache,
+}
+
+/// Sent when usage exceeds the budget even after evicting every background cache.
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryPressure {
+    pub over_by: usize,
+}
+
+fn enforce_memory_budget(
+    budget: Res<MemoryBudget>,
+    all: Query<&MemoryUsage>,
+    background: Query<(Entity, &MemoryUsage), Without<Visible>>,
+    mut evict: EventWriter<EvictCache>,
+    mut pressure: EventWriter<MemoryPressure>,
+) {
+    let mut total: usize = all.iter().map(MemoryUsage::total).sum();
+    if total <= budget.limit {
+        return;
+    }
+    debug!("🧮 {total} bytes in use, budget is {}", budget.limit);
+
+    let mut candidates: Vec<_> = background.iter().collect();
+    candidates.sort_by(|(_, a), (_, b)| a.last_used.total_cmp(&b.last_used));
+
+    // Syntax trees are cheap to rebuild, so they go first; undo compression only when needed.
+    for cache in [Cache::SyntaxTree, Cache::Undo] {
+        for (entity, usage) in &candidates {
+            if total <= budget.limit {
+                return;
+            }
+            let freed = usage.reclaimable(cache);
+            if freed > 0 {
+                evict.send(EvictCache {
+                    entity: *entity,
+                    cache,
+                });
+                total = total.saturating_sub(freed);
+            }
+        }
+    }
+
+    if total > budget.limit {
+        pressure.send(MemoryPressure {
+            over_by: total - budget.limit,
+        });
+    }
+}
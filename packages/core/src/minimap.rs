@@ -0,0 +1,300 @@
+//! A small picture of the whole document on screen next to its text, a row of colored
+//! blocks per line, with the part in view marked. Dragging the mark, sent by the view as
+//! [`CoreCommand::MinimapDrag`], scrolls the document.
+
+use crate::{
+    command::{CoreCommand, UICommand},
+    config::Settings,
+    document::Document,
+    fold::Folds,
+    layout::Layout,
+    pipeline::{AppPipelineExt, EditorStage},
+    render::Viewport,
+    syntax::Highlights,
+    theme::{DesignTokens, Rgb},
+    workspace::Workspace,
+    zoom::{LayoutMetrics, Scroll},
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        query::{Changed, Or},
+        system::{Local, Query, Res},
+    },
+};
+use std::{ops::Range, sync::Arc};
+
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_editor_system(EditorStage::Input, drag_minimap)
+            .add_editor_system(EditorStage::Present, render_minimap);
+    }
+}
+
+/// `editor.minimap.enabled`, and `editor.minimap.maxColumn`, the columns of a line drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MinimapSettings {
+    pub enabled: bool,
+    pub max_column: usize,
+    /// Height of a row in physical pixels.
+    pub row_height: f32,
+    /// Width of a column in physical pixels.
+    pub char_width: f32,
+}
+
+impl Default for MinimapSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_column: 120,
+            row_height: 2.,
+            char_width: 1.,
+        }
+    }
+}
+
+impl MinimapSettings {
+    pub fn new(settings: Option<&Settings>) -> Self {
+        let default = Self::default();
+        let settings = match settings {
+            Some(settings) => settings,
+            None => return default,
+        };
+        Self {
+            enabled: settings
+                .get("editor.minimap.enabled")
+                .unwrap_or(default.enabled),
+            max_column: settings
+                .get("editor.minimap.maxColumn")
+                .unwrap_or(default.max_column),
+            ..default
+        }
+    }
+}
+
+/// Text of one color on a row, whitespace left out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MinimapBlock {
+    pub column: usize,
+    pub len: usize,
+    pub color: Rgb,
+}
+
+/// The minimap of the document on screen, sent when it or the part in view changed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Minimap {
+    pub entity: Entity,
+    /// Of the longest row it can draw.
+    pub width: f32,
+    pub row_height: f32,
+    pub char_width: f32,
+    /// A row for every line not folded away, top to bottom.
+    pub rows: Arc<Vec<Vec<MinimapBlock>>>,
+    /// The part of the document in view, from the top of the minimap.
+    pub shown: Range<f32>,
+    /// How far the minimap itself is scrolled, when taller than the view.
+    pub top: f32,
+}
+
+impl Minimap {
+    pub fn height(&self) -> f32 {
+        self.rows.len() as f32 * self.row_height
+    }
+}
+
+/// The blocks of `line`, colored by its highlights, up to `max_column` columns.
+pub fn minimap_row(
+    document: &Document,
+    line: usize,
+    highlights: Option<&Highlights>,
+    tokens: &DesignTokens,
+    max_column: usize,
+) -> Vec<MinimapBlock> {
+    let buffer = document.buffer();
+    let start = buffer.line_start(line);
+    let content = buffer.get_line_content(line);
+    let spans = highlights.map_or(&[][..], |h| h.in_range(start..start + content.len()));
+    let foreground = tokens
+        .color("editor.foreground")
+        .unwrap_or(Rgb(0xd4, 0xd4, 0xd4));
+    let color_at = |offset: usize| {
+        spans
+            .iter()
+            .find(|span| span.range.contains(&(start + offset)))
+            .and_then(|span| tokens.syntax(highlights?.name(span)))
+            .unwrap_or(foreground)
+    };
+
+    let mut blocks: Vec<MinimapBlock> = vec![];
+    for (column, (offset, c)) in content.char_indices().take(max_column).enumerate() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let color = color_at(offset);
+        match blocks.last_mut() {
+            Some(block) if block.color == color && block.column + block.len == column => {
+                block.len += 1
+            }
+            _ => blocks.push(MinimapBlock {
+                column,
+                len: 1,
+                color,
+            }),
+        }
+    }
+    blocks
+}
+
+/// Scale from the document to the minimap and how far it is scrolled.
+struct Scale {
+    ratio: f32,
+    top: f32,
+}
+
+impl Scale {
+    fn new(minimap_height: f32, document_height: f32, viewport: f32, scroll: f32) -> Self {
+        let ratio = match document_height > 0. {
+            true => minimap_height / document_height,
+            false => 0.,
+        };
+        let overflow = (minimap_height - viewport).max(0.);
+        let scrollable = (document_height - viewport).max(0.);
+        let top = match scrollable > 0. {
+            true => overflow * (scroll / scrollable).clamp(0., 1.),
+            false => 0.,
+        };
+        Self { ratio, top }
+    }
+}
+
+fn drag_minimap(
+    mut events: EventReader<CoreCommand>,
+    (viewport, workspace, user): (Res<Viewport>, Option<Res<Workspace>>, Option<Res<Settings>>),
+    mut documents: Query<(
+        &Document,
+        &Layout,
+        &LayoutMetrics,
+        Option<&Folds>,
+        &mut Scroll,
+    )>,
+) {
+    let settings = MinimapSettings::new(user.as_deref());
+    for e in events.iter() {
+        let y = match e {
+            CoreCommand::MinimapDrag { y } => *y,
+            _ => continue,
+        };
+        let entity = match viewport.shown(workspace.as_deref()) {
+            Some(entity) => entity,
+            None => continue,
+        };
+        let (document, layout, metrics, folds, mut scroll) = match documents.get_mut(entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let lines = document.buffer().line_count();
+        let rows = folds.map_or(lines, |folds| folds.visible_line_count(lines));
+        let height = layout.visual_line_count() as f32 * metrics.line_height;
+        let scale = Scale::new(
+            rows as f32 * settings.row_height,
+            height,
+            viewport.height,
+            scroll.top,
+        );
+        if scale.ratio <= 0. {
+            continue;
+        }
+        let top = (y / scale.ratio).clamp(0., (height - viewport.height).max(0.));
+        if scroll.top != top {
+            scroll.top = top;
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn render_minimap(
+    (viewport, workspace, user, tokens): (
+        Res<Viewport>,
+        Option<Res<Workspace>>,
+        Option<Res<Settings>>,
+        Res<DesignTokens>,
+    ),
+    documents: Query<(
+        &Document,
+        &Layout,
+        &LayoutMetrics,
+        &Scroll,
+        Option<&Folds>,
+        Option<&Highlights>,
+    )>,
+    (changed, moved): (
+        Query<(), Or<(Changed<Highlights>, Changed<Folds>)>>,
+        Query<(), Or<(Changed<Layout>, Changed<Scroll>)>>,
+    ),
+    // The rows of the document on screen, as of the version they were drawn from.
+    mut drawn: Local<Option<(Entity, u64, Arc<Vec<Vec<MinimapBlock>>>)>>,
+    mut enabled: Local<bool>,
+    mut ui: EventWriter<UICommand>,
+) {
+    let settings = MinimapSettings::new(user.as_deref());
+    if !settings.enabled {
+        if *enabled {
+            *enabled = false;
+            *drawn = None;
+            ui.send(UICommand::Minimap(None));
+        }
+        return;
+    }
+    let entity = match viewport.shown(workspace.as_deref()) {
+        Some(entity) => entity,
+        None => return,
+    };
+    let (document, layout, metrics, scroll, folds, highlights) = match documents.get(entity) {
+        Ok(document) => document,
+        Err(_) => return,
+    };
+
+    let found = (entity, document.version());
+    let stale = match &*drawn {
+        Some((entity, version, _)) => (*entity, *version) != found,
+        None => true,
+    };
+    let settings_changed = user.as_ref().is_some_and(|user| user.is_changed());
+    let redraw = stale || changed.get(entity).is_ok() || tokens.is_changed() || settings_changed;
+    if redraw {
+        let unfolded = Folds::default();
+        let folds = folds.unwrap_or(&unfolded);
+        let rows = (0..document.buffer().line_count())
+            .filter(|&line| !folds.is_hidden(line))
+            .map(|line| minimap_row(document, line, highlights, &tokens, settings.max_column))
+            .collect();
+        *drawn = Some((found.0, found.1, Arc::new(rows)));
+    }
+    if !redraw && *enabled && moved.get(entity).is_err() && !viewport.is_changed() {
+        return;
+    }
+    *enabled = true;
+    let rows = match &*drawn {
+        Some((.., rows)) => rows.clone(),
+        None => return,
+    };
+
+    let height = layout.visual_line_count() as f32 * metrics.line_height;
+    let minimap_height = rows.len() as f32 * settings.row_height;
+    let scale = Scale::new(minimap_height, height, viewport.height, scroll.top);
+    let top = scroll.top * scale.ratio;
+    let bottom = (scroll.top + viewport.height).min(height) * scale.ratio;
+    ui.send(UICommand::Minimap(Some(Minimap {
+        entity,
+        width: settings.max_column as f32 * settings.char_width,
+        row_height: settings.row_height,
+        char_width: settings.char_width,
+        rows,
+        shown: top..bottom.max(top),
+        top: scale.top,
+    })));
+}
@@ -0,0 +1,81 @@
+use crate::{config::SettingsSection, indent::SyntaxContext};
+use bevy::ecs::component::Component;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Characters typed in pairs, closed as soon as the opening one is typed and wrapped
+/// around a selection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AutoClosePair {
+    pub open: char,
+    pub close: char,
+    /// Where typing `open` inserts it alone, e.g. a quote inside a comment.
+    pub not_in: Vec<SyntaxContext>,
+}
+
+impl AutoClosePair {
+    pub fn new(open: char, close: char) -> Self {
+        Self {
+            open,
+            close,
+            not_in: vec![],
+        }
+    }
+
+    pub fn not_in(mut self, contexts: &[SyntaxContext]) -> Self {
+        self.not_in = contexts.to_vec();
+        self
+    }
+
+    pub fn is_symmetric(&self) -> bool {
+        self.open == self.close
+    }
+}
+
+/// The pairs of a language. Used as a resource for documents without their own.
+#[derive(Component, Clone, Debug, PartialEq, Eq)]
+pub struct AutoClosePairs(pub Vec<AutoClosePair>);
+
+impl Default for AutoClosePairs {
+    fn default() -> Self {
+        // Text in strings and comments is rarely code, pairs only get in the way there.
+        let prose = [SyntaxContext::String, SyntaxContext::Comment];
+        Self(
+            [('{', '}'), ('(', ')'), ('[', ']'), ('"', '"'), ('`', '`')]
+                .into_iter()
+                .map(|(open, close)| AutoClosePair::new(open, close).not_in(&prose))
+                .collect(),
+        )
+    }
+}
+
+impl AutoClosePairs {
+    /// The pairs `settings` leave enabled.
+    pub fn enabled<'a>(
+        &'a self,
+        settings: &'a PairSettings,
+    ) -> impl Iterator<Item = &'a AutoClosePair> {
+        self.0.iter().filter(|pair| settings.is_enabled(pair.open))
+    }
+}
+
+/// The auto-closing part of the settings file: pairs turned on or off by their opening
+/// character, e.g. `{"editor.autoClosingPairs": {"`": false}}`.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct PairSettings {
+    #[serde(rename = "editor.autoClosingPairs", default)]
+    pub pairs: BTreeMap<char, bool>,
+}
+
+impl SettingsSection for PairSettings {
+    /// Pairs set in `other` win over the ones in `self`.
+    fn merge(&mut self, other: Self) {
+        self.pairs.extend(other.pairs);
+    }
+}
+
+impl PairSettings {
+    pub fn is_enabled(&self, open: char) -> bool {
+        self.pairs.get(&open).copied().unwrap_or(true)
+    }
+}
@@ -0,0 +1,275 @@
+//! What participants of a shared session may edit, for pairing or teaching: the host lets
+//! a participant only read a document, or only edit ranges of it, anchored like markers
+//! so they move along with the text.
+//!
+//! The same [`Permissions`] are checked on both ends. On the participant's, its own
+//! ranges guard the [`Document`], which refuses edits outside them whoever makes them. On
+//! the host's, every [`RemoteEdit`] is checked against the grant of whoever made it before
+//! it is applied. Either way a refused edit is reported with [`EditRejected`], for the
+//! session to tell the participant.
+
+use crate::{
+    document::{Document, DocumentChanged, DocumentEditSet, Edit},
+    markers::{shift_range, ClearMarkers, MarkerStyle, SetMarkers, Stickiness, TextMarker},
+    pipeline::{AppPipelineExt, EditorStage},
+    theme::{DesignTokens, Rgb},
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        query::Changed,
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Commands, Query, Res},
+    },
+    log::{debug, warn},
+};
+use std::{collections::HashMap, fmt, ops::Range};
+
+/// The owner of the [`TextMarker`]s showing where this editor may type.
+pub const MARKER_OWNER: &str = "permissions";
+
+pub struct PermissionsPlugin;
+
+impl Plugin for PermissionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LocalParticipant>()
+            .add_event::<SetPermission>()
+            .add_event::<RemoteEdit>()
+            .add_event::<EditRejected>()
+            .add_editor_system(EditorStage::Commands, set_permissions)
+            .add_editor_system(
+                EditorStage::Edits,
+                apply_remote_edits.label(DocumentEditSet),
+            )
+            .add_editor_system(EditorStage::Derive, shift_permissions)
+            .add_editor_system(EditorStage::Derive, report_rejected);
+    }
+}
+
+/// Someone in a shared session, as the session knows them.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Participant(pub String);
+
+impl fmt::Display for Participant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Who this editor is in the shared session it joined, None when it hosts one or isn't in
+/// any. Only the local participant's grants guard the documents here.
+#[derive(Clone, Debug, Default)]
+pub struct LocalParticipant(pub Option<Participant>);
+
+impl LocalParticipant {
+    fn is(&self, participant: &Participant) -> bool {
+        self.0.as_ref() == Some(participant)
+    }
+}
+
+/// What the host lets a participant do in a document.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Grant {
+    ReadOnly,
+    /// Edits only within these byte ranges, which grow with what is typed at their edges.
+    Ranges(Vec<Range<usize>>),
+}
+
+/// The grants of a document by participant. Participants without one edit anywhere.
+#[derive(Component, Clone, Debug, Default)]
+pub struct Permissions(HashMap<Participant, Vec<Range<usize>>>);
+
+impl Permissions {
+    /// Where `participant` may edit, None for anywhere.
+    pub fn editable(&self, participant: &Participant) -> Option<&[Range<usize>]> {
+        self.0.get(participant).map(Vec::as_slice)
+    }
+
+    /// Whether `participant` may replace `range`.
+    pub fn allows(&self, participant: &Participant, range: &Range<usize>) -> bool {
+        self.editable(participant).is_none_or(|editable| {
+            editable
+                .iter()
+                .any(|r| r.start <= range.start && range.end <= r.end)
+        })
+    }
+
+    fn set(&mut self, participant: Participant, ranges: Option<Vec<Range<usize>>>) {
+        match ranges {
+            Some(ranges) => self.0.insert(participant, ranges),
+            None => self.0.remove(&participant),
+        };
+    }
+}
+
+/// Grants `participant` what they may edit in a document, or lifts their restriction
+/// without a grant. Sent by the host, and by the session when the host's arrives.
+#[derive(Clone, Debug)]
+pub struct SetPermission {
+    pub entity: Entity,
+    pub participant: Participant,
+    pub grant: Option<Grant>,
+}
+
+/// An edit another participant of the session made, to check and apply.
+#[derive(Clone, Debug)]
+pub struct RemoteEdit {
+    pub entity: Entity,
+    pub participant: Participant,
+    pub edit: Edit,
+}
+
+/// An edit was refused, `participant`'s or this editor's own without one.
+#[derive(Clone, Debug)]
+pub struct EditRejected {
+    pub entity: Entity,
+    pub participant: Option<Participant>,
+    /// The byte range it would have replaced.
+    pub range: Range<usize>,
+}
+
+fn edit_range(edit: &Edit) -> Range<usize> {
+    match edit {
+        Edit::Insert { offset, .. } => *offset..*offset,
+        Edit::Delete(range) => range.clone(),
+    }
+}
+
+fn set_permissions(
+    mut commands: Commands,
+    mut events: EventReader<SetPermission>,
+    (local, tokens): (Res<LocalParticipant>, Res<DesignTokens>),
+    mut documents: Query<(&mut Document, Option<&mut Permissions>)>,
+    mut set_markers: EventWriter<SetMarkers>,
+    mut clear_markers: EventWriter<ClearMarkers>,
+) {
+    let background = tokens
+        .color("editor.editableRange")
+        .unwrap_or(Rgb(0x1f, 0x33, 0x25));
+    // Several grants may arrive before the component is inserted.
+    let mut inserted: HashMap<Entity, Permissions> = HashMap::new();
+    for e in events.iter() {
+        let (mut document, current) = match documents.get_mut(e.entity) {
+            Ok(found) => found,
+            Err(_) => continue,
+        };
+        let len = document.buffer().len();
+        let ranges = e.grant.as_ref().map(|grant| match grant {
+            Grant::ReadOnly => vec![],
+            Grant::Ranges(ranges) => ranges
+                .iter()
+                .map(|r| {
+                    let start = r.start.min(len);
+                    start..r.end.clamp(start, len)
+                })
+                .collect(),
+        });
+        debug!("🔒 {} may edit {ranges:?} in {:?}", e.participant, e.entity);
+
+        if local.is(&e.participant) {
+            match &ranges {
+                Some(ranges) => set_markers.send(SetMarkers {
+                    entity: e.entity,
+                    owner: MARKER_OWNER.to_string(),
+                    markers: ranges
+                        .iter()
+                        .map(|range| {
+                            TextMarker::new(range.clone(), MarkerStyle::Background(background))
+                                .with_stickiness(Stickiness::AlwaysGrows)
+                        })
+                        .collect(),
+                }),
+                None => clear_markers.send(ClearMarkers {
+                    entity: e.entity,
+                    owner: Some(MARKER_OWNER.to_string()),
+                }),
+            }
+            document.set_editable(ranges.clone());
+        }
+        match current {
+            Some(mut current) => current.set(e.participant.clone(), ranges),
+            None => inserted
+                .entry(e.entity)
+                .or_default()
+                .set(e.participant.clone(), ranges),
+        }
+    }
+    for (entity, permissions) in inserted {
+        commands.entity(entity).insert(permissions);
+    }
+}
+
+/// The host's end: edits of participants outside their grant are refused. Ones this
+/// editor's own grant doesn't cover are still applied, as it doesn't restrict others.
+fn apply_remote_edits(
+    mut events: EventReader<RemoteEdit>,
+    mut documents: Query<(&mut Document, Option<&Permissions>)>,
+    mut changed: EventWriter<DocumentChanged>,
+    mut rejected: EventWriter<EditRejected>,
+) {
+    for e in events.iter() {
+        let (mut document, permissions) = match documents.get_mut(e.entity) {
+            Ok(found) => found,
+            Err(_) => continue,
+        };
+        let range = edit_range(&e.edit);
+        if !permissions.is_none_or(|p| p.allows(&e.participant, &range)) {
+            warn!("🔒 Refused an edit of {} at {range:?}", e.participant);
+            rejected.send(EditRejected {
+                entity: e.entity,
+                participant: Some(e.participant.clone()),
+                range,
+            });
+            continue;
+        }
+        document.apply_unrestricted(&e.edit);
+        changed.send(DocumentChanged {
+            entity: e.entity,
+            version: document.version(),
+            changes: document.take_changes(),
+            cursor: None,
+        });
+    }
+}
+
+/// Grants move along with the text like the ranges guarding the document do.
+fn shift_permissions(
+    mut changes: EventReader<DocumentChanged>,
+    mut permissions: Query<&mut Permissions>,
+) {
+    for e in changes.iter() {
+        if let Ok(mut permissions) = permissions.get_mut(e.entity) {
+            for ranges in permissions.0.values_mut() {
+                for range in ranges.iter_mut() {
+                    for change in &e.changes {
+                        *range = shift_range(range, change, Stickiness::AlwaysGrows);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// This editor's own edits the document refused, typed or made by any command.
+fn report_rejected(
+    mut documents: Query<(Entity, &mut Document), Changed<Document>>,
+    mut rejected: EventWriter<EditRejected>,
+) {
+    for (entity, mut document) in documents.iter_mut() {
+        // Only borrowed mutably with something to report, so it isn't marked changed.
+        if !document.has_rejected() {
+            continue;
+        }
+        for range in document.take_rejected() {
+            debug!("🔒 Refused an edit at {range:?} outside the editable ranges");
+            rejected.send(EditRejected {
+                entity,
+                participant: None,
+                range,
+            });
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! The stages a frame goes through, so systems that depend on each other never race:
+//!
+//! 1. [`EditorStage::Input`] turns keys and API calls into commands.
+//! 2. [`EditorStage::Commands`] turns commands into requests, e.g. to edit or to move.
+//! 3. [`EditorStage::Edits`] applies edits to documents, labelled
+//!    [`DocumentEditSet`](crate::document::DocumentEditSet).
+//! 4. Bevy's `CoreStage::Update` runs systems without a stage of their own. They see the
+//!    frame's edits and may still make their own.
+//! 5. [`EditorStage::Derive`] brings state kept alongside documents up to date: cursors,
+//!    folds, markers, syntax, journals and the like.
+//! 6. [`EditorStage::Layout`] lays out what the derived state changed.
+//! 7. [`EditorStage::Present`] tells the view what to redraw.
+//!
+//! Commands spawning and inserting components are applied between stages, so a stage sees
+//! what the ones before it spawned. Text does not change from [`EditorStage::Derive`] on,
+//! which [`PipelinePlugin`] asserts in debug builds.
+
+use crate::document::Document;
+use bevy::{
+    app::{App, CoreStage, Plugin},
+    ecs::{
+        entity::Entity,
+        schedule::{
+            ExclusiveSystemDescriptorCoercion, IntoSystemDescriptor, StageLabel, SystemStage,
+        },
+        system::{IntoExclusiveSystem, Query, Res, ResMut},
+    },
+    log::error,
+};
+use std::collections::HashMap;
+
+pub struct PipelinePlugin;
+
+impl Plugin for PipelinePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_editor_stages()
+            .init_resource::<DerivedVersions>()
+            .add_system_to_stage(
+                EditorStage::Derive,
+                remember_versions.exclusive_system().at_start(),
+            )
+            .add_system_to_stage(
+                EditorStage::Present,
+                assert_unedited.exclusive_system().at_end(),
+            );
+    }
+}
+
+#[derive(StageLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EditorStage {
+    Input,
+    Commands,
+    Edits,
+    Derive,
+    Layout,
+    Present,
+}
+
+pub trait AppPipelineExt {
+    /// Adds the [`EditorStage`]s around `CoreStage::Update` unless they are there already.
+    fn add_editor_stages(&mut self) -> &mut Self;
+
+    /// Adds `system` to `stage`, adding the stages first if needed, so plugins work in any
+    /// order and without [`PipelinePlugin`].
+    fn add_editor_system<Params>(
+        &mut self,
+        stage: EditorStage,
+        system: impl IntoSystemDescriptor<Params>,
+    ) -> &mut Self;
+}
+
+impl AppPipelineExt for App {
+    fn add_editor_stages(&mut self) -> &mut Self {
+        if self
+            .schedule
+            .get_stage::<SystemStage>(&EditorStage::Input)
+            .is_some()
+        {
+            return self;
+        }
+        self.add_stage_before(
+            CoreStage::Update,
+            EditorStage::Input,
+            SystemStage::parallel(),
+        )
+        .add_stage_before(
+            CoreStage::Update,
+            EditorStage::Commands,
+            SystemStage::parallel(),
+        )
+        .add_stage_before(
+            CoreStage::Update,
+            EditorStage::Edits,
+            SystemStage::parallel(),
+        )
+        .add_stage_after(
+            CoreStage::Update,
+            EditorStage::Derive,
+            SystemStage::parallel(),
+        )
+        .add_stage_after(
+            EditorStage::Derive,
+            EditorStage::Layout,
+            SystemStage::parallel(),
+        )
+        .add_stage_after(
+            EditorStage::Layout,
+            EditorStage::Present,
+            SystemStage::parallel(),
+        )
+    }
+
+    fn add_editor_system<Params>(
+        &mut self,
+        stage: EditorStage,
+        system: impl IntoSystemDescriptor<Params>,
+    ) -> &mut Self {
+        self.add_editor_stages().add_system_to_stage(stage, system)
+    }
+}
+
+/// Versions of the documents when [`EditorStage::Derive`] started.
+#[derive(Debug, Default)]
+struct DerivedVersions(HashMap<Entity, u64>);
+
+fn remember_versions(mut versions: ResMut<DerivedVersions>, documents: Query<(Entity, &Document)>) {
+    versions.0.clear();
+    versions
+        .0
+        .extend(documents.iter().map(|(entity, d)| (entity, d.version())));
+}
+
+/// An edit after `CoreStage::Update` is missed by the derived state until the next frame,
+/// or for good by systems that only look at the frame's changes.
+fn assert_unedited(versions: Res<DerivedVersions>, documents: Query<(Entity, &Document)>) {
+    let edited: Vec<_> = documents
+        .iter()
+        .filter(|(entity, d)| versions.0.get(entity).is_some_and(|&v| v != d.version()))
+        .map(|(entity, _)| entity)
+        .collect();
+    if edited.is_empty() {
+        return;
+    }
+    let message = format!("🧭 Documents {edited:?} were edited after the Update stage");
+    if cfg!(debug_assertions) {
+        panic!("{message}");
+    }
+    error!("{message}");
+}
@@ -0,0 +1,481 @@
+//! Replays how a document evolved since it was opened. Every edit, undo and redo is kept
+//! with when it was made in the document's [`Timeline`], and [`ReplayHistory`] opens a
+//! read-only copy of it as it was then, which plays the edits back at the pace they were
+//! made. [`ControlPlayback`], or [`CoreCommand::Playback`] from the scrubber of the view,
+//! plays, pauses and seeks it. Closing the copy ends the replay.
+
+use crate::{
+    command::{CoreCommand, RegisterCommand, RunCommand, UICommand},
+    config::Settings,
+    document::{Document, DocumentChanged, DocumentEditSet, Edit, VirtualDocument},
+    pipeline::{AppPipelineExt, EditorStage},
+    render::Viewport,
+    text_buffer::{TextBuffer, TextSnapshot},
+    workspace::Workspace,
+};
+use bevy::{
+    app::{App, Plugin},
+    core::Time,
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        query::{Added, Without},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Commands, Local, Query, Res},
+    },
+    log::debug,
+};
+use std::ops::Range;
+
+/// Steps between two snapshots of a timeline, the most replayed to seek anywhere.
+const KEYFRAME_INTERVAL: usize = 100;
+
+pub struct PlaybackPlugin;
+
+impl Plugin for PlaybackPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ReplayHistory>()
+            .add_event::<ControlPlayback>()
+            .register_command("history.replay", "History", "Replay Edits")
+            .register_command("history.togglePlayback", "History", "Play or Pause Replay")
+            .register_command("history.stepBackward", "History", "Step Replay Backward")
+            .register_command("history.stepForward", "History", "Step Replay Forward")
+            .add_editor_system(EditorStage::Commands, run_playback_commands)
+            .add_editor_system(EditorStage::Commands, replay_history)
+            .add_editor_system(EditorStage::Edits, play.label(DocumentEditSet))
+            .add_editor_system(EditorStage::Derive, record_steps)
+            .add_editor_system(EditorStage::Present, send_playback);
+    }
+}
+
+/// `editor.playback.speed`, how many times faster than they were made edits play back,
+/// `editor.playback.maxPause`, the longest pause between two of them in seconds, and
+/// `editor.playback.maxSteps`, how many a timeline keeps.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlaybackSettings {
+    pub speed: f64,
+    pub max_pause: f64,
+    pub max_steps: usize,
+}
+
+impl Default for PlaybackSettings {
+    fn default() -> Self {
+        Self {
+            speed: 1.,
+            max_pause: 1.,
+            max_steps: 10_000,
+        }
+    }
+}
+
+impl PlaybackSettings {
+    pub fn new(settings: Option<&Settings>) -> Self {
+        let default = Self::default();
+        let settings = match settings {
+            Some(settings) => settings,
+            None => return default,
+        };
+        Self {
+            speed: settings
+                .get("editor.playback.speed")
+                .unwrap_or(default.speed),
+            max_pause: settings
+                .get("editor.playback.maxPause")
+                .unwrap_or(default.max_pause),
+            max_steps: settings
+                .get("editor.playback.maxSteps")
+                .unwrap_or(default.max_steps),
+        }
+    }
+}
+
+/// An edit, undo or redo: the changes of one [`DocumentChanged`].
+#[derive(Clone, Debug)]
+pub struct Step {
+    /// Seconds since startup.
+    pub at: f64,
+    /// `range` of the text before each replaced with the text, one after another.
+    pub changes: Vec<(Range<usize>, String)>,
+}
+
+/// Everything done to a document since it was opened, up to
+/// [`PlaybackSettings::max_steps`] of the latest steps.
+#[derive(Component, Clone, Debug)]
+pub struct Timeline {
+    /// When the first step kept could start, seconds since startup.
+    since: f64,
+    steps: Vec<Step>,
+    /// The text before the step at each index, every [`KEYFRAME_INTERVAL`] steps from the
+    /// first one.
+    keyframes: Vec<(usize, TextSnapshot)>,
+}
+
+impl Timeline {
+    fn new(since: f64, text: TextSnapshot) -> Self {
+        Self {
+            since,
+            steps: vec![],
+            keyframes: vec![(0, text)],
+        }
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// When the text was as it is after `position` steps, seconds since startup.
+    pub fn at(&self, position: usize) -> f64 {
+        match position.checked_sub(1).and_then(|i| self.steps.get(i)) {
+            Some(step) => step.at,
+            None => self.since,
+        }
+    }
+
+    /// The text after the first `position` steps.
+    pub fn text_at(&self, position: usize) -> TextBuffer {
+        let position = position.min(self.steps.len());
+        let (start, keyframe) = self
+            .keyframes
+            .iter()
+            .rev()
+            .find(|(i, _)| *i <= position)
+            .unwrap_or(&self.keyframes[0]);
+        let mut buffer = TextBuffer::clone(keyframe);
+        for step in &self.steps[*start..position] {
+            for (range, text) in &step.changes {
+                buffer.delete(range.start, range.len());
+                buffer.insert(range.start, text);
+            }
+        }
+        buffer
+    }
+
+    /// Drops the oldest steps up to the second keyframe, so it becomes the first.
+    fn trim(&mut self) {
+        if self.keyframes.len() < 2 {
+            return;
+        }
+        let (dropped, _) = self.keyframes.remove(0);
+        let dropped = self.keyframes[0].0 - dropped;
+        self.since = self.steps[dropped - 1].at;
+        self.steps.drain(..dropped);
+        for (i, _) in &mut self.keyframes {
+            *i -= dropped;
+        }
+    }
+}
+
+/// Opens a read-only copy of `entity` as it was when opened, and plays back what was done
+/// to it since.
+#[derive(Clone, Copy, Debug)]
+pub struct ReplayHistory {
+    pub entity: Entity,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlaybackControl {
+    Play,
+    Pause,
+    /// Shows the text after this many steps, paused.
+    Seek(usize),
+}
+
+/// Plays, pauses or seeks the replay shown in `entity`.
+#[derive(Clone, Copy, Debug)]
+pub struct ControlPlayback {
+    pub entity: Entity,
+    pub control: PlaybackControl,
+}
+
+/// The read-only copy of [`Playback::source`] a replay is shown in.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Playback {
+    pub source: Entity,
+    /// Steps of the source's [`Timeline`] the copy shows the text after.
+    pub position: usize,
+    pub playing: bool,
+    /// The time of the timeline played back up to, seconds since startup.
+    clock: f64,
+}
+
+/// The replay on screen, sent when it moved or was played or paused, and None once another
+/// document is shown.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlaybackState {
+    pub entity: Entity,
+    pub position: usize,
+    pub steps: usize,
+    pub playing: bool,
+    /// Seconds from the start of the timeline to the text shown, and to its last step.
+    pub elapsed: f64,
+    pub duration: f64,
+}
+
+fn run_playback_commands(
+    mut events: EventReader<RunCommand>,
+    workspace: Res<Workspace>,
+    playbacks: Query<&Playback>,
+    timelines: Query<&Timeline>,
+    mut replays: EventWriter<ReplayHistory>,
+    mut controls: EventWriter<ControlPlayback>,
+) {
+    for e in events.iter() {
+        let entity = match (e.id.starts_with("history."), workspace.active()) {
+            (true, Some(entity)) => entity,
+            _ => continue,
+        };
+        if e.id == "history.replay" {
+            replays.send(ReplayHistory { entity });
+            continue;
+        }
+        let playback = match playbacks.get(entity) {
+            Ok(playback) => playback,
+            Err(_) => continue,
+        };
+        let steps = timelines
+            .get(playback.source)
+            .map_or(0, |timeline| timeline.steps.len());
+        let control = match e.id.as_str() {
+            "history.togglePlayback" if playback.playing => PlaybackControl::Pause,
+            "history.togglePlayback" => PlaybackControl::Play,
+            "history.stepBackward" => PlaybackControl::Seek(playback.position.saturating_sub(1)),
+            "history.stepForward" => PlaybackControl::Seek((playback.position + 1).min(steps)),
+            _ => continue,
+        };
+        controls.send(ControlPlayback { entity, control });
+    }
+}
+
+/// Opens the copy as it was when its source was opened, which the workspace focuses like
+/// any document spawned. Asking to replay a copy replays
+/// its source again from the start.
+fn replay_history(
+    mut commands: Commands,
+    mut events: EventReader<ReplayHistory>,
+    time: Res<Time>,
+    timelines: Query<&Timeline>,
+    playbacks: Query<&Playback>,
+) {
+    for e in events.iter() {
+        let source = playbacks.get(e.entity).map_or(e.entity, |p| p.source);
+        let timeline = match timelines.get(source) {
+            Ok(timeline) => timeline,
+            Err(_) => continue,
+        };
+        let mut document = Document::new(None, timeline.text_at(0));
+        document.set_editable(Some(vec![]));
+        commands
+            .spawn()
+            .insert(document)
+            .insert(VirtualDocument)
+            .insert(Playback {
+                source,
+                position: 0,
+                playing: true,
+                clock: timeline.since,
+            });
+        debug!(
+            "⏪ Replaying {} steps of {source:?}, from {:.1}s ago",
+            timeline.steps.len(),
+            time.seconds_since_startup() - timeline.since,
+        );
+    }
+}
+
+/// Moves each replay to where it is seeked or played to, applying the steps in between to
+/// its copy.
+#[allow(clippy::type_complexity)]
+fn play(
+    mut events: EventReader<ControlPlayback>,
+    mut core: EventReader<CoreCommand>,
+    (time, user, viewport, workspace): (
+        Res<Time>,
+        Option<Res<Settings>>,
+        Res<Viewport>,
+        Option<Res<Workspace>>,
+    ),
+    timelines: Query<&Timeline>,
+    mut playbacks: Query<(Entity, &mut Document, &mut Playback)>,
+    mut changed: EventWriter<DocumentChanged>,
+) {
+    let settings = PlaybackSettings::new(user.as_deref());
+    let mut seeks: Vec<(Entity, PlaybackControl)> =
+        events.iter().map(|e| (e.entity, e.control)).collect();
+    // The scrubber controls the replay on screen.
+    let shown = viewport.shown(workspace.as_deref());
+    for e in core.iter() {
+        if let (CoreCommand::Playback(control), Some(entity)) = (e, shown) {
+            seeks.push((entity, *control));
+        }
+    }
+
+    for (entity, mut document, mut playback) in playbacks.iter_mut() {
+        let timeline = match timelines.get(playback.source) {
+            Ok(timeline) => timeline,
+            Err(_) => continue,
+        };
+        let steps = timeline.steps.len();
+        let mut target = playback.position.min(steps);
+        let mut playing = playback.playing;
+        let mut clock = playback.clock;
+        for (_, control) in seeks.iter().filter(|(e, _)| *e == entity) {
+            match *control {
+                // Played again from the start once it reached the end.
+                PlaybackControl::Play if target >= steps => {
+                    target = 0;
+                    clock = timeline.at(0);
+                    playing = true;
+                }
+                PlaybackControl::Play => playing = true,
+                PlaybackControl::Pause => playing = false,
+                PlaybackControl::Seek(position) => {
+                    target = position.min(steps);
+                    clock = timeline.at(target);
+                    playing = false;
+                }
+            }
+        }
+        if playing {
+            clock += time.delta_seconds_f64() * settings.speed;
+            if let Some(next) = timeline.steps.get(target) {
+                clock = clock.max(next.at - settings.max_pause);
+            }
+            while timeline
+                .steps
+                .get(target)
+                .is_some_and(|step| step.at <= clock)
+            {
+                target += 1;
+            }
+            playing = target < steps;
+        }
+
+        // Only borrowed mutably for a change, so a paused replay isn't sent again.
+        if playback.playing != playing {
+            playback.playing = playing;
+        }
+        if playback.clock != clock {
+            playback.clock = clock;
+        }
+        if target == playback.position {
+            continue;
+        }
+
+        let cursor =
+            if target > playback.position && target - playback.position <= KEYFRAME_INTERVAL {
+                let mut cursor = None;
+                for step in &timeline.steps[playback.position..target] {
+                    for (range, text) in &step.changes {
+                        replace(&mut document, range.clone(), text);
+                        cursor = Some(range.start + text.len());
+                    }
+                }
+                cursor
+            } else {
+                let text = timeline.text_at(target);
+                let len = document.buffer().len();
+                replace(&mut document, 0..len, &text.text_in(0..text.len()));
+                timeline.steps[..target]
+                    .last()
+                    .and_then(|step| step.changes.last())
+                    .map(|(range, text)| (range.start + text.len()).min(document.buffer().len()))
+            };
+        playback.position = target;
+        document.mark_clean();
+        changed.send(DocumentChanged {
+            entity,
+            version: document.version(),
+            changes: document.take_changes(),
+            cursor,
+        });
+    }
+}
+
+fn replace(document: &mut Document, range: Range<usize>, text: &str) {
+    if !range.is_empty() {
+        document.apply_unrestricted(&Edit::Delete(range.clone()));
+    }
+    if !text.is_empty() {
+        document.apply_unrestricted(&Edit::Insert {
+            offset: range.start,
+            text: text.to_string(),
+        });
+    }
+}
+
+/// Starts the timeline of each document opened and adds what was done to it since. Steps
+/// are not trimmed while a replay of the document is open, so its position stays valid.
+#[allow(clippy::type_complexity)]
+fn record_steps(
+    mut commands: Commands,
+    mut events: EventReader<DocumentChanged>,
+    (time, user): (Res<Time>, Option<Res<Settings>>),
+    opened: Query<(Entity, &Document), (Added<Document>, Without<Playback>)>,
+    mut timelines: Query<(&Document, &mut Timeline)>,
+    playbacks: Query<&Playback>,
+) {
+    let settings = PlaybackSettings::new(user.as_deref());
+    let now = time.seconds_since_startup();
+    for (entity, document) in opened.iter() {
+        commands
+            .entity(entity)
+            .insert(Timeline::new(now, document.buffer().snapshot()));
+    }
+
+    for e in events.iter().filter(|e| !e.changes.is_empty()) {
+        let (document, mut timeline) = match timelines.get_mut(e.entity) {
+            Ok(found) => found,
+            Err(_) => continue,
+        };
+        timeline.steps.push(Step {
+            at: now,
+            changes: e
+                .changes
+                .iter()
+                .map(|change| (change.range.clone(), change.text.clone()))
+                .collect(),
+        });
+        let steps = timeline.steps.len();
+        let last = timeline.keyframes.last().map_or(0, |(i, _)| *i);
+        // Only a document that changed no further since this event reads as it did then.
+        if steps - last >= KEYFRAME_INTERVAL && e.version == document.version() {
+            timeline
+                .keyframes
+                .push((steps, document.buffer().snapshot()));
+        }
+        let replayed = playbacks.iter().any(|p| p.source == e.entity);
+        while !replayed && timeline.steps.len() > settings.max_steps && timeline.keyframes.len() > 1
+        {
+            timeline.trim();
+        }
+    }
+}
+
+fn send_playback(
+    (viewport, workspace): (Res<Viewport>, Option<Res<Workspace>>),
+    playbacks: Query<&Playback>,
+    timelines: Query<&Timeline>,
+    mut sent: Local<Option<PlaybackState>>,
+    mut ui: EventWriter<UICommand>,
+) {
+    let state = viewport
+        .shown(workspace.as_deref())
+        .and_then(|entity| Some((entity, playbacks.get(entity).ok()?)))
+        .and_then(|(entity, playback)| {
+            let timeline = timelines.get(playback.source).ok()?;
+            let start = timeline.at(0);
+            Some(PlaybackState {
+                entity,
+                position: playback.position,
+                steps: timeline.steps.len(),
+                playing: playback.playing,
+                elapsed: timeline.at(playback.position) - start,
+                duration: timeline.at(timeline.steps.len()) - start,
+            })
+        });
+    if *sent != state {
+        *sent = state.clone();
+        ui.send(UICommand::Playback(state));
+    }
+}
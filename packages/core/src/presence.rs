@@ -0,0 +1,386 @@
+//! Where the other participants of a shared session are: their cursors and selections,
+//! as the session's presence channel reports them with [`PresenceUpdate`], drawn in their
+//! color with their name next to the caret. Someone who stops moving fades out.
+//!
+//! This editor's own cursor goes the other way as [`SendPresence`], at most every
+//! [`PresenceSettings::interval`] so typing doesn't flood the channel.
+
+use crate::{
+    command::UICommand,
+    cursor::{Cursor, Selection},
+    damage::VisibleLines,
+    document::{Change, Document, DocumentChanged},
+    layout::Layout,
+    markers::{shift_range, ClearMarkers, MarkerStyle, SetMarkers, Stickiness, TextMarker},
+    permissions::Participant,
+    pipeline::{AppPipelineExt, EditorStage},
+    render::Viewport,
+    theme::{DesignTokens, Rgb},
+    workspace::Workspace,
+    zoom::LayoutMetrics,
+};
+use bevy::{
+    app::{App, Plugin},
+    core::Time,
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        query::{Changed, Or},
+        system::{Commands, Local, Query, Res},
+    },
+    log::debug,
+};
+use std::{collections::HashMap, time::Duration};
+
+/// The owner of the [`TextMarker`]s of remote cursors and selections.
+pub const MARKER_OWNER: &str = "presence";
+
+/// Colors participants are told apart by, picked by their id so everyone sees the same.
+const COLORS: [Rgb; 8] = [
+    Rgb(0xe5, 0x73, 0x73),
+    Rgb(0x64, 0xb5, 0xf6),
+    Rgb(0x81, 0xc7, 0x84),
+    Rgb(0xff, 0xb7, 0x4d),
+    Rgb(0xba, 0x68, 0xc8),
+    Rgb(0x4d, 0xd0, 0xe1),
+    Rgb(0xf0, 0x62, 0x92),
+    Rgb(0xdc, 0xe7, 0x75),
+];
+
+pub struct PresencePlugin;
+
+impl Plugin for PresencePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PresenceSettings>()
+            .add_event::<PresenceUpdate>()
+            .add_event::<PresenceLeft>()
+            .add_event::<SendPresence>()
+            .add_system(receive_presence)
+            .add_editor_system(EditorStage::Derive, shift_presence)
+            .add_editor_system(EditorStage::Present, send_presence)
+            .add_editor_system(EditorStage::Present, render_presence);
+    }
+}
+
+pub struct PresenceSettings {
+    /// Least time between two updates sent, or two redraws of remote cursors.
+    pub interval: Duration,
+    /// How long a participant may not move before they start fading out.
+    pub idle: Duration,
+    /// How long fading out takes, after which they are no longer drawn.
+    pub fade: Duration,
+}
+
+impl Default for PresenceSettings {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(100),
+            idle: Duration::from_secs(30),
+            fade: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Where a participant's cursor is in a document, from the presence channel.
+#[derive(Clone, Debug)]
+pub struct PresenceUpdate {
+    pub entity: Entity,
+    pub participant: Participant,
+    /// Shown next to the caret.
+    pub name: String,
+    pub offset: usize,
+    /// The other end of the selection, `offset` when nothing is selected.
+    pub anchor: usize,
+}
+
+/// A participant left the session, or closed the document if `entity` is set.
+#[derive(Clone, Debug)]
+pub struct PresenceLeft {
+    pub entity: Option<Entity>,
+    pub participant: Participant,
+}
+
+/// This editor's cursor moved in a document, for the session to tell the others.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SendPresence {
+    pub entity: Entity,
+    pub offset: usize,
+    pub anchor: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RemoteCursor {
+    pub name: String,
+    pub color: Rgb,
+    pub offset: usize,
+    pub anchor: usize,
+    /// When it last moved, in seconds since startup.
+    pub seen: f64,
+}
+
+/// Cursors of the other participants in a document, moving along with edits.
+#[derive(Component, Clone, Debug, Default)]
+pub struct Presence(HashMap<Participant, RemoteCursor>);
+
+impl Presence {
+    pub fn get(&self, participant: &Participant) -> Option<&RemoteCursor> {
+        self.0.get(participant)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Participant, &RemoteCursor)> {
+        self.0.iter()
+    }
+}
+
+/// The name tag of a remote caret on screen.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PresenceTag {
+    pub name: String,
+    pub color: Rgb,
+    /// From 1 down to 0 as the participant fades out.
+    pub opacity: f32,
+    /// From the top of the document, as [`crate::render::RenderedLine::top`].
+    pub top: f32,
+    /// From the start of the visual line.
+    pub x: f32,
+}
+
+/// The remote carets of the document on screen, sent when any moved, came or faded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PresenceTags {
+    pub entity: Entity,
+    pub tags: Vec<PresenceTag>,
+}
+
+/// The color of `participant`, the same for everyone in the session.
+pub fn participant_color(participant: &Participant) -> Rgb {
+    let hash = participant.0.bytes().fold(0usize, |hash, b| {
+        hash.wrapping_mul(31).wrapping_add(b as usize)
+    });
+    COLORS[hash % COLORS.len()]
+}
+
+/// How visible someone last seen at `seen` is at `now`.
+fn opacity(settings: &PresenceSettings, seen: f64, now: f64) -> f32 {
+    let idle = now - seen - settings.idle.as_secs_f64();
+    match idle <= 0. {
+        true => 1.,
+        false => (1. - idle / settings.fade.as_secs_f64().max(f64::EPSILON)).max(0.) as f32,
+    }
+}
+
+fn receive_presence(
+    mut commands: Commands,
+    (mut updates, mut left): (EventReader<PresenceUpdate>, EventReader<PresenceLeft>),
+    time: Res<Time>,
+    mut documents: Query<(Entity, &Document, Option<&mut Presence>)>,
+) {
+    let now = time.seconds_since_startup();
+    // Several participants may show up before the component is inserted.
+    let mut inserted: HashMap<Entity, Presence> = HashMap::new();
+    for e in updates.iter() {
+        let (_, document, current) = match documents.get_mut(e.entity) {
+            Ok(found) => found,
+            Err(_) => continue,
+        };
+        let len = document.buffer().len();
+        let cursor = RemoteCursor {
+            name: e.name.clone(),
+            color: participant_color(&e.participant),
+            offset: e.offset.min(len),
+            anchor: e.anchor.min(len),
+            seen: now,
+        };
+        match current {
+            Some(mut current) => current.0.insert(e.participant.clone(), cursor),
+            None => inserted
+                .entry(e.entity)
+                .or_default()
+                .0
+                .insert(e.participant.clone(), cursor),
+        };
+    }
+    for (entity, presence) in inserted {
+        commands.entity(entity).insert(presence);
+    }
+
+    for e in left.iter() {
+        debug!("👥 {} left {:?}", e.participant, e.entity);
+        for (entity, _, presence) in documents.iter_mut() {
+            if e.entity.is_some_and(|left| left != entity) {
+                continue;
+            }
+            if let Some(mut presence) = presence {
+                if presence.0.contains_key(&e.participant) {
+                    presence.0.remove(&e.participant);
+                }
+            }
+        }
+    }
+}
+
+fn shift_presence(mut changes: EventReader<DocumentChanged>, mut documents: Query<&mut Presence>) {
+    for e in changes.iter() {
+        let mut presence = match documents.get_mut(e.entity) {
+            Ok(presence) => presence,
+            Err(_) => continue,
+        };
+        // Left untouched without anyone, so nothing is drawn again for every edit.
+        if presence.0.is_empty() {
+            continue;
+        }
+        // Text typed right at a caret goes before it, as it would for the caret's owner.
+        let shift = |offset: usize, change: &Change| {
+            shift_range(&(offset..offset), change, Stickiness::GrowsAfter).start
+        };
+        for cursor in presence.0.values_mut() {
+            for change in &e.changes {
+                cursor.offset = shift(cursor.offset, change);
+                cursor.anchor = shift(cursor.anchor, change);
+            }
+        }
+    }
+}
+
+/// This editor's cursor, as of when it was last sent and whether it moved since.
+#[derive(Default)]
+struct Sent {
+    at: f64,
+    last: Option<SendPresence>,
+    pending: Option<SendPresence>,
+}
+
+#[allow(clippy::type_complexity)]
+fn send_presence(
+    settings: Res<PresenceSettings>,
+    time: Res<Time>,
+    moved: Query<(Entity, &Cursor, &Selection), Or<(Changed<Cursor>, Changed<Selection>)>>,
+    mut sent: Local<Sent>,
+    mut presence: EventWriter<SendPresence>,
+) {
+    for (entity, cursor, selection) in moved.iter() {
+        sent.pending = Some(SendPresence {
+            entity,
+            offset: cursor.offset,
+            anchor: selection.anchor,
+        });
+    }
+    let now = time.seconds_since_startup();
+    if now - sent.at < settings.interval.as_secs_f64() {
+        return;
+    }
+    if let Some(next) = sent.pending.take() {
+        if sent.last != Some(next) {
+            presence.send(next);
+            sent.last = Some(next);
+            sent.at = now;
+        }
+    }
+}
+
+/// When remote cursors were last drawn, and when they have to be again.
+#[derive(Default)]
+struct Drawn {
+    at: f64,
+    /// For someone to start or go on fading out.
+    wake: Option<f64>,
+    /// Something moved since.
+    pending: bool,
+}
+
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::type_complexity)]
+fn render_presence(
+    (viewport, workspace, settings, tokens): (
+        Res<Viewport>,
+        Option<Res<Workspace>>,
+        Res<PresenceSettings>,
+        Res<DesignTokens>,
+    ),
+    time: Res<Time>,
+    documents: Query<(&Document, &Presence, &Layout, &LayoutMetrics, &VisibleLines)>,
+    changed: Query<(), Or<(Changed<Presence>, Changed<Layout>, Changed<VisibleLines>)>>,
+    mut drawn: Local<Drawn>,
+    (mut set, mut clear): (EventWriter<SetMarkers>, EventWriter<ClearMarkers>),
+    mut ui: EventWriter<UICommand>,
+) {
+    let entity = match viewport.shown(workspace.as_deref()) {
+        Some(entity) => entity,
+        None => return,
+    };
+    let now = time.seconds_since_startup();
+    drawn.pending |= changed.get(entity).is_ok();
+    let due = drawn.pending || drawn.wake.is_some_and(|wake| now >= wake);
+    if now - drawn.at < settings.interval.as_secs_f64() || !due {
+        return;
+    }
+    let (document, presence, layout, metrics, visible) = match documents.get(entity) {
+        Ok(document) => document,
+        Err(_) => return,
+    };
+    let background = tokens
+        .color("editor.background")
+        .unwrap_or(Rgb(0x1e, 0x1e, 0x1e));
+    let buffer = document.buffer();
+
+    let mut markers = vec![];
+    let mut tags = vec![];
+    let mut wake: Option<f64> = None;
+    for (participant, cursor) in presence.0.iter() {
+        let opacity = opacity(&settings, cursor.seen, now);
+        if opacity <= 0. {
+            continue;
+        }
+        let fades_at = match opacity < 1. {
+            true => now,
+            false => cursor.seen + settings.idle.as_secs_f64(),
+        };
+        wake = Some(wake.map_or(fades_at, |wake| wake.min(fades_at)));
+        let color = cursor.color.mix(background, 1. - opacity);
+        let (start, end) = (
+            cursor.offset.min(cursor.anchor),
+            cursor.offset.max(cursor.anchor),
+        );
+        if start < end {
+            markers.push(TextMarker::new(
+                start..end,
+                MarkerStyle::Background(color.mix(background, 0.6)),
+            ));
+        }
+        let caret = cursor.offset.min(buffer.len());
+        markers.push(TextMarker::new(
+            caret..caret,
+            MarkerStyle::Widget(format!("{MARKER_OWNER}:{participant}")),
+        ));
+        let position = buffer.position_at(caret);
+        if visible.0.contains(&position.line) && layout.is_current(buffer.line_count()) {
+            let (visual, x) = layout.visual_position(buffer, position);
+            tags.push(PresenceTag {
+                name: cursor.name.clone(),
+                color,
+                opacity,
+                top: visual as f32 * metrics.line_height,
+                x,
+            });
+        }
+    }
+    *drawn = Drawn {
+        at: now,
+        wake,
+        pending: false,
+    };
+
+    match markers.is_empty() {
+        true => clear.send(ClearMarkers {
+            entity,
+            owner: Some(MARKER_OWNER.to_string()),
+        }),
+        false => set.send(SetMarkers {
+            entity,
+            owner: MARKER_OWNER.to_string(),
+            markers,
+        }),
+    }
+    ui.send(UICommand::Presence(PresenceTags { entity, tags }));
+}
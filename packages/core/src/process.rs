@@ -0,0 +1,392 @@
+use crate::{
+    idle::throttled,
+    shutdown::{AppShutdownExt, Shutdown, ShutdownStage},
+    toolchain::Toolchains,
+};
+use bevy::{
+    app::{App, AppExit, Plugin},
+    core::Time,
+    ecs::{
+        event::{EventReader, EventWriter},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Local, Res, ResMut},
+    },
+    log::{debug, warn},
+};
+use std::{
+    ffi::OsString,
+    fs, io, mem,
+    path::PathBuf,
+    process::{Child, Stdio},
+    time::{Duration, Instant},
+};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+const IDLE_SAMPLE_INTERVAL: Duration = Duration::from_secs(30);
+/// How long processes get to exit on their own during shutdown before they are killed.
+const TERMINATE_GRACE_SECS: f64 = 1.0;
+const SHUTDOWN_PARTICIPANT: &str = "processes";
+
+pub struct ProcessPlugin;
+
+impl Plugin for ProcessPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChildProcesses>()
+            .add_event::<SpawnProcess>()
+            .add_event::<RestartProcess>()
+            .add_event::<KillProcess>()
+            .add_event::<ProcessExited>()
+            .add_system(handle_process_commands)
+            .add_system(reap_processes)
+            .add_system(
+                sample_processes
+                    .with_run_criteria(throttled(SAMPLE_INTERVAL, IDLE_SAMPLE_INTERVAL)),
+            )
+            .add_system(stop_processes_on_shutdown)
+            .add_system(kill_processes_on_exit)
+            .add_shutdown_participant(ShutdownStage::StopProcesses, SHUTDOWN_PARTICIPANT);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ProcessKind {
+    LanguageServer,
+    Task,
+    Terminal,
+    Formatter,
+    /// A program started from a launch configuration.
+    Debuggee,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProcessSpec {
+    pub kind: ProcessKind,
+    pub name: String,
+    pub program: OsString,
+    pub args: Vec<OsString>,
+    /// Pipe stdin/stdout so the owner can talk to the process, e.g. a language server.
+    pub piped: bool,
+    /// Where it runs instead of the toolchain root.
+    pub cwd: Option<PathBuf>,
+    /// Set on top of the toolchain's environment.
+    pub env: Vec<(OsString, OsString)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ProcessId(pub u64);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Usage {
+    pub cpu_percent: f32,
+    pub memory_bytes: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProcessInfo {
+    pub id: ProcessId,
+    pub spec: ProcessSpec,
+    pub pid: u32,
+    pub started: Instant,
+    pub restarts: u32,
+    /// Last sampled resource usage. Only available where the platform exposes it (Linux).
+    pub usage: Option<Usage>,
+}
+
+struct Entry {
+    info: ProcessInfo,
+    child: Child,
+    cpu_ticks: Option<(u64, Instant)>,
+}
+
+/// Every child process spawned by the editor. Anything still running is killed when this is
+/// dropped, so servers never outlive the app.
+#[derive(Default)]
+pub struct ChildProcesses {
+    next_id: u64,
+    entries: Vec<Entry>,
+}
+
+impl ChildProcesses {
+    pub fn spawn(&mut self, spec: ProcessSpec, toolchains: &Toolchains) -> io::Result<ProcessId> {
+        let id = ProcessId(self.next_id);
+        self.next_id += 1;
+        let entry = start(id, spec, 0, toolchains)?;
+        self.entries.push(entry);
+        Ok(id)
+    }
+
+    pub fn restart(&mut self, id: ProcessId, toolchains: &Toolchains) -> io::Result<()> {
+        let i = self.index(id)?;
+        let old = &self.entries[i];
+        // Started before the old one is killed, so a failed spawn keeps the process around.
+        let entry = start(id, old.info.spec.clone(), old.info.restarts + 1, toolchains)?;
+        let old = mem::replace(&mut self.entries[i], entry);
+        terminate(old.child);
+        Ok(())
+    }
+
+    pub fn kill(&mut self, id: ProcessId) -> io::Result<()> {
+        let i = self.index(id)?;
+        terminate(self.entries.remove(i).child);
+        Ok(())
+    }
+
+    pub fn kill_all(&mut self) {
+        for entry in self.entries.drain(..) {
+            terminate(entry.child);
+        }
+    }
+
+    /// Asks every process to exit (SIGTERM on Unix) without waiting for it.
+    pub fn terminate_all(&self) {
+        #[cfg(unix)]
+        for entry in &self.entries {
+            // SAFETY: kill(2) has no memory safety preconditions.
+            unsafe {
+                libc::kill(entry.info.pid as libc::pid_t, libc::SIGTERM);
+            }
+        }
+    }
+
+    pub fn get(&self, id: ProcessId) -> Option<&ProcessInfo> {
+        self.entries.iter().map(|e| &e.info).find(|i| i.id == id)
+    }
+
+    /// Access to the underlying child, e.g. to take its piped stdin/stdout.
+    pub fn child_mut(&mut self, id: ProcessId) -> Option<&mut Child> {
+        self.entries
+            .iter_mut()
+            .find(|e| e.info.id == id)
+            .map(|e| &mut e.child)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ProcessInfo> {
+        self.entries.iter().map(|e| &e.info)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn index(&self, id: ProcessId) -> io::Result<usize> {
+        self.entries
+            .iter()
+            .position(|e| e.info.id == id)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{id:?} not found")))
+    }
+
+    fn reap(&mut self) -> Vec<ProcessExited> {
+        let mut exited = vec![];
+
+        self.entries.retain_mut(|e| match e.child.try_wait() {
+            Ok(Some(status)) => {
+                exited.push(ProcessExited {
+                    id: e.info.id,
+                    code: status.code(),
+                });
+                false
+            }
+            _ => true,
+        });
+
+        exited
+    }
+
+    fn sample(&mut self) {
+        for entry in &mut self.entries {
+            let now = Instant::now();
+            let sample = sample_usage(entry.info.pid);
+
+            entry.info.usage = sample.map(|(ticks, memory_bytes)| {
+                let cpu_percent = match entry.cpu_ticks {
+                    Some((last, at)) => {
+                        let secs = now.duration_since(at).as_secs_f32();
+                        let used = ticks.saturating_sub(last) as f32 / clock_ticks_per_sec();
+                        if secs > 0.0 {
+                            used / secs * 100.0
+                        } else {
+                            0.0
+                        }
+                    }
+                    None => 0.0,
+                };
+                entry.cpu_ticks = Some((ticks, now));
+
+                Usage {
+                    cpu_percent,
+                    memory_bytes,
+                }
+            });
+        }
+    }
+}
+
+impl Drop for ChildProcesses {
+    fn drop(&mut self) {
+        self.kill_all();
+    }
+}
+
+fn start(
+    id: ProcessId,
+    spec: ProcessSpec,
+    restarts: u32,
+    toolchains: &Toolchains,
+) -> io::Result<Entry> {
+    let mut command = toolchains.command(&spec.program);
+    command.args(&spec.args).envs(spec.env.iter().cloned());
+    if let Some(cwd) = &spec.cwd {
+        command.current_dir(cwd);
+    }
+    if spec.piped {
+        command.stdin(Stdio::piped()).stdout(Stdio::piped());
+    } else {
+        command.stdin(Stdio::null()).stdout(Stdio::null());
+    }
+
+    let child = command.spawn()?;
+    debug!("🚀 {} [{}] {:?}", spec.name, child.id(), spec.kind);
+
+    Ok(Entry {
+        info: ProcessInfo {
+            id,
+            pid: child.id(),
+            spec,
+            started: Instant::now(),
+            restarts,
+            usage: None,
+        },
+        child,
+        cpu_ticks: None,
+    })
+}
+
+fn terminate(mut child: Child) {
+    if let Err(e) = child.kill() {
+        if e.kind() != io::ErrorKind::InvalidInput {
+            warn!("Failed to kill process {}: {e}", child.id());
+        }
+    }
+    let _ = child.wait();
+}
+
+/// USER_HZ, the unit of the CPU times in `/proc/<pid>/stat`.
+#[cfg(unix)]
+fn clock_ticks_per_sec() -> f32 {
+    // SAFETY: sysconf(3) has no memory safety preconditions.
+    unsafe { libc::sysconf(libc::_SC_CLK_TCK) as f32 }
+}
+
+#[cfg(not(unix))]
+fn clock_ticks_per_sec() -> f32 {
+    100.0
+}
+
+/// The unit of the memory sizes in `/proc/<pid>/statm`.
+#[cfg(unix)]
+fn page_size() -> u64 {
+    // SAFETY: sysconf(3) has no memory safety preconditions.
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as u64 }
+}
+
+#[cfg(not(unix))]
+fn page_size() -> u64 {
+    4096
+}
+
+/// Total CPU ticks (user + system) and resident memory in bytes.
+fn sample_usage(pid: u32) -> Option<(u64, u64)> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // The command name may contain spaces, so fields are counted from its closing paren.
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+
+    let statm = fs::read_to_string(format!("/proc/{pid}/statm")).ok()?;
+    let resident: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+
+    Some((utime + stime, resident * page_size()))
+}
+
+#[derive(Clone, Debug)]
+pub struct SpawnProcess(pub ProcessSpec);
+
+#[derive(Clone, Copy, Debug)]
+pub struct RestartProcess(pub ProcessId);
+
+#[derive(Clone, Copy, Debug)]
+pub struct KillProcess(pub ProcessId);
+
+#[derive(Clone, Copy, Debug)]
+pub struct ProcessExited {
+    pub id: ProcessId,
+    pub code: Option<i32>,
+}
+
+fn handle_process_commands(
+    mut spawn: EventReader<SpawnProcess>,
+    mut restart: EventReader<RestartProcess>,
+    mut kill: EventReader<KillProcess>,
+    mut processes: ResMut<ChildProcesses>,
+    toolchains: Res<Toolchains>,
+) {
+    for SpawnProcess(spec) in spawn.iter() {
+        if let Err(e) = processes.spawn(spec.clone(), &toolchains) {
+            warn!("Failed to spawn {}: {e}", spec.name);
+        }
+    }
+    for RestartProcess(id) in restart.iter() {
+        if let Err(e) = processes.restart(*id, &toolchains) {
+            warn!("Failed to restart {id:?}: {e}");
+        }
+    }
+    for KillProcess(id) in kill.iter() {
+        if let Err(e) = processes.kill(*id) {
+            warn!("Failed to kill {id:?}: {e}");
+        }
+    }
+}
+
+fn reap_processes(mut processes: ResMut<ChildProcesses>, mut exited: EventWriter<ProcessExited>) {
+    for e in processes.reap() {
+        debug!("💀 {:?} exited with {:?}", e.id, e.code);
+        exited.send(e);
+    }
+}
+
+fn sample_processes(mut processes: ResMut<ChildProcesses>) {
+    processes.sample();
+}
+
+fn stop_processes_on_shutdown(
+    time: Res<Time>,
+    mut signalled_at: Local<Option<f64>>,
+    mut shutdown: ResMut<Shutdown>,
+    mut processes: ResMut<ChildProcesses>,
+) {
+    if !shutdown.is_pending(SHUTDOWN_PARTICIPANT) {
+        return;
+    }
+
+    let now = time.seconds_since_startup();
+    let signalled = *signalled_at.get_or_insert_with(|| {
+        processes.terminate_all();
+        now
+    });
+
+    processes.reap();
+    if processes.is_empty() || now - signalled >= TERMINATE_GRACE_SECS {
+        processes.kill_all();
+        shutdown.complete(SHUTDOWN_PARTICIPANT);
+    }
+}
+
+fn kill_processes_on_exit(mut events: EventReader<AppExit>, mut processes: ResMut<ChildProcesses>) {
+    if events.iter().count() > 0 {
+        processes.kill_all();
+    }
+}
@@ -0,0 +1,106 @@
+use crate::{
+    cursor::Cursor,
+    document::{Document, DocumentChanged, DocumentEditSet},
+    indent::IndentRules,
+    pipeline::{AppPipelineExt, EditorStage},
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Query, Res},
+    },
+    log::debug,
+};
+
+pub struct QuotesPlugin;
+
+impl Plugin for QuotesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ConvertQuotes>()
+            .add_editor_system(EditorStage::Edits, convert_quotes.label(DocumentEditSet));
+    }
+}
+
+/// Toggles the string at the cursor between double and single quotes. Strings are found
+/// with the quotes of the document's [`IndentRules`], so a language quoting with `'` has
+/// to list it there.
+#[derive(Clone, Copy, Debug)]
+pub struct ConvertQuotes {
+    pub entity: Entity,
+}
+
+/// `literal`, quotes included, with `quote` around it instead. The new quote is escaped
+/// inside and escapes of the old one are dropped.
+pub fn requote(literal: &str, quote: char) -> String {
+    let old = literal.chars().next().unwrap_or(quote);
+    let inner = &literal[old.len_utf8()..literal.len() - old.len_utf8()];
+
+    let mut text = String::with_capacity(literal.len() + 2);
+    text.push(quote);
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped) if escaped == old => text.push(old),
+                Some(escaped) => {
+                    text.push('\\');
+                    text.push(escaped);
+                }
+                None => text.push('\\'),
+            },
+            c if c == quote => {
+                text.push('\\');
+                text.push(c);
+            }
+            c => text.push(c),
+        }
+    }
+    text.push(quote);
+    text
+}
+
+fn convert_quotes(
+    mut events: EventReader<ConvertQuotes>,
+    default_rules: Res<IndentRules>,
+    mut documents: Query<(&mut Document, &Cursor, Option<&IndentRules>)>,
+    mut changed: EventWriter<DocumentChanged>,
+) {
+    for e in events.iter() {
+        let (mut document, cursor, rules) = match documents.get_mut(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let rules = rules.unwrap_or(&default_rules);
+        let buffer = document.buffer();
+        let line = buffer.line_at(cursor.offset);
+        let start = buffer.line_start(line);
+        let content = buffer.get_line_content(line);
+        let literal = match rules.string_at(&content, cursor.offset - start) {
+            Some(literal) => literal,
+            None => continue,
+        };
+        let text = &content[literal.clone()];
+        let quote = match text.chars().next() {
+            Some('"') => '\'',
+            Some('\'') => '"',
+            _ => continue,
+        };
+        let requoted = requote(text, quote);
+        debug!("📄 Converted {text} to {requoted}");
+
+        let range = start + literal.start..start + literal.end;
+        document.history_mut().begin();
+        document.delete(range.clone());
+        document.insert(range.start, &requoted);
+        document.history_mut().commit();
+        changed.send(DocumentChanged {
+            entity: e.entity,
+            version: document.version(),
+            changes: document.take_changes(),
+            cursor: None,
+        });
+    }
+}
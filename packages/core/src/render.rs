@@ -0,0 +1,390 @@
+//! Draws the document on screen a line at a time: only visible lines are laid out into
+//! runs of colored text, and only the ones [`Redraw`] names are laid out again. The view
+//! places each [`RenderedLine`] at its `top` in a box as tall as the whole document,
+//! scrolled to the [`Frame`]'s `top` and `left`. Those move by [`crate::scroll`], e.g. for
+//! the wheel, or by the view scrolling itself and reporting [`CoreCommand::Scroll`].
+
+use crate::{
+    command::{CoreCommand, UICommand},
+    damage::{CollectDamage, Redraw, VisibleLines},
+    document::{Document, DocumentChanged, LineChange},
+    fold::Folds,
+    layout::{monospace, Layout, LayoutSet, LineLayout},
+    pipeline::{AppPipelineExt, EditorStage},
+    syntax::Highlights,
+    theme::{DesignTokens, Rgb},
+    workspace::Workspace,
+    zoom::{LayoutMetrics, Scroll},
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        query::{Changed, Or},
+        schedule::{ParallelSystemDescriptorCoercion, SystemLabel},
+        system::{Commands, Query, Res, ResMut},
+    },
+    tasks::AsyncComputeTaskPool,
+};
+use std::{collections::BTreeMap, ops::Range};
+use unicode_segmentation::UnicodeSegmentation;
+
+pub struct RenderPlugin;
+
+impl Plugin for RenderPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Viewport>()
+            .add_editor_system(EditorStage::Input, follow_view)
+            .add_editor_system(
+                EditorStage::Layout,
+                show_document.after(LayoutSet).label(ShowDocument),
+            )
+            .add_editor_system(EditorStage::Present, render_lines.after(CollectDamage));
+    }
+}
+
+/// The system laying out the document on screen and finding its visible lines, which
+/// systems moving its [`Scroll`] run before.
+#[derive(SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ShowDocument;
+
+/// The area the view shows the document in, in physical pixels, updated by
+/// [`CoreCommand::Resize`]. The scroll offset is the document's [`Scroll`], so every
+/// document keeps its own.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Viewport {
+    /// The document on screen, the active one of the workspace if None.
+    pub document: Option<Entity>,
+    pub width: f32,
+    pub height: f32,
+    /// Wraps lines at `width` instead of scrolling sideways.
+    pub wrap: bool,
+}
+
+impl Default for Viewport {
+    /// A window's worth until the view reports its size.
+    fn default() -> Self {
+        Self {
+            document: None,
+            width: 800.,
+            height: 600.,
+            wrap: false,
+        }
+    }
+}
+
+impl Viewport {
+    /// The document on screen.
+    pub fn shown(&self, workspace: Option<&Workspace>) -> Option<Entity> {
+        self.document.or_else(|| workspace?.active())
+    }
+}
+
+/// Text of one color on a visual line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextRun {
+    pub text: String,
+    /// From the start of the visual line.
+    pub x: f32,
+    pub color: Rgb,
+}
+
+/// A line of the document as drawn, one visual line per row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderedLine {
+    pub line: usize,
+    /// Where its first row is drawn, from the top of the document.
+    pub top: f32,
+    pub rows: Vec<Vec<TextRun>>,
+}
+
+/// What the view shows of a document, sent whenever any of it was drawn again.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub entity: Entity,
+    /// Of the whole document, for the view to scroll through.
+    pub height: f32,
+    pub line_height: f32,
+    /// Where it is scrolled to, the document's [`Scroll`].
+    pub top: f32,
+    pub left: f32,
+    /// The visible lines, top to bottom.
+    pub lines: Vec<RenderedLine>,
+}
+
+/// Visible lines as last drawn, by line. Lines scrolled out of view are dropped.
+#[derive(Component, Clone, Debug, Default)]
+pub struct RenderedLines(BTreeMap<usize, RenderedLine>);
+
+impl RenderedLines {
+    /// Moves the lines below `change` along, dropping the ones it replaced.
+    fn record(&mut self, change: &LineChange) {
+        let old_end = change.start + change.removed + 1;
+        let new_end = change.start + change.inserted + 1;
+        let below = self.0.split_off(&old_end);
+        self.0.retain(|line, _| *line < change.start);
+        self.0.extend(below.into_iter().map(|(line, mut rendered)| {
+            rendered.line = line - old_end + new_end;
+            (rendered.line, rendered)
+        }));
+    }
+
+    fn retain(&mut self, visible: Range<usize>) {
+        self.0.retain(|line, _| visible.contains(line));
+    }
+}
+
+/// Lays out one line into rows of runs, colored by its highlights.
+pub fn render_line(
+    document: &Document,
+    line: usize,
+    layout: &LineLayout,
+    highlights: Option<&Highlights>,
+    tokens: &DesignTokens,
+) -> Vec<Vec<TextRun>> {
+    let buffer = document.buffer();
+    let start = buffer.line_start(line);
+    let content = buffer.get_line_content(line);
+    let spans = highlights.map_or(&[][..], |h| h.in_range(start..start + content.len()));
+    let foreground = tokens
+        .color("editor.foreground")
+        .unwrap_or(Rgb(0xd4, 0xd4, 0xd4));
+    let color_at = |offset: usize| {
+        spans
+            .iter()
+            .find(|span| span.range.contains(&(start + offset)))
+            .and_then(|span| tokens.syntax(highlights?.name(span)))
+            .unwrap_or(foreground)
+    };
+
+    let graphemes: Vec<(usize, &str)> = content.grapheme_indices(true).collect();
+    (0..layout.row_count())
+        .map(|row| {
+            let mut runs: Vec<TextRun> = vec![];
+            for column in layout.row_columns(row) {
+                let (offset, grapheme) = graphemes[column];
+                let color = color_at(offset);
+                match runs.last_mut() {
+                    Some(run) if run.color == color => run.text.push_str(grapheme),
+                    _ => runs.push(TextRun {
+                        text: grapheme.to_string(),
+                        x: layout.x_of(column),
+                        color,
+                    }),
+                }
+            }
+            runs
+        })
+        .collect()
+}
+
+/// The lines with a visual line between `top` and `top + height`.
+fn visible_lines(layout: &Layout, top: f32, height: f32, line_height: f32) -> Range<usize> {
+    let total = layout.visual_line_count();
+    if total == 0 || line_height <= 0. {
+        return 0..0;
+    }
+    let first = (top.max(0.) / line_height) as usize;
+    let last = (((top.max(0.) + height) / line_height).ceil() as usize).clamp(first + 1, total);
+    layout.line_at(first)..layout.line_at(last - 1) + 1
+}
+
+fn follow_view(
+    mut events: EventReader<CoreCommand>,
+    mut viewport: ResMut<Viewport>,
+    workspace: Option<Res<Workspace>>,
+    mut scrolls: Query<&mut Scroll>,
+) {
+    for e in events.iter() {
+        match *e {
+            CoreCommand::Resize { width, height }
+                if viewport.width != width || viewport.height != height =>
+            {
+                viewport.width = width;
+                viewport.height = height;
+            }
+            CoreCommand::Scroll { top, left } => {
+                let shown = viewport.shown(workspace.as_deref());
+                if let Some(mut scroll) = shown.and_then(|entity| scrolls.get_mut(entity).ok()) {
+                    if scroll.top != top || scroll.left != left {
+                        scroll.top = top;
+                        scroll.left = left;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Lays out the visible lines of the document on screen and tells which they are. The
+/// lines off screen are wrapped on the pool.
+#[allow(clippy::type_complexity)]
+fn show_document(
+    mut commands: Commands,
+    (viewport, workspace, pool): (
+        Res<Viewport>,
+        Option<Res<Workspace>>,
+        Res<AsyncComputeTaskPool>,
+    ),
+    mut documents: Query<(
+        &Document,
+        &LayoutMetrics,
+        &Scroll,
+        Option<&Folds>,
+        Option<&mut Layout>,
+        Option<&mut VisibleLines>,
+    )>,
+    metrics_changed: Query<(), Changed<LayoutMetrics>>,
+) {
+    let entity = match viewport.shown(workspace.as_deref()) {
+        Some(entity) => entity,
+        None => return,
+    };
+    let (document, metrics, scroll, folds, layout, visible) = match documents.get_mut(entity) {
+        Ok(document) => document,
+        Err(_) => return,
+    };
+    let buffer = document.buffer();
+    let unfolded = Folds::default();
+    let folds = folds.unwrap_or(&unfolded);
+    let width = viewport.wrap.then_some(viewport.width);
+    let measure = monospace(metrics.char_width);
+    let (top, height, line_height) = (scroll.top, viewport.height, metrics.line_height);
+
+    let next = match layout {
+        Some(mut layout) => {
+            // Only touched when it changes, the view is sent a frame when it does.
+            if metrics_changed.get(entity).is_ok() {
+                layout.set_measure(measure, folds);
+            }
+            if layout.width() != width {
+                layout.set_width(width, folds);
+            }
+            if layout.is_rebuilding() {
+                layout.receive(folds);
+            }
+            let mut next = visible_lines(&layout, top, height, line_height);
+            // Wrapping the visible lines may push some of them out of view.
+            for _ in 0..2 {
+                if layout.is_laid_out(next.clone()) {
+                    break;
+                }
+                layout.lay_out(buffer, next, folds);
+                next = visible_lines(&layout, top, height, line_height);
+            }
+            if layout.needs_rebuild() {
+                layout.rebuild(&pool, buffer);
+            }
+            next
+        }
+        None => {
+            let mut layout = Layout::new(buffer, width, measure, folds);
+            let mut next = visible_lines(&layout, top, height, line_height);
+            layout.lay_out(buffer, next, folds);
+            next = visible_lines(&layout, top, height, line_height);
+            layout.lay_out(buffer, next.clone(), folds);
+            layout.rebuild(&pool, buffer);
+            commands
+                .entity(entity)
+                .insert(layout)
+                .insert(RenderedLines::default());
+            next
+        }
+    };
+    match visible {
+        Some(mut visible) if visible.0 != next => visible.0 = next,
+        Some(_) => {}
+        None => {
+            commands.entity(entity).insert(VisibleLines(next));
+        }
+    }
+}
+
+/// Draws the damaged lines again, and the ones that came into view, and hands the visible
+/// ones to the view.
+#[allow(clippy::type_complexity)]
+fn render_lines(
+    mut redraws: EventReader<Redraw>,
+    mut changes: EventReader<DocumentChanged>,
+    tokens: Res<DesignTokens>,
+    mut documents: Query<(
+        &Document,
+        &Layout,
+        &LayoutMetrics,
+        &Scroll,
+        &VisibleLines,
+        Option<&Highlights>,
+        &mut RenderedLines,
+    )>,
+    moved: Query<Entity, Or<(Changed<Layout>, Changed<VisibleLines>, Changed<Scroll>)>>,
+    mut ui: EventWriter<UICommand>,
+) {
+    // Lines below an edit move without being damaged.
+    for e in changes.iter() {
+        if let Ok((.., mut rendered)) = documents.get_mut(e.entity) {
+            for change in &e.changes {
+                rendered.record(&change.lines);
+            }
+        }
+    }
+
+    let mut drawn: Vec<Entity> = moved.iter().collect();
+    for e in redraws.iter() {
+        if let Ok((.., mut rendered)) = documents.get_mut(e.entity) {
+            for lines in &e.lines {
+                for line in lines.clone() {
+                    rendered.0.remove(&line);
+                }
+            }
+            drawn.push(e.entity);
+        }
+    }
+    drawn.sort();
+    drawn.dedup();
+
+    for entity in drawn {
+        let (document, layout, metrics, scroll, visible, highlights, mut rendered) =
+            match documents.get_mut(entity) {
+                Ok(document) => document,
+                Err(_) => continue,
+            };
+        rendered.retain(visible.0.clone());
+        let line_height = metrics.line_height;
+        let mut lines = vec![];
+        for line in visible.0.clone() {
+            let visual = layout.visual_lines(line..line + 1);
+            // Folded away.
+            if visual.is_empty() {
+                continue;
+            }
+            let top = visual.start as f32 * line_height;
+            if let Some(drawn) = rendered.0.get_mut(&line) {
+                drawn.top = top;
+                lines.push(drawn.clone());
+                continue;
+            }
+            let line_layout = match layout.line(line) {
+                Some(line_layout) => line_layout,
+                None => continue,
+            };
+            let drawn = RenderedLine {
+                line,
+                top,
+                rows: render_line(document, line, line_layout, highlights, &tokens),
+            };
+            rendered.0.insert(line, drawn.clone());
+            lines.push(drawn);
+        }
+        ui.send(UICommand::Render(Frame {
+            entity,
+            height: layout.visual_line_count() as f32 * line_height,
+            line_height,
+            top: scroll.top,
+            left: scroll.left,
+            lines,
+        }));
+    }
+}
@@ -0,0 +1,368 @@
+use crate::{
+    generate::{GenerateSettings, Generators},
+    launch::InterpolationError,
+    workspace::Workspace,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        event::{EventReader, EventWriter},
+        system::Res,
+    },
+    log::{debug, warn},
+    tasks::IoTaskPool,
+};
+use dip_editor::date::civil_date;
+use std::{
+    collections::HashMap,
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Template folders looked up in every workspace root and in the home directory.
+pub const TEMPLATES_DIR: &str = ".dip/templates";
+const DEFAULT_LICENSE: &str = "MIT OR Apache-2.0";
+
+pub struct ScaffoldPlugin;
+
+impl Plugin for ScaffoldPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PendingScaffolds>()
+            .add_event::<Scaffold>()
+            .add_event::<ScaffoldFinished>()
+            .add_event::<ScaffoldFailed>()
+            .add_system(start_scaffolds)
+            .add_system(poll_scaffolds);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TemplateSource {
+    /// A folder whose contents are copied.
+    Local(PathBuf),
+    /// A repository cloned first, its `.git` folder is left out.
+    Git { url: String, rev: Option<String> },
+}
+
+/// Creates the files of `template` under `target`. `${name}` in file names and contents is
+/// replaced by `variables`, falling back to `projectName`, `date`, `year`, `license` and
+/// the [`Generators`], e.g. `${uuid}`.
+#[derive(Clone, Debug)]
+pub struct Scaffold {
+    pub template: TemplateSource,
+    pub target: PathBuf,
+    pub variables: HashMap<String, String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ScaffoldFinished {
+    pub target: PathBuf,
+    pub created: Vec<PathBuf>,
+}
+
+#[derive(Debug)]
+pub struct ScaffoldFailed {
+    pub target: PathBuf,
+    pub error: ScaffoldError,
+}
+
+#[derive(Debug)]
+pub enum ScaffoldError {
+    Io(io::Error),
+    Git(String),
+    /// Nothing is written over existing files.
+    Exists(PathBuf),
+    Interpolation(PathBuf, InterpolationError),
+}
+
+impl From<io::Error> for ScaffoldError {
+    fn from(e: io::Error) -> Self {
+        ScaffoldError::Io(e)
+    }
+}
+
+impl fmt::Display for ScaffoldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScaffoldError::Io(e) => write!(f, "{e}"),
+            ScaffoldError::Git(message) => write!(f, "git failed: {message}"),
+            ScaffoldError::Exists(path) => write!(f, "{} already exists", path.display()),
+            ScaffoldError::Interpolation(path, e) => write!(f, "{}: {e}", path.display()),
+        }
+    }
+}
+
+/// Template folders found under [`TEMPLATES_DIR`], by name. Roots come first, so a project
+/// can shadow a template from the home directory.
+pub fn available_templates(workspace: &Workspace) -> Vec<(String, PathBuf)> {
+    let home = env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from);
+    let dirs = workspace
+        .roots()
+        .iter()
+        .map(|root| root.path.join(TEMPLATES_DIR))
+        .chain(home.map(|home| home.join(TEMPLATES_DIR)));
+
+    let mut templates: Vec<(String, PathBuf)> = vec![];
+    for dir in dirs {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            if is_dir && templates.iter().all(|(n, _)| *n != name) {
+                templates.push((name, entry.path()));
+            }
+        }
+    }
+    templates
+}
+
+/// Runs `scaffold` to completion, returning the files created.
+pub fn scaffold(scaffold: &Scaffold) -> Result<Vec<PathBuf>, ScaffoldError> {
+    let variables = variables(&scaffold.target, &scaffold.variables);
+    match &scaffold.template {
+        TemplateSource::Local(dir) => copy_template(dir, &scaffold.target, &variables),
+        TemplateSource::Git { url, rev } => {
+            let checkout = checkout_dir()?;
+            let result = clone(url, rev.as_deref(), &checkout)
+                .and_then(|_| copy_template(&checkout, &scaffold.target, &variables));
+            let _ = fs::remove_dir_all(&checkout);
+            result
+        }
+    }
+}
+
+fn variables(target: &Path, given: &HashMap<String, String>) -> HashMap<String, String> {
+    let (year, month, day) = civil_date(unix_time() / 86_400);
+    let project_name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut variables = HashMap::from([
+        ("projectName".to_string(), project_name),
+        ("date".to_string(), format!("{year:04}-{month:02}-{day:02}")),
+        ("year".to_string(), year.to_string()),
+        ("license".to_string(), DEFAULT_LICENSE.to_string()),
+    ]);
+    variables.extend(given.iter().map(|(k, v)| (k.clone(), v.clone())));
+    variables
+}
+
+/// An empty folder of its own in the temp directory, created here so two scaffolds never
+/// clone into the same one.
+fn checkout_dir() -> io::Result<PathBuf> {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    loop {
+        let dir = env::temp_dir().join(format!(
+            "dip-template-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        match fs::create_dir(&dir) {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            result => return result.map(|_| dir),
+        }
+    }
+}
+
+/// Clones the latest commit, or the whole history when `rev` names a branch, a tag or a
+/// commit to check out.
+fn clone(url: &str, rev: Option<&str>, into: &Path) -> Result<(), ScaffoldError> {
+    let mut command = Command::new("git");
+    command.arg("clone").arg("--quiet");
+    match rev {
+        Some(_) => command.arg("--no-checkout"),
+        None => command.arg("--depth").arg("1"),
+    };
+    git(command.arg("--").arg(url).arg(into))?;
+
+    if let Some(rev) = rev {
+        if rev.starts_with('-') {
+            return Err(ScaffoldError::Git(format!("invalid revision {rev}")));
+        }
+        let mut command = Command::new("git");
+        command
+            .arg("-C")
+            .arg(into)
+            .args(["checkout", "--quiet", "--detach", rev]);
+        git(&mut command)?;
+    }
+    Ok(())
+}
+
+fn git(command: &mut Command) -> Result<(), ScaffoldError> {
+    let output = command.output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(ScaffoldError::Git(stderr.trim().to_string()))
+    }
+}
+
+/// Renders every file before writing any, so a conflict or a bad variable leaves nothing
+/// half written.
+fn copy_template(
+    template: &Path,
+    target: &Path,
+    variables: &HashMap<String, String>,
+) -> Result<Vec<PathBuf>, ScaffoldError> {
+    let mut files = vec![];
+    collect_files(template, template, &mut files)?;
+
+    let mut rendered = Vec::with_capacity(files.len());
+    for relative in files {
+        let interpolation = |e| ScaffoldError::Interpolation(relative.clone(), e);
+        let dest = interpolate(&relative.to_string_lossy(), variables).map_err(interpolation)?;
+        let dest = target.join(dest);
+        if dest.exists() {
+            return Err(ScaffoldError::Exists(dest));
+        }
+        let contents = match String::from_utf8(fs::read(template.join(&relative))?) {
+            Ok(text) => interpolate(&text, variables)
+                .map_err(interpolation)?
+                .into_bytes(),
+            // Binary files are copied as they are.
+            Err(e) => e.into_bytes(),
+        };
+        rendered.push((dest, contents));
+    }
+
+    let mut created = Vec::with_capacity(rendered.len());
+    for (dest, contents) in rendered {
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&dest, contents)?;
+        created.push(dest);
+    }
+    Ok(created)
+}
+
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            collect_files(root, &path, files)?;
+        } else {
+            files.push(path.strip_prefix(root).unwrap().to_path_buf());
+        }
+    }
+    Ok(())
+}
+
+/// Replaces `${name}` with its value. `$$` escapes a dollar sign.
+fn interpolate(
+    value: &str,
+    variables: &HashMap<String, String>,
+) -> Result<String, InterpolationError> {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        if let Some(after) = after.strip_prefix('$') {
+            out.push('$');
+            rest = after;
+        } else if let Some(after) = after.strip_prefix('{') {
+            let end = after.find('}').ok_or(InterpolationError::Unterminated)?;
+            let name = &after[..end];
+            let value = variables
+                .get(name)
+                .ok_or_else(|| InterpolationError::Unknown(name.to_string()))?;
+            out.push_str(value);
+            rest = &after[end + 1..];
+        } else {
+            out.push('$');
+            rest = after;
+        }
+    }
+    out.push_str(rest);
+
+    Ok(out)
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+type Outcome = (PathBuf, Result<Vec<PathBuf>, ScaffoldError>);
+
+/// Scaffolds run on the IO pool and report back here.
+struct PendingScaffolds {
+    sender: Mutex<Sender<Outcome>>,
+    receiver: Mutex<Receiver<Outcome>>,
+}
+
+impl Default for PendingScaffolds {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender: Mutex::new(sender),
+            receiver: Mutex::new(receiver),
+        }
+    }
+}
+
+fn start_scaffolds(
+    mut events: EventReader<Scaffold>,
+    pool: Res<IoTaskPool>,
+    pending: Res<PendingScaffolds>,
+    generators: Option<Res<Generators>>,
+    settings: Option<Res<GenerateSettings>>,
+) {
+    for e in events.iter() {
+        let mut e = e.clone();
+        if let (Some(generators), Some(settings)) = (&generators, &settings) {
+            for (name, value) in generators.variables(settings) {
+                e.variables.entry(name).or_insert(value);
+            }
+        }
+        let sender = pending.sender.lock().unwrap().clone();
+        debug!("🧩 Scaffolding {}", e.target.display());
+        pool.spawn(async move {
+            let result = scaffold(&e);
+            let _ = sender.send((e.target, result));
+        })
+        .detach();
+    }
+}
+
+fn poll_scaffolds(
+    pending: Res<PendingScaffolds>,
+    mut finished: EventWriter<ScaffoldFinished>,
+    mut failed: EventWriter<ScaffoldFailed>,
+) {
+    let receiver = pending.receiver.lock().unwrap();
+    while let Ok((target, result)) = receiver.try_recv() {
+        match result {
+            Ok(created) => {
+                debug!("🧩 Created {} files in {}", created.len(), target.display());
+                finished.send(ScaffoldFinished { target, created });
+            }
+            Err(error) => {
+                warn!("🧩 Failed to scaffold {}: {error}", target.display());
+                failed.send(ScaffoldFailed { target, error });
+            }
+        }
+    }
+}
@@ -0,0 +1,397 @@
+//! Moving the [`Scroll`] of the document on screen: by the mouse wheel and the trackpad,
+//! sent by the view as [`CoreCommand::Wheel`], and along with the cursor so it stays in
+//! view with some lines around it. Moves of more than a few pixels are animated with
+//! [`SmoothScroll`] unless `editor.smoothScrolling` is off.
+
+use crate::{
+    command::CoreCommand,
+    config::Settings,
+    cursor::Cursor,
+    document::Document,
+    layout::{Layout, LayoutSet},
+    pipeline::{AppPipelineExt, EditorStage},
+    render::{ShowDocument, Viewport},
+    workspace::Workspace,
+    zoom::{LayoutMetrics, Scroll},
+};
+use bevy::{
+    app::{App, Plugin},
+    core::Time,
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::EventReader,
+        query::{Added, Changed, Without},
+        schedule::{ParallelSystemDescriptorCoercion, SystemLabel},
+        system::{Commands, Query, Res},
+        world::Mut,
+    },
+};
+use serde::Deserialize;
+use std::time::Duration;
+
+pub struct ScrollPlugin;
+
+/// The system keeping the cursor in view, which [`SmoothScroll`] animations step after.
+#[derive(SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RevealCursor;
+
+impl Plugin for ScrollPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(attach_smooth_scroll)
+            .add_editor_system(EditorStage::Input, scroll_by_wheel)
+            .add_editor_system(
+                EditorStage::Layout,
+                reveal_cursor
+                    .label(RevealCursor)
+                    .after(LayoutSet)
+                    .before(ShowDocument),
+            )
+            .add_editor_system(
+                EditorStage::Layout,
+                animate_scroll
+                    .after(RevealCursor)
+                    .after(LayoutSet)
+                    .before(ShowDocument),
+            );
+    }
+}
+
+/// `editor.smoothScrollingEasing` in the settings, how an animation speeds up and slows
+/// down.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Easing {
+    Linear,
+    #[default]
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    /// How far along an animation `t` of the way through it is, both from 0 to 1.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0., 1.);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseOut => 1. - (1. - t).powi(3),
+            Easing::EaseInOut if t < 0.5 => 4. * t * t * t,
+            Easing::EaseInOut => 1. - (-2. * t + 2.).powi(3) / 2.,
+        }
+    }
+}
+
+/// How the wheel amounts of [`CoreCommand::Wheel`] are counted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WheelUnit {
+    /// Physical pixels, as trackpads report them, followed right away.
+    Pixel,
+    /// Lines, as mouse wheels report them, animated.
+    Line,
+    Page,
+}
+
+/// From `editor.smoothScrolling`, `editor.smoothScrollingEasing`,
+/// `editor.cursorSurroundingLines` and `editor.mouseWheelScrollSensitivity`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScrollSettings {
+    pub smooth: bool,
+    pub easing: Easing,
+    pub duration: Duration,
+    /// Lines kept in view above and below the cursor.
+    pub margin_lines: usize,
+    /// Columns kept in view left and right of the cursor when lines don't wrap.
+    pub margin_columns: usize,
+    /// Lines scrolled by a notch of the wheel.
+    pub wheel_lines: f32,
+}
+
+impl Default for ScrollSettings {
+    fn default() -> Self {
+        Self {
+            smooth: true,
+            easing: Easing::EaseOut,
+            duration: Duration::from_millis(125),
+            margin_lines: 3,
+            margin_columns: 4,
+            wheel_lines: 3.,
+        }
+    }
+}
+
+impl ScrollSettings {
+    pub fn new(settings: Option<&Settings>) -> Self {
+        let default = Self::default();
+        let settings = match settings {
+            Some(settings) => settings,
+            None => return default,
+        };
+        let sensitivity: f32 = settings
+            .get("editor.mouseWheelScrollSensitivity")
+            .unwrap_or(1.);
+        Self {
+            smooth: settings
+                .get("editor.smoothScrolling")
+                .unwrap_or(default.smooth),
+            easing: settings
+                .get("editor.smoothScrollingEasing")
+                .unwrap_or(default.easing),
+            margin_lines: settings
+                .get("editor.cursorSurroundingLines")
+                .unwrap_or(default.margin_lines),
+            wheel_lines: default.wheel_lines * sensitivity,
+            ..default
+        }
+    }
+}
+
+/// A scroll under way, from one offset to another.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScrollAnimation {
+    pub from: Scroll,
+    pub to: Scroll,
+    /// Seconds since startup.
+    pub start: f64,
+    pub duration: f32,
+    pub easing: Easing,
+}
+
+impl ScrollAnimation {
+    /// Where it is at `now`, and whether it is done.
+    fn at(&self, now: f64) -> (Scroll, bool) {
+        let t = match self.duration > 0. {
+            true => ((now - self.start) as f32 / self.duration).min(1.),
+            false => 1.,
+        };
+        let eased = self.easing.apply(t);
+        let lerp = |from: f32, to: f32| from + (to - from) * eased;
+        let at = Scroll {
+            top: lerp(self.from.top, self.to.top),
+            left: lerp(self.from.left, self.to.left),
+        };
+        (at, t >= 1.)
+    }
+}
+
+/// Where the [`Scroll`] of a view is headed.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq)]
+pub struct SmoothScroll {
+    pub animation: Option<ScrollAnimation>,
+}
+
+impl SmoothScroll {
+    /// Where scrolling ends up, for moves adding up while one is under way.
+    pub fn target(&self, scroll: &Scroll) -> Scroll {
+        self.animation.map_or(*scroll, |animation| animation.to)
+    }
+
+    /// Scrolls to `to`, animated from where it is now.
+    fn start(&mut self, scroll: &Scroll, to: Scroll, now: f64, settings: &ScrollSettings) {
+        self.animation = Some(ScrollAnimation {
+            from: *scroll,
+            to,
+            start: now,
+            duration: settings.duration.as_secs_f32(),
+            easing: settings.easing,
+        });
+    }
+}
+
+/// The farthest the document on screen scrolls, with its last line at the bottom and its
+/// longest line's end on the right.
+fn max_scroll(layout: &Layout, metrics: &LayoutMetrics, viewport: &Viewport) -> Scroll {
+    let height = layout.visual_line_count() as f32 * metrics.line_height;
+    let width = match viewport.wrap {
+        true => 0.,
+        false => layout.max_line_width(),
+    };
+    Scroll {
+        top: (height - viewport.height).max(0.),
+        left: (width - viewport.width).max(0.),
+    }
+}
+
+fn clamp(scroll: Scroll, max: Scroll) -> Scroll {
+    Scroll {
+        top: scroll.top.clamp(0., max.top),
+        left: scroll.left.clamp(0., max.left),
+    }
+}
+
+/// Moves `scroll` to `to`, animated if it is far enough and smooth scrolling is on.
+/// Only touched when it moves, as the view is sent a frame when it does.
+fn scroll_to(
+    (scroll, smooth): (&mut Mut<Scroll>, &mut Mut<SmoothScroll>),
+    to: Scroll,
+    now: f64,
+    settings: &ScrollSettings,
+) {
+    let far = (to.top - scroll.top).abs() > 1. || (to.left - scroll.left).abs() > 1.;
+    if settings.smooth && far {
+        smooth.start(scroll, to, now, settings);
+        return;
+    }
+    if smooth.animation.is_some() {
+        smooth.animation = None;
+    }
+    if **scroll != to {
+        **scroll = to;
+    }
+}
+
+fn attach_smooth_scroll(
+    mut commands: Commands,
+    views: Query<Entity, (Added<Scroll>, Without<SmoothScroll>)>,
+) {
+    for entity in views.iter() {
+        commands.entity(entity).insert(SmoothScroll::default());
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn scroll_by_wheel(
+    mut events: EventReader<CoreCommand>,
+    (viewport, workspace, user, time): (
+        Res<Viewport>,
+        Option<Res<Workspace>>,
+        Option<Res<Settings>>,
+        Res<Time>,
+    ),
+    mut views: Query<(&Layout, &LayoutMetrics, &mut Scroll, &mut SmoothScroll)>,
+) {
+    let settings = ScrollSettings::new(user.as_deref());
+    let now = time.seconds_since_startup();
+    for e in events.iter() {
+        let entity = match viewport.shown(workspace.as_deref()) {
+            Some(entity) => entity,
+            None => continue,
+        };
+        let (layout, metrics, mut scroll, mut smooth) = match views.get_mut(entity) {
+            Ok(view) => view,
+            Err(_) => continue,
+        };
+        match *e {
+            // Scrolled by the view, which is already there.
+            CoreCommand::Scroll { .. } if smooth.animation.is_some() => smooth.animation = None,
+            CoreCommand::Wheel { x, y, unit } => {
+                let (dx, dy) = match unit {
+                    WheelUnit::Pixel => (x, y),
+                    WheelUnit::Line => {
+                        let lines = settings.wheel_lines;
+                        (
+                            x * lines * metrics.char_width,
+                            y * lines * metrics.line_height,
+                        )
+                    }
+                    WheelUnit::Page => (x * viewport.width, y * viewport.height),
+                };
+                let from = smooth.target(&scroll);
+                let to = clamp(
+                    Scroll {
+                        top: from.top + dy,
+                        left: from.left + dx,
+                    },
+                    max_scroll(layout, metrics, &viewport),
+                );
+                let settings = match unit {
+                    // Already smooth, and animating would lag behind the fingers.
+                    WheelUnit::Pixel => ScrollSettings {
+                        smooth: false,
+                        ..settings
+                    },
+                    _ => settings,
+                };
+                scroll_to((&mut scroll, &mut smooth), to, now, &settings);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Scrolls the cursor of the document on screen into view when it moved, keeping the
+/// margins around it.
+#[allow(clippy::type_complexity)]
+fn reveal_cursor(
+    (viewport, workspace, user, time): (
+        Res<Viewport>,
+        Option<Res<Workspace>>,
+        Option<Res<Settings>>,
+        Res<Time>,
+    ),
+    mut views: Query<(
+        &Document,
+        &Cursor,
+        &Layout,
+        &LayoutMetrics,
+        &mut Scroll,
+        &mut SmoothScroll,
+    )>,
+    moved: Query<(), Changed<Cursor>>,
+) {
+    let entity = match viewport.shown(workspace.as_deref()) {
+        Some(entity) => entity,
+        None => return,
+    };
+    if moved.get(entity).is_err() {
+        return;
+    }
+    let (document, cursor, layout, metrics, mut scroll, mut smooth) = match views.get_mut(entity) {
+        Ok(view) => view,
+        Err(_) => return,
+    };
+    let buffer = document.buffer();
+    if !layout.is_current(buffer.line_count()) || metrics.line_height <= 0. {
+        return;
+    }
+    let settings = ScrollSettings::new(user.as_deref());
+    let (visual, x) = layout.visual_position(buffer, cursor.position(buffer));
+    let line_height = metrics.line_height;
+    // Half the view at most, or the cursor could never be in view.
+    let rows = (viewport.height / line_height).floor() as usize;
+    let margin = settings.margin_lines.min(rows.saturating_sub(1) / 2) as f32 * line_height;
+    let top = visual as f32 * line_height;
+
+    let current = smooth.target(&scroll);
+    let mut to = current;
+    if top - margin < current.top {
+        to.top = top - margin;
+    } else if top + line_height + margin > current.top + viewport.height {
+        to.top = top + line_height + margin - viewport.height;
+    }
+    if !viewport.wrap {
+        let columns = (viewport.width / metrics.char_width.max(1.)).floor() as usize;
+        let margin =
+            settings.margin_columns.min(columns.saturating_sub(1) / 2) as f32 * metrics.char_width;
+        if x - margin < current.left {
+            to.left = x - margin;
+        } else if x + margin > current.left + viewport.width {
+            to.left = x + margin - viewport.width;
+        }
+    }
+    let to = clamp(to, max_scroll(layout, metrics, &viewport));
+    if to != current {
+        scroll_to(
+            (&mut scroll, &mut smooth),
+            to,
+            time.seconds_since_startup(),
+            &settings,
+        );
+    }
+}
+
+fn animate_scroll(time: Res<Time>, mut views: Query<(&mut Scroll, &mut SmoothScroll)>) {
+    let now = time.seconds_since_startup();
+    for (mut scroll, mut smooth) in views.iter_mut() {
+        let animation = match smooth.animation {
+            Some(animation) => animation,
+            None => continue,
+        };
+        let (at, done) = animation.at(now);
+        if *scroll != at {
+            *scroll = at;
+        }
+        if done {
+            smooth.animation = None;
+        }
+    }
+}
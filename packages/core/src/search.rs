@@ -0,0 +1,75 @@
+use crate::{
+    announce::{count, Announcement},
+    document::{Document, DocumentChanged, DocumentEditSet},
+    pipeline::{AppPipelineExt, EditorStage},
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::Query,
+    },
+    log::{debug, warn},
+};
+
+pub use crate::text_buffer::search::{
+    build_regex, find_literal, regex_captures, Find, LiteralScanner, SearchOptions,
+};
+
+pub struct SearchPlugin;
+
+impl Plugin for SearchPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ReplaceAll>()
+            .add_editor_system(EditorStage::Edits, replace_all.label(DocumentEditSet));
+    }
+}
+
+/// Replaces every match of the regex `pattern` in one undo step. `$1` or `${name}` in
+/// `replacement` insert capture groups.
+#[derive(Clone, Debug)]
+pub struct ReplaceAll {
+    pub entity: Entity,
+    pub pattern: String,
+    pub replacement: String,
+    pub options: SearchOptions,
+}
+
+fn replace_all(
+    mut events: EventReader<ReplaceAll>,
+    mut documents: Query<&mut Document>,
+    mut changed: EventWriter<DocumentChanged>,
+    mut announce: EventWriter<Announcement>,
+) {
+    for e in events.iter() {
+        let mut document = match documents.get_mut(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let regex = match build_regex(&e.pattern, e.options) {
+            Ok(regex) => regex,
+            Err(err) => {
+                warn!("🔎 Invalid pattern {:?}: {err}", e.pattern);
+                continue;
+            }
+        };
+        let replaced = document.replace_all(&regex, &e.replacement);
+        debug!("🔎 Replaced {replaced} matches of {:?}", e.pattern);
+        if replaced == 0 {
+            announce.send(Announcement::polite("No matches"));
+            continue;
+        }
+        announce.send(Announcement::polite(format!(
+            "Replaced {}",
+            count(replaced, "match")
+        )));
+        changed.send(DocumentChanged {
+            entity: e.entity,
+            version: document.version(),
+            changes: document.take_changes(),
+            cursor: None,
+        });
+    }
+}
@@ -0,0 +1,310 @@
+use crate::{
+    cursor::{clamp, Cursor, SecondaryCursor, Selection},
+    document::{same_file, Document, DocumentLoadFailed, DocumentOpened, OpenDocument},
+    fold::{Folds, FoldsChanged},
+    journal::state_dir,
+    shutdown::{AppShutdownExt, Shutdown, ShutdownStage, ShutdownStageStarted},
+    tab::{ClosedTab, RecentlyClosed},
+    workspace::{FocusDocument, Workspace},
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        system::{Commands, Query, Res, ResMut},
+    },
+    log::{debug, warn},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+/// Bumped when the schema changes in a way older builds can't read. Sessions of a newer
+/// version are left alone.
+pub const SESSION_VERSION: u32 = 1;
+
+const PARTICIPANT: &str = "session";
+
+pub struct SessionPlugin;
+
+impl Plugin for SessionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SessionSettings>()
+            .init_resource::<Session>()
+            .add_shutdown_participant(ShutdownStage::Flush, PARTICIPANT)
+            .add_startup_system(restore_session)
+            .add_system(match_opened)
+            .add_system(restore_documents)
+            .add_system(save_session);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SessionSettings {
+    /// Where sessions are kept, one per directory dip was started in. None turns sessions
+    /// off.
+    pub dir: Option<PathBuf>,
+    /// Reopens the last session on startup, otherwise it is only saved.
+    pub restore: bool,
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        Self {
+            dir: state_dir().map(|dir| dir.join("sessions")),
+            restore: true,
+        }
+    }
+}
+
+impl SessionSettings {
+    /// The session of the current directory.
+    pub fn file(&self) -> Option<PathBuf> {
+        let root = env::current_dir().ok()?;
+        let digest = Sha256::digest(root.to_string_lossy().as_bytes());
+        let name: String = digest[..8].iter().map(|b| format!("{b:02x}")).collect();
+        Some(self.dir.as_ref()?.join(name).with_extension("json"))
+    }
+}
+
+/// What is written on exit. Fields added later need `#[serde(default)]` so older sessions
+/// still read.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct SessionFile {
+    pub version: u32,
+    /// In the order they were opened. Untitled documents are left out.
+    #[serde(default)]
+    pub documents: Vec<SessionDocument>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active: Option<PathBuf>,
+    /// Most recently closed first, to reopen them after a restart.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub closed: Vec<ClosedTab>,
+}
+
+impl SessionFile {
+    pub fn read(file: &Path) -> io::Result<Self> {
+        let source = fs::read_to_string(file)?;
+        serde_json::from_str(&source).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn write(&self, file: &Path) -> io::Result<()> {
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Written aside and renamed, so a crash never leaves half a session.
+        let temp = file.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(&temp, file)
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SessionDocument {
+    pub path: PathBuf,
+    /// The primary cursor first.
+    #[serde(default)]
+    pub cursors: Vec<SessionCursor>,
+    /// Folded line ranges.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub folds: Vec<Range<usize>>,
+}
+
+/// Byte offsets, snapped into the document if it changed since.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SessionCursor {
+    pub offset: usize,
+    pub anchor: usize,
+}
+
+/// The documents of the restored session still being opened.
+#[derive(Debug, Default)]
+pub struct Session {
+    opening: Vec<SessionDocument>,
+    opened: HashMap<Entity, SessionDocument>,
+    active: Option<PathBuf>,
+    focus: Option<Entity>,
+}
+
+impl Session {
+    /// Whether documents of the last session are still being opened.
+    pub fn is_restoring(&self) -> bool {
+        !self.opening.is_empty() || !self.opened.is_empty()
+    }
+}
+
+fn restore_session(
+    settings: Res<SessionSettings>,
+    mut session: ResMut<Session>,
+    mut closed: ResMut<RecentlyClosed>,
+    mut open: EventWriter<OpenDocument>,
+) {
+    let file = match settings.file() {
+        Some(file) if settings.restore && file.exists() => file,
+        _ => return,
+    };
+    let saved = match SessionFile::read(&file) {
+        Ok(saved) if saved.version <= SESSION_VERSION => saved,
+        Ok(saved) => {
+            warn!("💾 Not restoring session version {}", saved.version);
+            return;
+        }
+        Err(error) => {
+            warn!("💾 Failed to read {}: {error}", file.display());
+            return;
+        }
+    };
+    debug!("💾 Restoring {} documents", saved.documents.len());
+    for document in saved.documents {
+        // Files removed since are dropped from the session.
+        if document.path.is_file() {
+            open.send(OpenDocument {
+                path: document.path.clone(),
+            });
+            session.opening.push(document);
+        }
+    }
+    session.active = saved.active;
+    for tab in saved.closed.into_iter().rev() {
+        if tab.path.is_file() {
+            closed.push(tab);
+        }
+    }
+}
+
+fn match_opened(
+    mut opened: EventReader<DocumentOpened>,
+    mut failed: EventReader<DocumentLoadFailed>,
+    mut session: ResMut<Session>,
+) {
+    if session.opening.is_empty() {
+        return;
+    }
+    let session = &mut *session;
+    for e in opened.iter() {
+        if let Some(i) = session
+            .opening
+            .iter()
+            .position(|d| same_file(&d.path, &e.path))
+        {
+            let document = session.opening.remove(i);
+            if session.active.as_deref() == Some(document.path.as_path()) {
+                session.focus = Some(e.entity);
+            }
+            session.opened.insert(e.entity, document);
+        }
+    }
+    for e in failed.iter() {
+        session.opening.retain(|d| !same_file(&d.path, &e.path));
+    }
+}
+
+fn restore_documents(
+    mut commands: Commands,
+    mut session: ResMut<Session>,
+    mut documents: Query<(&Document, &mut Cursor, &mut Selection, &mut Folds)>,
+    (mut folds_changed, mut focus): (EventWriter<FoldsChanged>, EventWriter<FocusDocument>),
+) {
+    let session = &mut *session;
+    session.opened.retain(|&entity, saved| {
+        // Cursors and folds are attached a frame after the document is spawned.
+        let (document, mut cursor, mut selection, mut folds) = match documents.get_mut(entity) {
+            Ok(found) => found,
+            Err(_) => return true,
+        };
+        let buffer = document.buffer();
+        for (i, saved) in saved.cursors.iter().enumerate() {
+            let offset = clamp(buffer, saved.offset);
+            let anchor = Selection {
+                anchor: clamp(buffer, saved.anchor),
+            };
+            if i == 0 {
+                *cursor = Cursor::at(offset);
+                *selection = anchor;
+            } else {
+                commands
+                    .spawn()
+                    .insert(SecondaryCursor { document: entity })
+                    .insert(Cursor::at(offset))
+                    .insert(anchor);
+            }
+        }
+        let lines = buffer.line_count();
+        let mut folded = false;
+        for range in saved.folds.iter().filter(|range| range.end <= lines) {
+            folded |= folds.fold(range.clone());
+        }
+        if folded {
+            folds_changed.send(FoldsChanged { entity });
+        }
+        false
+    });
+    // Opening focuses each document in turn, so the active one is focused last.
+    if !session.is_restoring() {
+        if let Some(entity) = session.focus.take() {
+            focus.send(FocusDocument { entity });
+        }
+    }
+}
+
+fn save_session(
+    mut started: EventReader<ShutdownStageStarted>,
+    (settings, workspace, closed): (Res<SessionSettings>, Res<Workspace>, Res<RecentlyClosed>),
+    documents: Query<(&Document, &Cursor, &Selection, Option<&Folds>)>,
+    secondary: Query<(&SecondaryCursor, &Cursor, &Selection)>,
+    mut shutdown: ResMut<Shutdown>,
+) {
+    if !started.iter().any(|e| e.0 == ShutdownStage::Flush) {
+        return;
+    }
+    shutdown.complete(PARTICIPANT);
+    let file = match settings.file() {
+        Some(file) => file,
+        None => return,
+    };
+    let path = |entity| documents.get(entity).ok()?.0.path();
+    let mut session = SessionFile {
+        version: SESSION_VERSION,
+        documents: vec![],
+        active: workspace.active().and_then(path).map(Path::to_path_buf),
+        closed: closed.iter().cloned().collect(),
+    };
+    for &entity in workspace.documents() {
+        let (document, cursor, selection, folds) = match documents.get(entity) {
+            Ok(found) => found,
+            Err(_) => continue,
+        };
+        let path = match document.path() {
+            Some(path) => path,
+            None => continue,
+        };
+        let mut cursors = vec![SessionCursor {
+            offset: cursor.offset,
+            anchor: selection.anchor,
+        }];
+        cursors.extend(
+            secondary
+                .iter()
+                .filter(|(secondary, _, _)| secondary.document == entity)
+                .map(|(_, cursor, selection)| SessionCursor {
+                    offset: cursor.offset,
+                    anchor: selection.anchor,
+                }),
+        );
+        session.documents.push(SessionDocument {
+            path: path.to_path_buf(),
+            cursors,
+            folds: folds.map_or(vec![], |folds| folds.folded().to_vec()),
+        });
+    }
+    match session.write(&file) {
+        Ok(()) => debug!("💾 Saved session of {} documents", session.documents.len()),
+        Err(error) => warn!("💾 Failed to write {}: {error}", file.display()),
+    }
+}
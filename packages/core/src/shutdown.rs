@@ -0,0 +1,355 @@
+use crate::{
+    command::CoreCommand,
+    document::{
+        Document, DocumentSaveFailed, DocumentSaved, SaveConflict, SaveDocument, VirtualDocument,
+    },
+};
+use bevy::{
+    app::{App, AppExit, Plugin},
+    core::Time,
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        query::Without,
+        system::{Local, Query, Res, ResMut},
+    },
+    log::{debug, warn},
+};
+use std::{collections::HashMap, time::Duration};
+
+pub struct ShutdownPlugin;
+
+impl Plugin for ShutdownPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Shutdown>()
+            .init_resource::<ShutdownSettings>()
+            .add_event::<RequestShutdown>()
+            .add_event::<CancelShutdown>()
+            .add_event::<ShutdownStageStarted>()
+            .add_event::<UnsavedChangesPrompt>()
+            .add_event::<ResolveUnsavedChanges>()
+            .add_shutdown_participant(ShutdownStage::UnsavedChanges, UNSAVED_CHANGES)
+            .add_system(request_shutdown_on_exit)
+            .add_system(start_shutdown)
+            .add_system(resolve_unsaved_changes)
+            .add_system(advance_shutdown);
+    }
+}
+
+const UNSAVED_CHANGES: &str = "unsaved changes";
+
+/// What to do with dirty documents when the app is asked to quit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnsavedChangesPolicy {
+    /// Asks with [`UnsavedChangesPrompt`] and waits for the answer.
+    Prompt,
+    /// Saves the documents with a path. Untitled ones have nowhere to go, so they are
+    /// asked about anyway.
+    AutoSave,
+    Discard,
+}
+
+pub struct ShutdownSettings {
+    pub unsaved_changes: UnsavedChangesPolicy,
+    /// How long a stage may take before it is skipped. The unsaved changes stage waits for the
+    /// user and never times out.
+    pub stage_timeout: Duration,
+}
+
+impl Default for ShutdownSettings {
+    fn default() -> Self {
+        Self {
+            unsaved_changes: UnsavedChangesPolicy::Prompt,
+            stage_timeout: Duration::from_secs(3),
+        }
+    }
+}
+
+/// Shutdown runs these stages in order and only sends `AppExit` after the last one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ShutdownStage {
+    /// Save, prompt or discard dirty documents. Can be cancelled.
+    UnsavedChanges,
+    /// Flush journals and session state.
+    Flush,
+    /// Send shutdown/exit to language servers.
+    StopServers,
+    /// Terminate remaining child processes.
+    StopProcesses,
+}
+
+impl ShutdownStage {
+    const ORDER: [ShutdownStage; 4] = [
+        ShutdownStage::UnsavedChanges,
+        ShutdownStage::Flush,
+        ShutdownStage::StopServers,
+        ShutdownStage::StopProcesses,
+    ];
+
+    fn next(self) -> Option<Self> {
+        let i = Self::ORDER.iter().position(|s| *s == self)?;
+        Self::ORDER.get(i + 1).copied()
+    }
+}
+
+/// Progress of the shutdown sequence. Plugins that have work to do in a stage register as a
+/// participant and call [`Shutdown::complete`] once done; a stage ends when every participant
+/// completed it.
+#[derive(Debug, Default)]
+pub struct Shutdown {
+    stage: Option<ShutdownStage>,
+    participants: HashMap<ShutdownStage, Vec<&'static str>>,
+    pending: Vec<&'static str>,
+    deadline: Option<f64>,
+}
+
+impl Shutdown {
+    pub fn register(&mut self, stage: ShutdownStage, participant: &'static str) {
+        self.participants
+            .entry(stage)
+            .or_default()
+            .push(participant);
+    }
+
+    pub fn stage(&self) -> Option<ShutdownStage> {
+        self.stage
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.stage.is_some()
+    }
+
+    /// Whether `participant` still has work to do in the current stage.
+    pub fn is_pending(&self, participant: &str) -> bool {
+        self.pending.contains(&participant)
+    }
+
+    pub fn complete(&mut self, participant: &str) {
+        self.pending.retain(|p| *p != participant);
+    }
+
+    fn enter(&mut self, stage: ShutdownStage, now: f64, timeout: Duration) {
+        self.stage = Some(stage);
+        self.pending = self.participants.get(&stage).cloned().unwrap_or_default();
+        self.deadline = match stage {
+            ShutdownStage::UnsavedChanges => None,
+            _ => Some(now + timeout.as_secs_f64()),
+        };
+    }
+
+    fn cancel(&mut self) {
+        self.stage = None;
+        self.pending.clear();
+        self.deadline = None;
+    }
+}
+
+pub trait AppShutdownExt {
+    fn add_shutdown_participant(
+        &mut self,
+        stage: ShutdownStage,
+        participant: &'static str,
+    ) -> &mut Self;
+}
+
+impl AppShutdownExt for App {
+    fn add_shutdown_participant(
+        &mut self,
+        stage: ShutdownStage,
+        participant: &'static str,
+    ) -> &mut Self {
+        self.init_resource::<Shutdown>();
+        self.world
+            .get_resource_mut::<Shutdown>()
+            .unwrap()
+            .register(stage, participant);
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RequestShutdown;
+
+/// Aborts a shutdown that is still waiting on unsaved changes, e.g. "Cancel" in the prompt.
+#[derive(Clone, Copy, Debug)]
+pub struct CancelShutdown;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ShutdownStageStarted(pub ShutdownStage);
+
+/// Dirty documents the user is asked about before quitting. Shutdown waits for
+/// [`ResolveUnsavedChanges`], or [`CancelShutdown`] to keep going.
+#[derive(Clone, Debug)]
+pub struct UnsavedChangesPrompt {
+    pub documents: Vec<Entity>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnsavedChoice {
+    /// Saves the documents still dirty. Untitled ones are up to the UI to save somewhere
+    /// with [`SaveDocument`] first, shutdown or closing is cancelled if any are left.
+    Save,
+    Discard,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ResolveUnsavedChanges(pub UnsavedChoice);
+
+fn request_shutdown_on_exit(
+    mut events: EventReader<CoreCommand>,
+    mut shutdown: EventWriter<RequestShutdown>,
+) {
+    for cmd in events.iter() {
+        if let CoreCommand::Exit = cmd {
+            shutdown.send(RequestShutdown);
+        }
+    }
+}
+
+fn start_shutdown(
+    mut requests: EventReader<RequestShutdown>,
+    mut cancels: EventReader<CancelShutdown>,
+    mut shutdown: ResMut<Shutdown>,
+    settings: Res<ShutdownSettings>,
+    time: Res<Time>,
+    mut started: EventWriter<ShutdownStageStarted>,
+) {
+    if cancels.iter().count() > 0 {
+        if shutdown.stage == Some(ShutdownStage::UnsavedChanges) {
+            debug!("🛑 Shutdown cancelled");
+            shutdown.cancel();
+        } else if shutdown.is_shutting_down() {
+            warn!("Shutdown is past the point where it can be cancelled");
+        }
+    }
+
+    if requests.iter().count() > 0 && !shutdown.is_shutting_down() {
+        let stage = ShutdownStage::UnsavedChanges;
+        shutdown.enter(stage, time.seconds_since_startup(), settings.stage_timeout);
+        started.send(ShutdownStageStarted(stage));
+    }
+}
+
+/// Dirty documents, and whether each has a path to be saved to.
+fn dirty_documents(
+    documents: &Query<(Entity, &Document), Without<VirtualDocument>>,
+) -> Vec<(Entity, bool)> {
+    documents
+        .iter()
+        .filter(|(_, document)| document.is_dirty())
+        .map(|(entity, document)| (entity, document.path().is_some()))
+        .collect()
+}
+
+/// Holds the unsaved changes stage until every dirty document was saved or the user chose
+/// what to do. A save that fails cancels the shutdown, so nothing is lost on the way out.
+#[allow(clippy::too_many_arguments)]
+fn resolve_unsaved_changes(
+    mut started: EventReader<ShutdownStageStarted>,
+    mut answers: EventReader<ResolveUnsavedChanges>,
+    mut saved: EventReader<DocumentSaved>,
+    mut failed: EventReader<DocumentSaveFailed>,
+    mut conflicts: EventReader<SaveConflict>,
+    documents: Query<(Entity, &Document), Without<VirtualDocument>>,
+    settings: Res<ShutdownSettings>,
+    mut shutdown: ResMut<Shutdown>,
+    mut saving: Local<Vec<Entity>>,
+    mut saves: EventWriter<SaveDocument>,
+    mut prompts: EventWriter<UnsavedChangesPrompt>,
+) {
+    let mut save = |dirty: Vec<(Entity, bool)>, saving: &mut Vec<Entity>| {
+        for (entity, _) in dirty {
+            saves.send(SaveDocument { entity, path: None });
+            saving.push(entity);
+        }
+    };
+
+    if started.iter().any(|e| e.0 == ShutdownStage::UnsavedChanges) {
+        saving.clear();
+        let dirty = dirty_documents(&documents);
+        match settings.unsaved_changes {
+            _ if dirty.is_empty() => shutdown.complete(UNSAVED_CHANGES),
+            UnsavedChangesPolicy::Discard => {
+                debug!("🛑 Discarding unsaved changes of {} documents", dirty.len());
+                shutdown.complete(UNSAVED_CHANGES);
+            }
+            UnsavedChangesPolicy::AutoSave if dirty.iter().all(|(_, titled)| *titled) => {
+                save(dirty, &mut saving);
+            }
+            UnsavedChangesPolicy::AutoSave | UnsavedChangesPolicy::Prompt => {
+                prompts.send(UnsavedChangesPrompt {
+                    documents: dirty.into_iter().map(|(entity, _)| entity).collect(),
+                });
+            }
+        }
+    }
+
+    for answer in answers.iter() {
+        if !shutdown.is_pending(UNSAVED_CHANGES) || !saving.is_empty() {
+            continue;
+        }
+        let dirty = dirty_documents(&documents);
+        match answer.0 {
+            UnsavedChoice::Discard => shutdown.complete(UNSAVED_CHANGES),
+            _ if dirty.is_empty() => shutdown.complete(UNSAVED_CHANGES),
+            UnsavedChoice::Save if dirty.iter().any(|(_, titled)| !titled) => {
+                warn!("Untitled documents have nowhere to be saved, not quitting");
+                shutdown.cancel();
+            }
+            UnsavedChoice::Save => save(dirty, &mut saving),
+        }
+    }
+
+    let was_saving = !saving.is_empty();
+    for e in saved.iter() {
+        saving.retain(|entity| *entity != e.entity);
+    }
+    let failed: Vec<Entity> = failed
+        .iter()
+        .map(|e| e.entity)
+        .chain(conflicts.iter().map(|e| e.entity))
+        .collect();
+    if failed.iter().any(|entity| saving.contains(entity)) {
+        warn!("Failed to save unsaved changes, not quitting");
+        saving.clear();
+        if shutdown.stage == Some(ShutdownStage::UnsavedChanges) {
+            shutdown.cancel();
+        }
+    } else if was_saving && saving.is_empty() {
+        shutdown.complete(UNSAVED_CHANGES);
+    }
+}
+
+fn advance_shutdown(
+    mut shutdown: ResMut<Shutdown>,
+    settings: Res<ShutdownSettings>,
+    time: Res<Time>,
+    mut started: EventWriter<ShutdownStageStarted>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let stage = match shutdown.stage {
+        Some(stage) => stage,
+        None => return,
+    };
+
+    let now = time.seconds_since_startup();
+    let timed_out = shutdown.deadline.is_some_and(|d| now >= d);
+    if !shutdown.pending.is_empty() {
+        if !timed_out {
+            return;
+        }
+        warn!(
+            "Shutdown stage {stage:?} timed out waiting for {:?}",
+            shutdown.pending
+        );
+    }
+
+    match stage.next() {
+        Some(next) => {
+            debug!("🛑 {next:?}");
+            shutdown.enter(next, now, settings.stage_timeout);
+            started.send(ShutdownStageStarted(next));
+        }
+        None => exit.send(AppExit),
+    }
+}
@@ -0,0 +1,156 @@
+use crate::{
+    announce::count,
+    clipboard::Clipboard,
+    cursor::{self, Documents, Placed, Secondaries},
+    document::{DocumentChanged, DocumentEditSet},
+    generate::{self, GenerateSettings, Generators},
+    pipeline::{AppPipelineExt, EditorStage},
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Commands, Res, ResMut},
+    },
+    log::{debug, warn},
+};
+use std::{
+    collections::HashMap,
+    ops::Range,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub use dip_editor::snippet::{
+    document_variables, time_variables, Expansion, Snippet, SnippetError,
+};
+
+pub struct SnippetPlugin;
+
+impl Plugin for SnippetPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<InsertSnippet>()
+            .add_event::<SnippetFailed>()
+            .add_editor_system(EditorStage::Edits, insert_snippets.label(DocumentEditSet));
+    }
+}
+
+/// Replaces every selection with `snippet` in the TextMate syntax VS Code snippets use,
+/// selecting its first tabstop. Variables are filled in for each caret.
+#[derive(Clone, Debug)]
+pub struct InsertSnippet {
+    pub entity: Entity,
+    pub snippet: String,
+}
+
+/// Nothing was inserted.
+#[derive(Clone, Debug)]
+pub struct SnippetFailed {
+    pub entity: Entity,
+    pub error: String,
+}
+
+fn insert_snippets(
+    mut commands: Commands,
+    mut events: EventReader<InsertSnippet>,
+    mut documents: Documents<()>,
+    mut secondaries: Secondaries,
+    (generators, settings): (Option<Res<Generators>>, Option<Res<GenerateSettings>>),
+    (clipboard, mut placed): (Option<Res<Clipboard>>, ResMut<Placed>),
+    (mut changed, mut failed): (EventWriter<DocumentChanged>, EventWriter<SnippetFailed>),
+) {
+    for e in events.iter() {
+        let (mut document, mut cursor, mut selection, ()) = match documents.get_mut(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let mut fail = |error: String| {
+            warn!("🧷 {error}");
+            failed.send(SnippetFailed {
+                entity: e.entity,
+                error,
+            });
+        };
+        let snippet = match Snippet::parse(&e.snippet) {
+            Ok(snippet) => snippet,
+            Err(error) => {
+                fail(format!("Failed to parse the snippet: {error}"));
+                continue;
+            }
+        };
+        if document.is_read_only() {
+            fail("The document is read only".to_string());
+            continue;
+        }
+        let mut carets = cursor::carets(e.entity, (&cursor, &selection), &mut secondaries);
+
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        let timezone = settings
+            .as_ref()
+            .map_or(generate::Timezone::Local, |s| s.timezone);
+        let mut shared = time_variables(secs, timezone.offset_at(secs));
+        if let Some(clipboard) = &clipboard {
+            shared.insert("CLIPBOARD".to_string(), clipboard.text());
+        }
+        // Each caret gets its own, e.g. a different `$UUID`.
+        let expansions: HashMap<Range<usize>, Expansion> = carets
+            .iter()
+            .map(|caret| {
+                let mut variables = shared.clone();
+                variables.extend(document_variables(&document, caret.range()));
+                if let (Some(generators), Some(settings)) = (&generators, &settings) {
+                    variables.extend(generators.variables(settings));
+                }
+                variables.extend(random_variables());
+                (caret.range(), snippet.expand(&variables))
+            })
+            .collect();
+
+        document.history_mut().begin();
+        cursor::edit_carets(&mut document, &mut carets, |document, caret| {
+            let range = caret.range();
+            let expansion = &expansions[&range];
+            document.delete(range.clone());
+            document.insert(range.start, &expansion.text);
+            let selection = expansion.selection();
+            range.start + selection.start..range.start + selection.end
+        });
+        cursor::store(
+            carets,
+            (&mut cursor, &mut selection),
+            &mut secondaries,
+            &mut commands,
+        );
+        placed.0.insert((e.entity, document.version()));
+        debug!(
+            "🧷 Inserted a snippet at {}",
+            count(expansions.len(), "caret")
+        );
+        changed.send(DocumentChanged {
+            entity: e.entity,
+            version: document.version(),
+            changes: document.take_changes(),
+            cursor: None,
+        });
+    }
+}
+
+/// `UUID`, `RANDOM` and `RANDOM_HEX`, left out without randomness.
+fn random_variables() -> HashMap<String, String> {
+    let bytes: [u8; 16] = match generate::random_bytes() {
+        Ok(bytes) => bytes,
+        Err(_) => return HashMap::new(),
+    };
+    let number = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    HashMap::from([
+        ("UUID".to_string(), generate::uuid_v4(bytes)),
+        ("RANDOM".to_string(), format!("{:06}", number % 1_000_000)),
+        (
+            "RANDOM_HEX".to_string(),
+            format!("{:06x}", number & 0xff_ffff),
+        ),
+    ])
+}
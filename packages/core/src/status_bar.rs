@@ -0,0 +1,292 @@
+//! The bar along the bottom of the window, made of segments plugins register with
+//! [`AppStatusBarExt::add_status_segment`] and fill in with [`SetStatus`]. The built-in
+//! ones follow the document on screen: where its cursor is, its line endings, encoding and
+//! language, and how many diagnostics it has. The view is sent the whole bar as
+//! [`UICommand::StatusBar`] whenever a segment changed.
+
+use crate::{
+    command::UICommand,
+    cursor::{Cursor, Selection},
+    diagnostics::Diagnostics,
+    document::{Document, LineEnding},
+    pipeline::{AppPipelineExt, EditorStage},
+    render::Viewport,
+    syntax::Syntax,
+    theme::Severity,
+    workspace::Workspace,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        query::{Changed, Or},
+        schedule::{ParallelSystemDescriptorCoercion, SystemLabel},
+        system::{Local, Query, Res, ResMut},
+    },
+};
+
+pub const CURSOR_POSITION: &str = "cursor.position";
+pub const LINE_ENDING: &str = "editor.lineEnding";
+pub const ENCODING: &str = "editor.encoding";
+pub const LANGUAGE_MODE: &str = "editor.languageMode";
+pub const DIAGNOSTICS: &str = "diagnostics.count";
+
+pub struct StatusBarPlugin;
+
+/// The system applying [`SetStatus`], in [`EditorStage::Present`]. Systems there setting
+/// segments run before it so they show the same frame.
+#[derive(SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SetStatuses;
+
+impl Plugin for StatusBarPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StatusBar>()
+            .add_event::<SetStatus>()
+            .add_status_segment(DIAGNOSTICS, Alignment::Left, 100)
+            .add_status_segment(CURSOR_POSITION, Alignment::Right, 100)
+            .add_status_segment(ENCODING, Alignment::Right, 90)
+            .add_status_segment(LINE_ENDING, Alignment::Right, 80)
+            .add_status_segment(LANGUAGE_MODE, Alignment::Right, 70)
+            .add_editor_system(EditorStage::Present, follow_document.before(SetStatuses))
+            .add_editor_system(EditorStage::Present, set_statuses.label(SetStatuses))
+            .add_editor_system(EditorStage::Present, send_status_bar.after(SetStatuses));
+    }
+}
+
+/// The side of the bar a segment is on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Alignment {
+    Left,
+    Right,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StatusSegment {
+    pub id: String,
+    pub alignment: Alignment,
+    /// Segments with a higher one come first on their side, from left to right.
+    pub priority: i32,
+    /// Hidden while empty.
+    pub text: String,
+    pub tooltip: Option<String>,
+    /// Run when clicked, e.g. `editor.changeLanguageMode`.
+    pub command: Option<String>,
+}
+
+/// The registered segments, in the order they are shown: the left ones, then the right
+/// ones.
+#[derive(Clone, Debug, Default)]
+pub struct StatusBar {
+    segments: Vec<StatusSegment>,
+}
+
+impl StatusBar {
+    pub fn get(&self, id: &str) -> Option<&StatusSegment> {
+        self.segments.iter().find(|s| s.id == id)
+    }
+
+    /// The segments with text, on `alignment`'s side.
+    pub fn shown(&self, alignment: Alignment) -> impl Iterator<Item = &StatusSegment> {
+        self.segments
+            .iter()
+            .filter(move |s| s.alignment == alignment && !s.text.is_empty())
+    }
+
+    fn register(&mut self, id: &str, alignment: Alignment, priority: i32) {
+        self.segments.retain(|s| s.id != id);
+        self.segments.push(StatusSegment {
+            id: id.to_string(),
+            alignment,
+            priority,
+            text: String::new(),
+            tooltip: None,
+            command: None,
+        });
+        self.segments.sort_by_key(|s| {
+            let side = match s.alignment {
+                Alignment::Left => 0,
+                Alignment::Right => 1,
+            };
+            (side, -s.priority)
+        });
+    }
+
+    /// Whether setting `e` would change the bar.
+    fn differs(&self, e: &SetStatus) -> bool {
+        self.get(&e.id).is_some_and(|s| {
+            s.text != e.text
+                || s.tooltip != e.tooltip
+                || (e.command.is_some() && s.command != e.command)
+        })
+    }
+
+    fn set(&mut self, e: &SetStatus) {
+        if let Some(segment) = self.segments.iter_mut().find(|s| s.id == e.id) {
+            segment.text = e.text.clone();
+            segment.tooltip = e.tooltip.clone();
+            if e.command.is_some() {
+                segment.command = e.command.clone();
+            }
+        }
+    }
+}
+
+pub trait AppStatusBarExt {
+    /// Adds the segment `id` to the bar, empty until its plugin sends [`SetStatus`].
+    fn add_status_segment(&mut self, id: &str, alignment: Alignment, priority: i32) -> &mut Self;
+}
+
+impl AppStatusBarExt for App {
+    fn add_status_segment(&mut self, id: &str, alignment: Alignment, priority: i32) -> &mut Self {
+        self.init_resource::<StatusBar>();
+        self.world
+            .get_resource_mut::<StatusBar>()
+            .unwrap()
+            .register(id, alignment, priority);
+        self
+    }
+}
+
+/// Shows `text` in the segment `id`, or hides it if empty. A segment nobody registered is
+/// ignored.
+#[derive(Clone, Debug)]
+pub struct SetStatus {
+    pub id: String,
+    pub text: String,
+    pub tooltip: Option<String>,
+    /// Replaces the command run when clicked, left as is if None.
+    pub command: Option<String>,
+}
+
+impl SetStatus {
+    pub fn new(id: &str, text: impl Into<String>) -> Self {
+        Self {
+            id: id.to_string(),
+            text: text.into(),
+            tooltip: None,
+            command: None,
+        }
+    }
+
+    pub fn with_tooltip(mut self, tooltip: impl Into<String>) -> Self {
+        self.tooltip = Some(tooltip.into());
+        self
+    }
+}
+
+/// "Ln 12, Col 5", with how many characters are selected if any.
+fn cursor_position(document: &Document, cursor: &Cursor, selection: &Selection) -> String {
+    let buffer = document.buffer();
+    let position = cursor.position(buffer);
+    let text = format!("Ln {}, Col {}", position.line + 1, position.column + 1);
+    let range = selection.range(cursor);
+    match range.is_empty() {
+        true => text,
+        false => {
+            let selected = buffer.text_in(range).chars().count();
+            format!("{text} ({selected} selected)")
+        }
+    }
+}
+
+fn encoding(document: &Document) -> &'static str {
+    match document.has_bom() {
+        true => "UTF-8 with BOM",
+        false => "UTF-8",
+    }
+}
+
+fn diagnostic_counts(diagnostics: Option<&Diagnostics>) -> String {
+    let (errors, warnings) = diagnostics.map_or((0, 0), |d| {
+        (d.count(Severity::Error), d.count(Severity::Warning))
+    });
+    format!("⊗ {errors} ⚠ {warnings}")
+}
+
+/// Fills in the built-in segments from the document on screen, when it or what they show
+/// of it changed.
+#[allow(clippy::type_complexity)]
+fn follow_document(
+    (viewport, workspace): (Res<Viewport>, Option<Res<Workspace>>),
+    documents: Query<(
+        &Document,
+        Option<&Cursor>,
+        Option<&Selection>,
+        Option<&Syntax>,
+        Option<&Diagnostics>,
+    )>,
+    changed: Query<
+        (),
+        Or<(
+            Changed<Document>,
+            Changed<Cursor>,
+            Changed<Selection>,
+            Changed<Syntax>,
+            Changed<Diagnostics>,
+        )>,
+    >,
+    mut shown: Local<Option<Entity>>,
+    mut statuses: EventWriter<SetStatus>,
+) {
+    let entity = viewport.shown(workspace.as_deref());
+    let switched = *shown != entity;
+    *shown = entity;
+    let entity = match entity {
+        Some(entity) => entity,
+        None => {
+            if switched {
+                for id in [
+                    CURSOR_POSITION,
+                    LINE_ENDING,
+                    ENCODING,
+                    LANGUAGE_MODE,
+                    DIAGNOSTICS,
+                ] {
+                    statuses.send(SetStatus::new(id, ""));
+                }
+            }
+            return;
+        }
+    };
+    if !switched && changed.get(entity).is_err() {
+        return;
+    }
+    let (document, cursor, selection, syntax, diagnostics) = match documents.get(entity) {
+        Ok(document) => document,
+        Err(_) => return,
+    };
+
+    let position = match (cursor, selection) {
+        (Some(cursor), Some(selection)) => cursor_position(document, cursor, selection),
+        _ => String::new(),
+    };
+    let line_ending = match document.line_ending() {
+        LineEnding::Lf => "LF",
+        LineEnding::CrLf => "CRLF",
+    };
+    let language = syntax.map_or("Plain Text", |syntax| syntax.grammar());
+    statuses.send(SetStatus::new(CURSOR_POSITION, position));
+    statuses.send(SetStatus::new(LINE_ENDING, line_ending));
+    statuses.send(SetStatus::new(ENCODING, encoding(document)));
+    statuses.send(SetStatus::new(LANGUAGE_MODE, language));
+    statuses.send(
+        SetStatus::new(DIAGNOSTICS, diagnostic_counts(diagnostics))
+            .with_tooltip("Errors and warnings"),
+    );
+}
+
+fn set_statuses(mut events: EventReader<SetStatus>, mut bar: ResMut<StatusBar>) {
+    for e in events.iter() {
+        // Only borrowed mutably for a change, so the bar isn't sent again every frame.
+        if bar.differs(e) {
+            bar.set(e);
+        }
+    }
+}
+
+fn send_status_bar(bar: Res<StatusBar>, mut ui: EventWriter<UICommand>) {
+    if bar.is_changed() {
+        ui.send(UICommand::StatusBar(bar.segments.clone()));
+    }
+}
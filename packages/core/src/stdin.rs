@@ -0,0 +1,174 @@
+use crate::{
+    document::{Document, DocumentChanged, DocumentEditSet, Utf8Decoder, READ_CHUNK_SIZE},
+    memory::MemoryUsage,
+    pipeline::{AppPipelineExt, EditorStage},
+    text_buffer::TextBufferBuilder,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Commands, Query, ResMut},
+    },
+    log::{debug, warn},
+};
+use std::{
+    io::{self, Read},
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Mutex,
+    },
+    thread,
+};
+
+pub struct StdinPlugin;
+
+impl Plugin for StdinPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StdinPipe>()
+            .add_event::<ReadStdin>()
+            .add_event::<StdinOpened>()
+            .add_system(start_reading_stdin)
+            .add_editor_system(EditorStage::Edits, receive_stdin.label(DocumentEditSet));
+    }
+}
+
+/// Reads standard input into an untitled document, as in `cat log | dip -`.
+#[derive(Clone, Copy, Debug)]
+pub struct ReadStdin;
+
+#[derive(Clone, Copy, Debug)]
+pub struct StdinOpened {
+    pub entity: Entity,
+}
+
+/// Put on the document standard input is read into.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Piped {
+    /// Still open, new output is appended as it arrives.
+    pub following: bool,
+}
+
+#[derive(Default)]
+struct StdinPipe {
+    receiver: Option<Mutex<Receiver<String>>>,
+    entity: Option<Entity>,
+}
+
+fn read_stdin(sender: mpsc::Sender<String>) {
+    let mut stdin = io::stdin().lock();
+    let mut chunk = vec![0; READ_CHUNK_SIZE];
+    let mut decoder = Utf8Decoder::default();
+    loop {
+        let n = match stdin.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                warn!("📄 Failed to read standard input: {e}");
+                break;
+            }
+        };
+        let text = match decoder.push(&chunk[..n]) {
+            Ok(text) => text,
+            Err(e) => {
+                warn!("📄 Stopped reading standard input: {e}");
+                return;
+            }
+        };
+        if !text.is_empty() && sender.send(text.to_string()).is_err() {
+            return;
+        }
+    }
+    if let Err(e) = decoder.finish() {
+        warn!("📄 Standard input ended: {e}");
+    }
+}
+
+fn start_reading_stdin(mut events: EventReader<ReadStdin>, mut pipe: ResMut<StdinPipe>) {
+    // Standard input can only be read once.
+    if events.iter().count() == 0 || pipe.receiver.is_some() || pipe.entity.is_some() {
+        return;
+    }
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || read_stdin(sender));
+    *pipe = StdinPipe {
+        receiver: Some(Mutex::new(receiver)),
+        entity: None,
+    };
+}
+
+/// The document is spawned from the output received by the first frame that has any,
+/// later output is appended to it.
+fn receive_stdin(
+    mut commands: Commands,
+    mut pipe: ResMut<StdinPipe>,
+    mut documents: Query<(&mut Document, &mut Piped)>,
+    mut opened: EventWriter<StdinOpened>,
+    mut changed: EventWriter<DocumentChanged>,
+) {
+    let mut received = vec![];
+    let closed = match &pipe.receiver {
+        Some(receiver) => {
+            let receiver = receiver.lock().unwrap();
+            loop {
+                match receiver.try_recv() {
+                    Ok(text) => received.push(text),
+                    Err(TryRecvError::Empty) => break false,
+                    Err(TryRecvError::Disconnected) => break true,
+                }
+            }
+        }
+        None => return,
+    };
+
+    match pipe.entity {
+        None => {
+            if received.is_empty() && !closed {
+                return;
+            }
+            let mut builder = TextBufferBuilder::new();
+            for text in &received {
+                builder.accept_chunk(text);
+            }
+            let document = Document::new(None, builder.finish());
+            debug!(
+                "📄 Reading standard input, {} bytes",
+                document.buffer().len()
+            );
+            let entity = commands
+                .spawn()
+                .insert(document)
+                .insert(MemoryUsage::default())
+                .insert(Piped { following: !closed })
+                .id();
+            pipe.entity = Some(entity);
+            opened.send(StdinOpened { entity });
+        }
+        Some(entity) => {
+            if let Ok((mut document, mut piped)) = documents.get_mut(entity) {
+                for text in &received {
+                    document.append(text);
+                }
+                if !received.is_empty() {
+                    changed.send(DocumentChanged {
+                        entity,
+                        version: document.version(),
+                        changes: document.take_changes(),
+                        cursor: None,
+                    });
+                }
+                if closed {
+                    piped.following = false;
+                }
+            }
+        }
+    }
+
+    if closed {
+        pipe.receiver = None;
+    }
+}
@@ -0,0 +1,283 @@
+use crate::{
+    document::{Document, DocumentSaved},
+    fuzzy::fuzzy_match,
+    idle::throttled,
+    syntax::Grammars,
+    workspace::{RootId, Workspace, WorkspaceChanged},
+    workspace_search::{FileScan, WorkspaceScan, WorkspaceSearchSettings},
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        event::{EventReader, EventWriter},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Query, Res, ResMut},
+    },
+    log::debug,
+    tasks::AsyncComputeTaskPool,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// How often indexed files are collected while the user is idle, every frame otherwise.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Indexes the definitions of every file of the workspace with a grammar, on the task
+/// pools, for going to a symbol by name. The index is built again when the roots or the
+/// grammars change, and files saved are indexed again from their buffer.
+pub struct SymbolIndexPlugin;
+
+impl Plugin for SymbolIndexPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SymbolIndex>()
+            .add_event::<SymbolsIndexed>()
+            .add_system(start_indexing)
+            .add_system(index_saved_documents)
+            .add_system(
+                poll_indexing.with_run_criteria(throttled(Duration::ZERO, IDLE_POLL_INTERVAL)),
+            );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SymbolKind {
+    Function,
+    Method,
+    /// Structs, enums and type aliases too.
+    Class,
+    /// Traits too.
+    Interface,
+    Module,
+    Macro,
+    Constant,
+    Other,
+}
+
+impl SymbolKind {
+    /// The kind of a `@definition.<kind>` capture of a tags query.
+    pub fn from_tag(kind: &str) -> Self {
+        match kind {
+            "function" => SymbolKind::Function,
+            "method" => SymbolKind::Method,
+            "class" => SymbolKind::Class,
+            "interface" => SymbolKind::Interface,
+            "module" => SymbolKind::Module,
+            "macro" => SymbolKind::Macro,
+            "constant" => SymbolKind::Constant,
+            _ => SymbolKind::Other,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub root: RootId,
+    pub path: PathBuf,
+    /// Bytes of the name where it is defined.
+    pub range: Range<usize>,
+}
+
+#[derive(Debug)]
+pub struct SymbolMatch<'a> {
+    pub symbol: &'a Symbol,
+    pub score: i32,
+    /// Char indices of the name matching the query.
+    pub positions: Vec<usize>,
+}
+
+/// Sent once every file of the workspace was indexed.
+#[derive(Clone, Debug)]
+pub struct SymbolsIndexed {
+    pub files: usize,
+    pub symbols: usize,
+    pub elapsed: Duration,
+}
+
+/// The definitions of the workspace by file. Those of a file read in a pass still running
+/// are there already, and those of the last pass until it finishes.
+#[derive(Default)]
+pub struct SymbolIndex {
+    files: HashMap<PathBuf, Vec<Symbol>>,
+    indexing: Option<Indexing>,
+}
+
+/// A pass over the workspace, with the files it indexed so far.
+struct Indexing {
+    scan: WorkspaceScan<FileSymbols>,
+    seen: HashSet<PathBuf>,
+}
+
+struct FileSymbols {
+    path: PathBuf,
+    symbols: Vec<Symbol>,
+}
+
+impl SymbolIndex {
+    /// Symbols indexed, across files.
+    pub fn len(&self) -> usize {
+        self.files.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.values().all(Vec::is_empty)
+    }
+
+    pub fn is_indexing(&self) -> bool {
+        self.indexing.is_some()
+    }
+
+    pub fn in_file(&self, path: &Path) -> &[Symbol] {
+        self.files.get(path).map_or(&[], Vec::as_slice)
+    }
+
+    /// At most `limit` symbols whose name fuzzy matches `query`, best first, then by name
+    /// and where they are.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SymbolMatch<'_>> {
+        let mut matches: Vec<SymbolMatch> = self
+            .files
+            .values()
+            .flatten()
+            .filter_map(|symbol| {
+                let (score, positions) = fuzzy_match(query, &symbol.name)?;
+                Some(SymbolMatch {
+                    symbol,
+                    score,
+                    positions,
+                })
+            })
+            .collect();
+        matches.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then_with(|| a.symbol.name.cmp(&b.symbol.name))
+                .then_with(|| a.symbol.path.cmp(&b.symbol.path))
+                .then_with(|| a.symbol.range.start.cmp(&b.symbol.range.start))
+        });
+        matches.truncate(limit);
+        matches
+    }
+
+    fn insert(&mut self, file: FileSymbols) {
+        if let Some(indexing) = &mut self.indexing {
+            indexing.seen.insert(file.path.clone());
+        }
+        self.files.insert(file.path, file.symbols);
+    }
+}
+
+/// The definitions in `text`, if the file has a grammar.
+fn symbols(grammars: &Grammars, root: RootId, path: &Path, text: &str) -> Option<Vec<Symbol>> {
+    let grammar = grammars.for_path(path)?;
+    let symbols = grammar
+        .definitions(text)
+        .into_iter()
+        .map(|(range, kind)| Symbol {
+            name: text[range.clone()].to_string(),
+            kind: SymbolKind::from_tag(kind),
+            root,
+            path: path.to_path_buf(),
+            range,
+        })
+        .collect();
+    Some(symbols)
+}
+
+/// Reads the definitions of files with a grammar.
+struct Definitions(Grammars);
+
+impl FileScan for Definitions {
+    type Output = FileSymbols;
+
+    fn wants(&self, path: &Path) -> bool {
+        self.0.for_path(path).is_some()
+    }
+
+    fn scan(&self, root: RootId, path: &Path, text: &str) -> Option<FileSymbols> {
+        let symbols = symbols(&self.0, root, path, text)?;
+        Some(FileSymbols {
+            path: path.to_path_buf(),
+            symbols,
+        })
+    }
+}
+
+/// Starts a pass over the workspace when its roots or the grammars change, cancelling the
+/// one running.
+fn start_indexing(
+    mut changed: EventReader<WorkspaceChanged>,
+    (pool, settings): (Res<AsyncComputeTaskPool>, Res<WorkspaceSearchSettings>),
+    grammars: Res<Grammars>,
+    workspace: Res<Workspace>,
+    mut index: ResMut<SymbolIndex>,
+) {
+    if changed.iter().count() == 0 && !grammars.is_changed() {
+        return;
+    }
+    debug!("🏷️ Indexing {} roots", workspace.roots().len());
+    let definitions = Definitions(grammars.clone());
+    index.indexing = Some(Indexing {
+        scan: WorkspaceScan::start(&pool, workspace.roots(), &settings, definitions),
+        seen: HashSet::new(),
+    });
+}
+
+fn poll_indexing(mut index: ResMut<SymbolIndex>, mut indexed: EventWriter<SymbolsIndexed>) {
+    let indexing = match &index.indexing {
+        Some(indexing) => indexing,
+        None => return,
+    };
+
+    let mut found = vec![];
+    let done = indexing.scan.drain(&mut found);
+    let (files, elapsed) = (indexing.scan.files_read(), indexing.scan.elapsed());
+    for file in found {
+        index.insert(file);
+    }
+
+    if done {
+        // Files gone from the workspace since the last pass.
+        if let Some(indexing) = index.indexing.take() {
+            index.files.retain(|path, _| indexing.seen.contains(path));
+        }
+        let symbols = index.len();
+        debug!("🏷️ Indexed {symbols} symbols in {files} files in {elapsed:?}");
+        indexed.send(SymbolsIndexed {
+            files,
+            symbols,
+            elapsed,
+        });
+    }
+}
+
+/// Files saved are indexed again from what was saved, if they are in the workspace.
+fn index_saved_documents(
+    mut saved: EventReader<DocumentSaved>,
+    documents: Query<&Document>,
+    grammars: Res<Grammars>,
+    workspace: Res<Workspace>,
+    mut index: ResMut<SymbolIndex>,
+) {
+    for e in saved.iter() {
+        let root = match workspace.root_for(&e.path) {
+            Some(root) => root.id,
+            None => continue,
+        };
+        let document = match documents.get(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let text = document.buffer().to_string();
+        if let Some(symbols) = symbols(&grammars, root, &e.path, &text) {
+            index.insert(FileSymbols {
+                path: e.path.clone(),
+                symbols,
+            });
+        }
+    }
+}
@@ -0,0 +1,782 @@
+use crate::{
+    damage::DecorationsChanged,
+    document::{Change, Document, DocumentChanged},
+    indent::IndentScope,
+    memory::{Cache, EvictCache, MemoryUsage, Visible},
+    pipeline::{AppPipelineExt, EditorStage},
+    text_buffer::TextBuffer,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        query::{Added, Changed, Or, With, Without},
+        system::{Commands, Query, Res},
+    },
+    log::{debug, warn},
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    ops::Range,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use streaming_iterator::StreamingIterator;
+use tree_sitter::{InputEdit, Language, Parser, Point, Query as TreeQuery, QueryCursor, Tree};
+
+pub struct SyntaxPlugin;
+
+impl Plugin for SyntaxPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SyntaxSettings>()
+            .init_resource::<Grammars>()
+            .init_resource::<Parsers>()
+            .add_system(attach_syntax)
+            .add_system(evict_syntax)
+            .add_system(update_syntax_memory)
+            .add_editor_system(EditorStage::Derive, update_syntax);
+    }
+}
+
+pub struct SyntaxSettings {
+    /// Documents larger than this many bytes are not highlighted.
+    pub max_len: usize,
+}
+
+impl Default for SyntaxSettings {
+    fn default() -> Self {
+        Self {
+            max_len: 16 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum GrammarError {
+    /// The language was generated by a tree-sitter too old or too new for this one.
+    Language(String),
+    /// The highlights query does not fit the language.
+    Query(String),
+    /// The indents query does not fit the language.
+    Indents(String),
+    /// The tags query does not fit the language.
+    Tags(String),
+}
+
+impl fmt::Display for GrammarError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GrammarError::Language(error) => write!(f, "{error}"),
+            GrammarError::Query(error) => write!(f, "invalid highlights query: {error}"),
+            GrammarError::Indents(error) => write!(f, "invalid indents query: {error}"),
+            GrammarError::Tags(error) => write!(f, "invalid tags query: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for GrammarError {}
+
+/// A tree-sitter language with the query highlighting it, and optionally the ones telling
+/// how its blocks are indented and what it defines.
+pub struct Grammar {
+    pub name: String,
+    /// File extensions it is used for, without the dot.
+    pub extensions: Vec<String>,
+    language: Language,
+    query: TreeQuery,
+    names: Arc<Vec<String>>,
+    indents: Option<TreeQuery>,
+    tags: Option<TreeQuery>,
+}
+
+impl Grammar {
+    /// `highlights` captures nodes by highlight name, e.g. `(string_literal) @string`, the
+    /// way the `highlights.scm` of tree-sitter grammars do. A node captured by several
+    /// patterns takes the first.
+    pub fn new(
+        name: &str,
+        extensions: &[&str],
+        language: Language,
+        highlights: &str,
+    ) -> Result<Self, GrammarError> {
+        Parser::new()
+            .set_language(&language)
+            .map_err(|e| GrammarError::Language(e.to_string()))?;
+        let query = TreeQuery::new(&language, highlights)
+            .map_err(|e| GrammarError::Query(e.to_string()))?;
+        let names = query
+            .capture_names()
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        Ok(Self {
+            name: name.to_string(),
+            extensions: extensions.iter().map(|e| e.to_string()).collect(),
+            language,
+            query,
+            names: Arc::new(names),
+            indents: None,
+            tags: None,
+        })
+    }
+
+    /// `indents` captures the nodes whose lines are indented one level deeper than the line
+    /// they start on as `@indent`, and the nodes closing them, e.g. `"}"`, as `@outdent`,
+    /// the way the `indents.scm` of tree-sitter based editors do. Without it, documents are
+    /// indented by their [`crate::indent::IndentRules`].
+    pub fn with_indents(mut self, indents: &str) -> Result<Self, GrammarError> {
+        let query = TreeQuery::new(&self.language, indents)
+            .map_err(|e| GrammarError::Indents(e.to_string()))?;
+        self.indents = Some(query);
+        Ok(self)
+    }
+
+    /// `tags` captures definitions as `@definition.<kind>` and their name as `@name`, e.g.
+    /// `(function_item name: (identifier) @name) @definition.function`, the way the
+    /// `tags.scm` of tree-sitter grammars do. Without it, nothing is indexed for the
+    /// grammar's files.
+    pub fn with_tags(mut self, tags: &str) -> Result<Self, GrammarError> {
+        let query =
+            TreeQuery::new(&self.language, tags).map_err(|e| GrammarError::Tags(e.to_string()))?;
+        self.tags = Some(query);
+        Ok(self)
+    }
+
+    /// The definitions in `text`, as the range of their name and their kind, e.g.
+    /// `function`, in the order they appear. A name captured by several patterns takes the
+    /// first, e.g. a method over a function.
+    pub fn definitions(&self, text: &str) -> Vec<(Range<usize>, &str)> {
+        let query = match &self.tags {
+            Some(query) => query,
+            None => return vec![],
+        };
+        let name = match query.capture_index_for_name("name") {
+            Some(name) => name,
+            None => return vec![],
+        };
+        let mut parser = Parser::new();
+        let tree = match parser.set_language(&self.language) {
+            Ok(()) => parser.parse(text, None),
+            Err(_) => None,
+        };
+        let tree = match tree {
+            Some(tree) => tree,
+            None => return vec![],
+        };
+
+        let kinds = query.capture_names();
+        let mut found: BTreeMap<(usize, usize), (usize, &str)> = BTreeMap::new();
+        let mut cursor = QueryCursor::new();
+        let mut matches = cursor.matches(query, tree.root_node(), text.as_bytes());
+        while let Some(found_match) = matches.next() {
+            let mut range = None;
+            let mut kind = None;
+            for capture in found_match.captures {
+                if capture.index == name {
+                    range = Some(capture.node.byte_range());
+                } else if let Some(k) = kinds[capture.index as usize].strip_prefix("definition.") {
+                    kind = Some(k);
+                }
+            }
+            if let (Some(range), Some(kind)) = (range, kind) {
+                let pattern = found_match.pattern_index;
+                let entry = found
+                    .entry((range.start, range.end))
+                    .or_insert((pattern, kind));
+                if pattern < entry.0 {
+                    *entry = (pattern, kind);
+                }
+            }
+        }
+        found
+            .into_iter()
+            .map(|((start, end), (_, kind))| (start..end, kind))
+            .collect()
+    }
+}
+
+impl fmt::Debug for Grammar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Grammar")
+            .field("name", &self.name)
+            .field("extensions", &self.extensions)
+            .finish()
+    }
+}
+
+const RUST_INDENTS: &str = include_str!("../queries/rust/indents.scm");
+const JAVASCRIPT_INDENTS: &str = include_str!("../queries/javascript/indents.scm");
+const TYPESCRIPT_INDENTS: &str = include_str!("../queries/typescript/indents.scm");
+
+/// The grammars documents are parsed with, picked by file extension. Rust, JavaScript,
+/// TypeScript and Markdown come built in, all but Markdown with indents and tags.
+#[derive(Clone, Debug)]
+pub struct Grammars {
+    grammars: Vec<Arc<Grammar>>,
+}
+
+impl Default for Grammars {
+    fn default() -> Self {
+        let javascript = tree_sitter_javascript::HIGHLIGHT_QUERY;
+        let jsx = tree_sitter_javascript::JSX_HIGHLIGHT_QUERY;
+        // TypeScript adds to the JavaScript query, its own patterns first so they win.
+        let typescript = tree_sitter_typescript::HIGHLIGHTS_QUERY;
+        let script_indents = &format!("{TYPESCRIPT_INDENTS}\n{JAVASCRIPT_INDENTS}");
+        let javascript_tags = tree_sitter_javascript::TAGS_QUERY;
+        let script_tags = &format!("{}\n{javascript_tags}", tree_sitter_typescript::TAGS_QUERY);
+        let built_in = [
+            Grammar::new(
+                "rust",
+                &["rs"],
+                tree_sitter_rust::LANGUAGE.into(),
+                tree_sitter_rust::HIGHLIGHTS_QUERY,
+            )
+            .and_then(|grammar| grammar.with_indents(RUST_INDENTS))
+            .and_then(|grammar| grammar.with_tags(tree_sitter_rust::TAGS_QUERY)),
+            Grammar::new(
+                "javascript",
+                &["js", "mjs", "cjs", "jsx"],
+                tree_sitter_javascript::LANGUAGE.into(),
+                &format!("{jsx}\n{javascript}"),
+            )
+            .and_then(|grammar| grammar.with_indents(JAVASCRIPT_INDENTS))
+            .and_then(|grammar| grammar.with_tags(javascript_tags)),
+            Grammar::new(
+                "typescript",
+                &["ts", "mts", "cts"],
+                tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+                &format!("{typescript}\n{javascript}"),
+            )
+            .and_then(|grammar| grammar.with_indents(script_indents))
+            .and_then(|grammar| grammar.with_tags(script_tags)),
+            Grammar::new(
+                "tsx",
+                &["tsx"],
+                tree_sitter_typescript::LANGUAGE_TSX.into(),
+                &format!("{typescript}\n{jsx}\n{javascript}"),
+            )
+            .and_then(|grammar| grammar.with_indents(script_indents))
+            .and_then(|grammar| grammar.with_tags(script_tags)),
+            Grammar::new(
+                "markdown",
+                &["md", "markdown"],
+                tree_sitter_md::LANGUAGE.into(),
+                tree_sitter_md::HIGHLIGHT_QUERY_BLOCK,
+            ),
+        ];
+        let mut grammars = Self { grammars: vec![] };
+        for grammar in built_in {
+            grammars.add(grammar.expect("built-in grammars are valid"));
+        }
+        grammars
+    }
+}
+
+impl Grammars {
+    /// Adds a grammar, or replaces the one with the same name. Documents opened from now on
+    /// use it.
+    pub fn add(&mut self, grammar: Grammar) {
+        let grammar = Arc::new(grammar);
+        match self.grammars.iter_mut().find(|g| g.name == grammar.name) {
+            Some(existing) => *existing = grammar,
+            None => self.grammars.push(grammar),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<Grammar>> {
+        self.grammars.iter().find(|g| g.name == name)
+    }
+
+    pub fn for_path(&self, path: &Path) -> Option<&Arc<Grammar>> {
+        let extension = path.extension()?.to_str()?;
+        self.grammars.iter().rev().find(|g| {
+            g.extensions
+                .iter()
+                .any(|e| e.eq_ignore_ascii_case(extension))
+        })
+    }
+}
+
+/// One parser for every document, as a parser can only be used by one thread at a time.
+#[derive(Default)]
+struct Parsers(Mutex<Option<Parser>>);
+
+/// Where the lines of the text last parsed start, but the first, so the points of an edit
+/// can be given in the text before it.
+#[derive(Debug)]
+struct LineStarts(Vec<usize>);
+
+impl LineStarts {
+    fn new(buffer: &TextBuffer) -> Self {
+        Self(
+            (1..buffer.line_count())
+                .map(|l| buffer.line_start(l))
+                .collect(),
+        )
+    }
+
+    fn point(&self, offset: usize) -> Point {
+        let row = self.0.partition_point(|&start| start <= offset);
+        let start = row.checked_sub(1).map_or(0, |i| self.0[i]);
+        Point::new(row, offset - start)
+    }
+
+    /// Applies `change` and returns it as tree-sitter takes it.
+    fn apply(&mut self, change: &Change) -> InputEdit {
+        let Range { start, end } = change.range;
+        let new_end = start + change.text.len();
+        let (start_position, old_end_position) = (self.point(start), self.point(end));
+        let from = self.0.partition_point(|&s| s <= start);
+        let to = self.0.partition_point(|&s| s <= end);
+        let inserted: Vec<usize> = change
+            .text
+            .match_indices('\n')
+            .map(|(i, _)| start + i + 1)
+            .collect();
+        let after = from + inserted.len();
+        self.0.splice(from..to, inserted);
+        for line_start in &mut self.0[after..] {
+            *line_start = *line_start - end + new_end;
+        }
+        InputEdit {
+            start_byte: start,
+            old_end_byte: end,
+            new_end_byte: new_end,
+            start_position,
+            old_end_position,
+            new_end_position: self.point(new_end),
+        }
+    }
+}
+
+/// The syntax tree of a document with a grammar, kept up to date by parsing again only
+/// what each edit touched.
+#[derive(Component)]
+pub struct Syntax {
+    grammar: Arc<Grammar>,
+    tree: Tree,
+    lines: LineStarts,
+    /// The version of the document the edits made to the tree so far lead to.
+    version: u64,
+    /// Ranges to highlight again once parsed.
+    stale: Vec<Range<usize>>,
+}
+
+impl Syntax {
+    pub fn grammar(&self) -> &str {
+        &self.grammar.name
+    }
+
+    pub fn tree(&self) -> &Tree {
+        &self.tree
+    }
+
+    /// Whether the tree was parsed from the text `document` has now.
+    pub fn is_current(&self, document: &Document) -> bool {
+        self.version == document.version()
+    }
+
+    /// The innermost node the indents query of the grammar captures as `@indent` around
+    /// `offset`, with where the `@outdent` node closing it starts. None when the grammar
+    /// has no indents query or no such node surrounds `offset`.
+    pub fn indent_scope(&self, buffer: &TextBuffer, offset: usize) -> Option<IndentScope> {
+        let query = self.grammar.indents.as_ref()?;
+        let indent = query.capture_index_for_name("indent")?;
+        let outdent = query.capture_index_for_name("outdent");
+        let text = |node: tree_sitter::Node| buffer.chunks_in(node.byte_range()).map(str::as_bytes);
+
+        let mut cursor = QueryCursor::new();
+        cursor.set_byte_range(offset.saturating_sub(1)..offset + 1);
+        let mut captures = cursor.captures(query, self.tree.root_node(), text);
+        let mut scope: Option<tree_sitter::Node> = None;
+        while let Some((found, index)) = captures.next() {
+            let capture = found.captures[*index];
+            let node = capture.node;
+            let surrounds = node.start_byte() < offset && offset < node.end_byte();
+            let inner = scope.is_none_or(|scope| node.start_byte() >= scope.start_byte());
+            if capture.index == indent && surrounds && inner {
+                scope = Some(node);
+            }
+        }
+        let scope = scope?;
+
+        // The closing node is the last child, past the range searched so far.
+        let mut close = None;
+        if let Some(outdent) = outdent {
+            let mut cursor = QueryCursor::new();
+            cursor.set_byte_range(scope.end_byte().saturating_sub(1)..scope.end_byte());
+            let mut captures = cursor.captures(query, scope, text);
+            while let Some((found, index)) = captures.next() {
+                let capture = found.captures[*index];
+                let node = capture.node;
+                if capture.index == outdent
+                    && node.parent() == Some(scope)
+                    && node.end_byte() == scope.end_byte()
+                {
+                    close = Some(node.start_byte());
+                }
+            }
+        }
+        Some(IndentScope {
+            range: scope.byte_range(),
+            close,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HighlightSpan {
+    pub range: Range<usize>,
+    /// Index of its name in [`Highlights::names`].
+    pub highlight: usize,
+}
+
+/// Highlighted ranges of a document, sorted and disjoint. Text in none of them is plain.
+#[derive(Component, Clone, Debug, Default)]
+pub struct Highlights {
+    names: Arc<Vec<String>>,
+    spans: Vec<HighlightSpan>,
+}
+
+impl Highlights {
+    /// Highlight names of the grammar, e.g. `keyword` or `function.method`, for a theme to
+    /// pick colors by.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn name(&self, span: &HighlightSpan) -> &str {
+        &self.names[span.highlight]
+    }
+
+    pub fn spans(&self) -> &[HighlightSpan] {
+        &self.spans
+    }
+
+    /// The spans overlapping `range`, e.g. the visible lines.
+    pub fn in_range(&self, range: Range<usize>) -> &[HighlightSpan] {
+        let start = self.spans.partition_point(|s| s.range.end <= range.start);
+        let end = self.spans.partition_point(|s| s.range.start < range.end);
+        &self.spans[start..end.max(start)]
+    }
+
+    /// Moves the spans after `change` along. The ones it touches are dropped, and returned
+    /// as they would be now for them to be highlighted again.
+    fn apply(&mut self, change: &Change) -> Range<usize> {
+        let Range { start, end } = change.range;
+        let new_end = start + change.text.len();
+        let shift = |offset: usize| offset - end + new_end;
+        let mut stale = start..new_end;
+        self.spans.retain_mut(|span| {
+            if span.range.end < start {
+                true
+            } else if span.range.start > end {
+                span.range = shift(span.range.start)..shift(span.range.end);
+                true
+            } else {
+                stale.start = stale.start.min(span.range.start);
+                stale.end = stale.end.max(match span.range.end > end {
+                    true => shift(span.range.end),
+                    false => new_end,
+                });
+                false
+            }
+        });
+        stale
+    }
+}
+
+fn parse(parser: &mut Parser, buffer: &TextBuffer, old: Option<&Tree>) -> Option<Tree> {
+    parser.parse_with(&mut |offset, _| buffer.bytes_at(offset), old)
+}
+
+/// Rough bytes a node of a tree takes, to tell the memory budget.
+const BYTES_PER_NODE: usize = 48;
+
+/// Parses documents as they are opened, and evicted ones again as they are shown.
+#[allow(clippy::type_complexity)]
+fn attach_syntax(
+    mut commands: Commands,
+    documents: Query<(Entity, &Document), (Or<(Added<Document>, Added<Visible>)>, Without<Syntax>)>,
+    (settings, grammars, parsers): (Res<SyntaxSettings>, Res<Grammars>, Res<Parsers>),
+    mut decorations: EventWriter<DecorationsChanged>,
+) {
+    let mut parser = parsers.0.lock().unwrap();
+    for (entity, document) in documents.iter() {
+        let buffer = document.buffer();
+        let grammar = match document.path().and_then(|path| grammars.for_path(path)) {
+            Some(grammar) if buffer.len() <= settings.max_len => grammar.clone(),
+            _ => continue,
+        };
+        let parser = parser.get_or_insert_with(Parser::new);
+        if let Err(e) = parser.set_language(&grammar.language) {
+            warn!("🌳 Failed to use {}: {e}", grammar.name);
+            continue;
+        }
+        let tree = match parse(parser, buffer, None) {
+            Some(tree) => tree,
+            None => continue,
+        };
+        debug!("🌳 Parsed document {entity:?} as {}", grammar.name);
+        let mut highlights = Highlights {
+            names: grammar.names.clone(),
+            spans: vec![],
+        };
+        highlight(&mut highlights, &grammar, &tree, buffer, 0..buffer.len());
+        commands
+            .entity(entity)
+            .insert(Syntax {
+                grammar,
+                tree,
+                lines: LineStarts::new(buffer),
+                version: document.version(),
+                stale: vec![],
+            })
+            .insert(highlights);
+        decorations.send(DecorationsChanged {
+            entity,
+            lines: 0..buffer.line_count(),
+        });
+    }
+}
+
+/// Drops the trees and highlights of background documents, to be parsed again when shown.
+fn evict_syntax(
+    mut commands: Commands,
+    mut events: EventReader<EvictCache>,
+    mut usages: Query<&mut MemoryUsage, (With<Syntax>, Without<Visible>)>,
+) {
+    for e in events.iter().filter(|e| e.cache == Cache::SyntaxTree) {
+        if let Ok(mut usage) = usages.get_mut(e.entity) {
+            debug!("🌳 Dropping the syntax tree of {:?}", e.entity);
+            commands
+                .entity(e.entity)
+                .remove::<Syntax>()
+                .remove::<Highlights>();
+            usage.syntax = 0;
+        }
+    }
+}
+
+fn update_syntax_memory(mut documents: Query<(&Syntax, &mut MemoryUsage), Changed<Syntax>>) {
+    for (syntax, mut usage) in documents.iter_mut() {
+        usage.syntax = syntax.tree.root_node().descendant_count() * BYTES_PER_NODE;
+    }
+}
+
+fn update_syntax(
+    mut events: EventReader<DocumentChanged>,
+    parsers: Res<Parsers>,
+    mut documents: Query<(&Document, &mut Syntax, &mut Highlights)>,
+    mut decorations: EventWriter<DecorationsChanged>,
+) {
+    // The edits of every event go into the tree before it is parsed once.
+    let mut edited = HashMap::new();
+    for e in events.iter() {
+        let (_, mut syntax, mut highlights) = match documents.get_mut(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        if e.version <= syntax.version {
+            continue;
+        }
+        let syntax = &mut *syntax;
+        for change in &e.changes {
+            let edit = syntax.lines.apply(change);
+            syntax.tree.edit(&edit);
+            // Ranges after the edit move along, ones within it shrink to its start.
+            let shift = |offset: usize, within: usize| match offset > edit.old_end_byte {
+                true => offset - edit.old_end_byte + edit.new_end_byte,
+                false if offset > edit.start_byte => within,
+                false => offset,
+            };
+            for range in &mut syntax.stale {
+                *range = shift(range.start, edit.start_byte)..shift(range.end, edit.start_byte);
+            }
+            let stale = highlights.apply(change);
+            syntax.stale.push(stale);
+        }
+        syntax.version = e.version;
+        edited.insert(e.entity, ());
+    }
+
+    let mut parser = parsers.0.lock().unwrap();
+    for entity in edited.into_keys() {
+        let (document, mut syntax, mut highlights) = match documents.get_mut(entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        // Changed again by a system after this one, the events of that come next frame.
+        if syntax.version != document.version() {
+            continue;
+        }
+        let buffer = document.buffer();
+        let parser = parser.get_or_insert_with(Parser::new);
+        if parser.set_language(&syntax.grammar.language).is_err() {
+            continue;
+        }
+        let tree = match parse(parser, buffer, Some(&syntax.tree)) {
+            Some(tree) => tree,
+            None => continue,
+        };
+        let mut stale: Vec<Range<usize>> = std::mem::take(&mut syntax.stale);
+        stale.extend(
+            syntax
+                .tree
+                .changed_ranges(&tree)
+                .map(|range| range.start_byte..range.end_byte),
+        );
+        syntax.tree = tree;
+
+        // Whole lines, merged.
+        let mut lines: Vec<Range<usize>> = stale
+            .into_iter()
+            .map(|range| {
+                let start = buffer.line_at(range.start.min(buffer.len()));
+                let end = buffer.line_at(range.end.min(buffer.len()));
+                start..end + 1
+            })
+            .collect();
+        lines.sort_by_key(|lines| lines.start);
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(lines.len());
+        for range in lines {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        for lines in merged {
+            let end = match lines.end < buffer.line_count() {
+                true => buffer.line_start(lines.end),
+                false => buffer.len(),
+            };
+            let range = buffer.line_start(lines.start)..end;
+            highlight(
+                &mut highlights,
+                &syntax.grammar,
+                &syntax.tree,
+                buffer,
+                range,
+            );
+            decorations.send(DecorationsChanged { entity, lines });
+        }
+    }
+}
+
+/// Highlights `range` again. Spans reaching into it are cut at its edges.
+fn highlight(
+    highlights: &mut Highlights,
+    grammar: &Grammar,
+    tree: &Tree,
+    buffer: &TextBuffer,
+    range: Range<usize>,
+) {
+    let mut cursor = QueryCursor::new();
+    cursor.set_byte_range(range.clone());
+    let text = |node: tree_sitter::Node| buffer.chunks_in(node.byte_range()).map(str::as_bytes);
+    let mut captures = cursor.captures(&grammar.query, tree.root_node(), text);
+    let mut found: Vec<(Range<usize>, usize)> = vec![];
+    while let Some((found_match, index)) = captures.next() {
+        let capture = found_match.captures[*index];
+        found.push((capture.node.byte_range(), capture.index as usize));
+    }
+    let spans = flatten(found);
+
+    let mut kept = Vec::with_capacity(highlights.spans.len() + spans.len());
+    let mut inserted = false;
+    for span in highlights.spans.drain(..) {
+        if span.range.end <= range.start || span.range.start >= range.end {
+            if span.range.start >= range.end && !inserted {
+                kept.extend(clip(&spans, &range));
+                inserted = true;
+            }
+            kept.push(span);
+            continue;
+        }
+        // Cut at the edges of the range.
+        if span.range.start < range.start {
+            kept.push(HighlightSpan {
+                range: span.range.start..range.start,
+                highlight: span.highlight,
+            });
+        }
+        if span.range.end > range.end {
+            if !inserted {
+                kept.extend(clip(&spans, &range));
+                inserted = true;
+            }
+            kept.push(HighlightSpan {
+                range: range.end..span.range.end,
+                highlight: span.highlight,
+            });
+        }
+    }
+    if !inserted {
+        kept.extend(clip(&spans, &range));
+    }
+    highlights.spans = kept;
+}
+
+fn clip<'a>(
+    spans: &'a [HighlightSpan],
+    range: &'a Range<usize>,
+) -> impl Iterator<Item = HighlightSpan> + 'a {
+    spans.iter().filter_map(move |span| {
+        let clipped = span.range.start.max(range.start)..span.range.end.min(range.end);
+        (!clipped.is_empty()).then_some(HighlightSpan {
+            range: clipped,
+            highlight: span.highlight,
+        })
+    })
+}
+
+/// Nested captures into disjoint spans, the innermost one winning. Of captures of the same
+/// node, the first wins.
+fn flatten(mut captures: Vec<(Range<usize>, usize)>) -> Vec<HighlightSpan> {
+    // Stable, outer ones first, the order of patterns kept for the same node.
+    captures.sort_by_key(|(range, _)| (range.start, std::cmp::Reverse(range.end)));
+    captures.dedup_by(|b, a| a.0 == b.0);
+
+    let mut spans: Vec<HighlightSpan> = vec![];
+    let mut push = |range: Range<usize>, highlight: usize| {
+        if range.is_empty() {
+            return;
+        }
+        match spans.last_mut() {
+            Some(last) if last.range.end == range.start && last.highlight == highlight => {
+                last.range.end = range.end;
+            }
+            _ => spans.push(HighlightSpan { range, highlight }),
+        }
+    };
+    // Captures open around the current one, and where the next span starts.
+    let mut open: Vec<(Range<usize>, usize)> = vec![];
+    let mut at = 0;
+    for (range, highlight) in captures {
+        while let Some((outer, outer_highlight)) = open.last().cloned() {
+            if outer.end > range.start {
+                break;
+            }
+            push(at.max(outer.start)..outer.end, outer_highlight);
+            at = at.max(outer.end);
+            open.pop();
+        }
+        if let Some((outer, outer_highlight)) = open.last() {
+            push(at.max(outer.start)..range.start, *outer_highlight);
+        }
+        at = at.max(range.start);
+        // Nodes nest, but keep within the one around just in case.
+        let end = open
+            .last()
+            .map_or(range.end, |(outer, _)| range.end.min(outer.end));
+        open.push((range.start..end, highlight));
+    }
+    while let Some((outer, highlight)) = open.pop() {
+        push(at.max(outer.start)..outer.end, highlight);
+        at = at.max(outer.end);
+    }
+    spans
+}
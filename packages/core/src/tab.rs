@@ -0,0 +1,555 @@
+use crate::{
+    command::{CoreCommand, UICommand},
+    cursor::{clamp, Cursor, Selection},
+    document::{
+        same_file, Document, DocumentChanged, DocumentLoadFailed, DocumentOpened, OpenDocument,
+    },
+    limbo::Closed,
+    workspace::{CloseDocument, DocumentClosed, FocusDocument},
+    zoom::Scroll,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        query::Without,
+        system::{Query, Res, ResMut},
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+};
+
+pub struct TabPlugin;
+
+impl Plugin for TabPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EditorGroup>()
+            .init_resource::<RecentlyClosed>()
+            .init_resource::<Reopening>()
+            .add_event::<PreviewDocument>()
+            .add_event::<CloseAllTabs>()
+            .add_event::<ReopenClosedTab>()
+            .add_system(preview_documents)
+            .add_system(open_tabs)
+            .add_system(keep_edited_tabs)
+            .add_system(close_tabs)
+            .add_system(run_tab_actions)
+            .add_system(send_tabs)
+            .add_system(record_closed_tab)
+            .add_system(reopen_closed_tab)
+            .add_system(restore_reopened_tabs)
+            .add_system(send_recently_closed);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tab {
+    pub path: PathBuf,
+    /// Preview tabs are reused by the next single-click open and shown in italic.
+    pub preview: bool,
+    /// Pinned tabs stay at the front of the group and survive [`CloseAllTabs`].
+    pub pinned: bool,
+}
+
+/// The tabs of the documents opened with a path, following [`DocumentOpened`],
+/// [`DocumentClosed`] and edits.
+#[derive(Debug, Default)]
+pub struct EditorGroup {
+    tabs: Vec<Tab>,
+    active: Option<PathBuf>,
+    /// Sent with [`PreviewDocument`] and not opened yet.
+    previewing: Vec<PathBuf>,
+}
+
+impl EditorGroup {
+    pub fn tabs(&self) -> &[Tab] {
+        &self.tabs
+    }
+
+    pub fn active(&self) -> Option<&Tab> {
+        let active = self.active.as_ref()?;
+        self.tabs.iter().find(|t| &t.path == active)
+    }
+
+    pub fn position(&self, path: &Path) -> Option<usize> {
+        self.tabs.iter().position(|t| t.path == path)
+    }
+
+    /// Makes the tab of `path` the active one, if it has one.
+    pub fn focus(&mut self, path: &Path) {
+        if self.position(path).is_some() {
+            self.active = Some(path.to_path_buf());
+        }
+    }
+
+    /// Single-click open: shows `path` in the group's preview tab, replacing whatever it held.
+    pub fn open_preview(&mut self, path: PathBuf) -> usize {
+        if let Some(i) = self.position(&path) {
+            self.active = Some(path);
+            return i;
+        }
+
+        let tab = Tab {
+            path: path.clone(),
+            preview: true,
+            pinned: false,
+        };
+        let i = match self.tabs.iter().position(|t| t.preview) {
+            Some(i) => {
+                self.tabs[i] = tab;
+                i
+            }
+            None => {
+                self.tabs.push(tab);
+                self.tabs.len() - 1
+            }
+        };
+        self.active = Some(path);
+        i
+    }
+
+    /// Double-click open: opens `path` in a regular tab, keeping it if it was previewed.
+    pub fn open(&mut self, path: PathBuf) -> usize {
+        let i = match self.position(&path) {
+            Some(i) => i,
+            None => {
+                self.tabs.push(Tab {
+                    path: path.clone(),
+                    preview: false,
+                    pinned: false,
+                });
+                self.tabs.len() - 1
+            }
+        };
+        self.tabs[i].preview = false;
+        self.active = Some(path);
+        i
+    }
+
+    /// Turns a preview tab into a regular one, e.g. once its document is edited.
+    pub fn keep(&mut self, path: &Path) {
+        if let Some(i) = self.position(path) {
+            self.tabs[i].preview = false;
+        }
+    }
+
+    /// Pins the tab at `index`, moving it to the end of the pinned tabs. Returns its new index,
+    /// or `None` if there is no tab at `index`.
+    pub fn pin(&mut self, index: usize) -> Option<usize> {
+        if index >= self.tabs.len() {
+            return None;
+        }
+
+        let mut tab = self.tabs.remove(index);
+        tab.preview = false;
+        tab.pinned = true;
+        let to = self.pinned_count();
+        self.tabs.insert(to, tab);
+        Some(to)
+    }
+
+    /// Unpins the tab at `index`, moving it right after the pinned tabs. Returns its new index,
+    /// or `None` if there is no tab at `index`.
+    pub fn unpin(&mut self, index: usize) -> Option<usize> {
+        if index >= self.tabs.len() {
+            return None;
+        }
+
+        let mut tab = self.tabs.remove(index);
+        tab.pinned = false;
+        let to = self.pinned_count();
+        self.tabs.insert(to, tab);
+        Some(to)
+    }
+
+    pub fn close(&mut self, index: usize) -> Option<Tab> {
+        if index >= self.tabs.len() {
+            return None;
+        }
+
+        let tab = self.tabs.remove(index);
+        if self.active.as_ref() == Some(&tab.path) {
+            let next = index.min(self.tabs.len().saturating_sub(1));
+            self.active = self.tabs.get(next).map(|t| t.path.clone());
+        }
+        Some(tab)
+    }
+
+    /// The tabs [`CloseAllTabs`] closes, all but the pinned ones.
+    pub fn unpinned(&self) -> impl Iterator<Item = &Tab> {
+        self.tabs.iter().filter(|t| !t.pinned)
+    }
+
+    fn pinned_count(&self) -> usize {
+        self.tabs.iter().take_while(|t| t.pinned).count()
+    }
+}
+
+/// Opens `path` in the preview tab, e.g. while stepping through search results. The next
+/// preview replaces it and closes its document, unless it was kept or edited.
+#[derive(Clone, Debug)]
+pub struct PreviewDocument {
+    pub path: PathBuf,
+}
+
+/// Closes the documents of every tab but the pinned ones.
+#[derive(Clone, Copy, Debug)]
+pub struct CloseAllTabs;
+
+/// From the tab strip of the view, by index into [`EditorGroup::tabs`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TabAction {
+    Focus(usize),
+    /// Double-click, turning a preview tab into a regular one.
+    Keep(usize),
+    Pin(usize),
+    Unpin(usize),
+    Close(usize),
+}
+
+/// The tabs of the editor group as the view shows them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TabStrip {
+    pub tabs: Vec<Tab>,
+    pub active: Option<usize>,
+}
+
+/// A closed document and where it was scrolled to, to open it the same way again.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct ClosedTab {
+    pub path: PathBuf,
+    /// Byte offset of the primary cursor.
+    pub cursor: usize,
+    /// [`Scroll::top`], in physical pixels.
+    pub scroll: f32,
+}
+
+/// Bounded stack of closed tabs, most recent first.
+#[derive(Debug)]
+pub struct RecentlyClosed {
+    tabs: VecDeque<ClosedTab>,
+    capacity: usize,
+}
+
+impl Default for RecentlyClosed {
+    fn default() -> Self {
+        Self::with_capacity(20)
+    }
+}
+
+impl RecentlyClosed {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            tabs: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, tab: ClosedTab) {
+        self.tabs.retain(|t| t.path != tab.path);
+        self.tabs.push_front(tab);
+        self.tabs.truncate(self.capacity);
+    }
+
+    pub fn pop(&mut self) -> Option<ClosedTab> {
+        self.tabs.pop_front()
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<ClosedTab> {
+        self.tabs.remove(index)
+    }
+
+    /// Entries for a picker, most recently closed first.
+    pub fn iter(&self) -> impl Iterator<Item = &ClosedTab> {
+        self.tabs.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tabs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tabs.is_empty()
+    }
+}
+
+/// Opens a closed tab again with [`OpenDocument`], which revives its document if it is
+/// still [`crate::limbo::Closed`], and puts its cursor and scroll back.
+#[derive(Clone, Copy, Debug)]
+pub enum ReopenClosedTab {
+    Last,
+    /// Index into `RecentlyClosed::iter`, as picked from the list.
+    Index(usize),
+}
+
+/// Closed tabs being opened again.
+#[derive(Debug, Default)]
+pub struct Reopening {
+    opening: Vec<ClosedTab>,
+    /// Waiting for their cursor and scroll, attached a frame after the document is spawned.
+    opened: Vec<(Entity, ClosedTab)>,
+}
+
+/// The open document of `path`, leaving out closed ones kept to be reopened.
+fn document_at(
+    documents: &Query<(Entity, &Document), Without<Closed>>,
+    path: &Path,
+) -> Option<Entity> {
+    documents
+        .iter()
+        .find(|(_, document)| document.path().is_some_and(|p| same_file(p, path)))
+        .map(|(entity, _)| entity)
+}
+
+fn preview_documents(
+    mut events: EventReader<PreviewDocument>,
+    mut group: ResMut<EditorGroup>,
+    mut open: EventWriter<OpenDocument>,
+) {
+    for e in events.iter() {
+        group.previewing.push(e.path.clone());
+        open.send(OpenDocument {
+            path: e.path.clone(),
+        });
+    }
+}
+
+fn open_tabs(
+    mut opened: EventReader<DocumentOpened>,
+    mut failed: EventReader<DocumentLoadFailed>,
+    mut focused: EventReader<FocusDocument>,
+    mut group: ResMut<EditorGroup>,
+    documents: Query<(Entity, &Document), Without<Closed>>,
+    mut closes: EventWriter<CloseDocument>,
+) {
+    for e in opened.iter() {
+        let previewing = group.previewing.iter().position(|p| same_file(p, &e.path));
+        let i = match previewing {
+            Some(i) => i,
+            None => {
+                group.open(e.path.clone());
+                continue;
+            }
+        };
+        group.previewing.remove(i);
+        let replaced = group
+            .tabs()
+            .iter()
+            .find(|t| t.preview && !same_file(&t.path, &e.path))
+            .map(|t| t.path.clone());
+        group.open_preview(e.path.clone());
+        if let Some(entity) = replaced.and_then(|path| document_at(&documents, &path)) {
+            closes.send(CloseDocument {
+                entity,
+                discard: false,
+            });
+        }
+    }
+    for e in failed.iter() {
+        group.previewing.retain(|p| !same_file(p, &e.path));
+    }
+    for e in focused.iter() {
+        if let Some(path) = documents.get(e.entity).ok().and_then(|(_, d)| d.path()) {
+            group.focus(path);
+        }
+    }
+}
+
+/// Keeps the preview tab of a document once it is edited, not when it is reloaded.
+fn keep_edited_tabs(
+    mut changed: EventReader<DocumentChanged>,
+    documents: Query<&Document>,
+    mut group: ResMut<EditorGroup>,
+) {
+    for e in changed.iter() {
+        let document = match documents.get(e.entity) {
+            Ok(document) if document.is_dirty() => document,
+            _ => continue,
+        };
+        let path = match document.path() {
+            Some(path) => path,
+            None => continue,
+        };
+        // Only borrowed mutably for a change, so the tabs aren't sent again every edit.
+        let preview = group
+            .position(path)
+            .is_some_and(|i| group.tabs()[i].preview);
+        if preview {
+            group.keep(path);
+        }
+    }
+}
+
+fn close_tabs(
+    mut closed: EventReader<DocumentClosed>,
+    mut close_all: EventReader<CloseAllTabs>,
+    mut group: ResMut<EditorGroup>,
+    documents: Query<(Entity, &Document), Without<Closed>>,
+    mut closes: EventWriter<CloseDocument>,
+) {
+    for e in closed.iter() {
+        if let Some(i) = e.path.as_ref().and_then(|path| group.position(path)) {
+            group.close(i);
+        }
+    }
+    // The tabs go once their documents are closed, so a document with unsaved changes
+    // keeps its tab while the user is asked about them.
+    if close_all.iter().count() > 0 {
+        for tab in group.unpinned() {
+            if let Some(entity) = document_at(&documents, &tab.path) {
+                closes.send(CloseDocument {
+                    entity,
+                    discard: false,
+                });
+            }
+        }
+    }
+}
+
+fn run_tab_actions(
+    mut events: EventReader<CoreCommand>,
+    mut group: ResMut<EditorGroup>,
+    documents: Query<(Entity, &Document), Without<Closed>>,
+    (mut focus, mut closes): (EventWriter<FocusDocument>, EventWriter<CloseDocument>),
+) {
+    for e in events.iter() {
+        let action = match e {
+            CoreCommand::Tab(action) => *action,
+            _ => continue,
+        };
+        match action {
+            TabAction::Pin(i) => {
+                group.pin(i);
+            }
+            TabAction::Unpin(i) => {
+                group.unpin(i);
+            }
+            TabAction::Focus(i) | TabAction::Keep(i) | TabAction::Close(i) => {
+                let path = match group.tabs().get(i) {
+                    Some(tab) => tab.path.clone(),
+                    None => continue,
+                };
+                let entity = document_at(&documents, &path);
+                match (action, entity) {
+                    (TabAction::Keep(_), _) => group.keep(&path),
+                    (TabAction::Focus(_), Some(entity)) => focus.send(FocusDocument { entity }),
+                    (TabAction::Close(_), Some(entity)) => closes.send(CloseDocument {
+                        entity,
+                        discard: false,
+                    }),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+fn send_tabs(group: Res<EditorGroup>, mut ui: EventWriter<UICommand>) {
+    if group.is_changed() {
+        ui.send(UICommand::Tabs(TabStrip {
+            tabs: group.tabs.clone(),
+            active: group
+                .active
+                .as_deref()
+                .and_then(|path| group.position(path)),
+        }));
+    }
+}
+
+fn record_closed_tab(
+    mut events: EventReader<DocumentClosed>,
+    views: Query<(Option<&Cursor>, Option<&Scroll>)>,
+    mut closed: ResMut<RecentlyClosed>,
+) {
+    for e in events.iter() {
+        // Untitled documents can't be opened again.
+        let path = match &e.path {
+            Some(path) => path.clone(),
+            None => continue,
+        };
+        // Gone already when its changes were thrown away.
+        let (cursor, scroll) = views.get(e.entity).unwrap_or((None, None));
+        closed.push(ClosedTab {
+            path,
+            cursor: cursor.map_or(0, |cursor| cursor.offset),
+            scroll: scroll.map_or(0., |scroll| scroll.top),
+        });
+    }
+}
+
+fn reopen_closed_tab(
+    mut events: EventReader<ReopenClosedTab>,
+    mut core: EventReader<CoreCommand>,
+    mut closed: ResMut<RecentlyClosed>,
+    mut reopening: ResMut<Reopening>,
+    mut open: EventWriter<OpenDocument>,
+) {
+    let picked = core.iter().filter_map(|cmd| match cmd {
+        CoreCommand::ReopenClosedTab(i) => Some(ReopenClosedTab::Index(*i)),
+        _ => None,
+    });
+    for cmd in events.iter().copied().chain(picked) {
+        let tab = match cmd {
+            ReopenClosedTab::Last => closed.pop(),
+            ReopenClosedTab::Index(i) => closed.remove(i),
+        };
+
+        if let Some(tab) = tab {
+            open.send(OpenDocument {
+                path: tab.path.clone(),
+            });
+            reopening.opening.push(tab);
+        }
+    }
+}
+
+fn restore_reopened_tabs(
+    mut opened: EventReader<DocumentOpened>,
+    mut failed: EventReader<DocumentLoadFailed>,
+    mut reopening: ResMut<Reopening>,
+    mut views: Query<(&Document, &mut Cursor, &mut Selection, &mut Scroll)>,
+) {
+    if reopening.opening.is_empty() && reopening.opened.is_empty() {
+        return;
+    }
+    let reopening = &mut *reopening;
+    for e in opened.iter() {
+        if let Some(i) = reopening
+            .opening
+            .iter()
+            .position(|tab| same_file(&tab.path, &e.path))
+        {
+            let tab = reopening.opening.remove(i);
+            reopening.opened.push((e.entity, tab));
+        }
+    }
+    for e in failed.iter() {
+        reopening
+            .opening
+            .retain(|tab| !same_file(&tab.path, &e.path));
+    }
+
+    reopening.opened.retain(|(entity, tab)| {
+        let (document, mut cursor, mut selection, mut scroll) = match views.get_mut(*entity) {
+            Ok(found) => found,
+            Err(_) => return true,
+        };
+        // The file may have changed while it was closed.
+        let offset = clamp(document.buffer(), tab.cursor);
+        *cursor = Cursor::at(offset);
+        *selection = Selection { anchor: offset };
+        scroll.top = tab.scroll;
+        false
+    });
+}
+
+fn send_recently_closed(closed: Res<RecentlyClosed>, mut ui: EventWriter<UICommand>) {
+    if closed.is_changed() {
+        let paths = closed.iter().map(|tab| tab.path.clone()).collect();
+        ui.send(UICommand::RecentlyClosed(paths));
+    }
+}
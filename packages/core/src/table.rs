@@ -0,0 +1,170 @@
+use crate::{
+    command::{RegisterCommand, RunCommand},
+    cursor::Cursor,
+    document::{Document, DocumentChanged, DocumentEditSet},
+    keymap::{parse_keys, Binding, Keymap, Layer},
+    pipeline::{AppPipelineExt, EditorStage},
+    workspace::Workspace,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Local, Query, Res, ResMut},
+    },
+    log::{debug, warn},
+};
+
+pub use dip_editor::table::{
+    edit_table, is_table_line, Alignment, Row, Table, TableEdit, TableError, TableStyle,
+};
+
+const LAYER: &str = "table";
+/// Where tab moves between cells. Elsewhere a line starting with `|` is more likely code,
+/// like a match arm.
+const TABLE_EXTENSIONS: &[&str] = &["md", "markdown", "org", "txt"];
+
+pub struct TablePlugin;
+
+impl Plugin for TablePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<EditTable>()
+            .add_event::<TableFailed>()
+            .register_command("table.format", "Table", "Format Table")
+            .register_command("table.nextCell", "Table", "Next Cell")
+            .register_command("table.previousCell", "Table", "Previous Cell")
+            .register_command("table.insertRowAbove", "Table", "Insert Row Above")
+            .register_command("table.insertRowBelow", "Table", "Insert Row Below")
+            .register_command("table.deleteRow", "Table", "Delete Row")
+            .register_command("table.insertColumnLeft", "Table", "Insert Column Left")
+            .register_command("table.insertColumnRight", "Table", "Insert Column Right")
+            .register_command("table.deleteColumn", "Table", "Delete Column")
+            .add_editor_system(EditorStage::Commands, run_table_commands)
+            .add_editor_system(EditorStage::Edits, edit_tables.label(DocumentEditSet))
+            .add_system(bind_table_keys);
+    }
+}
+
+/// Edits the pipe table under the primary cursor, which is formatted along the way.
+#[derive(Clone, Copy, Debug)]
+pub struct EditTable {
+    pub entity: Entity,
+    pub edit: TableEdit,
+}
+
+/// Nothing was changed.
+#[derive(Clone, Debug)]
+pub struct TableFailed {
+    pub entity: Entity,
+    pub error: String,
+}
+
+fn run_table_commands(
+    mut events: EventReader<RunCommand>,
+    workspace: Res<Workspace>,
+    mut tables: EventWriter<EditTable>,
+) {
+    for e in events.iter() {
+        let entity = match (e.id.starts_with("table."), workspace.active()) {
+            (true, Some(entity)) => entity,
+            _ => continue,
+        };
+        let edit = match e.id.as_str() {
+            "table.format" => TableEdit::Format,
+            "table.nextCell" => TableEdit::NextCell,
+            "table.previousCell" => TableEdit::PreviousCell,
+            "table.insertRowAbove" => TableEdit::InsertRow { above: true },
+            "table.insertRowBelow" => TableEdit::InsertRow { above: false },
+            "table.deleteRow" => TableEdit::DeleteRow,
+            "table.insertColumnLeft" => TableEdit::InsertColumn { left: true },
+            "table.insertColumnRight" => TableEdit::InsertColumn { left: false },
+            "table.deleteColumn" => TableEdit::DeleteColumn,
+            _ => continue,
+        };
+        tables.send(EditTable { entity, edit });
+    }
+}
+
+fn edit_tables(
+    mut events: EventReader<EditTable>,
+    mut documents: Query<(&mut Document, &Cursor)>,
+    mut changed: EventWriter<DocumentChanged>,
+    mut failed: EventWriter<TableFailed>,
+) {
+    for e in events.iter() {
+        let (mut document, cursor) = match documents.get_mut(e.entity) {
+            Ok(found) => found,
+            Err(_) => continue,
+        };
+        // The cursor catches up with other edits of this frame later.
+        let cursor = match edit_table(&mut document, cursor.offset, e.edit) {
+            Ok(Some(cursor)) => cursor,
+            Ok(None) => continue,
+            Err(error) => {
+                warn!("📊 {error}");
+                failed.send(TableFailed {
+                    entity: e.entity,
+                    error: error.to_string(),
+                });
+                continue;
+            }
+        };
+        let line = document.buffer().line_at(cursor);
+        debug!("📊 {:?} in the table at line {}", e.edit, line + 1);
+        changed.send(DocumentChanged {
+            entity: e.entity,
+            version: document.version(),
+            changes: document.take_changes(),
+            cursor: Some(cursor),
+        });
+    }
+}
+
+/// Takes tab and shift+tab for moving between cells while the cursor is in a table of a
+/// document that has them.
+fn bind_table_keys(
+    workspace: Res<Workspace>,
+    documents: Query<(&Document, &Cursor)>,
+    mut keymap: ResMut<Keymap>,
+    mut bound: Local<bool>,
+) {
+    let in_table = workspace
+        .active()
+        .and_then(|entity| documents.get(entity).ok())
+        .is_some_and(|(document, cursor)| {
+            let prose = document
+                .path()
+                .and_then(|path| path.extension())
+                .and_then(|extension| extension.to_str())
+                .is_none_or(|extension| {
+                    TABLE_EXTENSIONS
+                        .iter()
+                        .any(|e| e.eq_ignore_ascii_case(extension))
+                });
+            let buffer = document.buffer();
+            let line = buffer.line_at(cursor.offset.min(buffer.len()));
+            prose && is_table_line(&buffer.get_line_content(line))
+        });
+    if in_table == *bound {
+        return;
+    }
+    *bound = in_table;
+    if !in_table {
+        keymap.remove_layer(LAYER);
+        return;
+    }
+    let mut layer = Layer::new(LAYER);
+    for (keys, command) in [
+        ("tab", "table.nextCell"),
+        ("shift+tab", "table.previousCell"),
+    ] {
+        layer.bindings.push(Binding {
+            keys: parse_keys(keys).expect("built-in keys parse"),
+            command: Some(command.to_string()),
+            mode: None,
+        });
+    }
+    keymap.set_layer(layer);
+}
@@ -0,0 +1,692 @@
+use crate::{
+    config::{Settings, SettingsSection},
+    damage::DecorationsChanged,
+    document::{DiskStamp, Document},
+    exclude::SETTINGS_FILE,
+    macros::config_dir,
+    workspace::{Workspace, WorkspaceChanged},
+};
+use bevy::{
+    app::{App, Plugin},
+    core::Time,
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        system::{Local, Query, Res, ResMut},
+    },
+    log::{debug, warn},
+};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// The color theme without `workbench.colorTheme` set.
+pub const DEFAULT_COLOR_THEME: &str = "dark";
+
+/// How often the file of the color theme is checked for changes, in seconds.
+const POLL_INTERVAL: f64 = 1.0;
+
+pub struct ThemePlugin;
+
+impl Plugin for ThemePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Theme>()
+            .init_resource::<Themes>()
+            .init_resource::<DesignTokens>()
+            .add_system(load_theme);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl From<lsp_types::DiagnosticSeverity> for Severity {
+    fn from(severity: lsp_types::DiagnosticSeverity) -> Self {
+        match severity {
+            lsp_types::DiagnosticSeverity::ERROR => Severity::Error,
+            lsp_types::DiagnosticSeverity::WARNING => Severity::Warning,
+            lsp_types::DiagnosticSeverity::INFORMATION => Severity::Information,
+            _ => Severity::Hint,
+        }
+    }
+}
+
+/// How a line differs from the version on disk or in git, marked in the gutter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LineStatus {
+    Added,
+    Modified,
+    Deleted,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    const fn hex(rgb: u32) -> Self {
+        Self((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+    }
+
+    /// `amount` of the way from this color to `other`.
+    pub fn mix(self, other: Rgb, amount: f32) -> Rgb {
+        let amount = amount.clamp(0., 1.);
+        let channel = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * amount).round() as u8;
+        Rgb(
+            channel(self.0, other.0),
+            channel(self.1, other.1),
+            channel(self.2, other.2),
+        )
+    }
+}
+
+/// `#rrggbb`, as in CSS.
+impl FromStr for Rgb {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s
+            .strip_prefix('#')
+            .filter(|hex| hex.len() == 6 && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(rgb) => Ok(Rgb::hex(rgb)),
+            None => Err(format!("expected a color like #1e1e1e, found {s:?}")),
+        }
+    }
+}
+
+impl TryFrom<String> for Rgb {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
+
+/// Drawn in the gutter and on the scrollbar, so markers tell apart without their color.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Shape {
+    Circle,
+    Triangle,
+    Square,
+    Diamond,
+    Bar,
+    StripedBar,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Underline {
+    Wavy,
+    Dashed,
+    Dotted,
+}
+
+/// How a diagnostic or line status is drawn. `shape` is None when only color is used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Marker {
+    pub color: Rgb,
+    pub shape: Option<Shape>,
+    pub underline: Underline,
+}
+
+/// Colors of the theme variants. The color-blind safe ones keep marker colors apart for
+/// red-green (deuteranopia, protanopia) or blue-yellow (tritanopia) color blindness.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Palette {
+    #[default]
+    Default,
+    RedGreenSafe,
+    BlueYellowSafe,
+}
+
+impl Palette {
+    pub fn severity(self, severity: Severity) -> Rgb {
+        let rgb = match (self, severity) {
+            (Palette::Default, Severity::Error) => 0xf14c4c,
+            (Palette::Default, Severity::Warning) => 0xcca700,
+            (Palette::Default, Severity::Information) => 0x3794ff,
+            // Okabe and Ito's palette.
+            (Palette::RedGreenSafe, Severity::Error) => 0xd55e00,
+            (Palette::RedGreenSafe, Severity::Warning) => 0xe69f00,
+            (Palette::RedGreenSafe, Severity::Information) => 0x0072b2,
+            // Paul Tol's bright and vibrant schemes.
+            (Palette::BlueYellowSafe, Severity::Error) => 0xcc3311,
+            (Palette::BlueYellowSafe, Severity::Warning) => 0xee3377,
+            (Palette::BlueYellowSafe, Severity::Information) => 0x009988,
+            (_, Severity::Hint) => 0xa0a0a0,
+        };
+        Rgb::hex(rgb)
+    }
+
+    pub fn line_status(self, status: LineStatus) -> Rgb {
+        let rgb = match (self, status) {
+            (Palette::Default, LineStatus::Added) => 0x2ea043,
+            (Palette::Default, LineStatus::Modified) => 0x0078d4,
+            (Palette::Default, LineStatus::Deleted) => 0xf85149,
+            (Palette::RedGreenSafe, LineStatus::Added) => 0x56b4e9,
+            (Palette::RedGreenSafe, LineStatus::Modified) => 0xe69f00,
+            (Palette::RedGreenSafe, LineStatus::Deleted) => 0xd55e00,
+            (Palette::BlueYellowSafe, LineStatus::Added) => 0x009988,
+            (Palette::BlueYellowSafe, LineStatus::Modified) => 0xee7733,
+            (Palette::BlueYellowSafe, LineStatus::Deleted) => 0xcc3311,
+        };
+        Rgb::hex(rgb)
+    }
+}
+
+/// The palette and encodings decorations and scrollbar markers are drawn with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Theme {
+    pub palette: Palette,
+    /// Tells markers apart by shape and underline too, not just by color.
+    pub shapes: bool,
+}
+
+impl Theme {
+    pub fn severity(&self, severity: Severity) -> Marker {
+        let (shape, underline) = match severity {
+            Severity::Error => (Shape::Circle, Underline::Wavy),
+            Severity::Warning => (Shape::Triangle, Underline::Dashed),
+            Severity::Information => (Shape::Square, Underline::Dotted),
+            Severity::Hint => (Shape::Diamond, Underline::Dotted),
+        };
+        self.marker(self.palette.severity(severity), shape, underline)
+    }
+
+    pub fn line_status(&self, status: LineStatus) -> Marker {
+        let shape = match status {
+            LineStatus::Added => Shape::Bar,
+            LineStatus::Modified => Shape::StripedBar,
+            LineStatus::Deleted => Shape::Triangle,
+        };
+        self.marker(self.palette.line_status(status), shape, Underline::Wavy)
+    }
+
+    fn marker(&self, color: Rgb, shape: Shape, underline: Underline) -> Marker {
+        if self.shapes {
+            Marker {
+                color,
+                shape: Some(shape),
+                underline,
+            }
+        } else {
+            Marker {
+                color,
+                shape: None,
+                underline: Underline::Wavy,
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeKind {
+    #[default]
+    Dark,
+    Light,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Typography {
+    /// A CSS font family list.
+    pub font_family: String,
+    /// In pixels.
+    pub font_size: f32,
+    /// A multiple of the font size.
+    pub line_height: f32,
+}
+
+impl Default for Typography {
+    fn default() -> Self {
+        Self {
+            font_family: "Menlo, Consolas, 'DejaVu Sans Mono', monospace".into(),
+            font_size: 14.,
+            line_height: 1.5,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Spacing {
+    /// In pixels, the gaps between elements are multiples of it.
+    pub unit: f32,
+}
+
+impl Default for Spacing {
+    fn default() -> Self {
+        Self { unit: 4. }
+    }
+}
+
+impl Spacing {
+    pub fn step(&self, n: u32) -> f32 {
+        self.unit * n as f32
+    }
+}
+
+/// What the view draws with: colors by name, e.g. `editor.background` or
+/// `statusBar.foreground`, colors of syntax highlights, fonts and spacing. Picked by
+/// `workbench.colorTheme` and replaced when it or the theme's file changes, so rendering
+/// systems look at the resource's change detection.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DesignTokens {
+    pub name: String,
+    pub kind: ThemeKind,
+    pub colors: BTreeMap<String, Rgb>,
+    /// By highlight name, e.g. `keyword`, see [`DesignTokens::syntax`].
+    pub syntax: BTreeMap<String, Rgb>,
+    pub typography: Typography,
+    pub spacing: Spacing,
+}
+
+impl Default for DesignTokens {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl DesignTokens {
+    pub fn dark() -> Self {
+        Self::builtin(
+            "dark",
+            ThemeKind::Dark,
+            &[
+                ("editor.background", 0x1e1e1e),
+                ("editor.foreground", 0xd4d4d4),
+                ("editor.selection", 0x264f78),
+                ("editor.cursor", 0xaeafad),
+                ("editor.lineHighlight", 0x2a2d2e),
+                ("editor.editableRange", 0x1f3325),
+                ("gutter.background", 0x1e1e1e),
+                ("gutter.foreground", 0x858585),
+                ("statusBar.background", 0x007acc),
+                ("statusBar.foreground", 0xffffff),
+                ("accent", 0x0078d4),
+            ],
+            &[
+                ("comment", 0x6a9955),
+                ("constant", 0x4fc1ff),
+                ("function", 0xdcdcaa),
+                ("keyword", 0x569cd6),
+                ("number", 0xb5cea8),
+                ("operator", 0xd4d4d4),
+                ("string", 0xce9178),
+                ("type", 0x4ec9b0),
+                ("variable", 0x9cdcfe),
+            ],
+        )
+    }
+
+    pub fn light() -> Self {
+        Self::builtin(
+            "light",
+            ThemeKind::Light,
+            &[
+                ("editor.background", 0xffffff),
+                ("editor.foreground", 0x1f1f1f),
+                ("editor.selection", 0xadd6ff),
+                ("editor.cursor", 0x000000),
+                ("editor.lineHighlight", 0xf3f3f3),
+                ("editor.editableRange", 0xe6f4e6),
+                ("gutter.background", 0xffffff),
+                ("gutter.foreground", 0x237893),
+                ("statusBar.background", 0x007acc),
+                ("statusBar.foreground", 0xffffff),
+                ("accent", 0x005fb8),
+            ],
+            &[
+                ("comment", 0x008000),
+                ("constant", 0x0070c1),
+                ("function", 0x795e26),
+                ("keyword", 0x0000ff),
+                ("number", 0x098658),
+                ("operator", 0x000000),
+                ("string", 0xa31515),
+                ("type", 0x267f99),
+                ("variable", 0x001080),
+            ],
+        )
+    }
+
+    fn builtin(
+        name: &str,
+        kind: ThemeKind,
+        colors: &[(&str, u32)],
+        syntax: &[(&str, u32)],
+    ) -> Self {
+        let map = |colors: &[(&str, u32)]| {
+            colors
+                .iter()
+                .map(|&(key, rgb)| (key.to_string(), Rgb::hex(rgb)))
+                .collect()
+        };
+        Self {
+            name: name.into(),
+            kind,
+            colors: map(colors),
+            syntax: map(syntax),
+            typography: Typography::default(),
+            spacing: Spacing::default(),
+        }
+    }
+
+    pub fn color(&self, key: &str) -> Option<Rgb> {
+        self.colors.get(key).copied()
+    }
+
+    /// Color of a highlight, falling back to the name's parents, e.g. `function` for
+    /// `function.method`.
+    pub fn syntax(&self, highlight: &str) -> Option<Rgb> {
+        let mut name = highlight;
+        loop {
+            if let Some(&rgb) = self.syntax.get(name) {
+                return Some(rgb);
+            }
+            name = name.rsplit_once('.')?.0;
+        }
+    }
+
+    /// The tokens as CSS custom properties for a web view to style itself with, e.g.
+    /// `--dip-color-status-bar-background` or `--dip-space-2`.
+    pub fn css_variables(&self) -> String {
+        let mut css = String::new();
+        for (key, rgb) in &self.colors {
+            css += &format!("--dip-color-{}: {rgb}; ", kebab_case(key));
+        }
+        for (key, rgb) in &self.syntax {
+            css += &format!("--dip-syntax-{}: {rgb}; ", kebab_case(key));
+        }
+        let Typography {
+            font_family,
+            font_size,
+            line_height,
+        } = &self.typography;
+        css += &format!("--dip-font-family: {font_family}; ");
+        css += &format!("--dip-font-size: {font_size}px; ");
+        css += &format!("--dip-line-height: {line_height}; ");
+        for n in 1..=4 {
+            css += &format!("--dip-space-{n}: {}px; ", self.spacing.step(n));
+        }
+        css.trim_end().to_string()
+    }
+}
+
+fn kebab_case(key: &str) -> String {
+    let mut kebab = String::with_capacity(key.len());
+    for c in key.chars() {
+        match c {
+            '.' | '_' => kebab.push('-'),
+            c if c.is_ascii_uppercase() => {
+                kebab.push('-');
+                kebab.push(c.to_ascii_lowercase());
+            }
+            c => kebab.push(c),
+        }
+    }
+    kebab
+}
+
+/// A theme file, `<name>.toml` or `<name>.json`, changing what it wants of the theme it
+/// extends.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct ThemeFile {
+    /// A registered theme, `dark` if left out.
+    extends: Option<String>,
+    kind: Option<ThemeKind>,
+    colors: BTreeMap<String, Rgb>,
+    syntax: BTreeMap<String, Rgb>,
+    typography: TypographyFile,
+    spacing: SpacingFile,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct TypographyFile {
+    font_family: Option<String>,
+    font_size: Option<f32>,
+    line_height: Option<f32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct SpacingFile {
+    unit: Option<f32>,
+}
+
+/// The color themes to pick from: the registered ones, `dark` and `light` and those apps
+/// ship, then the files in `dir`.
+#[derive(Clone, Debug)]
+pub struct Themes {
+    /// None reads no theme files.
+    pub dir: Option<PathBuf>,
+    registered: BTreeMap<String, DesignTokens>,
+}
+
+impl Default for Themes {
+    fn default() -> Self {
+        let mut themes = Self {
+            dir: config_dir().map(|dir| dir.join("themes")),
+            registered: BTreeMap::new(),
+        };
+        themes.register(DesignTokens::dark());
+        themes.register(DesignTokens::light());
+        themes
+    }
+}
+
+impl Themes {
+    /// Adds a theme by its name, replacing the one of the same name.
+    pub fn register(&mut self, tokens: DesignTokens) {
+        self.registered.insert(tokens.name.clone(), tokens);
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.registered.keys().map(String::as_str)
+    }
+
+    /// The file of the theme `name`, None for registered themes.
+    pub fn file(&self, name: &str) -> Option<PathBuf> {
+        if self.registered.contains_key(name) {
+            return None;
+        }
+        let dir = self.dir.as_ref()?;
+        ["toml", "json"]
+            .iter()
+            .map(|extension| dir.join(name).with_extension(extension))
+            .find(|path| path.is_file())
+    }
+
+    pub fn get(&self, name: &str) -> io::Result<DesignTokens> {
+        if let Some(tokens) = self.registered.get(name) {
+            return Ok(tokens.clone());
+        }
+        match self.file(name) {
+            Some(path) => self.read(name, &path),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no color theme {name}"),
+            )),
+        }
+    }
+
+    fn read(&self, name: &str, path: &Path) -> io::Result<DesignTokens> {
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+        let source = fs::read_to_string(path)?;
+        let file: ThemeFile = if path.extension().is_some_and(|e| e == "toml") {
+            toml::from_str(&source).map_err(|e| invalid(e.to_string()))?
+        } else {
+            serde_json::from_str(&source).map_err(|e| invalid(e.to_string()))?
+        };
+        let base = file.extends.as_deref().unwrap_or(DEFAULT_COLOR_THEME);
+        let mut tokens = match self.registered.get(base) {
+            Some(tokens) => tokens.clone(),
+            None => return Err(invalid(format!("extends unknown theme {base}"))),
+        };
+        tokens.name = name.into();
+        tokens.kind = file.kind.unwrap_or(tokens.kind);
+        tokens.colors.extend(file.colors);
+        tokens.syntax.extend(file.syntax);
+        let typography = &mut tokens.typography;
+        if let Some(font_family) = file.typography.font_family {
+            typography.font_family = font_family;
+        }
+        typography.font_size = file.typography.font_size.unwrap_or(typography.font_size);
+        typography.line_height = file
+            .typography
+            .line_height
+            .unwrap_or(typography.line_height);
+        tokens.spacing.unit = file.spacing.unit.unwrap_or(tokens.spacing.unit);
+        Ok(tokens)
+    }
+}
+
+/// The theme part of the settings file. Shapes are on by default with a color-blind
+/// safe palette.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ThemeSettings {
+    #[serde(rename = "workbench.colorPalette", default)]
+    pub palette: Option<Palette>,
+    #[serde(rename = "editor.markerShapes", default)]
+    pub shapes: Option<bool>,
+    /// The name of the [`DesignTokens`], see [`Themes`].
+    #[serde(rename = "workbench.colorTheme", default)]
+    pub color_theme: Option<String>,
+}
+
+impl SettingsSection for ThemeSettings {
+    fn merge(&mut self, other: Self) {
+        self.palette = other.palette.or(self.palette);
+        self.shapes = other.shapes.or(self.shapes);
+        self.color_theme = other.color_theme.or(self.color_theme.take());
+    }
+}
+
+impl ThemeSettings {
+    /// The whole window shares one theme: the user settings apply, then the workspace
+    /// file's, then the ones of the first root.
+    pub fn load(workspace: &Workspace, user: Option<&Settings>) -> Self {
+        let mut settings = Self::default();
+        if let Some(user) = user {
+            settings.merge_values(user.values(), "user settings");
+        }
+        settings.merge_values(workspace.settings().clone(), "workspace settings");
+        if let Some(root) = workspace.roots().first() {
+            settings.merge_file(&root.path.join(SETTINGS_FILE));
+        }
+        settings
+    }
+
+    pub fn theme(&self) -> Theme {
+        let palette = self.palette.unwrap_or_default();
+        Theme {
+            palette,
+            shapes: self.shapes.unwrap_or(palette != Palette::Default),
+        }
+    }
+
+    pub fn color_theme(&self) -> &str {
+        self.color_theme.as_deref().unwrap_or(DEFAULT_COLOR_THEME)
+    }
+}
+
+/// What [`load_theme`] last loaded, to tell when to load again.
+#[derive(Debug, Default)]
+struct Loaded {
+    once: bool,
+    polled: f64,
+    /// The file of the color theme and how it looked.
+    file: Option<(PathBuf, Option<DiskStamp>)>,
+}
+
+impl Loaded {
+    /// Whether the color theme's file changed since it was read, looking once a second.
+    fn file_changed(&mut self, now: f64) -> bool {
+        if now - self.polled < POLL_INTERVAL {
+            return false;
+        }
+        self.polled = now;
+        match &mut self.file {
+            Some((path, stamp)) => {
+                let read = DiskStamp::read(path);
+                read != std::mem::replace(stamp, read)
+            }
+            None => false,
+        }
+    }
+}
+
+fn load_theme(
+    mut loaded: Local<Loaded>,
+    mut events: EventReader<WorkspaceChanged>,
+    (workspace, user, themes, time): (
+        Res<Workspace>,
+        Option<Res<Settings>>,
+        Res<Themes>,
+        Res<Time>,
+    ),
+    (mut theme, mut tokens): (ResMut<Theme>, ResMut<DesignTokens>),
+    documents: Query<(Entity, &Document)>,
+    mut decorations: EventWriter<DecorationsChanged>,
+) {
+    let user_changed = user.as_ref().is_some_and(|user| user.is_changed());
+    let settings_changed = events.iter().count() > 0 || user_changed || themes.is_changed();
+    let file_changed = loaded.file_changed(time.seconds_since_startup());
+    if !settings_changed && !file_changed && loaded.once {
+        return;
+    }
+    loaded.once = true;
+    let settings = ThemeSettings::load(&workspace, user.as_deref());
+    let name = settings.color_theme();
+    loaded.file = themes.file(name).map(|path| {
+        let stamp = DiskStamp::read(&path);
+        (path, stamp)
+    });
+    let next_tokens = match themes.get(name) {
+        Ok(next) => next,
+        Err(e) => {
+            warn!("🎨 Failed to load color theme {name}: {e}");
+            // A theme file saved half way through editing keeps what it was.
+            match tokens.name == name {
+                true => tokens.clone(),
+                false => DesignTokens::default(),
+            }
+        }
+    };
+    let next = settings.theme();
+    if next == *theme && next_tokens == *tokens {
+        return;
+    }
+    if next != *theme {
+        debug!("🎨 {next:?}");
+        *theme = next;
+    }
+    if next_tokens != *tokens {
+        debug!("🎨 Color theme {}", next_tokens.name);
+        *tokens = next_tokens;
+    }
+    for (entity, document) in documents.iter() {
+        decorations.send(DecorationsChanged {
+            entity,
+            lines: 0..document.buffer().line_count(),
+        });
+    }
+}
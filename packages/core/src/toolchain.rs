@@ -0,0 +1,202 @@
+use crate::control::{socket_path, SOCKET_ENV};
+use bevy::{
+    app::{App, Plugin},
+    ecs::system::ResMut,
+    log::debug,
+};
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+pub struct ToolchainPlugin;
+
+impl Plugin for ToolchainPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Toolchains>()
+            .add_startup_system(detect_toolchains);
+    }
+}
+
+/// Toolchains resolved for a workspace root. Tasks and language servers should be spawned
+/// through [`Toolchains::command`] so they see the same PATH the project expects.
+#[derive(Clone, Debug, Default)]
+pub struct Toolchains {
+    pub root: PathBuf,
+    pub rust: Option<RustToolchain>,
+    pub node: Option<NodeToolchain>,
+    pub python: Option<PythonEnv>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RustToolchain {
+    /// Channel pinned by `rust-toolchain(.toml)`, if any.
+    pub channel: Option<String>,
+    pub bin_dir: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeToolchain {
+    /// Version pinned by `.nvmrc` or `.node-version`, if any.
+    pub version: Option<String>,
+    pub bin_dirs: Vec<PathBuf>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PythonEnv {
+    pub root: PathBuf,
+}
+
+impl Toolchains {
+    pub fn detect(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            rust: detect_rust(root),
+            node: detect_node(root),
+            python: detect_python(root),
+        }
+    }
+
+    /// Directories to prepend to PATH, most specific first.
+    pub fn path_entries(&self) -> Vec<PathBuf> {
+        let mut entries = vec![];
+
+        if let Some(python) = &self.python {
+            entries.push(python.bin_dir());
+        }
+        if let Some(node) = &self.node {
+            entries.extend(node.bin_dirs.iter().cloned());
+        }
+        if let Some(bin_dir) = self.rust.as_ref().and_then(|r| r.bin_dir.clone()) {
+            entries.push(bin_dir);
+        }
+
+        entries
+    }
+
+    /// Environment variables a child process needs to run with the resolved toolchains.
+    pub fn env(&self) -> Vec<(OsString, OsString)> {
+        let mut vars = vec![];
+
+        let mut path = self.path_entries();
+        path.extend(env::var_os("PATH").iter().flat_map(env::split_paths));
+        if let Ok(path) = env::join_paths(path) {
+            vars.push(("PATH".into(), path));
+        }
+
+        if let Some(channel) = self.rust.as_ref().and_then(|r| r.channel.clone()) {
+            vars.push(("RUSTUP_TOOLCHAIN".into(), channel.into()));
+        }
+        if let Some(python) = &self.python {
+            vars.push(("VIRTUAL_ENV".into(), python.root.clone().into()));
+        }
+        if let Some(socket) = socket_path() {
+            vars.push((SOCKET_ENV.into(), socket.into()));
+        }
+
+        vars
+    }
+
+    pub fn command(&self, program: impl AsRef<OsStr>) -> Command {
+        let mut command = Command::new(program);
+        command.current_dir(&self.root).envs(self.env());
+        command
+    }
+}
+
+impl PythonEnv {
+    pub fn bin_dir(&self) -> PathBuf {
+        if cfg!(windows) {
+            self.root.join("Scripts")
+        } else {
+            self.root.join("bin")
+        }
+    }
+}
+
+fn detect_rust(root: &Path) -> Option<RustToolchain> {
+    let channel = ancestors_find(root, "rust-toolchain.toml")
+        .and_then(|p| fs::read_to_string(p).ok())
+        .and_then(|toml| {
+            toml.lines()
+                .filter_map(|l| l.trim().strip_prefix("channel"))
+                .filter_map(|l| l.trim_start().strip_prefix('='))
+                .map(|v| v.trim().trim_matches('"').to_string())
+                .next()
+        })
+        .or_else(|| {
+            ancestors_find(root, "rust-toolchain")
+                .and_then(|p| fs::read_to_string(p).ok())
+                .map(|s| s.trim().to_string())
+        });
+
+    let bin_dir = env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home_dir().map(|h| h.join(".cargo")))
+        .map(|h| h.join("bin"))
+        .filter(|bin| bin.is_dir());
+
+    let is_rust_project = ancestors_find(root, "Cargo.toml").is_some();
+    (is_rust_project || channel.is_some()).then_some(RustToolchain { channel, bin_dir })
+}
+
+fn detect_node(root: &Path) -> Option<NodeToolchain> {
+    let version = [".nvmrc", ".node-version"]
+        .iter()
+        .find_map(|name| ancestors_find(root, name))
+        .and_then(|p| fs::read_to_string(p).ok())
+        .map(|v| v.trim().trim_start_matches('v').to_string());
+
+    let mut bin_dirs: Vec<PathBuf> = root
+        .ancestors()
+        .map(|dir| dir.join("node_modules").join(".bin"))
+        .filter(|bin| bin.is_dir())
+        .collect();
+
+    if let (Some(version), Some(home)) = (&version, home_dir()) {
+        let nvm = env::var_os("NVM_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| home.join(".nvm"));
+        let bin = nvm
+            .join("versions")
+            .join("node")
+            .join(format!("v{version}"))
+            .join("bin");
+        if bin.is_dir() {
+            bin_dirs.push(bin);
+        }
+    }
+
+    let is_node_project = ancestors_find(root, "package.json").is_some();
+    (is_node_project || version.is_some()).then_some(NodeToolchain { version, bin_dirs })
+}
+
+fn detect_python(root: &Path) -> Option<PythonEnv> {
+    [".venv", "venv"]
+        .iter()
+        .map(|name| root.join(name))
+        .find(|dir| dir.join("pyvenv.cfg").is_file())
+        .or_else(|| env::var_os("VIRTUAL_ENV").map(PathBuf::from))
+        .map(|root| PythonEnv { root })
+}
+
+fn ancestors_find(root: &Path, name: &str) -> Option<PathBuf> {
+    root.ancestors()
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+fn detect_toolchains(mut toolchains: ResMut<Toolchains>) {
+    let root = env::current_dir().unwrap_or_default();
+    *toolchains = Toolchains::detect(&root);
+    debug!("🔧 {:?}", *toolchains);
+}
@@ -0,0 +1,90 @@
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        event::{EventReader, EventWriter},
+        system::ResMut,
+    },
+    log::{debug, warn},
+};
+use std::{fmt, path::PathBuf};
+
+pub use dip_document::vault::{
+    is_encrypted, vault_root, VaultError, VaultKey, Vaults, MAGIC, VAULT_FILE,
+};
+
+pub struct VaultPlugin;
+
+impl Plugin for VaultPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Vaults>()
+            .add_event::<UnlockVault>()
+            .add_event::<LockVault>()
+            .add_event::<VaultUnlocked>()
+            .add_event::<VaultUnlockFailed>()
+            .add_system(unlock_vaults)
+            .add_system(lock_vaults);
+    }
+}
+
+/// Unlocks the vault at `root`, creating it if `root` is not one yet.
+#[derive(Clone)]
+pub struct UnlockVault {
+    pub root: PathBuf,
+    pub passphrase: String,
+}
+
+impl fmt::Debug for UnlockVault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UnlockVault")
+            .field("root", &self.root)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LockVault {
+    pub root: PathBuf,
+}
+
+#[derive(Clone, Debug)]
+pub struct VaultUnlocked {
+    pub root: PathBuf,
+}
+
+#[derive(Debug)]
+pub struct VaultUnlockFailed {
+    pub root: PathBuf,
+    pub error: VaultError,
+}
+
+fn unlock_vaults(
+    mut events: EventReader<UnlockVault>,
+    mut vaults: ResMut<Vaults>,
+    mut unlocked: EventWriter<VaultUnlocked>,
+    mut failed: EventWriter<VaultUnlockFailed>,
+) {
+    for e in events.iter() {
+        match vaults.unlock(&e.root, &e.passphrase) {
+            Ok(()) => {
+                debug!("🔐 Unlocked {}", e.root.display());
+                unlocked.send(VaultUnlocked {
+                    root: e.root.clone(),
+                });
+            }
+            Err(error) => {
+                warn!("🔐 Failed to unlock {}: {error}", e.root.display());
+                failed.send(VaultUnlockFailed {
+                    root: e.root.clone(),
+                    error,
+                });
+            }
+        }
+    }
+}
+
+fn lock_vaults(mut events: EventReader<LockVault>, mut vaults: ResMut<Vaults>) {
+    for e in events.iter() {
+        vaults.lock(&e.root);
+        debug!("🔐 Locked {}", e.root.display());
+    }
+}
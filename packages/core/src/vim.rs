@@ -0,0 +1,1242 @@
+use crate::{
+    cursor::{Cursor, DeleteText, Movement, Selection, TypeText},
+    document::{Document, DocumentChanged, DocumentEditSet},
+    keymap::{key_name, parse_keys, Binding, Chord, Keymap, Layer},
+    pipeline::{AppPipelineExt, EditorStage},
+    text_buffer::TextBuffer,
+    workspace::Workspace,
+    Mode, ModeType,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        event::{EventReader, EventWriter},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Query, Res, ResMut},
+    },
+    input::{
+        keyboard::{KeyCode, KeyboardInput},
+        ElementState, Input,
+    },
+    log::debug,
+};
+use std::{borrow::Cow, collections::HashMap, iter::Peekable, ops::Range, str::Chars};
+use unicode_segmentation::UnicodeSegmentation;
+
+const ESCAPE: char = '\u{1b}';
+/// The register used when none is named.
+const UNNAMED: char = '"';
+/// The register holding the last yank.
+const YANKED: char = '0';
+/// Named apart from the `vim` preset, which this plugin stands in for.
+const LAYER: &str = "vim plugin";
+
+/// Modal editing the way vim does it, in an optional plugin added after
+/// [`crate::DipCorePlugin`]. Keys pressed in normal and visual mode are read as vim
+/// commands instead of going to the keymap; the keys vim leaves alone, e.g. `ctrl+r`, are
+/// bound on a keymap layer of its own, in place of the `vim` preset.
+pub struct VimPlugin;
+
+impl Plugin for VimPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Vim>()
+            .add_event::<VimKey>()
+            .add_startup_system(bind_vim_keys)
+            .add_editor_system(EditorStage::Input, read_vim_keys)
+            .add_editor_system(EditorStage::Edits, run_vim_keys.label(DocumentEditSet));
+    }
+}
+
+/// A key as vim reads it, e.g. `$` or `G`. Read from the keyboard with a US layout, so
+/// the UI may send these itself for other layouts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VimKey(pub char);
+
+impl VimKey {
+    pub const ESCAPE: VimKey = VimKey(ESCAPE);
+}
+
+/// Text yanked or deleted into a register.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Register {
+    pub text: String,
+    /// Whole lines, pasted above or below the cursor's line.
+    pub linewise: bool,
+}
+
+#[derive(Debug)]
+pub struct Vim {
+    mode: ModeType,
+    /// Keys of the command typed so far.
+    pending: String,
+    registers: HashMap<char, Register>,
+    /// The last change, repeated by `.`.
+    last_change: Option<Change>,
+    /// The change being made while in insert mode.
+    inserting: Option<Change>,
+    visual: Option<Visual>,
+}
+
+impl Default for Vim {
+    fn default() -> Self {
+        Self {
+            mode: ModeType::Normal,
+            pending: String::new(),
+            registers: HashMap::new(),
+            last_change: None,
+            inserting: None,
+            visual: None,
+        }
+    }
+}
+
+impl Vim {
+    pub fn mode(&self) -> ModeType {
+        self.mode
+    }
+
+    /// Keys of a command not complete yet, e.g. `2d`.
+    pub fn pending(&self) -> &str {
+        &self.pending
+    }
+
+    pub fn register(&self, name: char) -> Option<&Register> {
+        self.registers.get(&name.to_ascii_lowercase())
+    }
+
+    /// Stores `text` in the unnamed register and in `name`, appending to it for an upper
+    /// case name the way `"Ay` does.
+    fn store(&mut self, name: Option<char>, text: String, linewise: bool, yank: bool) {
+        let register = Register { text, linewise };
+        if let Some(name) = name.filter(|name| *name != UNNAMED) {
+            let lower = name.to_ascii_lowercase();
+            match self.registers.get_mut(&lower) {
+                Some(existing) if name.is_ascii_uppercase() => {
+                    existing.text.push_str(&register.text);
+                    existing.linewise |= register.linewise;
+                }
+                _ => {
+                    self.registers.insert(lower, register.clone());
+                }
+            }
+        } else if yank {
+            self.registers.insert(YANKED, register.clone());
+        }
+        self.registers.insert(UNNAMED, register);
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Change {
+    keys: String,
+    /// Text typed in insert mode after the keys.
+    inserted: String,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Visual {
+    anchor: usize,
+    head: usize,
+    linewise: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Motion {
+    Left,
+    Right,
+    Down,
+    Up,
+    WordStart,
+    WordBack,
+    WordEnd,
+    LineStart,
+    LineEnd,
+    FirstLine,
+    LastLine,
+}
+
+impl Motion {
+    fn from_key(key: char) -> Option<Self> {
+        Some(match key {
+            'h' => Motion::Left,
+            'l' => Motion::Right,
+            'j' => Motion::Down,
+            'k' => Motion::Up,
+            'w' => Motion::WordStart,
+            'b' => Motion::WordBack,
+            'e' => Motion::WordEnd,
+            '0' => Motion::LineStart,
+            '$' => Motion::LineEnd,
+            'G' => Motion::LastLine,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Target {
+    Motion(Motion),
+    /// The operator typed twice, e.g. `dd`.
+    Lines,
+    Selection,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Insert {
+    Before,
+    After,
+    LineStart,
+    LineEnd,
+    Below,
+    Above,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Action {
+    Move(Motion),
+    Operate(Operator, Target),
+    Paste { before: bool },
+    Insert(Insert),
+    Undo,
+    Repeat,
+    Visual { linewise: bool },
+    Escape,
+}
+
+impl Action {
+    /// Changes are what `.` repeats.
+    fn is_change(&self) -> bool {
+        matches!(
+            self,
+            Action::Operate(Operator::Delete | Operator::Change, _)
+                | Action::Paste { .. }
+                | Action::Insert(_)
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Command {
+    register: Option<char>,
+    count: Option<usize>,
+    action: Action,
+}
+
+enum Parsed {
+    Pending,
+    Invalid,
+    Done(Command),
+}
+
+/// Reads `keys` as `["x][count]command`, where an operator takes `[count]motion` or
+/// itself, e.g. `"a2d3w` or `dd`.
+fn parse(keys: &str, visual: bool) -> Parsed {
+    let mut keys = keys.chars().peekable();
+    let mut register = None;
+    if keys.peek() == Some(&'"') {
+        keys.next();
+        match keys.next() {
+            Some(name) if name.is_ascii_alphanumeric() || name == UNNAMED => register = Some(name),
+            Some(_) => return Parsed::Invalid,
+            None => return Parsed::Pending,
+        }
+    }
+    let first = count(&mut keys);
+    let key = match keys.next() {
+        Some(key) => key,
+        None => return Parsed::Pending,
+    };
+    let done = |action, count| {
+        Parsed::Done(Command {
+            register,
+            count,
+            action,
+        })
+    };
+    let operator = match key {
+        'd' | 'x' if visual => {
+            return done(Action::Operate(Operator::Delete, Target::Selection), first)
+        }
+        'c' | 's' if visual => {
+            return done(Action::Operate(Operator::Change, Target::Selection), first)
+        }
+        'y' if visual => return done(Action::Operate(Operator::Yank, Target::Selection), first),
+        'd' => Operator::Delete,
+        'c' => Operator::Change,
+        'y' => Operator::Yank,
+        'x' => {
+            return done(
+                Action::Operate(Operator::Delete, Target::Motion(Motion::Right)),
+                first,
+            )
+        }
+        'D' => {
+            return done(
+                Action::Operate(Operator::Delete, Target::Motion(Motion::LineEnd)),
+                first,
+            )
+        }
+        'C' => {
+            return done(
+                Action::Operate(Operator::Change, Target::Motion(Motion::LineEnd)),
+                first,
+            )
+        }
+        'Y' => return done(Action::Operate(Operator::Yank, Target::Lines), first),
+        'p' => return done(Action::Paste { before: false }, first),
+        'P' => return done(Action::Paste { before: true }, first),
+        'u' => return done(Action::Undo, first),
+        '.' => return done(Action::Repeat, first),
+        'i' => return done(Action::Insert(Insert::Before), first),
+        'a' => return done(Action::Insert(Insert::After), first),
+        'I' => return done(Action::Insert(Insert::LineStart), first),
+        'A' => return done(Action::Insert(Insert::LineEnd), first),
+        'o' => return done(Action::Insert(Insert::Below), first),
+        'O' => return done(Action::Insert(Insert::Above), first),
+        'v' => return done(Action::Visual { linewise: false }, first),
+        'V' => return done(Action::Visual { linewise: true }, first),
+        ESCAPE => return done(Action::Escape, None),
+        'g' => {
+            return match keys.next() {
+                Some('g') => done(Action::Move(Motion::FirstLine), first),
+                Some(_) => Parsed::Invalid,
+                None => Parsed::Pending,
+            }
+        }
+        _ => {
+            return match Motion::from_key(key) {
+                Some(motion) => done(Action::Move(motion), first),
+                None => Parsed::Invalid,
+            }
+        }
+    };
+
+    let second = count(&mut keys);
+    let count = match (first, second) {
+        (None, None) => None,
+        (a, b) => Some(a.unwrap_or(1).saturating_mul(b.unwrap_or(1))),
+    };
+    let target = match keys.next() {
+        None => return Parsed::Pending,
+        Some(same) if same == key => Target::Lines,
+        Some('g') => match keys.next() {
+            Some('g') => Target::Motion(Motion::FirstLine),
+            Some(_) => return Parsed::Invalid,
+            None => return Parsed::Pending,
+        },
+        Some(key) => match Motion::from_key(key) {
+            Some(motion) => Target::Motion(motion),
+            None => return Parsed::Invalid,
+        },
+    };
+    done(Action::Operate(operator, target), count)
+}
+
+fn count(keys: &mut Peekable<Chars>) -> Option<usize> {
+    let mut count: Option<usize> = None;
+    while let Some(digit) = keys.peek().and_then(|c| c.to_digit(10)) {
+        // A leading 0 is the motion to the line start.
+        if digit == 0 && count.is_none() {
+            break;
+        }
+        count = Some(count.unwrap_or(0).saturating_mul(10) + digit as usize);
+        keys.next();
+    }
+    count
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Exclusive,
+    Inclusive,
+    Linewise,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Class {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+fn class(grapheme: Option<&str>) -> Class {
+    match grapheme.and_then(|g| g.chars().next()) {
+        None => Class::Blank,
+        Some(c) if c.is_whitespace() => Class::Blank,
+        Some(c) if c.is_alphanumeric() || c == '_' => Class::Word,
+        Some(_) => Class::Punctuation,
+    }
+}
+
+/// Steps through a buffer a grapheme at a time. The end of each line is a position of its
+/// own, standing for the line break.
+#[derive(Clone)]
+struct Walker<'a> {
+    buffer: &'a TextBuffer,
+    line: usize,
+    content: Cow<'a, str>,
+    column: usize,
+}
+
+impl<'a> Walker<'a> {
+    fn new(buffer: &'a TextBuffer, offset: usize) -> Self {
+        let line = buffer.line_at(offset);
+        let content = buffer.get_line_content(line);
+        let column = (offset - buffer.line_start(line)).min(content.len());
+        Self {
+            buffer,
+            line,
+            content,
+            column,
+        }
+    }
+
+    fn offset(&self) -> usize {
+        self.buffer.line_start(self.line) + self.column
+    }
+
+    fn grapheme(&self) -> Option<&str> {
+        self.content[self.column..].graphemes(true).next()
+    }
+
+    fn class(&self) -> Class {
+        class(self.grapheme())
+    }
+
+    fn forward(&mut self) -> bool {
+        if let Some(grapheme) = self.grapheme() {
+            self.column += grapheme.len();
+            return true;
+        }
+        if self.line + 1 >= self.buffer.line_count() {
+            return false;
+        }
+        self.line += 1;
+        self.content = self.buffer.get_line_content(self.line);
+        self.column = 0;
+        true
+    }
+
+    fn backward(&mut self) -> bool {
+        if self.column > 0 {
+            let previous = self.content[..self.column]
+                .grapheme_indices(true)
+                .next_back();
+            self.column = previous.map_or(0, |(i, _)| i);
+            return true;
+        }
+        if self.line == 0 {
+            return false;
+        }
+        self.line -= 1;
+        self.content = self.buffer.get_line_content(self.line);
+        self.column = self.content.len();
+        true
+    }
+}
+
+/// `w`: the start of the next word. An empty line counts as a word.
+fn word_start(buffer: &TextBuffer, offset: usize) -> usize {
+    let mut walker = Walker::new(buffer, offset);
+    let start = walker.class();
+    if start != Class::Blank {
+        while walker.class() == start {
+            if !walker.forward() {
+                return walker.offset();
+            }
+        }
+    }
+    while walker.class() == Class::Blank {
+        if walker.content.is_empty() && walker.offset() != offset {
+            break;
+        }
+        if !walker.forward() {
+            break;
+        }
+    }
+    walker.offset()
+}
+
+/// `b`: the start of this word, or of the one before when already there.
+fn word_back(buffer: &TextBuffer, offset: usize) -> usize {
+    let mut walker = Walker::new(buffer, offset);
+    if !walker.backward() {
+        return offset;
+    }
+    while walker.class() == Class::Blank {
+        if walker.content.is_empty() || !walker.backward() {
+            return walker.offset();
+        }
+    }
+    let class = walker.class();
+    loop {
+        let mut previous = walker.clone();
+        if !previous.backward() || previous.class() != class {
+            return walker.offset();
+        }
+        walker = previous;
+    }
+}
+
+/// `e`: the last character of this word, or of the next one when already there.
+fn word_end(buffer: &TextBuffer, offset: usize) -> usize {
+    let mut walker = Walker::new(buffer, offset);
+    if !walker.forward() {
+        return offset;
+    }
+    while walker.class() == Class::Blank {
+        if !walker.forward() {
+            return walker.offset();
+        }
+    }
+    let class = walker.class();
+    loop {
+        let mut next = walker.clone();
+        if !next.forward() || next.class() != class {
+            return walker.offset();
+        }
+        walker = next;
+    }
+}
+
+fn line_end(buffer: &TextBuffer, line: usize) -> usize {
+    buffer.line_start(line) + buffer.get_line_length(line)
+}
+
+fn first_non_blank(buffer: &TextBuffer, line: usize) -> usize {
+    let content = buffer.get_line_content(line);
+    buffer.line_start(line) + content.len() - content.trim_start().len()
+}
+
+/// The offset after the grapheme at `offset`, staying on its line.
+fn next_grapheme(buffer: &TextBuffer, offset: usize) -> usize {
+    let walker = Walker::new(buffer, offset);
+    offset + walker.grapheme().map_or(0, str::len)
+}
+
+/// In normal mode the cursor sits on a character, not after the last one of a line.
+fn on_character(buffer: &TextBuffer, offset: usize) -> usize {
+    let offset = offset.min(buffer.len());
+    let line = buffer.line_at(offset);
+    let start = buffer.line_start(line);
+    let content = buffer.get_line_content(line);
+    if offset - start < content.len() || content.is_empty() {
+        return offset;
+    }
+    let last = content.grapheme_indices(true).next_back();
+    start + last.map_or(0, |(i, _)| i)
+}
+
+/// The last line, not counting the empty one after a line break ending the document.
+fn last_line(buffer: &TextBuffer) -> usize {
+    let last = buffer.line_count() - 1;
+    match last > 0 && buffer.get_line_content(last).is_empty() {
+        true => last - 1,
+        false => last,
+    }
+}
+
+/// Where `motion` goes `count` times from `cursor`, and how an operator takes the text
+/// up to there.
+fn motion_target(
+    buffer: &TextBuffer,
+    cursor: Cursor,
+    motion: Motion,
+    count: Option<usize>,
+) -> (Cursor, Kind) {
+    let offset = cursor.offset;
+    let line = buffer.line_at(offset);
+    let last_line = last_line(buffer);
+    let times = count.unwrap_or(1);
+    let repeat = |step: &dyn Fn(usize) -> usize| (0..times).fold(offset, |at, _| step(at));
+    let at = Cursor::at;
+    match motion {
+        Motion::Left => {
+            let start = buffer.line_start(line);
+            let target = repeat(&|at| {
+                let mut walker = Walker::new(buffer, at);
+                if at > start && walker.backward() {
+                    walker.offset()
+                } else {
+                    at
+                }
+            });
+            (at(target), Kind::Exclusive)
+        }
+        Motion::Right => {
+            let end = line_end(buffer, line);
+            let target = repeat(&|at| next_grapheme(buffer, at).min(end));
+            (at(target), Kind::Exclusive)
+        }
+        Motion::Down | Motion::Up => {
+            let movement = if motion == Motion::Down {
+                Movement::Down
+            } else {
+                Movement::Up
+            };
+            let moved = (0..times).fold(cursor, |cursor, _| cursor.moved(buffer, movement, 0));
+            match buffer.line_at(moved.offset) > last_line.max(line) {
+                true => (cursor, Kind::Linewise),
+                false => (moved, Kind::Linewise),
+            }
+        }
+        Motion::WordStart => (at(repeat(&|at| word_start(buffer, at))), Kind::Exclusive),
+        Motion::WordBack => (at(repeat(&|at| word_back(buffer, at))), Kind::Exclusive),
+        Motion::WordEnd => (at(repeat(&|at| word_end(buffer, at))), Kind::Inclusive),
+        Motion::LineStart => (at(buffer.line_start(line)), Kind::Exclusive),
+        Motion::LineEnd => {
+            let line = (line + times - 1).min(last_line);
+            (at(line_end(buffer, line)), Kind::Exclusive)
+        }
+        Motion::FirstLine | Motion::LastLine => {
+            let default = if motion == Motion::FirstLine {
+                0
+            } else {
+                last_line
+            };
+            let line = count.map_or(default, |n| n.saturating_sub(1).min(last_line));
+            (at(first_non_blank(buffer, line)), Kind::Linewise)
+        }
+    }
+}
+
+/// The lines `first..=last` as an operator takes them, with the text going to the
+/// register, which always ends in a line break.
+fn line_span(buffer: &TextBuffer, first: usize, last: usize) -> (Range<usize>, String) {
+    let start = buffer.line_start(first);
+    if last + 1 < buffer.line_count() {
+        let range = start..buffer.line_start(last + 1);
+        let text = buffer.text_in(range.clone());
+        return (range, text);
+    }
+    let mut text = buffer.text_in(start..buffer.len());
+    text.push('\n');
+    // The last line takes the line break before it instead.
+    let range = match first {
+        0 => 0..buffer.len(),
+        _ => line_end(buffer, first - 1)..buffer.len(),
+    };
+    (range, text)
+}
+
+/// What an edit left behind for [`DocumentChanged`].
+struct Edited {
+    cursor: usize,
+}
+
+/// The document a key acts on.
+struct Context<'a> {
+    document: &'a mut Document,
+    cursor: &'a mut Cursor,
+    selection: &'a mut Selection,
+    edited: Option<Edited>,
+}
+
+impl Context<'_> {
+    fn buffer(&self) -> &TextBuffer {
+        self.document.buffer()
+    }
+
+    fn replace(&mut self, range: Range<usize>, text: &str) {
+        if !range.is_empty() {
+            self.document.delete(range.clone());
+        }
+        self.document.insert(range.start, text);
+    }
+
+    /// Moves the cursor, onto a character unless inserting.
+    fn place(&mut self, cursor: Cursor, mode: ModeType) {
+        let mut cursor = cursor;
+        if mode != ModeType::Insert {
+            let offset = on_character(self.buffer(), cursor.offset);
+            if offset != cursor.offset {
+                cursor.offset = offset;
+            }
+        }
+        *self.cursor = cursor;
+        *self.selection = Selection {
+            anchor: cursor.offset,
+        };
+        if let Some(edited) = &mut self.edited {
+            edited.cursor = cursor.offset;
+        }
+    }
+
+    fn edited(&mut self) {
+        self.edited.get_or_insert(Edited {
+            cursor: self.cursor.offset,
+        });
+    }
+}
+
+impl Vim {
+    fn set_mode(&mut self, mode: ModeType) {
+        if self.mode != mode {
+            debug!("⌨️ Vim {mode:?} mode");
+        }
+        self.mode = mode;
+    }
+
+    /// Takes `key`, running the command it completes.
+    fn feed(&mut self, key: char, context: &mut Context) {
+        if self.mode == ModeType::Insert {
+            if key == ESCAPE {
+                self.leave_insert(context);
+            }
+            return;
+        }
+        self.pending.push(key);
+        let command = match parse(&self.pending, self.visual.is_some()) {
+            Parsed::Pending => return,
+            Parsed::Invalid => {
+                debug!("⌨️ {} is not a vim command", self.pending);
+                self.pending.clear();
+                return;
+            }
+            Parsed::Done(command) => command,
+        };
+        let keys = std::mem::take(&mut self.pending);
+        self.run(command, &keys, context);
+    }
+
+    fn run(&mut self, command: Command, keys: &str, context: &mut Context) {
+        let Command {
+            register,
+            count,
+            action,
+        } = command;
+        if action.is_change() && self.visual.is_none() {
+            self.last_change = Some(Change {
+                keys: keys.to_string(),
+                inserted: String::new(),
+            });
+        }
+        let editable = !context.document.is_read_only();
+        match action {
+            Action::Move(motion) => {
+                let head = self.visual.map_or(*context.cursor, |v| Cursor::at(v.head));
+                let (target, _) = motion_target(context.buffer(), head, motion, count);
+                match &mut self.visual {
+                    Some(visual) => {
+                        visual.head = on_character(context.buffer(), target.offset);
+                        self.show_visual(context);
+                    }
+                    None => context.place(target, self.mode),
+                }
+            }
+            Action::Operate(operator, target) if editable || operator == Operator::Yank => {
+                self.operate(operator, target, register, count, context)
+            }
+            Action::Operate(..) => {}
+            // Visual mode only moves and operates.
+            Action::Paste { .. } | Action::Insert(_) | Action::Undo | Action::Repeat
+                if self.visual.is_some() => {}
+            Action::Paste { before } if editable => self.paste(before, register, count, context),
+            Action::Paste { .. } => {}
+            Action::Insert(insert) if editable => self.insert(insert, context),
+            Action::Insert(_) => {}
+            Action::Undo => {
+                for _ in 0..count.unwrap_or(1) {
+                    match context.document.undo() {
+                        Some(offset) => {
+                            context.edited();
+                            context.place(Cursor::at(offset), self.mode);
+                        }
+                        None => break,
+                    }
+                }
+            }
+            Action::Repeat => self.repeat(count, context),
+            Action::Visual { linewise } => match &mut self.visual {
+                Some(visual) if visual.linewise != linewise => {
+                    visual.linewise = linewise;
+                    self.show_visual(context);
+                }
+                Some(visual) => {
+                    let head = visual.head;
+                    self.leave_visual(head, context);
+                }
+                None => {
+                    let offset = context.cursor.offset;
+                    self.visual = Some(Visual {
+                        anchor: offset,
+                        head: offset,
+                        linewise,
+                    });
+                    self.set_mode(ModeType::Visual);
+                    self.show_visual(context);
+                }
+            },
+            Action::Escape => {
+                if let Some(visual) = self.visual {
+                    self.leave_visual(visual.head, context);
+                }
+            }
+        }
+    }
+
+    /// The range of the visual selection, and whether it is whole lines.
+    fn visual_range(&self, buffer: &TextBuffer) -> Option<(Range<usize>, bool)> {
+        let visual = self.visual?;
+        let (start, end) = (
+            visual.anchor.min(visual.head),
+            visual.anchor.max(visual.head),
+        );
+        if visual.linewise {
+            let (first, last) = (buffer.line_at(start), buffer.line_at(end));
+            let end = match last + 1 < buffer.line_count() {
+                true => buffer.line_start(last + 1),
+                false => buffer.len(),
+            };
+            return Some((buffer.line_start(first)..end, true));
+        }
+        Some((start..next_grapheme(buffer, end), false))
+    }
+
+    /// Selects the visual range, with the cursor on the side of the head.
+    fn show_visual(&self, context: &mut Context) {
+        let (range, _) = match self.visual_range(context.buffer()) {
+            Some(range) => range,
+            None => return,
+        };
+        let visual = self.visual.unwrap();
+        let (anchor, offset) = if visual.head >= visual.anchor {
+            (range.start, range.end)
+        } else {
+            (range.end, range.start)
+        };
+        *context.cursor = Cursor::at(offset);
+        *context.selection = Selection { anchor };
+    }
+
+    fn leave_visual(&mut self, head: usize, context: &mut Context) {
+        self.visual = None;
+        self.set_mode(ModeType::Normal);
+        context.place(Cursor::at(head), self.mode);
+    }
+
+    fn operate(
+        &mut self,
+        operator: Operator,
+        target: Target,
+        register: Option<char>,
+        count: Option<usize>,
+        context: &mut Context,
+    ) {
+        let buffer = context.buffer();
+        let cursor = *context.cursor;
+        let line = buffer.line_at(cursor.offset);
+        // The range taken, or the lines for a linewise operator.
+        let (range, lines) = match target {
+            Target::Selection => match self.visual_range(buffer) {
+                Some((range, true)) => {
+                    let first = buffer.line_at(range.start);
+                    let last = buffer.line_at(range.end.saturating_sub(1).max(range.start));
+                    (range, Some((first, last)))
+                }
+                Some((range, false)) => (range, None),
+                None => return,
+            },
+            Target::Lines => {
+                let last = (line + count.unwrap_or(1) - 1).min(last_line(buffer).max(line));
+                (0..0, Some((line, last)))
+            }
+            Target::Motion(motion) => {
+                // `cw` changes to the end of the word, like `ce`.
+                let on_word = class(Walker::new(buffer, cursor.offset).grapheme()) != Class::Blank;
+                let motion = match motion {
+                    Motion::WordStart if operator == Operator::Change && on_word => Motion::WordEnd,
+                    motion => motion,
+                };
+                let (target, kind) = motion_target(buffer, cursor, motion, count);
+                let mut end = target.offset;
+                // `dw` on the last word of a line stops at its end.
+                if motion == Motion::WordStart && buffer.line_at(end) > line {
+                    let previous = line_end(buffer, buffer.line_at(end) - 1);
+                    if previous >= cursor.offset
+                        && first_non_blank(buffer, buffer.line_at(end)) == end
+                    {
+                        end = previous;
+                    }
+                }
+                match kind {
+                    Kind::Linewise => {
+                        let other = buffer.line_at(end);
+                        (0..0, Some((line.min(other), line.max(other))))
+                    }
+                    Kind::Exclusive => (cursor.offset.min(end)..cursor.offset.max(end), None),
+                    Kind::Inclusive => {
+                        let (start, last) = (cursor.offset.min(end), cursor.offset.max(end));
+                        (start..next_grapheme(buffer, last), None)
+                    }
+                }
+            }
+        };
+        let (range, text) = match lines {
+            Some((first, last)) => line_span(buffer, first, last),
+            None => (range.clone(), buffer.text_in(range)),
+        };
+        let linewise = lines.is_some();
+        let first_line = lines.map_or_else(|| buffer.line_at(range.start), |(first, _)| first);
+
+        self.store(register, text, linewise, operator == Operator::Yank);
+        let visual = self.visual.take();
+        if visual.is_some() {
+            self.set_mode(ModeType::Normal);
+        }
+        match operator {
+            Operator::Yank => {
+                let start = match (linewise, visual) {
+                    (true, None) if first_line == line => cursor.offset,
+                    (true, _) => buffer.line_start(first_line),
+                    (false, _) => range.start,
+                };
+                context.place(Cursor::at(start), self.mode);
+            }
+            Operator::Delete => {
+                context.edited();
+                context.document.history_mut().begin();
+                context.replace(range.clone(), "");
+                context.document.history_mut().commit();
+                let buffer = context.buffer();
+                let offset = match linewise {
+                    true => first_non_blank(buffer, first_line.min(last_line(buffer))),
+                    false => range.start,
+                };
+                context.place(Cursor::at(offset), self.mode);
+            }
+            Operator::Change => {
+                context.edited();
+                context.document.history_mut().begin();
+                let start = match lines {
+                    // The lines become one, empty but for the indent of the first.
+                    Some((first, last)) => {
+                        let buffer = context.buffer();
+                        let start = first_non_blank(buffer, first);
+                        let end = line_end(buffer, last);
+                        context.replace(start..end, "");
+                        start
+                    }
+                    None => {
+                        context.replace(range.clone(), "");
+                        range.start
+                    }
+                };
+                context.document.history_mut().commit();
+                self.enter_insert(visual.is_none());
+                context.place(Cursor::at(start), ModeType::Insert);
+            }
+        }
+    }
+
+    fn paste(
+        &mut self,
+        before: bool,
+        register: Option<char>,
+        count: Option<usize>,
+        context: &mut Context,
+    ) {
+        let register = match self.register(register.unwrap_or(UNNAMED)) {
+            Some(register) => register.clone(),
+            None => return,
+        };
+        let text = register.text.repeat(count.unwrap_or(1));
+        let buffer = context.buffer();
+        let offset = context.cursor.offset;
+        let line = buffer.line_at(offset);
+        // Where the text goes, and the line the cursor goes to the start of for lines.
+        let (at, text, start_of) = match (register.linewise, before) {
+            (true, true) => (buffer.line_start(line), text, Some(line)),
+            (true, false) if line + 1 < buffer.line_count() => {
+                (buffer.line_start(line + 1), text, Some(line + 1))
+            }
+            // Below the last line, which has no line break to paste after.
+            (true, false) => {
+                let text = format!("\n{}", text.strip_suffix('\n').unwrap_or(&text));
+                (buffer.len(), text, Some(line + 1))
+            }
+            (false, true) => (offset, text, None),
+            (false, false) => (next_grapheme(buffer, offset), text, None),
+        };
+        context.edited();
+        context.document.history_mut().begin();
+        context.replace(at..at, &text);
+        let cursor = match start_of {
+            Some(line) => first_non_blank(context.buffer(), line),
+            // On the last character pasted.
+            None => {
+                let mut walker = Walker::new(context.buffer(), at + text.len());
+                match !text.is_empty() && walker.backward() {
+                    true => walker.offset(),
+                    false => at,
+                }
+            }
+        };
+        context.document.history_mut().commit();
+        context.place(Cursor::at(cursor), self.mode);
+    }
+
+    fn insert(&mut self, insert: Insert, context: &mut Context) {
+        let buffer = context.buffer();
+        let offset = context.cursor.offset;
+        let line = buffer.line_at(offset);
+        let indent = {
+            let start = buffer.line_start(line);
+            buffer.text_in(start..first_non_blank(buffer, line))
+        };
+        // A new line keeps the indent of the cursor's.
+        let (at, line) = match insert {
+            Insert::Before => (offset, None),
+            Insert::After => (next_grapheme(buffer, offset), None),
+            Insert::LineStart => (first_non_blank(buffer, line), None),
+            Insert::LineEnd => (line_end(buffer, line), None),
+            Insert::Below => {
+                let end = line_end(buffer, line);
+                (end + 1 + indent.len(), Some((end, format!("\n{indent}"))))
+            }
+            Insert::Above => {
+                let start = buffer.line_start(line);
+                (start + indent.len(), Some((start, format!("{indent}\n"))))
+            }
+        };
+        if let Some((offset, text)) = line {
+            context.edited();
+            context.document.history_mut().begin();
+            context.replace(offset..offset, &text);
+            context.document.history_mut().commit();
+        }
+        self.enter_insert(true);
+        context.place(Cursor::at(at), ModeType::Insert);
+    }
+
+    /// Goes into insert mode, keeping what is typed for `.` if `recorded`.
+    fn enter_insert(&mut self, recorded: bool) {
+        self.inserting = match recorded {
+            true => self.last_change.take(),
+            false => None,
+        };
+        self.set_mode(ModeType::Insert);
+    }
+
+    /// Back to normal mode, on the character before the cursor as vim does.
+    fn leave_insert(&mut self, context: &mut Context) {
+        if let Some(change) = self.inserting.take() {
+            self.last_change = Some(change);
+        }
+        self.set_mode(ModeType::Normal);
+        let offset = context.cursor.offset;
+        let start = context
+            .buffer()
+            .line_start(context.buffer().line_at(offset));
+        let mut walker = Walker::new(context.buffer(), offset);
+        let offset = match offset > start && walker.backward() {
+            true => walker.offset(),
+            false => offset,
+        };
+        context.place(Cursor::at(offset), self.mode);
+    }
+
+    /// Runs the last change again, with `count` instead of its own if given, typing the
+    /// same text if it went into insert mode.
+    fn repeat(&mut self, count: Option<usize>, context: &mut Context) {
+        let change = match self.last_change.clone() {
+            Some(change) => change,
+            None => return,
+        };
+        let mut command = match parse(&change.keys, false) {
+            Parsed::Done(command) => command,
+            _ => return,
+        };
+        if count.is_some() {
+            command.count = count;
+        }
+        debug!("⌨️ Repeating {}", change.keys);
+        let version = context.document.version();
+        self.run(command, &change.keys, context);
+        if self.mode == ModeType::Insert {
+            let offset = context.cursor.offset;
+            context.edited();
+            // Undone together with what the keys changed, if anything.
+            match context.document.version() == version {
+                true => context.document.history_mut().begin(),
+                false => context.document.history_mut().amend(),
+            }
+            context.replace(offset..offset, &change.inserted);
+            context.document.history_mut().commit();
+            context.place(Cursor::at(offset + change.inserted.len()), ModeType::Insert);
+            self.leave_insert(context);
+        }
+        self.last_change = Some(change);
+    }
+}
+
+/// Chords vim reads as its keys, with a US layout. Modifiers other than shift leave the
+/// key to the keymap.
+fn vim_key(chord: Chord) -> Option<char> {
+    const SHIFTED: &[(char, char)] = &[
+        ('-', '_'),
+        ('=', '+'),
+        (',', '<'),
+        ('.', '>'),
+        ('/', '?'),
+        ('\\', '|'),
+        (';', ':'),
+        ('\'', '"'),
+        ('[', '{'),
+        (']', '}'),
+        ('`', '~'),
+    ];
+    if chord.ctrl || chord.alt || chord.logo {
+        return None;
+    }
+    match chord.key {
+        KeyCode::Escape => return Some(ESCAPE),
+        KeyCode::Space => return Some(' '),
+        _ => {}
+    }
+    let name = key_name(chord.key)?;
+    let mut chars = name.chars();
+    let key = match (chars.next(), chars.next()) {
+        (Some(key), None) => key,
+        _ => return None,
+    };
+    if !chord.shift {
+        return Some(key);
+    }
+    match key {
+        'a'..='z' => Some(key.to_ascii_uppercase()),
+        '0'..='9' => Some(b")!@#$%^&*("[key as usize - '0' as usize] as char),
+        _ => SHIFTED
+            .iter()
+            .find(|(plain, _)| *plain == key)
+            .map(|(_, shifted)| *shifted),
+    }
+}
+
+/// Keys pressed besides the vim commands: scrolling, redo and stepping numbers, and the
+/// editing keys of the default layer that would change text outside insert mode.
+fn bind_vim_keys(mut keymap: ResMut<Keymap>) {
+    let mut layer = Layer::new(LAYER);
+    for mode in [ModeType::Normal, ModeType::Visual] {
+        for (keys, command) in [
+            ("ctrl+f", Some("cursor.pageDown")),
+            ("ctrl+b", Some("cursor.pageUp")),
+            ("enter", Some("cursor.down")),
+            ("backspace", Some("cursor.left")),
+            ("tab", None),
+            ("delete", None),
+        ] {
+            layer.bindings.push(Binding {
+                keys: parse_keys(keys).expect("vim keys parse"),
+                command: command.map(str::to_string),
+                mode: Some(mode),
+            });
+        }
+    }
+    for (keys, command) in [
+        ("ctrl+r", "edit.redo"),
+        ("ctrl+a", "number.increment"),
+        ("ctrl+x", "number.decrement"),
+    ] {
+        layer.bindings.push(Binding {
+            keys: parse_keys(keys).expect("vim keys parse"),
+            command: Some(command.to_string()),
+            mode: Some(ModeType::Normal),
+        });
+    }
+    keymap.set_base_layer(layer);
+}
+
+fn read_vim_keys(
+    mut input: EventReader<KeyboardInput>,
+    pressed: Res<Input<KeyCode>>,
+    mut keys: EventWriter<VimKey>,
+) {
+    for e in input.iter() {
+        let key = match e.key_code {
+            Some(key) if e.state == ElementState::Pressed => key,
+            _ => continue,
+        };
+        let held = |a, b| pressed.pressed(a) || pressed.pressed(b);
+        let chord = Chord {
+            key,
+            ctrl: held(KeyCode::LControl, KeyCode::RControl),
+            shift: held(KeyCode::LShift, KeyCode::RShift),
+            alt: held(KeyCode::LAlt, KeyCode::RAlt),
+            logo: held(KeyCode::LWin, KeyCode::RWin),
+        };
+        if let Some(key) = vim_key(chord) {
+            keys.send(VimKey(key));
+        }
+    }
+}
+
+fn run_vim_keys(
+    mut keys: EventReader<VimKey>,
+    (mut typed, mut deleted): (EventReader<TypeText>, EventReader<DeleteText>),
+    mut vim: ResMut<Vim>,
+    mut modes: Query<&mut Mode>,
+    workspace: Res<Workspace>,
+    mut documents: Query<(&mut Document, &mut Cursor, &mut Selection)>,
+    mut changed: EventWriter<DocumentChanged>,
+) {
+    let active = workspace.active();
+    // Text typed in insert mode is kept for `.` to type again.
+    for e in typed.iter() {
+        if let Some(change) = vim.inserting.as_mut().filter(|_| Some(e.entity) == active) {
+            change.inserted.push_str(&e.text);
+        }
+    }
+    for e in deleted.iter() {
+        let change = vim.inserting.as_mut().filter(|_| Some(e.entity) == active);
+        if let (Some(change), Movement::Left) = (change, e.movement) {
+            let last = change.inserted.grapheme_indices(true).next_back();
+            change.inserted.truncate(last.map_or(0, |(i, _)| i));
+        }
+    }
+
+    let mut mode = match modes.get_single_mut() {
+        Ok(mode) => mode,
+        Err(_) => return,
+    };
+    // Command mode belongs to the command line.
+    if mode.0 == ModeType::Command {
+        keys.iter().for_each(drop);
+        return;
+    }
+    let entity = match active {
+        Some(entity) => entity,
+        None => return,
+    };
+    let (mut document, mut cursor, mut selection) = match documents.get_mut(entity) {
+        Ok(document) => document,
+        Err(_) => return,
+    };
+    let mut context = Context {
+        document: &mut document,
+        cursor: &mut cursor,
+        selection: &mut selection,
+        edited: None,
+    };
+    for VimKey(key) in keys.iter() {
+        vim.feed(*key, &mut context);
+    }
+    if let Some(edited) = context.edited.take() {
+        changed.send(DocumentChanged {
+            entity,
+            version: document.version(),
+            changes: document.take_changes(),
+            cursor: Some(edited.cursor),
+        });
+    }
+    if mode.0 != vim.mode {
+        mode.0 = vim.mode;
+    }
+}
@@ -0,0 +1,342 @@
+use crate::{
+    announce::Announcement,
+    conflict::{Resolution, ResolveConflict},
+    document::{difference, DiskStamp, Document, DocumentChanged, DocumentEditSet, DocumentError},
+    idle::throttled,
+    pipeline::{AppPipelineExt, EditorStage},
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Local, Query, Res, ResMut},
+    },
+    log::{debug, warn},
+    tasks::AsyncComputeTaskPool,
+};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+    time::Duration,
+};
+
+/// How often changes on disk are picked up while the user is idle, every frame otherwise.
+const IDLE_WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+pub struct FileWatcherPlugin;
+
+impl Plugin for FileWatcherPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FileWatcherSettings>()
+            .init_resource::<FileWatcher>()
+            .init_resource::<PendingRemaps>()
+            .add_event::<FileChangedOnDisk>()
+            .add_system(
+                watch_documents.with_run_criteria(throttled(Duration::ZERO, IDLE_WATCH_INTERVAL)),
+            )
+            .add_editor_system(EditorStage::Edits, reload_changed.label(DocumentEditSet))
+            .add_editor_system(EditorStage::Edits, finish_remaps.label(DocumentEditSet));
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FileWatcherSettings {
+    /// Documents without unsaved changes take what was written to their file. Others
+    /// always get a [`crate::conflict::Conflict`] to decide from. Read-only documents are
+    /// mapped again either way, they have nothing to lose.
+    pub auto_reload: bool,
+}
+
+impl Default for FileWatcherSettings {
+    fn default() -> Self {
+        Self { auto_reload: true }
+    }
+}
+
+/// Someone else wrote to or removed the file of a document. Sent once per change, not
+/// for the document's own saves.
+#[derive(Clone, Debug)]
+pub struct FileChangedOnDisk {
+    pub entity: Entity,
+    pub path: PathBuf,
+    pub removed: bool,
+    /// The document had unsaved changes.
+    pub dirty: bool,
+}
+
+/// Watches the directories of open documents rather than the files themselves, so files
+/// replaced by a rename, the way most programs save, stay watched.
+pub struct FileWatcher {
+    watcher: Option<Mutex<RecommendedWatcher>>,
+    events: Mutex<Receiver<notify::Result<notify::Event>>>,
+    directories: HashSet<PathBuf>,
+    /// Document paths as the watcher reports them.
+    resolved: HashMap<PathBuf, PathBuf>,
+}
+
+impl Default for FileWatcher {
+    fn default() -> Self {
+        let (sender, events) = mpsc::channel();
+        let watcher = notify::recommended_watcher(move |event| {
+            let _ = sender.send(event);
+        });
+        let watcher = match watcher {
+            Ok(watcher) => Some(Mutex::new(watcher)),
+            Err(error) => {
+                warn!("👀 Not watching files: {error}");
+                None
+            }
+        };
+        Self {
+            watcher,
+            events: Mutex::new(events),
+            directories: HashSet::new(),
+            resolved: HashMap::new(),
+        }
+    }
+}
+
+impl FileWatcher {
+    /// Watches the directories in `wanted` and stops watching the rest.
+    fn watch(&mut self, wanted: HashSet<PathBuf>) {
+        let mut watcher = match &self.watcher {
+            Some(watcher) => watcher.lock().unwrap(),
+            None => return,
+        };
+        for directory in self.directories.difference(&wanted) {
+            let _ = watcher.unwatch(directory);
+        }
+        for directory in wanted.difference(&self.directories) {
+            if let Err(error) = watcher.watch(directory, RecursiveMode::NonRecursive) {
+                debug!("👀 Not watching {}: {error}", directory.display());
+            }
+        }
+        self.directories = wanted;
+    }
+
+    /// Paths created, written to or removed since last time.
+    fn touched(&self) -> HashSet<PathBuf> {
+        let events = self.events.lock().unwrap();
+        events
+            .try_iter()
+            .filter_map(Result::ok)
+            .filter(|event| !matches!(event.kind, EventKind::Access(_)))
+            .flat_map(|event| event.paths)
+            .collect()
+    }
+}
+
+/// `path` with its directory canonicalized, the file itself may not exist.
+fn resolve(path: &Path) -> PathBuf {
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match (fs::canonicalize(directory), path.file_name()) {
+        (Ok(directory), Some(name)) => directory.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn watch_documents(
+    mut watcher: ResMut<FileWatcher>,
+    documents: Query<(Entity, &Document)>,
+    mut notified: Local<HashMap<Entity, Option<DiskStamp>>>,
+    mut changed: EventWriter<FileChangedOnDisk>,
+) {
+    let watcher = &mut *watcher;
+    let paths: HashMap<Entity, &Path> = documents
+        .iter()
+        .filter_map(|(entity, document)| Some((entity, document.path()?)))
+        .collect();
+    let open: HashSet<&Path> = paths.values().copied().collect();
+    watcher
+        .resolved
+        .retain(|path, _| open.contains(path.as_path()));
+    for path in open {
+        if !watcher.resolved.contains_key(path) {
+            watcher.resolved.insert(path.to_path_buf(), resolve(path));
+        }
+    }
+    let directories = watcher
+        .resolved
+        .values()
+        .filter_map(|path| Some(path.parent()?.to_path_buf()))
+        .collect();
+    watcher.watch(directories);
+
+    notified.retain(|entity, _| paths.contains_key(entity));
+    let touched = watcher.touched();
+    if touched.is_empty() {
+        return;
+    }
+    for (entity, document) in documents.iter() {
+        let path = match document.path() {
+            Some(path) if touched.contains(&watcher.resolved[path]) => path,
+            _ => continue,
+        };
+        let stamp = DiskStamp::read(path);
+        // Saving updates the document's stamp, so its own writes are skipped.
+        let last = notified
+            .get(&entity)
+            .copied()
+            .unwrap_or(document.disk_stamp());
+        if stamp == last || stamp == document.disk_stamp() {
+            continue;
+        }
+        notified.insert(entity, stamp);
+        debug!("👀 {} changed on disk", path.display());
+        changed.send(FileChangedOnDisk {
+            entity,
+            path: path.to_path_buf(),
+            removed: stamp.is_none(),
+            dirty: document.is_dirty(),
+        });
+    }
+}
+
+type Remapped = (
+    Entity,
+    PathBuf,
+    Result<(Document, Range<usize>, String), DocumentError>,
+);
+
+/// Read-only documents being mapped again on the async compute pool, as that reads the
+/// whole file, and compared to what they had.
+struct PendingRemaps {
+    sender: Mutex<Sender<Remapped>>,
+    receiver: Mutex<Receiver<Remapped>>,
+    /// Changes to these are picked up when the remap under way is done.
+    entities: Vec<Entity>,
+}
+
+impl Default for PendingRemaps {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender: Mutex::new(sender),
+            receiver: Mutex::new(receiver),
+            entities: vec![],
+        }
+    }
+}
+
+impl PendingRemaps {
+    fn spawn(&mut self, pool: &AsyncComputeTaskPool, entity: Entity, document: &Document) {
+        let path = match document.path() {
+            Some(path) => path.to_path_buf(),
+            None => return,
+        };
+        let mine = document.buffer().clone();
+        let sender = self.sender.lock().unwrap().clone();
+        pool.spawn(async move {
+            let result = Document::map_path(&path).map(|theirs| {
+                let (range, text) = difference(&mine, theirs.buffer());
+                (theirs, range, text)
+            });
+            let _ = sender.send((entity, path, result));
+        })
+        .detach();
+        self.entities.push(entity);
+    }
+}
+
+fn reload_changed(
+    mut events: EventReader<FileChangedOnDisk>,
+    settings: Res<FileWatcherSettings>,
+    mut documents: Query<&mut Document>,
+    (pool, mut pending): (Res<AsyncComputeTaskPool>, ResMut<PendingRemaps>),
+    (mut resolve, mut changed, mut announce): (
+        EventWriter<ResolveConflict>,
+        EventWriter<DocumentChanged>,
+        EventWriter<Announcement>,
+    ),
+) {
+    for e in events.iter() {
+        let mut document = match documents.get_mut(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        let name = e.path.file_name().unwrap_or_default().to_string_lossy();
+        if e.removed {
+            announce.send(Announcement::polite(format!("{name} was deleted on disk")));
+            continue;
+        }
+        if document.is_read_only() {
+            if !pending.entities.contains(&e.entity) {
+                debug!("👀 Mapping {} again", e.path.display());
+                pending.spawn(&pool, e.entity, &document);
+            }
+            continue;
+        }
+        if document.is_dirty() || !settings.auto_reload {
+            announce.send(Announcement::polite(format!("{name} changed on disk")));
+            resolve.send(ResolveConflict {
+                entity: e.entity,
+                resolution: Resolution::Compare,
+            });
+            continue;
+        }
+        match document.read_from_disk() {
+            Ok(theirs) => {
+                debug!("👀 Reloading {}", e.path.display());
+                document.take_disk(theirs);
+                let changes = document.take_changes();
+                if !changes.is_empty() {
+                    changed.send(DocumentChanged {
+                        entity: e.entity,
+                        version: document.version(),
+                        changes,
+                        cursor: None,
+                    });
+                }
+            }
+            Err(error) => warn!("👀 Failed to reload {}: {error}", e.path.display()),
+        }
+    }
+}
+
+fn finish_remaps(
+    pool: Res<AsyncComputeTaskPool>,
+    mut pending: ResMut<PendingRemaps>,
+    mut documents: Query<&mut Document>,
+    mut changed: EventWriter<DocumentChanged>,
+) {
+    let remapped: Vec<_> = pending.receiver.lock().unwrap().try_iter().collect();
+    for (entity, path, result) in remapped {
+        pending.entities.retain(|e| *e != entity);
+        let mut document = match documents.get_mut(entity) {
+            Ok(document) if document.path() == Some(path.as_path()) => document,
+            _ => continue,
+        };
+        match result {
+            Ok((theirs, range, text)) => {
+                document.take_mapped(theirs, range, text);
+                let changes = document.take_changes();
+                if !changes.is_empty() {
+                    changed.send(DocumentChanged {
+                        entity,
+                        version: document.version(),
+                        changes,
+                        cursor: None,
+                    });
+                }
+                // Written to again while it was being mapped.
+                let stamp = DiskStamp::read(&path);
+                if stamp.is_some() && stamp != document.disk_stamp() {
+                    pending.spawn(&pool, entity, &document);
+                }
+            }
+            Err(error) => warn!("👀 Failed to reload {}: {error}", path.display()),
+        }
+    }
+}
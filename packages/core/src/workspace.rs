@@ -0,0 +1,534 @@
+use crate::{
+    config::SettingsSection,
+    document::{
+        Document, DocumentOpened, DocumentSaveFailed, DocumentSaved, SaveConflict, SaveDocument,
+        VirtualDocument,
+    },
+    exclude::{ExcludeSettings, Excludes, SETTINGS_FILE},
+    limbo::Closed,
+    memory::{MemoryUsage, Visible},
+    pairs::PairSettings,
+    shutdown::UnsavedChoice,
+    toolchain::Toolchains,
+};
+use bevy::{
+    app::{App, Plugin},
+    core::Time,
+    ecs::{
+        entity::Entity,
+        event::{EventReader, EventWriter},
+        query::{Added, With},
+        system::{Commands, Local, Query, Res, ResMut},
+    },
+    log::{debug, warn},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fmt, fs, io, mem,
+    path::{Path, PathBuf},
+};
+
+pub const WORKSPACE_EXTENSION: &str = "dip-workspace";
+
+pub struct WorkspacePlugin;
+
+impl Plugin for WorkspacePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Workspace>()
+            .add_event::<OpenWorkspace>()
+            .add_event::<SaveWorkspace>()
+            .add_event::<ReloadWorkspaceSettings>()
+            .add_event::<WorkspaceChanged>()
+            .add_event::<CloseDocument>()
+            .add_event::<FocusDocument>()
+            .add_event::<DocumentClosed>()
+            .add_event::<UnsavedClosePrompt>()
+            .add_event::<ResolveUnsavedClose>()
+            .add_startup_system(open_current_dir)
+            .add_system(open_workspace)
+            .add_system(track_documents)
+            .add_system(close_documents)
+            .add_system(resolve_unsaved_close)
+            .add_system(focus_documents)
+            .add_system(mark_visible_documents)
+            .add_system(save_workspace)
+            .add_system(reload_workspace_settings);
+    }
+}
+
+/// The `.dip-workspace` file, listing root folders relative to the file itself.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct WorkspaceFile {
+    #[serde(default)]
+    pub folders: Vec<FolderEntry>,
+    /// Applied to every root, below each root's own `.dip/settings.json`.
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub settings: serde_json::Map<String, serde_json::Value>,
+}
+
+impl WorkspaceFile {
+    pub fn read(file: &Path) -> io::Result<Self> {
+        let source = fs::read_to_string(file)?;
+        serde_json::from_str(&source).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FolderEntry {
+    pub path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// Identifies a root for as long as the workspace is open, even when others are removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RootId(u32);
+
+/// A root folder with its own settings and toolchains, so language servers are spawned
+/// per root.
+#[derive(Clone, Debug)]
+pub struct WorkspaceRoot {
+    pub id: RootId,
+    pub name: String,
+    pub path: PathBuf,
+    /// Set when the workspace file named the root explicitly.
+    custom_name: bool,
+    pub excludes: Excludes,
+    pub pairs: PairSettings,
+    pub toolchains: Toolchains,
+}
+
+impl WorkspaceRoot {
+    pub fn relative<'a>(&self, path: &'a Path) -> Option<&'a Path> {
+        path.strip_prefix(&self.path).ok()
+    }
+}
+
+/// A path shown with the root it belongs to, e.g. `frontend/src/main.ts`.
+#[derive(Clone, Copy, Debug)]
+pub struct RootPath<'a> {
+    pub root: &'a WorkspaceRoot,
+    pub relative: &'a Path,
+}
+
+impl fmt::Display for RootPath<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.root.name, self.relative.display())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Workspace {
+    /// None for a single folder opened without a workspace file.
+    file: Option<PathBuf>,
+    roots: Vec<WorkspaceRoot>,
+    settings: serde_json::Map<String, serde_json::Value>,
+    next_id: u32,
+    /// Open documents in the order they were opened, kept when another workspace is opened.
+    documents: Vec<Entity>,
+    active: Option<Entity>,
+}
+
+impl Workspace {
+    pub fn folder(path: impl Into<PathBuf>) -> Self {
+        let mut workspace = Self::default();
+        workspace.add_root(path.into(), None);
+        workspace
+    }
+
+    pub fn open(file: &Path) -> io::Result<Self> {
+        let parsed = WorkspaceFile::read(file)?;
+        let base = file.parent().unwrap_or_else(|| Path::new(""));
+        let mut workspace = Self {
+            file: Some(file.to_path_buf()),
+            settings: parsed.settings,
+            ..Self::default()
+        };
+        for folder in parsed.folders {
+            workspace.add_root(base.join(folder.path), folder.name);
+        }
+        Ok(workspace)
+    }
+
+    /// Writes the workspace file, with roots relative to it where possible.
+    pub fn save(&mut self, file: &Path) -> io::Result<()> {
+        let base = file.parent().unwrap_or_else(|| Path::new(""));
+        let folders = self
+            .roots
+            .iter()
+            .map(|root| FolderEntry {
+                path: root.path.strip_prefix(base).unwrap_or(&root.path).into(),
+                name: root.custom_name.then(|| root.name.clone()),
+            })
+            .collect();
+        let contents = WorkspaceFile {
+            folders,
+            settings: self.settings.clone(),
+        };
+        let json = serde_json::to_string_pretty(&contents)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(file, json)?;
+        self.file = Some(file.to_path_buf());
+        Ok(())
+    }
+
+    pub fn file(&self) -> Option<&Path> {
+        self.file.as_deref()
+    }
+
+    /// Settings of the workspace file, empty without one.
+    pub fn settings(&self) -> &serde_json::Map<String, serde_json::Value> {
+        &self.settings
+    }
+
+    pub fn roots(&self) -> &[WorkspaceRoot] {
+        &self.roots
+    }
+
+    pub fn root(&self, id: RootId) -> Option<&WorkspaceRoot> {
+        self.roots.iter().find(|root| root.id == id)
+    }
+
+    /// The innermost root containing `path`, as roots may be nested in a monorepo.
+    pub fn root_for(&self, path: &Path) -> Option<&WorkspaceRoot> {
+        self.roots
+            .iter()
+            .filter(|root| path.starts_with(&root.path))
+            .max_by_key(|root| root.path.components().count())
+    }
+
+    pub fn qualify<'a>(&'a self, path: &'a Path) -> Option<RootPath<'a>> {
+        let root = self.root_for(path)?;
+        Some(RootPath {
+            root,
+            relative: root.relative(path)?,
+        })
+    }
+
+    pub fn documents(&self) -> &[Entity] {
+        &self.documents
+    }
+
+    pub fn active(&self) -> Option<Entity> {
+        self.active
+    }
+
+    pub fn is_open(&self, entity: Entity) -> bool {
+        self.documents.contains(&entity)
+    }
+
+    pub fn add_root(&mut self, path: PathBuf, name: Option<String>) -> RootId {
+        let id = RootId(self.next_id);
+        self.next_id += 1;
+
+        let custom_name = name.is_some();
+        let name = name.unwrap_or_else(|| match path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => path.display().to_string(),
+        });
+        let mut root = WorkspaceRoot {
+            id,
+            name,
+            custom_name,
+            excludes: Excludes::default(),
+            pairs: PairSettings::default(),
+            toolchains: Toolchains::detect(&path),
+            path,
+        };
+        root.excludes = self.load_excludes(&root.path);
+        root.pairs = self.load_pairs(&root.path);
+        self.roots.push(root);
+        id
+    }
+
+    pub fn remove_root(&mut self, id: RootId) {
+        self.roots.retain(|root| root.id != id);
+    }
+
+    /// Reads the settings of the workspace file and of every root again, leaving the roots
+    /// as they are.
+    pub fn reload_settings(&mut self) {
+        if let Some(file) = &self.file {
+            match WorkspaceFile::read(file) {
+                Ok(parsed) => self.settings = parsed.settings,
+                Err(e) => warn!("Failed to read {}: {e}", file.display()),
+            }
+        }
+        for i in 0..self.roots.len() {
+            self.roots[i].excludes = self.load_excludes(&self.roots[i].path);
+            self.roots[i].pairs = self.load_pairs(&self.roots[i].path);
+        }
+    }
+
+    /// Defaults, then the workspace file, then the root's own settings.
+    fn load_section<T: SettingsSection>(&self, root: &Path) -> T {
+        let mut settings = T::default();
+        settings.merge_values(self.settings.clone(), "workspace settings");
+        settings.merge_file(&root.join(SETTINGS_FILE));
+        settings
+    }
+
+    fn load_excludes(&self, root: &Path) -> Excludes {
+        Excludes::from_settings(&self.load_section::<ExcludeSettings>(root))
+    }
+
+    fn load_pairs(&self, root: &Path) -> PairSettings {
+        self.load_section(root)
+    }
+}
+
+/// Opens a `.dip-workspace` file, or a folder as a workspace with a single root.
+#[derive(Clone, Debug)]
+pub struct OpenWorkspace {
+    pub path: PathBuf,
+}
+
+#[derive(Clone, Debug)]
+pub struct SaveWorkspace {
+    pub path: PathBuf,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ReloadWorkspaceSettings;
+
+/// Roots or their settings changed.
+#[derive(Clone, Copy, Debug)]
+pub struct WorkspaceChanged;
+
+/// Takes the document out of the workspace, keeping it [`Closed`] for a while to be opened
+/// again. A dirty document stays open and [`UnsavedClosePrompt`] asks about its changes.
+#[derive(Clone, Copy, Debug)]
+pub struct CloseDocument {
+    pub entity: Entity,
+    /// Closes even with unsaved changes, throwing them away.
+    pub discard: bool,
+}
+
+/// A document asked to close has unsaved changes. It stays open until
+/// [`ResolveUnsavedClose`] answers.
+#[derive(Clone, Copy, Debug)]
+pub struct UnsavedClosePrompt {
+    pub entity: Entity,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ResolveUnsavedClose {
+    pub entity: Entity,
+    pub choice: UnsavedChoice,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct FocusDocument {
+    pub entity: Entity,
+}
+
+#[derive(Clone, Debug)]
+pub struct DocumentClosed {
+    pub entity: Entity,
+    /// None for an untitled document.
+    pub path: Option<PathBuf>,
+}
+
+fn open_current_dir(mut workspace: ResMut<Workspace>) {
+    let root = env::current_dir().unwrap_or_default();
+    *workspace = Workspace::folder(root);
+}
+
+fn open_workspace(
+    mut events: EventReader<OpenWorkspace>,
+    mut workspace: ResMut<Workspace>,
+    mut changed: EventWriter<WorkspaceChanged>,
+) {
+    for e in events.iter() {
+        let opened = if e.path.is_dir() {
+            Ok(Workspace::folder(&e.path))
+        } else {
+            Workspace::open(&e.path)
+        };
+        match opened {
+            Ok(mut opened) => {
+                debug!("🗂 Opened workspace with {} roots", opened.roots().len());
+                opened.documents = mem::take(&mut workspace.documents);
+                opened.active = workspace.active;
+                *workspace = opened;
+                changed.send(WorkspaceChanged);
+            }
+            Err(err) => warn!("🗂 Failed to open {}: {err}", e.path.display()),
+        }
+    }
+}
+
+fn save_workspace(mut events: EventReader<SaveWorkspace>, mut workspace: ResMut<Workspace>) {
+    for e in events.iter() {
+        if let Err(err) = workspace.save(&e.path) {
+            warn!("🗂 Failed to save {}: {err}", e.path.display());
+        }
+    }
+}
+
+fn reload_workspace_settings(
+    mut events: EventReader<ReloadWorkspaceSettings>,
+    mut workspace: ResMut<Workspace>,
+    mut changed: EventWriter<WorkspaceChanged>,
+) {
+    if events.iter().count() > 0 {
+        workspace.reload_settings();
+        changed.send(WorkspaceChanged);
+    }
+}
+
+/// Picks up documents however they were spawned, e.g. from standard input or a merge, and
+/// focuses the one last opened.
+fn track_documents(
+    mut workspace: ResMut<Workspace>,
+    added: Query<Entity, Added<Document>>,
+    mut opened: EventReader<DocumentOpened>,
+) {
+    for entity in added.iter() {
+        if !workspace.is_open(entity) {
+            workspace.documents.push(entity);
+            workspace.active = Some(entity);
+        }
+    }
+    // Also sent for a path that was already open, which brings it to the front.
+    for e in opened.iter() {
+        if !workspace.is_open(e.entity) {
+            workspace.documents.push(e.entity);
+        }
+        workspace.active = Some(e.entity);
+    }
+}
+
+fn close_documents(
+    mut commands: Commands,
+    mut events: EventReader<CloseDocument>,
+    mut workspace: ResMut<Workspace>,
+    time: Res<Time>,
+    documents: Query<(&Document, Option<&VirtualDocument>)>,
+    (mut closed, mut prompts): (EventWriter<DocumentClosed>, EventWriter<UnsavedClosePrompt>),
+) {
+    for e in events.iter() {
+        let i = match workspace.documents.iter().position(|d| *d == e.entity) {
+            Some(i) => i,
+            None => continue,
+        };
+        let document = documents.get(e.entity).ok();
+        let dirty = document.is_some_and(|(document, _)| document.is_dirty());
+        // Virtual documents are saved by their plugins, which keep what they need.
+        let real = document.is_some_and(|(_, virtual_document)| virtual_document.is_none());
+        if dirty && real && !e.discard {
+            prompts.send(UnsavedClosePrompt { entity: e.entity });
+            continue;
+        }
+        workspace.documents.remove(i);
+        if workspace.active == Some(e.entity) {
+            // The document to the right takes its place, as when closing a tab.
+            let next = i.min(workspace.documents.len().saturating_sub(1));
+            workspace.active = workspace.documents.get(next).copied();
+        }
+
+        let path = document.and_then(|(document, _)| document.path().map(Path::to_path_buf));
+        match path {
+            // Discarded changes must not come back with the document.
+            Some(_) if !dirty => {
+                let at = time.seconds_since_startup();
+                commands.entity(e.entity).insert(Closed { at });
+            }
+            _ => commands.entity(e.entity).despawn(),
+        }
+        debug!("🗂 Closed {:?}, {} open", path, workspace.documents.len());
+        closed.send(DocumentClosed {
+            entity: e.entity,
+            path,
+        });
+    }
+}
+
+/// Saves or throws away the changes of a document asked about, then closes it. It stays
+/// open when the save fails, so nothing is lost.
+fn resolve_unsaved_close(
+    mut answers: EventReader<ResolveUnsavedClose>,
+    (mut saved, mut failed, mut conflicts): (
+        EventReader<DocumentSaved>,
+        EventReader<DocumentSaveFailed>,
+        EventReader<SaveConflict>,
+    ),
+    documents: Query<&Document>,
+    mut saving: Local<Vec<Entity>>,
+    mut saves: EventWriter<SaveDocument>,
+    mut closes: EventWriter<CloseDocument>,
+) {
+    for e in answers.iter() {
+        let document = match documents.get(e.entity) {
+            Ok(document) => document,
+            Err(_) => continue,
+        };
+        match e.choice {
+            UnsavedChoice::Discard => closes.send(CloseDocument {
+                entity: e.entity,
+                discard: true,
+            }),
+            UnsavedChoice::Save if document.path().is_none() => {
+                warn!("🗂 Untitled documents have nowhere to be saved, not closing");
+            }
+            UnsavedChoice::Save => {
+                saves.send(SaveDocument {
+                    entity: e.entity,
+                    path: None,
+                });
+                saving.push(e.entity);
+            }
+        }
+    }
+
+    let failed = failed.iter().map(|e| e.entity);
+    for entity in failed.chain(conflicts.iter().map(|e| e.entity)) {
+        saving.retain(|e| *e != entity);
+    }
+    for e in saved.iter() {
+        if let Some(i) = saving.iter().position(|entity| *entity == e.entity) {
+            saving.remove(i);
+            closes.send(CloseDocument {
+                entity: e.entity,
+                discard: false,
+            });
+        }
+    }
+}
+
+/// Marks the active document [`Visible`], sparing its caches from eviction. Being shown
+/// counts as use, so a document hidden now is used last.
+fn mark_visible_documents(
+    mut commands: Commands,
+    workspace: Res<Workspace>,
+    time: Res<Time>,
+    visible: Query<Entity, With<Visible>>,
+    mut usages: Query<&mut MemoryUsage>,
+) {
+    let active = workspace.active();
+    let mut shown = false;
+    for entity in visible.iter() {
+        if Some(entity) == active {
+            shown = true;
+            continue;
+        }
+        commands.entity(entity).remove::<Visible>();
+        if let Ok(mut usage) = usages.get_mut(entity) {
+            usage.last_used = time.seconds_since_startup();
+        }
+    }
+    if let (Some(entity), false) = (active, shown) {
+        if let Ok(mut usage) = usages.get_mut(entity) {
+            commands.entity(entity).insert(Visible);
+            usage.last_used = time.seconds_since_startup();
+        }
+    }
+}
+
+fn focus_documents(mut events: EventReader<FocusDocument>, mut workspace: ResMut<Workspace>) {
+    for e in events.iter() {
+        if workspace.is_open(e.entity) {
+            workspace.active = Some(e.entity);
+        }
+    }
+}
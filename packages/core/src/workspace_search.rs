@@ -0,0 +1,473 @@
+use crate::{
+    exclude::Exclude,
+    idle::throttled,
+    search::find_literal,
+    workspace::{RootId, Workspace, WorkspaceChanged, WorkspaceRoot},
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        event::{EventReader, EventWriter},
+        schedule::ParallelSystemDescriptorCoercion,
+        system::{Res, ResMut},
+    },
+    log::debug,
+    tasks::{AsyncComputeTaskPool, TaskPool},
+};
+use std::{
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc, Condvar, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// How often results are collected while the user is idle, every frame otherwise.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Files containing a NUL byte in their first bytes are treated as binary and skipped.
+const BINARY_SNIFF_LEN: usize = 8 * 1024;
+
+pub struct WorkspaceSearchPlugin;
+
+impl Plugin for WorkspaceSearchPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WorkspaceSearchSettings>()
+            .init_resource::<ActiveWorkspaceSearch>()
+            .add_event::<SearchWorkspace>()
+            .add_event::<CancelWorkspaceSearch>()
+            .add_event::<FileMatches>()
+            .add_event::<WorkspaceSearchFinished>()
+            .add_system(start_workspace_search)
+            .add_system(
+                poll_workspace_search
+                    .with_run_criteria(throttled(Duration::ZERO, IDLE_POLL_INTERVAL)),
+            );
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WorkspaceSearchSettings {
+    /// Files read at the same time, so a search does not saturate the disk.
+    pub max_concurrent_reads: usize,
+    /// Larger files are skipped.
+    pub max_file_size: u64,
+}
+
+impl Default for WorkspaceSearchSettings {
+    fn default() -> Self {
+        Self {
+            max_concurrent_reads: 8,
+            max_file_size: 16 * 1024 * 1024,
+        }
+    }
+}
+
+/// Starts searching every root of the workspace, cancelling the search in progress.
+#[derive(Clone, Debug)]
+pub struct SearchWorkspace {
+    pub query: String,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CancelWorkspaceSearch;
+
+/// Byte ranges of the matches in one file, sent as the search goes.
+#[derive(Clone, Debug)]
+pub struct FileMatches {
+    pub root: RootId,
+    pub path: PathBuf,
+    pub ranges: Vec<Range<usize>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct WorkspaceSearchFinished {
+    pub query: String,
+    pub files_searched: usize,
+    pub elapsed: Duration,
+}
+
+#[derive(Default)]
+pub struct ActiveWorkspaceSearch {
+    pub search: Option<WorkspaceSearch>,
+}
+
+/// A search for a literal across the files of the workspace. Dropping it cancels it.
+pub struct WorkspaceSearch {
+    query: String,
+    scan: WorkspaceScan<FileMatches>,
+}
+
+impl WorkspaceSearch {
+    pub fn start(
+        pool: &TaskPool,
+        roots: &[WorkspaceRoot],
+        query: String,
+        settings: &WorkspaceSearchSettings,
+    ) -> Self {
+        let literal = Literal(query.clone());
+        let scan = WorkspaceScan::start(pool, roots, settings, literal);
+        Self { query, scan }
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn cancel(&self) {
+        self.scan.cancel();
+    }
+
+    /// Moves the matches found so far into `out`. Returns true once every worker is done.
+    pub fn drain(&self, out: &mut Vec<FileMatches>) -> bool {
+        self.scan.drain(out)
+    }
+
+    /// Blocks until the search is done and returns every match.
+    pub fn wait(&self) -> Vec<FileMatches> {
+        self.scan.wait()
+    }
+
+    pub fn files_searched(&self) -> usize {
+        self.scan.files_read()
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.scan.elapsed()
+    }
+}
+
+/// Finds a literal in every file.
+struct Literal(String);
+
+impl FileScan for Literal {
+    type Output = FileMatches;
+
+    fn scan(&self, root: RootId, path: &Path, text: &str) -> Option<FileMatches> {
+        let ranges = find_literal([text], &self.0);
+        (!ranges.is_empty()).then(|| FileMatches {
+            root,
+            path: path.to_path_buf(),
+            ranges,
+        })
+    }
+}
+
+/// What a [`WorkspaceScan`] does with each file, on the workers of a task pool.
+pub trait FileScan: Send + Sync + 'static {
+    type Output: Send + 'static;
+
+    /// Whether to read the file at all, e.g. only those of a known language.
+    fn wants(&self, _path: &Path) -> bool {
+        true
+    }
+
+    /// What was found in the text of the file, along with the root it belongs to.
+    fn scan(&self, root: RootId, path: &Path, text: &str) -> Option<Self::Output>;
+}
+
+/// Work done on every text file of the workspace roots on a task pool, e.g. a search or
+/// indexing symbols. Files are handed out one at a time from a shared cursor, so workers
+/// that finish small files early keep taking more instead of idling behind a fixed split.
+/// Reads wait for one of [`WorkspaceSearchSettings::max_concurrent_reads`] slots, so the
+/// workers do not saturate the disk. Dropping it cancels the work.
+pub struct WorkspaceScan<T> {
+    cancelled: Arc<AtomicBool>,
+    files_read: Arc<AtomicUsize>,
+    receiver: Mutex<Receiver<T>>,
+    started: Instant,
+}
+
+impl<T: Send + 'static> WorkspaceScan<T> {
+    /// Runs `scan` on the text of each file it wants, sending along what it finds.
+    pub fn start<S: FileScan<Output = T>>(
+        pool: &TaskPool,
+        roots: &[WorkspaceRoot],
+        settings: &WorkspaceSearchSettings,
+        scan: S,
+    ) -> Self {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let files_read = Arc::new(AtomicUsize::new(0));
+        let (sender, receiver) = mpsc::channel();
+
+        let workers = pool.thread_num().max(1);
+        let job = Job {
+            settings: settings.clone(),
+            cancelled: cancelled.clone(),
+            files_read: files_read.clone(),
+            io: IoLimiter::new(settings.max_concurrent_reads),
+        };
+        // A root nested in another is searched on its own, with its own excludes.
+        let roots: Vec<_> = roots
+            .iter()
+            .map(|root| {
+                let nested: Vec<_> = roots
+                    .iter()
+                    .filter(|other| other.path != root.path && other.path.starts_with(&root.path))
+                    .map(|other| other.path.clone())
+                    .collect();
+                (
+                    root.id,
+                    root.path.clone(),
+                    root.excludes.search.clone(),
+                    nested,
+                )
+            })
+            .collect();
+        let worker_pool = pool.clone();
+        pool.spawn(async move {
+            let mut files = vec![];
+            for (id, path, exclude, nested) in &roots {
+                job.walk(*id, path, exclude, nested, &scan, &mut files);
+            }
+            debug!("🔎 Reading {} files with {workers} workers", files.len());
+
+            let shared = Arc::new(Shared {
+                job,
+                files,
+                next: AtomicUsize::new(0),
+                scan,
+            });
+            for _ in 0..workers {
+                let shared = shared.clone();
+                let sender = sender.clone();
+                worker_pool
+                    .spawn(async move { shared.work(&sender) })
+                    .detach();
+            }
+        })
+        .detach();
+
+        Self {
+            cancelled,
+            files_read,
+            receiver: Mutex::new(receiver),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Moves what was found so far into `out`. Returns true once every worker is done.
+    pub fn drain(&self, out: &mut Vec<T>) -> bool {
+        let receiver = self.receiver.lock().unwrap();
+        loop {
+            match receiver.try_recv() {
+                Ok(found) => out.push(found),
+                Err(TryRecvError::Empty) => return false,
+                Err(TryRecvError::Disconnected) => return true,
+            }
+        }
+    }
+
+    /// Blocks until the work is done and returns everything found.
+    pub fn wait(&self) -> Vec<T> {
+        let receiver = self.receiver.lock().unwrap();
+        receiver.iter().collect()
+    }
+
+    /// Text files read so far, leaving out the binary and oversized ones skipped.
+    pub fn files_read(&self) -> usize {
+        self.files_read.load(Ordering::Relaxed)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+impl<T> Drop for WorkspaceScan<T> {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+struct Job {
+    settings: WorkspaceSearchSettings,
+    cancelled: Arc<AtomicBool>,
+    files_read: Arc<AtomicUsize>,
+    io: IoLimiter,
+}
+
+impl Job {
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Collects the files under `root`, with `exclude` matched against paths relative to it,
+    /// leaving out the `nested` roots.
+    fn walk(
+        &self,
+        id: RootId,
+        root: &Path,
+        exclude: &Exclude,
+        nested: &[PathBuf],
+        scan: &impl FileScan,
+        files: &mut Vec<(RootId, PathBuf)>,
+    ) {
+        let mut dirs = vec![root.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            if self.is_cancelled() {
+                break;
+            }
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    debug!("🔎 Skipping {}: {e}", dir.display());
+                    continue;
+                }
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                // Parents were checked on the way down.
+                let relative = path.strip_prefix(root).unwrap_or(&path);
+                if exclude.matches(relative) {
+                    continue;
+                }
+                match entry.file_type() {
+                    Ok(t) if t.is_dir() && nested.contains(&path) => {}
+                    Ok(t) if t.is_dir() => dirs.push(path),
+                    Ok(t) if t.is_file() && scan.wants(&path) => files.push((id, path)),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn read(&self, path: &Path) -> Option<String> {
+        let _permit = self.io.acquire();
+        let len = fs::metadata(path).ok()?.len();
+        if len > self.settings.max_file_size {
+            return None;
+        }
+        let bytes = fs::read(path).ok()?;
+        let sniff = &bytes[..bytes.len().min(BINARY_SNIFF_LEN)];
+        if memchr::memchr(0, sniff).is_some() {
+            return None;
+        }
+        String::from_utf8(bytes).ok()
+    }
+}
+
+struct Shared<S> {
+    job: Job,
+    files: Vec<(RootId, PathBuf)>,
+    next: AtomicUsize,
+    scan: S,
+}
+
+impl<S: FileScan> Shared<S> {
+    fn work(&self, sender: &Sender<S::Output>) {
+        while !self.job.is_cancelled() {
+            let i = self.next.fetch_add(1, Ordering::Relaxed);
+            let (root, path) = match self.files.get(i) {
+                Some((root, path)) => (*root, path),
+                None => break,
+            };
+            let text = match self.job.read(path) {
+                Some(text) => text,
+                None => continue,
+            };
+            self.job.files_read.fetch_add(1, Ordering::Relaxed);
+
+            if let Some(found) = self.scan.scan(root, path, &text) {
+                if sender.send(found).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Caps how many files are read at once across the workers of a scan.
+struct IoLimiter {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+struct IoPermit<'a>(&'a IoLimiter);
+
+impl IoLimiter {
+    fn new(permits: usize) -> Self {
+        Self {
+            available: Mutex::new(permits.max(1)),
+            released: Condvar::new(),
+        }
+    }
+
+    fn acquire(&self) -> IoPermit<'_> {
+        let mut available = self.available.lock().unwrap();
+        while *available == 0 {
+            available = self.released.wait(available).unwrap();
+        }
+        *available -= 1;
+        IoPermit(self)
+    }
+}
+
+impl Drop for IoPermit<'_> {
+    fn drop(&mut self) {
+        *self.0.available.lock().unwrap() += 1;
+        self.0.released.notify_one();
+    }
+}
+
+/// Starts the last search asked for. One running is cancelled by a new one, by
+/// [`CancelWorkspaceSearch`] and when the roots of the workspace change.
+fn start_workspace_search(
+    mut searches: EventReader<SearchWorkspace>,
+    (mut cancels, mut changed): (
+        EventReader<CancelWorkspaceSearch>,
+        EventReader<WorkspaceChanged>,
+    ),
+    (pool, settings): (Res<AsyncComputeTaskPool>, Res<WorkspaceSearchSettings>),
+    workspace: Res<Workspace>,
+    mut active: ResMut<ActiveWorkspaceSearch>,
+) {
+    if cancels.iter().count() + changed.iter().count() > 0 {
+        active.search = None;
+    }
+
+    if let Some(e) = searches.iter().last() {
+        debug!(
+            "🔎 Searching {} roots for {:?}",
+            workspace.roots().len(),
+            e.query
+        );
+        active.search = Some(WorkspaceSearch::start(
+            &pool,
+            workspace.roots(),
+            e.query.clone(),
+            &settings,
+        ));
+    }
+}
+
+fn poll_workspace_search(
+    mut active: ResMut<ActiveWorkspaceSearch>,
+    mut matches: EventWriter<FileMatches>,
+    mut finished: EventWriter<WorkspaceSearchFinished>,
+) {
+    let search = match &active.search {
+        Some(search) => search,
+        None => return,
+    };
+
+    let mut found = vec![];
+    let done = search.drain(&mut found);
+    matches.send_batch(found.into_iter());
+
+    if done {
+        finished.send(WorkspaceSearchFinished {
+            query: search.query().to_string(),
+            files_searched: search.files_searched(),
+            elapsed: search.elapsed(),
+        });
+        active.search = None;
+    }
+}
@@ -0,0 +1,257 @@
+use crate::{
+    layout::{LineLayout, Measure},
+    text_buffer::TextBuffer,
+};
+use bevy::tasks::TaskPool;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    ops::Range,
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Mutex,
+    },
+};
+
+fn hash_line(line: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    line.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[derive(Clone, Debug)]
+struct LineWrap {
+    hash: u64,
+    width: Option<f32>,
+    /// Of the whole line, as if it did not wrap.
+    line_width: f32,
+    /// Column each row starts at, the first one at 0.
+    rows: Vec<usize>,
+    /// Cleared when an edit touches the line, the entry is reused if its hash still matches.
+    verified: bool,
+}
+
+impl LineWrap {
+    fn new(text: &str, layout: &LineLayout, width: Option<f32>) -> Self {
+        Self {
+            hash: hash_line(text),
+            width,
+            line_width: layout.line_width(),
+            rows: layout.row_starts().to_vec(),
+            verified: true,
+        }
+    }
+
+    /// Whether the rows hold for `width`. A line narrower than it is one row either way.
+    fn fits(&self, width: Option<f32>) -> bool {
+        self.width == width
+            || (self.rows.len() == 1 && width.is_none_or(|width| self.line_width <= width))
+    }
+}
+
+/// Line count changes since a background job started, to map its results onto the lines
+/// as they are now.
+#[derive(Clone, Debug)]
+struct Splice {
+    lines: Range<usize>,
+    new_count: usize,
+}
+
+struct Background {
+    receiver: Mutex<Receiver<(usize, LineWrap)>>,
+    splices: Vec<Splice>,
+}
+
+/// Where every line of a document wraps for one width, without the rest of their
+/// [`LineLayout`], so the lines off screen cost a few numbers each.
+///
+/// Entries are keyed by the line's content hash and the width they were wrapped at. An
+/// edit only invalidates the lines it touches, and those are wrapped again only if their
+/// content actually changed. A width change only invalidates lines wider than the new
+/// width, as shorter lines are one row either way. Visible lines are recorded as they are
+/// laid out, the rest are rebuilt on a task pool.
+pub struct WrapCache {
+    width: Option<f32>,
+    measure: Measure,
+    lines: Vec<Option<LineWrap>>,
+    /// Known to have no stale line, so finding them can be skipped.
+    complete: bool,
+    background: Option<Background>,
+}
+
+impl WrapCache {
+    pub fn new(line_count: usize, width: Option<f32>, measure: Measure) -> Self {
+        Self {
+            width,
+            measure,
+            lines: vec![None; line_count],
+            complete: line_count == 0,
+            background: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn set_width(&mut self, width: Option<f32>) {
+        self.width = width;
+        self.complete = false;
+        // Wrapped for the old width.
+        self.background = None;
+    }
+
+    /// Records that `lines` were replaced by `new_count` lines.
+    pub fn edited(&mut self, lines: Range<usize>, new_count: usize) {
+        let end = lines.end.min(self.lines.len());
+        let start = lines.start.min(end);
+        let mut old: Vec<_> = self.lines.drain(start..end).collect();
+        old.resize(new_count, None);
+        for wrap in old.iter_mut().flatten() {
+            wrap.verified = false;
+        }
+        self.lines.splice(start..start, old);
+        self.complete = false;
+        if let Some(background) = &mut self.background {
+            background.splices.push(Splice { lines, new_count });
+        }
+    }
+
+    /// Keeps how `layout`, just laid out from `text`, wraps `line`.
+    pub fn record(&mut self, line: usize, text: &str, layout: &LineLayout) {
+        if let Some(entry) = self.lines.get_mut(line) {
+            *entry = Some(LineWrap::new(text, layout, self.width));
+        }
+    }
+
+    /// Columns the rows of `line` start at, if known for the current width.
+    pub fn rows(&self, line: usize) -> Option<&[usize]> {
+        let wrap = self.lines.get(line)?.as_ref()?;
+        (wrap.verified && wrap.fits(self.width)).then_some(&wrap.rows[..])
+    }
+
+    /// Number of visual rows `line` takes, or 1 while it is unknown.
+    pub fn row_count(&self, line: usize) -> usize {
+        self.rows(line).map_or(1, <[usize]>::len)
+    }
+
+    /// The widest line known, as if none wrapped.
+    pub fn max_line_width(&self) -> f32 {
+        self.lines
+            .iter()
+            .flatten()
+            .filter(|wrap| wrap.verified)
+            .map(|wrap| wrap.line_width)
+            .fold(0., f32::max)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    pub fn is_rebuilding(&self) -> bool {
+        self.background.is_some()
+    }
+
+    /// Wraps every stale line on `pool`, picked up by [`WrapCache::receive`]. Lines whose
+    /// content did not change are only checked here. A job already running is abandoned.
+    pub fn rebuild_in_background(&mut self, pool: &TaskPool, buffer: &TextBuffer) {
+        let mut stale: Vec<(usize, String)> = vec![];
+        for line in 0..self.lines.len().min(buffer.line_count()) {
+            if self.rows(line).is_some() {
+                continue;
+            }
+            let text = buffer.get_line_content(line);
+            match &mut self.lines[line] {
+                Some(wrap) if wrap.hash == hash_line(&text) && wrap.fits(self.width) => {
+                    wrap.verified = true;
+                }
+                _ => stale.push((line, text.into_owned())),
+            }
+        }
+        if stale.is_empty() {
+            self.complete = true;
+            self.background = None;
+            return;
+        }
+
+        let (width, measure) = (self.width, self.measure.clone());
+        let (sender, receiver) = mpsc::channel();
+        pool.spawn(async move {
+            for (line, text) in stale {
+                let layout = LineLayout::new(&text, width, &measure);
+                if sender
+                    .send((line, LineWrap::new(&text, &layout, width)))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        })
+        .detach();
+
+        self.background = Some(Background {
+            receiver: Mutex::new(receiver),
+            splices: vec![],
+        });
+    }
+
+    /// Merges what the background job wrapped so far. Returns whether any line was.
+    pub fn receive(&mut self) -> bool {
+        let background = match self.background.take() {
+            Some(background) => background,
+            None => return false,
+        };
+
+        let mut done = false;
+        let mut received = vec![];
+        {
+            let receiver = background.receiver.lock().unwrap();
+            loop {
+                match receiver.try_recv() {
+                    Ok(result) => received.push(result),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        done = true;
+                        break;
+                    }
+                }
+            }
+        }
+
+        let mut merged = false;
+        for (line, wrap) in received {
+            let line = match remap(&background.splices, line) {
+                Some(line) => line,
+                None => continue,
+            };
+            if line < self.lines.len() && self.rows(line).is_none() {
+                self.lines[line] = Some(wrap);
+                merged = true;
+            }
+        }
+
+        // Lines an edit replaced meanwhile are still stale, the next rebuild finds them.
+        if !done {
+            self.background = Some(background);
+        }
+        merged
+    }
+}
+
+/// Follows `line` through `splices`, or returns None if one of them replaced it.
+fn remap(splices: &[Splice], mut line: usize) -> Option<usize> {
+    for splice in splices {
+        if splice.lines.contains(&line) {
+            return None;
+        }
+        if line >= splice.lines.end {
+            line = line - splice.lines.len() + splice.new_count;
+        }
+    }
+    Some(line)
+}
@@ -0,0 +1,230 @@
+use crate::{
+    damage::{Damage, VisibleLines},
+    document::Document,
+};
+use bevy::{
+    app::{App, Plugin},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::EventReader,
+        query::Added,
+        system::{Commands, Query, Res, ResMut},
+    },
+};
+
+pub struct ZoomPlugin;
+
+impl Plugin for ZoomPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ZoomSettings>()
+            .init_resource::<UiScale>()
+            .add_event::<ChangeZoom>()
+            .add_event::<SetUiScale>()
+            .add_system(attach_zoom)
+            .add_system(change_zoom)
+            .add_system(set_ui_scale);
+    }
+}
+
+pub struct ZoomSettings {
+    /// Font size of a view that was never zoomed, in logical pixels.
+    pub font_size: f32,
+    /// Font size added or removed by each zoom step.
+    pub step: f32,
+    pub min_font_size: f32,
+    pub max_font_size: f32,
+    /// Line height as a multiple of the font size.
+    pub line_height: f32,
+    /// Advance of a monospace glyph as a multiple of the font size.
+    pub char_width: f32,
+}
+
+impl Default for ZoomSettings {
+    fn default() -> Self {
+        Self {
+            font_size: 14.,
+            step: 1.,
+            min_font_size: 6.,
+            max_font_size: 72.,
+            line_height: 1.5,
+            char_width: 0.6,
+        }
+    }
+}
+
+impl ZoomSettings {
+    fn font_size(&self, zoom: Zoom) -> f32 {
+        (self.font_size + zoom.0 as f32 * self.step).clamp(self.min_font_size, self.max_font_size)
+    }
+}
+
+/// Scale of the whole UI, e.g. for a high density display or presenting. Applies on top
+/// of the zoom of each view.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UiScale(pub f32);
+
+impl Default for UiScale {
+    fn default() -> Self {
+        Self(1.)
+    }
+}
+
+/// Steps a view is zoomed in by, negative when zoomed out.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Zoom(pub i32);
+
+/// Sizes a view lays text out with, in physical pixels.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct LayoutMetrics {
+    pub font_size: f32,
+    pub line_height: f32,
+    pub char_width: f32,
+}
+
+impl LayoutMetrics {
+    pub fn new(settings: &ZoomSettings, zoom: Zoom, scale: UiScale) -> Self {
+        let font_size = settings.font_size(zoom) * scale.0;
+        Self {
+            font_size,
+            line_height: (font_size * settings.line_height).round(),
+            char_width: font_size * settings.char_width,
+        }
+    }
+}
+
+/// Scroll offset of a view in physical pixels, from the top and the left of the document.
+/// Set by the view scrolling and by [`crate::scroll`], which animates it.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Scroll {
+    pub top: f32,
+    /// Zero while lines wrap.
+    pub left: f32,
+}
+
+impl Scroll {
+    /// Keeps `first`, the first visible line, and the part of it scrolled past, at the top
+    /// when lines change height.
+    fn rescale(&mut self, first: usize, from: &LayoutMetrics, to: &LayoutMetrics) {
+        let into = (self.top - first as f32 * from.line_height).max(0.);
+        self.top = first as f32 * to.line_height + into * to.line_height / from.line_height;
+        self.left *= to.char_width / from.char_width;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZoomChange {
+    In,
+    Out,
+    Reset,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ChangeZoom {
+    pub entity: Entity,
+    pub change: ZoomChange,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SetUiScale(pub f32);
+
+fn attach_zoom(
+    mut commands: Commands,
+    added: Query<Entity, Added<Document>>,
+    settings: Res<ZoomSettings>,
+    scale: Res<UiScale>,
+) {
+    for entity in added.iter() {
+        commands
+            .entity(entity)
+            .insert(Zoom::default())
+            .insert(LayoutMetrics::new(&settings, Zoom::default(), *scale))
+            .insert(Scroll::default());
+    }
+}
+
+type Views<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut Zoom,
+        &'static mut LayoutMetrics,
+        &'static mut Scroll,
+        Option<&'static VisibleLines>,
+        Option<&'static mut Damage>,
+    ),
+>;
+
+/// Lays a view out again with `to`, keeping its first visible line in place.
+fn relayout(
+    metrics: &mut LayoutMetrics,
+    scroll: &mut Scroll,
+    visible: Option<&VisibleLines>,
+    damage: Option<&mut Damage>,
+    to: LayoutMetrics,
+) {
+    if *metrics == to {
+        return;
+    }
+    let first = visible.map_or(0, |v| v.0.start);
+    scroll.rescale(first, metrics, &to);
+    *metrics = to;
+    if let Some(damage) = damage {
+        damage.mark_all();
+    }
+}
+
+fn change_zoom(
+    mut events: EventReader<ChangeZoom>,
+    settings: Res<ZoomSettings>,
+    scale: Res<UiScale>,
+    mut views: Views,
+) {
+    for e in events.iter() {
+        let (mut zoom, mut metrics, mut scroll, visible, damage) = match views.get_mut(e.entity) {
+            Ok(view) => view,
+            Err(_) => continue,
+        };
+        let level = match e.change {
+            ZoomChange::In => zoom.0 + 1,
+            ZoomChange::Out => zoom.0 - 1,
+            ZoomChange::Reset => 0,
+        };
+        // Stop at the limits, so zooming back out takes effect right away.
+        if settings.font_size(Zoom(level)) == settings.font_size(*zoom) {
+            continue;
+        }
+        *zoom = Zoom(level);
+        let to = LayoutMetrics::new(&settings, *zoom, *scale);
+        relayout(
+            &mut metrics,
+            &mut scroll,
+            visible,
+            damage.map(|d| d.into_inner()),
+            to,
+        );
+    }
+}
+
+fn set_ui_scale(
+    mut events: EventReader<SetUiScale>,
+    settings: Res<ZoomSettings>,
+    mut scale: ResMut<UiScale>,
+    mut views: Views,
+) {
+    let latest = match events.iter().last() {
+        Some(e) if e.0 > 0. => *e,
+        _ => return,
+    };
+    *scale = UiScale(latest.0);
+    for (zoom, mut metrics, mut scroll, visible, damage) in views.iter_mut() {
+        let to = LayoutMetrics::new(&settings, *zoom, *scale);
+        relayout(
+            &mut metrics,
+            &mut scroll,
+            visible,
+            damage.map(|d| d.into_inner()),
+            to,
+        );
+    }
+}
@@ -0,0 +1,48 @@
+//! What screen readers are told about deletions, and that the view is told to read it.
+
+mod common;
+
+use bevy::ecs::event::Events;
+use dip_core::{
+    announce::{AnnouncePlugin, Announcement},
+    command::UICommand,
+};
+
+fn deleted(text: &str) -> Option<String> {
+    Announcement::deleted(text).map(|a| a.text)
+}
+
+#[test]
+fn counts_characters_as_they_are_seen() {
+    assert_eq!(deleted("é"), None);
+    assert_eq!(deleted("e\u{301}"), None);
+    assert_eq!(deleted("👨‍👩‍👧"), None);
+    assert_eq!(deleted("ab👍🏽"), Some("Deleted 3 characters".into()));
+}
+
+#[test]
+fn tells_joined_lines_from_deleted_ones() {
+    assert_eq!(deleted("\n"), Some("Joined lines".into()));
+    assert_eq!(deleted("\r\n"), Some("Joined lines".into()));
+    assert_eq!(deleted("one\n"), Some("Deleted 1 line".into()));
+    assert_eq!(deleted("one\ntwo\n"), Some("Deleted 2 lines".into()));
+}
+
+#[test]
+fn hands_announcements_to_the_view() {
+    let mut app = common::app();
+    app.add_plugin(AnnouncePlugin).add_event::<UICommand>();
+    common::send(&mut app, Announcement::match_of(1, 15));
+    app.update();
+
+    let events = app.world.get_resource::<Events<UICommand>>().unwrap();
+    let announced: Vec<_> = events
+        .get_reader()
+        .iter(events)
+        .filter_map(|cmd| match cmd {
+            UICommand::Announce(announcement) => Some(announcement.text.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(announced, ["2 of 15 matches"]);
+}
@@ -0,0 +1,99 @@
+//! Every save is recorded with the file as it was before and after, and appended to a log
+//! file that outlives the session.
+
+mod common;
+
+use bevy::{
+    app::App,
+    ecs::{entity::Entity, event::Events},
+};
+use common::ScratchDir;
+use dip_core::{
+    audit::{AuditLog, AuditPlugin, FileDigest},
+    document::SaveDocument,
+    elevate::SaveElevated,
+};
+use std::fs;
+
+/// An app logging to `audit.log` in `dir`, with `notes.txt` there open and edited.
+fn edited(dir: &ScratchDir) -> (App, Entity) {
+    let mut app = common::app();
+    app.add_plugin(AuditPlugin)
+        // Sent by the plugins left out.
+        .add_event::<SaveElevated>();
+    app.world.get_resource_mut::<AuditLog>().unwrap().file = Some(dir.join("audit.log"));
+
+    fs::write(dir.notes(), "saved\n").unwrap();
+    let document = common::edited(&dir.notes(), "edited ");
+    let entity = app.world.spawn().insert(document).id();
+    app.update();
+    (app, entity)
+}
+
+fn save(app: &mut App, saves: &[SaveDocument]) {
+    let mut events = app
+        .world
+        .get_resource_mut::<Events<SaveDocument>>()
+        .unwrap();
+    for save in saves {
+        events.send(save.clone());
+    }
+    app.update();
+}
+
+#[test]
+fn records_the_file_before_and_after() {
+    let dir = ScratchDir::new("record");
+    let (mut app, entity) = edited(&dir);
+    let path = dir.notes();
+    let before = FileDigest::read(&path).unwrap();
+    save(&mut app, &[SaveDocument { entity, path: None }]);
+
+    let log = app.world.get_resource::<AuditLog>().unwrap();
+    let [entry] = log.entries() else {
+        panic!("{:?}", log.entries());
+    };
+    assert_eq!(entry.path, path);
+    assert_eq!(entry.before, Some(before));
+    assert_eq!(entry.after, FileDigest::read(&path).unwrap());
+    assert_eq!(entry.delta(), 7);
+    let file = fs::read_to_string(dir.join("audit.log")).unwrap();
+    assert_eq!(file, format!("{entry}\n"));
+}
+
+#[test]
+fn keeps_both_of_two_saves_in_one_frame() {
+    let dir = ScratchDir::new("twice");
+    let (mut app, entity) = edited(&dir);
+    let copy = dir.join("copy.txt");
+    save(
+        &mut app,
+        &[
+            SaveDocument { entity, path: None },
+            SaveDocument {
+                entity,
+                path: Some(copy.clone()),
+            },
+        ],
+    );
+
+    let log = app.world.get_resource::<AuditLog>().unwrap();
+    let paths: Vec<_> = log.entries().iter().map(|e| e.path.clone()).collect();
+    assert_eq!(paths, [dir.notes(), copy]);
+    assert!(log.entries()[0].before.is_some());
+    assert_eq!(log.entries()[1].before, None);
+    let file = fs::read_to_string(dir.join("audit.log")).unwrap();
+    assert_eq!(file.lines().count(), 2);
+}
+
+#[test]
+fn appends_to_the_log_of_earlier_sessions() {
+    let dir = ScratchDir::new("append");
+    fs::write(dir.join("audit.log"), "an earlier save\n").unwrap();
+    let (mut app, entity) = edited(&dir);
+    save(&mut app, &[SaveDocument { entity, path: None }]);
+
+    let file = fs::read_to_string(dir.join("audit.log")).unwrap();
+    assert!(file.starts_with("an earlier save\n"));
+    assert_eq!(file.lines().count(), 2);
+}
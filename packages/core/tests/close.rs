@@ -0,0 +1,118 @@
+//! Closing a document with unsaved changes asks about them first, and closed documents
+//! are only freed once nothing would be lost.
+
+mod common;
+
+use bevy::{
+    app::App,
+    ecs::{
+        entity::Entity,
+        event::{Events, ManualEventReader},
+    },
+};
+use common::ScratchDir;
+use dip_core::{
+    document::Document,
+    limbo::{Closed, LimboPlugin},
+    memory::MemoryPressure,
+    shutdown::UnsavedChoice,
+    workspace::{
+        CloseDocument, ResolveUnsavedClose, UnsavedClosePrompt, Workspace, WorkspacePlugin,
+    },
+};
+use std::fs;
+
+/// An app with `document` open.
+fn open(document: Document) -> (App, Entity) {
+    let mut app = common::app();
+    app.add_plugin(WorkspacePlugin)
+        .add_plugin(LimboPlugin)
+        // Sent by the plugins left out.
+        .add_event::<MemoryPressure>();
+    let entity = app.world.spawn().insert(document).id();
+    app.update();
+    (app, entity)
+}
+
+fn close(app: &mut App, entity: Entity) {
+    let discard = false;
+    common::send(app, CloseDocument { entity, discard });
+    app.update();
+}
+
+fn is_open(app: &App, entity: Entity) -> bool {
+    let workspace = app.world.get_resource::<Workspace>().unwrap();
+    workspace.is_open(entity)
+}
+
+fn prompted(app: &App, reader: &mut ManualEventReader<UnsavedClosePrompt>) -> Vec<Entity> {
+    let events = app
+        .world
+        .get_resource::<Events<UnsavedClosePrompt>>()
+        .unwrap();
+    reader.iter(events).map(|e| e.entity).collect()
+}
+
+#[test]
+fn asks_before_closing_unsaved_changes() {
+    let dir = ScratchDir::new("ask");
+    fs::write(dir.notes(), "saved\n").unwrap();
+    let (mut app, entity) = open(common::edited(&dir.notes(), "edited "));
+    let mut prompts = ManualEventReader::default();
+
+    close(&mut app, entity);
+    assert_eq!(prompted(&app, &mut prompts), [entity]);
+    assert!(is_open(&app, entity));
+
+    let choice = UnsavedChoice::Save;
+    common::send(&mut app, ResolveUnsavedClose { entity, choice });
+    for _ in 0..3 {
+        app.update();
+    }
+    assert_eq!(fs::read_to_string(dir.notes()).unwrap(), "edited saved\n");
+    assert!(!is_open(&app, entity));
+    assert!(app.world.get::<Closed>(entity).is_some());
+}
+
+#[test]
+fn discarding_despawns_the_document() {
+    let dir = ScratchDir::new("discard");
+    fs::write(dir.notes(), "saved\n").unwrap();
+    let (mut app, entity) = open(common::edited(&dir.notes(), "edited "));
+
+    close(&mut app, entity);
+    let choice = UnsavedChoice::Discard;
+    common::send(&mut app, ResolveUnsavedClose { entity, choice });
+    for _ in 0..3 {
+        app.update();
+    }
+    assert!(!is_open(&app, entity));
+    assert!(app.world.get_entity(entity).is_none());
+    assert_eq!(fs::read_to_string(dir.notes()).unwrap(), "saved\n");
+}
+
+#[test]
+fn never_frees_unsaved_changes() {
+    let dir = ScratchDir::new("limbo");
+    fs::write(dir.notes(), "saved\n").unwrap();
+    let (mut app, clean) = open(Document::from_path(dir.notes()).unwrap());
+    close(&mut app, clean);
+    // Edited after closing, e.g. by a rename across the workspace.
+    let dirty = app
+        .world
+        .spawn()
+        .insert(Document::new(None, "x".into()))
+        .id();
+    app.update();
+    app.world
+        .entity_mut(dirty)
+        .insert(Closed { at: 0.0 })
+        .get_mut::<Document>()
+        .unwrap()
+        .insert(0, "edited ");
+
+    common::send(&mut app, MemoryPressure { over_by: 1 });
+    app.update();
+    assert!(app.world.get_entity(clean).is_none());
+    assert!(app.world.get::<Closed>(dirty).is_some());
+}
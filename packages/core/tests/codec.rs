@@ -0,0 +1,135 @@
+//! Selections are encoded and decoded in place, and what they are encoded with is told from
+//! how they look.
+
+mod common;
+
+use bevy::{app::App, ecs::entity::Entity};
+use dip_core::{
+    announce::Announcement,
+    codec::{
+        base64_decode, base64_encode, detect, Codec, CodecError, CodecPlugin, Transform,
+        TransformFailed, TransformSelection,
+    },
+    command::RunCommand,
+    control::RevealPosition,
+    cursor::{Cursor, CursorPlugin, Selection},
+    document::Document,
+    format::FormatPlugin,
+    indent::IndentPlugin,
+    text_buffer::TextBuffer,
+    workspace::Workspace,
+};
+
+#[test]
+fn decodes_what_it_encodes() {
+    let text = "naïve <a href=\"x?q=1&r=2\">'quoted'</a>\n\ttab";
+    for codec in Codec::ALL {
+        let encoded = codec.encode(text);
+        assert_eq!(codec.decode(&encoded).as_deref(), Ok(text), "{codec:?}");
+    }
+}
+
+#[test]
+fn encodes_the_way_other_tools_do() {
+    assert_eq!(Codec::Base64.encode("dip!"), "ZGlwIQ==");
+    assert_eq!(Codec::Url.encode("a b/c~"), "a%20b%2Fc~");
+    assert_eq!(Codec::Html.encode("<'&'>"), "&lt;&#39;&amp;&#39;&gt;");
+    assert_eq!(Codec::Json.encode("say \"hi\"\n"), "say \\\"hi\\\"\\n");
+    assert_eq!(
+        Codec::Hex.encode("Hello, world!\0"),
+        "00000000: 4865 6c6c 6f2c 2077 6f72 6c64 2100       Hello, world!."
+    );
+}
+
+#[test]
+fn decodes_the_variants_found_in_the_wild() {
+    // URL-safe, unpadded and wrapped base64.
+    assert_eq!(base64_decode("-_8").unwrap(), [0xfb, 0xff]);
+    assert_eq!(base64_decode("ZGlw\nIQ").unwrap(), b"dip!");
+    assert_eq!(base64_encode(&[0xfb, 0xff]), "+/8=");
+    // A plus stays a plus outside form data.
+    assert_eq!(Codec::Url.decode("a+b%21").unwrap(), "a+b!");
+    assert_eq!(
+        Codec::Html
+            .decode("&#x41;&#66;&nbsp;&unknown; & done")
+            .unwrap(),
+        "AB\u{a0}&unknown; & done"
+    );
+    assert_eq!(Codec::Json.decode("\"quoted\\u0021\"").unwrap(), "quoted!");
+    assert_eq!(Codec::Hex.decode("0x64 0x69\n70").unwrap(), "dip");
+}
+
+#[test]
+fn points_at_what_does_not_decode() {
+    assert_eq!(
+        Codec::Base64.decode("ab*d"),
+        Err(CodecError::Invalid('*', 2))
+    );
+    assert_eq!(Codec::Base64.decode("abcde"), Err(CodecError::Truncated));
+    assert_eq!(Codec::Url.decode("100%"), Err(CodecError::Truncated));
+    assert_eq!(Codec::Url.decode("%zz"), Err(CodecError::Invalid('z', 0)));
+    assert_eq!(Codec::Hex.decode("abc"), Err(CodecError::Truncated));
+    assert_eq!(Codec::Hex.decode("ff fg"), Err(CodecError::Invalid('g', 4)));
+    assert_eq!(Codec::Hex.decode("ff"), Err(CodecError::NotText));
+}
+
+#[test]
+fn detects_encodings_from_how_text_looks() {
+    assert_eq!(detect("SGVsbG8sIHdvcmxkIQ=="), [Codec::Base64]);
+    assert_eq!(detect("name%3Ddip%26x"), [Codec::Url]);
+    assert_eq!(detect("&lt;b&gt;"), [Codec::Html]);
+    assert_eq!(detect("\"line\\nbreak\""), [Codec::Json]);
+    assert_eq!(detect("48656c6c6f"), [Codec::Hex]);
+    // Plain words are not base64, even though they decode.
+    assert_eq!(detect("Encyclopedia"), []);
+}
+
+/// An app with `text` open and the cursor at `cursor`.
+fn open(text: &str, cursor: usize) -> (App, Entity) {
+    let mut app = common::app();
+    app.init_resource::<Workspace>()
+        .add_plugin(IndentPlugin)
+        .add_plugin(FormatPlugin)
+        .add_plugin(CursorPlugin)
+        .add_plugin(CodecPlugin)
+        // Sent by the plugins left out.
+        .add_event::<RunCommand>()
+        .add_event::<Announcement>()
+        .add_event::<RevealPosition>();
+    let document = Document::new(None, TextBuffer::from(text));
+    let entity = app
+        .world
+        .spawn()
+        .insert(document)
+        .insert(Cursor::at(cursor))
+        .insert(Selection { anchor: cursor })
+        .id();
+    app.update();
+    (app, entity)
+}
+
+fn transform(app: &mut App, entity: Entity, transform: Transform) {
+    common::send(app, TransformSelection { entity, transform });
+    app.update();
+}
+
+#[test]
+fn decodes_the_word_at_the_cursor_and_selects_it() {
+    let (mut app, entity) = open("token: SGVsbG8sIHdvcmxkIQ== end", 10);
+    transform(&mut app, entity, Transform::DecodeDetected);
+    assert_eq!(common::text(&app, entity), "token: Hello, world! end");
+    let cursor = app.world.get::<Cursor>(entity).unwrap().offset;
+    let anchor = app.world.get::<Selection>(entity).unwrap().anchor;
+    assert_eq!((anchor.min(cursor), anchor.max(cursor)), (7, 20));
+
+    transform(&mut app, entity, Transform::Encode(Codec::Url));
+    assert_eq!(common::text(&app, entity), "token: Hello%2C%20world%21 end");
+}
+
+#[test]
+fn leaves_the_text_alone_when_it_does_not_decode() {
+    let (mut app, entity) = open("not*base64", 0);
+    transform(&mut app, entity, Transform::Decode(Codec::Base64));
+    assert!(common::sent::<TransformFailed>(&app));
+    assert_eq!(common::text(&app, entity), "not*base64");
+}
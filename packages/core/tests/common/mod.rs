@@ -0,0 +1,98 @@
+//! Scratch directories and shortcuts shared by the integration tests. Every test file uses
+//! some of them, so the others are dead code there.
+
+#![allow(dead_code)]
+
+use bevy::{
+    app::App,
+    core::CorePlugin,
+    ecs::{entity::Entity, event::Events},
+};
+use dip_core::{
+    document::{Document, DocumentPlugin},
+    memory::EvictCache,
+    pipeline::PipelinePlugin,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A directory of its own for each test, removed when it ends.
+pub struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    /// `name` tells apart the tests of one file, which the directory is named after too.
+    pub fn new(name: &str) -> Self {
+        let test = env!("CARGO_CRATE_NAME");
+        let dir = std::env::temp_dir().join(format!("dip-{test}-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+
+    /// The file most tests edit.
+    pub fn notes(&self) -> PathBuf {
+        self.join("notes.txt")
+    }
+
+    /// Files left in the directory, e.g. a temporary file a save forgot.
+    pub fn files(&self) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(&self.0)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// An app with documents and nothing else, for tests to add the plugins they are about.
+pub fn app() -> App {
+    let mut app = App::new();
+    app.add_plugin(CorePlugin)
+        .add_plugin(PipelinePlugin)
+        .add_plugin(DocumentPlugin)
+        // Sent by the plugins left out.
+        .add_event::<EvictCache>();
+    app
+}
+
+/// Sends `event` for the next frame to pick up.
+pub fn send<T: Send + Sync + 'static>(app: &mut App, event: T) {
+    app.world
+        .get_resource_mut::<Events<T>>()
+        .unwrap()
+        .send(event);
+}
+
+/// Whether `T` was sent in the last frames.
+pub fn sent<T: Send + Sync + 'static>(app: &App) -> bool {
+    !app.world.get_resource::<Events<T>>().unwrap().is_empty()
+}
+
+/// The file at `path` opened with `typed` typed at its start.
+pub fn edited(path: &Path, typed: &str) -> Document {
+    let mut document = Document::from_path(path).unwrap();
+    document.insert(0, typed);
+    document
+}
+
+pub fn text(app: &App, entity: Entity) -> String {
+    let document = app.world.get::<Document>(entity).unwrap();
+    document.buffer().to_string()
+}
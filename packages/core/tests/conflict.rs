@@ -0,0 +1,197 @@
+//! Saves never overwrite what someone else wrote to the file meanwhile, and saves failing
+//! in a way that may pass are tried again.
+
+mod common;
+
+use bevy::{
+    app::App,
+    ecs::{entity::Entity, event::Events},
+};
+use common::{sent, text, ScratchDir};
+use dip_core::{
+    conflict::{Conflict, ConflictPlugin, Resolution, ResolveConflict},
+    document::{
+        Document, DocumentSaved, SaveConflict, SaveDocument, SaveRetries, SaveRetrySettings,
+        UndoDocument,
+    },
+};
+use std::{fs, thread, time::Duration};
+
+/// An app with `notes.txt` in `dir` open and edited.
+fn edited(dir: &ScratchDir) -> (App, Entity) {
+    let mut app = common::app();
+    app.add_plugin(ConflictPlugin);
+
+    fs::write(dir.notes(), "saved\n").unwrap();
+    let document = common::edited(&dir.notes(), "mine ");
+    let entity = app.world.spawn().insert(document).id();
+    app.update();
+    (app, entity)
+}
+
+fn send<T: Send + Sync + 'static>(app: &mut App, event: T) {
+    common::send(app, event);
+    app.update();
+}
+
+fn save(app: &mut App, entity: Entity) {
+    send(app, SaveDocument { entity, path: None });
+}
+
+fn resolve(app: &mut App, entity: Entity, resolution: Resolution) {
+    send(app, ResolveConflict { entity, resolution });
+}
+
+#[test]
+fn refuses_to_save_over_a_file_changed_on_disk() {
+    let dir = ScratchDir::new("refuse");
+    let (mut app, entity) = edited(&dir);
+    fs::write(dir.notes(), "their saved\n").unwrap();
+    save(&mut app, entity);
+
+    assert!(sent::<SaveConflict>(&app));
+    assert!(!sent::<DocumentSaved>(&app));
+    assert_eq!(fs::read_to_string(dir.notes()).unwrap(), "their saved\n");
+    assert!(app.world.get::<Document>(entity).unwrap().is_dirty());
+}
+
+#[test]
+fn saves_over_a_file_deleted_meanwhile() {
+    let dir = ScratchDir::new("deleted");
+    let (mut app, entity) = edited(&dir);
+    fs::remove_file(dir.notes()).unwrap();
+    save(&mut app, entity);
+
+    assert!(!sent::<SaveConflict>(&app));
+    assert_eq!(fs::read_to_string(dir.notes()).unwrap(), "mine saved\n");
+}
+
+#[test]
+fn keeping_mine_saves_over_theirs() {
+    let dir = ScratchDir::new("mine");
+    let (mut app, entity) = edited(&dir);
+    fs::write(dir.notes(), "their saved\n").unwrap();
+    save(&mut app, entity);
+    resolve(&mut app, entity, Resolution::KeepMine);
+
+    assert!(sent::<DocumentSaved>(&app));
+    assert_eq!(fs::read_to_string(dir.notes()).unwrap(), "mine saved\n");
+    assert!(!app.world.get::<Document>(entity).unwrap().is_dirty());
+}
+
+#[test]
+fn taking_theirs_reloads_and_can_be_undone() {
+    let dir = ScratchDir::new("theirs");
+    let (mut app, entity) = edited(&dir);
+    fs::write(dir.notes(), "their saved\n").unwrap();
+    save(&mut app, entity);
+    resolve(&mut app, entity, Resolution::TakeTheirs);
+
+    assert_eq!(text(&app, entity), "their saved\n");
+    let document = app.world.get::<Document>(entity).unwrap();
+    assert!(!document.is_dirty());
+    assert!(!document.changed_on_disk());
+
+    send(&mut app, UndoDocument { entity });
+    assert_eq!(text(&app, entity), "mine saved\n");
+    assert_eq!(fs::read_to_string(dir.notes()).unwrap(), "their saved\n");
+}
+
+#[test]
+fn comparing_puts_the_differences_on_the_document() {
+    let dir = ScratchDir::new("compare");
+    let (mut app, entity) = edited(&dir);
+    fs::write(dir.notes(), "saved\ntheirs\n").unwrap();
+    resolve(&mut app, entity, Resolution::Compare);
+
+    for _ in 0..200 {
+        if app.world.get::<Conflict>(entity).is_some() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+        app.update();
+    }
+    let conflict = app.world.get::<Conflict>(entity).expect("compared");
+    assert_eq!(conflict.path, dir.notes());
+    assert_eq!(conflict.theirs, "saved\ntheirs\n");
+    assert_eq!(conflict.hunks.len(), 1);
+    assert_eq!(text(&app, entity), "mine saved\n");
+}
+
+#[test]
+fn resolving_another_way_drops_a_late_comparison() {
+    let dir = ScratchDir::new("late");
+    let (mut app, entity) = edited(&dir);
+    fs::write(dir.notes(), "their saved\n").unwrap();
+    let mut events = app
+        .world
+        .get_resource_mut::<Events<ResolveConflict>>()
+        .unwrap();
+    for resolution in [Resolution::Compare, Resolution::KeepMine] {
+        events.send(ResolveConflict { entity, resolution });
+    }
+    app.update();
+
+    for _ in 0..20 {
+        thread::sleep(Duration::from_millis(10));
+        app.update();
+    }
+    assert!(app.world.get::<Conflict>(entity).is_none());
+    assert_eq!(fs::read_to_string(dir.notes()).unwrap(), "mine saved\n");
+}
+
+#[test]
+fn retries_are_sent_again_until_they_run_out() {
+    let dir = ScratchDir::new("retry");
+    let (mut app, entity) = edited(&dir);
+    let settings = SaveRetrySettings {
+        attempts: 2,
+        backoff: 0.0,
+    };
+    let save = SaveDocument { entity, path: None };
+    let mut retries = app.world.get_resource_mut::<SaveRetries>().unwrap();
+    assert!(retries.schedule(&save, &settings, 0.0));
+    assert!(retries.schedule(&save, &settings, 0.0));
+    assert!(!retries.schedule(&save, &settings, 0.0));
+    assert_eq!(retries.attempts(entity), 2);
+
+    app.update();
+    assert!(sent::<DocumentSaved>(&app));
+    assert_eq!(fs::read_to_string(dir.notes()).unwrap(), "mine saved\n");
+    let retries = app.world.get_resource::<SaveRetries>().unwrap();
+    assert_eq!(retries.attempts(entity), 0);
+}
+
+#[test]
+fn retries_back_off() {
+    let dir = ScratchDir::new("backoff");
+    let (mut app, entity) = edited(&dir);
+    let settings = SaveRetrySettings {
+        attempts: 3,
+        backoff: 60.0,
+    };
+    let save = SaveDocument { entity, path: None };
+    let mut retries = app.world.get_resource_mut::<SaveRetries>().unwrap();
+    assert!(retries.schedule(&save, &settings, 0.0));
+
+    app.update();
+    app.update();
+    assert!(!sent::<DocumentSaved>(&app));
+    assert_eq!(fs::read_to_string(dir.notes()).unwrap(), "saved\n");
+}
+
+#[test]
+fn conflicts_reset_the_retries() {
+    let dir = ScratchDir::new("no-retry");
+    let (mut app, entity) = edited(&dir);
+    let settings = SaveRetrySettings::default();
+    let save = SaveDocument { entity, path: None };
+    let mut retries = app.world.get_resource_mut::<SaveRetries>().unwrap();
+    assert!(retries.schedule(&save, &settings, 0.0));
+    fs::write(dir.notes(), "their saved\n").unwrap();
+    send(&mut app, save);
+
+    assert!(sent::<SaveConflict>(&app));
+    let retries = app.world.get_resource::<SaveRetries>().unwrap();
+    assert_eq!(retries.attempts(entity), 0);
+}
@@ -0,0 +1,94 @@
+//! Edits only lay out again the lines they touch, and scrolling lays out what comes into
+//! view.
+
+mod common;
+
+use bevy::{
+    app::App,
+    ecs::{entity::Entity, event::Events},
+};
+use dip_core::{
+    announce::Announcement,
+    control::RevealPosition,
+    cursor::{Cursor, CursorPlugin, Selection, TypeText},
+    damage::{DamagePlugin, Redraw, RedrawMetrics, VisibleLines},
+    document::Document,
+    format::FormatPlugin,
+    indent::IndentPlugin,
+    text_buffer::TextBuffer,
+};
+use std::ops::Range;
+
+/// A hundred lines in view, with the cursor at the start of the 50th, laid out once.
+fn shown() -> (App, Entity) {
+    let mut app = common::app();
+    app.add_plugin(DamagePlugin)
+        .add_plugin(IndentPlugin)
+        .add_plugin(FormatPlugin)
+        .add_plugin(CursorPlugin)
+        // Sent by the plugins left out.
+        .add_event::<Announcement>()
+        .add_event::<RevealPosition>();
+    let text = "a line\n".repeat(200);
+    let document = Document::new(None, TextBuffer::from(text.as_str()));
+    let cursor = document.buffer().line_start(50);
+    let entity = app
+        .world
+        .spawn()
+        .insert(document)
+        .insert(Cursor::at(cursor))
+        .insert(Selection { anchor: cursor })
+        .id();
+    app.update();
+    // Shown once the document is, as a view does.
+    app.world.entity_mut(entity).insert(VisibleLines(0..100));
+    app.update();
+    (app, entity)
+}
+
+fn type_text(app: &mut App, entity: Entity, text: &str) {
+    let text = text.to_string();
+    common::send(app, TypeText { entity, text });
+    app.update();
+}
+
+/// Lines redrawn in the last frame. Events of the one before are kept too, so only the
+/// last is looked at.
+fn redrawn(app: &App) -> Vec<Range<usize>> {
+    let events = app.world.get_resource::<Events<Redraw>>().unwrap();
+    let last = events.get_reader().iter(events).last().cloned();
+    last.expect("nothing was redrawn").lines
+}
+
+#[test]
+fn a_typed_character_damages_its_line() {
+    let (mut app, entity) = shown();
+    assert_eq!(
+        app.world
+            .get_resource::<RedrawMetrics>()
+            .unwrap()
+            .last_frame,
+        100
+    );
+
+    type_text(&mut app, entity, "x");
+    assert_eq!(redrawn(&app), vec![50..51]);
+    let metrics = app.world.get_resource::<RedrawMetrics>().unwrap();
+    assert_eq!(metrics.last_frame, 1);
+    assert_eq!(metrics.peak, 100);
+}
+
+#[test]
+fn a_line_break_damages_both_halves() {
+    let (mut app, entity) = shown();
+    type_text(&mut app, entity, "\n");
+    assert_eq!(redrawn(&app), vec![50..52]);
+}
+
+#[test]
+fn scrolling_lays_out_the_lines_shown() {
+    let (mut app, entity) = shown();
+    app.world.get_mut::<VisibleLines>(entity).unwrap().0 = 100..150;
+    app.update();
+    assert_eq!(redrawn(&app), vec![100..150]);
+}
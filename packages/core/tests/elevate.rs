@@ -0,0 +1,79 @@
+//! Saves retried as administrator go through a helper, here one that isn't elevating.
+
+#![cfg(unix)]
+
+mod common;
+
+use bevy::{
+    app::App,
+    ecs::{entity::Entity, event::Events},
+};
+use common::ScratchDir;
+use dip_core::{
+    audit::{AuditLog, AuditPlugin, FileDigest},
+    document::{Document, DocumentSaveFailed, DocumentSaved},
+    elevate::{ElevatePlugin, ElevateSettings, SaveElevated},
+};
+use std::{fs, path::Path, thread, time::Duration};
+
+/// Saves `path` edited through `helper` and runs frames until it is saved.
+fn save_elevated(helper: &[&str], path: &Path) -> (App, Entity, bool) {
+    let mut app = common::app();
+    app.add_plugin(AuditPlugin).add_plugin(ElevatePlugin);
+    app.world.get_resource_mut::<AuditLog>().unwrap().file = None;
+    app.world
+        .get_resource_mut::<ElevateSettings>()
+        .unwrap()
+        .helper = Some(helper.iter().map(Into::into).collect());
+
+    let document = common::edited(path, "edited ");
+    let entity = app.world.spawn().insert(document).id();
+    common::send(
+        &mut app,
+        SaveElevated {
+            entity,
+            path: path.to_path_buf(),
+        },
+    );
+    for _ in 0..500 {
+        app.update();
+        let saved = app.world.get_resource::<Events<DocumentSaved>>().unwrap();
+        let failed = app
+            .world
+            .get_resource::<Events<DocumentSaveFailed>>()
+            .unwrap();
+        match (saved.is_empty(), failed.is_empty()) {
+            (true, true) => thread::sleep(Duration::from_millis(10)),
+            (saved, _) => return (app, entity, !saved),
+        }
+    }
+    panic!("{} was not saved", path.display());
+}
+
+#[test]
+fn copies_the_document_over_the_file_and_audits_it() {
+    let dir = ScratchDir::new("copy");
+    let path = dir.join("hosts");
+    fs::write(&path, "saved\n").unwrap();
+    let before = FileDigest::read(&path).unwrap();
+
+    let (app, entity, saved) = save_elevated(&["env"], &path);
+    assert!(saved);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "edited saved\n");
+    assert!(!app.world.get::<Document>(entity).unwrap().is_dirty());
+    let log = app.world.get_resource::<AuditLog>().unwrap();
+    assert_eq!(log.entries().len(), 1);
+    assert_eq!(log.entries()[0].before, Some(before));
+}
+
+#[test]
+fn failing_helpers_leave_the_file_alone() {
+    let dir = ScratchDir::new("fail");
+    let path = dir.join("hosts");
+    fs::write(&path, "saved\n").unwrap();
+
+    let (app, entity, saved) = save_elevated(&["false"], &path);
+    assert!(!saved);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "saved\n");
+    assert!(app.world.get::<Document>(entity).unwrap().is_dirty());
+}
@@ -0,0 +1,106 @@
+//! The edits of a document in a vault are only exported into a vault, sealed.
+
+mod common;
+
+use bevy::{
+    app::App,
+    ecs::{entity::Entity, event::Events},
+};
+use common::ScratchDir;
+use dip_core::{
+    command::{CoreCommand, RunCommand, UICommand},
+    document::Document,
+    export::{ExportPlugin, ExportSession, SessionExportFailed, SessionExported, SessionFormat},
+    playback::PlaybackPlugin,
+    render::Viewport,
+    vault::{self, Vaults, VAULT_FILE},
+    workspace::Workspace,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// `dir` with a `vault` directory in it.
+fn vault(dir: &ScratchDir) -> PathBuf {
+    let vault = dir.join("vault");
+    fs::create_dir_all(&vault).unwrap();
+    vault
+}
+
+fn app() -> App {
+    let mut app = common::app();
+    app.add_plugin(PlaybackPlugin)
+        .add_plugin(ExportPlugin)
+        .init_resource::<Vaults>()
+        .init_resource::<Workspace>()
+        .init_resource::<Viewport>()
+        // Sent by the plugins left out.
+        .add_event::<RunCommand>()
+        .add_event::<CoreCommand>()
+        .add_event::<UICommand>();
+    app
+}
+
+/// Exports the edits of `entity` to `path` and returns whether it worked.
+fn export(app: &mut App, entity: Entity, path: Option<&Path>) -> bool {
+    common::send(
+        app,
+        ExportSession {
+            entity,
+            format: SessionFormat::Diffs,
+            path: path.map(Path::to_path_buf),
+        },
+    );
+    app.update();
+    app.update();
+    let failed = app
+        .world
+        .get_resource::<Events<SessionExportFailed>>()
+        .unwrap();
+    let mut reader = failed.get_reader();
+    reader.iter(failed).count() == 0
+}
+
+#[test]
+fn seals_exports_of_encrypted_documents() {
+    let dir = ScratchDir::new("sealed");
+    let mut app = app();
+    let mut vaults = app.world.get_resource_mut::<Vaults>().unwrap();
+    vaults.unlock(&vault(&dir), "secret").unwrap();
+    let notes = vault(&dir).join("notes.txt");
+    let key = vaults.key_for(&notes).unwrap();
+    let mut document = Document::new(Some(notes), "secret\n".into());
+    document.encrypt_with(key);
+    let entity = app.world.spawn().insert(document).id();
+    app.update();
+
+    let outside = dir.join("edits.md");
+    assert!(!export(&mut app, entity, Some(&outside)));
+    assert!(!outside.exists());
+
+    let inside = vault(&dir).join("edits.md");
+    assert!(export(&mut app, entity, Some(&inside)));
+    assert!(vault::is_encrypted(&fs::read(&inside).unwrap()));
+
+    assert!(export(&mut app, entity, None));
+    let exported = app.world.get_resource::<Events<SessionExported>>().unwrap();
+    let mut reader = exported.get_reader();
+    let untitled = reader.iter(exported).last().unwrap().document.unwrap();
+    assert!(app.world.get::<Document>(untitled).unwrap().is_encrypted());
+}
+
+#[test]
+fn refuses_to_export_into_a_locked_vault() {
+    let dir = ScratchDir::new("locked");
+    fs::write(vault(&dir).join(VAULT_FILE), "not unlocked").unwrap();
+    let mut app = app();
+    let document = Document::new(None, "plain\n".into());
+    let entity = app.world.spawn().insert(document).id();
+    app.update();
+
+    let inside = vault(&dir).join("edits.md");
+    assert!(!export(&mut app, entity, Some(&inside)));
+    assert!(!inside.exists());
+    assert!(export(&mut app, entity, Some(&dir.join("edits.md"))));
+}
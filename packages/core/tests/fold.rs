@@ -0,0 +1,164 @@
+//! Folds hide all but the header of a region, map document lines to the lines on screen,
+//! and open again when a cursor or an edit lands in them.
+
+mod common;
+
+use bevy::{app::App, ecs::entity::Entity};
+use dip_core::{
+    announce::Announcement,
+    command::RunCommand,
+    control::RevealPosition,
+    cursor::{Cursor, CursorPlugin, Selection, TypeText},
+    damage::DecorationsChanged,
+    document::Document,
+    fold::{indentation_regions, ChangeFolds, FoldChange, FoldPlugin, Folds},
+    format::FormatPlugin,
+    indent::IndentPlugin,
+    text_buffer::TextBuffer,
+    workspace::Workspace,
+};
+use std::ops::Range;
+
+const SOURCE: &str = "fn main() {
+    if ready {
+        go();
+    }
+
+}
+fn other() {}
+";
+
+#[test]
+fn finds_regions_by_indentation() {
+    let buffer = TextBuffer::from(SOURCE);
+    // The blank line at the end of `main` is left out of it.
+    assert_eq!(indentation_regions(&buffer), [0..4, 1..3]);
+}
+
+#[test]
+fn maps_lines_to_the_lines_on_screen() {
+    let mut folds = Folds::default();
+    assert!(folds.fold(1..4));
+    assert!(folds.fold(6..9));
+    assert!(!folds.fold(6..9) && !folds.fold(5..6));
+
+    assert!(folds.is_hidden(2) && !folds.is_hidden(1) && !folds.is_hidden(4));
+    assert_eq!(
+        (0..10).map(|l| folds.visible_line(l)).collect::<Vec<_>>(),
+        [0, 1, 1, 1, 2, 3, 4, 4, 4, 5]
+    );
+    assert_eq!(
+        (0..6).map(|v| folds.document_line(v)).collect::<Vec<_>>(),
+        [0, 1, 4, 5, 6, 9]
+    );
+    assert_eq!(folds.visible_line_count(10), 6);
+    assert_eq!(folds.visible_ranges(0..10), [0..2, 4..7, 9..10]);
+    assert_eq!(folds.shown_as(3), 1);
+    assert_eq!(folds.line_above(4), Some(1));
+    assert_eq!(folds.line_below(1, 10), Some(4));
+    assert_eq!(folds.line_below(6, 9), None);
+}
+
+#[test]
+fn nested_folds_hide_along_with_the_outer_one() {
+    let mut folds = Folds::default();
+    folds.fold(2..4);
+    folds.fold(0..6);
+    assert_eq!(folds.folded(), [0..6, 2..4]);
+    assert_eq!(folds.visible_line_count(8), 3);
+
+    assert!(folds.unfold(0..0));
+    assert_eq!(folds.folded(), vec![2..4; 1]);
+    assert_eq!(folds.visible_line_count(8), 7);
+    assert!(folds.unfold_all() && folds.is_empty());
+    assert!(!folds.unfold(2..3));
+}
+
+/// An app with [`SOURCE`] open, the cursor on the `go();` line.
+fn open() -> (App, Entity) {
+    let mut app = common::app();
+    app.init_resource::<Workspace>()
+        .add_plugin(IndentPlugin)
+        .add_plugin(FormatPlugin)
+        .add_plugin(CursorPlugin)
+        .add_plugin(FoldPlugin)
+        // Sent by the plugins left out.
+        .add_event::<RunCommand>()
+        .add_event::<DecorationsChanged>()
+        .add_event::<Announcement>()
+        .add_event::<RevealPosition>();
+    let cursor = SOURCE.find("go").unwrap();
+    let entity = app
+        .world
+        .spawn()
+        .insert(Document::new(None, TextBuffer::from(SOURCE)))
+        .insert(Cursor::at(cursor))
+        .insert(Selection { anchor: cursor })
+        .insert(Folds::default())
+        .id();
+    app.update();
+    (app, entity)
+}
+
+fn change(app: &mut App, entity: Entity, change: FoldChange) {
+    common::send(app, ChangeFolds { entity, change });
+    app.update();
+}
+
+fn folded(app: &App, entity: Entity) -> Vec<Range<usize>> {
+    app.world.get::<Folds>(entity).unwrap().folded().to_vec()
+}
+
+fn cursor(app: &App, entity: Entity) -> usize {
+    app.world.get::<Cursor>(entity).unwrap().offset
+}
+
+#[test]
+fn toggles_the_region_around_the_cursor_and_moves_it_out() {
+    let (mut app, entity) = open();
+    change(&mut app, entity, FoldChange::Toggle);
+    assert_eq!(folded(&app, entity), vec![1..3; 1]);
+    // Hidden, so it went to the end of the header.
+    assert_eq!(
+        cursor(&app, entity),
+        SOURCE.find(" {\n        go").unwrap() + 2
+    );
+
+    change(&mut app, entity, FoldChange::Toggle);
+    assert!(folded(&app, entity).is_empty());
+    change(&mut app, entity, FoldChange::FoldAll);
+    assert_eq!(folded(&app, entity), [0..4, 1..3]);
+    change(&mut app, entity, FoldChange::UnfoldAll);
+    assert!(folded(&app, entity).is_empty());
+}
+
+#[test]
+fn opens_when_a_cursor_or_an_edit_lands_in_it() {
+    let (mut app, entity) = open();
+    change(&mut app, entity, FoldChange::Fold(Some(0..4)));
+    assert_eq!(folded(&app, entity), vec![0..4; 1]);
+
+    // Going to a hidden line, e.g. a search result, shows it.
+    let go = SOURCE.find("go").unwrap();
+    *app.world.get_mut::<Cursor>(entity).unwrap() = Cursor::at(go);
+    app.update();
+    assert!(folded(&app, entity).is_empty());
+
+    // A line break on the header moves the hidden lines away from it.
+    change(&mut app, entity, FoldChange::Fold(Some(0..4)));
+    assert_eq!(cursor(&app, entity), SOURCE.find('\n').unwrap());
+    let text = "\n".to_string();
+    common::send(&mut app, TypeText { entity, text });
+    app.update();
+    assert!(folded(&app, entity).is_empty());
+
+    // Edits above a fold move it along.
+    change(&mut app, entity, FoldChange::Fold(Some(2..5)));
+    *app.world.get_mut::<Cursor>(entity).unwrap() = Cursor::at(0);
+    app.world.get_mut::<Selection>(entity).unwrap().anchor = 0;
+    app.update();
+    let text = "\n".to_string();
+    common::send(&mut app, TypeText { entity, text });
+    app.update();
+    assert_eq!(folded(&app, entity), vec![3..6; 1]);
+}
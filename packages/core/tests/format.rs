@@ -0,0 +1,66 @@
+//! Typing formats the line it ends in code, and leaves prose alone.
+
+mod common;
+
+use bevy::{app::App, ecs::entity::Entity};
+use dip_core::{
+    announce::Announcement,
+    control::RevealPosition,
+    cursor::{Cursor, CursorPlugin, Selection, TypeText},
+    document::Document,
+    format::FormatPlugin,
+    indent::IndentPlugin,
+    text_buffer::TextBuffer,
+};
+
+/// An app with `text` open as a file named `name` and the cursor at its end.
+fn open(name: &str, text: &str) -> (App, Entity) {
+    let mut app = common::app();
+    app.add_plugin(IndentPlugin)
+        .add_plugin(FormatPlugin)
+        .add_plugin(CursorPlugin)
+        // Sent by the plugins left out.
+        .add_event::<Announcement>()
+        .add_event::<RevealPosition>();
+    let document = Document::new(Some(name.into()), TextBuffer::from(text));
+    let entity = app
+        .world
+        .spawn()
+        .insert(document)
+        .insert(Cursor::at(text.len()))
+        .insert(Selection { anchor: text.len() })
+        .id();
+    app.update();
+    (app, entity)
+}
+
+fn typed(name: &str, text: &str, typed: &str) -> String {
+    let (mut app, entity) = open(name, text);
+    common::send(
+        &mut app,
+        TypeText {
+            entity,
+            text: typed.to_string(),
+        },
+    );
+    app.update();
+    common::text(&app, entity)
+}
+
+#[test]
+fn line_breaks_trim_the_line_they_end_in_code() {
+    assert_eq!(typed("main.rs", "let a = 1;  ", "\n"), "let a = 1;\n");
+    assert_eq!(typed("main.py", "a = 1  ", "\n"), "a = 1\n");
+}
+
+#[test]
+fn semicolons_drop_the_spaces_before_them_in_code() {
+    assert_eq!(typed("main.js", "call()  ", ";"), "call();");
+}
+
+#[test]
+fn prose_keeps_its_spaces() {
+    // Two trailing spaces break the line in Markdown.
+    assert_eq!(typed("notes.md", "first  ", "\n"), "first  \n");
+    assert_eq!(typed("notes.txt", "a list  ", ";"), "a list  ;");
+}
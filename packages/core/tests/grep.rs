@@ -0,0 +1,120 @@
+//! Saving a grep buffer writes its edited lines to their files, the way any save would.
+
+mod common;
+
+use bevy::{
+    app::App,
+    ecs::{entity::Entity, event::Events},
+};
+use common::{send, ScratchDir};
+use dip_core::{
+    audit::{AuditLog, AuditPlugin},
+    document::{Document, DocumentSaved, SaveConflict, SaveDocument},
+    elevate::SaveElevated,
+    grep_buffer::{GrepBuffer, GrepBufferPlugin, OpenGrepBuffer},
+    workspace::Workspace,
+    workspace_search::{FileMatches, SearchWorkspace, WorkspaceSearchFinished},
+};
+use std::{fs, time::Duration};
+
+/// An app with a grep buffer for "two" in `notes.txt`, found by a search of `dir`.
+fn grepped(dir: &ScratchDir) -> (App, Entity) {
+    let mut app = common::app();
+    app.add_plugin(AuditPlugin)
+        .add_plugin(GrepBufferPlugin)
+        .insert_resource(Workspace::folder(dir.path()))
+        // Sent by the plugins left out.
+        .add_event::<SaveElevated>()
+        .add_event::<SearchWorkspace>()
+        .add_event::<FileMatches>()
+        .add_event::<WorkspaceSearchFinished>();
+    app.world.get_resource_mut::<AuditLog>().unwrap().file = None;
+
+    fs::write(dir.notes(), "one\ntwo\n").unwrap();
+    let query = "two".to_string();
+    send(
+        &mut app,
+        OpenGrepBuffer {
+            query: query.clone(),
+        },
+    );
+    app.update();
+    let root = app.world.get_resource::<Workspace>().unwrap().roots()[0].id;
+    send(
+        &mut app,
+        FileMatches {
+            root,
+            path: dir.notes(),
+            ranges: vec![4..7; 1],
+        },
+    );
+    send(
+        &mut app,
+        WorkspaceSearchFinished {
+            query,
+            files_searched: 1,
+            elapsed: Duration::ZERO,
+        },
+    );
+    app.update();
+
+    let mut greps = app.world.query::<(Entity, &GrepBuffer)>();
+    let (entity, _) = greps.iter(&app.world).next().expect("a grep buffer");
+    (app, entity)
+}
+
+/// Replaces "two" in the grep buffer with "2" and saves it.
+fn edit_and_save(app: &mut App, entity: Entity) {
+    let mut document = app.world.get_mut::<Document>(entity).unwrap();
+    let start = document.buffer().to_string().rfind("two").unwrap();
+    document.delete(start..start + 3);
+    document.insert(start, "2");
+    send(app, SaveDocument { entity, path: None });
+    app.update();
+}
+
+fn documents(app: &mut App) -> usize {
+    app.world.query::<&Document>().iter(&app.world).count()
+}
+
+#[test]
+fn writes_to_files_that_are_not_open_through_a_save() {
+    let dir = ScratchDir::new("closed");
+    let (mut app, entity) = grepped(&dir);
+    edit_and_save(&mut app, entity);
+
+    assert_eq!(fs::read_to_string(dir.notes()).unwrap(), "one\n2\n");
+    let log = app.world.get_resource::<AuditLog>().unwrap();
+    let paths: Vec<_> = log.entries().iter().map(|e| e.path.clone()).collect();
+    assert_eq!(paths, [dir.notes()]);
+    // The file was only opened for the save.
+    assert_eq!(documents(&mut app), 1);
+}
+
+#[test]
+fn saves_open_files_unless_they_changed_on_disk() {
+    let dir = ScratchDir::new("open");
+    let (mut app, entity) = grepped(&dir);
+    let document = Document::from_path(dir.notes()).unwrap();
+    let open = app.world.spawn().insert(document).id();
+    fs::write(dir.notes(), "one\ntwo\nthree\n").unwrap();
+    edit_and_save(&mut app, entity);
+
+    let conflicts = app.world.get_resource::<Events<SaveConflict>>().unwrap();
+    let mut reader = conflicts.get_reader();
+    assert_eq!(
+        reader.iter(conflicts).map(|e| e.entity).collect::<Vec<_>>(),
+        [open]
+    );
+    assert!(app
+        .world
+        .get_resource::<Events<DocumentSaved>>()
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        fs::read_to_string(dir.notes()).unwrap(),
+        "one\ntwo\nthree\n"
+    );
+    let document = app.world.get::<Document>(open).unwrap();
+    assert_eq!(document.buffer().to_string(), "one\n2\n");
+}
@@ -0,0 +1,101 @@
+//! Line breaks and closing brackets are indented by the indents query of the grammar.
+
+mod common;
+
+use bevy::{app::App, ecs::entity::Entity};
+use dip_core::{
+    damage::DecorationsChanged,
+    document::Document,
+    indent::{self, IndentRules, IndentUnit, SyntaxContext},
+    syntax::{Syntax, SyntaxPlugin},
+    text_buffer::TextBuffer,
+};
+
+/// An app with `text` open as a file named `name`, parsed.
+fn parsed(name: &str, text: &str) -> (App, Entity) {
+    let mut app = common::app();
+    app.add_plugin(SyntaxPlugin)
+        // Sent by the plugins left out.
+        .add_event::<DecorationsChanged>();
+    let document = Document::new(Some(name.into()), TextBuffer::from(text));
+    let entity = app.world.spawn().insert(document).id();
+    app.update();
+    (app, entity)
+}
+
+/// What typing a line break at the `|` of `text` inserts.
+fn newline(name: &str, text: &str) -> String {
+    let offset = text.find('|').unwrap();
+    let (app, entity) = parsed(name, &text.replace('|', ""));
+    let buffer = app.world.get::<Document>(entity).unwrap().buffer();
+    let syntax = app.world.get::<Syntax>(entity).unwrap();
+    let scope = syntax.indent_scope(buffer, offset).expect("inside a block");
+    let (text, cursor) = indent::newline_in(buffer, offset, &scope, IndentUnit::Spaces(4));
+    format!("{}|{}", &text[..cursor], &text[cursor..])
+}
+
+#[test]
+fn indents_inside_blocks() {
+    assert_eq!(newline("main.rs", "fn main() {|}"), "\n    |\n");
+    assert_eq!(
+        newline("main.rs", "fn main() {\n    let a = 1;|\n}\n"),
+        "\n    |"
+    );
+    assert_eq!(
+        newline("main.rs", "fn main() {\n    call(a,|\n    );\n}\n"),
+        "\n        |"
+    );
+    assert_eq!(
+        newline("main.ts", "class A {\n  f() {|}\n}\n"),
+        "\n      |\n  "
+    );
+}
+
+#[test]
+fn moves_the_closing_bracket_to_the_level_of_the_block() {
+    assert_eq!(newline("main.rs", "fn main() {\n    let a = 1;|}\n"), "\n|");
+}
+
+#[test]
+fn closing_brackets_align_with_the_opening_line() {
+    let text = "fn main() {\n    if a {\n        b();\n        \n    }\n}\n";
+    let offset = text.find("        \n").unwrap() + 8;
+    let (app, entity) = parsed("main.rs", text);
+    let buffer = app.world.get::<Document>(entity).unwrap().buffer();
+    let syntax = app.world.get::<Syntax>(entity).unwrap();
+    let scope = syntax.indent_scope(buffer, offset).unwrap();
+    assert!(scope.is_closed_by(buffer, '}', &IndentRules::default()));
+    assert!(!scope.is_closed_by(buffer, ')', &IndentRules::default()));
+    let start = offset - 8;
+    assert_eq!(
+        indent::outdent_in(buffer, offset, &scope),
+        Some((start..offset, "    ".to_string()))
+    );
+}
+
+#[test]
+fn grammars_without_indents_leave_it_to_the_rules() {
+    let (app, entity) = parsed("notes.md", "# Notes\n\ntext\n");
+    let buffer = app.world.get::<Document>(entity).unwrap().buffer();
+    let syntax = app.world.get::<Syntax>(entity).unwrap();
+    assert_eq!(syntax.indent_scope(buffer, 3), None);
+}
+
+/// What typing a line break at the end of `line` inserts without a grammar.
+fn newline_by_rules(line: &str) -> String {
+    let buffer = TextBuffer::from(line);
+    let rules = IndentRules::default();
+    indent::newline(&buffer, line.len(), &rules, IndentUnit::Spaces(4)).0
+}
+
+#[test]
+fn quotes_hide_brackets_but_apostrophes_and_lifetimes_dont() {
+    assert_eq!(newline_by_rules("let open = '{';"), "\n");
+    assert_eq!(newline_by_rules("fn f<'a>(x: &'a str) {"), "\n    ");
+    assert_eq!(newline_by_rules("don't {"), "\n    ");
+
+    let rules = IndentRules::default();
+    assert_eq!(rules.context("don't ("), SyntaxContext::Code);
+    assert_eq!(rules.context("say('hi"), SyntaxContext::String);
+    assert_eq!(rules.string_at("a 'b' c", 3), Some(2..5));
+}
@@ -0,0 +1,231 @@
+//! Keys are looked up in the layers of the keymap, and the ones bound to nothing type text.
+
+mod common;
+
+use bevy::{
+    app::App,
+    ecs::event::{Events, ManualEventReader},
+    input::{
+        keyboard::{KeyCode, KeyboardInput},
+        ElementState, Input,
+    },
+    window::{ReceivedCharacter, WindowId},
+};
+use dip_core::{
+    command::RunCommand,
+    cursor::TypeText,
+    document::Document,
+    keymap::{parse_keys, Chord, Keymap, KeymapError, KeymapPlugin, Layer, Lookup},
+    text_buffer::TextBuffer,
+    workspace::WorkspacePlugin,
+    Mode, ModeType,
+};
+
+#[test]
+fn parses_chords() {
+    let chord: Chord = "Ctrl+Shift+K".parse().unwrap();
+    assert_eq!(
+        chord,
+        Chord {
+            ctrl: true,
+            shift: true,
+            ..Chord::new(KeyCode::K)
+        }
+    );
+    assert_eq!(chord.to_string(), "ctrl+shift+k");
+    let plus: Chord = "ctrl++".parse().unwrap();
+    assert_eq!(plus.key, KeyCode::Plus);
+    assert!(plus.ctrl);
+    assert_eq!("cmd+f12".parse::<Chord>().unwrap().to_string(), "cmd+f12");
+    assert_eq!(
+        "option+enter".parse::<Chord>().unwrap().to_string(),
+        "alt+enter"
+    );
+}
+
+#[test]
+fn rejects_what_is_not_a_chord() {
+    assert_eq!(
+        "ctrl+nope".parse::<Chord>(),
+        Err(KeymapError::UnknownKey("nope".into()))
+    );
+    assert_eq!(
+        "hyper+k".parse::<Chord>(),
+        Err(KeymapError::UnknownModifier("hyper".into()))
+    );
+    assert_eq!(
+        "f13".parse::<Chord>(),
+        Err(KeymapError::UnknownKey("f13".into()))
+    );
+    assert_eq!(parse_keys("  "), Err(KeymapError::NoKeys));
+    assert_eq!(parse_keys("ctrl+k ctrl+c").unwrap().len(), 2);
+}
+
+#[test]
+fn upper_layers_override_and_unbind() {
+    let mut keymap = Keymap::default();
+    let save = parse_keys("ctrl+s").unwrap();
+    assert_eq!(keymap.lookup(&save, None), Lookup::Command("file.save"));
+
+    let layer = Layer::parse(
+        "user",
+        r#"[
+            { "key": "ctrl+s", "command": "file.saveAll" },
+            { "key": "ctrl+z" },
+            { "key": "ctrl+k ctrl+c", "command": "edit.comment" },
+            { "key": "j", "command": "cursor.down", "mode": "normal" }
+        ]"#,
+    )
+    .unwrap();
+    keymap.set_layer(layer);
+    assert_eq!(keymap.lookup(&save, None), Lookup::Command("file.saveAll"));
+    let undo = parse_keys("ctrl+z").unwrap();
+    assert_eq!(keymap.lookup(&undo, None), Lookup::Unbound);
+    assert_eq!(keymap.keybinding("edit.undo", None), None);
+
+    let keys = parse_keys("ctrl+k ctrl+c").unwrap();
+    assert_eq!(keymap.lookup(&keys[..1], None), Lookup::Prefix);
+    assert_eq!(keymap.lookup(&keys, None), Lookup::Command("edit.comment"));
+    assert_eq!(
+        keymap.keybinding("edit.comment", None).as_deref(),
+        Some("ctrl+k ctrl+c")
+    );
+
+    let j = parse_keys("j").unwrap();
+    let normal = Some(ModeType::Normal);
+    assert_eq!(keymap.lookup(&j, normal), Lookup::Command("cursor.down"));
+    assert_eq!(keymap.lookup(&j, Some(ModeType::Insert)), Lookup::Unbound);
+
+    keymap.remove_layer("user");
+    assert_eq!(keymap.lookup(&save, None), Lookup::Command("file.save"));
+}
+
+#[test]
+fn rejects_unknown_modes() {
+    let source = r#"[{ "key": "j", "command": "cursor.down", "mode": "replace" }]"#;
+    assert_eq!(
+        Layer::parse("user", source),
+        Err(KeymapError::UnknownMode("replace".into()))
+    );
+}
+
+/// An app with a document focused in insert mode, recording the commands and text that
+/// keys turn into.
+struct Typing {
+    app: App,
+    commands: ManualEventReader<RunCommand>,
+    types: ManualEventReader<TypeText>,
+}
+
+impl Typing {
+    fn new() -> Self {
+        let mut app = common::app();
+        app.add_plugin(WorkspacePlugin)
+            .add_plugin(KeymapPlugin)
+            .init_resource::<Input<KeyCode>>()
+            // Sent by the plugins left out.
+            .add_event::<KeyboardInput>()
+            .add_event::<ReceivedCharacter>()
+            .add_event::<RunCommand>()
+            .add_event::<TypeText>();
+        app.world.spawn().insert(Mode(ModeType::Insert));
+        let document = Document::new(None, TextBuffer::from(""));
+        app.world.spawn().insert(document);
+        app.update();
+        Self {
+            app,
+            commands: Default::default(),
+            types: Default::default(),
+        }
+    }
+
+    fn hold(&mut self, key: KeyCode) {
+        let mut input = self.app.world.get_resource_mut::<Input<KeyCode>>().unwrap();
+        input.press(key);
+    }
+
+    /// Presses `keys` in one frame, each typing its character if it has one.
+    fn press(&mut self, keys: &[(KeyCode, Option<char>)]) {
+        for (key, c) in keys {
+            let mut inputs = self
+                .app
+                .world
+                .get_resource_mut::<Events<KeyboardInput>>()
+                .unwrap();
+            inputs.send(KeyboardInput {
+                scan_code: 0,
+                key_code: Some(*key),
+                state: ElementState::Pressed,
+            });
+            let mut characters = self
+                .app
+                .world
+                .get_resource_mut::<Events<ReceivedCharacter>>()
+                .unwrap();
+            if let Some(c) = c {
+                characters.send(ReceivedCharacter {
+                    id: WindowId::primary(),
+                    char: *c,
+                });
+            }
+        }
+        self.app.update();
+    }
+
+    /// What keys turned into in the last frame: commands, and text in quotes.
+    fn frame(&mut self) -> Vec<String> {
+        let world = &self.app.world;
+        let commands = world.get_resource::<Events<RunCommand>>().unwrap();
+        let types = world.get_resource::<Events<TypeText>>().unwrap();
+        let typed = self.types.iter(types).map(|e| format!("{:?}", e.text));
+        let ran = self.commands.iter(commands).map(|e| e.id.clone());
+        typed.chain(ran).collect()
+    }
+}
+
+#[test]
+fn text_typed_before_a_command_lands_before_it() {
+    let mut typing = Typing::new();
+    typing.press(&[(KeyCode::A, Some('a')), (KeyCode::Return, Some('\r'))]);
+    assert_eq!(typing.frame(), ["\"a\""]);
+    typing.app.update();
+    assert_eq!(typing.frame(), ["edit.newline"]);
+    typing.app.update();
+    assert_eq!(typing.frame(), Vec::<String>::new());
+}
+
+#[test]
+fn text_typed_after_a_command_waits_for_it() {
+    let mut typing = Typing::new();
+    typing.press(&[
+        (KeyCode::Return, Some('\r')),
+        (KeyCode::B, Some('b')),
+        (KeyCode::C, Some('c')),
+    ]);
+    assert_eq!(typing.frame(), ["edit.newline"]);
+    typing.app.update();
+    assert_eq!(typing.frame(), ["\"bc\""]);
+}
+
+#[test]
+fn shortcuts_type_nothing_but_altgr_does() {
+    let mut typing = Typing::new();
+    typing.hold(KeyCode::LControl);
+    typing.press(&[(KeyCode::C, Some('\u{3}')), (KeyCode::K, Some('k'))]);
+    assert_eq!(typing.frame(), ["edit.copy"]);
+    typing.app.update();
+    assert_eq!(typing.frame(), Vec::<String>::new());
+
+    // AltGr is ctrl with alt on Windows.
+    typing.hold(KeyCode::RAlt);
+    typing.press(&[(KeyCode::Q, Some('@'))]);
+    assert_eq!(typing.frame(), ["\"@\""]);
+}
+
+#[test]
+fn characters_without_a_key_are_typed_too() {
+    let mut typing = Typing::new();
+    // A dead key types nothing, the key after it the composed character.
+    typing.press(&[(KeyCode::Apostrophe, None), (KeyCode::E, Some('é'))]);
+    assert_eq!(typing.frame(), ["\"é\""]);
+}
@@ -0,0 +1,217 @@
+//! Launch files are checked where they are written, their variables expanded when a
+//! configuration starts, and launch requests run as processes of the first workspace root.
+
+mod common;
+
+use bevy::{
+    app::App,
+    ecs::{entity::Entity, event::Events},
+};
+use common::ScratchDir;
+use dip_core::{
+    diagnostics::PublishDiagnostics,
+    document::Document,
+    launch::{
+        InterpolationError, LaunchConfigurations, LaunchFile, LaunchPlugin,
+        ReloadLaunchConfigurations, StartDebugging, Variables, LAUNCH_FILE,
+    },
+    process::SpawnProcess,
+    workspace::{Workspace, WorkspaceChanged},
+};
+use std::{env, fs, path::PathBuf};
+
+const LAUNCH: &str = r#"{
+  "configurations": [
+    { "name": "server", "type": "lldb", "request": "launch",
+      "program": "${workspaceFolder}/target/debug/server", "args": ["--port", "8080"] },
+    { "name": "client", "type": "node", "request": "launch",
+      "program": "${fileBasename}", "cwd": "${fileDirname}" },
+    { "name": "running", "type": "lldb", "request": "attach" }
+  ],
+  "compounds": [
+    { "name": "both", "configurations": ["client", "server"] }
+  ]
+}"#;
+
+/// `(line, column, message)` of what is wrong with `source`, 1-based.
+fn problems(source: &str) -> Vec<(usize, usize, String)> {
+    let diagnostics = LaunchFile::parse(source).err().unwrap_or_default();
+    diagnostics
+        .into_iter()
+        .map(|d| (d.line + 1, d.column + 1, d.message))
+        .collect()
+}
+
+#[test]
+fn points_at_what_is_wrong() {
+    let source = r#"{
+  "configurations": [
+    { "name": "a", "type": "lldb", "request": "launch", "program": "a" },
+    { "name": "a", "type": "lldb", "request": "launch" }
+  ],
+  "compounds": [{ "name": "all", "configurations": ["a", "b"] }]
+}"#;
+    assert_eq!(
+        problems(source),
+        [
+            (4, 15, "duplicate configuration name `a`".to_string()),
+            (
+                4,
+                15,
+                "`a` is a launch request without a `program`".to_string()
+            ),
+            (
+                6,
+                58,
+                "compound `all` refers to unknown configuration `b`".to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn points_at_syntax_errors() {
+    let problems = problems("{\n  \"configurations\": [\n    { \"name\": }\n  ]\n}");
+    assert_eq!(problems.len(), 1);
+    assert_eq!((problems[0].0, problems[0].1), (3, 15));
+    assert!(LaunchFile::parse(LAUNCH).is_ok());
+}
+
+fn variables() -> Variables {
+    Variables {
+        workspace_folder: PathBuf::from("/src/dip"),
+        file: Some(PathBuf::from("/src/dip/web/main.ts")),
+    }
+}
+
+#[test]
+fn expands_variables() {
+    let vars = variables();
+    let expanded = vars.interpolate("${workspaceFolderBasename}: ${fileDirname}/${fileBasename}");
+    assert_eq!(expanded.unwrap(), "dip: /src/dip/web/main.ts");
+    assert_eq!(
+        vars.interpolate("${workspaceFolder}/out").unwrap(),
+        "/src/dip/out"
+    );
+    assert_eq!(
+        vars.interpolate("${env:PATH}").unwrap(),
+        env::var("PATH").unwrap_or_default()
+    );
+    assert_eq!(vars.interpolate("${env:DIP_NEVER_SET}").unwrap(), "");
+}
+
+#[test]
+fn refuses_variables_it_cannot_expand() {
+    let vars = variables();
+    assert_eq!(
+        vars.interpolate("${home}"),
+        Err(InterpolationError::Unknown("home".into()))
+    );
+    assert_eq!(
+        vars.interpolate("${file"),
+        Err(InterpolationError::Unterminated)
+    );
+    let vars = Variables {
+        file: None,
+        ..variables()
+    };
+    assert_eq!(
+        vars.interpolate("${fileDirname}"),
+        Err(InterpolationError::NoActiveFile("fileDirname".into()))
+    );
+}
+
+#[test]
+fn compounds_start_in_their_listed_order() {
+    let file = LaunchFile::parse(LAUNCH).unwrap();
+    let configs = file.resolve("both", &variables()).unwrap();
+    let programs: Vec<_> = configs.iter().map(|c| c.program.as_deref()).collect();
+    assert_eq!(
+        programs,
+        [Some("main.ts"), Some("/src/dip/target/debug/server")]
+    );
+    assert_eq!(configs[0].cwd.as_deref(), Some("/src/dip/web"));
+    assert!(file.resolve("nothing", &variables()).unwrap().is_empty());
+}
+
+/// An app with `dir` as its only root, its launch file read on the first frame.
+fn app(dir: &ScratchDir) -> App {
+    let mut app = common::app();
+    app.insert_resource(Workspace::folder(dir.path()))
+        .add_plugin(LaunchPlugin)
+        // Sent by the plugins left out.
+        .add_event::<WorkspaceChanged>()
+        .add_event::<PublishDiagnostics>()
+        .add_event::<SpawnProcess>();
+    app
+}
+
+fn write_launch(dir: &ScratchDir, source: &str) -> PathBuf {
+    let path = dir.join(LAUNCH_FILE);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, source).unwrap();
+    path
+}
+
+/// What was last published for `entity`, as `(start, message)`.
+fn published(app: &App, entity: Entity) -> Vec<(usize, String)> {
+    let events = app
+        .world
+        .get_resource::<Events<PublishDiagnostics>>()
+        .unwrap();
+    let last = events
+        .get_reader()
+        .iter(events)
+        .rfind(|e| e.entity == entity)
+        .expect("nothing was published");
+    let diagnostics = last.diagnostics.iter();
+    diagnostics
+        .map(|d| (d.range.start, d.message.clone()))
+        .collect()
+}
+
+#[test]
+fn shows_the_problems_of_the_launch_file_in_it() {
+    let dir = ScratchDir::new("problems");
+    let source = r#"{ "configurations": [{ "name": "a", "type": "lldb", "request": "launch" }] }"#;
+    let path = write_launch(&dir, source);
+    let mut app = app(&dir);
+    let document = Document::from_path(&path).unwrap();
+    let entity = app.world.spawn().insert(document).id();
+    app.update();
+    let launch = app.world.get_resource::<LaunchConfigurations>().unwrap();
+    assert_eq!(launch.variables.workspace_folder, dir.path());
+    assert_eq!(
+        published(&app, entity),
+        [(
+            31,
+            "`a` is a launch request without a `program`".to_string()
+        )]
+    );
+
+    write_launch(&dir, LAUNCH);
+    common::send(&mut app, ReloadLaunchConfigurations);
+    app.update();
+    assert_eq!(published(&app, entity), []);
+}
+
+#[test]
+fn runs_launch_requests_from_the_first_root() {
+    let dir = ScratchDir::new("run");
+    write_launch(&dir, LAUNCH);
+    let mut app = app(&dir);
+    app.update();
+    common::send(&mut app, StartDebugging("server".into()));
+    common::send(&mut app, StartDebugging("running".into()));
+    // Run the frame after whichever system runs first.
+    app.update();
+    app.update();
+
+    let events = app.world.get_resource::<Events<SpawnProcess>>().unwrap();
+    let spawned: Vec<_> = events.get_reader().iter(events).cloned().collect();
+    assert_eq!(spawned.len(), 1);
+    let SpawnProcess(spec) = &spawned[0];
+    let program = dir.join("target/debug/server");
+    assert_eq!(spec.program, program.as_os_str());
+    assert_eq!(spec.args, ["--port", "8080"]);
+}
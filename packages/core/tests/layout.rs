@@ -0,0 +1,171 @@
+//! Layouts only lay out the lines in view and count the others from where they wrap,
+//! worked out on a task pool, and map positions to the rows showing them for moving
+//! through them.
+
+use bevy::tasks::TaskPool;
+use dip_core::{
+    document::LineChange,
+    fold::Folds,
+    layout::{monospace, Layout},
+    text_buffer::{Position, TextBuffer},
+};
+use std::{thread, time::Duration};
+
+/// `count` lines of `line`.
+fn buffer(line: &str, count: usize) -> TextBuffer {
+    TextBuffer::from(vec![line; count].join("\n").as_str())
+}
+
+/// Waits for the lines off screen to be wrapped.
+fn rebuilt(layout: &mut Layout, buffer: &TextBuffer) {
+    let pool = TaskPool::new();
+    layout.rebuild(&pool, buffer);
+    for _ in 0..500 {
+        layout.receive(&Folds::default());
+        if !layout.is_rebuilding() {
+            return;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    panic!("the lines were not wrapped");
+}
+
+#[test]
+fn only_lays_out_the_lines_in_view() {
+    let buffer = buffer("twelve chars", 1000);
+    let folds = Folds::default();
+    let mut layout = Layout::new(&buffer, Some(6.), monospace(1.), &folds);
+    assert!(layout.line(0).is_none());
+    // Each line is one row until it is known to wrap.
+    assert_eq!(layout.visual_line_count(), 1000);
+
+    layout.lay_out(&buffer, 100..110, &folds);
+    assert!(layout.is_laid_out(100..110));
+    assert_eq!(layout.line(100).unwrap().row_count(), 2);
+    assert!(layout.line(0).is_none() && layout.line(500).is_none());
+    assert_eq!(layout.visual_lines(100..101).len(), 2);
+
+    rebuilt(&mut layout, &buffer);
+    assert_eq!(layout.visual_line_count(), 2000);
+    assert!(layout.line(500).is_none());
+}
+
+#[test]
+fn lays_out_edited_lines_again() {
+    let buffer = buffer("twelve chars", 20);
+    let folds = Folds::default();
+    let mut layout = Layout::new(&buffer, None, monospace(1.), &folds);
+    layout.lay_out(&buffer, 0..20, &folds);
+
+    let change = LineChange {
+        start: 5,
+        removed: 0,
+        inserted: 1,
+    };
+    layout.edited([change], &folds);
+    assert!(layout.is_current(21));
+    assert!(layout.line(4).is_some());
+    assert!(layout.line(5).is_none() && layout.line(6).is_none());
+    // Moved along without being laid out again.
+    assert!(layout.line(7).is_some());
+    assert_eq!(layout.visual_line_count(), 21);
+}
+
+#[test]
+fn a_resize_keeps_the_lines_narrower_than_both_widths() {
+    let buffer = TextBuffer::from("short\na line wider than ten\nshort");
+    let folds = Folds::default();
+    let mut layout = Layout::new(&buffer, Some(30.), monospace(1.), &folds);
+    rebuilt(&mut layout, &buffer);
+    assert_eq!(layout.visual_line_count(), 3);
+
+    layout.set_width(Some(10.), &folds);
+    // The wide line counts as one row until it is wrapped again.
+    assert!(layout.needs_rebuild());
+    rebuilt(&mut layout, &buffer);
+    assert_eq!(layout.visual_line_count(), 5);
+}
+
+/// A line of three rows, "one two " "three " "four", between two short ones.
+fn wrapped() -> (TextBuffer, Layout) {
+    let buffer = TextBuffer::from("short\none two three four\nend");
+    let folds = Folds::default();
+    let mut layout = Layout::new(&buffer, Some(8.), monospace(1.), &folds);
+    layout.lay_out(&buffer, 0..3, &folds);
+    (buffer, layout)
+}
+
+#[test]
+fn maps_positions_to_the_rows_showing_them_and_back() {
+    let (buffer, layout) = wrapped();
+    let line = layout.line(1).unwrap();
+    assert_eq!(line.row_count(), 3);
+    assert_eq!(
+        (0..3).map(|row| line.row_columns(row)).collect::<Vec<_>>(),
+        [0..8, 8..14, 14..18]
+    );
+    assert_eq!(layout.visual_line_count(), 5);
+    assert_eq!(layout.visual_lines(1..2), 1..4);
+    assert_eq!(
+        (0..6).map(|v| layout.line_at(v)).collect::<Vec<_>>(),
+        [0, 1, 1, 1, 2, 2]
+    );
+
+    // The column a row wraps at shows at the start of the next one.
+    assert_eq!(
+        layout.visual_position(&buffer, Position::new(1, 8)),
+        (2, 0.)
+    );
+    assert_eq!(
+        layout.visual_position(&buffer, Position::new(1, 10)),
+        (2, 2.)
+    );
+    assert_eq!(
+        layout.visual_position(&buffer, Position::new(1, 18)),
+        (3, 4.)
+    );
+    assert_eq!(layout.position_at(&buffer, 2, 2.), Position::new(1, 10));
+    // Rows other than the last stop before their wrap, the last at the end of the line.
+    assert_eq!(layout.position_at(&buffer, 1, 50.), Position::new(1, 7));
+    assert_eq!(layout.position_at(&buffer, 3, 50.), Position::new(1, 18));
+    assert_eq!(layout.position_at(&buffer, 9, 1.), Position::new(2, 1));
+}
+
+#[test]
+fn moves_up_and_down_through_rows_keeping_to_the_goal() {
+    let (buffer, layout) = wrapped();
+    let (down, goal) = layout.moved(&buffer, Position::new(0, 4), None, 1);
+    assert_eq!((down, goal), (Position::new(1, 4), 4.));
+    let (down, _) = layout.moved(&buffer, down, Some(goal), 1);
+    assert_eq!(down, Position::new(1, 12));
+    let (down, _) = layout.moved(&buffer, down, Some(goal), 1);
+    // The last row is shorter, and the goal is kept for the next move.
+    assert_eq!(down, Position::new(1, 18));
+    let (down, _) = layout.moved(&buffer, down, Some(goal), 1);
+    assert_eq!(down, Position::new(2, 3));
+    let (up, _) = layout.moved(&buffer, down, Some(goal), -2);
+    assert_eq!(up, Position::new(1, 12));
+
+    // Past the first or last line goes to the start or end of the document.
+    let (top, _) = layout.moved(&buffer, Position::new(0, 4), None, -1);
+    assert_eq!(top, Position::new(0, 0));
+    let (bottom, _) = layout.moved(&buffer, Position::new(2, 1), None, 1);
+    assert_eq!(bottom, Position::new(2, 3));
+}
+
+#[test]
+fn skips_folded_lines() {
+    let buffer = TextBuffer::from("fn a() {\n    one\n    two\n}\nafter");
+    let mut folds = Folds::default();
+    folds.fold(0..3);
+    let mut layout = Layout::new(&buffer, None, monospace(1.), &folds);
+    layout.lay_out(&buffer, 0..5, &folds);
+    assert_eq!(layout.visual_line_count(), 3);
+    // A hidden line shows as the end of its header.
+    assert_eq!(
+        layout.visual_position(&buffer, Position::new(2, 4)),
+        (0, 0.)
+    );
+    let (down, _) = layout.moved(&buffer, Position::new(0, 2), None, 1);
+    assert_eq!(down, Position::new(3, 1));
+}
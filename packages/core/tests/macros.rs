@@ -0,0 +1,60 @@
+//! Saved macros are loaded from their directory at startup.
+
+mod common;
+
+use bevy::{app::App, core::CorePlugin};
+use common::ScratchDir;
+use dip_core::{
+    command::{CommandRegistry, RunCommand},
+    cursor::TypeText,
+    keymap::Keymap,
+    macros::{MacroPlugin, MacroSettings, Macros},
+    pipeline::PipelinePlugin,
+    workspace::Workspace,
+};
+use std::fs;
+
+/// An app with the macros saved in `dir` loaded.
+fn loaded(dir: &ScratchDir) -> App {
+    let mut app = App::new();
+    app.add_plugin(CorePlugin)
+        .add_plugin(PipelinePlugin)
+        .insert_resource(MacroSettings {
+            dir: Some(dir.path().to_path_buf()),
+        })
+        .init_resource::<Keymap>()
+        .init_resource::<Workspace>()
+        // Sent by the plugins left out.
+        .add_event::<RunCommand>()
+        .add_event::<TypeText>()
+        .add_plugin(MacroPlugin);
+    app.update();
+    app
+}
+
+#[test]
+fn loads_saved_macros_but_not_ones_running_macros() {
+    let dir = ScratchDir::new("load");
+    fs::write(
+        dir.join("greet.json"),
+        r#"{ "name": "greet", "title": "Greet", "steps": [{ "type": "hello" }] }"#,
+    )
+    .unwrap();
+    fs::write(
+        dir.join("again.json"),
+        r#"{
+            "name": "again",
+            "title": "Again",
+            "steps": [{ "type": "again" }, { "command": "macro.again" }]
+        }"#,
+    )
+    .unwrap();
+    let app = loaded(&dir);
+
+    let macros = app.world.get_resource::<Macros>().unwrap();
+    let names: Vec<_> = macros.saved().iter().map(|m| m.name.as_str()).collect();
+    assert_eq!(names, ["greet"]);
+    let registry = app.world.get_resource::<CommandRegistry>().unwrap();
+    assert!(registry.get("macro.greet").is_some());
+    assert!(registry.get("macro.again").is_none());
+}
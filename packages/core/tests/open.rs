@@ -0,0 +1,71 @@
+//! Opening files, large ones mapped read-only off the main thread.
+
+mod common;
+
+use bevy::{app::App, ecs::event::Events};
+use common::ScratchDir;
+use dip_core::document::{Document, DocumentOpened, LargeFileSettings, OpenDocument};
+use std::{fs, path::Path, thread, time::Duration};
+
+fn app(map_threshold: Option<u64>) -> App {
+    let mut app = common::app();
+    app.world
+        .get_resource_mut::<LargeFileSettings>()
+        .unwrap()
+        .map_threshold = map_threshold;
+    app
+}
+
+/// Opens `path` `times` times in one frame and runs frames until it is open.
+fn open(app: &mut App, path: &Path, times: usize) -> Vec<DocumentOpened> {
+    let mut events = app
+        .world
+        .get_resource_mut::<Events<OpenDocument>>()
+        .unwrap();
+    for _ in 0..times {
+        events.send(OpenDocument {
+            path: path.to_path_buf(),
+        });
+    }
+    let mut reader = app
+        .world
+        .get_resource::<Events<DocumentOpened>>()
+        .unwrap()
+        .get_reader();
+    for _ in 0..500 {
+        app.update();
+        let events = app.world.get_resource::<Events<DocumentOpened>>().unwrap();
+        let opened: Vec<_> = reader.iter(events).cloned().collect();
+        if !opened.is_empty() {
+            return opened;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("{} was not opened", path.display());
+}
+
+#[test]
+fn maps_large_files_read_only() {
+    let dir = ScratchDir::new("large");
+    fs::write(dir.notes(), b"\xef\xbb\xbfline\n\xff\n").unwrap();
+    let mut app = app(Some(0));
+    let opened = open(&mut app, &dir.notes(), 2);
+    assert_eq!(opened.len(), 1);
+
+    let document = app.world.get::<Document>(opened[0].entity).unwrap();
+    assert!(document.is_read_only());
+    assert_eq!(document.buffer().to_string(), "line\n\u{fffd}\n");
+    let documents = app.world.query::<&Document>().iter(&app.world).count();
+    assert_eq!(documents, 1);
+}
+
+#[test]
+fn reads_smaller_files_into_memory() {
+    let dir = ScratchDir::new("small");
+    fs::write(dir.notes(), "line\n").unwrap();
+    let mut app = app(None);
+    let opened = open(&mut app, &dir.notes(), 1);
+    let document = app.world.get::<Document>(opened[0].entity).unwrap();
+    assert!(!document.is_read_only());
+    assert_eq!(document.buffer().to_string(), "line\n");
+}
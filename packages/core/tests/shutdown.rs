@@ -0,0 +1,98 @@
+//! Quitting with unsaved changes saves, asks about or throws them away as configured.
+
+mod common;
+
+use bevy::{
+    app::{App, AppExit},
+    ecs::{entity::Entity, event::Events},
+};
+use common::ScratchDir;
+use dip_core::{
+    command::CoreCommand,
+    document::Document,
+    shutdown::{
+        RequestShutdown, ResolveUnsavedChanges, ShutdownPlugin, ShutdownSettings,
+        UnsavedChangesPolicy, UnsavedChangesPrompt, UnsavedChoice,
+    },
+};
+use std::{fs, path::Path};
+
+/// An app quitting with `path` open and edited.
+fn quit_with_dirty_document(policy: UnsavedChangesPolicy, path: &Path) -> (App, Entity) {
+    fs::write(path, "saved\n").unwrap();
+    let mut app = common::app();
+    app.add_plugin(ShutdownPlugin)
+        // Sent by the plugins left out.
+        .add_event::<CoreCommand>();
+    app.world
+        .get_resource_mut::<ShutdownSettings>()
+        .unwrap()
+        .unsaved_changes = policy;
+
+    let document = common::edited(path, "edited ");
+    let entity = app.world.spawn().insert(document).id();
+    common::send(&mut app, RequestShutdown);
+    (app, entity)
+}
+
+/// Runs a few frames, enough to go through every stage, and returns whether the app
+/// exited and who was asked about.
+fn run(app: &mut App) -> (bool, Vec<Entity>) {
+    let mut exits = app
+        .world
+        .get_resource::<Events<AppExit>>()
+        .unwrap()
+        .get_reader();
+    let mut prompts = app
+        .world
+        .get_resource::<Events<UnsavedChangesPrompt>>()
+        .unwrap()
+        .get_reader();
+    let (mut exited, mut asked) = (false, vec![]);
+    for _ in 0..10 {
+        app.update();
+        let events = app.world.get_resource::<Events<AppExit>>().unwrap();
+        exited |= exits.iter(events).count() > 0;
+        let events = app
+            .world
+            .get_resource::<Events<UnsavedChangesPrompt>>()
+            .unwrap();
+        asked.extend(prompts.iter(events).flat_map(|e| e.documents.clone()));
+    }
+    (exited, asked)
+}
+
+#[test]
+fn auto_save_saves_and_quits() {
+    let dir = ScratchDir::new("auto-save");
+    let path = dir.notes();
+    let (mut app, entity) = quit_with_dirty_document(UnsavedChangesPolicy::AutoSave, &path);
+
+    assert_eq!(run(&mut app), (true, vec![]));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "edited saved\n");
+    assert!(!app.world.get::<Document>(entity).unwrap().is_dirty());
+}
+
+#[test]
+fn discard_quits_without_saving() {
+    let dir = ScratchDir::new("discard");
+    let path = dir.notes();
+    let (mut app, _) = quit_with_dirty_document(UnsavedChangesPolicy::Discard, &path);
+
+    assert_eq!(run(&mut app), (true, vec![]));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "saved\n");
+}
+
+#[test]
+fn prompt_waits_for_the_answer() {
+    let dir = ScratchDir::new("prompt");
+    let path = dir.notes();
+    let (mut app, entity) = quit_with_dirty_document(UnsavedChangesPolicy::Prompt, &path);
+
+    assert_eq!(run(&mut app), (false, vec![entity]));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "saved\n");
+
+    common::send(&mut app, ResolveUnsavedChanges(UnsavedChoice::Save));
+    assert_eq!(run(&mut app), (true, vec![]));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "edited saved\n");
+}
@@ -0,0 +1,122 @@
+//! Definitions are indexed across the workspace on the task pools and found by name.
+
+mod common;
+
+use bevy::{app::App, ecs::event::Events};
+use common::ScratchDir;
+use dip_core::{
+    document::{Document, DocumentSaved},
+    idle::Idle,
+    symbol_index::{SymbolIndex, SymbolIndexPlugin, SymbolKind, SymbolsIndexed},
+    syntax::Grammars,
+    workspace::{Workspace, WorkspaceChanged},
+    workspace_search::WorkspaceSearchSettings,
+};
+use std::{fs, thread, time::Duration};
+
+const RUST: &str = "struct Parser;
+
+impl Parser {
+    fn parse_item(&self) {}
+}
+
+fn parse_file() {}
+";
+
+const TYPESCRIPT: &str = "export class Session {
+  start(): void {}
+}
+
+function parseArgs() {}
+";
+
+/// An app indexing `dir`, with a Rust and a TypeScript file and one it has no grammar for.
+fn app(dir: &ScratchDir) -> App {
+    fs::write(dir.join("parser.rs"), RUST).unwrap();
+    fs::create_dir_all(dir.join("web")).unwrap();
+    fs::write(dir.join("web/session.ts"), TYPESCRIPT).unwrap();
+    fs::write(dir.join("notes.txt"), "fn parse_notes() {}").unwrap();
+
+    let mut app = common::app();
+    app.insert_resource(Workspace::folder(dir.path()))
+        .init_resource::<Idle>()
+        .init_resource::<WorkspaceSearchSettings>()
+        .init_resource::<Grammars>()
+        .add_plugin(SymbolIndexPlugin)
+        // Sent by the plugins left out.
+        .add_event::<WorkspaceChanged>();
+    app
+}
+
+/// Runs frames until a pass over the workspace is done.
+fn indexed(app: &mut App) -> SymbolsIndexed {
+    for _ in 0..500 {
+        app.update();
+        let events = app.world.get_resource::<Events<SymbolsIndexed>>().unwrap();
+        if let Some(e) = events.get_reader().iter(events).last() {
+            return e.clone();
+        }
+        thread::sleep(Duration::from_millis(2));
+    }
+    panic!("the workspace was not indexed");
+}
+
+/// `(name, kind)` of the symbols matching `query`, best first.
+fn found(app: &App, query: &str) -> Vec<(String, SymbolKind)> {
+    let index = app.world.get_resource::<SymbolIndex>().unwrap();
+    let matches = index.search(query, 10);
+    matches
+        .iter()
+        .map(|m| (m.symbol.name.clone(), m.symbol.kind))
+        .collect()
+}
+
+#[test]
+fn indexes_the_definitions_of_files_with_a_grammar() {
+    let dir = ScratchDir::new("index");
+    let mut app = app(&dir);
+    let pass = indexed(&mut app);
+    assert_eq!((pass.files, pass.symbols), (2, 6));
+
+    // A method is not indexed as a function too.
+    assert_eq!(
+        found(&app, "parse_"),
+        [
+            ("parse_file".to_string(), SymbolKind::Function),
+            ("parse_item".to_string(), SymbolKind::Method),
+        ]
+    );
+    assert_eq!(
+        found(&app, "sess"),
+        [("Session".to_string(), SymbolKind::Class)]
+    );
+    let index = app.world.get_resource::<SymbolIndex>().unwrap();
+    let symbols = index.in_file(&dir.join("parser.rs"));
+    let names: Vec<_> = symbols.iter().map(|s| &RUST[s.range.clone()]).collect();
+    assert_eq!(names, ["Parser", "parse_item", "parse_file"]);
+}
+
+#[test]
+fn indexes_saved_files_again_and_forgets_removed_ones() {
+    let dir = ScratchDir::new("save");
+    let mut app = app(&dir);
+    indexed(&mut app);
+
+    let path = dir.join("parser.rs");
+    let mut document = Document::from_path(&path).unwrap();
+    document.insert(RUST.len(), "fn render() {}\n");
+    let entity = app.world.spawn().insert(document).id();
+    common::send(&mut app, DocumentSaved { entity, path });
+    app.update();
+    assert_eq!(
+        found(&app, "render"),
+        [("render".to_string(), SymbolKind::Function)]
+    );
+
+    fs::remove_file(dir.join("web/session.ts")).unwrap();
+    common::send(&mut app, WorkspaceChanged);
+    indexed(&mut app);
+    assert_eq!(found(&app, "sess"), []);
+    // What was saved but not written is read again from the file.
+    assert_eq!(found(&app, "render"), []);
+}
@@ -0,0 +1,210 @@
+//! Tabs follow the documents opened, edited and closed, and closed tabs are remembered
+//! with their cursor and scroll, to come back with them.
+
+mod common;
+
+use bevy::{app::App, ecs::entity::Entity};
+use common::ScratchDir;
+use dip_core::{
+    announce::Announcement,
+    command::{CoreCommand, UICommand},
+    control::RevealPosition,
+    cursor::{Cursor, CursorPlugin, TypeText},
+    document::OpenDocument,
+    format::FormatPlugin,
+    indent::IndentPlugin,
+    limbo::{Closed, LimboPlugin},
+    memory::MemoryPressure,
+    tab::{
+        CloseAllTabs, ClosedTab, EditorGroup, PreviewDocument, RecentlyClosed, ReopenClosedTab,
+        TabAction, TabPlugin,
+    },
+    workspace::{CloseDocument, Workspace, WorkspacePlugin},
+    zoom::{Scroll, ZoomPlugin},
+};
+use std::{fs, path::PathBuf};
+
+fn app() -> App {
+    let mut app = common::app();
+    app.add_plugin(WorkspacePlugin)
+        .add_plugin(LimboPlugin)
+        .add_plugin(IndentPlugin)
+        .add_plugin(FormatPlugin)
+        .add_plugin(CursorPlugin)
+        .add_plugin(ZoomPlugin)
+        .add_plugin(TabPlugin)
+        // Sent by the plugins left out.
+        .add_event::<Announcement>()
+        .add_event::<MemoryPressure>()
+        .add_event::<RevealPosition>()
+        .add_event::<CoreCommand>()
+        .add_event::<UICommand>();
+    app
+}
+
+/// Runs frames until the workspace has a document in front.
+fn active(app: &mut App) -> Entity {
+    for _ in 0..10 {
+        app.update();
+        if let Some(entity) = app.world.get_resource::<Workspace>().unwrap().active() {
+            return entity;
+        }
+    }
+    panic!("nothing was opened");
+}
+
+/// Opens `notes.txt`, moves its cursor and scroll, then closes it.
+fn open_and_close(app: &mut App, dir: &ScratchDir) -> Entity {
+    fs::write(dir.notes(), "one\ntwo\nthree\n").unwrap();
+    common::send(app, OpenDocument { path: dir.notes() });
+    let entity = active(app);
+    app.update();
+    let mut view = app.world.entity_mut(entity);
+    *view.get_mut::<Cursor>().unwrap() = Cursor::at(4);
+    view.get_mut::<Scroll>().unwrap().top = 20.;
+
+    let discard = false;
+    common::send(app, CloseDocument { entity, discard });
+    // Recorded by then whichever system runs first.
+    app.update();
+    app.update();
+    entity
+}
+
+#[test]
+fn reopens_the_closed_document_where_it_was() {
+    let dir = ScratchDir::new("revive");
+    let mut app = app();
+    let closed = open_and_close(&mut app, &dir);
+    let recent = app.world.get_resource::<RecentlyClosed>().unwrap();
+    let tab = ClosedTab {
+        path: dir.notes(),
+        cursor: 4,
+        scroll: 20.,
+    };
+    assert_eq!(recent.iter().collect::<Vec<_>>(), [&tab]);
+
+    common::send(&mut app, ReopenClosedTab::Last);
+    assert_eq!(active(&mut app), closed);
+    assert_eq!(app.world.get::<Cursor>(closed).unwrap().offset, 4);
+    assert!(app
+        .world
+        .get_resource::<RecentlyClosed>()
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn reopens_freed_documents_from_disk() {
+    let dir = ScratchDir::new("freed");
+    let mut app = app();
+    let closed = open_and_close(&mut app, &dir);
+    common::send(&mut app, MemoryPressure { over_by: 1 });
+    app.update();
+    assert!(app.world.get_entity(closed).is_none());
+
+    common::send(&mut app, CoreCommand::ReopenClosedTab(0));
+    let reopened = active(&mut app);
+    for _ in 0..3 {
+        app.update();
+    }
+    assert_eq!(app.world.get::<Cursor>(reopened).unwrap().offset, 4);
+    assert_eq!(app.world.get::<Scroll>(reopened).unwrap().top, 20.);
+}
+
+/// Writes `name` into `dir` and sends `event` for its path, then runs a few frames.
+fn open<T: Send + Sync + 'static>(
+    app: &mut App,
+    dir: &ScratchDir,
+    name: &str,
+    event: fn(PathBuf) -> T,
+) -> Entity {
+    fs::write(dir.join(name), name).unwrap();
+    common::send(app, event(dir.join(name)));
+    for _ in 0..3 {
+        app.update();
+    }
+    app.world
+        .get_resource::<Workspace>()
+        .unwrap()
+        .active()
+        .unwrap()
+}
+
+fn preview(path: PathBuf) -> PreviewDocument {
+    PreviewDocument { path }
+}
+
+fn regular(path: PathBuf) -> OpenDocument {
+    OpenDocument { path }
+}
+
+/// Tabs by file name, with `*` for a preview and `!` for a pinned one.
+fn tabs(app: &App) -> Vec<String> {
+    let group = app.world.get_resource::<EditorGroup>().unwrap();
+    let tabs = group.tabs().iter().map(|tab| {
+        let name = tab.path.file_name().unwrap().to_string_lossy();
+        match (tab.preview, tab.pinned) {
+            (true, _) => format!("{name}*"),
+            (_, true) => format!("{name}!"),
+            _ => name.into_owned(),
+        }
+    });
+    tabs.collect()
+}
+
+#[test]
+fn a_preview_replaces_the_last_one() {
+    let dir = ScratchDir::new("preview");
+    let mut app = app();
+    let a = open(&mut app, &dir, "a.txt", preview);
+    assert_eq!(tabs(&app), ["a.txt*"]);
+
+    open(&mut app, &dir, "b.txt", preview);
+    assert_eq!(tabs(&app), ["b.txt*"]);
+    assert!(app.world.get::<Closed>(a).is_some());
+
+    open(&mut app, &dir, "b.txt", regular);
+    assert_eq!(tabs(&app), ["b.txt"]);
+}
+
+#[test]
+fn editing_keeps_a_preview() {
+    let dir = ScratchDir::new("keep");
+    let mut app = app();
+    let entity = open(&mut app, &dir, "a.txt", preview);
+    let text = "edited ".to_string();
+    common::send(&mut app, TypeText { entity, text });
+    app.update();
+    assert_eq!(tabs(&app), ["a.txt"]);
+
+    open(&mut app, &dir, "b.txt", preview);
+    assert_eq!(tabs(&app), ["a.txt", "b.txt*"]);
+    let kept = app
+        .world
+        .get_resource::<Workspace>()
+        .unwrap()
+        .is_open(entity);
+    assert!(kept);
+}
+
+#[test]
+fn close_all_spares_pinned_tabs() {
+    let dir = ScratchDir::new("close-all");
+    let mut app = app();
+    open(&mut app, &dir, "a.txt", regular);
+    let b = open(&mut app, &dir, "b.txt", regular);
+    open(&mut app, &dir, "c.txt", regular);
+    common::send(&mut app, CoreCommand::Tab(TabAction::Pin(1)));
+    app.update();
+    assert_eq!(tabs(&app), ["b.txt!", "a.txt", "c.txt"]);
+
+    common::send(&mut app, CloseAllTabs);
+    for _ in 0..3 {
+        app.update();
+    }
+    assert_eq!(tabs(&app), ["b.txt!"]);
+    let workspace = app.world.get_resource::<Workspace>().unwrap();
+    assert_eq!(workspace.documents(), [b]);
+    assert_eq!(workspace.active(), Some(b));
+}
@@ -0,0 +1,149 @@
+//! Pipe tables are formatted as a whole whenever the cursor moves through them or they gain
+//! or lose rows and columns.
+
+mod common;
+
+use bevy::{app::App, ecs::entity::Entity};
+use dip_core::{
+    announce::Announcement,
+    command::RunCommand,
+    control::RevealPosition,
+    cursor::{Cursor, CursorPlugin, Selection},
+    document::Document,
+    format::FormatPlugin,
+    indent::IndentPlugin,
+    keymap::Keymap,
+    table::{is_table_line, EditTable, Table, TableEdit, TableFailed, TablePlugin, TableStyle},
+    text_buffer::TextBuffer,
+    workspace::Workspace,
+};
+
+fn formatted(lines: &[&str], style: TableStyle) -> Vec<String> {
+    Table::parse(lines, 0, style).unwrap().render()
+}
+
+#[test]
+fn pads_columns_as_their_rule_aligns_them() {
+    let lines = [
+        "| name | qty | note |",
+        "|:-|--:|:-:|",
+        "| apple | 3 | a\\|b |",
+        "| kiwi | 12",
+    ];
+    assert_eq!(
+        formatted(&lines, TableStyle::Markdown),
+        [
+            "| name  | qty | note |",
+            "| :---- | --: | :--: |",
+            "| apple |   3 | a\\|b |",
+            "| kiwi  |  12 |      |",
+        ]
+    );
+    assert!(is_table_line("  | indented"));
+    assert!(!is_table_line("a | b"));
+    assert!(Table::parse(&["| a |", "b"], 0, TableStyle::Markdown).is_none());
+}
+
+#[test]
+fn formats_org_tables_with_their_own_rules() {
+    let lines = ["  | a | longer |", "  |-+-|", "  | bb | c |"];
+    let table = Table::parse(&lines, 0, TableStyle::Org).unwrap();
+    assert_eq!(table.columns(), 2);
+    assert_eq!(
+        table.render(),
+        [
+            "  | a  | longer |",
+            "  |----+--------|",
+            "  | bb | c      |",
+        ]
+    );
+}
+
+/// An app with `text` open as `notes.md`, the cursor at `cursor`.
+fn open(text: &str, cursor: usize) -> (App, Entity) {
+    let mut app = common::app();
+    app.init_resource::<Keymap>()
+        .init_resource::<Workspace>()
+        .add_plugin(IndentPlugin)
+        .add_plugin(FormatPlugin)
+        .add_plugin(TablePlugin)
+        .add_plugin(CursorPlugin)
+        // Sent by the plugins left out.
+        .add_event::<RunCommand>()
+        .add_event::<Announcement>()
+        .add_event::<RevealPosition>();
+    let document = Document::new(Some("notes.md".into()), TextBuffer::from(text));
+    let entity = app
+        .world
+        .spawn()
+        .insert(document)
+        .insert(Cursor::at(cursor))
+        .insert(Selection { anchor: cursor })
+        .id();
+    app.update();
+    (app, entity)
+}
+
+fn edit(app: &mut App, entity: Entity, edit: TableEdit) {
+    common::send(app, EditTable { entity, edit });
+    app.update();
+}
+
+/// The text with a `^` where the cursor is.
+fn shown(app: &App, entity: Entity) -> String {
+    let mut text = common::text(app, entity);
+    let cursor = app.world.get::<Cursor>(entity).unwrap();
+    text.insert(cursor.offset, '^');
+    text
+}
+
+const TABLE: &str = "Fruit:\n| name | qty |\n|--|--|\n| apple | 3 |\nThe end.\n";
+
+#[test]
+fn tab_moves_through_the_cells_and_adds_a_row_after_the_last() {
+    let apple = TABLE.find("apple").unwrap();
+    let (mut app, entity) = open(TABLE, apple);
+    edit(&mut app, entity, TableEdit::NextCell);
+    assert_eq!(
+        shown(&app, entity),
+        "Fruit:\n| name  | qty |\n| ----- | --- |\n| apple | ^3   |\nThe end.\n"
+    );
+
+    edit(&mut app, entity, TableEdit::NextCell);
+    assert_eq!(
+        shown(&app, entity),
+        "Fruit:\n| name  | qty |\n| ----- | --- |\n| apple | 3   |\n| ^      |     |\nThe end.\n"
+    );
+
+    edit(&mut app, entity, TableEdit::PreviousCell);
+    edit(&mut app, entity, TableEdit::PreviousCell);
+    assert!(shown(&app, entity).contains("| ^apple | 3   |"));
+}
+
+#[test]
+fn adds_and_removes_rows_and_columns() {
+    let apple = TABLE.find("apple").unwrap();
+    let (mut app, entity) = open(TABLE, apple);
+    edit(&mut app, entity, TableEdit::InsertColumn { left: false });
+    assert_eq!(
+        shown(&app, entity),
+        "Fruit:\n| name  |     | qty |\n| ----- | --- | --- |\n| apple | ^    | 3   |\nThe end.\n"
+    );
+
+    edit(&mut app, entity, TableEdit::DeleteColumn);
+    edit(&mut app, entity, TableEdit::InsertRow { above: true });
+    edit(&mut app, entity, TableEdit::DeleteRow);
+    edit(&mut app, entity, TableEdit::DeleteRow);
+    assert_eq!(
+        shown(&app, entity),
+        "Fruit:\n| name | ^qty |\n| ---- | --- |\nThe end.\n"
+    );
+}
+
+#[test]
+fn refuses_to_edit_outside_a_table() {
+    let (mut app, entity) = open(TABLE, 0);
+    edit(&mut app, entity, TableEdit::Format);
+    assert!(common::sent::<TableFailed>(&app));
+    assert_eq!(common::text(&app, entity), TABLE);
+}
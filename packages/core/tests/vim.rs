@@ -0,0 +1,151 @@
+//! Vim commands typed into the active document.
+
+use bevy::{
+    app::App,
+    core::CorePlugin,
+    ecs::{entity::Entity, event::Events},
+    input::InputPlugin,
+};
+use dip_core::{
+    command::CoreCommand,
+    cursor::{Cursor, DeleteText, Selection, TypeText},
+    document::{Document, DocumentPlugin},
+    keymap::Keymap,
+    memory::EvictCache,
+    pipeline::PipelinePlugin,
+    text_buffer::TextBuffer,
+    vim::{Register, Vim, VimKey, VimPlugin},
+    workspace::WorkspacePlugin,
+    Mode, ModeType,
+};
+
+/// An app with `text` open and the cursor at its start.
+fn open(text: &str) -> (App, Entity) {
+    let mut app = App::new();
+    app.add_plugin(CorePlugin)
+        .add_plugin(InputPlugin)
+        .add_plugin(PipelinePlugin)
+        .add_plugin(DocumentPlugin)
+        .add_plugin(WorkspacePlugin)
+        .init_resource::<Keymap>()
+        // Sent by the plugins left out.
+        .add_event::<CoreCommand>()
+        .add_event::<EvictCache>()
+        .add_event::<TypeText>()
+        .add_event::<DeleteText>()
+        .add_plugin(VimPlugin);
+    app.world.spawn().insert(Mode::default());
+    let document = Document::new(None, TextBuffer::from(text));
+    let entity = app
+        .world
+        .spawn()
+        .insert(document)
+        .insert(Cursor::at(0))
+        .insert(Selection { anchor: 0 })
+        .id();
+    app.update();
+    (app, entity)
+}
+
+/// Types `keys` in one frame, `⎋` standing for escape.
+fn press(app: &mut App, keys: &str) {
+    let mut events = app.world.get_resource_mut::<Events<VimKey>>().unwrap();
+    for key in keys.chars() {
+        events.send(match key {
+            '⎋' => VimKey::ESCAPE,
+            key => VimKey(key),
+        });
+    }
+    app.update();
+}
+
+fn text(app: &App, entity: Entity) -> String {
+    app.world
+        .get::<Document>(entity)
+        .unwrap()
+        .buffer()
+        .to_string()
+}
+
+fn cursor(app: &App, entity: Entity) -> usize {
+    app.world.get::<Cursor>(entity).unwrap().offset
+}
+
+fn vim(app: &App) -> &Vim {
+    app.world.get_resource::<Vim>().unwrap()
+}
+
+#[test]
+fn motions_take_counts() {
+    let (mut app, entity) = open("one two three\nfour\n");
+    press(&mut app, "2w");
+    assert_eq!(cursor(&app, entity), 8);
+    press(&mut app, "$");
+    assert_eq!(cursor(&app, entity), 12);
+    press(&mut app, "j0");
+    assert_eq!(cursor(&app, entity), 14);
+    press(&mut app, "gg");
+    assert_eq!(cursor(&app, entity), 0);
+}
+
+#[test]
+fn operators_delete_over_motions_and_lines() {
+    let (mut app, entity) = open("one two three\nfour\nfive\n");
+    press(&mut app, "dw");
+    assert_eq!(text(&app, entity), "two three\nfour\nfive\n");
+    press(&mut app, "2dd");
+    assert_eq!(text(&app, entity), "five\n");
+    press(&mut app, "u");
+    assert_eq!(text(&app, entity), "two three\nfour\nfive\n");
+}
+
+#[test]
+fn yank_and_paste_go_through_registers() {
+    let (mut app, entity) = open("one\ntwo\n");
+    press(&mut app, "\"ayy");
+    assert_eq!(
+        vim(&app).register('a'),
+        Some(&Register {
+            text: "one\n".to_string(),
+            linewise: true,
+        })
+    );
+    press(&mut app, "\"Ayyj\"ap");
+    assert_eq!(text(&app, entity), "one\ntwo\none\none\n");
+    assert_eq!(vim(&app).mode(), ModeType::Normal);
+}
+
+#[test]
+fn dot_repeats_the_last_change() {
+    let (mut app, entity) = open("a b c d\n");
+    press(&mut app, "x..");
+    assert_eq!(text(&app, entity), " c d\n");
+}
+
+#[test]
+fn insert_mode_ends_on_escape() {
+    let (mut app, _) = open("text\n");
+    press(&mut app, "i");
+    assert_eq!(vim(&app).mode(), ModeType::Insert);
+    press(&mut app, "⎋");
+    assert_eq!(vim(&app).mode(), ModeType::Normal);
+}
+
+#[test]
+fn pending_keys_wait_for_the_rest_of_the_command() {
+    let (mut app, entity) = open("one two\n");
+    press(&mut app, "\"a2d");
+    assert_eq!(vim(&app).pending(), "\"a2d");
+    assert_eq!(text(&app, entity), "one two\n");
+    press(&mut app, "q");
+    assert_eq!(vim(&app).pending(), "");
+    assert_eq!(text(&app, entity), "one two\n");
+}
+
+#[test]
+fn visual_mode_deletes_the_selection() {
+    let (mut app, entity) = open("one two\n");
+    press(&mut app, "vld");
+    assert_eq!(text(&app, entity), "e two\n");
+    assert_eq!(vim(&app).mode(), ModeType::Normal);
+}
@@ -0,0 +1,116 @@
+//! Documents take what someone else wrote to their file, as small changes, read-only ones
+//! mapped again off the main thread.
+
+mod common;
+
+use bevy::{
+    app::App,
+    ecs::{
+        entity::Entity,
+        event::{Events, ManualEventReader},
+    },
+};
+use common::{send, text, ScratchDir};
+use dip_core::{
+    announce::Announcement,
+    conflict::ResolveConflict,
+    document::{DiskStamp, Document, DocumentChanged, UndoDocument},
+    idle::Idle,
+    watcher::{FileChangedOnDisk, FileWatcherPlugin, FileWatcherSettings},
+};
+use std::{fs, thread, time::Duration};
+
+/// Replaces `notes.txt` by a rename, the way most programs save, so a mapping of the old
+/// file stays whole.
+fn save(dir: &ScratchDir, text: &str) {
+    let temporary = dir.join("notes.txt~");
+    fs::write(&temporary, text).unwrap();
+    fs::rename(temporary, dir.notes()).unwrap();
+}
+
+/// An app with `document` open, recording the changes made to it.
+fn watching(document: Document) -> (App, Entity) {
+    let mut app = common::app();
+    app.add_plugin(FileWatcherPlugin)
+        .init_resource::<Idle>()
+        // Sent by the plugins left out.
+        .add_event::<ResolveConflict>()
+        .add_event::<Announcement>();
+    let entity = app.world.spawn().insert(document).id();
+    app.update();
+    (app, entity)
+}
+
+/// Tells the app `notes.txt` changed, the way watching it would.
+fn changed_on_disk(app: &mut App, entity: Entity, dir: &ScratchDir) {
+    send(
+        app,
+        FileChangedOnDisk {
+            entity,
+            path: dir.notes(),
+            removed: false,
+            dirty: false,
+        },
+    );
+}
+
+/// Runs frames until the document changes and returns the changes.
+fn changes(app: &mut App, reader: &mut ManualEventReader<DocumentChanged>) -> Vec<DocumentChanged> {
+    for _ in 0..500 {
+        app.update();
+        let events = app.world.get_resource::<Events<DocumentChanged>>().unwrap();
+        let changed: Vec<_> = reader.iter(events).cloned().collect();
+        if !changed.is_empty() {
+            return changed;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("the document did not change");
+}
+
+#[test]
+fn reloads_only_the_lines_that_changed() {
+    let dir = ScratchDir::new("reload");
+    fs::write(dir.notes(), "one\ntwo\nthree\n").unwrap();
+    let (mut app, entity) = watching(Document::from_path(dir.notes()).unwrap());
+    let mut reader = ManualEventReader::default();
+    save(&dir, "one\n2\nthree\nfour\n");
+    changed_on_disk(&mut app, entity, &dir);
+
+    let changed = changes(&mut app, &mut reader);
+    assert_eq!(text(&app, entity), "one\n2\nthree\nfour\n");
+    let ranges: Vec<_> = changed[0].changes.iter().map(|c| c.range.clone()).collect();
+    assert_eq!(ranges, [14..14, 4..8, 4..4]);
+    let document = app.world.get::<Document>(entity).unwrap();
+    assert!(!document.is_dirty());
+
+    send(&mut app, UndoDocument { entity });
+    app.update();
+    assert_eq!(text(&app, entity), "one\ntwo\nthree\n");
+}
+
+#[test]
+fn maps_read_only_documents_again() {
+    let dir = ScratchDir::new("remap");
+    fs::write(dir.notes(), "line\nline\n").unwrap();
+    let (mut app, entity) = watching(Document::map_path(dir.notes()).unwrap());
+    app.world
+        .get_resource_mut::<FileWatcherSettings>()
+        .unwrap()
+        .auto_reload = false;
+    let mut reader = ManualEventReader::default();
+    save(&dir, "line\nline 2\nline\n");
+    changed_on_disk(&mut app, entity, &dir);
+
+    let changed = changes(&mut app, &mut reader);
+    assert_eq!(text(&app, entity), "line\nline 2\nline\n");
+    let change = &changed[0].changes[..];
+    assert_eq!(change.len(), 1);
+    assert_eq!(change[0].range, 9..9);
+    assert_eq!(change[0].text, " 2\nline");
+    assert_eq!(change[0].lines.inserted, 1);
+    let document = app.world.get::<Document>(entity).unwrap();
+    assert!(document.is_read_only());
+    assert!(!document.is_dirty());
+    assert_eq!(document.disk_stamp(), DiskStamp::read(&dir.notes()));
+}
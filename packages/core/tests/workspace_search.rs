@@ -0,0 +1,109 @@
+//! Searches read the files of every root on the task pools, and are cancelled when the roots
+//! change.
+
+mod common;
+
+use bevy::{
+    app::App,
+    ecs::event::Events,
+    tasks::{TaskPool, TaskPoolBuilder},
+};
+use common::ScratchDir;
+use dip_core::{
+    idle::Idle,
+    workspace::{Workspace, WorkspaceChanged},
+    workspace_search::{
+        ActiveWorkspaceSearch, FileMatches, SearchWorkspace, WorkspaceSearch,
+        WorkspaceSearchFinished, WorkspaceSearchPlugin, WorkspaceSearchSettings,
+    },
+};
+use std::{fs, sync::mpsc, thread, time::Duration};
+
+fn app(dir: &ScratchDir) -> App {
+    fs::write(dir.notes(), "one\ntwo\n").unwrap();
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(dir.join("src/two.rs"), "// two, twice: two").unwrap();
+    fs::write(dir.join("src/one.rs"), "// one").unwrap();
+
+    let mut app = common::app();
+    app.insert_resource(Workspace::folder(dir.path()))
+        .init_resource::<Idle>()
+        .add_plugin(WorkspaceSearchPlugin)
+        // Sent by the plugins left out.
+        .add_event::<WorkspaceChanged>();
+    app
+}
+
+fn finished(app: &App) -> Option<WorkspaceSearchFinished> {
+    let events = app
+        .world
+        .get_resource::<Events<WorkspaceSearchFinished>>()
+        .unwrap();
+    let last = events.get_reader().iter(events).last();
+    last.cloned()
+}
+
+#[test]
+fn finds_the_matches_of_every_file() {
+    let dir = ScratchDir::new("find");
+    let mut app = app(&dir);
+    common::send(
+        &mut app,
+        SearchWorkspace {
+            query: "two".into(),
+        },
+    );
+    let mut matches = vec![];
+    for _ in 0..500 {
+        app.update();
+        let events = app.world.get_resource::<Events<FileMatches>>().unwrap();
+        matches.extend(events.get_reader().iter(events).cloned());
+        if let Some(finished) = finished(&app) {
+            assert_eq!(finished.files_searched, 3);
+            break;
+        }
+        thread::sleep(Duration::from_millis(2));
+    }
+
+    let mut found: Vec<_> = matches.into_iter().map(|m| (m.path, m.ranges)).collect();
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+    found.dedup();
+    assert_eq!(
+        found,
+        [
+            (dir.notes(), vec![4..7; 1]),
+            (dir.join("src/two.rs"), vec![3..6, 15..18]),
+        ]
+    );
+}
+
+#[test]
+fn changing_the_roots_cancels_the_search() {
+    let dir = ScratchDir::new("cancel");
+    let mut app = app(&dir);
+    app.update();
+
+    // The only thread of the pool is held, so the search cannot get anywhere.
+    let pool: TaskPool = TaskPoolBuilder::new().num_threads(1).build();
+    let (release, held) = mpsc::channel::<()>();
+    pool.spawn(async move { held.recv() }).detach();
+    let roots = app
+        .world
+        .get_resource::<Workspace>()
+        .unwrap()
+        .roots()
+        .to_vec();
+    let settings = WorkspaceSearchSettings::default();
+    let search = WorkspaceSearch::start(&pool, &roots, "two".into(), &settings);
+    app.world
+        .get_resource_mut::<ActiveWorkspaceSearch>()
+        .unwrap()
+        .search = Some(search);
+
+    common::send(&mut app, WorkspaceChanged);
+    app.update();
+    let active = app.world.get_resource::<ActiveWorkspaceSearch>().unwrap();
+    assert!(active.search.is_none());
+    assert!(finished(&app).is_none());
+    release.send(()).unwrap();
+}
@@ -1,4 +1,9 @@
 #![allow(non_snake_case)]
 
+pub mod closed_tabs;
+pub mod editor;
+pub mod live_region;
+pub mod playback;
 pub mod root;
 pub mod status_bar;
+pub mod tabs;
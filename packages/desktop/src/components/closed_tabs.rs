@@ -0,0 +1,58 @@
+use dioxus::prelude::*;
+use dip_core::command::{CoreCommand, UICommand};
+use std::path::PathBuf;
+
+/// Lists recently closed tabs, most recent first, to pick one to reopen.
+pub fn ClosedTabs(cx: Scope) -> Element {
+    let window = use_bevy_window::<CoreCommand, UICommand>(&cx);
+    let closed = use_state(&cx, Vec::<PathBuf>::new);
+    let expanded = use_state(&cx, || false);
+
+    use_future(&cx, (), |_| {
+        let mut rx = window.receiver();
+        let closed = closed.clone();
+
+        async move {
+            while let Ok(cmd) = rx.recv().await {
+                if let UICommand::RecentlyClosed(next) = cmd {
+                    *closed.make_mut() = next;
+                }
+            }
+        }
+    });
+
+    if closed.is_empty() {
+        return cx.render(rsx! { div {} });
+    }
+    let entries = closed.iter().enumerate().map(|(i, path)| {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let title = path.display();
+        rsx! {
+            li {
+                key: "{title}",
+                title: "{title}",
+                style: "cursor: pointer;",
+                onclick: move |_| {
+                    window.send(CoreCommand::ReopenClosedTab(i)).unwrap();
+                    expanded.set(false);
+                },
+                "{name}"
+            }
+        }
+    });
+
+    cx.render(rsx! {
+        div {
+            button {
+                onclick: move |_| expanded.set(!expanded.get()),
+                "Recently Closed",
+            }
+            expanded.get().then(|| rsx! {
+                ul {
+                    style: "list-style: none; margin: 0; padding: var(--dip-space-1);",
+                    entries
+                }
+            })
+        }
+    })
+}
@@ -0,0 +1,210 @@
+use dioxus::prelude::*;
+use dip_core::{
+    command::{CoreCommand, UICommand},
+    gutter::{FoldArrow, Gutter, GutterArea, GutterClick},
+    minimap::Minimap,
+    presence::PresenceTags,
+    render::Frame,
+    scroll::WheelUnit,
+};
+
+/// The document on screen, drawn from the lines the core laid out in the last [`Frame`],
+/// with its [`Gutter`] on the left, its [`Minimap`] on the right and the carets of others
+/// in a shared session over it.
+pub fn Editor(cx: Scope) -> Element {
+    let window = use_bevy_window::<CoreCommand, UICommand>(&cx);
+    let frame = use_state(&cx, || None::<Frame>);
+    let gutter = use_state(&cx, || None::<Gutter>);
+    let minimap = use_state(&cx, || None::<Minimap>);
+    let presence = use_state(&cx, || None::<PresenceTags>);
+    // Where the pointer and the mark of the part in view were when dragging started.
+    let drag = use_state(&cx, || None::<(i32, f32)>);
+
+    use_future(&cx, (), |_| {
+        let mut rx = window.receiver();
+        let frame = frame.clone();
+        let gutter = gutter.clone();
+        let minimap = minimap.clone();
+        let presence = presence.clone();
+
+        async move {
+            while let Ok(cmd) = rx.recv().await {
+                match cmd {
+                    UICommand::Render(next) => *frame.make_mut() = Some(next),
+                    UICommand::Gutter(next) => *gutter.make_mut() = Some(next),
+                    UICommand::Minimap(next) => *minimap.make_mut() = next,
+                    UICommand::Presence(next) => *presence.make_mut() = Some(next),
+                    _ => {}
+                }
+            }
+        }
+    });
+
+    let frame = match frame.get() {
+        Some(frame) => frame,
+        None => return cx.render(rsx! { div {} }),
+    };
+    let line_height = frame.line_height;
+    let rows = frame.lines.iter().flat_map(|line| {
+        line.rows.iter().enumerate().map(move |(row, runs)| {
+            let top = line.top + row as f32 * line_height;
+            rsx! {
+                div {
+                    key: "{line.line}-{row}",
+                    style: "position: absolute; top: {top}px; height: {line_height}px; white-space: pre;",
+                    runs.iter().map(|run| rsx! {
+                        span {
+                            style: "position: absolute; left: {run.x}px; color: {run.color};",
+                            "{run.text}"
+                        }
+                    })
+                }
+            }
+        })
+    });
+
+    let carets = presence
+        .get()
+        .iter()
+        .filter(|presence| presence.entity == frame.entity)
+        .flat_map(|presence| &presence.tags)
+        .map(|tag| {
+            rsx! {
+                div {
+                    style: "position: absolute; top: {tag.top}px; left: {tag.x}px; \
+                            height: {line_height}px; border-left: 2px solid {tag.color}; \
+                            opacity: {tag.opacity}; pointer-events: none;",
+                    span {
+                        style: "position: absolute; bottom: 100%; left: -2px; \
+                                padding: 0 var(--dip-space-1); white-space: nowrap; \
+                                background: {tag.color}; color: var(--dip-color-editor-background);",
+                        "{tag.name}"
+                    }
+                }
+            }
+        });
+
+    let gutter_width = gutter.get().as_ref().map_or(0., |gutter| gutter.width);
+    let gutter_lines = gutter.get().iter().flat_map(|gutter| &gutter.lines).map(|line| {
+        let top = line.top;
+        let click = move |area| {
+            window
+                .send(CoreCommand::GutterClick(GutterClick { y: top, area }))
+                .unwrap();
+        };
+        let number = line.number.clone().unwrap_or_default();
+        let weight = if line.current { "bold" } else { "normal" };
+        let arrow = match line.fold {
+            Some(FoldArrow::Open) => "⌄",
+            Some(FoldArrow::Folded) => "›",
+            None => "",
+        };
+        let marker = match (line.breakpoint, line.diagnostic) {
+            (true, _) => "color: var(--dip-color-accent);".to_string(),
+            (false, Some(marker)) => format!("color: {};", marker.color),
+            (false, None) => "visibility: hidden;".to_string(),
+        };
+        rsx! {
+            div {
+                key: "{line.line}",
+                style: "position: absolute; top: {top}px; height: {line_height}px; display: flex;",
+                span {
+                    style: "width: var(--dip-space-4); {marker}",
+                    onclick: move |_| click(GutterArea::Marker),
+                    "●"
+                }
+                span {
+                    style: "width: {gutter_width}px; text-align: right; font-weight: {weight};",
+                    onclick: move |_| click(GutterArea::LineNumber),
+                    "{number}"
+                }
+                span {
+                    style: "width: var(--dip-space-4); text-align: center;",
+                    onclick: move |_| click(GutterArea::Fold),
+                    "{arrow}"
+                }
+            }
+        }
+    });
+
+    let (scrolled, scrolled_left) = (frame.top, frame.left);
+    let minimap = minimap.get().as_ref().map(|minimap| {
+        let (row_height, char_width) = (minimap.row_height, minimap.char_width);
+        let blocks = minimap.rows.iter().enumerate().flat_map(|(row, blocks)| {
+            let top = row as f32 * row_height;
+            blocks.iter().map(move |block| {
+                let (left, width) = (
+                    block.column as f32 * char_width,
+                    block.len as f32 * char_width,
+                );
+                rsx! {
+                    div {
+                        style: "position: absolute; top: {top}px; left: {left}px; \
+                                width: {width}px; height: {row_height}px; \
+                                background: {block.color};",
+                    }
+                }
+            })
+        });
+        let (shown, top, height) = (minimap.shown.clone(), minimap.top, minimap.height());
+        let width = minimap.width;
+        let mark = shown.end - shown.start;
+        rsx! {
+            div {
+                style: "position: relative; overflow: hidden; width: calc({width}px + var(--dip-space-2)); \
+                        background: var(--dip-color-editor-background);",
+                onmousemove: move |e| {
+                    if let Some((client_y, from)) = *drag.get() {
+                        let y = from + (e.data.client_y - client_y) as f32;
+                        window.send(CoreCommand::MinimapDrag { y }).unwrap();
+                    }
+                },
+                onmouseup: move |_| drag.set(None),
+                onmouseleave: move |_| drag.set(None),
+                div {
+                    style: "position: absolute; top: -{top}px; width: 100%; height: {height}px;",
+                    blocks
+                    div {
+                        style: "position: absolute; top: {shown.start}px; width: 100%; \
+                                height: {mark}px; background: var(--dip-color-editor-selection); \
+                                opacity: 0.4;",
+                        onmousedown: move |e| drag.set(Some((e.data.client_y, shown.start))),
+                    }
+                }
+            }
+        }
+    });
+
+    cx.render(rsx! {
+        div {
+            style: "position: relative; overflow: hidden; flex: 1; display: flex;",
+            onwheel: move |e| {
+                let unit = match e.data.delta_mode {
+                    1 => WheelUnit::Line,
+                    2 => WheelUnit::Page,
+                    _ => WheelUnit::Pixel,
+                };
+                let (x, y) = (e.data.delta_x as f32, e.data.delta_y as f32);
+                window.send(CoreCommand::Wheel { x, y, unit }).unwrap();
+            },
+            div {
+                style: "position: relative; height: {frame.height}px; \
+                        transform: translateY(-{scrolled}px); \
+                        min-width: calc({gutter_width}px + 2 * var(--dip-space-4)); \
+                        background: var(--dip-color-gutter-background); \
+                        color: var(--dip-color-gutter-foreground);",
+                gutter_lines
+            }
+            div {
+                style: "position: relative; flex: 1; overflow: hidden;",
+                div {
+                    style: "position: relative; height: {frame.height}px; \
+                            transform: translate(-{scrolled_left}px, -{scrolled}px);",
+                    rows
+                    carets
+                }
+            }
+            minimap
+        }
+    })
+}
@@ -0,0 +1,53 @@
+use dioxus::prelude::*;
+use dip_core::command::{CoreCommand, UICommand};
+
+/// Hidden from sight, so only screen readers see it.
+const HIDDEN: &str = "position: absolute; width: 1px; height: 1px; overflow: hidden; \
+                      clip: rect(0 0 0 0); white-space: nowrap;";
+
+/// Live regions screen readers read [`UICommand::Announce`] out of, one waiting for what is
+/// being read and one interrupting it. Each announcement replaces the text node, so the
+/// same text is read out again.
+pub fn LiveRegion(cx: Scope) -> Element {
+    let window = use_bevy_window::<CoreCommand, UICommand>(&cx);
+    let polite = use_state(&cx, || (0, String::new()));
+    let assertive = use_state(&cx, || (0, String::new()));
+
+    use_future(&cx, (), |_| {
+        let mut rx = window.receiver();
+        let polite = polite.clone();
+        let assertive = assertive.clone();
+
+        async move {
+            while let Ok(cmd) = rx.recv().await {
+                if let UICommand::Announce(announcement) = cmd {
+                    let region = match announcement.assertive {
+                        true => &assertive,
+                        false => &polite,
+                    };
+                    let mut region = region.make_mut();
+                    region.0 += 1;
+                    region.1 = announcement.text;
+                }
+            }
+        }
+    });
+
+    let (polite_count, polite_text) = polite.get();
+    let (assertive_count, assertive_text) = assertive.get();
+
+    cx.render(rsx! {
+        div {
+            style: "{HIDDEN}",
+            role: "status",
+            "aria-live": "polite",
+            span { key: "{polite_count}", "{polite_text}" }
+        }
+        div {
+            style: "{HIDDEN}",
+            role: "alert",
+            "aria-live": "assertive",
+            span { key: "{assertive_count}", "{assertive_text}" }
+        }
+    })
+}
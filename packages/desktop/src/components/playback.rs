@@ -0,0 +1,62 @@
+use dioxus::prelude::*;
+use dip_core::command::{CoreCommand, UICommand};
+use dip_core::playback::{PlaybackControl, PlaybackState};
+
+/// Plays, pauses and seeks the replay of a document's edits while it is on screen.
+pub fn Scrubber(cx: Scope) -> Element {
+    let window = use_bevy_window::<CoreCommand, UICommand>(&cx);
+    let state = use_state(&cx, || None::<PlaybackState>);
+
+    use_future(&cx, (), |_| {
+        let mut rx = window.receiver();
+        let state = state.clone();
+
+        async move {
+            while let Ok(cmd) = rx.recv().await {
+                if let UICommand::Playback(next) = cmd {
+                    *state.make_mut() = next;
+                }
+            }
+        }
+    });
+
+    let state = match state.get() {
+        Some(state) => state,
+        None => return cx.render(rsx! { div {} }),
+    };
+    let (label, control) = match state.playing {
+        true => ("⏸", PlaybackControl::Pause),
+        false => ("▶", PlaybackControl::Play),
+    };
+
+    cx.render(rsx! {
+        div {
+            style: "background: var(--dip-color-status-bar-background); \
+                    color: var(--dip-color-status-bar-foreground); \
+                    padding: var(--dip-space-1) var(--dip-space-2); \
+                    display: flex; align-items: center; gap: var(--dip-space-2);",
+            button {
+                onclick: move |_| window.send(CoreCommand::Playback(control)).unwrap(),
+                "{label}"
+            }
+            input {
+                r#type: "range",
+                style: "flex: 1;",
+                min: "0",
+                max: "{state.steps}",
+                value: "{state.position}",
+                oninput: move |e| {
+                    if let Ok(position) = e.data.value.parse() {
+                        window.send(CoreCommand::Playback(PlaybackControl::Seek(position))).unwrap();
+                    }
+                },
+            }
+            span {
+                [format_args!(
+                    "{}/{} · {:.1}s/{:.1}s",
+                    state.position, state.steps, state.elapsed, state.duration
+                )]
+            }
+        }
+    })
+}
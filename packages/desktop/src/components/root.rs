@@ -1,23 +1,58 @@
-use crate::components::status_bar;
+use crate::components::{closed_tabs, editor, live_region, playback, status_bar, tabs};
 use bevy::log::info;
-use dioxus::{bevy::prelude::*, prelude::*};
-use dip_core::command::{CoreCommand, UICommand};
+use dioxus::{bevy::prelude::*, desktop::use_window, prelude::*};
+use dip_core::{
+    command::{CoreCommand, UICommand},
+    theme::DesignTokens,
+};
 
 pub fn Root(cx: Scope) -> Element {
     let window = use_bevy_window::<CoreCommand, UICommand>(&cx);
+    let desktop = use_window(&cx);
+    let tokens = use_state(&cx, DesignTokens::default);
 
     use_future(&cx, (), |_| {
         let mut rx = window.receiver();
+        let desktop = desktop.clone();
+        let tokens = tokens.clone();
 
         async move {
             while let Ok(cmd) = rx.recv().await {
-                info!("🎨 {:?}", cmd);
+                match cmd {
+                    // Sent every frame something is drawn.
+                    UICommand::Render(_)
+                    | UICommand::Gutter(_)
+                    | UICommand::Minimap(_)
+                    | UICommand::Presence(_)
+                    | UICommand::StatusBar(_)
+                    | UICommand::Playback(_)
+                    | UICommand::RecentlyClosed(_)
+                    | UICommand::Tabs(_)
+                    | UICommand::Announce(_) => {}
+                    UICommand::FocusWindow => desktop.focus(),
+                    UICommand::ThemeChange(next) => {
+                        info!("🎨 Color theme {}", next.name);
+                        *tokens.make_mut() = next;
+                    }
+                    cmd => info!("🎨 {:?}", cmd),
+                }
             }
         }
     });
 
+    // Components below style themselves with the variables, e.g.
+    // `var(--dip-color-editor-foreground)`.
+    let style = format!(
+        "{} background: var(--dip-color-editor-background); \
+         color: var(--dip-color-editor-foreground); \
+         font-family: var(--dip-font-family); font-size: var(--dip-font-size); \
+         line-height: var(--dip-line-height);",
+        tokens.css_variables()
+    );
+
     cx.render(rsx! {
         div {
+            style: "{style}",
             h1 { "dip: Text Editor" },
             button {
                 onclick: |_e| {
@@ -25,7 +60,18 @@ pub fn Root(cx: Scope) -> Element {
                 },
                 "Exit",
             }
+            button {
+                onclick: |_e| {
+                    window.send(CoreCommand::Run("file.close".into())).unwrap();
+                },
+                "Close",
+            }
+            closed_tabs::ClosedTabs {}
+            tabs::TabStrip {}
+            editor::Editor {}
+            playback::Scrubber {}
             status_bar::StatusBar {}
+            live_region::LiveRegion {}
         }
     })
 }
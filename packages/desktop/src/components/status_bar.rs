@@ -1,14 +1,17 @@
 use dioxus::prelude::*;
 use dip_core::command::{CoreCommand, UICommand};
+use dip_core::status_bar::{Alignment, StatusSegment};
 use dip_core::ModeType;
 
 pub fn StatusBar(cx: Scope) -> Element {
     let window = use_bevy_window::<CoreCommand, UICommand>(&cx);
     let mode_type = use_state(&cx, || ModeType::Normal);
+    let segments = use_state(&cx, Vec::<StatusSegment>::new);
 
     use_future(&cx, (), |_| {
         let mut rx = window.receiver();
         let mode_type = mode_type.clone();
+        let segments = segments.clone();
 
         async move {
             while let Ok(cmd) = rx.recv().await {
@@ -16,14 +19,61 @@ pub fn StatusBar(cx: Scope) -> Element {
                     UICommand::ModeChange(m) => {
                         *mode_type.make_mut() = m.0;
                     }
+                    UICommand::StatusBar(next) => *segments.make_mut() = next,
+                    UICommand::ThemeChange(_)
+                    | UICommand::Render(_)
+                    | UICommand::Gutter(_)
+                    | UICommand::Minimap(_)
+                    | UICommand::Presence(_)
+                    | UICommand::Playback(_)
+                    | UICommand::RecentlyClosed(_)
+                    | UICommand::Tabs(_)
+                    | UICommand::Announce(_)
+                    | UICommand::FocusWindow => {}
                 }
             }
         }
     });
 
+    let side = |alignment: Alignment| {
+        segments
+            .get()
+            .iter()
+            .filter(move |s| s.alignment == alignment && !s.text.is_empty())
+            .map(|segment| {
+                let title = segment.tooltip.clone().unwrap_or_default();
+                let command = segment.command.clone();
+                let cursor = if command.is_some() {
+                    "pointer"
+                } else {
+                    "default"
+                };
+                rsx! {
+                    span {
+                        key: "{segment.id}",
+                        title: "{title}",
+                        style: "padding: 0 var(--dip-space-2); cursor: {cursor};",
+                        onclick: move |_| {
+                            if let Some(command) = &command {
+                                window.send(CoreCommand::Run(command.clone())).unwrap();
+                            }
+                        },
+                        "{segment.text}"
+                    }
+                }
+            })
+    };
+
     cx.render(rsx! {
         div {
+            style: "background: var(--dip-color-status-bar-background); \
+                    color: var(--dip-color-status-bar-foreground); \
+                    padding: var(--dip-space-1) var(--dip-space-2); \
+                    display: flex;",
             div { [format_args!("Mode: {mode_type:?}")] }
+            side(Alignment::Left)
+            div { style: "flex: 1;" }
+            side(Alignment::Right)
         }
     })
 }
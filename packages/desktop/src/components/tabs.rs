@@ -0,0 +1,69 @@
+use dioxus::prelude::*;
+use dip_core::command::{CoreCommand, UICommand};
+use dip_core::tab::{self, TabAction};
+
+/// The tabs of the editor group. A preview tab is shown in italic until it is double-clicked
+/// or edited, and pinned tabs stay at the front.
+pub fn TabStrip(cx: Scope) -> Element {
+    let window = use_bevy_window::<CoreCommand, UICommand>(&cx);
+    let strip = use_state(&cx, || tab::TabStrip {
+        tabs: vec![],
+        active: None,
+    });
+
+    use_future(&cx, (), |_| {
+        let mut rx = window.receiver();
+        let strip = strip.clone();
+
+        async move {
+            while let Ok(cmd) = rx.recv().await {
+                if let UICommand::Tabs(next) = cmd {
+                    *strip.make_mut() = next;
+                }
+            }
+        }
+    });
+
+    let tabs = strip.tabs.iter().enumerate().map(|(i, tab)| {
+        let name = tab.path.file_name().unwrap_or_default().to_string_lossy();
+        let title = tab.path.display();
+        let style = match tab.preview {
+            true => "font-style: italic;",
+            false => "",
+        };
+        let border = match strip.active == Some(i) {
+            true => "var(--dip-color-editor-foreground)",
+            false => "transparent",
+        };
+        let (pin_label, pin) = match tab.pinned {
+            true => ("📌", TabAction::Unpin(i)),
+            false => ("📍", TabAction::Pin(i)),
+        };
+        rsx! {
+            div {
+                key: "{title}",
+                title: "{title}",
+                style: "{style} cursor: pointer; padding: 0 var(--dip-space-2); \
+                        border-bottom: 2px solid {border};",
+                onclick: move |_| window.send(CoreCommand::Tab(TabAction::Focus(i))).unwrap(),
+                ondblclick: move |_| window.send(CoreCommand::Tab(TabAction::Keep(i))).unwrap(),
+                "{name}"
+                button {
+                    onclick: move |_| window.send(CoreCommand::Tab(pin)).unwrap(),
+                    "{pin_label}"
+                }
+                button {
+                    onclick: move |_| window.send(CoreCommand::Tab(TabAction::Close(i))).unwrap(),
+                    "×"
+                }
+            }
+        }
+    });
+
+    cx.render(rsx! {
+        div {
+            style: "display: flex; gap: var(--dip-space-1);",
+            tabs
+        }
+    })
+}
@@ -1,6 +1,19 @@
 use bevy::app::App;
+use dip_core::cli::{Invocation, USAGE};
 use dip_desktop::prelude::DipDesktopPlugin;
+use std::{env, process};
 
 fn main() {
-    App::new().add_plugin(DipDesktopPlugin).run();
+    let invocation = match Invocation::parse(env::args().skip(1)) {
+        Ok(invocation) => invocation,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            process::exit(2);
+        }
+    };
+
+    App::new()
+        .insert_resource(invocation)
+        .add_plugin(DipDesktopPlugin)
+        .run();
 }
@@ -0,0 +1,233 @@
+//! Line diffs and three-way merges, as used to reload files changed on disk and by the
+//! diff and merge views.
+
+use std::ops::Range;
+
+/// Lines `old` of the left side were replaced by lines `new` of the right side.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hunk {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+/// Smallest set of hunks turning `a` into `b`, using Myers' algorithm in linear space.
+pub fn diff<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Hunk> {
+    let mut matches = vec![];
+    conquer(a, b, (0, 0), &mut matches);
+
+    let mut hunks = vec![];
+    let (mut i, mut j) = (0, 0);
+    for (x, y) in matches.into_iter().chain(Some((a.len(), b.len()))) {
+        if x > i || y > j {
+            hunks.push(Hunk {
+                old: i..x,
+                new: j..y,
+            });
+        }
+        i = x + 1;
+        j = y + 1;
+    }
+    hunks
+}
+
+/// Adds the matching index pairs of a shortest edit script to `matches`, in order, with
+/// `at` the position of `a` and `b` in the whole. The common prefix and suffix are matched
+/// first, then what is left is split where a shortest path crosses its middle, and both
+/// parts are diffed the same way.
+fn conquer<T: PartialEq>(a: &[T], b: &[T], at: (usize, usize), matches: &mut Vec<(usize, usize)>) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    matches.extend((0..prefix).map(|i| (at.0 + i, at.1 + i)));
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let at = (at.0 + prefix, at.1 + prefix);
+
+    let suffix = a
+        .iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a, b) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+
+    if !a.is_empty() && !b.is_empty() {
+        if let Some((x, y)) = middle(a, b) {
+            conquer(&a[..x], &b[..y], at, matches);
+            conquer(&a[x..], &b[y..], (at.0 + x, at.1 + y), matches);
+        }
+    }
+    matches.extend((0..suffix).map(|i| (at.0 + a.len() + i, at.1 + b.len() + i)));
+}
+
+/// Where a shortest path through the edit graph of `a` and `b` crosses its middle, found
+/// by searching from both ends until the paths meet. Paths leaving the graph stop being
+/// followed. None when `a` and `b` have nothing in common.
+fn middle<T: PartialEq>(a: &[T], b: &[T]) -> Option<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m + 1) / 2;
+    let offset = max;
+    // Furthest x reached on each diagonal, -1 where none was yet.
+    let mut forward = vec![-1isize; 2 * max as usize + 2];
+    let mut backward = forward.clone();
+    forward[offset as usize + 1] = 0;
+    backward[offset as usize + 1] = 0;
+    let delta = n - m;
+    // Which search can meet the other first depends on the parity of `delta`.
+    let odd = delta % 2 != 0;
+    let (mut forward_start, mut forward_end) = (0, 0);
+    let (mut backward_start, mut backward_end) = (0, 0);
+    let at = |k: isize| (k + offset) as usize;
+    let opposite = |k: isize| {
+        let k = offset + delta - k;
+        (0..2 * max + 2).contains(&k).then_some(k as usize)
+    };
+
+    for d in 0..max {
+        for k in (-d + forward_start..=d - forward_end).step_by(2) {
+            let mut x = if k == -d || (k != d && forward[at(k - 1)] < forward[at(k + 1)]) {
+                forward[at(k + 1)]
+            } else {
+                forward[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[at(k)] = x;
+            if x > n {
+                forward_end += 2;
+            } else if y > m {
+                forward_start += 2;
+            } else if odd {
+                if let Some(other) = opposite(k).filter(|&i| backward[i] != -1) {
+                    if x >= n - backward[other] {
+                        return Some((x as usize, y as usize));
+                    }
+                }
+            }
+        }
+
+        for k in (-d + backward_start..=d - backward_end).step_by(2) {
+            let mut x = if k == -d || (k != d && backward[at(k - 1)] < backward[at(k + 1)]) {
+                backward[at(k + 1)]
+            } else {
+                backward[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[(n - x - 1) as usize] == b[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[at(k)] = x;
+            if x > n {
+                backward_end += 2;
+            } else if y > m {
+                backward_start += 2;
+            } else if !odd {
+                if let Some(other) = opposite(k).filter(|&i| forward[i] != -1) {
+                    let forward_x = forward[other];
+                    let forward_y = forward_x - (other as isize - offset);
+                    if forward_x >= n - x {
+                        return Some((forward_x as usize, forward_y as usize));
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Outcome of a three-way merge. Conflicting regions are wrapped in git style markers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Merged {
+    pub text: String,
+    pub conflicts: usize,
+}
+
+/// Merges the changes `ours` and `theirs` made to `base`, line by line. Changes touching
+/// the same or adjacent lines conflict unless both sides made the same change.
+pub fn merge3(base: &str, ours: &str, theirs: &str) -> Merged {
+    let base: Vec<&str> = base.split_inclusive('\n').collect();
+    let ours: Vec<&str> = ours.split_inclusive('\n').collect();
+    let theirs: Vec<&str> = theirs.split_inclusive('\n').collect();
+    let sides = [diff(&base, &ours), diff(&base, &theirs)];
+
+    let mut text = String::new();
+    let mut conflicts = 0;
+    let mut next = [0, 0];
+    // Lines the hunks before the current group added to each side.
+    let mut shift = [0isize, 0];
+    let mut copied = 0;
+
+    loop {
+        let first = (0..2)
+            .filter_map(|s| sides[s].get(next[s]).map(|h| (h.old.start, s)))
+            .min();
+        let (start, side) = match first {
+            Some(first) => first,
+            None => break,
+        };
+
+        // Grow the group until no hunk on either side touches it.
+        let mut end = sides[side][next[side]].old.end;
+        let mut taken = [next[0], next[1]];
+        loop {
+            let mut grew = false;
+            for s in 0..2 {
+                while let Some(h) = sides[s].get(taken[s]) {
+                    if h.old.start > end {
+                        break;
+                    }
+                    end = end.max(h.old.end);
+                    taken[s] += 1;
+                    grew = true;
+                }
+            }
+            if !grew {
+                break;
+            }
+        }
+
+        text.extend(base[copied..start].iter().copied());
+        let lines = [&ours, &theirs];
+        let mut versions: [&[&str]; 2] = [&[], &[]];
+        for s in 0..2 {
+            let added: isize = sides[s][next[s]..taken[s]]
+                .iter()
+                .map(|h| h.new.len() as isize - h.old.len() as isize)
+                .sum();
+            let from = (start as isize + shift[s]) as usize;
+            let to = (end as isize + shift[s] + added) as usize;
+            versions[s] = &lines[s][from..to];
+            shift[s] += added;
+        }
+
+        let changed = [taken[0] > next[0], taken[1] > next[1]];
+        match changed {
+            [true, false] => text.extend(versions[0].iter().copied()),
+            [false, true] => text.extend(versions[1].iter().copied()),
+            _ if versions[0] == versions[1] => text.extend(versions[0].iter().copied()),
+            _ => {
+                conflicts += 1;
+                text.push_str("<<<<<<< ours\n");
+                push_lines(&mut text, versions[0]);
+                text.push_str("=======\n");
+                push_lines(&mut text, versions[1]);
+                text.push_str(">>>>>>> theirs\n");
+            }
+        }
+
+        next = taken;
+        copied = end;
+    }
+    text.extend(base[copied..].iter().copied());
+
+    Merged { text, conflicts }
+}
+
+/// Lines ending a file without a line break get one, so markers start on their own line.
+fn push_lines(text: &mut String, lines: &[&str]) {
+    text.extend(lines.iter().copied());
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+}
@@ -0,0 +1,944 @@
+//! A file as it is edited: its text, where it came from and how it is written back, and
+//! the changes made to it since they were last taken.
+
+#[cfg(unix)]
+use crate::text_buffer::Mapping;
+use crate::{
+    diff::diff,
+    history::EditHistory,
+    markers::{shift_range, Stickiness},
+    text_buffer::{search::regex_captures, TextBuffer, TextBufferBuilder},
+    vault::{self, VaultError, VaultKey},
+};
+#[cfg(feature = "bevy")]
+use bevy::ecs::component::Component;
+use regex::Regex;
+use std::{
+    error, fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Read, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+/// How much of a file is read at a time.
+pub const READ_CHUNK_SIZE: usize = 64 * 1024;
+const BOM: char = '\u{feff}';
+
+/// Larger files are refused instead of loaded into memory, unless they are mapped.
+pub const MAX_DOCUMENT_SIZE: u64 = 1024 * 1024 * 1024;
+
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "bevy", derive(Component))]
+pub struct Document {
+    path: Option<PathBuf>,
+    buffer: TextBuffer,
+    history: EditHistory,
+    /// Bumped on every change to the buffer.
+    version: u64,
+    /// Changed since it was loaded or last saved.
+    dirty: bool,
+    /// Changes since the last [`Document::take_changes`].
+    changes: Vec<Change>,
+    line_ending: LineEnding,
+    /// Whether the file started with a UTF-8 byte order mark, which is kept out of the
+    /// buffer and written back on save.
+    bom: bool,
+    /// Edits are ignored, e.g. for a memory-mapped file.
+    read_only: bool,
+    /// Edits outside these are refused, e.g. for a participant of a shared session the
+    /// host restricted. None allows them anywhere.
+    editable: Option<Vec<Range<usize>>>,
+    /// Ranges of edits refused since the last [`Document::take_rejected`].
+    rejected: Vec<Range<usize>>,
+    /// Encrypts the file on save, for documents in a vault.
+    key: Option<Arc<VaultKey>>,
+    /// The file as last read or written, to notice it changed underneath.
+    disk: Option<DiskStamp>,
+}
+
+/// What a file looked like on disk. Changes when anyone writes to it, like an etag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DiskStamp {
+    pub modified: Option<SystemTime>,
+    pub len: u64,
+}
+
+impl DiskStamp {
+    pub fn new(metadata: &fs::Metadata) -> Self {
+        Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        }
+    }
+
+    /// None if the file doesn't exist.
+    pub fn read(path: &Path) -> Option<Self> {
+        fs::metadata(path).ok().map(|m| Self::new(&m))
+    }
+}
+
+/// Line ending written for line feeds on save, detected from the first line of the file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn detect(buffer: &TextBuffer) -> Self {
+        if buffer.line_count() < 2 {
+            return LineEnding::default();
+        }
+        // The length leaves out a carriage return before the line feed.
+        if buffer.get_line_length(0) < buffer.line_range(0).len() {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        }
+    }
+}
+
+/// One mutation of the buffer, in the coordinates of the text before it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    /// Replaced byte range.
+    pub range: Range<usize>,
+    pub start: Utf16Position,
+    pub end: Utf16Position,
+    pub text: String,
+    pub lines: LineChange,
+}
+
+/// Line and column in UTF-16 code units, the way language servers count them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Utf16Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Lines `start..=start + removed` were replaced by lines `start..=start + inserted`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineChange {
+    pub start: usize,
+    pub removed: usize,
+    pub inserted: usize,
+}
+
+impl Document {
+    pub fn new(path: Option<PathBuf>, buffer: TextBuffer) -> Self {
+        Self {
+            path,
+            history: EditHistory::default(),
+            version: 0,
+            dirty: false,
+            changes: vec![],
+            line_ending: LineEnding::detect(&buffer),
+            bom: false,
+            read_only: false,
+            editable: None,
+            rejected: vec![],
+            key: None,
+            disk: None,
+            buffer,
+        }
+    }
+
+    /// Reads the file at `path`, which the document keeps for saving.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, DocumentError> {
+        let path = path.as_ref();
+        let mut file = File::open(path)?;
+        let metadata = file.metadata()?;
+        let size = metadata.len();
+        if size > MAX_DOCUMENT_SIZE {
+            return Err(DocumentError::TooLarge(size));
+        }
+
+        let mut builder = TextBufferBuilder::new();
+        let mut chunk = vec![0; READ_CHUNK_SIZE];
+        let mut decoder = Utf8Decoder::default();
+        let mut first = true;
+        let mut bom = false;
+        loop {
+            let n = file.read(&mut chunk)?;
+            if n == 0 {
+                break;
+            }
+            if first && vault::is_encrypted(&chunk[..n]) {
+                return Err(DocumentError::Encrypted);
+            }
+            let mut text = decoder.push(&chunk[..n])?;
+            if first && !text.is_empty() {
+                first = false;
+                if let Some(rest) = text.strip_prefix(BOM) {
+                    text = rest;
+                    bom = true;
+                }
+            }
+            builder.accept_chunk(text);
+        }
+        decoder.finish()?;
+
+        let mut document = Self::new(Some(path.to_path_buf()), builder.finish());
+        document.bom = bom;
+        document.disk = Some(DiskStamp::new(&metadata));
+        Ok(document)
+    }
+
+    /// Reads a file of a vault, decrypting it with `key`, which the document keeps for
+    /// saving. A file still in plain text is read as is and encrypted on the next save.
+    pub fn from_encrypted(
+        path: impl AsRef<Path>,
+        key: Arc<VaultKey>,
+    ) -> Result<Self, DocumentError> {
+        let path = path.as_ref();
+        let metadata = fs::metadata(path)?;
+        let size = metadata.len();
+        if size > MAX_DOCUMENT_SIZE {
+            return Err(DocumentError::TooLarge(size));
+        }
+        let bytes = fs::read(path)?;
+        let plain = if vault::is_encrypted(&bytes) {
+            key.open(&bytes).map_err(DocumentError::Vault)?
+        } else {
+            bytes
+        };
+        let text = String::from_utf8(plain).map_err(|_| DocumentError::InvalidUtf8)?;
+        let (text, bom) = match text.strip_prefix(BOM) {
+            Some(rest) => (rest, true),
+            None => (text.as_str(), false),
+        };
+
+        let mut document = Self::new(Some(path.to_path_buf()), TextBuffer::from(text));
+        document.bom = bom;
+        document.key = Some(key);
+        document.disk = Some(DiskStamp::new(&metadata));
+        Ok(document)
+    }
+
+    /// Maps the file at `path` into memory instead of reading it, for files too large to
+    /// copy. The document is read-only. Its lines are still indexed here, see
+    /// [`TextBuffer::from_mapping`].
+    #[cfg(unix)]
+    pub fn map_path(path: impl AsRef<Path>) -> Result<Self, DocumentError> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let mapping = Mapping::new(&file)?;
+        let bom = mapping
+            .bytes()
+            .starts_with(BOM.encode_utf8(&mut [0; 3]).as_bytes());
+        let start = if bom { BOM.len_utf8() } else { 0 };
+        let buffer = TextBuffer::from_mapping(Arc::new(mapping), start);
+
+        let mut document = Self::new(Some(path.to_path_buf()), buffer);
+        document.bom = bom;
+        document.read_only = true;
+        document.disk = Some(DiskStamp::new(&file.metadata()?));
+        Ok(document)
+    }
+
+    /// Memory mapping needs unix, elsewhere the file is read like any other.
+    #[cfg(not(unix))]
+    pub fn map_path(path: impl AsRef<Path>) -> Result<Self, DocumentError> {
+        Self::from_path(path)
+    }
+
+    /// Writes the document back to its path. Fails for documents that were never saved.
+    pub fn save(&mut self) -> io::Result<()> {
+        let path = self
+            .path
+            .clone()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "untitled document"))?;
+        self.write(&path)?;
+        self.dirty = false;
+        self.disk = DiskStamp::read(&path);
+        Ok(())
+    }
+
+    /// Writes the document to `path`, which it keeps from then on.
+    pub fn save_as(&mut self, path: impl Into<PathBuf>) -> io::Result<()> {
+        let path = path.into();
+        self.write(&path)?;
+        self.disk = DiskStamp::read(&path);
+        self.path = Some(path);
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// The file as last read or written by this document.
+    pub fn disk_stamp(&self) -> Option<DiskStamp> {
+        self.disk
+    }
+
+    /// Whether someone else wrote to the file since, so saving would overwrite their
+    /// changes. A file deleted since is not a conflict, saving just creates it again.
+    pub fn changed_on_disk(&self) -> bool {
+        match (&self.path, self.disk) {
+            (Some(path), Some(stamp)) => DiskStamp::read(path).is_some_and(|now| now != stamp),
+            _ => false,
+        }
+    }
+
+    /// The file as it is on disk now, read the way this document was. Read-only documents
+    /// are mapped again, which reads every byte, so large ones are better reloaded off the
+    /// main thread.
+    pub fn read_from_disk(&self) -> Result<Document, DocumentError> {
+        let path = self.path.as_ref().ok_or(DocumentError::NotFound)?;
+        match &self.key {
+            Some(key) => Self::from_encrypted(path, key.clone()),
+            None if self.read_only => Self::map_path(path),
+            None => Self::from_path(path),
+        }
+    }
+
+    /// Accepts what is on disk now as the version this document was edited from, so the
+    /// next save overwrites it.
+    pub fn accept_disk(&mut self) {
+        self.disk = self.path.as_deref().and_then(DiskStamp::read);
+    }
+
+    /// Replaces the contents with `theirs`, read from disk, as one edit that can be undone.
+    /// Only the lines that differ are replaced, so undo keeps the rest and listeners see
+    /// small changes. Read-only documents take it as in [`Document::take_mapped`].
+    pub fn take_disk(&mut self, theirs: Document) {
+        if self.read_only {
+            let (range, text) = difference(&self.buffer, &theirs.buffer);
+            self.take_mapped(theirs, range, text);
+            return;
+        }
+        let mine = self.buffer.to_string();
+        let text = theirs.buffer.to_string();
+        if text != mine {
+            let a: Vec<&str> = mine.split_inclusive('\n').collect();
+            let b: Vec<&str> = text.split_inclusive('\n').collect();
+            let mut starts = vec![0];
+            starts.extend(a.iter().scan(0, |offset, line| {
+                *offset += line.len();
+                Some(*offset)
+            }));
+            self.history.begin();
+            // From the last hunk up, so the offsets of the ones before stay put.
+            for hunk in diff(&a, &b).iter().rev() {
+                let (start, end) = (starts[hunk.old.start], starts[hunk.old.end]);
+                if start < end {
+                    self.delete(start..end);
+                }
+                let lines = b[hunk.new.clone()].concat();
+                if !lines.is_empty() {
+                    self.insert(start, &lines);
+                }
+            }
+            self.history.commit();
+        }
+        self.line_ending = theirs.line_ending;
+        self.bom = theirs.bom;
+        self.disk = theirs.disk;
+        self.dirty = false;
+    }
+
+    /// Replaces a read-only document with `theirs`, its file mapped again, where it differs
+    /// from it in `range` by `text`, see [`difference`]. Nothing of a read-only document
+    /// can be undone, so its history starts over.
+    pub fn take_mapped(&mut self, theirs: Document, range: Range<usize>, text: String) {
+        let start = self.utf16_position(range.start);
+        let end = self.utf16_position(range.end);
+        self.buffer = theirs.buffer;
+        if !range.is_empty() || !text.is_empty() {
+            let inserted = memchr::memchr_iter(b'\n', text.as_bytes()).count();
+            self.changed(Change {
+                range,
+                start,
+                end,
+                text,
+                lines: LineChange {
+                    start: start.line,
+                    removed: end.line - start.line,
+                    inserted,
+                },
+            });
+        }
+        self.history = EditHistory::default();
+        self.line_ending = theirs.line_ending;
+        self.bom = theirs.bom;
+        self.disk = theirs.disk;
+        self.dirty = false;
+    }
+
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        self.line_ending = line_ending;
+    }
+
+    pub fn has_bom(&self) -> bool {
+        self.bom
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn editable(&self) -> Option<&[Range<usize>]> {
+        self.editable.as_deref()
+    }
+
+    /// Refuses edits outside `ranges` from now on, which move along with the text and grow
+    /// with what is typed at their edges. No ranges make the document read-only, None
+    /// allows edits anywhere again.
+    pub fn set_editable(&mut self, ranges: Option<Vec<Range<usize>>>) {
+        self.editable = ranges;
+    }
+
+    /// Whether an edit replacing `range` is allowed.
+    pub fn allows(&self, range: &Range<usize>) -> bool {
+        self.editable.as_ref().is_none_or(|editable| {
+            editable
+                .iter()
+                .any(|r| r.start <= range.start && range.end <= r.end)
+        })
+    }
+
+    pub fn take_rejected(&mut self) -> Vec<Range<usize>> {
+        std::mem::take(&mut self.rejected)
+    }
+
+    pub fn has_rejected(&self) -> bool {
+        !self.rejected.is_empty()
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// Encrypts the file from the next save on, e.g. when saving it into a vault.
+    pub fn encrypt_with(&mut self, key: Arc<VaultKey>) {
+        self.key = Some(key);
+    }
+
+    /// Writes to a temporary file next to `path` and renames it over `path`, so a crash
+    /// never leaves a truncated file behind. A symlink is written through, not replaced.
+    fn write(&self, path: &Path) -> io::Result<()> {
+        let path = &resolve_symlinks(path)?;
+        // Renaming over a read-only file fails there anyway, and the attribute is meant to
+        // be respected.
+        #[cfg(windows)]
+        if fs::metadata(path).is_ok_and(|m| m.permissions().readonly()) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "file is read-only",
+            ));
+        }
+        let name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+        let temp = path.with_file_name(format!(
+            ".{}.dip-save-{}",
+            name.to_string_lossy(),
+            std::process::id()
+        ));
+
+        match self
+            .write_to(&temp, path)
+            .and_then(|_| fs::rename(&temp, path))
+        {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                let _ = fs::remove_file(&temp);
+                Err(e)
+            }
+        }
+    }
+
+    /// Writes the contents to `temp` with the metadata of `path`, if it exists. `temp` is
+    /// created anew, never opened through someone else's file or symlink, and no more
+    /// readable than `path` while the contents are written. New files get the permissions
+    /// the umask leaves.
+    fn write_to(&self, temp: &Path, path: &Path) -> io::Result<()> {
+        let metadata = fs::metadata(path).ok();
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            let mode = metadata
+                .as_ref()
+                .map_or(0o666, |m| m.permissions().mode() & 0o777);
+            options.mode(mode);
+        }
+        let mut out = BufWriter::new(options.open(temp)?);
+        self.encode(&mut out)?;
+        let file = out.into_inner().map_err(|e| e.into_error())?;
+        if let Some(metadata) = &metadata {
+            preserve_metadata(&file, temp, path, metadata)?;
+        }
+        file.sync_all()
+    }
+
+    /// Writes the file contents as they are saved: with the line ending and byte order mark
+    /// of the document, and encrypted in a vault.
+    pub fn encode(&self, out: &mut impl Write) -> io::Result<()> {
+        match &self.key {
+            // The plain text is sealed in memory, it never reaches the disk.
+            Some(key) => {
+                let mut plain = vec![];
+                self.write_contents(&mut plain)?;
+                out.write_all(&key.seal(&plain))
+            }
+            None => self.write_contents(out),
+        }
+    }
+
+    /// Records a save done outside of [`Document::save`], e.g. by a helper process. Only
+    /// clean if nothing changed since `version` was written.
+    pub fn saved_elsewhere(&mut self, path: PathBuf, version: u64) {
+        self.disk = DiskStamp::read(&path);
+        self.path = Some(path);
+        if version == self.version {
+            self.dirty = false;
+        }
+    }
+
+    /// Marks the document saved, for one its owner writes somewhere other than its path,
+    /// e.g. search results written back to the files they came from.
+    pub fn mark_clean(&mut self) {
+        self.dirty = false;
+    }
+
+    fn write_contents(&self, out: &mut impl Write) -> io::Result<()> {
+        if self.bom {
+            write!(out, "{BOM}")?;
+        }
+        let mut last = 0;
+        for chunk in self.buffer.chunks() {
+            write_chunk(out, chunk, self.line_ending, last)?;
+            last = chunk.as_bytes().last().copied().unwrap_or(last);
+        }
+        Ok(())
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn buffer(&self) -> &TextBuffer {
+        &self.buffer
+    }
+
+    pub fn history(&self) -> &EditHistory {
+        &self.history
+    }
+
+    pub fn history_mut(&mut self) -> &mut EditHistory {
+        &mut self.history
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn take_changes(&mut self) -> Vec<Change> {
+        std::mem::take(&mut self.changes)
+    }
+
+    /// Adds `text` at the end without recording it for undo, e.g. output streamed from a
+    /// pipe.
+    pub fn append(&mut self, text: &str) {
+        let end = self.buffer.len();
+        if !self.read_only && self.guard(end..end) {
+            self.insert_text(end, text);
+        }
+    }
+
+    pub fn insert(&mut self, offset: usize, text: &str) {
+        if self.read_only || !self.guard(offset..offset) {
+            return;
+        }
+        self.insert_text(offset, text);
+        self.history.record_insert(offset, text);
+    }
+
+    pub fn delete(&mut self, range: Range<usize>) {
+        if self.read_only || !self.guard(range.clone()) {
+            return;
+        }
+        let deleted = self.buffer.text_in(range.clone());
+        self.delete_range(range.clone());
+        self.history.record_delete(range.start, deleted);
+    }
+
+    /// Applies `edits` from the last to the first, so earlier ranges stay valid, and returns
+    /// where `offset` ends up.
+    pub fn apply_edits(
+        &mut self,
+        mut edits: Vec<(Range<usize>, String)>,
+        mut offset: usize,
+    ) -> usize {
+        edits.sort_by_key(|(range, _)| range.start);
+        for (range, text) in edits.into_iter().rev() {
+            if !range.is_empty() {
+                self.delete(range.clone());
+            }
+            self.insert(range.start, &text);
+            if range.end <= offset {
+                offset = offset - range.len() + text.len();
+            }
+        }
+        offset
+    }
+
+    /// Replaces every match of `regex` in one undo step and returns how many there were.
+    /// `$1` or `${name}` in `replacement` insert capture groups.
+    pub fn replace_all(&mut self, regex: &Regex, replacement: &str) -> usize {
+        let mut edits = vec![];
+        regex_captures(self.buffer(), regex, |start, captures| {
+            let m = captures.get(0).unwrap();
+            let mut text = String::new();
+            captures.expand(replacement, &mut text);
+            edits.push((start + m.start()..start + m.end(), text));
+        });
+        let count = edits.len();
+        self.history.begin();
+        self.apply_edits(edits, 0);
+        self.history.commit();
+        count
+    }
+
+    /// Returns where the cursor should go, or None if there is nothing to undo.
+    pub fn undo(&mut self) -> Option<usize> {
+        let edits = self.history.undo()?;
+        Some(self.apply(&edits))
+    }
+
+    /// Returns where the cursor should go, or None if there is nothing to redo.
+    pub fn redo(&mut self) -> Option<usize> {
+        let edits = self.history.redo()?;
+        Some(self.apply(&edits))
+    }
+
+    /// Applies `edits` without recording them and returns the cursor after the last one.
+    fn apply(&mut self, edits: &[Edit]) -> usize {
+        let mut cursor = 0;
+        for edit in edits {
+            cursor = match edit {
+                Edit::Insert { offset, text } => {
+                    self.insert_text(*offset, text);
+                    offset + text.len()
+                }
+                Edit::Delete(range) => {
+                    self.delete_range(range.clone());
+                    range.start
+                }
+            };
+        }
+        cursor
+    }
+
+    /// Applies an edit made by someone the [`Document::editable`] ranges don't restrict,
+    /// e.g. the host of a shared session, without recording it for undo.
+    pub fn apply_unrestricted(&mut self, edit: &Edit) {
+        if self.read_only {
+            return;
+        }
+        match edit {
+            Edit::Insert { offset, text } => self.insert_text(*offset, text),
+            Edit::Delete(range) => self.delete_range(range.clone()),
+        }
+    }
+
+    /// Whether `range` may be edited, remembering it otherwise.
+    fn guard(&mut self, range: Range<usize>) -> bool {
+        let allowed = self.allows(&range);
+        if !allowed {
+            self.rejected.push(range);
+        }
+        allowed
+    }
+
+    fn insert_text(&mut self, offset: usize, text: &str) {
+        let position = self.utf16_position(offset);
+        self.buffer.insert(offset, text);
+        self.changed(Change {
+            range: offset..offset,
+            start: position,
+            end: position,
+            text: text.to_string(),
+            lines: LineChange {
+                start: position.line,
+                removed: 0,
+                inserted: memchr::memchr_iter(b'\n', text.as_bytes()).count(),
+            },
+        });
+    }
+
+    fn delete_range(&mut self, range: Range<usize>) {
+        let start = self.utf16_position(range.start);
+        let end = self.utf16_position(range.end);
+        self.buffer.delete(range.start, range.len());
+        self.changed(Change {
+            range,
+            start,
+            end,
+            text: String::new(),
+            lines: LineChange {
+                start: start.line,
+                removed: end.line - start.line,
+                inserted: 0,
+            },
+        });
+    }
+
+    fn changed(&mut self, change: Change) {
+        self.version += 1;
+        self.dirty = true;
+        if let Some(editable) = &mut self.editable {
+            for range in editable.iter_mut() {
+                *range = shift_range(range, &change, Stickiness::AlwaysGrows);
+            }
+        }
+        self.changes.push(change);
+    }
+
+    pub fn utf16_position(&self, offset: usize) -> Utf16Position {
+        let line = self.buffer.line_at(offset);
+        let start = self.buffer.line_start(line);
+        let column = self.buffer.utf16_offset_at(offset) - self.buffer.utf16_offset_at(start);
+        Utf16Position { line, column }
+    }
+
+    /// Offset of a position sent by a language server. Columns past the end of the line or
+    /// inside a surrogate pair clamp to the character boundary before them.
+    pub fn offset_at_utf16(&self, position: Utf16Position) -> usize {
+        let line = position.line.min(self.buffer.line_count() - 1);
+        let start = self.buffer.line_start(line);
+        let offset = self
+            .buffer
+            .byte_offset_at(self.buffer.utf16_offset_at(start) + position.column);
+        offset.min(start + self.buffer.get_line_length(line))
+    }
+}
+
+/// The range of `mine` that `theirs` differs in, and the text it has there instead. Only
+/// what lies between the bytes both start and end with is copied, so comparing a large
+/// file to the same file with lines appended is cheap in memory, if not in time.
+pub fn difference(mine: &TextBuffer, theirs: &TextBuffer) -> (Range<usize>, String) {
+    let continues = |byte: u8| byte & 0xc0 == 0x80;
+
+    // The prefix ends before the first byte that differs, or the char it is part of.
+    let (mut prefix, mut boundary) = (0, 0);
+    let mut a = mine.chunks().flat_map(str::bytes);
+    let mut b = theirs.chunks().flat_map(str::bytes);
+    loop {
+        match (a.next(), b.next()) {
+            (Some(x), Some(y)) if x == y => {
+                if !continues(x) {
+                    boundary = prefix;
+                }
+                prefix += 1;
+            }
+            (x, y) => {
+                if x.is_some_and(continues) || y.is_some_and(continues) {
+                    prefix = boundary;
+                }
+                break;
+            }
+        }
+    }
+
+    // The suffix starts at a char both end with, after the prefix in both.
+    let most = mine.len().min(theirs.len()) - prefix;
+    let (mut suffix, mut equal) = (0, 0);
+    let a: Vec<&str> = mine.chunks().collect();
+    let b: Vec<&str> = theirs.chunks().collect();
+    let mut a = a.iter().rev().flat_map(|chunk| chunk.bytes().rev());
+    let mut b = b.iter().rev().flat_map(|chunk| chunk.bytes().rev());
+    while equal < most {
+        match (a.next(), b.next()) {
+            (Some(x), Some(y)) if x == y => {
+                equal += 1;
+                if !continues(x) {
+                    suffix = equal;
+                }
+            }
+            _ => break,
+        }
+    }
+
+    let text = theirs.text_in(prefix..theirs.len() - suffix);
+    (prefix..mine.len() - suffix, text)
+}
+
+#[derive(Debug)]
+pub enum DocumentError {
+    NotFound,
+    PermissionDenied,
+    InvalidUtf8,
+    /// Size of the file in bytes.
+    TooLarge(u64),
+    /// The file is in a vault that is still locked.
+    Encrypted,
+    Vault(VaultError),
+    Io(io::Error),
+}
+
+impl From<io::Error> for DocumentError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotFound => DocumentError::NotFound,
+            io::ErrorKind::PermissionDenied => DocumentError::PermissionDenied,
+            io::ErrorKind::InvalidData => DocumentError::InvalidUtf8,
+            _ => DocumentError::Io(e),
+        }
+    }
+}
+
+impl fmt::Display for DocumentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DocumentError::NotFound => write!(f, "file not found"),
+            DocumentError::PermissionDenied => write!(f, "permission denied"),
+            DocumentError::InvalidUtf8 => write!(f, "file is not valid UTF-8"),
+            DocumentError::TooLarge(size) => {
+                write!(
+                    f,
+                    "file is too large ({size} bytes, at most {MAX_DOCUMENT_SIZE})"
+                )
+            }
+            DocumentError::Encrypted => write!(f, "file is encrypted, unlock its vault first"),
+            DocumentError::Vault(e) => write!(f, "{e}"),
+            DocumentError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl error::Error for DocumentError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            DocumentError::Vault(e) => Some(e),
+            DocumentError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Decodes a byte stream read in chunks, which may end in the middle of a character.
+#[derive(Debug, Default)]
+pub struct Utf8Decoder {
+    pending: Vec<u8>,
+    /// Bytes of `pending` handed out by the last push.
+    valid: usize,
+}
+
+impl Utf8Decoder {
+    /// The text completed by `bytes`. An incomplete character at the end is kept for the
+    /// next push.
+    pub fn push(&mut self, bytes: &[u8]) -> Result<&str, DocumentError> {
+        self.pending.drain(..self.valid);
+        self.pending.extend_from_slice(bytes);
+        self.valid = match std::str::from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => return Err(DocumentError::InvalidUtf8),
+        };
+        Ok(std::str::from_utf8(&self.pending[..self.valid]).unwrap())
+    }
+
+    /// Fails if the stream ended in the middle of a character.
+    pub fn finish(&self) -> Result<(), DocumentError> {
+        if self.pending.len() > self.valid {
+            Err(DocumentError::InvalidUtf8)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The file `path` points to, following symlinks.
+fn resolve_symlinks(path: &Path) -> io::Result<PathBuf> {
+    let mut path = path.to_path_buf();
+    // The limit of Linux, a cycle never resolves.
+    for _ in 0..40 {
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                let target = fs::read_link(&path)?;
+                path = match path.parent() {
+                    Some(dir) => dir.join(target),
+                    None => target,
+                };
+            }
+            _ => return Ok(path),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "too many levels of symbolic links",
+    ))
+}
+
+/// Gives `file`, written at `temp`, the permissions, owner, group and extended attributes
+/// of `original`. Owner, group and attributes are best effort: without privileges only some
+/// can be set.
+fn preserve_metadata(
+    file: &File,
+    temp: &Path,
+    original: &Path,
+    metadata: &fs::Metadata,
+) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{fchown, MetadataExt};
+
+        // Before the mode, changing the owner clears the setuid and setgid bits.
+        if fchown(file, Some(metadata.uid()), Some(metadata.gid())).is_err() {
+            let _ = fchown(file, None, Some(metadata.gid()));
+        }
+        if let Ok(names) = xattr::list(original) {
+            for name in names {
+                if let Ok(Some(value)) = xattr::get(original, &name) {
+                    let _ = xattr::set(temp, &name, &value);
+                }
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (temp, original);
+    file.set_permissions(metadata.permissions())
+}
+
+/// Line feeds not preceded by a carriage return become CRLF for [`LineEnding::CrLf`].
+/// `before` is the last byte written, as a CRLF may be split across chunks.
+fn write_chunk(
+    out: &mut impl Write,
+    chunk: &str,
+    line_ending: LineEnding,
+    before: u8,
+) -> io::Result<()> {
+    if line_ending == LineEnding::Lf {
+        return out.write_all(chunk.as_bytes());
+    }
+    let bytes = chunk.as_bytes();
+    let mut start = 0;
+    for lf in memchr::memchr_iter(b'\n', bytes) {
+        let prev = if lf == 0 { before } else { bytes[lf - 1] };
+        if prev != b'\r' {
+            out.write_all(&bytes[start..lf])?;
+            out.write_all(b"\r")?;
+            start = lf;
+        }
+    }
+    out.write_all(&bytes[start..])
+}
+
+#[derive(Clone, Debug)]
+pub enum Edit {
+    Insert { offset: usize, text: String },
+    Delete(Range<usize>),
+}
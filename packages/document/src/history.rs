@@ -0,0 +1,223 @@
+use crate::{
+    document::Edit,
+    payload::{PayloadId, PayloadStore},
+};
+
+#[derive(Clone, Copy, Debug)]
+enum Operation {
+    Insert {
+        offset: usize,
+        len: usize,
+        text: PayloadId,
+    },
+    Delete {
+        offset: usize,
+        text: PayloadId,
+    },
+}
+
+impl Operation {
+    fn payload(&self) -> PayloadId {
+        match *self {
+            Operation::Insert { text, .. } | Operation::Delete { text, .. } => text,
+        }
+    }
+}
+
+/// Operations undone and redone together.
+#[derive(Debug, Default)]
+struct Transaction {
+    operations: Vec<Operation>,
+}
+
+/// Characters typed one after another, recorded as a single insert once the run ends.
+#[derive(Debug)]
+struct Typing {
+    offset: usize,
+    text: String,
+}
+
+/// Undo and redo stacks of a document. Every recorded operation is its own transaction,
+/// unless it happens between [`EditHistory::begin`] and [`EditHistory::commit`] or is part
+/// of a run of single characters typed at consecutive offsets.
+#[derive(Debug, Default)]
+pub struct EditHistory {
+    payloads: PayloadStore,
+    undo: Vec<Transaction>,
+    redo: Vec<Transaction>,
+    open: Option<Transaction>,
+    typing: Option<Typing>,
+}
+
+impl EditHistory {
+    /// Groups everything recorded until [`EditHistory::commit`] into one undo step.
+    pub fn begin(&mut self) {
+        self.flush_typing();
+        self.open.get_or_insert_with(Transaction::default);
+    }
+
+    /// Like [`EditHistory::begin`], but joins the last undo step, e.g. for formatting applied
+    /// in response to the keystroke before.
+    pub fn amend(&mut self) {
+        self.flush_typing();
+        if self.open.is_none() {
+            self.open = Some(self.undo.pop().unwrap_or_default());
+        }
+    }
+
+    pub fn commit(&mut self) {
+        if let Some(transaction) = self.open.take() {
+            if !transaction.operations.is_empty() {
+                self.undo.push(transaction);
+            }
+        }
+    }
+
+    pub fn record_insert(&mut self, offset: usize, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        self.discard_redo();
+
+        if self.open.is_none() && is_typed(text) {
+            match &mut self.typing {
+                Some(typing) if typing.offset + typing.text.len() == offset => {
+                    typing.text.push_str(text);
+                }
+                _ => {
+                    self.flush_typing();
+                    self.typing = Some(Typing {
+                        offset,
+                        text: text.to_string(),
+                    });
+                }
+            }
+            return;
+        }
+
+        self.flush_typing();
+        let len = text.len();
+        let text = self.payloads.push(text.to_string());
+        self.push(Operation::Insert { offset, len, text });
+    }
+
+    pub fn record_delete(&mut self, offset: usize, deleted: String) {
+        if deleted.is_empty() {
+            return;
+        }
+        self.discard_redo();
+        self.flush_typing();
+        let text = self.payloads.push(deleted);
+        self.push(Operation::Delete { offset, text });
+    }
+
+    /// Ends the current run of typed characters, e.g. when the cursor moves away.
+    pub fn break_group(&mut self) {
+        self.flush_typing();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.typing.is_some() || self.open.is_some() || !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Pops the last transaction and returns the edits reverting it, in order.
+    pub fn undo(&mut self) -> Option<Vec<Edit>> {
+        self.flush_typing();
+        self.commit();
+        let transaction = self.undo.pop()?;
+
+        let edits = transaction
+            .operations
+            .iter()
+            .rev()
+            .map(|operation| match *operation {
+                Operation::Insert { offset, len, .. } => Edit::Delete(offset..offset + len),
+                Operation::Delete { offset, text } => Edit::Insert {
+                    offset,
+                    text: self.payloads.get(text).into_owned(),
+                },
+            })
+            .collect();
+
+        self.redo.push(transaction);
+        Some(edits)
+    }
+
+    /// Pops the last undone transaction and returns the edits reapplying it, in order.
+    pub fn redo(&mut self) -> Option<Vec<Edit>> {
+        let transaction = self.redo.pop()?;
+
+        let edits = transaction
+            .operations
+            .iter()
+            .map(|operation| match *operation {
+                Operation::Insert { offset, text, .. } => Edit::Insert {
+                    offset,
+                    text: self.payloads.get(text).into_owned(),
+                },
+                Operation::Delete { offset, text } => {
+                    Edit::Delete(offset..offset + self.payloads.get(text).len())
+                }
+            })
+            .collect();
+
+        self.undo.push(transaction);
+        Some(edits)
+    }
+
+    /// Bytes held by undo and redo payloads.
+    pub fn heap_size(&self) -> usize {
+        self.payloads.heap_size() + self.typing.as_ref().map_or(0, |t| t.text.capacity())
+    }
+
+    /// Compresses every payload, trading slower undo for memory.
+    pub fn compress(&mut self) {
+        self.payloads.compress_all();
+    }
+
+    fn push(&mut self, operation: Operation) {
+        match &mut self.open {
+            Some(transaction) => transaction.operations.push(operation),
+            None => self.undo.push(Transaction {
+                operations: vec![operation],
+            }),
+        }
+    }
+
+    fn flush_typing(&mut self) {
+        if let Some(typing) = self.typing.take() {
+            let len = typing.text.len();
+            let text = self.payloads.push(typing.text);
+            self.push(Operation::Insert {
+                offset: typing.offset,
+                len,
+                text,
+            });
+        }
+    }
+
+    /// A new edit makes the redo stack unreachable. Its payloads are always the newest, so
+    /// they can be dropped from the store too.
+    fn discard_redo(&mut self) {
+        let first = self
+            .redo
+            .iter()
+            .flat_map(|t| &t.operations)
+            .map(Operation::payload)
+            .min();
+        if let Some(first) = first {
+            self.payloads.truncate(first);
+        }
+        self.redo.clear();
+    }
+}
+
+/// Single characters other than line breaks are grouped as typing.
+fn is_typed(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!((chars.next(), chars.next()), (Some(c), None) if c != '\n')
+}
@@ -0,0 +1,15 @@
+//! Documents of the dip editor as plain types: a file read into a [`text_buffer::TextBuffer`],
+//! the edits made to it and how they are undone, and how it is written back.
+//!
+//! Nothing here needs Bevy. `dip_core` drives documents from its plugins; the `bevy` feature
+//! only makes [`document::Document`] a component for it.
+
+pub mod diff;
+pub mod document;
+pub mod history;
+pub mod markers;
+pub mod payload;
+pub mod vault;
+
+/// The text engine documents are stored in.
+pub use dip_text as text_buffer;
@@ -0,0 +1,51 @@
+//! How ranges in a document move along with its edits, e.g. markers, folds and the
+//! ranges a shared session lets a participant edit.
+
+use crate::document::Change;
+use std::ops::Range;
+
+/// Whether text typed right at the edges of a marker ends up inside it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Stickiness {
+    #[default]
+    AlwaysGrows,
+    NeverGrows,
+    /// Only text typed at its start, e.g. for a widget drawn after what it annotates.
+    GrowsBefore,
+    /// Only text typed at its end, e.g. for a widget drawn before what it annotates.
+    GrowsAfter,
+}
+
+impl Stickiness {
+    /// Whether the start and the end stay before text inserted right at them.
+    fn stays_before(self) -> (bool, bool) {
+        match self {
+            Stickiness::AlwaysGrows => (true, false),
+            Stickiness::NeverGrows => (false, true),
+            Stickiness::GrowsBefore => (true, true),
+            Stickiness::GrowsAfter => (false, false),
+        }
+    }
+}
+
+/// Where `range` ends up after `change`, moving the way a marker with `stickiness` does.
+pub fn shift_range(range: &Range<usize>, change: &Change, stickiness: Stickiness) -> Range<usize> {
+    let inserted = change.text.len();
+    let replaced = &change.range;
+    let map = |offset: usize, stays_before: bool| {
+        let after = replaced.start + inserted;
+        if offset < replaced.start || (offset == replaced.start && !replaced.is_empty()) {
+            offset
+        } else if offset > replaced.end || (offset == replaced.end && !replaced.is_empty()) {
+            offset - replaced.len() + inserted
+        } else if stays_before {
+            replaced.start
+        } else {
+            after
+        }
+    };
+    let (start_before, end_before) = stickiness.stays_before();
+    let start = map(range.start, start_before);
+    let end = map(range.end, end_before);
+    start..end.max(start)
+}
@@ -0,0 +1,105 @@
+use std::borrow::Cow;
+
+/// Payloads shorter than this are kept as is, lz4 would not gain anything on them.
+const MIN_COMPRESS_LEN: usize = 64;
+
+/// Append-only store for undo payloads (inserted and deleted text). The most recent `window`
+/// payloads stay uncompressed for fast undo; older ones are lz4 compressed and only
+/// decompressed when an undo reaches them.
+#[derive(Debug)]
+pub struct PayloadStore {
+    entries: Vec<Payload>,
+    window: usize,
+    /// Entries before this index are outside the window and already compressed when worth it.
+    compressed_until: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PayloadId(usize);
+
+#[derive(Debug)]
+enum Payload {
+    Plain(String),
+    Compressed(Vec<u8>),
+}
+
+impl Default for PayloadStore {
+    fn default() -> Self {
+        Self::with_window(256)
+    }
+}
+
+impl PayloadStore {
+    pub fn with_window(window: usize) -> Self {
+        Self {
+            entries: vec![],
+            window,
+            compressed_until: 0,
+        }
+    }
+
+    pub fn push(&mut self, text: String) -> PayloadId {
+        let id = PayloadId(self.entries.len());
+        self.entries.push(Payload::Plain(text));
+
+        let outside = self.entries.len().saturating_sub(self.window);
+        self.compress_range(outside);
+
+        id
+    }
+
+    pub fn get(&self, id: PayloadId) -> Cow<'_, str> {
+        match &self.entries[id.0] {
+            Payload::Plain(text) => Cow::Borrowed(text),
+            Payload::Compressed(data) => {
+                let bytes = lz4_flex::decompress_size_prepended(data)
+                    .expect("undo payload is not valid lz4");
+                Cow::Owned(String::from_utf8(bytes).expect("undo payload is not valid UTF-8"))
+            }
+        }
+    }
+
+    /// Drops every payload from `id` on, e.g. when the redo stack is discarded.
+    pub fn truncate(&mut self, id: PayloadId) {
+        self.entries.truncate(id.0);
+        self.compressed_until = self.compressed_until.min(id.0);
+    }
+
+    /// Compresses everything, window included. Used when memory runs short.
+    pub fn compress_all(&mut self) {
+        self.compress_range(self.entries.len());
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Bytes held by payloads, compressed or not.
+    pub fn heap_size(&self) -> usize {
+        self.entries
+            .iter()
+            .map(|p| match p {
+                Payload::Plain(text) => text.capacity(),
+                Payload::Compressed(data) => data.capacity(),
+            })
+            .sum()
+    }
+
+    fn compress_range(&mut self, until: usize) {
+        for payload in &mut self.entries[self.compressed_until.min(until)..until] {
+            if let Payload::Plain(text) = payload {
+                if text.len() >= MIN_COMPRESS_LEN {
+                    let data = lz4_flex::compress_prepend_size(text.as_bytes());
+                    if data.len() < text.len() {
+                        *payload = Payload::Compressed(data);
+                    }
+                }
+            }
+        }
+        self.compressed_until = self.compressed_until.max(until);
+    }
+}
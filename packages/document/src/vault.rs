@@ -0,0 +1,195 @@
+//! Vaults: directories whose files are encrypted on disk, and the keys that open them.
+
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use std::{
+    error, fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Starts every encrypted file, so they are told apart from plain ones in the same
+/// directory.
+pub const MAGIC: &[u8; 8] = b"dipvlt1\n";
+
+/// Salt and passphrase check of a vault, in its root directory.
+pub const VAULT_FILE: &str = ".dip-vault";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const CHECK: &[u8] = b"dip vault";
+
+/// Key of an unlocked vault, derived from its passphrase with Argon2id. Files are sealed
+/// with XChaCha20-Poly1305 under a random nonce each, so their plaintext only ever lives
+/// in memory.
+pub struct VaultKey(XChaCha20Poly1305);
+
+impl VaultKey {
+    fn derive(passphrase: &str, salt: &[u8]) -> Result<Self, VaultError> {
+        let mut key = [0; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|_| VaultError::Kdf)?;
+        Ok(Self(XChaCha20Poly1305::new(&key.into())))
+    }
+
+    /// `plain` encrypted, with [`MAGIC`] and the nonce in front.
+    pub fn seal(&self, plain: &[u8]) -> Vec<u8> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let sealed = self
+            .0
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plain,
+                    aad: MAGIC,
+                },
+            )
+            .expect("encrypting to memory does not fail");
+        let mut out = Vec::with_capacity(MAGIC.len() + NONCE_LEN + sealed.len());
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&sealed);
+        out
+    }
+
+    /// Decrypts what [`VaultKey::seal`] returned. Fails for another key or altered bytes.
+    pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>, VaultError> {
+        let rest = sealed.strip_prefix(MAGIC).ok_or(VaultError::NotEncrypted)?;
+        if rest.len() < NONCE_LEN {
+            return Err(VaultError::Corrupt);
+        }
+        let (nonce, message) = rest.split_at(NONCE_LEN);
+        self.0
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: message,
+                    aad: MAGIC,
+                },
+            )
+            .map_err(|_| VaultError::Corrupt)
+    }
+}
+
+impl fmt::Debug for VaultKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("VaultKey(..)")
+    }
+}
+
+pub fn is_encrypted(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+#[derive(Debug)]
+pub enum VaultError {
+    WrongPassphrase,
+    /// The file has no [`MAGIC`], i.e. it was never encrypted.
+    NotEncrypted,
+    /// Truncated, altered or sealed with another key.
+    Corrupt,
+    Kdf,
+    Io(io::Error),
+}
+
+impl From<io::Error> for VaultError {
+    fn from(e: io::Error) -> Self {
+        VaultError::Io(e)
+    }
+}
+
+impl fmt::Display for VaultError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VaultError::WrongPassphrase => write!(f, "wrong passphrase"),
+            VaultError::NotEncrypted => write!(f, "file is not encrypted"),
+            VaultError::Corrupt => write!(f, "encrypted file is damaged or from another vault"),
+            VaultError::Kdf => write!(f, "could not derive a key from the passphrase"),
+            VaultError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl error::Error for VaultError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            VaultError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Directories whose documents are encrypted on disk, with the keys of the unlocked ones.
+#[derive(Debug, Default)]
+pub struct Vaults {
+    unlocked: Vec<(PathBuf, Arc<VaultKey>)>,
+}
+
+impl Vaults {
+    /// Key for a file at `path`, from the innermost unlocked vault containing it.
+    pub fn key_for(&self, path: &Path) -> Option<Arc<VaultKey>> {
+        self.unlocked
+            .iter()
+            .filter(|(root, _)| path.starts_with(root))
+            .max_by_key(|(root, _)| root.components().count())
+            .map(|(_, key)| key.clone())
+    }
+
+    pub fn is_unlocked(&self, root: &Path) -> bool {
+        self.unlocked.iter().any(|(r, _)| r == root)
+    }
+
+    /// Whether `path` is inside a vault that is locked, where plain text must not be saved.
+    pub fn is_locked(&self, path: &Path) -> bool {
+        vault_root(path).is_some_and(|root| !self.is_unlocked(&root))
+    }
+
+    /// Derives the key of the vault at `root`, making `root` a vault with this passphrase
+    /// if it is not one yet.
+    pub fn unlock(&mut self, root: &Path, passphrase: &str) -> Result<(), VaultError> {
+        let file = root.join(VAULT_FILE);
+        let key = match fs::read(&file) {
+            Ok(header) => {
+                if header.len() < SALT_LEN {
+                    return Err(VaultError::Corrupt);
+                }
+                let (salt, check) = header.split_at(SALT_LEN);
+                let key = VaultKey::derive(passphrase, salt)?;
+                match key.open(check) {
+                    Ok(plain) if plain == CHECK => key,
+                    _ => return Err(VaultError::WrongPassphrase),
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let mut salt = [0; SALT_LEN];
+                OsRng.fill_bytes(&mut salt);
+                let key = VaultKey::derive(passphrase, &salt)?;
+                let mut header = salt.to_vec();
+                header.extend(key.seal(CHECK));
+                fs::write(&file, header)?;
+                key
+            }
+            Err(e) => return Err(e.into()),
+        };
+        self.lock(root);
+        self.unlocked.push((root.to_path_buf(), Arc::new(key)));
+        Ok(())
+    }
+
+    /// Forgets the key. Documents already open keep theirs until they are closed.
+    pub fn lock(&mut self, root: &Path) {
+        self.unlocked.retain(|(r, _)| r != root);
+    }
+}
+
+/// The innermost directory above `path` that is a vault.
+pub fn vault_root(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .skip(1)
+        .find(|dir| dir.join(VAULT_FILE).is_file())
+        .map(Path::to_path_buf)
+}
@@ -0,0 +1,60 @@
+//! Scratch directories and shortcuts shared by the integration tests. Not every test file
+//! uses all of them, so the others are dead code there.
+
+#![allow(dead_code)]
+
+use dip_document::document::Document;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A directory of its own for each test, removed when it ends.
+pub struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    /// `name` tells apart the tests of one file, which the directory is named after too.
+    pub fn new(name: &str) -> Self {
+        let test = env!("CARGO_CRATE_NAME");
+        let dir = std::env::temp_dir().join(format!("dip-{test}-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+
+    /// The file most tests edit.
+    pub fn notes(&self) -> PathBuf {
+        self.join("notes.txt")
+    }
+
+    /// Files left in the directory, e.g. a temporary file a save forgot.
+    pub fn files(&self) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(&self.0)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// The file at `path` opened with `typed` typed at its start.
+pub fn edited(path: &Path, typed: &str) -> Document {
+    let mut document = Document::from_path(path).unwrap();
+    document.insert(0, typed);
+    document
+}
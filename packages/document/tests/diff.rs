@@ -0,0 +1,102 @@
+//! Line diffs turn one side into the other with as few changes as possible.
+
+use dip_document::diff::{diff, merge3, Hunk};
+
+/// Applies `hunks` to `a`, taking the new lines from `b`.
+fn apply(a: &[u8], b: &[u8], hunks: &[Hunk]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut copied = 0;
+    for hunk in hunks {
+        out.extend(&a[copied..hunk.old.start]);
+        out.extend(&b[hunk.new.clone()]);
+        copied = hunk.old.end;
+    }
+    out.extend(&a[copied..]);
+    out
+}
+
+/// Length of the longest common subsequence, the lines a shortest diff keeps.
+fn common(a: &[u8], b: &[u8]) -> usize {
+    let mut row = vec![0; b.len() + 1];
+    for x in a {
+        let mut diagonal = 0;
+        for (j, y) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if x == y {
+                diagonal + 1
+            } else {
+                above.max(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// Xorshift, so failures reproduce.
+fn sides(seed: u64, count: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut state = seed;
+    let mut next = move |below: u64| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state % below
+    };
+    (0..count)
+        .map(|_| {
+            let alphabet = 1 + next(4);
+            let mut side = || {
+                let len = next(40);
+                (0..len).map(|_| b'a' + next(alphabet) as u8).collect()
+            };
+            (side(), side())
+        })
+        .collect()
+}
+
+#[test]
+fn keeps_the_most_lines_in_common() {
+    for (a, b) in sides(0x9e37_79b9_7f4a_7c15, 2_000) {
+        let hunks = diff(&a, &b);
+        assert_eq!(apply(&a, &b, &hunks), b, "{a:?} -> {b:?}");
+        let changed: usize = hunks.iter().map(|h| h.old.len()).sum();
+        assert_eq!(a.len() - changed, common(&a, &b), "{a:?} -> {b:?}");
+    }
+}
+
+#[test]
+fn reports_replaced_lines_as_one_hunk() {
+    let a = ["one", "two", "three", "four"];
+    let b = ["one", "2", "3", "four", "five"];
+    assert_eq!(
+        diff(&a, &b),
+        vec![
+            Hunk {
+                old: 1..3,
+                new: 1..3,
+            },
+            Hunk {
+                old: 4..4,
+                new: 4..5,
+            },
+        ]
+    );
+    assert_eq!(diff(&a, &a), vec![]);
+}
+
+#[test]
+fn diffs_large_sides() {
+    let a: Vec<usize> = (0..200_000).collect();
+    let b: Vec<usize> = (0..200_000).filter(|i| i % 1_000 != 0).collect();
+    assert_eq!(diff(&a, &b).len(), 200);
+}
+
+#[test]
+fn merges_changes_to_different_lines() {
+    let merged = merge3("a\nb\nc\n", "A\nb\nc\n", "a\nb\nC\n");
+    assert_eq!(merged.text, "A\nb\nC\n");
+    assert_eq!(merged.conflicts, 0);
+
+    let merged = merge3("a\n", "ours\n", "theirs\n");
+    assert_eq!(merged.conflicts, 1);
+}
@@ -0,0 +1,105 @@
+//! Undo steps group typing, transactions and amendments, and a new edit drops what was
+//! undone.
+
+use dip_document::{document::Document, text_buffer::TextBuffer};
+
+fn document(text: &str) -> Document {
+    Document::new(None, TextBuffer::from(text))
+}
+
+fn text(document: &Document) -> String {
+    document.buffer().to_string()
+}
+
+/// Types `typed` one character at a time from `offset`.
+fn type_at(document: &mut Document, offset: usize, typed: &str) {
+    for (i, c) in typed.char_indices() {
+        document.insert(offset + i, &c.to_string());
+    }
+}
+
+#[test]
+fn undoes_a_run_of_typing_at_once() {
+    let mut doc = document("");
+    type_at(&mut doc, 0, "hello");
+    // A line break is a step of its own, and so is what is typed after it.
+    type_at(&mut doc, 5, "\nworld");
+    assert_eq!(text(&doc), "hello\nworld");
+
+    assert_eq!(doc.undo(), Some(6));
+    assert_eq!(text(&doc), "hello\n");
+    assert_eq!(doc.undo(), Some(5));
+    assert_eq!(doc.undo(), Some(0));
+    assert_eq!(text(&doc), "");
+    assert_eq!(doc.undo(), None);
+    assert!(doc.history().can_redo());
+}
+
+#[test]
+fn typing_elsewhere_or_after_a_break_starts_a_new_step() {
+    let mut doc = document("ac");
+    type_at(&mut doc, 1, "b");
+    // Not right after the last character typed.
+    type_at(&mut doc, 0, "_");
+    type_at(&mut doc, 4, "d");
+    doc.history_mut().break_group();
+    type_at(&mut doc, 5, "e");
+    assert_eq!(text(&doc), "_abcde");
+
+    doc.undo();
+    assert_eq!(text(&doc), "_abcd");
+    doc.undo();
+    assert_eq!(text(&doc), "_abc");
+    doc.undo();
+    assert_eq!(text(&doc), "abc");
+}
+
+#[test]
+fn groups_a_transaction_and_amends_the_step_before() {
+    let mut doc = document("one two");
+    doc.history_mut().begin();
+    doc.delete(0..3);
+    doc.insert(0, "1");
+    doc.delete(2..5);
+    doc.insert(2, "2");
+    doc.history_mut().commit();
+    assert_eq!(text(&doc), "1 2");
+
+    type_at(&mut doc, 3, ";");
+    // Formatting the line after the keystroke, undone along with it.
+    doc.history_mut().amend();
+    doc.insert(3, " ");
+    doc.history_mut().commit();
+    assert_eq!(text(&doc), "1 2 ;");
+
+    doc.undo();
+    assert_eq!(text(&doc), "1 2");
+    doc.undo();
+    assert_eq!(text(&doc), "one two");
+}
+
+#[test]
+fn redoes_until_a_new_edit_drops_what_was_undone() {
+    let mut doc = document("");
+    doc.insert(0, "first\n");
+    doc.insert(6, "second\n");
+    doc.insert(13, "third\n");
+    doc.undo();
+    doc.undo();
+    assert_eq!(text(&doc), "first\n");
+
+    assert_eq!(doc.redo(), Some(13));
+    assert_eq!(text(&doc), "first\nsecond\n");
+    doc.insert(13, "other\n");
+    assert!(!doc.history().can_redo());
+    assert_eq!(doc.redo(), None);
+
+    // Compressed payloads undo the same.
+    doc.history_mut().compress();
+    doc.undo();
+    doc.undo();
+    assert_eq!(text(&doc), "first\n");
+    doc.redo();
+    doc.redo();
+    assert_eq!(text(&doc), "first\nsecond\nother\n");
+}
@@ -0,0 +1,113 @@
+//! Saving keeps what the file was besides its contents.
+
+mod common;
+
+use common::ScratchDir;
+use dip_document::document::Document;
+use std::{fs, path::Path};
+
+fn edit_and_save(path: &Path) {
+    common::edited(path, "edited ").save().unwrap();
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::*;
+    use std::os::unix::fs::{symlink, MetadataExt, PermissionsExt};
+
+    #[test]
+    fn keeps_the_mode() {
+        let dir = ScratchDir::new("mode");
+        let path = dir.join("run.sh");
+        fs::write(&path, "echo hi\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o754)).unwrap();
+
+        edit_and_save(&path);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "edited echo hi\n");
+        assert_eq!(fs::metadata(&path).unwrap().mode() & 0o7777, 0o754);
+    }
+
+    #[test]
+    fn writes_through_symlinks() {
+        let dir = ScratchDir::new("symlink");
+        fs::create_dir(dir.join("real")).unwrap();
+        let target = dir.join("real/config");
+        fs::write(&target, "value\n").unwrap();
+        symlink("real/config", dir.join("link")).unwrap();
+        symlink(dir.join("link"), dir.join("link-to-link")).unwrap();
+
+        edit_and_save(&dir.join("link-to-link"));
+        assert!(fs::symlink_metadata(dir.join("link-to-link"))
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert!(fs::symlink_metadata(dir.join("link"))
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "edited value\n");
+    }
+
+    #[test]
+    fn symlink_cycles_fail() {
+        let dir = ScratchDir::new("cycle");
+        symlink("b", dir.join("a")).unwrap();
+        symlink("a", dir.join("b")).unwrap();
+        let mut document = Document::new(Some(dir.join("a")), "text".into());
+        assert!(document.save().is_err());
+    }
+
+    #[test]
+    fn keeps_extended_attributes() {
+        let dir = ScratchDir::new("xattr");
+        let path = dir.join("notes");
+        fs::write(&path, "text").unwrap();
+        if xattr::set(&path, "user.dip.test", b"kept").is_err() {
+            // The file system has no user attributes.
+            return;
+        }
+
+        edit_and_save(&path);
+        assert_eq!(
+            xattr::get(&path, "user.dip.test").unwrap().as_deref(),
+            Some(&b"kept"[..])
+        );
+    }
+
+    #[test]
+    fn keeps_the_owner() {
+        if unsafe { libc::geteuid() } != 0 {
+            // Only root can give a file away.
+            return;
+        }
+        let dir = ScratchDir::new("owner");
+        let path = dir.join("owned");
+        fs::write(&path, "text").unwrap();
+        std::os::unix::fs::chown(&path, Some(1), Some(1)).unwrap();
+
+        edit_and_save(&path);
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!((metadata.uid(), metadata.gid()), (1, 1));
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use super::*;
+
+    #[test]
+    fn respects_the_read_only_attribute() {
+        let dir = ScratchDir::new("readonly");
+        let path = dir.join("locked.txt");
+        fs::write(&path, "text").unwrap();
+        let mut permissions = fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&path, permissions).unwrap();
+
+        let mut document = common::edited(&path, "edited ");
+        let error = document.save().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "text");
+        assert!(document.is_dirty());
+    }
+}
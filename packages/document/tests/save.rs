@@ -0,0 +1,115 @@
+//! Saving writes the document over its file in one step, the way it was read.
+
+mod common;
+
+use common::ScratchDir;
+use dip_document::{document::Document, text_buffer::TextBuffer};
+use std::{fs, io, path::Path};
+
+fn edit(path: &Path) -> Document {
+    common::edited(path, "edited ")
+}
+
+#[test]
+fn save_writes_over_the_file() {
+    let dir = ScratchDir::new("over");
+    let path = dir.join("notes.txt");
+    fs::write(&path, "saved\n").unwrap();
+
+    let mut document = edit(&path);
+    assert!(document.is_dirty());
+    document.save().unwrap();
+    assert!(!document.is_dirty());
+    assert!(!document.changed_on_disk());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "edited saved\n");
+    assert_eq!(dir.files(), ["notes.txt"]);
+}
+
+#[test]
+fn save_keeps_line_endings_and_byte_order_mark() {
+    let dir = ScratchDir::new("line-endings");
+    let path = dir.join("notes.txt");
+    fs::write(&path, "\u{feff}one\r\ntwo\r\n").unwrap();
+
+    let mut document = Document::from_path(&path).unwrap();
+    let end = document.buffer().len();
+    document.insert(end, "three\n");
+    document.save().unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "\u{feff}one\r\ntwo\r\nthree\r\n"
+    );
+}
+
+#[test]
+fn untitled_documents_need_a_path() {
+    let dir = ScratchDir::new("untitled");
+    let mut document = Document::new(None, TextBuffer::from("draft\n"));
+    let error = document.save().unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+    let path = dir.join("draft.txt");
+    document.save_as(&path).unwrap();
+    assert_eq!(document.path(), Some(path.as_path()));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "draft\n");
+}
+
+#[test]
+fn failed_saves_leave_the_document_unsaved() {
+    let dir = ScratchDir::new("failed");
+    let path = dir.join("missing").join("notes.txt");
+    let mut document = Document::new(None, TextBuffer::from("draft\n"));
+    document.insert(0, "edited ");
+    assert!(document.save_as(&path).is_err());
+    assert_eq!(document.path(), None);
+    assert!(document.is_dirty());
+    assert_eq!(dir.files(), Vec::<String>::new());
+}
+
+#[cfg(unix)]
+#[test]
+fn save_writes_through_symlinks_and_keeps_the_mode() {
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    let dir = ScratchDir::new("symlink");
+    let target = dir.join("target.sh");
+    let link = dir.join("link.sh");
+    fs::write(&target, "saved\n").unwrap();
+    fs::set_permissions(&target, fs::Permissions::from_mode(0o750)).unwrap();
+    symlink(&target, &link).unwrap();
+
+    edit(&link).save().unwrap();
+    assert!(fs::symlink_metadata(&link)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert_eq!(fs::read_to_string(&target).unwrap(), "edited saved\n");
+    let mode = fs::metadata(&target).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o750);
+}
+
+#[cfg(unix)]
+#[test]
+fn save_never_writes_through_a_planted_temporary_file() {
+    use std::os::unix::fs::symlink;
+
+    let dir = ScratchDir::new("planted");
+    let path = dir.join("notes.txt");
+    let victim = dir.join("victim.txt");
+    fs::write(&path, "saved\n").unwrap();
+    fs::write(&victim, "victim\n").unwrap();
+    let temp = dir.join(format!(".notes.txt.dip-save-{}", std::process::id()));
+    symlink(&victim, &temp).unwrap();
+
+    let mut document = edit(&path);
+    assert_eq!(
+        document.save().unwrap_err().kind(),
+        io::ErrorKind::AlreadyExists
+    );
+    assert_eq!(fs::read_to_string(&victim).unwrap(), "victim\n");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "saved\n");
+
+    document.save().unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "edited saved\n");
+    assert_eq!(dir.files(), ["notes.txt", "victim.txt"]);
+}
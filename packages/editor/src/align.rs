@@ -0,0 +1,273 @@
+//! Lining up the delimiters of lines, e.g. the `=` of assignments or the pipes of a table,
+//! by padding the text before them.
+
+use dip_document::{document::Document, text_buffer::TextBuffer};
+use regex::Regex;
+use std::{fmt, ops::Range};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Where the delimiters of a line are, and the text between them.
+struct Cells<'a> {
+    cells: Vec<&'a str>,
+    delimiters: Vec<&'a str>,
+}
+
+impl<'a> Cells<'a> {
+    fn split(line: &'a str, delimiter: &Regex, single: bool, all: bool) -> Option<Self> {
+        let mut cells = vec![];
+        let mut delimiters = vec![];
+        let mut start = 0;
+        for m in delimiter.find_iter(line) {
+            if m.as_str().is_empty() || (single && m.as_str().chars().nth(1).is_some()) {
+                continue;
+            }
+            cells.push(&line[start..m.start()]);
+            delimiters.push(m.as_str());
+            start = m.end();
+            if !all {
+                break;
+            }
+        }
+        if delimiters.is_empty() {
+            return None;
+        }
+        cells.push(&line[start..]);
+        Some(Self { cells, delimiters })
+    }
+}
+
+/// How many columns `text` takes, counting graphemes.
+pub fn width(text: &str) -> usize {
+    text.graphemes(true).count()
+}
+
+/// `lines` with their delimiters lined up.
+///
+/// Whether padding goes before a delimiter or after it follows the lines: `a = 1` keeps the
+/// delimiter apart from the text before it and `a: 1` attached, and so does the space after
+/// it. Indentation is kept and trailing blanks are dropped, so aligning
+/// aligned lines changes nothing.
+pub fn align(lines: &[&str], delimiter: &Regex, all: bool) -> Vec<String> {
+    align_lines(lines, delimiter, false, all)
+}
+
+fn align_lines(lines: &[&str], delimiter: &Regex, single: bool, all: bool) -> Vec<String> {
+    let split: Vec<Option<Cells>> = lines
+        .iter()
+        .map(|line| Cells::split(line, delimiter, single, all))
+        .collect();
+    let mut out: Vec<String> = split
+        .iter()
+        .zip(lines)
+        .map(|(cells, line)| match cells {
+            Some(cells) => cells.cells[0].trim_end().to_string(),
+            None => line.to_string(),
+        })
+        .collect();
+
+    let columns = split
+        .iter()
+        .flatten()
+        .map(|cells| cells.delimiters.len())
+        .max()
+        .unwrap_or(0);
+    for column in 0..columns {
+        let rows: Vec<usize> = (0..lines.len())
+            .filter(|row| {
+                split[*row]
+                    .as_ref()
+                    .is_some_and(|cells| column < cells.delimiters.len())
+            })
+            .collect();
+        let cells = |row: usize| split[row].as_ref().expect("rows have cells");
+        let spaced = rows.iter().any(|row| {
+            let before = cells(*row).cells[column];
+            before.ends_with(char::is_whitespace) && !before.trim().is_empty()
+        });
+        // Padding after an attached delimiter looks like a space, so one stays only if every
+        // line had it.
+        let mut after = rows
+            .iter()
+            .map(|row| cells(*row).cells[column + 1])
+            .filter(|next| !next.trim().is_empty())
+            .map(|next| next.starts_with(char::is_whitespace));
+        let space_after = match spaced {
+            true => after.any(|space| space),
+            false => after.all(|space| space),
+        };
+
+        if !spaced {
+            for row in &rows {
+                out[*row].push_str(cells(*row).delimiters[column]);
+            }
+        }
+        let target = rows.iter().map(|row| width(&out[*row])).max().unwrap_or(0);
+        for row in &rows {
+            let (cells, out) = (cells(*row), &mut out[*row]);
+            let pad = target - width(out);
+            out.push_str(&" ".repeat(pad));
+            if spaced {
+                out.push(' ');
+                out.push_str(cells.delimiters[column]);
+            }
+            let next = cells.cells[column + 1].trim();
+            if !next.is_empty() {
+                if space_after {
+                    out.push(' ');
+                }
+                out.push_str(next);
+            }
+        }
+    }
+    // Attached delimiters at the ends of lines would be padded for nothing.
+    for line in &mut out {
+        line.truncate(line.trim_end().len());
+    }
+    for (line, original) in out.iter_mut().zip(lines) {
+        if line.trim_end() == original.trim_end() {
+            *line = original.to_string();
+        }
+    }
+    out
+}
+
+/// The lines to align for a caret: the ones its selection touches, or the paragraph it is
+/// in. A selection ending at the start of a line leaves that line out.
+fn lines_for(buffer: &TextBuffer, range: Range<usize>) -> Range<usize> {
+    let first = buffer.line_at(range.start);
+    if !range.is_empty() {
+        let last = buffer.line_at(range.end);
+        let last = match range.end == buffer.line_start(last) && last > first {
+            true => last - 1,
+            false => last,
+        };
+        return first..last + 1;
+    }
+    let blank = |line: usize| buffer.get_line_content(line).trim().is_empty();
+    if blank(first) {
+        return first..first;
+    }
+    let mut start = first;
+    while start > 0 && !blank(start - 1) {
+        start -= 1;
+    }
+    let mut end = first + 1;
+    while end < buffer.line_count() && !blank(end) {
+        end += 1;
+    }
+    start..end
+}
+
+/// The smallest edit turning `old` into `new`, as the byte range of `old` and its text.
+pub fn line_edit(old: &str, new: &str) -> Option<(Range<usize>, String)> {
+    if old == new {
+        return None;
+    }
+    let prefix: usize = old
+        .chars()
+        .zip(new.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(c, _)| c.len_utf8())
+        .sum();
+    let suffix: usize = old[prefix..]
+        .chars()
+        .rev()
+        .zip(new[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(c, _)| c.len_utf8())
+        .sum();
+    Some((
+        prefix..old.len() - suffix,
+        new[prefix..new.len() - suffix].to_string(),
+    ))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AlignError {
+    ReadOnly,
+    /// The delimiter as given, and why it is not a regular expression.
+    InvalidDelimiter(String, String),
+    /// No line has the delimiter.
+    NotFound(String),
+}
+
+impl fmt::Display for AlignError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AlignError::ReadOnly => write!(f, "The document is read only"),
+            AlignError::InvalidDelimiter(delimiter, error) => {
+                write!(f, "Invalid delimiter {delimiter}: {error}")
+            }
+            AlignError::NotFound(delimiter) => write!(f, "No {delimiter} to align"),
+        }
+    }
+}
+
+/// Pads the lines each of `ranges` touches, or the paragraph around an empty one, so
+/// `delimiter` lines up, as one undo step. `regex` reads it as a regular expression, and
+/// `all` lines up every occurrence rather than only the first. Returns whether anything
+/// changed.
+pub fn align_ranges(
+    document: &mut Document,
+    ranges: &[Range<usize>],
+    delimiter: &str,
+    regex: bool,
+    all: bool,
+) -> Result<bool, AlignError> {
+    if document.is_read_only() {
+        return Err(AlignError::ReadOnly);
+    }
+    let pattern = match regex {
+        true => delimiter.to_string(),
+        false => format!("(?:{})+", regex::escape(delimiter)),
+    };
+    let single = !regex && delimiter.chars().count() == 1;
+    let pattern = Regex::new(&pattern)
+        .map_err(|error| AlignError::InvalidDelimiter(delimiter.to_string(), error.to_string()))?;
+
+    let buffer = document.buffer();
+    let mut groups: Vec<Range<usize>> = ranges
+        .iter()
+        .map(|range| lines_for(buffer, range.clone()))
+        .filter(|lines| !lines.is_empty())
+        .collect();
+    groups.sort_by_key(|lines| lines.start);
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(groups.len());
+    for lines in groups {
+        match merged.last_mut() {
+            Some(last) if lines.start <= last.end => last.end = last.end.max(lines.end),
+            _ => merged.push(lines),
+        }
+    }
+
+    // Only the padding changes, so the edits are small and cursors move along with them.
+    let mut found = false;
+    let mut edits = vec![];
+    for lines in merged {
+        let contents: Vec<_> = lines
+            .clone()
+            .map(|line| buffer.get_line_content(line))
+            .collect();
+        let old: Vec<&str> = contents.iter().map(|line| line.as_ref()).collect();
+        found |= old
+            .iter()
+            .any(|line| Cells::split(line, &pattern, single, all).is_some());
+        let new = align_lines(&old, &pattern, single, all);
+        for (line, (old, new)) in lines.zip(old.iter().zip(&new)) {
+            if let Some((range, text)) = line_edit(old, new) {
+                let start = buffer.line_start(line);
+                edits.push((start + range.start..start + range.end, text));
+            }
+        }
+    }
+    if !found {
+        return Err(AlignError::NotFound(delimiter.to_string()));
+    }
+    if edits.is_empty() {
+        return Ok(false);
+    }
+    document.history_mut().begin();
+    document.apply_edits(edits, 0);
+    document.history_mut().commit();
+    Ok(true)
+}
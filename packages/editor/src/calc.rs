@@ -0,0 +1,742 @@
+//! A calculator for expressions written in documents, with units and number bases, and
+//! adding up the numbers in them.
+
+use dip_document::{document::Document, text_buffer::TextBuffer};
+use std::{f64::consts, fmt, ops::Range};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CalcError {
+    Empty,
+    UnexpectedEnd,
+    /// A character, number or name that does not belong there.
+    Unexpected(String),
+    UnknownName(String),
+    /// Units of different kinds, e.g. `km` and `kg`.
+    Incompatible(String, String),
+    /// A unit where only numbers go, e.g. `sqrt(4 km)`.
+    UnitNotAllowed(String),
+    DivisionByZero,
+    /// Only whole numbers convert to another base.
+    NotAnInteger,
+    /// The result is infinite or not a number, e.g. `sqrt(-1)`.
+    NotANumber,
+    /// More than [`MAX_DEPTH`] parentheses, signs or powers inside each other.
+    TooDeep,
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CalcError::Empty => write!(f, "nothing to evaluate"),
+            CalcError::UnexpectedEnd => write!(f, "unexpected end"),
+            CalcError::Unexpected(text) => write!(f, "unexpected `{text}`"),
+            CalcError::UnknownName(name) => write!(f, "unknown name `{name}`"),
+            CalcError::Incompatible(a, b) => write!(f, "`{a}` does not convert to `{b}`"),
+            CalcError::UnitNotAllowed(unit) => write!(f, "`{unit}` is not allowed here"),
+            CalcError::DivisionByZero => write!(f, "division by zero"),
+            CalcError::NotAnInteger => write!(f, "not a whole number"),
+            CalcError::NotANumber => write!(f, "not a number"),
+            CalcError::TooDeep => write!(f, "nested too deeply"),
+        }
+    }
+}
+
+impl std::error::Error for CalcError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Dimension {
+    Length,
+    Mass,
+    Time,
+    Volume,
+    Data,
+    Temperature,
+}
+
+#[derive(Debug)]
+struct Unit {
+    /// How results show it.
+    symbol: &'static str,
+    /// What it is called in lower case, the symbol first.
+    names: &'static [&'static str],
+    dimension: Dimension,
+    /// A value in the base unit of its dimension is `value * factor + offset`.
+    factor: f64,
+    offset: f64,
+}
+
+const fn unit(
+    symbol: &'static str,
+    names: &'static [&'static str],
+    dimension: Dimension,
+    factor: f64,
+) -> Unit {
+    Unit {
+        symbol,
+        names,
+        dimension,
+        factor,
+        offset: 0.0,
+    }
+}
+
+/// `in` is left out of the names of inches, as it converts, e.g. `5 km in mi`.
+const UNITS: &[Unit] = &[
+    unit("mm", &["mm"], Dimension::Length, 0.001),
+    unit("cm", &["cm"], Dimension::Length, 0.01),
+    unit("m", &["m", "meter", "meters"], Dimension::Length, 1.0),
+    unit("km", &["km"], Dimension::Length, 1000.0),
+    unit("inch", &["inch", "inches"], Dimension::Length, 0.0254),
+    unit("ft", &["ft", "foot", "feet"], Dimension::Length, 0.3048),
+    unit("yd", &["yd", "yard", "yards"], Dimension::Length, 0.9144),
+    unit("mi", &["mi", "mile", "miles"], Dimension::Length, 1609.344),
+    unit("mg", &["mg"], Dimension::Mass, 0.001),
+    unit("g", &["g", "gram", "grams"], Dimension::Mass, 1.0),
+    unit("kg", &["kg"], Dimension::Mass, 1000.0),
+    unit(
+        "oz",
+        &["oz", "ounce", "ounces"],
+        Dimension::Mass,
+        28.349523125,
+    ),
+    unit(
+        "lb",
+        &["lb", "lbs", "pound", "pounds"],
+        Dimension::Mass,
+        453.59237,
+    ),
+    unit("ms", &["ms"], Dimension::Time, 0.001),
+    unit(
+        "s",
+        &["s", "sec", "second", "seconds"],
+        Dimension::Time,
+        1.0,
+    ),
+    unit("min", &["min", "minute", "minutes"], Dimension::Time, 60.0),
+    unit("h", &["h", "hr", "hour", "hours"], Dimension::Time, 3600.0),
+    unit("d", &["d", "day", "days"], Dimension::Time, 86400.0),
+    unit("week", &["week", "weeks"], Dimension::Time, 604800.0),
+    unit("ml", &["ml"], Dimension::Volume, 0.001),
+    unit("l", &["l", "liter", "liters"], Dimension::Volume, 1.0),
+    unit(
+        "gal",
+        &["gal", "gallon", "gallons"],
+        Dimension::Volume,
+        3.785411784,
+    ),
+    unit("bytes", &["bytes", "byte"], Dimension::Data, 1.0),
+    unit("KB", &["kb"], Dimension::Data, 1e3),
+    unit("MB", &["mb"], Dimension::Data, 1e6),
+    unit("GB", &["gb"], Dimension::Data, 1e9),
+    unit("TB", &["tb"], Dimension::Data, 1e12),
+    unit("KiB", &["kib"], Dimension::Data, 1024.0),
+    unit("MiB", &["mib"], Dimension::Data, 1048576.0),
+    unit("GiB", &["gib"], Dimension::Data, 1073741824.0),
+    unit("TiB", &["tib"], Dimension::Data, 1099511627776.0),
+    unit("K", &["k", "kelvin"], Dimension::Temperature, 1.0),
+    Unit {
+        symbol: "°C",
+        names: &["°c", "c", "celsius"],
+        dimension: Dimension::Temperature,
+        factor: 1.0,
+        offset: 273.15,
+    },
+    Unit {
+        symbol: "°F",
+        names: &["°f", "f", "fahrenheit"],
+        dimension: Dimension::Temperature,
+        factor: 5.0 / 9.0,
+        offset: 459.67 * 5.0 / 9.0,
+    },
+];
+
+fn find_unit(name: &str) -> Option<&'static Unit> {
+    UNITS.iter().find(|unit| unit.names.contains(&name))
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Value {
+    number: f64,
+    unit: Option<&'static Unit>,
+}
+
+impl Value {
+    fn plain(number: f64) -> Self {
+        Self { number, unit: None }
+    }
+
+    /// The number in `to`, or in it already without a unit.
+    fn convert(self, to: &Unit) -> Result<f64, CalcError> {
+        let from = match self.unit {
+            Some(from) => from,
+            None => return Ok(self.number),
+        };
+        if from.dimension != to.dimension {
+            return Err(CalcError::Incompatible(
+                from.symbol.to_string(),
+                to.symbol.to_string(),
+            ));
+        }
+        let base = self.number * from.factor + from.offset;
+        Ok((base - to.offset) / to.factor)
+    }
+
+    /// The number, if it has no unit.
+    fn number(self) -> Result<f64, CalcError> {
+        match self.unit {
+            Some(unit) => Err(CalcError::UnitNotAllowed(unit.symbol.to_string())),
+            None => Ok(self.number),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Symbol(char),
+}
+
+fn unexpected(token: Token) -> CalcError {
+    CalcError::Unexpected(match token {
+        Token::Number(n) => format_number(n),
+        Token::Name(name) => name,
+        Token::Symbol(c) => c.to_string(),
+    })
+}
+
+fn tokens(expression: &str) -> Result<Vec<Token>, CalcError> {
+    let chars: Vec<char> = expression.chars().collect();
+    let scan = |from: usize, keep: &dyn Fn(char) -> bool| {
+        (from..chars.len())
+            .find(|&i| !keep(chars[i]))
+            .unwrap_or(chars.len())
+    };
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let digit_next = chars.get(i + 1).is_some_and(|c| c.is_ascii_digit());
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' && digit_next {
+            let radix = match (c, chars.get(i + 1)) {
+                ('0', Some('x' | 'X')) => 16,
+                ('0', Some('b' | 'B')) => 2,
+                ('0', Some('o' | 'O')) => 8,
+                _ => 10,
+            };
+            let (end, number) = if radix == 10 {
+                let mut end = scan(i, &|c| c.is_ascii_digit() || c == '.' || c == '_');
+                if matches!(chars.get(end), Some('e' | 'E')) {
+                    let digits = match chars.get(end + 1) {
+                        Some('+' | '-') => end + 2,
+                        _ => end + 1,
+                    };
+                    if chars.get(digits).is_some_and(|c| c.is_ascii_digit()) {
+                        end = scan(digits, &|c| c.is_ascii_digit());
+                    }
+                }
+                let text: String = chars[i..end].iter().filter(|c| **c != '_').collect();
+                (end, text.parse::<f64>().ok())
+            } else {
+                let end = scan(i + 2, &|c| c.is_digit(radix) || c == '_');
+                let digits: String = chars[i + 2..end].iter().filter(|c| **c != '_').collect();
+                let number = i128::from_str_radix(&digits, radix).ok();
+                (end, number.map(|n| n as f64))
+            };
+            match number {
+                Some(number) => tokens.push(Token::Number(number)),
+                None => return Err(CalcError::Unexpected(chars[i..end].iter().collect())),
+            }
+            i = end;
+        } else if c.is_alphabetic() || c == '°' {
+            let end = scan(i, &|c| c.is_alphanumeric() || c == '°' || c == '_');
+            let name: String = chars[i..end].iter().collect();
+            tokens.push(Token::Name(name.to_lowercase()));
+            i = end;
+        } else if "+-*/%^(),×÷−".contains(c) {
+            tokens.push(Token::Symbol(match c {
+                '×' => '*',
+                '÷' => '/',
+                '−' => '-',
+                c => c,
+            }));
+            i += 1;
+        } else {
+            return Err(CalcError::Unexpected(c.to_string()));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Evaluates `expression` and formats the result to be written next to it, e.g. `2 * (3 +
+/// 4)`, `sqrt(2) ^ 2`, `5 km + 300 m in mi`, `100 °F to c`, `255 in hex` or `0b1010 to
+/// dec`. Numbers can be written in hex, binary and octal with `0x`, `0b` and `0o`.
+pub fn evaluate(expression: &str) -> Result<String, CalcError> {
+    let mut parser = Parser {
+        tokens: tokens(expression)?,
+        next: 0,
+        depth: 0,
+    };
+    if parser.tokens.is_empty() {
+        return Err(CalcError::Empty);
+    }
+    let value = parser.sum()?;
+    let target = match parser.bump() {
+        None => None,
+        Some(Token::Name(word)) if matches!(word.as_str(), "in" | "to" | "as") => {
+            match parser.bump() {
+                Some(Token::Name(target)) => Some(target),
+                Some(token) => return Err(unexpected(token)),
+                None => return Err(CalcError::UnexpectedEnd),
+            }
+        }
+        Some(token) => return Err(unexpected(token)),
+    };
+    if let Some(token) = parser.bump() {
+        return Err(unexpected(token));
+    }
+
+    let radix = match target.as_deref() {
+        Some("hex" | "hexadecimal") => 16,
+        Some("dec" | "decimal") => 10,
+        Some("bin" | "binary") => 2,
+        Some("oct" | "octal") => 8,
+        Some(name) => {
+            let unit = find_unit(name).ok_or_else(|| CalcError::UnknownName(name.to_string()))?;
+            let number = finite(value.convert(unit)?)?;
+            return Ok(format!("{} {}", format_number(number), unit.symbol));
+        }
+        None => {
+            let number = finite(value.number)?;
+            return Ok(match value.unit {
+                Some(unit) => format!("{} {}", format_number(number), unit.symbol),
+                None => format_number(number),
+            });
+        }
+    };
+    let number = finite(value.number()?)?;
+    if number.fract() != 0.0 || number.abs() >= i128::MAX as f64 {
+        return Err(CalcError::NotAnInteger);
+    }
+    let sign = if number < 0.0 { "-" } else { "" };
+    let n = number.abs() as i128;
+    Ok(match radix {
+        16 => format!("{sign}0x{n:x}"),
+        2 => format!("{sign}0b{n:b}"),
+        8 => format!("{sign}0o{n:o}"),
+        _ => format!("{sign}{n}"),
+    })
+}
+
+fn finite(number: f64) -> Result<f64, CalcError> {
+    match number.is_finite() {
+        true => Ok(number),
+        false => Err(CalcError::NotANumber),
+    }
+}
+
+/// Whole numbers without a fraction, others to 10 decimals at most, so `0.1 + 0.2` is
+/// `0.3`.
+fn format_number(number: f64) -> String {
+    if number == 0.0 {
+        return "0".to_string();
+    }
+    if !(1e-6..1e15).contains(&number.abs()) {
+        return format!("{number:e}");
+    }
+    let text = format!("{number:.10}");
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// How deep expressions nest, so parsing one cannot run out of stack.
+pub const MAX_DEPTH: usize = 64;
+
+/// Precedence climbing from `sum` down to `primary`.
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+    /// Every nested expression is parsed through `unary`, which counts them here.
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn bump(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += token.is_some() as usize;
+        token
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        let found = self.peek() == Some(&Token::Symbol(symbol));
+        self.next += found as usize;
+        found
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), CalcError> {
+        match self.bump() {
+            Some(Token::Symbol(c)) if c == symbol => Ok(()),
+            Some(token) => Err(unexpected(token)),
+            None => Err(CalcError::UnexpectedEnd),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Value, CalcError> {
+        let mut value = self.product()?;
+        loop {
+            let sign = match () {
+                () if self.eat('+') => 1.0,
+                () if self.eat('-') => -1.0,
+                () => return Ok(value),
+            };
+            let other = self.product()?;
+            // Added in the unit on the left, or the one on the right if only it has one.
+            let number = match value.unit {
+                Some(unit) => other.convert(unit)?,
+                None => other.number,
+            };
+            value = Value {
+                number: value.number + sign * number,
+                unit: value.unit.or(other.unit),
+            };
+        }
+    }
+
+    fn product(&mut self) -> Result<Value, CalcError> {
+        let mut value = self.unary()?;
+        loop {
+            let symbol = match self.peek() {
+                Some(Token::Symbol(c @ ('*' | '/' | '%'))) => *c,
+                _ => return Ok(value),
+            };
+            self.next += 1;
+            let other = self.unary()?;
+            value = match (symbol, value.unit, other.unit) {
+                ('*', Some(_), Some(unit)) => {
+                    return Err(CalcError::UnitNotAllowed(unit.symbol.to_string()))
+                }
+                ('*', ..) => Value {
+                    number: value.number * other.number,
+                    unit: value.unit.or(other.unit),
+                },
+                // A ratio of two lengths, say, is a plain number.
+                (_, Some(unit), Some(_)) => {
+                    let divisor = other.convert(unit)?;
+                    Value::plain(divide(symbol, value.number, divisor)?)
+                }
+                (_, _, Some(unit)) => {
+                    return Err(CalcError::UnitNotAllowed(unit.symbol.to_string()))
+                }
+                (_, unit, None) => Value {
+                    number: divide(symbol, value.number, other.number)?,
+                    unit,
+                },
+            };
+        }
+    }
+
+    fn unary(&mut self) -> Result<Value, CalcError> {
+        if self.depth == MAX_DEPTH {
+            return Err(CalcError::TooDeep);
+        }
+        self.depth += 1;
+        let value = self.signed();
+        self.depth -= 1;
+        value
+    }
+
+    fn signed(&mut self) -> Result<Value, CalcError> {
+        if self.eat('-') {
+            let value = self.unary()?;
+            return Ok(Value {
+                number: -value.number,
+                ..value
+            });
+        }
+        if self.eat('+') {
+            return self.unary();
+        }
+        self.power()
+    }
+
+    /// `^` is right associative and binds tighter than a sign on its left, so `-2 ^ 2` is
+    /// `-4`.
+    fn power(&mut self) -> Result<Value, CalcError> {
+        let base = self.postfix()?;
+        if !self.eat('^') {
+            return Ok(base);
+        }
+        let exponent = self.unary()?.number()?;
+        Ok(Value::plain(base.number()?.powf(exponent)))
+    }
+
+    /// A value followed by its unit, e.g. `5 km` or `(2 + 3) km`.
+    fn postfix(&mut self) -> Result<Value, CalcError> {
+        let value = self.primary()?;
+        let unit = match self.peek() {
+            Some(Token::Name(name)) if value.unit.is_none() => find_unit(name),
+            _ => None,
+        };
+        match unit {
+            Some(unit) => {
+                self.next += 1;
+                Ok(Value {
+                    number: value.number,
+                    unit: Some(unit),
+                })
+            }
+            None => Ok(value),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Value, CalcError> {
+        match self.bump() {
+            Some(Token::Number(number)) => Ok(Value::plain(number)),
+            Some(Token::Symbol('(')) => {
+                let value = self.sum()?;
+                self.expect(')')?;
+                Ok(value)
+            }
+            Some(Token::Name(name)) if self.peek() == Some(&Token::Symbol('(')) => {
+                self.next += 1;
+                let mut arguments = vec![self.sum()?];
+                while self.eat(',') {
+                    arguments.push(self.sum()?);
+                }
+                self.expect(')')?;
+                call(&name, arguments)
+            }
+            Some(Token::Name(name)) => match name.as_str() {
+                "pi" | "π" => Ok(Value::plain(consts::PI)),
+                "tau" | "τ" => Ok(Value::plain(consts::TAU)),
+                "e" => Ok(Value::plain(consts::E)),
+                _ => Err(CalcError::UnknownName(name)),
+            },
+            Some(token) => Err(unexpected(token)),
+            None => Err(CalcError::UnexpectedEnd),
+        }
+    }
+}
+
+fn divide(symbol: char, dividend: f64, divisor: f64) -> Result<f64, CalcError> {
+    if divisor == 0.0 {
+        return Err(CalcError::DivisionByZero);
+    }
+    Ok(match symbol {
+        '%' => dividend % divisor,
+        _ => dividend / divisor,
+    })
+}
+
+/// Functions keeping the unit of their first argument, and ones taking numbers.
+fn call(name: &str, arguments: Vec<Value>) -> Result<Value, CalcError> {
+    let first = arguments[0];
+    let keep = |f: fn(f64) -> f64| -> Result<Value, CalcError> {
+        match arguments.len() {
+            1 => Ok(Value {
+                number: f(first.number),
+                unit: first.unit,
+            }),
+            _ => Err(CalcError::Unexpected(",".to_string())),
+        }
+    };
+    let plain = |f: fn(f64) -> f64| -> Result<Value, CalcError> {
+        match arguments.len() {
+            1 => Ok(Value::plain(f(first.number()?))),
+            _ => Err(CalcError::Unexpected(",".to_string())),
+        }
+    };
+    match name {
+        "abs" => keep(f64::abs),
+        "round" => keep(f64::round),
+        "floor" => keep(f64::floor),
+        "ceil" => keep(f64::ceil),
+        "min" | "max" => {
+            let mut best = first.number;
+            for other in &arguments[1..] {
+                let number = match first.unit {
+                    Some(unit) => other.convert(unit)?,
+                    None => other.number()?,
+                };
+                best = match name {
+                    "min" => best.min(number),
+                    _ => best.max(number),
+                };
+            }
+            Ok(Value {
+                number: best,
+                unit: first.unit,
+            })
+        }
+        "sqrt" => plain(f64::sqrt),
+        "cbrt" => plain(f64::cbrt),
+        "exp" => plain(f64::exp),
+        "ln" => plain(f64::ln),
+        "log" => plain(f64::log10),
+        "log2" => plain(f64::log2),
+        "sin" => plain(f64::sin),
+        "cos" => plain(f64::cos),
+        "tan" => plain(f64::tan),
+        "asin" => plain(f64::asin),
+        "acos" => plain(f64::acos),
+        "atan" => plain(f64::atan),
+        _ => Err(CalcError::UnknownName(name.to_string())),
+    }
+}
+
+/// The selection, or the line without its indent and trailing blanks if it is empty.
+fn expression_range(buffer: &TextBuffer, selection: Range<usize>) -> Range<usize> {
+    if !selection.is_empty() {
+        return selection;
+    }
+    let line = buffer.line_at(selection.start);
+    let content = buffer.get_line_content(line);
+    let start = buffer.line_start(line);
+    let indent = content.len() - content.trim_start().len();
+    start + indent..start + content.trim_end().len().max(indent)
+}
+
+/// The edit evaluating the expression in `selection`, or on its line if it is empty. The
+/// result replaces the expression with `replace`, and otherwise follows an `=` ending it or
+/// is appended after ` = `. None when there is no expression.
+pub fn evaluation(
+    buffer: &TextBuffer,
+    selection: Range<usize>,
+    replace: bool,
+) -> Result<Option<(Range<usize>, String)>, String> {
+    let range = expression_range(buffer, selection);
+    let text = buffer.text_in(range.clone());
+    let trimmed = text.trim_end();
+    let (expression, equals) = match trimmed.strip_suffix('=') {
+        Some(expression) => (expression, true),
+        None => (trimmed, false),
+    };
+    if expression.trim().is_empty() {
+        return Ok(None);
+    }
+    let result = evaluate(expression)
+        .map_err(|e| format!("Failed to evaluate {}: {e}", expression.trim()))?;
+    let end = range.start + trimmed.len();
+    Ok(Some(match (replace, equals) {
+        (true, _) => (range, result),
+        (false, true) => (end..end, format!(" {result}")),
+        (false, false) => (end..end, format!(" = {result}")),
+    }))
+}
+
+/// The number touching `offset` on its line, or the first one after it.
+fn number_at(buffer: &TextBuffer, offset: usize) -> Option<Range<usize>> {
+    let line = buffer.line_at(offset);
+    let start = buffer.line_start(line);
+    let content = buffer.get_line_content(line);
+    let column = offset - start;
+    let part = |c: char| c.is_ascii_digit() || c == '.' || c == ',' || c == '_';
+    let mut numbers = vec![];
+    let mut i = 0;
+    while i < content.len() {
+        let c = content[i..].chars().next()?;
+        if !part(c) {
+            i += c.len_utf8();
+            continue;
+        }
+        let end = content[i..]
+            .find(|c| !part(c))
+            .map_or(content.len(), |n| i + n);
+        let text = content[i..end].trim_end_matches(['.', ',']);
+        if text.chars().any(|c| c.is_ascii_digit()) {
+            let negative = content[..i].ends_with('-');
+            numbers.push(i - negative as usize..i + text.len());
+        }
+        i = end;
+    }
+    numbers
+        .iter()
+        .find(|n| n.start <= column && column <= n.end)
+        .or_else(|| numbers.iter().find(|n| n.start > column))
+        .map(|n| start + n.start..start + n.end)
+}
+
+/// A number written with thousands separators or a currency sign, and its decimals.
+fn parse_number(text: &str) -> Option<(f64, usize)> {
+    let text: String = text
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, ',' | '_' | '$' | '€' | '£' | '¥'))
+        .collect();
+    let number = text.parse::<f64>().ok()?;
+    let decimals = text
+        .split_once('.')
+        .map_or(0, |(_, fraction)| fraction.len());
+    Some((number, decimals))
+}
+
+/// The total of the numbers added up by [`insert_sum`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sum {
+    pub count: usize,
+    pub total: String,
+    /// The end of the line the total was written on.
+    pub cursor: usize,
+}
+
+/// Adds up the numbers in `ranges`, or at the empty ones, and writes the total on a line
+/// below the last one, as one undo step. None when there are no numbers.
+pub fn insert_sum(document: &mut Document, ranges: &[Range<usize>]) -> Option<Sum> {
+    let buffer = document.buffer();
+    let mut ranges: Vec<Range<usize>> = ranges
+        .iter()
+        .filter_map(|range| match range {
+            range if range.is_empty() => number_at(buffer, range.start),
+            range => Some(range.clone()),
+        })
+        .collect();
+    ranges.sort_by_key(|range| range.start);
+    ranges.dedup();
+    let numbers: Vec<(f64, usize)> = ranges
+        .iter()
+        .filter_map(|range| parse_number(&buffer.text_in(range.clone())))
+        .collect();
+    let last = match ranges.last() {
+        Some(last) if !numbers.is_empty() => last.clone(),
+        _ => return None,
+    };
+    let total: f64 = numbers.iter().map(|(number, _)| number).sum();
+    let total = match numbers.iter().map(|(_, decimals)| *decimals).max() {
+        Some(decimals) if decimals > 0 => format!("{total:.decimals$}"),
+        _ => format_number(total),
+    };
+
+    // Lined up under the last number, by their right edges if they all line up.
+    let line = buffer.line_at(last.start);
+    let line_start = buffer.line_start(line);
+    let column = |offset: usize| {
+        let line_start = buffer.line_start(buffer.line_at(offset));
+        buffer.text_in(line_start..offset).chars().count()
+    };
+    let width = match ranges.iter().all(|r| column(r.end) == column(last.end)) {
+        true => column(last.end).saturating_sub(total.chars().count()),
+        false => column(last.start),
+    };
+    let indent: String = buffer
+        .text_in(line_start..last.start)
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .chain(std::iter::repeat(' '))
+        .take(width)
+        .collect();
+    let at = buffer.line_range(line).end;
+    let text = format!("\n{indent}{total}");
+    document.history_mut().begin();
+    document.insert(at, &text);
+    document.history_mut().commit();
+    Some(Sum {
+        count: numbers.len(),
+        total,
+        cursor: at + text.len(),
+    })
+}
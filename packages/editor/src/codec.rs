@@ -0,0 +1,355 @@
+//! Encodings text is often found in, e.g. base64 in a config file or percent-encoding in a
+//! URL, and telling which one a piece of text is in.
+
+use std::fmt;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+/// Bytes per line of a hex dump.
+const DUMP_WIDTH: usize = 16;
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Codec {
+    Base64,
+    /// Percent-encoding of everything but the unreserved characters of RFC 3986.
+    Url,
+    Html,
+    /// The inside of a JSON string, quotes left out.
+    Json,
+    /// `xxd` style: offsets, bytes in groups of two and the printable ones.
+    Hex,
+}
+
+impl Codec {
+    pub const ALL: [Codec; 5] = [
+        Codec::Json,
+        Codec::Url,
+        Codec::Html,
+        Codec::Hex,
+        Codec::Base64,
+    ];
+
+    pub fn encode(self, text: &str) -> String {
+        match self {
+            Codec::Base64 => base64_encode(text.as_bytes()),
+            Codec::Url => url_encode(text),
+            Codec::Html => html_encode(text),
+            Codec::Json => {
+                let quoted = serde_json::Value::String(text.to_string()).to_string();
+                quoted[1..quoted.len() - 1].to_string()
+            }
+            Codec::Hex => hex_dump(text.as_bytes()),
+        }
+    }
+
+    pub fn decode(self, text: &str) -> Result<String, CodecError> {
+        let bytes = match self {
+            Codec::Base64 => base64_decode(text)?,
+            Codec::Url => url_decode(text)?,
+            Codec::Html => return Ok(html_decode(text)),
+            Codec::Json => return json_unescape(text),
+            Codec::Hex => hex_decode(text)?,
+        };
+        String::from_utf8(bytes).map_err(|_| CodecError::NotText)
+    }
+
+    /// Whether `text` looks encoded this way, beyond merely decoding.
+    fn looks_encoded(self, text: &str) -> bool {
+        let text = text.trim();
+        match self {
+            Codec::Json => {
+                (text.len() >= 2 && text.starts_with('"') && text.ends_with('"'))
+                    || ["\\\"", "\\n", "\\t", "\\\\", "\\u"]
+                        .iter()
+                        .any(|escape| text.contains(escape))
+            }
+            Codec::Url => text
+                .as_bytes()
+                .windows(3)
+                .any(|w| w[0] == b'%' && w[1].is_ascii_hexdigit() && w[2].is_ascii_hexdigit()),
+            Codec::Html => text.contains('&') && text.contains(';') && html_decode(text) != text,
+            Codec::Hex => {
+                let digits = text.chars().filter(|c| !c.is_whitespace()).count();
+                digits >= 8 && text.chars().any(|c| c.is_ascii_digit())
+            }
+            Codec::Base64 => {
+                let body = text.trim_end_matches('=');
+                body.len() >= 8
+                    && !body.contains(char::is_whitespace)
+                    && (text.len().is_multiple_of(4) || text.ends_with('='))
+                    && body.chars().any(|c| c.is_ascii_uppercase())
+                    && body
+                        .chars()
+                        .any(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+            }
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Codec::Base64 => "Base64",
+            Codec::Url => "URL",
+            Codec::Html => "HTML entities",
+            Codec::Json => "JSON string",
+            Codec::Hex => "Hex",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CodecError {
+    /// A character that cannot be part of the encoding, with its byte offset.
+    Invalid(char, usize),
+    /// Cut off, e.g. an odd number of hex digits.
+    Truncated,
+    /// Decoded bytes that are not UTF-8.
+    NotText,
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CodecError::Invalid(c, at) => write!(f, "Unexpected {c:?} at {at}"),
+            CodecError::Truncated => write!(f, "The text is cut off"),
+            CodecError::NotText => write!(f, "The decoded bytes are not UTF-8 text"),
+        }
+    }
+}
+
+/// The codecs `text` looks encoded with and decodes to readable text with, most telling
+/// first.
+pub fn detect(text: &str) -> Vec<Codec> {
+    let readable = |decoded: String| {
+        decoded
+            .chars()
+            .all(|c| !c.is_control() || c.is_whitespace())
+    };
+    Codec::ALL
+        .into_iter()
+        .filter(|codec| codec.looks_encoded(text))
+        .filter(|codec| codec.decode(text).is_ok_and(readable))
+        .collect()
+}
+
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => text.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char),
+                false => text.push('='),
+            }
+        }
+    }
+    text
+}
+
+/// Standard or URL-safe base64, with or without padding. Whitespace is skipped, as in
+/// wrapped output.
+pub fn base64_decode(text: &str) -> Result<Vec<u8>, CodecError> {
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    let (mut n, mut bits) = (0u32, 0);
+    let body = text.trim_end().trim_end_matches('=');
+    for (at, c) in body.char_indices() {
+        let value = match c {
+            'A'..='Z' => c as u32 - 'A' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 26,
+            '0'..='9' => c as u32 - '0' as u32 + 52,
+            '+' | '-' => 62,
+            '/' | '_' => 63,
+            c if c.is_whitespace() => continue,
+            c => return Err(CodecError::Invalid(c, at)),
+        };
+        n = n << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((n >> bits) as u8);
+        }
+    }
+    // One character left over holds less than a byte.
+    if bits >= 6 {
+        return Err(CodecError::Truncated);
+    }
+    Ok(bytes)
+}
+
+fn url_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for b in text.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(b as char)
+            }
+            b => encoded.push_str(&format!("%{b:02X}")),
+        }
+    }
+    encoded
+}
+
+/// Decodes `%XX` escapes. A `+` stays one, it only means a space in form data.
+fn url_decode(text: &str) -> Result<Vec<u8>, CodecError> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'%' {
+            decoded.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        let hex = text.get(i + 1..i + 3).ok_or(CodecError::Truncated)?;
+        let byte = u8::from_str_radix(hex, 16).map_err(|_| {
+            let c = hex.chars().find(|c| !c.is_ascii_hexdigit()).unwrap_or('%');
+            CodecError::Invalid(c, i)
+        })?;
+        decoded.push(byte);
+        i += 3;
+    }
+    Ok(decoded)
+}
+
+fn html_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => encoded.push_str("&amp;"),
+            '<' => encoded.push_str("&lt;"),
+            '>' => encoded.push_str("&gt;"),
+            '"' => encoded.push_str("&quot;"),
+            '\'' => encoded.push_str("&#39;"),
+            c => encoded.push(c),
+        }
+    }
+    encoded
+}
+
+/// Decodes numeric references and the common named ones. Anything else is kept.
+fn html_decode(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('&') {
+        decoded.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let end = match rest[1..].find(|c: char| !c.is_ascii_alphanumeric() && c != '#') {
+            Some(end) if rest[1 + end..].starts_with(';') => 1 + end,
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let name = &rest[1..end];
+        let c = match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => match name.strip_prefix('#') {
+                Some(hex) if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16)
+                    .ok()
+                    .and_then(char::from_u32),
+                Some(decimal) => decimal.parse().ok().and_then(char::from_u32),
+                None => None,
+            },
+        };
+        match c {
+            Some(c) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// The text of a JSON string, with or without its quotes.
+fn json_unescape(text: &str) -> Result<String, CodecError> {
+    let trimmed = text.trim();
+    let quoted = match trimmed.len() >= 2 && trimmed.starts_with('"') && trimmed.ends_with('"') {
+        true => trimmed.to_string(),
+        false => format!("\"{text}\""),
+    };
+    serde_json::from_str::<String>(&quoted).map_err(|e| {
+        // Columns count the quote added in front.
+        let at = e.column().saturating_sub(2);
+        match quoted[..].chars().nth(at + 1) {
+            Some(c) if !e.is_eof() => CodecError::Invalid(c, at),
+            _ => CodecError::Truncated,
+        }
+    })
+}
+
+fn hex_dump(bytes: &[u8]) -> String {
+    let mut lines = vec![];
+    for (i, chunk) in bytes.chunks(DUMP_WIDTH).enumerate() {
+        let mut line = format!("{:08x}:", i * DUMP_WIDTH);
+        for (j, b) in chunk.iter().enumerate() {
+            if j.is_multiple_of(2) {
+                line.push(' ');
+            }
+            line.push_str(&format!("{b:02x}"));
+        }
+        // Short last lines keep the text column in place.
+        let missing = DUMP_WIDTH - chunk.len();
+        line.push_str(&" ".repeat(missing * 2 + missing / 2));
+        line.push_str("  ");
+        line.extend(chunk.iter().map(|b| match b {
+            0x20..=0x7e => *b as char,
+            _ => '.',
+        }));
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+/// Bytes from a hex dump like [`Codec::Hex`] writes, or from bare hex digits with optional
+/// whitespace and `0x` prefixes between them.
+fn hex_decode(text: &str) -> Result<Vec<u8>, CodecError> {
+    let mut digits = vec![];
+    let mut start = 0;
+    for line in text.split_inclusive('\n') {
+        let offset = line.split_once(": ").filter(|(offset, _)| {
+            !offset.is_empty() && offset.chars().all(|c| c.is_ascii_hexdigit())
+        });
+        let (skip, hex) = match offset {
+            Some((offset, rest)) => {
+                let hex = rest.split("  ").next().unwrap_or(rest);
+                (offset.len() + 2, hex)
+            }
+            None => (0, line),
+        };
+        let hex_start = start + skip;
+        let mut chars = hex.char_indices().peekable();
+        while let Some((at, c)) = chars.next() {
+            if c.is_whitespace() {
+                continue;
+            }
+            if c == '0' && matches!(chars.peek(), Some((_, 'x' | 'X'))) {
+                chars.next();
+                continue;
+            }
+            match c.to_digit(16) {
+                Some(digit) => digits.push(digit as u8),
+                None => return Err(CodecError::Invalid(c, hex_start + at)),
+            }
+        }
+        start += line.len();
+    }
+    if !digits.len().is_multiple_of(2) {
+        return Err(CodecError::Truncated);
+    }
+    Ok(digits
+        .chunks(2)
+        .map(|pair| pair[0] << 4 | pair[1])
+        .collect())
+}
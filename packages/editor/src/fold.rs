@@ -0,0 +1,308 @@
+//! Folding regions of lines away, and mapping the lines of a document to the lines on
+//! screen around what is folded.
+
+#[cfg(feature = "bevy")]
+use bevy::ecs::component::Component;
+use dip_document::{document::LineChange, text_buffer::TextBuffer};
+use std::{cmp::Reverse, ops::Range};
+
+/// Columns a tab indents by when comparing indentation.
+const TAB_WIDTH: usize = 4;
+
+/// Folded regions of a document and the mapping between its lines and the lines on screen.
+///
+/// A region is a range of lines whose first line, the header, stays visible while the rest
+/// is hidden. Regions nest, folding an outer one hides the inner ones along with it.
+#[cfg_attr(feature = "bevy", derive(Component))]
+#[derive(Clone, Debug, Default)]
+pub struct Folds {
+    /// Sorted by start, longest first.
+    folded: Vec<Range<usize>>,
+    provided: Option<Vec<Range<usize>>>,
+    /// Lines hidden by `folded`, sorted and disjoint, with the number hidden before each.
+    hidden: Vec<(Range<usize>, usize)>,
+}
+
+impl Folds {
+    pub fn folded(&self) -> &[Range<usize>] {
+        &self.folded
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.folded.is_empty()
+    }
+
+    /// Hides all but the first of `lines`. Returns false if it was folded already or is a
+    /// single line.
+    pub fn fold(&mut self, lines: Range<usize>) -> bool {
+        if lines.len() < 2 || self.folded.contains(&lines) {
+            return false;
+        }
+        let at = self
+            .folded
+            .partition_point(|r| (r.start, Reverse(r.end)) < (lines.start, Reverse(lines.end)));
+        self.folded.insert(at, lines);
+        self.rebuild();
+        true
+    }
+
+    /// Unfolds every folded region overlapping `lines`. Returns false if there was none.
+    pub fn unfold(&mut self, lines: Range<usize>) -> bool {
+        let count = self.folded.len();
+        // An empty range still unfolds the regions around its line.
+        let end = lines.end.max(lines.start + 1);
+        self.folded
+            .retain(|r| !(r.start < end && lines.start < r.end));
+        if self.folded.len() == count {
+            return false;
+        }
+        self.rebuild();
+        true
+    }
+
+    /// Unfolds the regions `line` is the header of. Returns false if there was none.
+    pub fn unfold_header(&mut self, line: usize) -> bool {
+        let count = self.folded.len();
+        self.folded.retain(|r| r.start != line);
+        self.rebuild();
+        self.folded.len() != count
+    }
+
+    /// Folds every region there is. Returns false if they all were folded already.
+    pub fn fold_all(&mut self, buffer: &TextBuffer) -> bool {
+        let regions = self.regions(buffer);
+        // Folding every region at once only builds the mapping once.
+        let count = self.folded.len();
+        self.folded.extend(regions);
+        self.folded.sort_by_key(|r| (r.start, Reverse(r.end)));
+        self.folded.dedup();
+        self.rebuild();
+        self.folded.len() != count
+    }
+
+    pub fn unfold_all(&mut self) -> bool {
+        let any = !self.folded.is_empty();
+        self.folded.clear();
+        self.hidden.clear();
+        any
+    }
+
+    /// The regions that can be folded: the provided ones, or else the ones indentation
+    /// makes out. Sorted by start, longest first.
+    pub fn regions(&self, buffer: &TextBuffer) -> Vec<Range<usize>> {
+        match &self.provided {
+            Some(provided) => provided.clone(),
+            None => indentation_regions(buffer),
+        }
+    }
+
+    /// Makes the regions that can be folded `ranges`, e.g. as a language server sees them,
+    /// or the ones indentation makes out again with None.
+    pub fn provide(&mut self, ranges: Option<&[Range<usize>]>, line_count: usize) {
+        self.provided = ranges.map(|ranges| {
+            let mut ranges: Vec<Range<usize>> = ranges
+                .iter()
+                .map(|r| r.start..r.end.min(line_count))
+                .filter(|r| r.len() >= 2)
+                .collect();
+            ranges.sort_by_key(|r| (r.start, Reverse(r.end)));
+            ranges.dedup();
+            ranges
+        });
+    }
+
+    /// The innermost region starting on `line` that is still open, or else around it.
+    pub fn region_at(&self, buffer: &TextBuffer, line: usize) -> Option<Range<usize>> {
+        let open: Vec<Range<usize>> = self
+            .regions(buffer)
+            .into_iter()
+            .filter(|r| r.start <= line && line < r.end && !self.folded.contains(r))
+            .collect();
+        let innermost = |regions: &mut dyn Iterator<Item = &Range<usize>>| {
+            regions.min_by_key(|r| r.len()).cloned()
+        };
+        innermost(&mut open.iter().filter(|r| r.start == line))
+            .or_else(|| innermost(&mut open.iter()))
+    }
+
+    pub fn is_hidden(&self, line: usize) -> bool {
+        self.hiding(line).is_some()
+    }
+
+    /// The line on screen showing `line`, which for a hidden line is the header folding it.
+    pub fn visible_line(&self, line: usize) -> usize {
+        let i = self.hidden.partition_point(|(r, _)| r.start <= line);
+        match i.checked_sub(1).map(|i| &self.hidden[i]) {
+            None => line,
+            Some((r, before)) if line < r.end => r.start - 1 - before,
+            Some((r, before)) => line - before - r.len(),
+        }
+    }
+
+    /// The document line shown as `visible`, the inverse of [`Folds::visible_line`] for
+    /// lines that are not hidden.
+    pub fn document_line(&self, visible: usize) -> usize {
+        let i = self
+            .hidden
+            .partition_point(|(r, before)| r.start - before <= visible);
+        match i.checked_sub(1).map(|i| &self.hidden[i]) {
+            None => visible,
+            Some((r, before)) => visible + before + r.len(),
+        }
+    }
+
+    /// Lines on screen for a document of `line_count` lines.
+    pub fn visible_line_count(&self, line_count: usize) -> usize {
+        let hidden: usize = self
+            .hidden
+            .iter()
+            .map(|(r, _)| r.end.min(line_count).saturating_sub(r.start))
+            .sum();
+        line_count - hidden
+    }
+
+    /// The runs of lines within `lines` that are not hidden.
+    pub fn visible_ranges(&self, lines: Range<usize>) -> Vec<Range<usize>> {
+        let mut ranges = vec![];
+        let mut start = lines.start;
+        let first = self.hidden.partition_point(|(r, _)| r.end <= lines.start);
+        for (r, _) in self.hidden[first..].iter() {
+            if r.start >= lines.end {
+                break;
+            }
+            if r.start > start {
+                ranges.push(start..r.start);
+            }
+            start = start.max(r.end);
+        }
+        if start < lines.end {
+            ranges.push(start..lines.end);
+        }
+        ranges
+    }
+
+    /// The document line on screen standing for `line`: itself, or the header folding it.
+    pub fn shown_as(&self, line: usize) -> usize {
+        self.hiding(line).map_or(line, |r| r.start - 1)
+    }
+
+    /// The first line on screen above `line`.
+    pub fn line_above(&self, line: usize) -> Option<usize> {
+        let above = line.checked_sub(1)?;
+        Some(self.shown_as(above))
+    }
+
+    /// The first line on screen below `line` in a document of `line_count` lines.
+    pub fn line_below(&self, line: usize, line_count: usize) -> Option<usize> {
+        let below = line + 1;
+        let below = self.hiding(below).map_or(below, |r| r.end);
+        (below < line_count).then_some(below)
+    }
+
+    /// The hidden lines around `line`.
+    fn hiding(&self, line: usize) -> Option<&Range<usize>> {
+        let i = self.hidden.partition_point(|(r, _)| r.start <= line);
+        let (r, _) = self.hidden.get(i.checked_sub(1)?)?;
+        (line < r.end).then_some(r)
+    }
+
+    /// Follows an edit. A fold whose hidden lines it touches, or that gains or loses lines
+    /// on its header, is unfolded. Returns whether any was.
+    pub fn edited(&mut self, change: &LineChange, line_count: usize) -> bool {
+        if self.folded.is_empty() && self.provided.is_none() {
+            return false;
+        }
+        let touched = change.start..change.start + change.removed + 1;
+        let count = self.folded.len();
+        self.folded.retain_mut(|r| {
+            if touched.end <= r.start {
+                *r = r.start - change.removed + change.inserted
+                    ..r.end - change.removed + change.inserted;
+                true
+            } else {
+                change.start >= r.end || (touched == (r.start..r.start + 1) && change.inserted == 0)
+            }
+        });
+        self.folded.retain(|r| r.end <= line_count);
+        if let Some(provided) = &mut self.provided {
+            let map = |line: usize| match line {
+                line if line <= change.start => line,
+                line if line >= touched.end => line + change.inserted - change.removed,
+                _ => change.start + change.inserted,
+            };
+            for r in provided.iter_mut() {
+                *r = map(r.start)..map(r.end).min(line_count);
+            }
+            provided.retain(|r| r.len() >= 2);
+        }
+        self.rebuild();
+        self.folded.len() != count
+    }
+
+    fn rebuild(&mut self) {
+        let mut merged: Vec<Range<usize>> = vec![];
+        for r in &self.folded {
+            let hide = r.start + 1..r.end;
+            match merged.last_mut() {
+                Some(last) if hide.start <= last.end => last.end = last.end.max(hide.end),
+                _ => merged.push(hide),
+            }
+        }
+        let mut before = 0;
+        self.hidden = merged
+            .into_iter()
+            .map(|r| {
+                let len = r.len();
+                let entry = (r, before);
+                before += len;
+                entry
+            })
+            .collect();
+    }
+}
+
+fn indent_width(line: &str) -> usize {
+    let mut width = 0;
+    for c in line.chars() {
+        match c {
+            ' ' => width += 1,
+            '\t' => width += TAB_WIDTH - width % TAB_WIDTH,
+            _ => break,
+        }
+    }
+    width
+}
+
+/// Regions of lines indented deeper than the line before them, which is their header.
+/// Blank lines at the end of a region are left out of it. Sorted by start, longest first.
+pub fn indentation_regions(buffer: &TextBuffer) -> Vec<Range<usize>> {
+    let mut regions = vec![];
+    // Headers not closed yet, with their indentation.
+    let mut open: Vec<(usize, usize)> = vec![];
+    let mut last = 0;
+    for line in 0..buffer.line_count() {
+        let content = buffer.get_line_content(line);
+        if content.trim().is_empty() {
+            continue;
+        }
+        let indent = indent_width(&content);
+        while let Some(&(header, width)) = open.last() {
+            if width < indent {
+                break;
+            }
+            open.pop();
+            if last > header {
+                regions.push(header..last + 1);
+            }
+        }
+        open.push((line, indent));
+        last = line;
+    }
+    for (header, _) in open {
+        if last > header {
+            regions.push(header..last + 1);
+        }
+    }
+    regions.sort_by_key(|r| (r.start, Reverse(r.end)));
+    regions
+}
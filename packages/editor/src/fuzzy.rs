@@ -0,0 +1,98 @@
+//! Fuzzy matching of what is typed into a picker against file names, commands and symbols.
+
+/// Points for each query character found, plus these for where it was found.
+const MATCH: i32 = 1;
+const WORD_START: i32 = 8;
+const CONSECUTIVE: i32 = 5;
+/// Taken per character of the text, so shorter texts win ties.
+const LENGTH_PENALTY: i32 = 1;
+
+/// How well `query` matches `text` when its characters appear in order, ignoring case,
+/// with the char indices of `text` they were found at. None if they do not all appear.
+/// Characters at the start of words and right after each other score higher, like the
+/// quick open of most editors.
+pub fn fuzzy_match(query: &str, text: &str) -> Option<(i32, Vec<usize>)> {
+    let query: Vec<char> = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect();
+    let chars: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = chars
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+    if query.is_empty() {
+        return Some((0, vec![]));
+    }
+    if query.len() > chars.len() {
+        return None;
+    }
+
+    let n = chars.len();
+    // rows[i][j]: the best score with query character i at j, and where character i - 1
+    // was.
+    let mut rows: Vec<Vec<Option<(i32, usize)>>> = Vec::with_capacity(query.len());
+    for (i, q) in query.iter().enumerate() {
+        let mut row = vec![None; n];
+        // The best of the previous row before j - 1, and where it is.
+        let mut before: Option<(i32, usize)> = None;
+        for j in 0..n {
+            if i > 0 && j >= 2 {
+                if let Some((score, _)) = rows[i - 1][j - 2] {
+                    if before.is_none_or(|(best, _)| score > best) {
+                        before = Some((score, j - 2));
+                    }
+                }
+            }
+            if lower[j] != *q {
+                continue;
+            }
+            let bonus = MATCH
+                + if is_word_start(&chars, j) {
+                    WORD_START
+                } else {
+                    0
+                };
+            row[j] = if i == 0 {
+                Some((bonus, usize::MAX))
+            } else {
+                let adjacent = j
+                    .checked_sub(1)
+                    .and_then(|k| rows[i - 1][k].map(|(score, _)| (score + CONSECUTIVE, k)));
+                match (adjacent, before) {
+                    (Some(a), Some(b)) if b.0 > a.0 => Some(b),
+                    (Some(a), _) => Some(a),
+                    (None, b) => b,
+                }
+                .map(|(score, k)| (score + bonus, k))
+            };
+        }
+        rows.push(row);
+    }
+
+    let (mut j, (score, _)) = rows
+        .last()?
+        .iter()
+        .enumerate()
+        .filter_map(|(j, cell)| cell.map(|cell| (j, cell)))
+        .max_by_key(|(j, (score, _))| (*score, std::cmp::Reverse(*j)))?;
+    let mut positions = vec![0; query.len()];
+    for i in (0..query.len()).rev() {
+        positions[i] = j;
+        j = rows[i][j].map_or(0, |(_, k)| k);
+    }
+    Some((score - n as i32 * LENGTH_PENALTY, positions))
+}
+
+/// At the start of the text, after a separator, or an upper case letter after a lower
+/// case one as in `camelCase`.
+fn is_word_start(chars: &[char], i: usize) -> bool {
+    match i.checked_sub(1).map(|p| chars[p]) {
+        None => true,
+        Some(previous) => {
+            !previous.is_alphanumeric() && chars[i].is_alphanumeric()
+                || previous.is_lowercase() && chars[i].is_uppercase()
+        }
+    }
+}
@@ -0,0 +1,15 @@
+//! Editing behaviors of the dip editor over plain [`dip_document::document::Document`]s:
+//! aligning and formatting tables, folding, a calculator, snippets, codecs and fuzzy
+//! matching.
+//!
+//! Nothing here needs Bevy. `dip_core` turns these into commands and events; the `bevy`
+//! feature only makes [`fold::Folds`] a component for it.
+
+pub mod align;
+pub mod calc;
+pub mod codec;
+pub mod date;
+pub mod fold;
+pub mod fuzzy;
+pub mod snippet;
+pub mod table;
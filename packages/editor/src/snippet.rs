@@ -0,0 +1,782 @@
+//! Snippets in the TextMate syntax VS Code uses: tabstops, placeholders, choices, variables
+//! and regex transforms of both.
+
+use crate::date::civil_date;
+use dip_document::document::Document;
+use regex::{Captures, Regex, RegexBuilder};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    ops::Range,
+};
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+const DAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SnippetError {
+    /// A `${` or a transform missing its end.
+    Unterminated,
+    Expected(char, usize),
+    Regex(String),
+}
+
+impl fmt::Display for SnippetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnippetError::Unterminated => write!(f, "Unterminated placeholder"),
+            SnippetError::Expected(c, at) => write!(f, "Expected `{c}` at {at}"),
+            SnippetError::Regex(e) => write!(f, "Invalid regex: {e}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Part {
+    Text(String),
+    /// `$1`, `${1:placeholder}`, or a mirror of another one, transformed by `${1/../../}`.
+    Tabstop {
+        index: usize,
+        placeholder: Vec<Part>,
+        transform: Option<Transform>,
+    },
+    Choice {
+        index: usize,
+        options: Vec<String>,
+    },
+    Variable {
+        name: String,
+        default: Option<Vec<Part>>,
+        transform: Option<Transform>,
+    },
+}
+
+/// `/regex/format/flags`, replacing the first match or every one with `g`.
+#[derive(Clone, Debug)]
+struct Transform {
+    regex: Regex,
+    format: Vec<Format>,
+    global: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Format {
+    Text(String),
+    Group(usize),
+    Case(usize, Case),
+    /// `${1:+if}`, `${1:-else}`, `${1:else}` and `${1:?if:else}` pick by whether the group
+    /// matched.
+    Conditional(usize, String, String),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Case {
+    Upcase,
+    Downcase,
+    Capitalize,
+    Camelcase,
+    Pascalcase,
+}
+
+impl Transform {
+    fn apply(&self, value: &str) -> String {
+        let mut out = String::with_capacity(value.len());
+        let mut last = 0;
+        for captures in self.regex.captures_iter(value) {
+            let matched = captures.get(0).expect("group 0 is the match");
+            out.push_str(&value[last..matched.start()]);
+            for format in &self.format {
+                format.write(&captures, &mut out);
+            }
+            last = matched.end();
+            if !self.global {
+                break;
+            }
+        }
+        out.push_str(&value[last..]);
+        out
+    }
+}
+
+impl Format {
+    fn write(&self, captures: &Captures, out: &mut String) {
+        let group = |n: usize| captures.get(n).map_or("", |m| m.as_str());
+        match self {
+            Format::Text(text) => out.push_str(text),
+            Format::Group(n) => out.push_str(group(*n)),
+            Format::Case(n, case) => out.push_str(&case.apply(group(*n))),
+            Format::Conditional(n, then, otherwise) => match group(*n).is_empty() {
+                false => out.push_str(then),
+                true => out.push_str(otherwise),
+            },
+        }
+    }
+}
+
+impl Case {
+    fn apply(self, text: &str) -> String {
+        let capitalize = |word: &str| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        };
+        let words = || {
+            text.split(|c: char| !c.is_alphanumeric())
+                .filter(|w| !w.is_empty())
+        };
+        match self {
+            Case::Upcase => text.to_uppercase(),
+            Case::Downcase => text.to_lowercase(),
+            Case::Capitalize => capitalize(text),
+            Case::Pascalcase => words().map(capitalize).collect(),
+            Case::Camelcase => words()
+                .enumerate()
+                .map(|(i, word)| match i {
+                    0 => word.to_lowercase(),
+                    _ => capitalize(word),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A parsed snippet, expanded once per caret.
+#[derive(Clone, Debug)]
+pub struct Snippet {
+    parts: Vec<Part>,
+}
+
+/// The text a snippet expands to and where its tabstops ended up in it, in the order they
+/// are visited, `$0` last.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Expansion {
+    pub text: String,
+    pub tabstops: Vec<(usize, Vec<Range<usize>>)>,
+}
+
+impl Expansion {
+    /// Where the cursor goes first, the end without tabstops.
+    pub fn selection(&self) -> Range<usize> {
+        self.tabstops
+            .first()
+            .and_then(|(_, ranges)| ranges.first().cloned())
+            .unwrap_or(self.text.len()..self.text.len())
+    }
+}
+
+impl Snippet {
+    pub fn parse(snippet: &str) -> Result<Self, SnippetError> {
+        let mut parser = Parser {
+            chars: snippet.chars().collect(),
+            at: 0,
+        };
+        let parts = parser.parts(false)?;
+        Ok(Self { parts })
+    }
+
+    /// Fills in `variables`. Unknown ones without a default are left as their name, the
+    /// way VS Code does.
+    pub fn expand(&self, variables: &HashMap<String, String>) -> Expansion {
+        let mut values = HashMap::new();
+        collect_values(&self.parts, variables, &mut values);
+        let mut expander = Expander {
+            variables,
+            values: &values,
+            text: String::new(),
+            tabstops: BTreeMap::new(),
+        };
+        expander.write(&self.parts);
+
+        let mut tabstops: Vec<_> = expander.tabstops.into_iter().collect();
+        if let Some(end) = tabstops.iter().position(|(index, _)| *index == 0) {
+            let end = tabstops.remove(end);
+            tabstops.push(end);
+        }
+        Expansion {
+            text: expander.text,
+            tabstops,
+        }
+    }
+}
+
+/// The placeholder of each tabstop, which its mirrors repeat.
+fn collect_values(
+    parts: &[Part],
+    variables: &HashMap<String, String>,
+    values: &mut HashMap<usize, String>,
+) {
+    for part in parts {
+        match part {
+            Part::Tabstop {
+                index,
+                placeholder,
+                transform: None,
+            } if !placeholder.is_empty() => {
+                collect_values(placeholder, variables, values);
+                if !values.contains_key(index) {
+                    let mut expander = Expander {
+                        variables,
+                        values,
+                        text: String::new(),
+                        tabstops: BTreeMap::new(),
+                    };
+                    expander.write(placeholder);
+                    let text = expander.text;
+                    values.insert(*index, text);
+                }
+            }
+            Part::Choice { index, options } => {
+                let first = options.first().cloned().unwrap_or_default();
+                values.entry(*index).or_insert(first);
+            }
+            Part::Variable {
+                default: Some(default),
+                ..
+            } => collect_values(default, variables, values),
+            _ => {}
+        }
+    }
+}
+
+struct Expander<'a> {
+    variables: &'a HashMap<String, String>,
+    values: &'a HashMap<usize, String>,
+    text: String,
+    tabstops: BTreeMap<usize, Vec<Range<usize>>>,
+}
+
+impl Expander<'_> {
+    fn write(&mut self, parts: &[Part]) {
+        for part in parts {
+            let start = self.text.len();
+            match part {
+                Part::Text(text) => self.text.push_str(text),
+                Part::Tabstop {
+                    index,
+                    transform: Some(transform),
+                    ..
+                } => {
+                    let value = self.values.get(index).map_or("", String::as_str);
+                    self.text.push_str(&transform.apply(value));
+                }
+                Part::Tabstop {
+                    index, placeholder, ..
+                } => {
+                    match placeholder.is_empty() {
+                        true => {
+                            let value = self.values.get(index).map_or("", String::as_str);
+                            self.text.push_str(value);
+                        }
+                        false => self.write(placeholder),
+                    }
+                    let end = self.text.len();
+                    self.tabstops.entry(*index).or_default().push(start..end);
+                }
+                Part::Choice { index, .. } => {
+                    let value = self.values.get(index).map_or("", String::as_str);
+                    self.text.push_str(value);
+                    let end = self.text.len();
+                    self.tabstops.entry(*index).or_default().push(start..end);
+                }
+                Part::Variable {
+                    name,
+                    default,
+                    transform,
+                } => {
+                    // Empty ones fall back to their default too.
+                    let value = self
+                        .variables
+                        .get(name)
+                        .filter(|value| !value.is_empty() || default.is_none());
+                    match (value, default, transform) {
+                        (Some(value), _, Some(transform)) => {
+                            self.text.push_str(&transform.apply(value))
+                        }
+                        (Some(value), _, None) => self.text.push_str(value),
+                        (None, Some(default), _) => self.write(default),
+                        (None, None, _) => self.text.push_str(name),
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    at: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.at).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        self.at += usize::from(found);
+        found
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), SnippetError> {
+        match self.eat(c) {
+            true => Ok(()),
+            false if self.peek().is_none() => Err(SnippetError::Unterminated),
+            false => Err(SnippetError::Expected(c, self.at)),
+        }
+    }
+
+    fn index(&mut self) -> Option<usize> {
+        let start = self.at;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.at += 1;
+        }
+        let digits: String = self.chars[start..self.at].iter().collect();
+        digits.parse().ok()
+    }
+
+    fn name(&mut self) -> Option<String> {
+        let start = self.at;
+        if !self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        {
+            return None;
+        }
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            self.at += 1;
+        }
+        Some(self.chars[start..self.at].iter().collect())
+    }
+
+    /// Parts up to the end, or up to the `}` closing a placeholder when `nested`.
+    fn parts(&mut self, nested: bool) -> Result<Vec<Part>, SnippetError> {
+        let mut parts = vec![];
+        let mut text = String::new();
+        loop {
+            let c = match self.peek() {
+                Some(c) => c,
+                None if nested => return Err(SnippetError::Unterminated),
+                None => break,
+            };
+            match c {
+                '}' if nested => {
+                    self.at += 1;
+                    break;
+                }
+                '\\' if matches!(self.chars.get(self.at + 1), Some('$' | '}' | '\\')) => {
+                    text.push(self.chars[self.at + 1]);
+                    self.at += 2;
+                }
+                '$' => {
+                    self.at += 1;
+                    match self.dollar()? {
+                        Some(part) => {
+                            if !text.is_empty() {
+                                parts.push(Part::Text(std::mem::take(&mut text)));
+                            }
+                            parts.push(part);
+                        }
+                        None => text.push('$'),
+                    }
+                }
+                c => {
+                    text.push(c);
+                    self.at += 1;
+                }
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(parts)
+    }
+
+    /// What follows a `$`, or None when it is just a dollar sign.
+    fn dollar(&mut self) -> Result<Option<Part>, SnippetError> {
+        if let Some(index) = self.index() {
+            return Ok(Some(Part::Tabstop {
+                index,
+                placeholder: vec![],
+                transform: None,
+            }));
+        }
+        if let Some(name) = self.name() {
+            return Ok(Some(Part::Variable {
+                name,
+                default: None,
+                transform: None,
+            }));
+        }
+        let start = self.at;
+        if !self.eat('{') {
+            return Ok(None);
+        }
+        if let Some(index) = self.index() {
+            let part = match self.peek() {
+                Some(':') => {
+                    self.at += 1;
+                    Part::Tabstop {
+                        index,
+                        placeholder: self.parts(true)?,
+                        transform: None,
+                    }
+                }
+                Some('|') => {
+                    self.at += 1;
+                    Part::Choice {
+                        index,
+                        options: self.options()?,
+                    }
+                }
+                Some('/') => {
+                    self.at += 1;
+                    let transform = self.transform()?;
+                    Part::Tabstop {
+                        index,
+                        placeholder: vec![],
+                        transform: Some(transform),
+                    }
+                }
+                _ => {
+                    self.expect('}')?;
+                    Part::Tabstop {
+                        index,
+                        placeholder: vec![],
+                        transform: None,
+                    }
+                }
+            };
+            return Ok(Some(part));
+        }
+        if let Some(name) = self.name() {
+            let part = match self.peek() {
+                Some(':') => {
+                    self.at += 1;
+                    Part::Variable {
+                        name,
+                        default: Some(self.parts(true)?),
+                        transform: None,
+                    }
+                }
+                Some('/') => {
+                    self.at += 1;
+                    Part::Variable {
+                        name,
+                        default: None,
+                        transform: Some(self.transform()?),
+                    }
+                }
+                _ => {
+                    self.expect('}')?;
+                    Part::Variable {
+                        name,
+                        default: None,
+                        transform: None,
+                    }
+                }
+            };
+            return Ok(Some(part));
+        }
+        // Not a placeholder after all, e.g. `${` in a template literal.
+        self.at = start;
+        Ok(None)
+    }
+
+    /// The options of `${1|one,two|}` after the first `|`.
+    fn options(&mut self) -> Result<Vec<String>, SnippetError> {
+        let mut options = vec![];
+        let mut option = String::new();
+        loop {
+            match self.peek() {
+                None => return Err(SnippetError::Unterminated),
+                Some('\\') if matches!(self.chars.get(self.at + 1), Some(',' | '|' | '\\')) => {
+                    option.push(self.chars[self.at + 1]);
+                    self.at += 2;
+                }
+                Some(',') => {
+                    self.at += 1;
+                    options.push(std::mem::take(&mut option));
+                }
+                Some('|') => {
+                    self.at += 1;
+                    self.expect('}')?;
+                    options.push(option);
+                    return Ok(options);
+                }
+                Some(c) => {
+                    option.push(c);
+                    self.at += 1;
+                }
+            }
+        }
+    }
+
+    /// `regex/format/flags}` after the first `/`.
+    fn transform(&mut self) -> Result<Transform, SnippetError> {
+        let mut source = String::new();
+        loop {
+            match self.peek() {
+                None => return Err(SnippetError::Unterminated),
+                Some('\\') if self.chars.get(self.at + 1) == Some(&'/') => {
+                    source.push('/');
+                    self.at += 2;
+                }
+                Some('\\') => {
+                    source.push('\\');
+                    source.extend(self.chars.get(self.at + 1));
+                    self.at += 2;
+                }
+                Some('/') => {
+                    self.at += 1;
+                    break;
+                }
+                Some(c) => {
+                    source.push(c);
+                    self.at += 1;
+                }
+            }
+        }
+        let format = self.format()?;
+        let mut builder = RegexBuilder::new(&source);
+        let mut global = false;
+        loop {
+            match self.peek() {
+                None => return Err(SnippetError::Unterminated),
+                Some('}') => {
+                    self.at += 1;
+                    break;
+                }
+                Some('g') => global = true,
+                Some('i') => _ = builder.case_insensitive(true),
+                Some('m') => _ = builder.multi_line(true),
+                Some('s') => _ = builder.dot_matches_new_line(true),
+                Some(_) => {}
+            }
+            self.at += 1;
+        }
+        let regex = builder
+            .build()
+            .map_err(|e| SnippetError::Regex(e.to_string()))?;
+        Ok(Transform {
+            regex,
+            format,
+            global,
+        })
+    }
+
+    /// The format of a transform up to its closing `/`.
+    fn format(&mut self) -> Result<Vec<Format>, SnippetError> {
+        let mut format = vec![];
+        let mut text = String::new();
+        loop {
+            match self.peek() {
+                None => return Err(SnippetError::Unterminated),
+                Some('\\') => {
+                    match self.chars.get(self.at + 1) {
+                        Some('n') => text.push('\n'),
+                        Some('t') => text.push('\t'),
+                        Some(c) => text.push(*c),
+                        None => return Err(SnippetError::Unterminated),
+                    }
+                    self.at += 2;
+                }
+                Some('/') => {
+                    self.at += 1;
+                    break;
+                }
+                Some('$') => {
+                    self.at += 1;
+                    let part = match self.index() {
+                        Some(n) => Some(Format::Group(n)),
+                        None if self.eat('{') => Some(self.format_group()?),
+                        None => None,
+                    };
+                    match part {
+                        Some(part) => {
+                            if !text.is_empty() {
+                                format.push(Format::Text(std::mem::take(&mut text)));
+                            }
+                            format.push(part);
+                        }
+                        None => text.push('$'),
+                    }
+                }
+                Some(c) => {
+                    text.push(c);
+                    self.at += 1;
+                }
+            }
+        }
+        if !text.is_empty() {
+            format.push(Format::Text(text));
+        }
+        Ok(format)
+    }
+
+    /// `1}`, `1:/upcase}` or a conditional after `${` in a format.
+    fn format_group(&mut self) -> Result<Format, SnippetError> {
+        let at = self.at;
+        let n = self.index().ok_or(SnippetError::Expected('0', at))?;
+        if self.eat('}') {
+            return Ok(Format::Group(n));
+        }
+        self.expect(':')?;
+        if self.eat('/') {
+            let at = self.at;
+            let case = match self.name().as_deref() {
+                Some("upcase") => Case::Upcase,
+                Some("downcase") => Case::Downcase,
+                Some("capitalize") => Case::Capitalize,
+                Some("camelcase") => Case::Camelcase,
+                Some("pascalcase") => Case::Pascalcase,
+                _ => return Err(SnippetError::Expected('}', at)),
+            };
+            self.expect('}')?;
+            return Ok(Format::Case(n, case));
+        }
+        let (then, otherwise) = if self.eat('+') {
+            (self.until(&['}'])?, String::new())
+        } else if self.eat('?') {
+            let then = self.until(&[':'])?;
+            (then, self.until(&['}'])?)
+        } else {
+            self.eat('-');
+            (String::new(), self.until(&['}'])?)
+        };
+        Ok(Format::Conditional(n, then, otherwise))
+    }
+
+    /// Text up to and past the first of `ends` not escaped.
+    fn until(&mut self, ends: &[char]) -> Result<String, SnippetError> {
+        let mut text = String::new();
+        loop {
+            match self.peek() {
+                None => return Err(SnippetError::Unterminated),
+                Some('\\') => {
+                    text.extend(self.chars.get(self.at + 1));
+                    self.at += 2;
+                }
+                Some(c) if ends.contains(&c) => {
+                    self.at += 1;
+                    return Ok(text);
+                }
+                Some(c) => {
+                    text.push(c);
+                    self.at += 1;
+                }
+            }
+        }
+    }
+}
+
+/// The `CURRENT_*` variables of TextMate snippets at `unix_secs`, `offset` minutes east of
+/// UTC.
+pub fn time_variables(unix_secs: i64, offset: i32) -> HashMap<String, String> {
+    let local = (unix_secs + i64::from(offset) * 60).max(0) as u64;
+    let days = local / 86_400;
+    let (year, month, day) = civil_date(days);
+    let secs = local % 86_400;
+    // 1970-01-01 was a Thursday.
+    let weekday = DAYS[((days + 4) % 7) as usize];
+    let month_name = MONTHS[month as usize - 1];
+    let sign = if offset < 0 { '-' } else { '+' };
+    let minutes = offset.unsigned_abs();
+    HashMap::from([
+        ("CURRENT_YEAR".to_string(), format!("{year:04}")),
+        (
+            "CURRENT_YEAR_SHORT".to_string(),
+            format!("{:02}", year % 100),
+        ),
+        ("CURRENT_MONTH".to_string(), format!("{month:02}")),
+        ("CURRENT_MONTH_NAME".to_string(), month_name.to_string()),
+        (
+            "CURRENT_MONTH_NAME_SHORT".to_string(),
+            month_name[..3].to_string(),
+        ),
+        ("CURRENT_DATE".to_string(), format!("{day:02}")),
+        ("CURRENT_DAY_NAME".to_string(), weekday.to_string()),
+        (
+            "CURRENT_DAY_NAME_SHORT".to_string(),
+            weekday[..3].to_string(),
+        ),
+        ("CURRENT_HOUR".to_string(), format!("{:02}", secs / 3600)),
+        (
+            "CURRENT_MINUTE".to_string(),
+            format!("{:02}", secs / 60 % 60),
+        ),
+        ("CURRENT_SECOND".to_string(), format!("{:02}", secs % 60)),
+        ("CURRENT_SECONDS_UNIX".to_string(), unix_secs.to_string()),
+        (
+            "CURRENT_TIMEZONE_OFFSET".to_string(),
+            format!("{sign}{:02}:{:02}", minutes / 60, minutes % 60),
+        ),
+    ])
+}
+
+/// The `TM_*` variables for a caret selecting `range`.
+pub fn document_variables(document: &Document, range: Range<usize>) -> HashMap<String, String> {
+    let buffer = document.buffer();
+    let range = range.start.min(buffer.len())..range.end.min(buffer.len());
+    let line = buffer.line_at(range.start);
+    let word = buffer
+        .word_at(range.start)
+        .map(|word| buffer.text_in(word))
+        .unwrap_or_default();
+    let mut variables = HashMap::from([
+        ("TM_SELECTED_TEXT".to_string(), buffer.text_in(range)),
+        (
+            "TM_CURRENT_LINE".to_string(),
+            buffer
+                .get_line_content(line)
+                .trim_end_matches(['\r', '\n'])
+                .to_string(),
+        ),
+        ("TM_CURRENT_WORD".to_string(), word),
+        ("TM_LINE_INDEX".to_string(), line.to_string()),
+        ("TM_LINE_NUMBER".to_string(), (line + 1).to_string()),
+    ]);
+    if let Some(path) = document.path() {
+        let name = |s: Option<&std::ffi::OsStr>| s.map(|s| s.to_string_lossy().into_owned());
+        variables.extend(
+            [
+                ("TM_FILEPATH", Some(path.to_string_lossy().into_owned())),
+                ("TM_FILENAME", name(path.file_name())),
+                ("TM_FILENAME_BASE", name(path.file_stem())),
+                (
+                    "TM_DIRECTORY",
+                    path.parent().map(|p| p.to_string_lossy().into_owned()),
+                ),
+            ]
+            .into_iter()
+            .filter_map(|(k, v)| Some((k.to_string(), v?))),
+        );
+    }
+    variables
+}
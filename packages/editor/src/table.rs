@@ -0,0 +1,529 @@
+//! Pipe tables, as in markdown and org files: parsing them, formatting their columns and
+//! adding and removing rows and columns with the cursor kept in its cell.
+
+use crate::align::{line_edit, width};
+use dip_document::{document::Document, text_buffer::TextBuffer};
+use std::{fmt, ops::Range};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableEdit {
+    /// Pads every column to its widest cell, as its alignment marker says.
+    Format,
+    /// Moves to the next cell, adding a row after the last one.
+    NextCell,
+    PreviousCell,
+    InsertRow {
+        above: bool,
+    },
+    DeleteRow,
+    InsertColumn {
+        left: bool,
+    },
+    DeleteColumn,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableStyle {
+    /// Rules like `| --- | :-: |`, whose colons align their columns.
+    Markdown,
+    /// Rules like `|-----+-----|`.
+    Org,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Alignment {
+    #[default]
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Row {
+    /// Trimmed cells as written, escaped pipes included.
+    Cells(Vec<String>),
+    Rule,
+}
+
+/// A pipe table as parsed from its lines.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Table {
+    pub style: TableStyle,
+    pub indent: String,
+    pub rows: Vec<Row>,
+    pub alignments: Vec<Alignment>,
+    /// The line each row was parsed from, None for rows added since.
+    origins: Vec<Option<usize>>,
+}
+
+/// Whether `line` is a row of a pipe table.
+pub fn is_table_line(line: &str) -> bool {
+    line.trim_start().starts_with('|')
+}
+
+/// The byte ranges of the cells of a table line, between its pipes. A missing pipe at the
+/// end still ends a cell with text in it, as when a row is being typed.
+fn cells(line: &str) -> Vec<Range<usize>> {
+    let first = match line.find('|') {
+        Some(first) => first + 1,
+        None => return vec![],
+    };
+    let mut ranges = vec![];
+    let mut start = first;
+    let mut escaped = false;
+    for (i, c) in line[first..].char_indices() {
+        let i = first + i;
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '|' => {
+                ranges.push(start..i);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if !line[start..].trim().is_empty() {
+        ranges.push(start..line.len());
+    }
+    ranges
+}
+
+fn trimmed(line: &str, range: Range<usize>) -> Range<usize> {
+    let cell = &line[range.clone()];
+    let start = range.start + (cell.len() - cell.trim_start().len());
+    start..(range.start + cell.trim_end().len()).max(start)
+}
+
+fn is_rule(line: &str, cells: &[&str]) -> bool {
+    let marker = |cell: &&str| {
+        let cell = cell.trim();
+        let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+        !dashes.is_empty() && dashes.chars().all(|c| c == '-')
+    };
+    line.trim_start().starts_with("|-") || (!cells.is_empty() && cells.iter().all(marker))
+}
+
+fn alignment(marker: &str) -> Alignment {
+    let marker = marker.trim();
+    match (
+        marker.starts_with(':'),
+        marker.ends_with(':') && marker.len() > 1,
+    ) {
+        (true, true) => Alignment::Center,
+        (true, false) => Alignment::Left,
+        (false, true) => Alignment::Right,
+        (false, false) => Alignment::None,
+    }
+}
+
+impl Table {
+    /// The table of `lines`, all of which must be table lines. `first` is the line number
+    /// of the first one.
+    pub fn parse(lines: &[&str], first: usize, style: TableStyle) -> Option<Self> {
+        if lines.is_empty() || !lines.iter().all(|line| is_table_line(line)) {
+            return None;
+        }
+        let indent = lines[0][..lines[0].len() - lines[0].trim_start().len()].to_string();
+        let mut rows = vec![];
+        let mut alignments = None;
+        for line in lines {
+            let cells: Vec<&str> = cells(line).into_iter().map(|r| &line[r]).collect();
+            if is_rule(line, &cells) {
+                if style == TableStyle::Markdown && alignments.is_none() {
+                    alignments = Some(cells.iter().map(|cell| alignment(cell)).collect());
+                }
+                rows.push(Row::Rule);
+            } else {
+                rows.push(Row::Cells(
+                    cells.iter().map(|cell| cell.trim().to_string()).collect(),
+                ));
+            }
+        }
+        let origins = (first..first + rows.len()).map(Some).collect();
+        Some(Self {
+            style,
+            indent,
+            rows,
+            alignments: alignments.unwrap_or_default(),
+            origins,
+        })
+    }
+
+    pub fn columns(&self) -> usize {
+        self.rows
+            .iter()
+            .map(|row| match row {
+                Row::Cells(cells) => cells.len(),
+                Row::Rule => 0,
+            })
+            .chain([self.alignments.len(), 1])
+            .max()
+            .unwrap_or(1)
+    }
+
+    fn widths(&self) -> Vec<usize> {
+        let least = match self.style {
+            TableStyle::Markdown => 3,
+            TableStyle::Org => 1,
+        };
+        (0..self.columns())
+            .map(|column| {
+                self.rows
+                    .iter()
+                    .filter_map(|row| match row {
+                        Row::Cells(cells) => cells.get(column).map(|cell| width(cell)),
+                        Row::Rule => None,
+                    })
+                    .chain([least])
+                    .max()
+                    .unwrap_or(least)
+            })
+            .collect()
+    }
+
+    fn alignment(&self, column: usize) -> Alignment {
+        self.alignments.get(column).copied().unwrap_or_default()
+    }
+
+    /// The lines of the table, formatted.
+    pub fn render(&self) -> Vec<String> {
+        self.layout().into_iter().map(|(line, _)| line).collect()
+    }
+
+    /// The lines, with where the text of each cell starts in them.
+    fn layout(&self) -> Vec<(String, Vec<usize>)> {
+        let widths = self.widths();
+        self.rows
+            .iter()
+            .map(|row| {
+                let mut line = format!("{}|", self.indent);
+                let mut starts = vec![];
+                match row {
+                    Row::Rule if self.style == TableStyle::Org => {
+                        let dashes: Vec<String> =
+                            widths.iter().map(|w| "-".repeat(w + 2)).collect();
+                        starts = widths.iter().map(|_| line.len() + 1).collect();
+                        line.push_str(&dashes.join("+"));
+                        line.push('|');
+                    }
+                    Row::Rule => {
+                        for (column, w) in widths.iter().enumerate() {
+                            let marker = match self.alignment(column) {
+                                Alignment::None => "-".repeat(*w),
+                                Alignment::Left => format!(":{}", "-".repeat(w - 1)),
+                                Alignment::Center => format!(":{}:", "-".repeat(w - 2)),
+                                Alignment::Right => format!("{}:", "-".repeat(w - 1)),
+                            };
+                            line.push(' ');
+                            starts.push(line.len());
+                            line.push_str(&marker);
+                            line.push_str(" |");
+                        }
+                    }
+                    Row::Cells(cells) => {
+                        for (column, w) in widths.iter().enumerate() {
+                            let cell = cells.get(column).map_or("", String::as_str);
+                            let pad = w - width(cell);
+                            let left = match self.alignment(column) {
+                                Alignment::Right => pad,
+                                Alignment::Center => pad / 2,
+                                Alignment::None | Alignment::Left => 0,
+                            };
+                            line.push(' ');
+                            line.push_str(&" ".repeat(left));
+                            starts.push(line.len());
+                            line.push_str(cell);
+                            line.push_str(&" ".repeat(pad - left));
+                            line.push_str(" |");
+                        }
+                    }
+                }
+                (line, starts)
+            })
+            .collect()
+    }
+
+    fn is_cells(&self, row: usize) -> bool {
+        matches!(self.rows.get(row), Some(Row::Cells(_)))
+    }
+
+    fn insert_row(&mut self, at: usize) {
+        self.rows.insert(at, Row::Cells(vec![]));
+        self.origins.insert(at, None);
+    }
+
+    /// Applies `edit` with the cursor in `cell`, returning where it goes, or None when no
+    /// cells are left.
+    fn edit(&mut self, edit: TableEdit, cell: Cell) -> Option<Cell> {
+        let Cell { row, column, .. } = cell;
+        let columns = self.columns();
+        let start = |row, column| Cell {
+            row,
+            column,
+            offset: 0,
+        };
+        // A markdown table needs the rule under its header to be one.
+        if self.style == TableStyle::Markdown && !self.rows.contains(&Row::Rule) {
+            self.rows.insert(1.min(self.rows.len()), Row::Rule);
+            self.origins.insert(1.min(self.origins.len()), None);
+            let row = if row >= 1 { row + 1 } else { row };
+            return self.edit(edit, Cell { row, ..cell });
+        }
+        Some(match edit {
+            TableEdit::Format => cell,
+            TableEdit::NextCell => {
+                if self.is_cells(row) && column + 1 < columns {
+                    start(row, column + 1)
+                } else {
+                    match (row + 1..self.rows.len()).find(|r| self.is_cells(*r)) {
+                        Some(next) => start(next, 0),
+                        None => {
+                            self.insert_row(self.rows.len());
+                            start(self.rows.len() - 1, 0)
+                        }
+                    }
+                }
+            }
+            TableEdit::PreviousCell => {
+                if self.is_cells(row) && column > 0 {
+                    start(row, column - 1)
+                } else {
+                    match (0..row).rev().find(|r| self.is_cells(*r)) {
+                        Some(previous) => start(previous, columns - 1),
+                        None => start(row, column),
+                    }
+                }
+            }
+            TableEdit::InsertRow { above } => {
+                let at = if above { row } else { row + 1 };
+                self.insert_row(at);
+                start(at, column)
+            }
+            TableEdit::DeleteRow => {
+                self.rows.remove(row);
+                self.origins.remove(row);
+                let row = row.min(self.rows.len().saturating_sub(1));
+                let row = (row..self.rows.len())
+                    .chain((0..row).rev())
+                    .find(|r| self.is_cells(*r))?;
+                start(row, column)
+            }
+            TableEdit::InsertColumn { left } => {
+                let at = if left { column } else { column + 1 };
+                for row in &mut self.rows {
+                    if let Row::Cells(cells) = row {
+                        cells.resize(columns.max(cells.len()), String::new());
+                        cells.insert(at, String::new());
+                    }
+                }
+                if !self.alignments.is_empty() {
+                    self.alignments.resize(columns, Alignment::None);
+                    self.alignments.insert(at, Alignment::None);
+                }
+                start(row, at)
+            }
+            TableEdit::DeleteColumn => {
+                if columns == 1 {
+                    return None;
+                }
+                for row in &mut self.rows {
+                    if let Row::Cells(cells) = row {
+                        if column < cells.len() {
+                            cells.remove(column);
+                        }
+                    }
+                }
+                if column < self.alignments.len() {
+                    self.alignments.remove(column);
+                }
+                start(row, column.min(columns - 2))
+            }
+        })
+    }
+}
+
+/// Where a cursor is in a table: a row, a column and a byte offset into the cell's text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Cell {
+    row: usize,
+    column: usize,
+    offset: usize,
+}
+
+impl Cell {
+    fn at(line: &str, row: usize, column: usize) -> Self {
+        let ranges = cells(line);
+        let index = ranges
+            .iter()
+            .position(|range| column <= range.end)
+            .unwrap_or(ranges.len().saturating_sub(1));
+        let offset = match ranges.get(index) {
+            Some(range) => {
+                let text = trimmed(line, range.clone());
+                column.clamp(text.start, text.end) - text.start
+            }
+            None => 0,
+        };
+        Self {
+            row,
+            column: index,
+            offset,
+        }
+    }
+}
+
+/// The lines of the table around `line`.
+fn table_lines(buffer: &TextBuffer, line: usize) -> Option<Range<usize>> {
+    let is_table = |line: usize| is_table_line(&buffer.get_line_content(line));
+    if !is_table(line) {
+        return None;
+    }
+    let mut start = line;
+    while start > 0 && is_table(start - 1) {
+        start -= 1;
+    }
+    let mut end = line + 1;
+    while end < buffer.line_count() && is_table(end) {
+        end += 1;
+    }
+    Some(start..end)
+}
+
+fn style(document: &Document, lines: &[&str]) -> TableStyle {
+    let org = document
+        .path()
+        .and_then(|path| path.extension())
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("org"));
+    let org_rule = lines
+        .iter()
+        .any(|line| line.trim_start().starts_with("|-") && line.contains('+'));
+    match org || org_rule {
+        true => TableStyle::Org,
+        false => TableStyle::Markdown,
+    }
+}
+
+/// The edits turning `lines` into `new`, each new line coming from the old line in
+/// `origins` or added. Lines kept are changed only where they differ, so other cursors
+/// stay in their cells.
+fn edits(
+    buffer: &TextBuffer,
+    lines: Range<usize>,
+    origins: &[Option<usize>],
+    new: &[String],
+) -> Vec<(Range<usize>, String)> {
+    let mut edits = vec![];
+    let mut removed = lines.clone().filter(|line| !origins.contains(&Some(*line)));
+    while let Some(first) = removed.next() {
+        let mut end = first + 1;
+        while removed.clone().next() == Some(end) {
+            removed.next();
+            end += 1;
+        }
+        let range = match end < buffer.line_count() {
+            true => buffer.line_start(first)..buffer.line_start(end),
+            false if first > 0 => buffer.line_range(first - 1).end..buffer.len(),
+            false => 0..buffer.len(),
+        };
+        edits.push((range, String::new()));
+    }
+    for (i, (origin, text)) in origins.iter().zip(new).enumerate() {
+        match origin {
+            Some(line) => {
+                let old = buffer.get_line_content(*line);
+                if let Some((range, text)) = line_edit(&old, text) {
+                    let start = buffer.line_start(*line);
+                    edits.push((start + range.start..start + range.end, text));
+                }
+            }
+            None => match origins[i + 1..].iter().flatten().next() {
+                Some(next) => edits.push((
+                    buffer.line_start(*next)..buffer.line_start(*next),
+                    format!("{text}\n"),
+                )),
+                None if lines.end < buffer.line_count() => {
+                    let at = buffer.line_start(lines.end);
+                    edits.push((at..at, format!("{text}\n")));
+                }
+                None => {
+                    let at = buffer.len();
+                    edits.push((at..at, format!("\n{text}")));
+                }
+            },
+        }
+    }
+    edits
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TableError {
+    ReadOnly,
+    /// The cursor is not on a table line.
+    NoTable,
+}
+
+impl fmt::Display for TableError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TableError::ReadOnly => write!(f, "The document is read only"),
+            TableError::NoTable => write!(f, "No table at the cursor"),
+        }
+    }
+}
+
+/// Applies `edit` to the table around `offset`, formatting it along the way, as one undo
+/// step. Returns where the cursor goes, or None when nothing changed.
+pub fn edit_table(
+    document: &mut Document,
+    offset: usize,
+    edit: TableEdit,
+) -> Result<Option<usize>, TableError> {
+    if document.is_read_only() {
+        return Err(TableError::ReadOnly);
+    }
+    let buffer = document.buffer();
+    let offset = offset.min(buffer.len());
+    let line = buffer.line_at(offset);
+    let lines = table_lines(buffer, line).ok_or(TableError::NoTable)?;
+    let contents: Vec<_> = lines
+        .clone()
+        .map(|line| buffer.get_line_content(line))
+        .collect();
+    let old: Vec<&str> = contents.iter().map(|line| line.as_ref()).collect();
+    let mut table = match Table::parse(&old, lines.start, style(document, &old)) {
+        Some(table) => table,
+        None => return Ok(None),
+    };
+    let column = offset - buffer.line_start(line);
+    let cell = Cell::at(old[line - lines.start], line - lines.start, column);
+
+    let (edits, offset) = match table.edit(edit, cell) {
+        Some(cell) => {
+            let layout = table.layout();
+            let new: Vec<String> = layout.iter().map(|(line, _)| line.clone()).collect();
+            let edits = edits(buffer, lines.clone(), &table.origins, &new);
+            let (text, starts) = &layout[cell.row];
+            let len = match &table.rows[cell.row] {
+                Row::Cells(cells) => cells.get(cell.column).map_or(0, String::len),
+                Row::Rule => 0,
+            };
+            let start = starts.get(cell.column).copied().unwrap_or(text.len());
+            let column = start + cell.offset.min(len);
+            (edits, Some((lines.start + cell.row, column)))
+        }
+        None => (edits(buffer, lines.clone(), &[], &[]), None),
+    };
+    if edits.is_empty() && offset.is_none() {
+        return Ok(None);
+    }
+    document.history_mut().begin();
+    document.apply_edits(edits, 0);
+    document.history_mut().commit();
+    let buffer = document.buffer();
+    Ok(Some(match offset {
+        Some((line, column)) => buffer.line_start(line) + column,
+        None => buffer.line_start(lines.start.min(buffer.line_count() - 1)),
+    }))
+}
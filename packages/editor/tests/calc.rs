@@ -0,0 +1,49 @@
+//! Expressions evaluate with units and bases, and fail on what they can't make sense of.
+
+use dip_editor::calc::{evaluate, CalcError, MAX_DEPTH};
+
+#[test]
+fn evaluates_arithmetic_and_units() {
+    assert_eq!(evaluate("2 * (3 + 4)").unwrap(), "14");
+    assert_eq!(evaluate("-2 ^ 2").unwrap(), "-4");
+    assert_eq!(evaluate("0.1 + 0.2").unwrap(), "0.3");
+    assert_eq!(evaluate("1 km + 500 m in m").unwrap(), "1500 m");
+    assert_eq!(evaluate("1 / 0"), Err(CalcError::DivisionByZero));
+    assert_eq!(
+        evaluate("1 km + 1 kg"),
+        Err(CalcError::Incompatible("kg".into(), "km".into()))
+    );
+}
+
+#[test]
+fn converts_between_bases() {
+    assert_eq!(evaluate("255 in hex").unwrap(), "0xff");
+    assert_eq!(evaluate("0b1010 to dec").unwrap(), "10");
+    assert_eq!(evaluate("-0o17 in bin").unwrap(), "-0b1111");
+    assert_eq!(evaluate("1.5 in hex"), Err(CalcError::NotAnInteger));
+}
+
+#[test]
+fn reads_numbers_past_64_bits() {
+    assert_eq!(
+        evaluate("0x1_0000_0000_0000_0000 in hex").unwrap(),
+        "0x10000000000000000"
+    );
+    assert_eq!(evaluate("2 ^ 70 in hex").unwrap(), "0x400000000000000000");
+    assert_eq!(evaluate("2 ^ 130 in hex"), Err(CalcError::NotAnInteger));
+}
+
+#[test]
+fn limits_how_deep_expressions_nest() {
+    let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+    assert_eq!(evaluate(&nested(MAX_DEPTH - 1)).unwrap(), "1");
+    assert_eq!(evaluate(&nested(100_000)), Err(CalcError::TooDeep));
+    assert_eq!(
+        evaluate(&format!("{}1", "-".repeat(100_000))),
+        Err(CalcError::TooDeep)
+    );
+    assert_eq!(
+        evaluate(&format!("2{}", " ^ 2".repeat(100_000))),
+        Err(CalcError::TooDeep)
+    );
+}
@@ -0,0 +1,141 @@
+//! Snippets expand their tabstops, variables and transforms the way TextMate and VS Code
+//! snippets do.
+
+use dip_document::{document::Document, text_buffer::TextBuffer};
+use dip_editor::snippet::{document_variables, time_variables, Snippet, SnippetError};
+use std::{collections::HashMap, path::PathBuf};
+
+fn variables(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+fn expand(snippet: &str, pairs: &[(&str, &str)]) -> String {
+    let snippet = Snippet::parse(snippet).unwrap();
+    snippet.expand(&variables(pairs)).text
+}
+
+#[test]
+fn visits_tabstops_in_order_and_the_end_last() {
+    let snippet = Snippet::parse("let ${1:x} = $1 + ${2:one};$0").unwrap();
+    let expansion = snippet.expand(&HashMap::new());
+    assert_eq!(expansion.text, "let x = x + one;");
+    assert_eq!(
+        expansion.tabstops,
+        [
+            (1, vec![4..5, 8..9]),
+            (2, vec![12..15; 1]),
+            (0, vec![16..16; 1])
+        ]
+    );
+    assert_eq!(expansion.selection(), 4..5);
+
+    let choice = Snippet::parse("${1|one,two\\,three|}").unwrap();
+    assert_eq!(choice.expand(&HashMap::new()).text, "one");
+    let plain = Snippet::parse("no tabstops").unwrap();
+    assert_eq!(plain.expand(&HashMap::new()).selection(), 11..11);
+}
+
+#[test]
+fn fills_in_variables_or_their_defaults() {
+    let file = [("TM_FILENAME_BASE", "main"), ("TM_SELECTED_TEXT", "")];
+    assert_eq!(expand("mod ${TM_FILENAME_BASE};", &file), "mod main;");
+    // Empty ones take their default too, unknown ones without one are left as their name.
+    assert_eq!(expand("${TM_SELECTED_TEXT:none}", &file), "none");
+    assert_eq!(expand("${NOT_A_VARIABLE}", &file), "NOT_A_VARIABLE");
+    assert_eq!(expand("${NOT_A_VARIABLE:${1:x}}", &file), "x");
+    // Dollar signs that start nothing are text.
+    assert_eq!(expand("`${` costs \\$5 $", &file), "`${` costs $5 $");
+}
+
+#[test]
+fn transforms_variables_and_tabstops() {
+    let file = [("TM_FILENAME", "my_widget.rs"), ("TM_SELECTED_TEXT", "")];
+    assert_eq!(expand(r"${TM_FILENAME/(.*)\..+$/$1/}", &file), "my_widget");
+    assert_eq!(
+        expand(r"${TM_FILENAME/(\w+)\.rs/${1:/pascalcase}/}", &file),
+        "MyWidget"
+    );
+    assert_eq!(
+        expand(r"${TM_FILENAME/(\w+)\.rs/${1:/camelcase}/}", &file),
+        "myWidget"
+    );
+    assert_eq!(
+        expand(r"${TM_FILENAME/(\w+)/${1:/upcase}/}", &file),
+        "MY_WIDGET.rs"
+    );
+    // The first match unless global, and conditionals on whether a group matched.
+    assert_eq!(expand("${1:banana} ${1/a/o/}", &[]), "banana bonana");
+    assert_eq!(expand("${1:banana} ${1/A/o/gi}", &[]), "banana bonono");
+    assert_eq!(
+        expand("${TM_SELECTED_TEXT/(.+)?/${1:?some:none}/}", &file),
+        "none"
+    );
+    assert_eq!(expand("${TM_FILENAME/(.+)?/${1:+some}/}", &file), "some");
+}
+
+#[test]
+fn refuses_what_does_not_parse() {
+    let error = |snippet: &str| Snippet::parse(snippet).unwrap_err();
+    assert_eq!(error("${1:open"), SnippetError::Unterminated);
+    assert_eq!(error("${1/a/b/"), SnippetError::Unterminated);
+    assert_eq!(error("${1 x}"), SnippetError::Expected('}', 3));
+    assert!(matches!(error("${1/(/x/}"), SnippetError::Regex(_)));
+}
+
+#[test]
+fn tells_the_time_where_the_user_is() {
+    // 2024-02-29 23:34:56 UTC.
+    let unix = 1_709_249_696;
+    let east = time_variables(unix, 90);
+    let date = |vars: &HashMap<String, String>| {
+        [
+            "CURRENT_YEAR",
+            "CURRENT_MONTH",
+            "CURRENT_DATE",
+            "CURRENT_HOUR",
+            "CURRENT_MINUTE",
+            "CURRENT_SECOND",
+            "CURRENT_DAY_NAME_SHORT",
+            "CURRENT_MONTH_NAME",
+            "CURRENT_TIMEZONE_OFFSET",
+        ]
+        .map(|name| vars[name].clone())
+    };
+    assert_eq!(
+        date(&east),
+        ["2024", "03", "01", "01", "04", "56", "Fri", "March", "+01:30"]
+    );
+    let west = time_variables(unix, -330);
+    assert_eq!(
+        date(&west),
+        ["2024", "02", "29", "18", "04", "56", "Thu", "February", "-05:30"]
+    );
+    assert_eq!(west["CURRENT_SECONDS_UNIX"], "1709249696");
+    assert_eq!(west["CURRENT_YEAR_SHORT"], "24");
+}
+
+#[test]
+fn reads_the_document_around_the_caret() {
+    let text = "fn main() {\n    let value = 1;\n}\n";
+    let document = Document::new(
+        Some(PathBuf::from("/src/app/main.rs")),
+        TextBuffer::from(text),
+    );
+    let start = text.find("value").unwrap();
+    let vars = document_variables(&document, start..start + 5);
+    let get = |name: &str| vars.get(name).map(String::as_str);
+    assert_eq!(get("TM_SELECTED_TEXT"), Some("value"));
+    assert_eq!(get("TM_CURRENT_LINE"), Some("    let value = 1;"));
+    assert_eq!(get("TM_CURRENT_WORD"), Some("value"));
+    assert_eq!(get("TM_LINE_INDEX"), Some("1"));
+    assert_eq!(get("TM_LINE_NUMBER"), Some("2"));
+    assert_eq!(get("TM_FILENAME"), Some("main.rs"));
+    assert_eq!(get("TM_FILENAME_BASE"), Some("main"));
+    assert_eq!(get("TM_DIRECTORY"), Some("/src/app"));
+
+    let untitled = Document::new(None, TextBuffer::from(text));
+    assert!(!document_variables(&untitled, 0..0).contains_key("TM_FILENAME"));
+}
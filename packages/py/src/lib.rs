@@ -0,0 +1,218 @@
+//! Python bindings for scripting edits of documents without the editor running, e.g. a
+//! bulk regex replace over files from a notebook:
+//!
+//! ```python
+//! import dip
+//!
+//! doc = dip.Document.open("src/lib.rs")
+//! doc.replace(r"Foo(\w+)", r"Bar$1")
+//! doc.save()
+//! ```
+//!
+//! Offsets count code points, the way Python strings are indexed, and are converted to the
+//! buffer's byte offsets by walking the text up to them. Lines are zero-based. Saving keeps
+//! what the file was besides its contents, like the editor does.
+
+use dip_document::{
+    document::{Document as EditorDocument, DocumentError},
+    text_buffer::{
+        search::{build_regex, Find, SearchOptions},
+        TextBuffer,
+    },
+};
+use pyo3::{
+    exceptions::{PyFileNotFoundError, PyIOError, PyPermissionError, PyValueError},
+    prelude::*,
+};
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+fn open_error(path: &Path, error: DocumentError) -> PyErr {
+    let message = format!("{}: {error}", path.display());
+    match error {
+        DocumentError::NotFound => PyFileNotFoundError::new_err(message),
+        DocumentError::PermissionDenied => PyPermissionError::new_err(message),
+        _ => PyIOError::new_err(message),
+    }
+}
+
+fn out_of_bounds(what: &str, value: usize) -> PyErr {
+    PyValueError::new_err(format!("{what} {value} is out of bounds"))
+}
+
+fn options(case_sensitive: bool, whole_word: bool) -> SearchOptions {
+    SearchOptions {
+        case_sensitive,
+        whole_word,
+    }
+}
+
+/// A document to edit and save, untitled until saved somewhere if made from text.
+#[pyclass(unsendable)]
+pub struct Document {
+    document: EditorDocument,
+}
+
+#[pymethods]
+impl Document {
+    #[new]
+    #[pyo3(signature = (text = ""))]
+    fn new(text: &str) -> Self {
+        Self {
+            document: EditorDocument::new(None, TextBuffer::from(text)),
+        }
+    }
+
+    /// Reads the file at `path`, detecting its line endings and byte order mark.
+    #[staticmethod]
+    fn open(path: PathBuf) -> PyResult<Self> {
+        let document = EditorDocument::from_path(&path).map_err(|e| open_error(&path, e))?;
+        Ok(Self { document })
+    }
+
+    /// None until saved.
+    #[getter]
+    fn path(&self) -> Option<PathBuf> {
+        self.document.path().map(PathBuf::from)
+    }
+
+    #[getter]
+    fn text(&self) -> String {
+        self.document.buffer().to_string()
+    }
+
+    #[getter]
+    fn line_count(&self) -> usize {
+        self.document.buffer().line_count()
+    }
+
+    /// Whether it was edited since it was opened or last saved.
+    #[getter]
+    fn is_dirty(&self) -> bool {
+        self.document.is_dirty()
+    }
+
+    /// Content of `line` without its line ending.
+    fn line(&self, line: usize) -> PyResult<String> {
+        let buffer = self.document.buffer();
+        match line < buffer.line_count() {
+            true => Ok(buffer.get_line_content(line).into_owned()),
+            false => Err(out_of_bounds("line", line)),
+        }
+    }
+
+    fn insert(&mut self, offset: usize, text: &str) -> PyResult<()> {
+        let offset = self.byte_offset(offset)?;
+        self.document.insert(offset, text);
+        Ok(())
+    }
+
+    /// Deletes from `start` up to `end`.
+    fn delete(&mut self, start: usize, end: usize) -> PyResult<()> {
+        let range = self.byte_range(start, end)?;
+        self.document.delete(range);
+        Ok(())
+    }
+
+    /// Ranges `(start, end)` matched by the regular expression `pattern`, in Rust's regex
+    /// syntax. Only patterns mentioning `\n` match across lines.
+    #[pyo3(signature = (pattern, case_sensitive = true, whole_word = false))]
+    fn find(
+        &self,
+        pattern: &str,
+        case_sensitive: bool,
+        whole_word: bool,
+    ) -> PyResult<Vec<(usize, usize)>> {
+        let regex = build_regex(pattern, options(case_sensitive, whole_word))
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let matches = self.document.buffer().find_all_regex(&regex);
+        Ok(matches
+            .into_iter()
+            .map(|m| (self.char_offset(m.start), self.char_offset(m.end)))
+            .collect())
+    }
+
+    /// Replaces every match of `pattern` like `find` finds them, with `$1` or
+    /// `${name}` in `replacement` inserting capture groups, and returns how many there were.
+    #[pyo3(signature = (pattern, replacement, case_sensitive = true, whole_word = false))]
+    fn replace(
+        &mut self,
+        pattern: &str,
+        replacement: &str,
+        case_sensitive: bool,
+        whole_word: bool,
+    ) -> PyResult<usize> {
+        let regex = build_regex(pattern, options(case_sensitive, whole_word))
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(self.document.replace_all(&regex, replacement))
+    }
+
+    /// Writes it back to its path, failing for one that was never saved.
+    fn save(&mut self) -> PyResult<()> {
+        self.document
+            .save()
+            .map_err(|e| PyIOError::new_err(e.to_string()))
+    }
+
+    /// Writes it to `path`, which it keeps from then on.
+    fn save_as(&mut self, path: PathBuf) -> PyResult<()> {
+        self.document
+            .save_as(&path)
+            .map_err(|e| PyIOError::new_err(format!("{}: {e}", path.display())))
+    }
+
+    fn __len__(&self) -> usize {
+        self.char_offset(self.document.buffer().len())
+    }
+
+    fn __repr__(&self) -> String {
+        match self.document.path() {
+            Some(path) => format!("<dip.Document {}>", path.display()),
+            None => "<dip.Document untitled>".to_string(),
+        }
+    }
+}
+
+impl Document {
+    /// Byte offset of the code point `chars` from the start.
+    fn byte_offset(&self, chars: usize) -> PyResult<usize> {
+        let mut left = chars;
+        let mut offset = 0;
+        for chunk in self.document.buffer().chunks() {
+            match chunk.char_indices().nth(left) {
+                Some((i, _)) => return Ok(offset + i),
+                None => {
+                    left -= chunk.chars().count();
+                    offset += chunk.len();
+                }
+            }
+        }
+        match left {
+            0 => Ok(offset),
+            _ => Err(out_of_bounds("offset", chars)),
+        }
+    }
+
+    fn byte_range(&self, start: usize, end: usize) -> PyResult<Range<usize>> {
+        if start > end {
+            return Err(out_of_bounds("start", start));
+        }
+        Ok(self.byte_offset(start)?..self.byte_offset(end)?)
+    }
+
+    fn char_offset(&self, offset: usize) -> usize {
+        self.document
+            .buffer()
+            .chunks_in(0..offset)
+            .map(|chunk| chunk.chars().count())
+            .sum()
+    }
+}
+
+#[pymodule]
+fn dip(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<Document>()?;
+    Ok(())
+}
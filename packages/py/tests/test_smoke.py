@@ -0,0 +1,49 @@
+import pytest
+
+import dip
+
+
+def test_edits_in_code_points():
+    doc = dip.Document("héllo\n😀 wörld")
+    assert len(doc) == len("héllo\n😀 wörld")
+    assert doc.line_count == 2
+
+    doc.insert(8, "big ")
+    doc.delete(0, 1)
+    assert doc.text == "éllo\n😀 big wörld"
+    assert doc.line(1) == "😀 big wörld"
+    assert doc.is_dirty
+
+
+def test_finds_and_replaces():
+    doc = dip.Document("Foo foo\n😀 food")
+    assert doc.find("foo") == [(4, 7), (10, 13)]
+    assert len(doc.find("foo", case_sensitive=False, whole_word=True)) == 2
+    assert doc.replace(r"f(o+)", r"b$1") == 2
+    assert doc.text == "Foo boo\n😀 bood"
+    with pytest.raises(ValueError, match="regex parse error"):
+        doc.find("(")
+
+
+def test_rejects_offsets_out_of_bounds():
+    doc = dip.Document("ab")
+    with pytest.raises(ValueError, match="offset 3 is out of bounds"):
+        doc.insert(3, "x")
+    with pytest.raises(ValueError, match="line 1 is out of bounds"):
+        doc.line(1)
+    assert len(dip.Document()) == 0
+
+
+def test_opens_and_saves(tmp_path):
+    path = tmp_path / "notes.txt"
+    path.write_bytes(b"one\r\ntwo\r\n")
+    doc = dip.Document.open(path)
+    assert doc.replace("two", "three") == 1
+    doc.save()
+    assert path.read_bytes() == b"one\r\nthree\r\n"
+    assert not doc.is_dirty
+
+    with pytest.raises(FileNotFoundError):
+        dip.Document.open(tmp_path / "missing.txt")
+    with pytest.raises(OSError):
+        dip.Document("untitled").save()
@@ -0,0 +1,95 @@
+use super::{extend_utf16_marks, line_starts, StringBuffer, Text, TextBuffer, AVERAGE_BUFFER_SIZE};
+use std::{mem, thread};
+
+/// Below this many buffers line starts are found on the calling thread.
+const PARALLEL_BUFFERS: usize = 64;
+
+/// Builds a [`TextBuffer`] from text arriving in chunks, e.g. while reading a file.
+///
+/// Like VS Code's piece tree, the text is kept in many original buffers of about
+/// [`AVERAGE_BUFFER_SIZE`] bytes instead of one, so a large file never needs a single
+/// giant allocation, and their line starts can be found in parallel.
+#[derive(Debug, Default)]
+pub struct TextBufferBuilder {
+    texts: Vec<String>,
+    pending: String,
+}
+
+impl TextBufferBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn accept_chunk(&mut self, mut chunk: &str) {
+        while self.pending.len() + chunk.len() >= AVERAGE_BUFFER_SIZE {
+            let mut end = AVERAGE_BUFFER_SIZE - self.pending.len();
+            while !chunk.is_char_boundary(end) {
+                end -= 1;
+            }
+            self.pending.push_str(&chunk[..end]);
+            chunk = &chunk[end..];
+            self.flush();
+        }
+        self.pending.push_str(chunk);
+    }
+
+    pub fn finish(mut self) -> TextBuffer {
+        self.flush();
+        TextBuffer::new(index(self.texts.into_iter().map(Text::Owned).collect()))
+    }
+
+    fn flush(&mut self) {
+        if !self.pending.is_empty() {
+            self.texts.push(mem::take(&mut self.pending));
+        }
+    }
+}
+
+/// Finds the line starts and UTF-16 marks of each text, spread over the available cores for
+/// large files.
+pub(super) fn index(texts: Vec<Text>) -> Vec<StringBuffer> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let per_thread = texts.len().div_ceil(threads).max(PARALLEL_BUFFERS);
+    let starts = |group: &[Text]| -> Vec<(Vec<usize>, Vec<usize>)> {
+        group
+            .iter()
+            .map(|text| {
+                // Original buffers never grow, so don't keep the slack of collecting.
+                let mut starts: Vec<usize> = line_starts(text.as_bytes(), 0).collect();
+                starts.shrink_to_fit();
+                let mut marks = vec![];
+                extend_utf16_marks(&mut marks, text.as_bytes());
+                marks.shrink_to_fit();
+                (starts, marks)
+            })
+            .collect()
+    };
+
+    let indexes = if texts.len() <= per_thread {
+        starts(&texts)
+    } else {
+        thread::scope(|scope| {
+            let workers: Vec<_> = texts
+                .chunks(per_thread)
+                .map(|group| scope.spawn(move || starts(group)))
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("line start worker panicked"))
+                .collect()
+        })
+    };
+    texts
+        .into_iter()
+        .zip(indexes)
+        .map(|(text, (line_starts, marks))| StringBuffer::with_index(text, line_starts, marks))
+        .collect()
+}
+
+impl From<&str> for TextBuffer {
+    fn from(text: &str) -> Self {
+        let mut builder = TextBufferBuilder::new();
+        builder.accept_chunk(text);
+        builder.finish()
+    }
+}
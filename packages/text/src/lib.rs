@@ -0,0 +1,853 @@
+//! Piece tree text buffer, after the design of VS Code's `PieceTreeTextBuffer`.
+//!
+//! The document is never stored as one string. Text loaded from disk lives in read-only
+//! original buffers and everything typed afterwards is appended to a change buffer; the
+//! document itself is a red-black tree of pieces pointing into those buffers. Offsets are
+//! byte offsets into the UTF-8 document and must fall on char boundaries.
+//!
+//! Only `\n` counts as a line feed. A `\r` before it stays part of the line's content.
+//!
+//! Language servers count in UTF-16 code units instead, so every piece also knows its UTF-16
+//! length and [`TextBuffer::utf16_offset_at`]/[`TextBuffer::byte_offset_at`] convert
+//! between the two in O(log n).
+
+mod builder;
+#[cfg(unix)]
+mod mapped;
+#[cfg(feature = "serde")]
+mod persist;
+pub mod search;
+mod tree;
+mod words;
+
+pub use builder::TextBufferBuilder;
+#[cfg(unix)]
+pub use mapped::Mapping;
+#[cfg(feature = "serde")]
+pub use persist::FORMAT_VERSION;
+
+use std::{
+    borrow::Cow,
+    fmt, mem,
+    ops::{Deref, Range},
+    sync::{Arc, Mutex},
+};
+use tree::{NodeId, Piece, Tree, NIL};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Texts larger than this get a buffer of their own instead of growing the change buffer.
+const AVERAGE_BUFFER_SIZE: usize = 65535;
+
+/// The change buffer is always the first one.
+const CHANGE_BUFFER: usize = 0;
+
+/// Buffers remember their UTF-16 length up to every this many bytes, so measuring a piece
+/// scans at most this much at either end.
+const UTF16_STRIDE: usize = 1024;
+
+/// Text of a buffer, on the heap or in a memory-mapped file.
+#[derive(Clone, Debug)]
+enum Text {
+    Owned(String),
+    #[cfg(unix)]
+    Mapped(Arc<Mapping>, Range<usize>),
+}
+
+impl Default for Text {
+    fn default() -> Self {
+        Text::Owned(String::new())
+    }
+}
+
+impl Text {
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            Text::Owned(text) => text.as_bytes(),
+            #[cfg(unix)]
+            Text::Mapped(mapping, range) => &mapping.bytes()[range.clone()],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.as_bytes().len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn is_char_boundary(&self, offset: usize) -> bool {
+        match self.as_bytes().get(offset) {
+            Some(&byte) => byte & 0xc0 != 0x80,
+            None => offset == self.len(),
+        }
+    }
+
+    /// The text in `range`, which must fall on char boundaries.
+    ///
+    /// Mapped text is decoded again on every access: it was UTF-8 when mapped, but another
+    /// process may have rewritten the file since, and that must not end up in a `str`.
+    fn slice(&self, range: Range<usize>) -> &str {
+        match self {
+            Text::Owned(text) => &text[range],
+            #[cfg(unix)]
+            Text::Mapped(..) => std::str::from_utf8(&self.as_bytes()[range])
+                .expect("mapped file was changed on disk while in use"),
+        }
+    }
+
+    fn push_str(&mut self, text: &str) {
+        match self {
+            Text::Owned(owned) => owned.push_str(text),
+            #[cfg(unix)]
+            Text::Mapped(..) => unreachable!("mapped buffers are read-only"),
+        }
+    }
+
+    fn heap_size(&self) -> usize {
+        match self {
+            Text::Owned(text) => text.capacity(),
+            #[cfg(unix)]
+            Text::Mapped(..) => 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct StringBuffer {
+    text: Text,
+    /// Byte offset of every line start but the first, i.e. one past each `\n`.
+    line_starts: Vec<usize>,
+    /// UTF-16 length of the text before byte `(i + 1) * UTF16_STRIDE`.
+    utf16_marks: Vec<usize>,
+}
+
+impl StringBuffer {
+    pub fn new(text: String) -> Self {
+        let line_starts = line_starts(text.as_bytes(), 0).collect();
+        let mut utf16_marks = vec![];
+        extend_utf16_marks(&mut utf16_marks, text.as_bytes());
+        Self {
+            text: Text::Owned(text),
+            line_starts,
+            utf16_marks,
+        }
+    }
+
+    fn with_index(text: Text, line_starts: Vec<usize>, utf16_marks: Vec<usize>) -> Self {
+        Self {
+            text,
+            line_starts,
+            utf16_marks,
+        }
+    }
+
+    fn push_str(&mut self, text: &str) {
+        let offset = self.text.len();
+        self.text.push_str(text);
+        self.line_starts
+            .extend(line_starts(text.as_bytes(), offset));
+        extend_utf16_marks(&mut self.utf16_marks, self.text.as_bytes());
+    }
+
+    /// Line feeds in `start..end`.
+    fn line_feeds(&self, start: usize, end: usize) -> usize {
+        let from = self.line_starts.partition_point(|&s| s <= start);
+        let to = self.line_starts.partition_point(|&s| s <= end);
+        to - from
+    }
+
+    /// UTF-16 length of the text before `offset`.
+    fn utf16_before(&self, offset: usize) -> usize {
+        let mark = offset / UTF16_STRIDE;
+        let before = match mark {
+            0 => 0,
+            mark => self.utf16_marks[mark - 1],
+        };
+        before + utf16_len(&self.text.as_bytes()[mark * UTF16_STRIDE..offset])
+    }
+
+    /// Byte offset in `start..end` where the text reaches UTF-16 length `units`. Inside a
+    /// surrogate pair that is the start of its char, past the end `end`.
+    fn offset_at_utf16(&self, start: usize, end: usize, units: usize) -> usize {
+        let mark = self.utf16_marks.partition_point(|&m| m <= units);
+        let from = start.max(mark * UTF16_STRIDE);
+        let mut before = self.utf16_before(from);
+        let bytes = self.text.as_bytes();
+        for (i, &byte) in bytes.iter().enumerate().take(end).skip(from) {
+            let width = utf16_width(byte);
+            if width > 0 && before + width > units {
+                return i;
+            }
+            before += width;
+        }
+        end
+    }
+}
+
+/// UTF-16 code units a UTF-8 byte begins: one per char, two for those outside the BMP, and
+/// none for continuation bytes. This lets any byte range be measured without decoding.
+fn utf16_width(byte: u8) -> usize {
+    match byte {
+        0x80..=0xbf => 0,
+        0xf0..=0xff => 2,
+        _ => 1,
+    }
+}
+
+fn utf16_len(bytes: &[u8]) -> usize {
+    bytes.iter().map(|&b| utf16_width(b)).sum()
+}
+
+/// Adds the marks `bytes` have grown past since `marks` were taken.
+fn extend_utf16_marks(marks: &mut Vec<usize>, bytes: &[u8]) {
+    while (marks.len() + 1) * UTF16_STRIDE <= bytes.len() {
+        let start = marks.len() * UTF16_STRIDE;
+        let before = marks.last().copied().unwrap_or(0);
+        marks.push(before + utf16_len(&bytes[start..start + UTF16_STRIDE]));
+    }
+}
+
+fn line_starts(bytes: &[u8], offset: usize) -> impl Iterator<Item = usize> + '_ {
+    memchr::memchr_iter(b'\n', bytes).map(move |i| offset + i + 1)
+}
+
+#[derive(Clone, Copy, Debug)]
+struct CacheEntry {
+    node: NodeId,
+    node_start: usize,
+    lf_before: usize,
+}
+
+/// Remembers the node of the last lookup, as edits and reads tend to stay local. Both
+/// offset and line lookups are served from it, so rendering consecutive lines mostly
+/// hits the same node. Reads go through `&self`, hence the lock.
+#[derive(Debug, Default)]
+struct SearchCache {
+    entry: Mutex<Option<CacheEntry>>,
+}
+
+impl Clone for SearchCache {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl SearchCache {
+    fn get(&self, tree: &Tree, offset: usize) -> Option<CacheEntry> {
+        self.entry().filter(|e| {
+            e.node_start <= offset && offset <= e.node_start + tree.piece(e.node).length
+        })
+    }
+
+    /// Entry for the node holding the `n`th line feed.
+    fn get_line_feed(&self, tree: &Tree, n: usize) -> Option<CacheEntry> {
+        self.entry()
+            .filter(|e| e.lf_before < n && n <= e.lf_before + tree.piece(e.node).line_feed_count)
+    }
+
+    fn set(&self, entry: CacheEntry) {
+        *self.entry.lock().unwrap() = Some(entry);
+    }
+
+    /// Drops cached positions an edit at `offset` may have shifted.
+    fn validate(&self, offset: usize) {
+        let mut entry = self.entry.lock().unwrap();
+        if entry.is_some_and(|e| e.node_start >= offset) {
+            *entry = None;
+        }
+    }
+
+    fn forget(&self, node: NodeId) {
+        let mut entry = self.entry.lock().unwrap();
+        if entry.is_some_and(|e| e.node == node) {
+            *entry = None;
+        }
+    }
+
+    fn entry(&self) -> Option<CacheEntry> {
+        *self.entry.lock().unwrap()
+    }
+}
+
+/// Immutable text from [`TextBuffer::snapshot`], cheap to clone and send to other threads.
+/// Reads go through the [`TextBuffer`] it derefs to.
+#[derive(Clone, Debug)]
+pub struct TextSnapshot(Arc<TextBuffer>);
+
+impl Deref for TextSnapshot {
+    type Target = TextBuffer;
+
+    fn deref(&self) -> &TextBuffer {
+        &self.0
+    }
+}
+
+/// Line and column, counted in grapheme clusters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    pub fn new(line: usize, column: usize) -> Self {
+        Self { line, column }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TextBuffer {
+    /// Shared with snapshots, which never see them change: only the change buffer is
+    /// written to, and only while no snapshot holds it.
+    buffers: Vec<Arc<StringBuffer>>,
+    tree: Tree,
+    search_cache: SearchCache,
+}
+
+impl Default for TextBuffer {
+    fn default() -> Self {
+        Self::new(vec![])
+    }
+}
+
+impl TextBuffer {
+    pub(crate) fn new(originals: Vec<StringBuffer>) -> Self {
+        let mut buffers = vec![Arc::default()];
+        buffers.extend(originals.into_iter().map(Arc::new));
+
+        let mut tree = Tree::default();
+        let mut last = NIL;
+        for (i, buffer) in buffers.iter().enumerate().skip(1) {
+            if buffer.text.is_empty() {
+                continue;
+            }
+            let piece = Piece {
+                buffer: i,
+                start: 0,
+                length: buffer.text.len(),
+                line_feed_count: buffer.line_starts.len(),
+                utf16_length: buffer.utf16_before(buffer.text.len()),
+            };
+            last = tree.insert_right(last, piece);
+        }
+
+        Self {
+            buffers,
+            tree,
+            search_cache: SearchCache::default(),
+        }
+    }
+
+    /// Length in bytes.
+    pub fn len(&self) -> usize {
+        self.tree.total_size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn line_feed_count(&self) -> usize {
+        self.tree.total_line_feeds()
+    }
+
+    /// Length in UTF-16 code units.
+    pub fn utf16_len(&self) -> usize {
+        self.tree.total_utf16()
+    }
+
+    /// The document as consecutive slices, one per piece.
+    pub fn chunks(&self) -> impl Iterator<Item = &str> + '_ {
+        self.tree.iter().map(move |x| {
+            let piece = self.tree.piece(x);
+            self.piece_text(piece, 0..piece.length)
+        })
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_feed_count() + 1
+    }
+
+    /// Bytes allocated for buffers, line starts and the tree.
+    pub fn heap_size(&self) -> usize {
+        let buffers: usize = self
+            .buffers
+            .iter()
+            .map(|b| {
+                b.text.heap_size()
+                    + (b.line_starts.capacity() + b.utf16_marks.capacity())
+                        * std::mem::size_of::<usize>()
+            })
+            .sum();
+        buffers + self.tree.heap_size()
+    }
+
+    /// A view of the text as it is now, for reading on another thread while editing goes
+    /// on here. Buffers are shared and only the pieces are copied.
+    pub fn snapshot(&self) -> TextSnapshot {
+        TextSnapshot(Arc::new(self.clone()))
+    }
+
+    /// Offset of the first byte of `line`.
+    pub fn line_start(&self, line: usize) -> usize {
+        if line == 0 {
+            return 0;
+        }
+        let (node, node_start, lf_before) = self.node_at_line_feed(line);
+        let piece = self.tree.piece(node);
+        let buffer = &self.buffers[piece.buffer];
+        let first = buffer.line_starts.partition_point(|&s| s <= piece.start);
+        let start = buffer.line_starts[first + line - lf_before - 1];
+        node_start + start - piece.start
+    }
+
+    /// Line containing `offset`.
+    pub fn line_at(&self, offset: usize) -> usize {
+        assert!(offset <= self.len(), "offset {offset} is out of bounds");
+        if self.tree.root() == NIL {
+            return 0;
+        }
+        let (node, node_start, lf_before) = self.node_at(offset);
+        let piece = self.tree.piece(node);
+        let buffer = &self.buffers[piece.buffer];
+        lf_before + buffer.line_feeds(piece.start, piece.start + offset - node_start)
+    }
+
+    /// Offset of the grapheme at `column` of `line`. Columns past the end of the line clamp
+    /// to its end.
+    pub fn offset_at(&self, line: usize, column: usize) -> usize {
+        let start = self.line_start(line);
+        let content = self.get_line_content(line);
+        let offset = content
+            .grapheme_indices(true)
+            .nth(column)
+            .map_or(content.len(), |(i, _)| i);
+        start + offset
+    }
+
+    /// Line and grapheme column of `offset`. An offset inside a grapheme cluster maps to
+    /// the cluster's column.
+    pub fn position_at(&self, offset: usize) -> Position {
+        let line = self.line_at(offset);
+        let start = self.line_start(line);
+        let content = self.get_line_content(line);
+        let column = content
+            .grapheme_indices(true)
+            .take_while(|(i, g)| start + i + g.len() <= offset)
+            .count();
+        Position { line, column }
+    }
+
+    /// UTF-16 offset of byte `offset`.
+    pub fn utf16_offset_at(&self, offset: usize) -> usize {
+        assert!(offset <= self.len(), "offset {offset} is out of bounds");
+        if self.tree.root() == NIL {
+            return 0;
+        }
+        let (node, node_start, _) = self.node_at(offset);
+        let piece = self.tree.piece(node);
+        let buffer = &self.buffers[piece.buffer];
+        let start = piece.start + offset - node_start;
+        self.tree.utf16_before(node) + buffer.utf16_before(start) - buffer.utf16_before(piece.start)
+    }
+
+    /// Byte offset of UTF-16 offset `units`. One inside a surrogate pair maps to the start
+    /// of its char, and past the end to the end.
+    pub fn byte_offset_at(&self, units: usize) -> usize {
+        let (node, node_start, utf16_before) = match self.tree.node_at_utf16(units) {
+            Some(found) => found,
+            None => return self.len(),
+        };
+        let piece = self.tree.piece(node);
+        let buffer = &self.buffers[piece.buffer];
+        let units = buffer.utf16_before(piece.start) + units - utf16_before;
+        let end = buffer.offset_at_utf16(piece.start, piece.start + piece.length, units);
+        node_start + end - piece.start
+    }
+
+    /// Byte range of `line`, without its line feed.
+    pub fn line_range(&self, line: usize) -> Range<usize> {
+        let start = self.line_start(line);
+        let end = if line + 1 < self.line_count() {
+            self.line_start(line + 1) - 1
+        } else {
+            self.len()
+        };
+        start..end
+    }
+
+    /// Content of `line` without its line ending. Borrowed unless the line spans pieces.
+    pub fn get_line_content(&self, line: usize) -> Cow<'_, str> {
+        let range = self.line_range(line);
+        let mut chunks = self.chunks_in(range.clone());
+        match (chunks.next(), chunks.next()) {
+            (None, _) => Cow::Borrowed(""),
+            (Some(text), None) => Cow::Borrowed(text.strip_suffix('\r').unwrap_or(text)),
+            (Some(first), Some(second)) => {
+                let mut text = String::with_capacity(range.len());
+                text.push_str(first);
+                text.push_str(second);
+                text.extend(chunks);
+                if text.ends_with('\r') {
+                    text.pop();
+                }
+                Cow::Owned(text)
+            }
+        }
+    }
+
+    /// Length in bytes of `line` without its line ending.
+    pub fn get_line_length(&self, line: usize) -> usize {
+        let range = self.line_range(line);
+        if !range.is_empty() && self.byte_at(range.end - 1) == b'\r' {
+            range.len() - 1
+        } else {
+            range.len()
+        }
+    }
+
+    pub fn text_in(&self, range: Range<usize>) -> String {
+        let mut text = String::with_capacity(range.len());
+        text.extend(self.chunks_in(range));
+        text
+    }
+
+    /// Text between two positions, given in either order. Only the pieces overlapping the
+    /// range are visited.
+    pub fn get_text_in_range(&self, start: Position, end: Position) -> String {
+        let (start, end) = (start.min(end), start.max(end));
+        let start = self.offset_at(start.line, start.column);
+        let end = self.offset_at(end.line, end.column);
+        self.text_in(start..end)
+    }
+
+    /// The rest of the piece holding `offset`, empty at the end, for readers taking the
+    /// text a piece at a time such as parsers. Unlike [`TextBuffer::chunks_in`], `offset`
+    /// may fall within a character.
+    pub fn bytes_at(&self, offset: usize) -> &[u8] {
+        if offset >= self.len() {
+            return &[];
+        }
+        let (mut x, mut node_start, _) = self.tree.node_at(offset).unwrap_or((NIL, 0, 0));
+        while x != NIL {
+            let piece = self.tree.piece(x);
+            if offset < node_start + piece.length {
+                let bytes = self.buffers[piece.buffer].text.as_bytes();
+                return &bytes[piece.start + offset - node_start..piece.start + piece.length];
+            }
+            node_start += piece.length;
+            x = self.tree.next(x);
+        }
+        &[]
+    }
+
+    /// The slices making up `range`.
+    pub fn chunks_in(&self, range: Range<usize>) -> impl Iterator<Item = &str> + '_ {
+        assert!(
+            range.start <= range.end && range.end <= self.len(),
+            "range {range:?} is out of bounds"
+        );
+        let (mut x, mut node_start, _) = self.tree.node_at(range.start).unwrap_or((NIL, 0, 0));
+        std::iter::from_fn(move || {
+            while x != NIL && node_start < range.end {
+                let piece = self.tree.piece(x);
+                let start = range.start.max(node_start) - node_start;
+                let end = range.end.min(node_start + piece.length) - node_start;
+                node_start += piece.length;
+                x = self.tree.next(x);
+                if start < end {
+                    return Some(self.piece_text(piece, start..end));
+                }
+            }
+            None
+        })
+    }
+
+    pub fn insert(&mut self, offset: usize, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        assert!(
+            offset <= self.len(),
+            "insert offset {offset} is out of bounds"
+        );
+
+        self.unshare_change_buffer();
+        if self.tree.root() == NIL {
+            let piece = self.create_piece(text);
+            self.tree.insert_left(NIL, piece);
+            return;
+        }
+
+        let (node, node_start, _) = self.node_at(offset);
+        let remainder = offset - node_start;
+        let piece = *self.tree.piece(node);
+
+        if remainder == piece.length
+            && self.is_change_buffer_tail(&piece)
+            && text.len() < AVERAGE_BUFFER_SIZE
+        {
+            self.append_to_node(node, text);
+        } else if remainder == 0 {
+            self.insert_left(node, text);
+        } else if remainder < piece.length {
+            self.insert_middle(node, remainder, text);
+        } else {
+            self.insert_right(node, text);
+        }
+        self.search_cache.validate(offset);
+    }
+
+    pub fn delete(&mut self, offset: usize, length: usize) {
+        if length == 0 || self.tree.root() == NIL {
+            return;
+        }
+        assert!(
+            offset + length <= self.len(),
+            "delete range {offset}..{} is out of bounds",
+            offset + length
+        );
+
+        let (start_node, start_node_start, _) = self.node_at(offset);
+        let (end_node, end_node_start, _) = self.node_at(offset + length);
+        let start_remainder = offset - start_node_start;
+        let end_remainder = offset + length - end_node_start;
+
+        if start_node == end_node {
+            let piece = *self.tree.piece(start_node);
+            if start_remainder == 0 && end_remainder == piece.length {
+                self.delete_node(start_node);
+            } else if start_remainder == 0 {
+                self.delete_node_head(start_node, end_remainder);
+            } else if end_remainder == piece.length {
+                self.delete_node_tail(start_node, start_remainder);
+            } else {
+                self.shrink_node(start_node, start_remainder, end_remainder);
+            }
+            self.search_cache.validate(offset);
+            return;
+        }
+
+        let mut to_delete = vec![];
+
+        self.delete_node_tail(start_node, start_remainder);
+        if self.tree.piece(start_node).length == 0 {
+            to_delete.push(start_node);
+        }
+
+        self.delete_node_head(end_node, end_remainder);
+        if self.tree.piece(end_node).length == 0 {
+            to_delete.push(end_node);
+        }
+
+        let mut x = self.tree.next(start_node);
+        while x != NIL && x != end_node {
+            to_delete.push(x);
+            x = self.tree.next(x);
+        }
+
+        for x in to_delete {
+            self.delete_node(x);
+        }
+        self.search_cache.validate(offset);
+    }
+
+    fn node_at(&self, offset: usize) -> (NodeId, usize, usize) {
+        if let Some(e) = self.search_cache.get(&self.tree, offset) {
+            return (e.node, e.node_start, e.lf_before);
+        }
+
+        let (node, node_start, lf_before) = self
+            .tree
+            .node_at(offset)
+            .expect("offset is within the document");
+        self.search_cache.set(CacheEntry {
+            node,
+            node_start,
+            lf_before,
+        });
+        (node, node_start, lf_before)
+    }
+
+    fn node_at_line_feed(&self, n: usize) -> (NodeId, usize, usize) {
+        if let Some(e) = self.search_cache.get_line_feed(&self.tree, n) {
+            return (e.node, e.node_start, e.lf_before);
+        }
+
+        let (node, node_start, lf_before) = self
+            .tree
+            .node_at_line_feed(n)
+            .unwrap_or_else(|| panic!("line {n} is out of bounds"));
+        self.search_cache.set(CacheEntry {
+            node,
+            node_start,
+            lf_before,
+        });
+        (node, node_start, lf_before)
+    }
+
+    fn byte_at(&self, offset: usize) -> u8 {
+        let (mut node, mut node_start, _) = self.node_at(offset);
+        if offset - node_start == self.tree.piece(node).length {
+            node_start += self.tree.piece(node).length;
+            node = self.tree.next(node);
+        }
+        let piece = self.tree.piece(node);
+        self.buffers[piece.buffer].text.as_bytes()[piece.start + offset - node_start]
+    }
+
+    /// The text of `piece` in `range`, relative to its start.
+    fn piece_text(&self, piece: &Piece, range: Range<usize>) -> &str {
+        debug_assert!(range.end <= piece.length);
+        self.buffers[piece.buffer]
+            .text
+            .slice(piece.start + range.start..piece.start + range.end)
+    }
+
+    fn piece(&self, buffer: usize, start: usize, end: usize) -> Piece {
+        assert!(
+            self.buffers[buffer].text.is_char_boundary(start)
+                && self.buffers[buffer].text.is_char_boundary(end),
+            "offset is not on a char boundary"
+        );
+        Piece {
+            buffer,
+            start,
+            length: end - start,
+            line_feed_count: self.buffers[buffer].line_feeds(start, end),
+            utf16_length: self.buffers[buffer].utf16_before(end)
+                - self.buffers[buffer].utf16_before(start),
+        }
+    }
+
+    /// Stores `text` in a buffer and returns a piece covering it.
+    fn create_piece(&mut self, text: &str) -> Piece {
+        if text.len() >= AVERAGE_BUFFER_SIZE {
+            self.buffers
+                .push(Arc::new(StringBuffer::new(text.to_string())));
+            return self.piece(self.buffers.len() - 1, 0, text.len());
+        }
+
+        let start = self.buffers[CHANGE_BUFFER].text.len();
+        self.change_buffer().push_str(text);
+        self.piece(CHANGE_BUFFER, start, start + text.len())
+    }
+
+    /// If a snapshot still reads the change buffer, writing continues in a copy of it. One
+    /// too large to copy becomes a read-only buffer like the originals instead and writing
+    /// continues in a new one, so there is a buffer more per that much typed rather than
+    /// per snapshot taken.
+    fn unshare_change_buffer(&mut self) {
+        if Arc::get_mut(&mut self.buffers[CHANGE_BUFFER]).is_some() {
+            return;
+        }
+        if self.buffers[CHANGE_BUFFER].text.len() < AVERAGE_BUFFER_SIZE {
+            Arc::make_mut(&mut self.buffers[CHANGE_BUFFER]);
+            return;
+        }
+        let sealed = mem::take(&mut self.buffers[CHANGE_BUFFER]);
+        self.buffers.push(sealed);
+        let index = self.buffers.len() - 1;
+        let nodes: Vec<NodeId> = self.tree.iter().collect();
+        for node in nodes {
+            let piece = *self.tree.piece(node);
+            if piece.buffer == CHANGE_BUFFER {
+                self.tree.set_piece(
+                    node,
+                    Piece {
+                        buffer: index,
+                        ..piece
+                    },
+                );
+            }
+        }
+        self.search_cache = SearchCache::default();
+    }
+
+    fn change_buffer(&mut self) -> &mut StringBuffer {
+        Arc::get_mut(&mut self.buffers[CHANGE_BUFFER]).expect("change buffer is shared")
+    }
+
+    fn is_change_buffer_tail(&self, piece: &Piece) -> bool {
+        piece.buffer == CHANGE_BUFFER
+            && piece.start + piece.length == self.buffers[CHANGE_BUFFER].text.len()
+    }
+
+    /// Typing at the end of the last insert just grows its piece.
+    fn append_to_node(&mut self, node: NodeId, text: &str) {
+        self.change_buffer().push_str(text);
+        let piece = self.tree.piece(node);
+        let piece = self.piece(
+            piece.buffer,
+            piece.start,
+            piece.start + piece.length + text.len(),
+        );
+        self.tree.set_piece(node, piece);
+    }
+
+    fn insert_left(&mut self, node: NodeId, text: &str) {
+        let piece = self.create_piece(text);
+        self.tree.insert_left(node, piece);
+    }
+
+    fn insert_right(&mut self, node: NodeId, text: &str) {
+        let piece = self.create_piece(text);
+        self.tree.insert_right(node, piece);
+    }
+
+    /// Splits `node` at `remainder` and puts `text` in between.
+    fn insert_middle(&mut self, node: NodeId, remainder: usize, text: &str) {
+        let piece = *self.tree.piece(node);
+        let split = piece.start + remainder;
+        let head = self.piece(piece.buffer, piece.start, split);
+        let tail = self.piece(piece.buffer, split, piece.start + piece.length);
+
+        self.tree.set_piece(node, head);
+        let inserted = self.create_piece(text);
+        let inserted = self.tree.insert_right(node, inserted);
+        self.tree.insert_right(inserted, tail);
+    }
+
+    fn delete_node(&mut self, node: NodeId) {
+        self.search_cache.forget(node);
+        self.tree.delete(node);
+    }
+
+    /// Keeps the first `remainder` bytes of `node`.
+    fn delete_node_tail(&mut self, node: NodeId, remainder: usize) {
+        let piece = *self.tree.piece(node);
+        let piece = self.piece(piece.buffer, piece.start, piece.start + remainder);
+        self.tree.set_piece(node, piece);
+    }
+
+    /// Drops the first `remainder` bytes of `node`.
+    fn delete_node_head(&mut self, node: NodeId, remainder: usize) {
+        let piece = *self.tree.piece(node);
+        let piece = self.piece(
+            piece.buffer,
+            piece.start + remainder,
+            piece.start + piece.length,
+        );
+        self.tree.set_piece(node, piece);
+    }
+
+    /// Removes `start..end` from the middle of `node`, splitting it in two.
+    fn shrink_node(&mut self, node: NodeId, start: usize, end: usize) {
+        let piece = *self.tree.piece(node);
+        let head = self.piece(piece.buffer, piece.start, piece.start + start);
+        let tail = self.piece(piece.buffer, piece.start + end, piece.start + piece.length);
+
+        self.tree.set_piece(node, head);
+        self.tree.insert_right(node, tail);
+    }
+}
+
+impl fmt::Display for TextBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for chunk in self.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+use super::{builder::index, Text, TextBuffer};
+use std::{fmt, fs::File, io, os::unix::io::AsRawFd, ptr, slice, str, sync::Arc};
+
+/// Original buffers a mapped file is split into. Only their line starts live on the heap.
+const REGION_SIZE: usize = 1024 * 1024;
+
+/// A file mapped read-only into memory. Mapping reads nothing, so it is quick for files of
+/// any size.
+///
+/// The pages are read from the file as they are touched and never count as heap. Another
+/// process changing the file while it is mapped changes what is read, see
+/// [`TextBuffer::from_mapping`]; that is the price of not copying it.
+pub struct Mapping {
+    ptr: *const u8,
+    len: usize,
+}
+
+// The mapping is never written to and unmapped only on drop.
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    pub fn new(file: &File) -> io::Result<Self> {
+        let len = usize::try_from(file.metadata()?.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "file too large to map"))?;
+        if len == 0 {
+            return Ok(Self {
+                ptr: ptr::NonNull::dangling().as_ptr(),
+                len,
+            });
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            ptr: ptr as *const u8,
+            len,
+        })
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.len) };
+        }
+    }
+}
+
+impl fmt::Debug for Mapping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mapping").field("len", &self.len).finish()
+    }
+}
+
+impl TextBuffer {
+    /// A buffer reading the text of `mapping` from `start` on in place, split into regions
+    /// on char boundaries. A region that isn't UTF-8 is decoded lossily onto the heap
+    /// instead. Edits go to the change buffer as usual.
+    ///
+    /// Every byte is read to find the lines, so large files are better mapped off the main
+    /// thread.
+    ///
+    /// The file must not change while the buffer is alive. Writes by another process show
+    /// through: line starts and lengths go stale, and since the text is decoded again on
+    /// every access, a region that is no longer UTF-8 panics. Truncating the file makes
+    /// reading past the new end crash with SIGBUS. Editors that save by writing a new file
+    /// and renaming it over the old one are fine, the mapping keeps the old one.
+    pub fn from_mapping(mapping: Arc<Mapping>, start: usize) -> Self {
+        let bytes = mapping.bytes();
+        let mut regions = vec![];
+        let mut from = start;
+        while from < bytes.len() {
+            let end = (from + REGION_SIZE).min(bytes.len());
+            // A char split at the end goes to the next region. It has at most three
+            // continuation bytes, more aren't text anyway.
+            let to = (end.saturating_sub(3).max(from + 1)..=end)
+                .rev()
+                .find(|&to| to == bytes.len() || bytes[to] & 0xc0 != 0x80)
+                .unwrap_or(end);
+            let region = &bytes[from..to];
+            regions.push(match str::from_utf8(region) {
+                Ok(_) => Text::Mapped(mapping.clone(), from..to),
+                Err(_) => Text::Owned(String::from_utf8_lossy(region).into_owned()),
+            });
+            from = to;
+        }
+        TextBuffer::new(index(regions))
+    }
+}
@@ -0,0 +1,153 @@
+//! `Serialize` and `Deserialize` for [`TextBuffer`], behind the `serde` feature, e.g. to
+//! snapshot a document for crash recovery or hand it to another process.
+//!
+//! The buffers are written with the pieces pointing into them, in document order, so
+//! reading it back keeps the same pieces instead of one big buffer. Line starts, UTF-16
+//! marks and the tree are rebuilt rather than trusted, and every piece is checked to lie
+//! within its buffer on char boundaries. Mapped buffers are written out as their text and
+//! read back onto the heap. Original buffers no piece points into are left out.
+//!
+//! [`FORMAT_VERSION`] is bumped when the layout changes. Reading handles every version
+//! written before, so snapshots outlive the editor that wrote them.
+
+use super::{builder::index, Piece, StringBuffer, Text, TextBuffer, Tree, CHANGE_BUFFER, NIL};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
+
+/// The version [`TextBuffer`]s are written in.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// `length` bytes of `buffer` from `start`.
+#[derive(Debug, Deserialize, Serialize)]
+struct PieceRef {
+    buffer: usize,
+    start: usize,
+    length: usize,
+}
+
+/// What a buffer adds up to, checked against the pieces when read.
+#[derive(Debug, Deserialize, Serialize)]
+struct Info {
+    len: usize,
+    line_feeds: usize,
+    utf16_len: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Stored<'a> {
+    version: u32,
+    /// The change buffer first.
+    buffers: Vec<Cow<'a, str>>,
+    pieces: Vec<PieceRef>,
+    info: Info,
+}
+
+/// All of `buffer`'s text, as it is written out.
+fn whole(buffer: &StringBuffer) -> &str {
+    buffer.text.slice(0..buffer.text.len())
+}
+
+impl Serialize for TextBuffer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // Where each buffer kept ends up, the change buffer staying first.
+        let mut kept = HashMap::from([(CHANGE_BUFFER, CHANGE_BUFFER)]);
+        let mut buffers = vec![Cow::Borrowed(whole(&self.buffers[CHANGE_BUFFER]))];
+        let pieces = self
+            .tree
+            .iter()
+            .map(|node| {
+                let piece = self.tree.piece(node);
+                let buffer = *kept.entry(piece.buffer).or_insert_with(|| {
+                    buffers.push(Cow::Borrowed(whole(&self.buffers[piece.buffer])));
+                    buffers.len() - 1
+                });
+                PieceRef {
+                    buffer,
+                    start: piece.start,
+                    length: piece.length,
+                }
+            })
+            .collect();
+        Stored {
+            version: FORMAT_VERSION,
+            buffers,
+            pieces,
+            info: Info {
+                len: self.len(),
+                line_feeds: self.line_feed_count(),
+                utf16_len: self.utf16_len(),
+            },
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TextBuffer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let stored = Stored::deserialize(deserializer)?;
+        match stored.version {
+            1 => stored.into_buffer().map_err(de::Error::custom),
+            version => Err(de::Error::custom(format!(
+                "unsupported text buffer format version {version}, newer than {FORMAT_VERSION}"
+            ))),
+        }
+    }
+}
+
+impl Stored<'_> {
+    fn into_buffer(self) -> Result<TextBuffer, String> {
+        let mut texts = self.buffers.into_iter().map(Cow::into_owned);
+        let change = texts.next().ok_or("no change buffer")?;
+        let mut buffers = vec![Arc::new(StringBuffer::new(change))];
+        buffers.extend(
+            index(texts.map(Text::Owned).collect())
+                .into_iter()
+                .map(Arc::new),
+        );
+
+        let mut tree = Tree::default();
+        let mut last = NIL;
+        for (i, p) in self.pieces.iter().enumerate() {
+            let buffer = buffers
+                .get(p.buffer)
+                .ok_or_else(|| format!("piece {i} points into missing buffer {}", p.buffer))?;
+            let end = p
+                .start
+                .checked_add(p.length)
+                .filter(|&end| end <= buffer.text.len())
+                .ok_or_else(|| format!("piece {i} runs past the end of buffer {}", p.buffer))?;
+            if p.length == 0
+                || !buffer.text.is_char_boundary(p.start)
+                || !buffer.text.is_char_boundary(end)
+            {
+                return Err(format!("piece {i} is empty or splits a char"));
+            }
+            let piece = Piece {
+                buffer: p.buffer,
+                start: p.start,
+                length: p.length,
+                line_feed_count: buffer.line_feeds(p.start, end),
+                utf16_length: buffer.utf16_before(end) - buffer.utf16_before(p.start),
+            };
+            last = tree.insert_right(last, piece);
+        }
+
+        let found = (
+            tree.total_size(),
+            tree.total_line_feeds(),
+            tree.total_utf16(),
+        );
+        let info = self.info;
+        if found != (info.len, info.line_feeds, info.utf16_len) {
+            return Err(format!(
+                "pieces add up to {found:?} bytes, line feeds and UTF-16 units, not {:?}",
+                (info.len, info.line_feeds, info.utf16_len)
+            ));
+        }
+        Ok(TextBuffer {
+            buffers,
+            tree,
+            search_cache: Default::default(),
+        })
+    }
+}
@@ -0,0 +1,232 @@
+//! Literal and regex search over a [`TextBuffer`], scanning its pieces in place.
+
+use crate::TextBuffer;
+use memchr::memmem::Finder;
+use regex::{Captures, Regex, RegexBuilder};
+use std::{mem, ops::Range};
+
+/// Literal search over text split into chunks, e.g. the pieces of a document, without
+/// concatenating them. Chunks are scanned in place with memchr's SIMD searcher; only the
+/// last `needle.len() - 1` bytes of what came before are carried over to find matches
+/// straddling a chunk boundary.
+///
+/// Matches are reported as byte ranges into the whole text, leftmost first and
+/// non-overlapping, the same as `str::match_indices`.
+pub struct LiteralScanner<'n> {
+    finder: Finder<'n>,
+    carry: Vec<u8>,
+    window: Vec<u8>,
+    /// Bytes fed so far.
+    offset: usize,
+    /// End of the last match, matches starting before it are skipped.
+    last_end: usize,
+    /// Set when the needle was lowercased, chunks are lowercased into it before scanning.
+    folded: Option<Vec<u8>>,
+}
+
+impl<'n> LiteralScanner<'n> {
+    pub fn new(needle: &'n str) -> Self {
+        Self {
+            finder: Finder::new(needle.as_bytes()),
+            carry: Vec::with_capacity(needle.len()),
+            window: Vec::with_capacity(needle.len() * 2),
+            offset: 0,
+            last_end: 0,
+            folded: None,
+        }
+    }
+
+    /// Ignores the case of ASCII letters. Others are compared as they are, so lowering
+    /// the case never changes the length of the text and offsets stay valid.
+    pub fn ignoring_case(needle: &str) -> LiteralScanner<'static> {
+        let mut scanner = LiteralScanner::new("");
+        scanner.finder = Finder::new(&needle.to_ascii_lowercase()).into_owned();
+        scanner.folded = Some(Vec::new());
+        scanner
+    }
+
+    pub fn feed(&mut self, chunk: &[u8], matches: &mut Vec<Range<usize>>) {
+        match self.folded.take() {
+            Some(mut folded) => {
+                folded.clear();
+                folded.extend(chunk.iter().map(u8::to_ascii_lowercase));
+                self.feed_bytes(&folded, matches);
+                self.folded = Some(folded);
+            }
+            None => self.feed_bytes(chunk, matches),
+        }
+    }
+
+    fn feed_bytes(&mut self, chunk: &[u8], matches: &mut Vec<Range<usize>>) {
+        let n = self.finder.needle().len();
+        if n == 0 {
+            return;
+        }
+
+        if !self.carry.is_empty() {
+            self.scan_boundary(chunk, matches);
+        }
+
+        let mut from = self.last_end.saturating_sub(self.offset);
+        while let Some(i) = chunk.get(from..).and_then(|c| self.finder.find(c)) {
+            let start = self.offset + from + i;
+            matches.push(start..start + n);
+            self.last_end = start + n;
+            from += i + n;
+        }
+
+        self.offset += chunk.len();
+        let keep = n - 1;
+        if chunk.len() >= keep {
+            self.carry.clear();
+            self.carry.extend_from_slice(&chunk[chunk.len() - keep..]);
+        } else {
+            self.carry.extend_from_slice(chunk);
+            let excess = self.carry.len().saturating_sub(keep);
+            self.carry.drain(..excess);
+        }
+    }
+
+    /// Finds matches starting in the carried tail and ending in `chunk`.
+    fn scan_boundary(&mut self, chunk: &[u8], matches: &mut Vec<Range<usize>>) {
+        let n = self.finder.needle().len();
+        let carry_len = self.carry.len();
+        let base = self.offset - carry_len;
+
+        let mut window = mem::take(&mut self.window);
+        window.clear();
+        window.extend_from_slice(&self.carry);
+        window.extend_from_slice(&chunk[..chunk.len().min(n - 1)]);
+
+        let mut from = self.last_end.saturating_sub(base);
+        while let Some(i) = window.get(from..).and_then(|w| self.finder.find(w)) {
+            if from + i >= carry_len {
+                break;
+            }
+            let start = base + from + i;
+            matches.push(start..start + n);
+            self.last_end = start + n;
+            from += i + n;
+        }
+        self.window = window;
+    }
+}
+
+/// Finds every occurrence of `needle` in the concatenation of `chunks`.
+pub fn find_literal<'a>(
+    chunks: impl IntoIterator<Item = &'a str>,
+    needle: &str,
+) -> Vec<Range<usize>> {
+    let mut scanner = LiteralScanner::new(needle);
+    let mut matches = vec![];
+    for chunk in chunks {
+        scanner.feed(chunk.as_bytes(), &mut matches);
+    }
+    matches
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchOptions {
+    pub case_sensitive: bool,
+    /// Skips matches touching a letter, digit or underscore on either side.
+    pub whole_word: bool,
+}
+
+/// Searching a [`TextBuffer`], kept apart from its editing methods.
+pub trait Find {
+    /// Ranges of `query` in the whole buffer, for the UI to highlight.
+    fn find(&self, query: &str, options: SearchOptions) -> Vec<Range<usize>>;
+
+    /// Like [`Find::find`], but only looks at `range`, e.g. the lines on screen or the ones
+    /// just edited. Pieces are scanned where they are.
+    fn find_in(
+        &self,
+        range: Range<usize>,
+        query: &str,
+        options: SearchOptions,
+    ) -> Vec<Range<usize>>;
+
+    /// Ranges matched by `regex`, leftmost first and non-overlapping.
+    fn find_all_regex(&self, regex: &Regex) -> Vec<Range<usize>>;
+}
+
+impl Find for TextBuffer {
+    fn find(&self, query: &str, options: SearchOptions) -> Vec<Range<usize>> {
+        self.find_in(0..self.len(), query, options)
+    }
+
+    fn find_in(
+        &self,
+        range: Range<usize>,
+        query: &str,
+        options: SearchOptions,
+    ) -> Vec<Range<usize>> {
+        let mut scanner = if options.case_sensitive {
+            LiteralScanner::new(query)
+        } else {
+            LiteralScanner::ignoring_case(query)
+        };
+        let mut matches = vec![];
+        for chunk in self.chunks_in(range.clone()) {
+            scanner.feed(chunk.as_bytes(), &mut matches);
+        }
+        for m in &mut matches {
+            *m = m.start + range.start..m.end + range.start;
+        }
+        if options.whole_word {
+            matches.retain(|m| is_word_boundary(self, m.start) && is_word_boundary(self, m.end));
+        }
+        matches
+    }
+
+    fn find_all_regex(&self, regex: &Regex) -> Vec<Range<usize>> {
+        let mut matches = vec![];
+        regex_captures(self, regex, |start, captures| {
+            let m = captures.get(0).unwrap();
+            matches.push(start + m.start()..start + m.end());
+        });
+        matches
+    }
+}
+
+/// Whether `offset` doesn't split a word. Words never span lines, so only the line of
+/// `offset` is read.
+fn is_word_boundary(buffer: &TextBuffer, offset: usize) -> bool {
+    let line = buffer.line_at(offset);
+    let content = buffer.get_line_content(line);
+    let (before, after) = content.split_at((offset - buffer.line_start(line)).min(content.len()));
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    !(before.chars().next_back().is_some_and(is_word) && after.chars().next().is_some_and(is_word))
+}
+
+/// Compiles `pattern` with `options` applied.
+pub fn build_regex(pattern: &str, options: SearchOptions) -> Result<Regex, regex::Error> {
+    let pattern = if options.whole_word {
+        format!(r"\b(?:{pattern})\b")
+    } else {
+        pattern.to_string()
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(!options.case_sensitive)
+        .build()
+}
+
+/// Like in VS Code, a pattern only matches across lines if it mentions `\n`. Others run
+/// line by line, on lines borrowed from their piece where they fit in one, instead of over
+/// a copy of the whole buffer.
+pub fn regex_captures(buffer: &TextBuffer, regex: &Regex, mut f: impl FnMut(usize, Captures)) {
+    let pattern = regex.as_str();
+    if pattern.contains("\\n") || pattern.contains('\n') {
+        for captures in regex.captures_iter(&buffer.to_string()) {
+            f(0, captures);
+        }
+        return;
+    }
+    for line in 0..buffer.line_count() {
+        let content = buffer.get_line_content(line);
+        let start = buffer.line_start(line);
+        for captures in regex.captures_iter(&content) {
+            f(start, captures);
+        }
+    }
+}
@@ -0,0 +1,679 @@
+//! Red-black tree of pieces, ordered by their position in the document.
+//!
+//! Nodes live in an arena and refer to each other by index, with index 0 as the shared
+//! sentinel leaf. Every node caches the total length, line feed count and UTF-16 length of
+//! its left subtree (`size_left`, `lf_left`, `utf16_left`), which makes offset, line and
+//! UTF-16 lookups O(log n); rotations and deletions keep those in sync.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Piece {
+    pub buffer: usize,
+    /// Byte offset into the buffer.
+    pub start: usize,
+    pub length: usize,
+    pub line_feed_count: usize,
+    /// Length in UTF-16 code units.
+    pub utf16_length: usize,
+}
+
+pub(crate) type NodeId = usize;
+
+pub(crate) const NIL: NodeId = 0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Color {
+    Red,
+    Black,
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    piece: Piece,
+    color: Color,
+    parent: NodeId,
+    left: NodeId,
+    right: NodeId,
+    size_left: usize,
+    lf_left: usize,
+    utf16_left: usize,
+}
+
+impl Node {
+    fn new(piece: Piece, color: Color) -> Self {
+        Self {
+            piece,
+            color,
+            parent: NIL,
+            left: NIL,
+            right: NIL,
+            size_left: 0,
+            lf_left: 0,
+            utf16_left: 0,
+        }
+    }
+}
+
+const EMPTY_PIECE: Piece = Piece {
+    buffer: 0,
+    start: 0,
+    length: 0,
+    line_feed_count: 0,
+    utf16_length: 0,
+};
+
+#[derive(Clone, Debug)]
+pub(crate) struct Tree {
+    nodes: Vec<Node>,
+    root: NodeId,
+    free: Vec<NodeId>,
+}
+
+impl Default for Tree {
+    fn default() -> Self {
+        Self {
+            nodes: vec![Node::new(EMPTY_PIECE, Color::Black)],
+            root: NIL,
+            free: vec![],
+        }
+    }
+}
+
+impl Tree {
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    pub fn piece(&self, x: NodeId) -> &Piece {
+        &self.nodes[x].piece
+    }
+
+    /// Bytes allocated for nodes.
+    pub fn heap_size(&self) -> usize {
+        self.nodes.capacity() * std::mem::size_of::<Node>()
+            + self.free.capacity() * std::mem::size_of::<NodeId>()
+    }
+
+    /// Replaces the piece of `x`, propagating the changes of its lengths upwards.
+    pub fn set_piece(&mut self, x: NodeId, piece: Piece) {
+        let old = self.nodes[x].piece;
+        self.nodes[x].piece = piece;
+        let delta = piece.length as isize - old.length as isize;
+        let lf_delta = piece.line_feed_count as isize - old.line_feed_count as isize;
+        let utf16_delta = piece.utf16_length as isize - old.utf16_length as isize;
+        self.update_metadata(x, delta, lf_delta, utf16_delta);
+    }
+
+    pub fn total_size(&self) -> usize {
+        self.size(self.root)
+    }
+
+    pub fn total_line_feeds(&self) -> usize {
+        self.line_feeds(self.root)
+    }
+
+    pub fn total_utf16(&self) -> usize {
+        self.utf16(self.root)
+    }
+
+    /// The node containing `offset`, the offset it starts at and the number of line feeds
+    /// before it. At the boundary between two nodes either may be returned.
+    pub fn node_at(&self, mut offset: usize) -> Option<(NodeId, usize, usize)> {
+        let mut x = self.root;
+        let mut node_start = 0;
+        let mut lf_before = 0;
+
+        while x != NIL {
+            let node = &self.nodes[x];
+            if node.size_left > offset {
+                x = node.left;
+            } else if node.size_left + node.piece.length >= offset {
+                return Some((x, node_start + node.size_left, lf_before + node.lf_left));
+            } else {
+                offset -= node.size_left + node.piece.length;
+                node_start += node.size_left + node.piece.length;
+                lf_before += node.lf_left + node.piece.line_feed_count;
+                x = node.right;
+            }
+        }
+
+        None
+    }
+
+    /// The node holding the `n`th line feed (1-based), the offset it starts at and the
+    /// number of line feeds before it.
+    pub fn node_at_line_feed(&self, mut n: usize) -> Option<(NodeId, usize, usize)> {
+        let mut x = self.root;
+        let mut node_start = 0;
+        let mut lf_before = 0;
+
+        while x != NIL {
+            let node = &self.nodes[x];
+            if node.lf_left >= n {
+                x = node.left;
+            } else if node.lf_left + node.piece.line_feed_count >= n {
+                return Some((x, node_start + node.size_left, lf_before + node.lf_left));
+            } else {
+                n -= node.lf_left + node.piece.line_feed_count;
+                node_start += node.size_left + node.piece.length;
+                lf_before += node.lf_left + node.piece.line_feed_count;
+                x = node.right;
+            }
+        }
+
+        None
+    }
+
+    /// The node containing UTF-16 offset `units`, the byte offset it starts at and its UTF-16
+    /// offset. At the boundary between two nodes either may be returned.
+    pub fn node_at_utf16(&self, mut units: usize) -> Option<(NodeId, usize, usize)> {
+        let mut x = self.root;
+        let mut node_start = 0;
+        let mut utf16_before = 0;
+
+        while x != NIL {
+            let node = &self.nodes[x];
+            if node.utf16_left > units {
+                x = node.left;
+            } else if node.utf16_left + node.piece.utf16_length >= units {
+                return Some((
+                    x,
+                    node_start + node.size_left,
+                    utf16_before + node.utf16_left,
+                ));
+            } else {
+                units -= node.utf16_left + node.piece.utf16_length;
+                node_start += node.size_left + node.piece.length;
+                utf16_before += node.utf16_left + node.piece.utf16_length;
+                x = node.right;
+            }
+        }
+
+        None
+    }
+
+    /// UTF-16 offset `x` starts at.
+    pub fn utf16_before(&self, mut x: NodeId) -> usize {
+        let mut units = self.nodes[x].utf16_left;
+        while x != self.root {
+            let parent = self.nodes[x].parent;
+            if self.nodes[parent].right == x {
+                units += self.nodes[parent].utf16_left + self.nodes[parent].piece.utf16_length;
+            }
+            x = parent;
+        }
+        units
+    }
+
+    pub fn first(&self) -> NodeId {
+        if self.root == NIL {
+            NIL
+        } else {
+            self.leftest(self.root)
+        }
+    }
+
+    pub fn next(&self, mut x: NodeId) -> NodeId {
+        if self.nodes[x].right != NIL {
+            return self.leftest(self.nodes[x].right);
+        }
+        while self.nodes[x].parent != NIL {
+            let parent = self.nodes[x].parent;
+            if self.nodes[parent].left == x {
+                return parent;
+            }
+            x = parent;
+        }
+        NIL
+    }
+
+    /// Node ids in document order.
+    pub fn iter(&self) -> impl Iterator<Item = NodeId> + '_ {
+        let mut x = self.first();
+        std::iter::from_fn(move || {
+            if x == NIL {
+                return None;
+            }
+            let current = x;
+            x = self.next(x);
+            Some(current)
+        })
+    }
+
+    /// Inserts `piece` right before `node`, or as the root if the tree is empty.
+    pub fn insert_left(&mut self, node: NodeId, piece: Piece) -> NodeId {
+        let z = self.alloc(piece);
+        if self.root == NIL {
+            self.root = z;
+            self.nodes[z].color = Color::Black;
+        } else if self.nodes[node].left == NIL {
+            self.nodes[node].left = z;
+            self.nodes[z].parent = node;
+        } else {
+            let prev = self.rightest(self.nodes[node].left);
+            self.nodes[prev].right = z;
+            self.nodes[z].parent = prev;
+        }
+        self.fix_insert(z);
+        z
+    }
+
+    /// Inserts `piece` right after `node`, or as the root if the tree is empty.
+    pub fn insert_right(&mut self, node: NodeId, piece: Piece) -> NodeId {
+        let z = self.alloc(piece);
+        if self.root == NIL {
+            self.root = z;
+            self.nodes[z].color = Color::Black;
+        } else if self.nodes[node].right == NIL {
+            self.nodes[node].right = z;
+            self.nodes[z].parent = node;
+        } else {
+            let next = self.leftest(self.nodes[node].right);
+            self.nodes[next].left = z;
+            self.nodes[z].parent = next;
+        }
+        self.fix_insert(z);
+        z
+    }
+
+    pub fn delete(&mut self, z: NodeId) {
+        let (mut x, y);
+        if self.nodes[z].left == NIL {
+            y = z;
+            x = self.nodes[y].right;
+        } else if self.nodes[z].right == NIL {
+            y = z;
+            x = self.nodes[y].left;
+        } else {
+            y = self.leftest(self.nodes[z].right);
+            x = self.nodes[y].right;
+        }
+
+        if y == self.root {
+            self.root = x;
+            self.nodes[x].color = Color::Black;
+            self.nodes[x].parent = NIL;
+            self.release(z);
+            self.reset_sentinel();
+            return;
+        }
+
+        let y_was_red = self.nodes[y].color == Color::Red;
+        let y_parent = self.nodes[y].parent;
+        if self.nodes[y_parent].left == y {
+            self.nodes[y_parent].left = x;
+        } else {
+            self.nodes[y_parent].right = x;
+        }
+
+        if y == z {
+            self.nodes[x].parent = y_parent;
+            self.recompute_metadata(x);
+        } else {
+            self.nodes[x].parent = if y_parent == z { y } else { y_parent };
+
+            // x's subtree changed, so fix the metadata above it before moving y.
+            self.recompute_metadata(x);
+
+            let (z_left, z_right, z_parent, z_color) = {
+                let z = &self.nodes[z];
+                (z.left, z.right, z.parent, z.color)
+            };
+            self.nodes[y].left = z_left;
+            self.nodes[y].right = z_right;
+            self.nodes[y].parent = z_parent;
+            self.nodes[y].color = z_color;
+
+            if z == self.root {
+                self.root = y;
+            } else if self.nodes[z_parent].left == z {
+                self.nodes[z_parent].left = y;
+            } else {
+                self.nodes[z_parent].right = y;
+            }
+
+            if z_left != NIL {
+                self.nodes[z_left].parent = y;
+            }
+            if z_right != NIL {
+                self.nodes[z_right].parent = y;
+            }
+
+            // y takes z's place, so it inherits z's left subtree metadata.
+            self.nodes[y].size_left = self.nodes[z].size_left;
+            self.nodes[y].lf_left = self.nodes[z].lf_left;
+            self.nodes[y].utf16_left = self.nodes[z].utf16_left;
+            self.recompute_metadata(y);
+        }
+
+        self.release(z);
+
+        let x_parent = self.nodes[x].parent;
+        if self.nodes[x_parent].left == x {
+            let size_left = self.size(x);
+            let lf_left = self.line_feeds(x);
+            let utf16_left = self.utf16(x);
+            let parent = &mut self.nodes[x_parent];
+            if size_left != parent.size_left
+                || lf_left != parent.lf_left
+                || utf16_left != parent.utf16_left
+            {
+                let delta = size_left as isize - parent.size_left as isize;
+                let lf_delta = lf_left as isize - parent.lf_left as isize;
+                let utf16_delta = utf16_left as isize - parent.utf16_left as isize;
+                parent.size_left = size_left;
+                parent.lf_left = lf_left;
+                parent.utf16_left = utf16_left;
+                self.update_metadata(x_parent, delta, lf_delta, utf16_delta);
+            }
+        }
+        self.recompute_metadata(x_parent);
+
+        if y_was_red {
+            self.reset_sentinel();
+            return;
+        }
+
+        while x != self.root && self.nodes[x].color == Color::Black {
+            let parent = self.nodes[x].parent;
+            if x == self.nodes[parent].left {
+                let mut w = self.nodes[parent].right;
+                if self.nodes[w].color == Color::Red {
+                    self.nodes[w].color = Color::Black;
+                    self.nodes[parent].color = Color::Red;
+                    self.rotate_left(parent);
+                    w = self.nodes[self.nodes[x].parent].right;
+                }
+
+                if self.color(self.nodes[w].left) == Color::Black
+                    && self.color(self.nodes[w].right) == Color::Black
+                {
+                    self.nodes[w].color = Color::Red;
+                    x = self.nodes[x].parent;
+                } else {
+                    if self.color(self.nodes[w].right) == Color::Black {
+                        let w_left = self.nodes[w].left;
+                        self.nodes[w_left].color = Color::Black;
+                        self.nodes[w].color = Color::Red;
+                        self.rotate_right(w);
+                        w = self.nodes[self.nodes[x].parent].right;
+                    }
+
+                    let parent = self.nodes[x].parent;
+                    self.nodes[w].color = self.nodes[parent].color;
+                    self.nodes[parent].color = Color::Black;
+                    let w_right = self.nodes[w].right;
+                    self.nodes[w_right].color = Color::Black;
+                    self.rotate_left(parent);
+                    x = self.root;
+                }
+            } else {
+                let mut w = self.nodes[parent].left;
+                if self.nodes[w].color == Color::Red {
+                    self.nodes[w].color = Color::Black;
+                    self.nodes[parent].color = Color::Red;
+                    self.rotate_right(parent);
+                    w = self.nodes[self.nodes[x].parent].left;
+                }
+
+                if self.color(self.nodes[w].left) == Color::Black
+                    && self.color(self.nodes[w].right) == Color::Black
+                {
+                    self.nodes[w].color = Color::Red;
+                    x = self.nodes[x].parent;
+                } else {
+                    if self.color(self.nodes[w].left) == Color::Black {
+                        let w_right = self.nodes[w].right;
+                        self.nodes[w_right].color = Color::Black;
+                        self.nodes[w].color = Color::Red;
+                        self.rotate_left(w);
+                        w = self.nodes[self.nodes[x].parent].left;
+                    }
+
+                    let parent = self.nodes[x].parent;
+                    self.nodes[w].color = self.nodes[parent].color;
+                    self.nodes[parent].color = Color::Black;
+                    let w_left = self.nodes[w].left;
+                    self.nodes[w_left].color = Color::Black;
+                    self.rotate_right(parent);
+                    x = self.root;
+                }
+            }
+        }
+
+        self.nodes[x].color = Color::Black;
+        self.reset_sentinel();
+    }
+
+    fn alloc(&mut self, piece: Piece) -> NodeId {
+        let node = Node::new(piece, Color::Red);
+        match self.free.pop() {
+            Some(id) => {
+                self.nodes[id] = node;
+                id
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn release(&mut self, x: NodeId) {
+        self.nodes[x] = Node::new(EMPTY_PIECE, Color::Black);
+        self.free.push(x);
+    }
+
+    /// The sentinel's parent is borrowed during deletion; put it back in shape.
+    fn reset_sentinel(&mut self) {
+        self.nodes[NIL] = Node::new(EMPTY_PIECE, Color::Black);
+    }
+
+    fn color(&self, x: NodeId) -> Color {
+        self.nodes[x].color
+    }
+
+    fn leftest(&self, mut x: NodeId) -> NodeId {
+        while self.nodes[x].left != NIL {
+            x = self.nodes[x].left;
+        }
+        x
+    }
+
+    fn rightest(&self, mut x: NodeId) -> NodeId {
+        while self.nodes[x].right != NIL {
+            x = self.nodes[x].right;
+        }
+        x
+    }
+
+    /// Total length of the subtree rooted at `x`.
+    fn size(&self, mut x: NodeId) -> usize {
+        let mut size = 0;
+        while x != NIL {
+            size += self.nodes[x].size_left + self.nodes[x].piece.length;
+            x = self.nodes[x].right;
+        }
+        size
+    }
+
+    /// Total line feed count of the subtree rooted at `x`.
+    fn line_feeds(&self, mut x: NodeId) -> usize {
+        let mut lf = 0;
+        while x != NIL {
+            lf += self.nodes[x].lf_left + self.nodes[x].piece.line_feed_count;
+            x = self.nodes[x].right;
+        }
+        lf
+    }
+
+    /// Total UTF-16 length of the subtree rooted at `x`.
+    fn utf16(&self, mut x: NodeId) -> usize {
+        let mut units = 0;
+        while x != NIL {
+            units += self.nodes[x].utf16_left + self.nodes[x].piece.utf16_length;
+            x = self.nodes[x].right;
+        }
+        units
+    }
+
+    fn rotate_left(&mut self, x: NodeId) {
+        let y = self.nodes[x].right;
+
+        self.nodes[y].size_left += self.nodes[x].size_left + self.nodes[x].piece.length;
+        self.nodes[y].lf_left += self.nodes[x].lf_left + self.nodes[x].piece.line_feed_count;
+        self.nodes[y].utf16_left += self.nodes[x].utf16_left + self.nodes[x].piece.utf16_length;
+
+        let y_left = self.nodes[y].left;
+        self.nodes[x].right = y_left;
+        if y_left != NIL {
+            self.nodes[y_left].parent = x;
+        }
+
+        let x_parent = self.nodes[x].parent;
+        self.nodes[y].parent = x_parent;
+        if x_parent == NIL {
+            self.root = y;
+        } else if self.nodes[x_parent].left == x {
+            self.nodes[x_parent].left = y;
+        } else {
+            self.nodes[x_parent].right = y;
+        }
+
+        self.nodes[y].left = x;
+        self.nodes[x].parent = y;
+    }
+
+    fn rotate_right(&mut self, y: NodeId) {
+        let x = self.nodes[y].left;
+
+        let x_right = self.nodes[x].right;
+        self.nodes[y].left = x_right;
+        if x_right != NIL {
+            self.nodes[x_right].parent = y;
+        }
+
+        let y_parent = self.nodes[y].parent;
+        self.nodes[x].parent = y_parent;
+
+        self.nodes[y].size_left -= self.nodes[x].size_left + self.nodes[x].piece.length;
+        self.nodes[y].lf_left -= self.nodes[x].lf_left + self.nodes[x].piece.line_feed_count;
+        self.nodes[y].utf16_left -= self.nodes[x].utf16_left + self.nodes[x].piece.utf16_length;
+
+        if y_parent == NIL {
+            self.root = x;
+        } else if self.nodes[y_parent].right == y {
+            self.nodes[y_parent].right = x;
+        } else {
+            self.nodes[y_parent].left = x;
+        }
+
+        self.nodes[x].right = y;
+        self.nodes[y].parent = x;
+    }
+
+    fn fix_insert(&mut self, mut x: NodeId) {
+        self.recompute_metadata(x);
+
+        while x != self.root && self.color(self.nodes[x].parent) == Color::Red {
+            let parent = self.nodes[x].parent;
+            let grandparent = self.nodes[parent].parent;
+
+            if parent == self.nodes[grandparent].left {
+                let uncle = self.nodes[grandparent].right;
+                if self.nodes[uncle].color == Color::Red {
+                    self.nodes[parent].color = Color::Black;
+                    self.nodes[uncle].color = Color::Black;
+                    self.nodes[grandparent].color = Color::Red;
+                    x = grandparent;
+                } else {
+                    if x == self.nodes[parent].right {
+                        x = parent;
+                        self.rotate_left(x);
+                    }
+                    let parent = self.nodes[x].parent;
+                    let grandparent = self.nodes[parent].parent;
+                    self.nodes[parent].color = Color::Black;
+                    self.nodes[grandparent].color = Color::Red;
+                    self.rotate_right(grandparent);
+                }
+            } else {
+                let uncle = self.nodes[grandparent].left;
+                if self.nodes[uncle].color == Color::Red {
+                    self.nodes[parent].color = Color::Black;
+                    self.nodes[uncle].color = Color::Black;
+                    self.nodes[grandparent].color = Color::Red;
+                    x = grandparent;
+                } else {
+                    if x == self.nodes[parent].left {
+                        x = parent;
+                        self.rotate_right(x);
+                    }
+                    let parent = self.nodes[x].parent;
+                    let grandparent = self.nodes[parent].parent;
+                    self.nodes[parent].color = Color::Black;
+                    self.nodes[grandparent].color = Color::Red;
+                    self.rotate_left(grandparent);
+                }
+            }
+        }
+
+        let root = self.root;
+        self.nodes[root].color = Color::Black;
+    }
+
+    /// Adds a change of `x`'s own length to every ancestor holding `x` in its left subtree.
+    fn update_metadata(
+        &mut self,
+        mut x: NodeId,
+        delta: isize,
+        lf_delta: isize,
+        utf16_delta: isize,
+    ) {
+        while x != self.root && x != NIL {
+            let parent = self.nodes[x].parent;
+            if self.nodes[parent].left == x {
+                let parent = &mut self.nodes[parent];
+                parent.size_left = parent.size_left.wrapping_add_signed(delta);
+                parent.lf_left = parent.lf_left.wrapping_add_signed(lf_delta);
+                parent.utf16_left = parent.utf16_left.wrapping_add_signed(utf16_delta);
+            }
+            x = parent;
+        }
+    }
+
+    /// Recomputes `size_left`/`lf_left`/`utf16_left` above `x` after its subtree changed shape.
+    fn recompute_metadata(&mut self, mut x: NodeId) {
+        if x == self.root {
+            return;
+        }
+
+        // Go up to the first ancestor whose left subtree contains x.
+        while x != self.root && x == self.nodes[self.nodes[x].parent].right {
+            x = self.nodes[x].parent;
+        }
+        if x == self.root {
+            return;
+        }
+        x = self.nodes[x].parent;
+
+        let left = self.nodes[x].left;
+        let delta = self.size(left) as isize - self.nodes[x].size_left as isize;
+        let lf_delta = self.line_feeds(left) as isize - self.nodes[x].lf_left as isize;
+        let utf16_delta = self.utf16(left) as isize - self.nodes[x].utf16_left as isize;
+        let node = &mut self.nodes[x];
+        node.size_left = node.size_left.wrapping_add_signed(delta);
+        node.lf_left = node.lf_left.wrapping_add_signed(lf_delta);
+        node.utf16_left = node.utf16_left.wrapping_add_signed(utf16_delta);
+
+        while x != self.root && (delta != 0 || lf_delta != 0 || utf16_delta != 0) {
+            let parent = self.nodes[x].parent;
+            if self.nodes[parent].left == x {
+                let parent = &mut self.nodes[parent];
+                parent.size_left = parent.size_left.wrapping_add_signed(delta);
+                parent.lf_left = parent.lf_left.wrapping_add_signed(lf_delta);
+                parent.utf16_left = parent.utf16_left.wrapping_add_signed(utf16_delta);
+            }
+            x = parent;
+        }
+    }
+}
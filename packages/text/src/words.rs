@@ -0,0 +1,85 @@
+use super::TextBuffer;
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+impl TextBuffer {
+    /// Where ctrl+left goes from `offset`: the start of the word before it, or the end of
+    /// the line above from the start of a line.
+    pub fn prev_word_boundary(&self, offset: usize) -> usize {
+        let line = self.line_at(offset);
+        let start = self.line_start(line);
+        let column = offset - start;
+        if column == 0 {
+            return match line {
+                0 => 0,
+                _ => self.line_start(line - 1) + self.get_line_length(line - 1),
+            };
+        }
+        let content = self.get_line_content(line);
+        let word = words(&content)
+            .into_iter()
+            .rev()
+            .filter(|(i, word)| *i < column && !is_blank(word))
+            .map(|(i, _)| i)
+            .next();
+        start + word.unwrap_or(0)
+    }
+
+    /// Where ctrl+right goes from `offset`: the end of the word after it, or the start of
+    /// the line below from the end of a line.
+    pub fn next_word_boundary(&self, offset: usize) -> usize {
+        let line = self.line_at(offset);
+        let start = self.line_start(line);
+        let content = self.get_line_content(line);
+        let column = offset - start;
+        if column >= content.len() {
+            return if line + 1 < self.line_count() {
+                self.line_start(line + 1)
+            } else {
+                offset
+            };
+        }
+        let word = words(&content)
+            .into_iter()
+            .filter(|(i, word)| i + word.len() > column && !is_blank(word))
+            .map(|(i, word)| i + word.len())
+            .next();
+        start + word.unwrap_or(content.len())
+    }
+
+    /// The word around `offset`, e.g. for selecting it on double click. None in whitespace.
+    pub fn word_at(&self, offset: usize) -> Option<Range<usize>> {
+        let line = self.line_at(offset);
+        let start = self.line_start(line);
+        let content = self.get_line_content(line);
+        let column = offset - start;
+        words(&content)
+            .into_iter()
+            .find(|(i, word)| *i <= column && column <= i + word.len() && !is_blank(word))
+            .map(|(i, word)| start + i..start + i + word.len())
+    }
+}
+
+/// Unicode word boundaries, also splitting at punctuation so `foo.bar` and `a::b` are
+/// separate words the way they are in code.
+fn words(line: &str) -> Vec<(usize, &str)> {
+    let is_separator = |c: char| c.is_ascii_punctuation() && c != '_';
+    let mut words = vec![];
+    for (start, word) in line.split_word_bound_indices() {
+        let mut from = 0;
+        let mut previous = None;
+        for (i, c) in word.char_indices() {
+            if previous.is_some_and(|p| is_separator(p) != is_separator(c)) {
+                words.push((start + from, &word[from..i]));
+                from = i;
+            }
+            previous = Some(c);
+        }
+        words.push((start + from, &word[from..]));
+    }
+    words
+}
+
+fn is_blank(word: &str) -> bool {
+    word.chars().all(char::is_whitespace)
+}
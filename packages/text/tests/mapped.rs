@@ -0,0 +1,51 @@
+//! Mapped files read in place, split into regions on char boundaries, whatever they hold.
+
+#![cfg(unix)]
+
+use dip_text::{Mapping, TextBuffer};
+use std::{fs, fs::File, io::Write, sync::Arc};
+
+fn mapped(name: &str, bytes: &[u8]) -> TextBuffer {
+    let path = std::env::temp_dir().join(format!("dip-mapped-{name}-{}", std::process::id()));
+    fs::write(&path, bytes).unwrap();
+    let mapping = Mapping::new(&File::open(&path).unwrap()).unwrap();
+    fs::remove_file(&path).unwrap();
+    TextBuffer::from_mapping(Arc::new(mapping), 0)
+}
+
+#[test]
+fn keeps_chars_across_regions_whole() {
+    let text = format!("{}é\n{}", "a".repeat(1024 * 1024 - 1), "b".repeat(10));
+    let buffer = mapped("regions", text.as_bytes());
+    assert_eq!(buffer.to_string(), text);
+    assert_eq!(buffer.line_count(), 2);
+}
+
+#[test]
+fn decodes_what_is_not_utf8_lossily() {
+    let buffer = mapped("lossy", b"ok\n\xff\xfe bad\nok\n");
+    assert_eq!(buffer.to_string(), "ok\n\u{fffd}\u{fffd} bad\nok\n");
+    assert_eq!(buffer.get_line_content(1), "\u{fffd}\u{fffd} bad");
+}
+
+#[test]
+fn maps_empty_files() {
+    assert_eq!(mapped("empty", b"").to_string(), "");
+}
+
+#[test]
+#[should_panic(expected = "changed on disk")]
+fn refuses_text_rewritten_under_it() {
+    let path = std::env::temp_dir().join(format!("dip-mapped-rewritten-{}", std::process::id()));
+    fs::write(&path, "café\n").unwrap();
+    let mapping = Mapping::new(&File::open(&path).unwrap()).unwrap();
+    let buffer = TextBuffer::from_mapping(Arc::new(mapping), 0);
+    // In place, as opposed to saving a new file over it.
+    fs::OpenOptions::new()
+        .write(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(b"caf\xff\xff\n"))
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    buffer.to_string();
+}
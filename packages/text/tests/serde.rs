@@ -0,0 +1,94 @@
+//! Buffers read back from what they serialize to hold the same text and edit the same.
+
+#![cfg(feature = "serde")]
+
+use dip_text::{TextBuffer, TextBufferBuilder, FORMAT_VERSION};
+use serde_json::{json, Value};
+
+fn edited() -> TextBuffer {
+    let mut builder = TextBufferBuilder::new();
+    builder.accept_chunk(&"héllo\n😀 wörld\r\n".repeat(10_000));
+    let mut buffer = builder.finish();
+    buffer.insert(7, "big ");
+    buffer.delete(0, 1);
+    let end = buffer.len();
+    buffer.insert(end, "✓ end");
+    buffer
+}
+
+fn round_trip(buffer: &TextBuffer) -> TextBuffer {
+    serde_json::from_str(&serde_json::to_string(buffer).unwrap()).unwrap()
+}
+
+#[test]
+fn reads_back_the_same_text() {
+    let mut buffer = edited();
+    let mut read = round_trip(&buffer);
+    assert_eq!(read.to_string(), buffer.to_string());
+    assert_eq!(read.line_count(), buffer.line_count());
+    assert_eq!(read.utf16_len(), buffer.utf16_len());
+    assert_eq!(read.line_start(5_000), buffer.line_start(5_000));
+
+    for b in [&mut buffer, &mut read] {
+        b.insert(3, "\nnew");
+        b.delete(100, 50);
+    }
+    assert_eq!(read.to_string(), buffer.to_string());
+    assert_eq!(round_trip(&TextBuffer::default()).len(), 0);
+}
+
+#[test]
+fn leaves_out_buffers_nothing_points_into() {
+    let mut builder = TextBufferBuilder::new();
+    builder.accept_chunk(&"x".repeat(200_000));
+    let mut buffer = builder.finish();
+    buffer.delete(0, 199_000);
+    let value = serde_json::to_value(&buffer).unwrap();
+    assert_eq!(value["version"], FORMAT_VERSION);
+    // The change buffer, and the original the remaining text is in.
+    assert_eq!(value["buffers"].as_array().unwrap().len(), 2);
+    assert_eq!(round_trip(&buffer).to_string(), "x".repeat(1_000));
+}
+
+#[test]
+fn rejects_other_versions_and_broken_pieces() {
+    let mut value = serde_json::to_value(edited()).unwrap();
+    let error = |value: &Value| {
+        serde_json::from_value::<TextBuffer>(value.clone())
+            .unwrap_err()
+            .to_string()
+    };
+
+    value["version"] = json!(FORMAT_VERSION + 1);
+    assert!(error(&value).contains("unsupported text buffer format version"));
+    value["version"] = json!(FORMAT_VERSION);
+
+    let length = value["pieces"][0]["length"].as_u64().unwrap();
+    value["pieces"][0]["length"] = json!(length + 1_000_000);
+    assert!(error(&value).contains("runs past the end"));
+    value["pieces"][0]["length"] = json!(length);
+
+    // Inside the é of the first text.
+    value["pieces"][0]["start"] = json!(1);
+    value["pieces"][0]["length"] = json!(1);
+    assert!(error(&value).contains("splits a char"));
+}
+
+#[test]
+fn snapshots_taken_while_typing_add_no_buffers() {
+    let mut buffer = TextBuffer::from("fn main() {}\n");
+    let mut snapshots = vec![];
+    for (i, c) in "let typed = 1;".char_indices() {
+        snapshots.push(buffer.snapshot());
+        buffer.insert(11 + i, &c.to_string());
+    }
+    let value = serde_json::to_value(&buffer).unwrap();
+    // The change buffer, and the original the text was loaded into.
+    assert_eq!(value["buffers"].as_array().unwrap().len(), 2);
+    assert_eq!(value["pieces"].as_array().unwrap().len(), 3);
+    assert_eq!(snapshots[4].to_string(), "fn main() {let }\n");
+    assert_eq!(
+        round_trip(&buffer).to_string(),
+        "fn main() {let typed = 1;}\n"
+    );
+}
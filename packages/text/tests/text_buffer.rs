@@ -0,0 +1,229 @@
+//! Edits a piece tree and a plain string side by side and checks they always agree.
+
+use dip_text::{Position, TextBuffer, TextBufferBuilder};
+use proptest::prelude::*;
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Clone, Debug)]
+enum Op {
+    /// Where to insert, as a fraction of the length.
+    Insert(f64, String),
+    Delete(f64, usize),
+    /// Inserts one char after another where the last edit ended, the way typing does.
+    Type(String),
+    Snapshot,
+}
+
+fn small_text() -> impl Strategy<Value = String> {
+    prop_oneof![6 => "[a-c\n]{0,12}", 1 => "[a\r\né✓🦀]{0,8}"]
+}
+
+fn text() -> impl Strategy<Value = String> {
+    prop_oneof![
+        7 => small_text(),
+        // Large enough for a buffer of its own.
+        1 => "[xy\n]{1,6}".prop_map(|s| "x".repeat(70_000) + &s),
+    ]
+}
+
+/// Mostly outside ASCII, and long enough to span several UTF-16 marks.
+fn wide_text() -> impl Strategy<Value = String> {
+    prop_oneof![
+        3 => small_text(),
+        1 => "[aé✓🦀\n]{1,8}".prop_map(|s| s.repeat(400)),
+    ]
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        4 => (0.0..=1.0, text()).prop_map(|(at, text)| Op::Insert(at, text)),
+        2 => (0.0..=1.0, 0..40usize).prop_map(|(at, len)| Op::Delete(at, len)),
+        2 => "[a-c \né🦀]{1,10}".prop_map(Op::Type),
+        1 => Just(Op::Snapshot),
+    ]
+}
+
+/// The char boundary at or before `at` of the way through `model`.
+fn offset(model: &str, at: f64) -> usize {
+    let mut offset = (model.len() as f64 * at) as usize;
+    while !model.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+/// Byte offset of the last char boundary at most `units` UTF-16 code units in.
+fn utf16_to_byte(model: &str, units: usize) -> usize {
+    let mut count = 0;
+    for (i, c) in model.char_indices() {
+        count += c.len_utf16();
+        if count > units {
+            return i;
+        }
+    }
+    model.len()
+}
+
+/// Line and grapheme column of `offset`, counted the slow way.
+fn position(model: &str, offset: usize) -> Position {
+    let line = model[..offset].matches('\n').count();
+    let start = model[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let end = model[start..].find('\n').map_or(model.len(), |i| start + i);
+    let content = &model[start..end];
+    let content = content.strip_suffix('\r').unwrap_or(content);
+    let column = content
+        .grapheme_indices(true)
+        .take_while(|(i, g)| start + i + g.len() <= offset)
+        .count();
+    Position::new(line, column)
+}
+
+fn check(buffer: &TextBuffer, model: &str) {
+    assert_eq!(buffer.len(), model.len());
+    assert_eq!(buffer.to_string(), model);
+
+    let lines: Vec<&str> = model.split('\n').collect();
+    assert_eq!(buffer.line_count(), lines.len());
+    let mut start = 0;
+    for (line, content) in lines.iter().enumerate() {
+        assert_eq!(buffer.line_start(line), start, "start of line {line}");
+        assert_eq!(buffer.line_range(line), start..start + content.len());
+        assert_eq!(buffer.line_at(start), line);
+        assert_eq!(buffer.line_at(start + content.len()), line);
+        let content = content.strip_suffix('\r').unwrap_or(content);
+        assert_eq!(buffer.get_line_content(line), content);
+        assert_eq!(buffer.get_line_length(line), content.len());
+        start += lines[line].len() + 1;
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(200))]
+
+    #[test]
+    fn edits_match_a_string(initial in text(), ops in prop::collection::vec(op(), 1..40)) {
+        let mut buffer = TextBuffer::from(initial.as_str());
+        let mut model = initial;
+        let mut snapshots = vec![];
+        let mut cursor = 0;
+        for op in ops {
+            match op {
+                Op::Insert(at, text) => {
+                    let at = offset(&model, at);
+                    buffer.insert(at, &text);
+                    model.insert_str(at, &text);
+                    cursor = at + text.len();
+                }
+                Op::Type(text) => {
+                    for c in text.chars() {
+                        buffer.insert(cursor, c.encode_utf8(&mut [0; 4]));
+                        model.insert(cursor, c);
+                        cursor += c.len_utf8();
+                    }
+                }
+                Op::Delete(at, len) => {
+                    let start = offset(&model, at);
+                    let mut end = (start + len).min(model.len());
+                    while !model.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    buffer.delete(start, end - start);
+                    model.replace_range(start..end, "");
+                    cursor = start;
+                }
+                Op::Snapshot => snapshots.push((buffer.snapshot(), model.clone())),
+            }
+        }
+        check(&buffer, &model);
+        for (snapshot, model) in &snapshots {
+            check(snapshot, model);
+        }
+    }
+
+    #[test]
+    fn ranges_match_a_string(
+        initial in text(),
+        inserts in prop::collection::vec((0.0..=1.0, text()), 0..20),
+        range in (0.0..=1.0, 0.0..=1.0),
+    ) {
+        let mut buffer = TextBuffer::from(initial.as_str());
+        let mut model = initial;
+        for (at, text) in inserts {
+            let at = offset(&model, at);
+            buffer.insert(at, &text);
+            model.insert_str(at, &text);
+        }
+        let (a, b) = (offset(&model, range.0), offset(&model, range.1));
+        let range = a.min(b)..a.max(b);
+        assert_eq!(buffer.text_in(range.clone()), &model[range.clone()]);
+        assert_eq!(buffer.chunks_in(range.clone()).collect::<String>(), &model[range.clone()]);
+        assert_eq!(buffer.line_at(range.start), model[..range.start].matches('\n').count());
+    }
+
+    #[test]
+    fn positions_match_a_string(
+        initial in small_text(),
+        inserts in prop::collection::vec((0.0..=1.0, small_text()), 0..20),
+        offsets in prop::collection::vec(0.0..=1.0, 1..20),
+    ) {
+        let mut buffer = TextBuffer::from(initial.as_str());
+        let mut model = initial;
+        for (at, text) in inserts {
+            let at = offset(&model, at);
+            buffer.insert(at, &text);
+            model.insert_str(at, &text);
+        }
+        for at in offsets {
+            let at = offset(&model, at);
+            let expected = position(&model, at);
+            assert_eq!(buffer.position_at(at), expected, "position of {at}");
+            assert_eq!(buffer.position_at(buffer.offset_at(expected.line, expected.column)), expected);
+        }
+    }
+
+    #[test]
+    fn utf16_offsets_match_a_string(
+        initial in wide_text(),
+        inserts in prop::collection::vec((0.0..=1.0, wide_text()), 0..20),
+        deletes in prop::collection::vec((0.0..=1.0, 0..3000usize), 0..5),
+        offsets in prop::collection::vec(0.0..=1.0, 1..20),
+    ) {
+        let mut buffer = TextBuffer::from(initial.as_str());
+        let mut model = initial;
+        for (at, text) in inserts {
+            let at = offset(&model, at);
+            buffer.insert(at, &text);
+            model.insert_str(at, &text);
+        }
+        for (at, len) in deletes {
+            let start = offset(&model, at);
+            let mut end = (start + len).min(model.len());
+            while !model.is_char_boundary(end) {
+                end -= 1;
+            }
+            buffer.delete(start, end - start);
+            model.replace_range(start..end, "");
+        }
+        let total = model.encode_utf16().count();
+        assert_eq!(buffer.utf16_len(), total);
+        for at in offsets {
+            let at = offset(&model, at);
+            let units = model[..at].encode_utf16().count();
+            assert_eq!(buffer.utf16_offset_at(at), units, "UTF-16 offset of {at}");
+            assert_eq!(buffer.byte_offset_at(units), at);
+            // Also halfway into surrogate pairs and past the end.
+            let units = units + 1;
+            assert_eq!(buffer.byte_offset_at(units), utf16_to_byte(&model, units));
+        }
+        assert_eq!(buffer.byte_offset_at(total + 5), model.len());
+    }
+
+    #[test]
+    fn chunked_loading_matches_a_string(chunks in prop::collection::vec(text(), 0..30)) {
+        let mut builder = TextBufferBuilder::new();
+        for chunk in &chunks {
+            builder.accept_chunk(chunk);
+        }
+        check(&builder.finish(), &chunks.concat());
+    }
+}
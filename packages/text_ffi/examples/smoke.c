@@ -0,0 +1,45 @@
+// A C host embedding the buffer. Build the library, then from packages/text_ffi:
+//
+//     cc examples/smoke.c -Iinclude -L../../target/debug -ldip_text_ffi -o smoke
+//     LD_LIBRARY_PATH=../../target/debug ./smoke
+
+#include <assert.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "dip_text.h"
+
+int main(void) {
+  const char *text = "fn main() {\n}";
+  DipTextBuffer *buffer = dip_text_buffer_new((const uint8_t *)text, strlen(text));
+  assert(buffer != NULL);
+
+  const char *body = "\n    println!(\"hi\");";
+  DipTextStatus status =
+      dip_text_buffer_insert(buffer, 11, (const uint8_t *)body, strlen(body));
+  assert(status == DIP_TEXT_STATUS_OK);
+  assert(dip_text_buffer_line_count(buffer) == 3);
+
+  // Offsets inside a UTF-8 sequence or past the end are refused, not undefined.
+  status = dip_text_buffer_delete(buffer, 0, 1000);
+  assert(status == DIP_TEXT_STATUS_OUT_OF_BOUNDS);
+
+  DipPosition start = {.line = 1, .column = 4};
+  DipPosition end = {.line = 1, .column = 12};
+  DipString word;
+  status = dip_text_buffer_get_text_in_range(buffer, start, end, &word);
+  assert(status == DIP_TEXT_STATUS_OK);
+  assert(word.len == 8 && memcmp(word.ptr, "println!", 8) == 0);
+  printf("%.*s\n", (int)word.len, (const char *)word.ptr);
+  dip_string_free(word);
+
+  size_t offset;
+  status = dip_text_buffer_offset_at(buffer, start, &offset);
+  assert(status == DIP_TEXT_STATUS_OK && offset == 16);
+  DipPosition position;
+  status = dip_text_buffer_position_at(buffer, offset, &position);
+  assert(status == DIP_TEXT_STATUS_OK && position.line == 1 && position.column == 4);
+
+  dip_text_buffer_free(buffer);
+  return 0;
+}
@@ -0,0 +1,117 @@
+#ifndef DIP_TEXT_H
+#define DIP_TEXT_H
+
+/* Written by hand after packages/text_ffi/src/lib.rs, in the layout of cbindgen.toml.
+   Change both together. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum DipTextStatus {
+  DIP_TEXT_STATUS_OK,
+  DIP_TEXT_STATUS_NULL_POINTER,
+  DIP_TEXT_STATUS_INVALID_UTF8,
+  // An offset past the end or a line past the last.
+  DIP_TEXT_STATUS_OUT_OF_BOUNDS,
+  // An offset inside a UTF-8 sequence.
+  DIP_TEXT_STATUS_NOT_CHAR_BOUNDARY,
+} DipTextStatus;
+
+// A text buffer owned by the host.
+typedef struct DipTextBuffer DipTextBuffer;
+
+// UTF-8 text owned by the host, not NUL-terminated.
+typedef struct DipString {
+  uint8_t *ptr;
+  size_t len;
+} DipString;
+
+// Line and column, counted in grapheme clusters.
+typedef struct DipPosition {
+  size_t line;
+  size_t column;
+} DipPosition;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// A buffer holding the `len` bytes of UTF-8 at `text`, or NULL if they aren't UTF-8.
+//
+// # Safety
+//
+// `text` must point to `len` readable bytes, or may be NULL when `len` is 0.
+DipTextBuffer *dip_text_buffer_new(const uint8_t *text, size_t len);
+
+// Frees a buffer. NULL is ignored.
+void dip_text_buffer_free(DipTextBuffer *buffer);
+
+// Length in bytes, 0 for NULL.
+size_t dip_text_buffer_len(const DipTextBuffer *buffer);
+
+// Lines, 0 for NULL.
+size_t dip_text_buffer_line_count(const DipTextBuffer *buffer);
+
+// Inserts the `len` bytes of UTF-8 at `text` at `offset`.
+//
+// # Safety
+//
+// `text` must point to `len` readable bytes, or may be NULL when `len` is 0.
+DipTextStatus dip_text_buffer_insert(DipTextBuffer *buffer,
+                                     size_t offset,
+                                     const uint8_t *text,
+                                     size_t len);
+
+// Deletes `length` bytes from `offset`.
+DipTextStatus dip_text_buffer_delete(DipTextBuffer *buffer, size_t offset, size_t length);
+
+// Writes the text from byte `start` to `end` to `out`.
+DipTextStatus dip_text_buffer_text_in(const DipTextBuffer *buffer,
+                                      size_t start,
+                                      size_t end,
+                                      DipString *out);
+
+// Writes the text between two positions, given in either order, to `out`. Columns past
+// the end of their line clamp to it.
+DipTextStatus dip_text_buffer_get_text_in_range(const DipTextBuffer *buffer,
+                                                DipPosition start,
+                                                DipPosition end,
+                                                DipString *out);
+
+// Writes the position of byte `offset` to `out`.
+DipTextStatus dip_text_buffer_position_at(const DipTextBuffer *buffer,
+                                          size_t offset,
+                                          DipPosition *out);
+
+// Writes the byte offset of `position` to `out`. A column past the end of its line clamps
+// to it.
+DipTextStatus dip_text_buffer_offset_at(const DipTextBuffer *buffer,
+                                        DipPosition position,
+                                        size_t *out);
+
+// Writes the UTF-16 offset of byte `offset` to `out`, for hosts whose strings count in
+// UTF-16 code units.
+DipTextStatus dip_text_buffer_utf16_offset_at(const DipTextBuffer *buffer,
+                                              size_t offset,
+                                              size_t *out);
+
+// Writes the byte offset of UTF-16 offset `units` to `out`. One inside a surrogate pair
+// maps to the start of its char.
+DipTextStatus dip_text_buffer_byte_offset_at(const DipTextBuffer *buffer,
+                                             size_t units,
+                                             size_t *out);
+
+// Frees text handed out by a buffer. A NULL `ptr` is ignored.
+//
+// # Safety
+//
+// `string` must come from this library and not have been freed yet.
+void dip_string_free(DipString string);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* DIP_TEXT_H */
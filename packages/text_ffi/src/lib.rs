@@ -0,0 +1,304 @@
+//! C ABI for [`dip_text::TextBuffer`], so hosts such as Swift and Kotlin can embed the
+//! buffer. `include/dip_text.h` declares it, written by hand and changed with this file.
+//!
+//! A buffer is owned by its handle: [`dip_text_buffer_new`] gives one out and
+//! [`dip_text_buffer_free`] takes it back. Text handed out is a [`DipString`] the caller owns
+//! until passing it to [`dip_string_free`]. Handles are not thread safe; a host sharing one
+//! between threads has to lock around every call.
+//!
+//! Offsets are byte offsets into the UTF-8 text. Every argument is checked and reported as
+//! a [`DipTextStatus`] instead of panicking, as a panic can't unwind into the host.
+
+use dip_text::{Position, TextBuffer};
+use std::{ptr, slice, str};
+
+/// A text buffer owned by the host.
+pub struct DipTextBuffer(TextBuffer);
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DipTextStatus {
+    Ok,
+    NullPointer,
+    InvalidUtf8,
+    /// An offset past the end or a line past the last.
+    OutOfBounds,
+    /// An offset inside a UTF-8 sequence.
+    NotCharBoundary,
+}
+
+/// Line and column, counted in grapheme clusters.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DipPosition {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl From<Position> for DipPosition {
+    fn from(position: Position) -> Self {
+        Self {
+            line: position.line,
+            column: position.column,
+        }
+    }
+}
+
+impl From<DipPosition> for Position {
+    fn from(position: DipPosition) -> Self {
+        Position::new(position.line, position.column)
+    }
+}
+
+/// UTF-8 text owned by the host, not NUL-terminated.
+#[repr(C)]
+#[derive(Debug)]
+pub struct DipString {
+    pub ptr: *mut u8,
+    pub len: usize,
+}
+
+impl From<String> for DipString {
+    fn from(text: String) -> Self {
+        let bytes = Box::into_raw(text.into_bytes().into_boxed_slice());
+        Self {
+            ptr: bytes as *mut u8,
+            len: bytes.len(),
+        }
+    }
+}
+
+impl Default for DipString {
+    fn default() -> Self {
+        Self {
+            ptr: ptr::null_mut(),
+            len: 0,
+        }
+    }
+}
+
+/// `len` bytes at `text`, which may be NULL when empty.
+unsafe fn text<'a>(text: *const u8, len: usize) -> Result<&'a str, DipTextStatus> {
+    if len == 0 {
+        return Ok("");
+    }
+    if text.is_null() {
+        return Err(DipTextStatus::NullPointer);
+    }
+    str::from_utf8(slice::from_raw_parts(text, len)).map_err(|_| DipTextStatus::InvalidUtf8)
+}
+
+fn check_offset(buffer: &TextBuffer, offset: usize) -> Result<(), DipTextStatus> {
+    if offset > buffer.len() {
+        return Err(DipTextStatus::OutOfBounds);
+    }
+    // A continuation byte never starts a char.
+    match buffer.bytes_at(offset).first() {
+        Some(&byte) if (byte as i8) < -0x40 => Err(DipTextStatus::NotCharBoundary),
+        _ => Ok(()),
+    }
+}
+
+fn check_line(buffer: &TextBuffer, line: usize) -> Result<(), DipTextStatus> {
+    match line < buffer.line_count() {
+        true => Ok(()),
+        false => Err(DipTextStatus::OutOfBounds),
+    }
+}
+
+fn status(result: Result<(), DipTextStatus>) -> DipTextStatus {
+    result.err().unwrap_or(DipTextStatus::Ok)
+}
+
+/// Writes what `f` returns to `out`.
+fn output<B, T>(
+    buffer: Option<B>,
+    out: Option<&mut T>,
+    f: impl FnOnce(B) -> Result<T, DipTextStatus>,
+) -> DipTextStatus {
+    let (buffer, out) = match buffer.zip(out) {
+        Some(found) => found,
+        None => return DipTextStatus::NullPointer,
+    };
+    status(f(buffer).map(|value| *out = value))
+}
+
+/// A buffer holding the `len` bytes of UTF-8 at `text`, or NULL if they aren't UTF-8.
+///
+/// # Safety
+///
+/// `text` must point to `len` readable bytes, or may be NULL when `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn dip_text_buffer_new(
+    text: *const u8,
+    len: usize,
+) -> Option<Box<DipTextBuffer>> {
+    let text = self::text(text, len).ok()?;
+    Some(Box::new(DipTextBuffer(TextBuffer::from(text))))
+}
+
+/// Frees a buffer. NULL is ignored.
+#[no_mangle]
+pub extern "C" fn dip_text_buffer_free(buffer: Option<Box<DipTextBuffer>>) {
+    drop(buffer);
+}
+
+/// Length in bytes, 0 for NULL.
+#[no_mangle]
+pub extern "C" fn dip_text_buffer_len(buffer: Option<&DipTextBuffer>) -> usize {
+    buffer.map_or(0, |buffer| buffer.0.len())
+}
+
+/// Lines, 0 for NULL.
+#[no_mangle]
+pub extern "C" fn dip_text_buffer_line_count(buffer: Option<&DipTextBuffer>) -> usize {
+    buffer.map_or(0, |buffer| buffer.0.line_count())
+}
+
+/// Inserts the `len` bytes of UTF-8 at `text` at `offset`.
+///
+/// # Safety
+///
+/// `text` must point to `len` readable bytes, or may be NULL when `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn dip_text_buffer_insert(
+    buffer: Option<&mut DipTextBuffer>,
+    offset: usize,
+    text: *const u8,
+    len: usize,
+) -> DipTextStatus {
+    let buffer = match buffer {
+        Some(buffer) => &mut buffer.0,
+        None => return DipTextStatus::NullPointer,
+    };
+    status(self::text(text, len).and_then(|text| {
+        check_offset(buffer, offset)?;
+        buffer.insert(offset, text);
+        Ok(())
+    }))
+}
+
+/// Deletes `length` bytes from `offset`.
+#[no_mangle]
+pub extern "C" fn dip_text_buffer_delete(
+    buffer: Option<&mut DipTextBuffer>,
+    offset: usize,
+    length: usize,
+) -> DipTextStatus {
+    let buffer = match buffer {
+        Some(buffer) => &mut buffer.0,
+        None => return DipTextStatus::NullPointer,
+    };
+    let end = match offset.checked_add(length) {
+        Some(end) => end,
+        None => return DipTextStatus::OutOfBounds,
+    };
+    status(check_offset(buffer, offset).and_then(|_| {
+        check_offset(buffer, end)?;
+        buffer.delete(offset, length);
+        Ok(())
+    }))
+}
+
+/// Writes the text from byte `start` to `end` to `out`.
+#[no_mangle]
+pub extern "C" fn dip_text_buffer_text_in(
+    buffer: Option<&DipTextBuffer>,
+    start: usize,
+    end: usize,
+    out: Option<&mut DipString>,
+) -> DipTextStatus {
+    output(buffer, out, |buffer| {
+        check_offset(&buffer.0, start)?;
+        check_offset(&buffer.0, end)?;
+        match start <= end {
+            true => Ok(buffer.0.text_in(start..end).into()),
+            false => Err(DipTextStatus::OutOfBounds),
+        }
+    })
+}
+
+/// Writes the text between two positions, given in either order, to `out`. Columns past
+/// the end of their line clamp to it.
+#[no_mangle]
+pub extern "C" fn dip_text_buffer_get_text_in_range(
+    buffer: Option<&DipTextBuffer>,
+    start: DipPosition,
+    end: DipPosition,
+    out: Option<&mut DipString>,
+) -> DipTextStatus {
+    output(buffer, out, |buffer| {
+        check_line(&buffer.0, start.line)?;
+        check_line(&buffer.0, end.line)?;
+        Ok(buffer.0.get_text_in_range(start.into(), end.into()).into())
+    })
+}
+
+/// Writes the position of byte `offset` to `out`.
+#[no_mangle]
+pub extern "C" fn dip_text_buffer_position_at(
+    buffer: Option<&DipTextBuffer>,
+    offset: usize,
+    out: Option<&mut DipPosition>,
+) -> DipTextStatus {
+    output(buffer, out, |buffer| {
+        check_offset(&buffer.0, offset)?;
+        Ok(buffer.0.position_at(offset).into())
+    })
+}
+
+/// Writes the byte offset of `position` to `out`. A column past the end of its line clamps
+/// to it.
+#[no_mangle]
+pub extern "C" fn dip_text_buffer_offset_at(
+    buffer: Option<&DipTextBuffer>,
+    position: DipPosition,
+    out: Option<&mut usize>,
+) -> DipTextStatus {
+    output(buffer, out, |buffer| {
+        check_line(&buffer.0, position.line)?;
+        Ok(buffer.0.offset_at(position.line, position.column))
+    })
+}
+
+/// Writes the UTF-16 offset of byte `offset` to `out`, for hosts whose strings count in
+/// UTF-16 code units.
+#[no_mangle]
+pub extern "C" fn dip_text_buffer_utf16_offset_at(
+    buffer: Option<&DipTextBuffer>,
+    offset: usize,
+    out: Option<&mut usize>,
+) -> DipTextStatus {
+    output(buffer, out, |buffer| {
+        check_offset(&buffer.0, offset)?;
+        Ok(buffer.0.utf16_offset_at(offset))
+    })
+}
+
+/// Writes the byte offset of UTF-16 offset `units` to `out`. One inside a surrogate pair
+/// maps to the start of its char.
+#[no_mangle]
+pub extern "C" fn dip_text_buffer_byte_offset_at(
+    buffer: Option<&DipTextBuffer>,
+    units: usize,
+    out: Option<&mut usize>,
+) -> DipTextStatus {
+    output(buffer, out, |buffer| match units <= buffer.0.utf16_len() {
+        true => Ok(buffer.0.byte_offset_at(units)),
+        false => Err(DipTextStatus::OutOfBounds),
+    })
+}
+
+/// Frees text handed out by a buffer. A NULL `ptr` is ignored.
+///
+/// # Safety
+///
+/// `string` must come from this library and not have been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn dip_string_free(string: DipString) {
+    if !string.ptr.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+            string.ptr, string.len,
+        )));
+    }
+}
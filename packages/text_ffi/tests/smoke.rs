@@ -0,0 +1,132 @@
+use dip_text_ffi::*;
+use std::{ptr, slice};
+
+fn string(string: DipString) -> String {
+    let text = unsafe { slice::from_raw_parts(string.ptr, string.len) };
+    let text = String::from_utf8(text.to_vec()).unwrap();
+    unsafe { dip_string_free(string) };
+    text
+}
+
+#[test]
+fn edits_and_reads_through_handles() {
+    let text = "héllo\nwörld";
+    let mut buffer = unsafe { dip_text_buffer_new(text.as_ptr(), text.len()) };
+    assert_eq!(dip_text_buffer_len(buffer.as_deref()), text.len());
+    assert_eq!(dip_text_buffer_line_count(buffer.as_deref()), 2);
+
+    let typed = "😀 ";
+    let status =
+        unsafe { dip_text_buffer_insert(buffer.as_deref_mut(), 7, typed.as_ptr(), typed.len()) };
+    assert_eq!(status, DipTextStatus::Ok);
+    assert_eq!(
+        dip_text_buffer_delete(buffer.as_deref_mut(), 0, 1),
+        DipTextStatus::Ok
+    );
+
+    let mut out = DipString::default();
+    let status = dip_text_buffer_text_in(buffer.as_deref(), 0, 17, Some(&mut out));
+    assert_eq!(status, DipTextStatus::Ok);
+    assert_eq!(string(out), "éllo\n😀 wörld");
+
+    let (start, end) = (
+        DipPosition { line: 1, column: 1 },
+        DipPosition { line: 0, column: 2 },
+    );
+    let mut out = DipString::default();
+    let status = dip_text_buffer_get_text_in_range(buffer.as_deref(), start, end, Some(&mut out));
+    assert_eq!(status, DipTextStatus::Ok);
+    assert_eq!(string(out), "lo\n😀");
+
+    let mut position = DipPosition::default();
+    let status = dip_text_buffer_position_at(buffer.as_deref(), 10, Some(&mut position));
+    assert_eq!(status, DipTextStatus::Ok);
+    assert_eq!(position, DipPosition { line: 1, column: 1 });
+    let mut offset = 0;
+    dip_text_buffer_offset_at(buffer.as_deref(), position, Some(&mut offset));
+    assert_eq!(offset, 10);
+    dip_text_buffer_utf16_offset_at(buffer.as_deref(), 10, Some(&mut offset));
+    assert_eq!(offset, 7);
+    // Inside the surrogate pair of the emoji.
+    dip_text_buffer_byte_offset_at(buffer.as_deref(), 6, Some(&mut offset));
+    assert_eq!(offset, 6);
+
+    dip_text_buffer_free(buffer.take());
+}
+
+#[test]
+fn rejects_bad_arguments() {
+    let text = "é";
+    let mut buffer = unsafe { dip_text_buffer_new(text.as_ptr(), text.len()) };
+    let invalid = [0xff, 0xfe];
+    assert!(unsafe { dip_text_buffer_new(invalid.as_ptr(), invalid.len()) }.is_none());
+    assert!(unsafe { dip_text_buffer_new(ptr::null(), 0) }
+        .is_some_and(|b| { dip_text_buffer_len(Some(&b)) == 0 }));
+
+    let insert = |buffer: Option<&mut DipTextBuffer>, offset, text: &[u8]| unsafe {
+        dip_text_buffer_insert(buffer, offset, text.as_ptr(), text.len())
+    };
+    assert_eq!(
+        insert(buffer.as_deref_mut(), 1, b"x"),
+        DipTextStatus::NotCharBoundary
+    );
+    assert_eq!(
+        insert(buffer.as_deref_mut(), 3, b"x"),
+        DipTextStatus::OutOfBounds
+    );
+    assert_eq!(
+        insert(buffer.as_deref_mut(), 0, &invalid),
+        DipTextStatus::InvalidUtf8
+    );
+    assert_eq!(insert(None, 0, b"x"), DipTextStatus::NullPointer);
+    let status = unsafe { dip_text_buffer_insert(buffer.as_deref_mut(), 0, ptr::null(), 1) };
+    assert_eq!(status, DipTextStatus::NullPointer);
+
+    assert_eq!(
+        dip_text_buffer_delete(buffer.as_deref_mut(), 0, 1),
+        DipTextStatus::NotCharBoundary
+    );
+    assert_eq!(
+        dip_text_buffer_delete(buffer.as_deref_mut(), 1, usize::MAX),
+        DipTextStatus::OutOfBounds
+    );
+    let mut out = DipString::default();
+    assert_eq!(
+        dip_text_buffer_text_in(buffer.as_deref(), 2, 0, Some(&mut out)),
+        DipTextStatus::OutOfBounds
+    );
+    assert_eq!(
+        dip_text_buffer_text_in(buffer.as_deref(), 0, 2, None),
+        DipTextStatus::NullPointer
+    );
+    let mut offset = 0;
+    let line = DipPosition { line: 1, column: 0 };
+    assert_eq!(
+        dip_text_buffer_offset_at(buffer.as_deref(), line, Some(&mut offset)),
+        DipTextStatus::OutOfBounds
+    );
+    assert_eq!(
+        dip_text_buffer_byte_offset_at(buffer.as_deref(), 2, Some(&mut offset)),
+        DipTextStatus::OutOfBounds
+    );
+    assert_eq!(dip_text_buffer_len(buffer.as_deref()), 2);
+
+    dip_text_buffer_free(buffer.take());
+    dip_text_buffer_free(None);
+    unsafe { dip_string_free(DipString::default()) };
+}
+
+#[test]
+fn header_declares_every_function() {
+    let source = include_str!("../src/lib.rs");
+    let header = include_str!("../include/dip_text.h");
+    let functions: Vec<&str> = source
+        .split("extern \"C\" fn ")
+        .skip(1)
+        .map(|rest| &rest[..rest.find('(').unwrap()])
+        .collect();
+    assert_eq!(functions.len(), 13);
+    for function in functions {
+        assert!(header.contains(&format!("{function}(")), "{function}");
+    }
+}
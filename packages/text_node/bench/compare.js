@@ -0,0 +1,82 @@
+// Times the same random edits and line reads on this buffer and, when `vscode-textbuffer`
+// is installed, on VS Code's piece tree. Run `npm run build` first.
+const { TextBuffer } = require('..');
+
+const LINES = 100000;
+const EDITS = 20000;
+
+// The same pseudo-random sequence on every run and for both buffers.
+function random(seed) {
+  return () => {
+    seed = (seed * 1103515245 + 12345) % 2147483648;
+    return seed / 2147483648;
+  };
+}
+
+function text() {
+  const lines = [];
+  for (let i = 0; i < LINES; i++) {
+    lines.push(`    let value_${i} = compute(${i}, "some text on line ${i}");`);
+  }
+  return lines.join('\n');
+}
+
+function time(name, f) {
+  const start = process.hrtime.bigint();
+  f();
+  const ms = Number(process.hrtime.bigint() - start) / 1e6;
+  console.log(`${name.padEnd(36)} ${ms.toFixed(1).padStart(9)} ms`);
+}
+
+function run(name, buffer) {
+  time(`${name}: ${EDITS} inserts and deletes`, () => {
+    const next = random(42);
+    for (let i = 0; i < EDITS; i++) {
+      const offset = Math.floor(next() * buffer.length());
+      if (next() < 0.7) {
+        buffer.insert(offset, 'typed text\n');
+      } else {
+        buffer.delete(offset, Math.min(8, buffer.length() - offset));
+      }
+    }
+  });
+  time(`${name}: ${EDITS} line reads`, () => {
+    const next = random(7);
+    for (let i = 0; i < EDITS; i++) {
+      buffer.lineContent(Math.floor(next() * buffer.lineCount()));
+    }
+  });
+}
+
+const source = text();
+
+const dip = new TextBuffer(source);
+run('dip_text', {
+  length: () => dip.length,
+  lineCount: () => dip.lineCount,
+  insert: (offset, value) => dip.insert(offset, value),
+  delete: (offset, count) => dip.delete(offset, count),
+  lineContent: (line) => dip.getLineContent(line),
+});
+time('dip_text: find "value_9"', () => dip.find('value_9', { caseSensitive: true }));
+time('dip_text: findRegex "compute\\(\\d+0,"', () => dip.findRegex('compute\\(\\d+0,'));
+
+let vscode;
+try {
+  vscode = require('vscode-textbuffer');
+} catch (_) {
+  console.log('vscode-textbuffer is not installed, skipping the comparison');
+  process.exit(0);
+}
+const builder = new vscode.PieceTreeTextBufferBuilder();
+builder.acceptChunk(source);
+// 1 is DefaultEndOfLine.LF.
+const tree = builder.finish(true).create(1);
+run('vscode-textbuffer', {
+  length: () => tree.getLength(),
+  lineCount: () => tree.getLineCount(),
+  insert: (offset, value) => tree.insert(offset, value),
+  delete: (offset, count) => tree.delete(offset, count),
+  // VS Code counts lines from 1.
+  lineContent: (line) => tree.getLineContent(line + 1),
+});
@@ -0,0 +1,36 @@
+/** Offsets and columns count UTF-16 code units, like JavaScript strings. Lines are zero-based. */
+export interface Position {
+  line: number;
+  column: number;
+}
+
+export interface Match {
+  start: number;
+  end: number;
+}
+
+export interface SearchOptions {
+  caseSensitive?: boolean;
+  /** Skips matches touching a letter, digit or underscore on either side. */
+  wholeWord?: boolean;
+}
+
+export class TextBuffer {
+  constructor(text?: string);
+  /** Length in UTF-16 code units, like `String.prototype.length`. */
+  get length(): number;
+  get lineCount(): number;
+  insert(offset: number, text: string): void;
+  delete(offset: number, length: number): void;
+  getText(): string;
+  getTextInRange(start: number, end: number): string;
+  /** Content of `line` without its line ending. */
+  getLineContent(line: number): string;
+  positionAt(offset: number): Position;
+  /** Offset of `position`. A column past the end of its line clamps to it. */
+  offsetAt(position: Position): number;
+  /** Ranges of `query`, leftmost first and non-overlapping. Case is ignored unless `caseSensitive` is set. */
+  find(query: string, options?: SearchOptions): Match[];
+  /** Ranges matched by the regular expression `pattern`, in Rust's regex syntax. Only patterns mentioning `\n` match across lines. */
+  findRegex(pattern: string, options?: SearchOptions): Match[];
+}
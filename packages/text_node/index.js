@@ -0,0 +1 @@
+module.exports = require('./dip_text_node.node');
@@ -0,0 +1,12 @@
+// Copies the library cargo built to dip_text_node.node, the name Node loads addons by.
+const fs = require('fs');
+const path = require('path');
+
+const profile = process.argv[2] || 'release';
+const names = {
+  darwin: 'libdip_text_node.dylib',
+  win32: 'dip_text_node.dll',
+};
+const name = names[process.platform] || 'libdip_text_node.so';
+const target = process.env.CARGO_TARGET_DIR || path.join(__dirname, '..', '..', '..', 'target');
+fs.copyFileSync(path.join(target, profile, name), path.join(__dirname, '..', 'dip_text_node.node'));
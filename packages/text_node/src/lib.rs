@@ -0,0 +1,186 @@
+//! Node.js bindings for [`dip_text::TextBuffer`] and its search, e.g. for benchmarking
+//! against VS Code's piece tree or embedding in Electron-based tools.
+//!
+//! Offsets and columns are counted in UTF-16 code units, the way JavaScript strings are
+//! indexed, and converted to the buffer's byte offsets in O(log n). Lines are zero-based.
+
+use dip_text::{
+    search::{self, Find},
+    TextBuffer as PieceTree,
+};
+use napi::{Error, Result, Status};
+use napi_derive::napi;
+use std::ops::Range;
+
+#[napi(object)]
+pub struct Position {
+    pub line: u32,
+    pub column: u32,
+}
+
+#[napi(object)]
+pub struct Match {
+    pub start: u32,
+    pub end: u32,
+}
+
+#[napi(object)]
+#[derive(Clone, Copy, Default)]
+pub struct SearchOptions {
+    pub case_sensitive: Option<bool>,
+    /// Skips matches touching a letter, digit or underscore on either side.
+    pub whole_word: Option<bool>,
+}
+
+impl From<SearchOptions> for search::SearchOptions {
+    fn from(options: SearchOptions) -> Self {
+        Self {
+            case_sensitive: options.case_sensitive.unwrap_or(false),
+            whole_word: options.whole_word.unwrap_or(false),
+        }
+    }
+}
+
+fn out_of_bounds(what: &str, value: u32) -> Error {
+    Error::new(
+        Status::InvalidArg,
+        format!("{what} {value} is out of bounds"),
+    )
+}
+
+#[napi]
+pub struct TextBuffer {
+    buffer: PieceTree,
+}
+
+#[napi]
+impl TextBuffer {
+    #[napi(constructor)]
+    pub fn new(text: Option<String>) -> Self {
+        Self {
+            buffer: PieceTree::from(text.as_deref().unwrap_or("")),
+        }
+    }
+
+    /// Length in UTF-16 code units, like `String.prototype.length`.
+    #[napi(getter)]
+    pub fn length(&self) -> u32 {
+        self.buffer.utf16_len() as u32
+    }
+
+    #[napi(getter)]
+    pub fn line_count(&self) -> u32 {
+        self.buffer.line_count() as u32
+    }
+
+    #[napi]
+    pub fn insert(&mut self, offset: u32, text: String) -> Result<()> {
+        let offset = self.byte_offset(offset)?;
+        self.buffer.insert(offset, &text);
+        Ok(())
+    }
+
+    #[napi]
+    pub fn delete(&mut self, offset: u32, length: u32) -> Result<()> {
+        let range = self.byte_range(offset, offset.saturating_add(length))?;
+        self.buffer.delete(range.start, range.len());
+        Ok(())
+    }
+
+    #[napi]
+    pub fn get_text(&self) -> String {
+        self.buffer.to_string()
+    }
+
+    #[napi]
+    pub fn get_text_in_range(&self, start: u32, end: u32) -> Result<String> {
+        Ok(self.buffer.text_in(self.byte_range(start, end)?))
+    }
+
+    /// Content of `line` without its line ending.
+    #[napi]
+    pub fn get_line_content(&self, line: u32) -> Result<String> {
+        self.check_line(line)?;
+        Ok(self.buffer.get_line_content(line as usize).into_owned())
+    }
+
+    #[napi]
+    pub fn position_at(&self, offset: u32) -> Result<Position> {
+        let offset = self.byte_offset(offset)?;
+        let line = self.buffer.line_at(offset);
+        let start = self.buffer.line_start(line);
+        Ok(Position {
+            line: line as u32,
+            column: (self.utf16(offset) - self.utf16(start)) as u32,
+        })
+    }
+
+    /// Offset of `position`. A column past the end of its line clamps to it.
+    #[napi]
+    pub fn offset_at(&self, position: Position) -> Result<u32> {
+        self.check_line(position.line)?;
+        let range = self.buffer.line_range(position.line as usize);
+        let (start, end) = (self.utf16(range.start), self.utf16(range.end));
+        Ok((start + position.column as usize).min(end) as u32)
+    }
+
+    /// Ranges of `query`, leftmost first and non-overlapping. Case is ignored unless
+    /// `caseSensitive` is set.
+    #[napi]
+    pub fn find(&self, query: String, options: Option<SearchOptions>) -> Vec<Match> {
+        let matches = self.buffer.find(&query, options.unwrap_or_default().into());
+        self.matches(matches)
+    }
+
+    /// Ranges matched by the regular expression `pattern`, in Rust's regex syntax. Only
+    /// patterns mentioning `\n` match across lines.
+    #[napi]
+    pub fn find_regex(
+        &self,
+        pattern: String,
+        options: Option<SearchOptions>,
+    ) -> Result<Vec<Match>> {
+        let regex = search::build_regex(&pattern, options.unwrap_or_default().into())
+            .map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?;
+        Ok(self.matches(self.buffer.find_all_regex(&regex)))
+    }
+}
+
+impl TextBuffer {
+    fn utf16(&self, offset: usize) -> usize {
+        self.buffer.utf16_offset_at(offset)
+    }
+
+    /// Byte offset of UTF-16 offset `units`. One inside a surrogate pair maps to the start
+    /// of its char.
+    fn byte_offset(&self, units: u32) -> Result<usize> {
+        match units as usize <= self.buffer.utf16_len() {
+            true => Ok(self.buffer.byte_offset_at(units as usize)),
+            false => Err(out_of_bounds("offset", units)),
+        }
+    }
+
+    fn byte_range(&self, start: u32, end: u32) -> Result<Range<usize>> {
+        if start > end {
+            return Err(out_of_bounds("start", start));
+        }
+        Ok(self.byte_offset(start)?..self.byte_offset(end)?)
+    }
+
+    fn check_line(&self, line: u32) -> Result<()> {
+        match (line as usize) < self.buffer.line_count() {
+            true => Ok(()),
+            false => Err(out_of_bounds("line", line)),
+        }
+    }
+
+    fn matches(&self, matches: Vec<Range<usize>>) -> Vec<Match> {
+        matches
+            .into_iter()
+            .map(|m| Match {
+                start: self.utf16(m.start) as u32,
+                end: self.utf16(m.end) as u32,
+            })
+            .collect()
+    }
+}
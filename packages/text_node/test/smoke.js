@@ -0,0 +1,39 @@
+const assert = require('assert');
+const test = require('node:test');
+const { TextBuffer } = require('..');
+
+test('edits and reads in UTF-16 offsets', () => {
+  const buffer = new TextBuffer('héllo\n😀 wörld');
+  assert.strictEqual(buffer.length, 'héllo\n😀 wörld'.length);
+  assert.strictEqual(buffer.lineCount, 2);
+
+  buffer.insert(8, 'big ');
+  buffer.delete(0, 1);
+  assert.strictEqual(buffer.getText(), 'éllo\n😀big  wörld');
+  assert.strictEqual(buffer.getTextInRange(5, 10), '😀big');
+  assert.strictEqual(buffer.getLineContent(1), '😀big  wörld');
+
+  assert.deepStrictEqual(buffer.positionAt(7), { line: 1, column: 2 });
+  assert.strictEqual(buffer.offsetAt({ line: 1, column: 2 }), 7);
+  assert.strictEqual(buffer.offsetAt({ line: 0, column: 100 }), 4);
+});
+
+test('searches', () => {
+  const buffer = new TextBuffer('Foo foo\n😀 food');
+  assert.deepStrictEqual(buffer.find('foo'), [
+    { start: 0, end: 3 },
+    { start: 4, end: 7 },
+    { start: 11, end: 14 },
+  ]);
+  assert.strictEqual(buffer.find('foo', { caseSensitive: true, wholeWord: true }).length, 1);
+  assert.deepStrictEqual(buffer.findRegex('fo+d'), [{ start: 11, end: 15 }]);
+  assert.throws(() => buffer.findRegex('('), /regex parse error/);
+});
+
+test('rejects offsets out of bounds', () => {
+  const buffer = new TextBuffer('ab');
+  assert.throws(() => buffer.insert(3, 'x'), /offset 3 is out of bounds/);
+  assert.throws(() => buffer.getTextInRange(2, 1), /out of bounds/);
+  assert.throws(() => buffer.getLineContent(1), /line 1 is out of bounds/);
+  assert.strictEqual(new TextBuffer().length, 0);
+});